@@ -0,0 +1,216 @@
+//! `#[derive(Point)]` for eyros. Use it through the `derive` feature of the
+//! eyros crate rather than depending on this crate directly.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span,TokenStream as TokenStream2};
+use quote::{quote,format_ident};
+use syn::{parse_macro_input,Data,DeriveInput,Fields,Ident,Type};
+
+/// Implement `eyros::Point` (through `eyros::PointOrd`, `eyros::PointCodec`
+/// and `eyros::BranchQuery`) for a struct with named fields, where each field
+/// is one dimension: a scalar `T`, an interval `(T,T)` or an `eyros::Mix<T>`
+/// that can hold either. Fields are compared in declaration order, so the
+/// first field is split on at the root of each tree.
+///
+/// Structs of scalars and intervals use the same layout and bounding boxes as
+/// the equivalent tuple. Structs of `Mix<T>` fields use the layout of the
+/// equivalent `MixN`. Scalars and intervals cannot be combined with `Mix<T>`
+/// fields in the same struct.
+#[proc_macro_derive(Point)]
+pub fn derive_point (input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  match expand(&input) {
+    Ok(tokens) => tokens.into(),
+    Err(e) => e.to_compile_error().into()
+  }
+}
+
+fn expand (input: &DeriveInput) -> Result<TokenStream2,syn::Error> {
+  let name = &input.ident;
+  if !input.generics.params.is_empty() {
+    return Err(syn::Error::new_spanned(&input.generics,
+      "#[derive(Point)] does not support generic structs"));
+  }
+  let fields = match &input.data {
+    Data::Struct(s) => match &s.fields {
+      Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+      _ => return Err(syn::Error::new_spanned(name,
+        "#[derive(Point)] requires a struct with named fields"))
+    },
+    _ => return Err(syn::Error::new_spanned(name,
+      "#[derive(Point)] only supports structs"))
+  };
+  if fields.len() < 2 || fields.len() > 8 {
+    return Err(syn::Error::new_spanned(name,
+      "#[derive(Point)] requires between 2 and 8 fields"));
+  }
+  let idents: Vec<&Ident> = fields.iter()
+    .map(|f| f.ident.as_ref().unwrap()).collect();
+  let types: Vec<&Type> = fields.iter().map(|f| &f.ty).collect();
+  let mixed: Vec<Option<&Type>> = types.iter().map(|t| mix_inner(t)).collect();
+  let n_mix = mixed.iter().filter(|m| m.is_some()).count();
+
+  // the delegate point type and conversions to and from it
+  let (delegate,to,from) = if n_mix == 0 {
+    let index = (0..idents.len()).map(syn::Index::from);
+    (
+      quote! { (#(#types),*) },
+      quote! { |p: &#name| (#(p.#idents),*) },
+      quote! { |t: Self::__Delegate| #name { #(#idents: t.#index),* } }
+    )
+  } else if n_mix == idents.len() {
+    let mix = format_ident!("Mix{}", idents.len());
+    let inner = mixed.iter().map(|m| m.unwrap());
+    let vs = (0..idents.len()).map(|i| format_ident!("v{}", i));
+    (
+      quote! { ::eyros::#mix<#(#inner),*> },
+      quote! { |p: &#name| ::eyros::#mix::new(#(p.#idents),*) },
+      quote! { |t: Self::__Delegate| #name { #(#idents: t.#vs),* } }
+    )
+  } else {
+    return Err(syn::Error::new(Span::call_site(),
+      "#[derive(Point)] cannot combine Mix<T> fields with other field types"));
+  };
+
+  let d = quote! { <#name as __EyrosDelegate>::__Delegate };
+  let o = quote! { ::eyros::PointOrd };
+  let c = quote! { ::eyros::PointCodec };
+  let q = quote! { ::eyros::BranchQuery };
+  let e = quote! { ::eyros::__derive };
+  Ok(quote! {
+    const _: () = {
+      trait __EyrosDelegate {
+        type __Delegate;
+        fn __to (&self) -> Self::__Delegate;
+        fn __from (t: Self::__Delegate) -> Self;
+      }
+      impl __EyrosDelegate for #name {
+        type __Delegate = #delegate;
+        fn __to (&self) -> Self::__Delegate { (#to)(self) }
+        fn __from (t: Self::__Delegate) -> Self { (#from)(t) }
+      }
+
+      impl #o for #name {
+        type Bounds = <#d as #o>::Bounds;
+        type Range = <#d as #o>::Range;
+        fn cmp_at (&self, other: &Self, level: usize)
+        -> ::std::cmp::Ordering {
+          #o::cmp_at(&self.__to(), &other.__to(), level)
+        }
+        fn midpoint_upper (&self, other: &Self) -> Self {
+          Self::__from(#o::midpoint_upper(&self.__to(), &other.__to()))
+        }
+        fn dim () -> usize { <#d as #o>::dim() }
+        fn overlaps (&self, bbox: &Self::Bounds) -> bool {
+          #o::overlaps(&self.__to(), bbox)
+        }
+        fn is_finite (&self) -> bool {
+          #o::is_finite(&self.__to())
+        }
+        fn is_ordered (&self) -> bool {
+          #o::is_ordered(&self.__to())
+        }
+        fn normalized (&self) -> Self {
+          Self::__from(#o::normalized(&self.__to()))
+        }
+        fn bounds (coords: &Vec<Self>) -> Option<Self::Bounds> {
+          <#d as #o>::bounds(&coords.iter().map(|c| c.__to()).collect())
+        }
+        fn bounds_to_range (bbox: Self::Bounds) -> Self::Range {
+          <#d as #o>::bounds_to_range(bbox)
+        }
+        fn spread_at (bbox: &Self::Bounds, level: usize) -> Option<f64> {
+          <#d as #o>::spread_at(bbox, level)
+        }
+        fn bounds_within (inner: &Self::Bounds, outer: &Self::Bounds)
+        -> bool {
+          <#d as #o>::bounds_within(inner, outer)
+        }
+        fn bounds_overlap (a: &Self::Bounds, b: &Self::Bounds) -> bool {
+          <#d as #o>::bounds_overlap(a, b)
+        }
+        fn overlaps_batch (points: &[Self], bbox: &Self::Bounds,
+        mask: &mut Vec<bool>) {
+          let points: Vec<#d> = points.iter().map(|p| p.__to()).collect();
+          <#d as #o>::overlaps_batch(&points, bbox, mask)
+        }
+      }
+
+      impl #c for #name {
+        fn serialize_at (&self, level: usize, dst: &mut [u8])
+        -> Result<usize,#e::Error> {
+          #c::serialize_at(&self.__to(), level, dst)
+        }
+        fn pivot_bytes_at (&self, level: usize) -> usize {
+          #c::pivot_bytes_at(&self.__to(), level)
+        }
+        fn count_bytes_at (buf: &[u8], level: usize)
+        -> Result<usize,#e::Error> {
+          <#d as #c>::count_bytes_at(buf, level)
+        }
+        fn pivot_cmp_at (buf: &[u8], bbox: &Self::Bounds, level: usize)
+        -> Result<(bool,bool),#e::Error> {
+          <#d as #c>::pivot_cmp_at(buf, bbox, level)
+        }
+        fn format_at (buf: &[u8], level: usize)
+        -> Result<String,#e::Error> {
+          <#d as #c>::format_at(buf, level)
+        }
+      }
+
+      impl #q for #name {
+        fn query_branch (buf: &[u8], bbox: &Self::Bounds,
+        branch_factor: usize, level: usize)
+        -> Result<(Vec<::eyros::Cursor>,Vec<::eyros::Block>),#e::Error> {
+          <#d as #q>::query_branch(buf, bbox, branch_factor, level)
+        }
+      }
+
+      impl #e::CountBytes for #name {
+        fn count_bytes (&self) -> usize {
+          #e::CountBytes::count_bytes(&self.__to())
+        }
+        fn count_from_bytes (buf: &[u8]) -> Result<usize,#e::Error> {
+          <#d as #e::CountBytes>::count_from_bytes(buf)
+        }
+      }
+
+      impl #e::ToBytes for #name {
+        fn to_bytes (&self) -> Result<Vec<u8>,#e::Error> {
+          #e::ToBytes::to_bytes(&self.__to())
+        }
+        fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,#e::Error> {
+          #e::ToBytes::write_bytes(&self.__to(), dst)
+        }
+      }
+
+      impl #e::FromBytes for #name {
+        fn from_bytes (src: &[u8]) -> Result<(usize,Self),#e::Error> {
+          let (size,t) = <#d as #e::FromBytes>::from_bytes(src)?;
+          Ok((size, Self::__from(t)))
+        }
+      }
+    };
+  })
+}
+
+// the `T` in a field type of `Mix<T>` or `eyros::Mix<T>`
+fn mix_inner (ty: &Type) -> Option<&Type> {
+  let path = match ty {
+    Type::Path(p) if p.qself.is_none() => &p.path,
+    _ => return None
+  };
+  let last = path.segments.last()?;
+  if last.ident != "Mix" { return None }
+  match &last.arguments {
+    syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+      match args.args.first()? {
+        syn::GenericArgument::Type(t) => Some(t),
+        _ => None
+      }
+    },
+    _ => None
+  }
+}
@@ -0,0 +1,69 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::hash::Hash;
+
+/// Aggregate function for `db.aggregate_by()`. `Sum`, `Min` and `Max` take a
+/// function that extracts the quantity to aggregate from each value.
+pub enum Agg<F> {
+  Count,
+  Sum(F),
+  Min(F),
+  Max(F)
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Group every record that intersects `bbox` by the key that `key_fn`
+  /// extracts from its value and aggregate each group with `agg`.
+  ///
+  /// Records are folded into the groups as the query streams, so only one
+  /// number per group is kept in memory. To count features by type:
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{DB,Agg};
+  /// # use failure::Error;
+  /// # use std::path::PathBuf;
+  /// # use random_access_disk::RandomAccessDisk;
+  /// # fn main () -> Result<(),Error> {
+  /// # let mut db: DB<_,_,((f32,f32),(f32,f32)),(u8,f32)> = DB::open(storage)?;
+  /// let bbox = ((-0.5,-0.8),(0.3,-0.5));
+  /// type F = fn(&(u8,f32)) -> f64;
+  /// let counts = db.aggregate_by(&bbox, |v| v.0, Agg::<F>::Count)?;
+  /// let areas = db.aggregate_by(&bbox, |v| v.0, Agg::Sum(|v: &(u8,f32)| {
+  ///   v.1 as f64
+  /// }))?;
+  /// # Ok(()) }
+  /// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+  /// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+  /// #   p.push(name);
+  /// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  /// # }
+  /// ```
+  pub fn aggregate_by<K,G,F> (&mut self, bbox: &P::Bounds, key_fn: G,
+  agg: Agg<F>) -> Result<HashMap<K,f64>,Error>
+  where K: Eq+Hash, G: Fn(&V) -> K, F: Fn(&V) -> f64 {
+    let mut groups: HashMap<K,f64> = HashMap::new();
+    for result in self.query(bbox)? {
+      let (_,v,_) = result?;
+      let (x,fold): (f64,fn(f64,f64) -> f64) = match &agg {
+        Agg::Count => (1.0, |a,b| a+b),
+        Agg::Sum(f) => (f(&v), |a,b| a+b),
+        Agg::Min(f) => (f(&v), f64::min),
+        Agg::Max(f) => (f(&v), f64::max)
+      };
+      match groups.entry(key_fn(&v)) {
+        Entry::Occupied(mut e) => {
+          let y = fold(*e.get(), x);
+          e.insert(y);
+        },
+        Entry::Vacant(e) => { e.insert(x); }
+      }
+    }
+    Ok(groups)
+  }
+}
@@ -0,0 +1,126 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+
+/// Data block whose bounds overlap many other blocks, as part of `Analysis`.
+/// Every query that touches the block's region reads all of those blocks.
+#[derive(Clone,Debug)]
+pub struct HotBlock<P> where P: Point {
+  /// Tree that references the block.
+  pub tree: usize,
+  /// Offset of the block in the `data` store.
+  pub offset: u64,
+  /// Depth of the branch that references the block, from 0 at the root.
+  pub depth: usize,
+  /// Live records in the block.
+  pub records: u64,
+  /// Bounds of the live records in the block.
+  pub bounds: P::Bounds,
+  /// Other data blocks, in any tree, whose bounds overlap `bounds`.
+  pub overlaps: usize
+}
+
+/// Report from `db.analyze()` on how the records are spread over the trees,
+/// to find data that degrades queries such as thousands of identical points.
+#[derive(Clone,Debug)]
+pub struct Analysis<P> where P: Point {
+  /// Live records in the staging area and the trees.
+  pub records: u64,
+  /// Data blocks in the trees that hold live records.
+  pub blocks: usize,
+  /// Distinct points among the live records.
+  pub distinct_points: u64,
+  /// Most live records that share a single point.
+  pub max_duplicates: u64,
+  /// Deepest branch that references a data block.
+  pub max_depth: usize,
+  /// Mean depth of the branches that reference data blocks.
+  pub mean_depth: f64,
+  /// Blocks with the most overlapping blocks, most overlaps first.
+  pub hot_blocks: Vec<HotBlock<P>>
+}
+
+impl<P> Analysis<P> where P: Point {
+  /// Live records whose point is shared with an earlier record.
+  pub fn duplicate_records (&self) -> u64 {
+    self.records - self.distinct_points
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Read every record and branch to report duplicate points, tree depth and
+  /// the `top` data blocks that overlap the most other blocks.
+  ///
+  /// This is a diagnostic pass: it reads the whole database and keeps the
+  /// serialized form of every distinct point in memory.
+  pub fn analyze (&mut self, top: usize) -> Result<Analysis<P>,Error> {
+    let mut points: HashMap<Vec<u8>,u64> = HashMap::new();
+    let mut records = 0;
+    self.staging.for_each(|p,_,_| {
+      *points.entry(p.to_bytes()?).or_insert(0) += 1;
+      records += 1;
+      Ok(())
+    })?;
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    let mut blocks: Vec<HotBlock<P>> = vec![];
+    let mut bounds: HashMap<u64,P::Bounds> = HashMap::new();
+    for (i,tree) in self.trees.iter().enumerate() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      for (offset,depth) in tree.block_depths()? {
+        let mut live = vec![];
+        for (p,_,loc) in dstore.list(offset)? {
+          if deletes.contains(&loc) { continue }
+          *points.entry(p.to_bytes()?).or_insert(0) += 1;
+          live.push(p);
+        }
+        records += live.len() as u64;
+        if let Some(b) = P::bounds(&live) {
+          bounds.insert(offset, b);
+          blocks.push(HotBlock {
+            tree: i,
+            offset,
+            depth,
+            records: live.len() as u64,
+            bounds: b,
+            overlaps: 0
+          });
+        }
+      }
+    }
+    for block in blocks.iter_mut() {
+      for tree in self.trees.iter() {
+        let mut tree = tree.try_borrow_mut()?;
+        if tree.is_empty()? { continue }
+        block.overlaps += tree.query_blocks(&block.bounds)?.iter()
+          .filter(|offset| **offset != block.offset)
+          .filter(|offset| bounds.get(offset)
+            .map(|b| P::bounds_overlap(b, &block.bounds))
+            .unwrap_or(false))
+          .count();
+      }
+    }
+    let depths: usize = blocks.iter().map(|b| b.depth).sum();
+    let nblocks = blocks.len();
+    let max_depth = blocks.iter().map(|b| b.depth).max().unwrap_or(0);
+    blocks.sort_unstable_by(|a,b| {
+      b.overlaps.cmp(&a.overlaps).then(b.records.cmp(&a.records))
+    });
+    blocks.truncate(top);
+    Ok(Analysis {
+      records,
+      blocks: nblocks,
+      distinct_points: points.len() as u64,
+      max_duplicates: points.values().copied().max().unwrap_or(0),
+      max_depth,
+      mean_depth: if nblocks == 0 { 0.0 }
+        else { depths as f64 / nblocks as f64 },
+      hot_blocks: blocks
+    })
+  }
+}
@@ -0,0 +1,170 @@
+use crate::{DB,Point,Value,data::DataStore};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+const CHUNK_SIZE: u64 = 1024*1024;
+
+/// Copy of a database in progress, started with `db.backup_to()`.
+///
+/// Every store except `data` is copied when the backup starts. The `data`
+/// store is only appended to, apart from the delete bitfields at the start of
+/// each block, so it is copied up to its length at the start of the backup by
+/// calling `backup.step()` between batches. The original bitfields of blocks
+/// that later batches delete from are kept in memory until the backup
+/// finishes, so the copy holds the database exactly as it was when the backup
+/// started.
+///
+/// The `meta` store is written last: a backup that does not finish can not
+/// be opened as a database.
+pub struct Backup<S,D,P,V>
+where S: RandomAccess<Error=Error>, D: RandomAccess<Error=Error>,
+P: Point, V: Value {
+  data_store: Rc<RefCell<DataStore<S,P,V>>>,
+  data: D,
+  meta_store: D,
+  meta: Vec<u8>,
+  offset: u64,
+  len: u64,
+  done: bool
+}
+
+impl<S,D,P,V> Backup<S,D,P,V>
+where S: RandomAccess<Error=Error>, D: RandomAccess<Error=Error>,
+P: Point, V: Value {
+  /// Copy up to `max_bytes` more of the `data` store. Returns `true` once the
+  /// backup is complete.
+  pub fn step (&mut self, max_bytes: u64) -> Result<bool,Error> {
+    if self.done { return Ok(true) }
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    if self.offset < self.len {
+      let size = max_bytes.max(1).min(self.len - self.offset);
+      let mut buf = dstore.store.read(self.offset, size)?;
+      if let Some(preserved) = &dstore.backup {
+        preserved.patch(self.offset, &mut buf);
+      }
+      self.data.write(self.offset, &buf)?;
+      self.offset += size;
+    }
+    if self.offset < self.len { return Ok(false) }
+    self.data.sync_all()?;
+    self.meta_store.write(0, &self.meta)?;
+    self.meta_store.sync_all()?;
+    dstore.backup = None;
+    self.done = true;
+    Ok(true)
+  }
+  /// Copy the rest of the `data` store to complete the backup.
+  pub fn finish (mut self) -> Result<(),Error> {
+    while !self.step(CHUNK_SIZE)? {}
+    Ok(())
+  }
+  /// Bytes of the `data` store copied so far and the total to copy.
+  pub fn progress (&self) -> (u64,u64) {
+    (self.offset, self.len)
+  }
+}
+
+impl<S,D,P,V> Drop for Backup<S,D,P,V>
+where S: RandomAccess<Error=Error>, D: RandomAccess<Error=Error>,
+P: Point, V: Value {
+  fn drop (&mut self) {
+    if self.done { return }
+    if let Ok(mut dstore) = self.data_store.try_borrow_mut() {
+      dstore.backup = None;
+    }
+  }
+}
+
+// Original contents of the data store regions that were overwritten while a
+// backup was copying the store, keyed by offset.
+pub(crate) struct Preserved {
+  pub len: u64,
+  ranges: HashMap<u64,Vec<u8>>
+}
+
+impl Preserved {
+  pub fn new (len: u64) -> Self {
+    Self { len, ranges: HashMap::new() }
+  }
+  // keep the current `length` bytes at `offset` before they are overwritten
+  pub fn preserve<S> (&mut self, store: &mut S, offset: u64, length: u64)
+  -> Result<(),Error> where S: RandomAccess<Error=Error> {
+    if offset >= self.len || self.ranges.contains_key(&offset) {
+      return Ok(())
+    }
+    let length = length.min(self.len - offset);
+    self.ranges.insert(offset, store.read(offset, length)?);
+    Ok(())
+  }
+  // restore the preserved bytes that overlap `buf`, read from `offset`
+  pub fn patch (&self, offset: u64, buf: &mut [u8]) {
+    let end = offset + buf.len() as u64;
+    for (start,bytes) in self.ranges.iter() {
+      let stop = start + bytes.len() as u64;
+      if stop <= offset || *start >= end { continue }
+      let from = offset.max(*start);
+      let to = end.min(stop);
+      buf[(from-offset) as usize..(to-offset) as usize]
+        .copy_from_slice(&bytes[(from-start) as usize..(to-start) as usize]);
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Start a point-in-time copy of the database into the stores opened with
+  /// `open_store`, which receives the same names as the storage function of
+  /// the database.
+  ///
+  /// Batches can continue while the returned `Backup` copies the `data`
+  /// store with `backup.step()` or `backup.finish()`. Only one backup can be
+  /// in progress at a time.
+  pub fn backup_to<D,T> (&mut self, open_store: T)
+  -> Result<Backup<S,D,P,V>,Error>
+  where D: RandomAccess<Error=Error>, T: Fn(&str) -> Result<D,Error> {
+    if self.data_store.try_borrow()?.backup.is_some() {
+      bail!["a backup is already in progress"];
+    }
+    self.staging.commit()?;
+    self.data_store.try_borrow_mut()?.commit()?;
+    let meta_len = self.store_len("meta")?;
+    let meta = self.read_store("meta", 0, meta_len)?;
+    for name in self.store_names() {
+      if name == "meta" || name == "data" { continue }
+      let len = self.store_len(&name)?;
+      let mut store = open_store(&name)?;
+      store.truncate(0)?;
+      let mut offset = 0;
+      while offset < len {
+        let size = CHUNK_SIZE.min(len-offset);
+        store.write(offset, &self.read_store(&name, offset, size)?)?;
+        offset += size;
+      }
+      store.sync_all()?;
+    }
+    let mut meta_store = open_store("meta")?;
+    meta_store.truncate(0)?;
+    let mut data = open_store("data")?;
+    data.truncate(0)?;
+    let len = {
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      let len = dstore.bytes()?;
+      dstore.backup = Some(Preserved::new(len));
+      len
+    };
+    Ok(Backup {
+      data_store: Rc::clone(&self.data_store),
+      data,
+      meta_store,
+      meta,
+      offset: 0,
+      len,
+      done: false
+    })
+  }
+}
@@ -0,0 +1,106 @@
+use crate::{DB,Point,Value,Row};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::collections::{HashSet,VecDeque};
+
+/// Identifier of a batch for `db.batch_with_id()`, such as the bytes of a
+/// UUID or of a hash of a message in a queue.
+pub type BatchId = [u8;16];
+
+/// Ids of the most recently applied batches, kept in the `batch_ids` store
+/// when enabled with `Setup::batch_ids()`.
+///
+/// Ids are appended to the store as 16-byte entries. Once the store holds
+/// twice as many ids as the window, the window is rewritten at the start of
+/// the store and the rest is truncated, so an interrupted rewrite only leaves
+/// older ids behind.
+pub struct BatchIds<S> where S: RandomAccess<Error=Error> {
+  pub store: S,
+  window: usize,
+  ids: VecDeque<BatchId>,
+  set: HashSet<BatchId>,
+  entries: u64
+}
+
+impl<S> BatchIds<S> where S: RandomAccess<Error=Error> {
+  pub fn open (mut store: S, window: usize) -> Result<Self,Error> {
+    let len = store.len()?;
+    let buf = if len > 0 { store.read(0, len)? } else { vec![] };
+    let mut batch_ids = Self {
+      store,
+      window,
+      ids: VecDeque::with_capacity(window+1),
+      set: HashSet::with_capacity(window+1),
+      entries: len/16
+    };
+    // a partial id at the end was never applied
+    for chunk in buf.chunks_exact(16) {
+      let mut id = [0u8;16];
+      id.copy_from_slice(chunk);
+      batch_ids.push(id);
+    }
+    Ok(batch_ids)
+  }
+  pub fn contains (&self, id: &BatchId) -> bool {
+    self.set.contains(id)
+  }
+  pub fn insert (&mut self, id: &BatchId) -> Result<(),Error> {
+    if self.entries as usize >= self.window*2 {
+      let mut buf = Vec::with_capacity(self.ids.len()*16);
+      for id in self.ids.iter() {
+        buf.extend_from_slice(id);
+      }
+      self.store.write(0, &buf)?;
+      self.store.truncate(buf.len() as u64)?;
+      self.entries = self.ids.len() as u64;
+    }
+    self.store.write(self.entries*16, id)?;
+    self.entries += 1;
+    self.push(*id);
+    Ok(())
+  }
+  fn push (&mut self, id: BatchId) {
+    if self.set.insert(id) {
+      self.ids.push_back(id);
+    }
+    while self.ids.len() > self.window {
+      if let Some(old) = self.ids.pop_front() {
+        self.set.remove(&old);
+      }
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Write `rows` like `db.batch()` unless a batch with the same `id` was
+  /// already applied, for pipelines that deliver batches at least once.
+  /// Returns whether the batch was written. Requires `Setup::batch_ids()`.
+  ///
+  /// Only the ids of the most recent `Setup::batch_ids()` batches are kept.
+  /// The id is recorded after the batch is written: a crash in between
+  /// writes the batch again when it is retried.
+  pub fn batch_with_id (&mut self, id: &BatchId, rows: &[Row<P,V>])
+  -> Result<bool,Error> {
+    match &self.batch_ids {
+      None => bail!["batch_with_id() requires Setup::batch_ids()"],
+      Some(ids) if ids.contains(id) => return Ok(false),
+      Some(_) => {}
+    }
+    self.batch(rows)?;
+    if let Some(ids) = &mut self.batch_ids {
+      ids.insert(id)?;
+      if self.sync_state.inline.get() {
+        ids.store.sync_all()?;
+      }
+    }
+    Ok(true)
+  }
+  /// Whether a batch with `id` was applied with `db.batch_with_id()`, among
+  /// the most recent `Setup::batch_ids()` batches.
+  pub fn has_batch_id (&self, id: &BatchId) -> bool {
+    self.batch_ids.as_ref().map(|ids| ids.contains(id)).unwrap_or(false)
+  }
+}
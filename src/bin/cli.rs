@@ -0,0 +1,336 @@
+extern crate eyros;
+extern crate failure;
+extern crate serde_json;
+
+use eyros::{DB,Setup,Row,PointOrd,DiskStore,IoMode,disk_storage,
+  reader::Reader};
+use failure::{Error,bail,format_err};
+use serde_json::Value as Json;
+use std::path::{Path,PathBuf};
+use std::io::{Read,Write,BufReader};
+use std::{env,fs,io};
+
+type P = ((f32,f32),(f32,f32));
+type V = Vec<u8>;
+type Storage = Box<dyn Fn(&str) -> Result<DiskStore,Error>>;
+type Db = DB<DiskStore,Storage,P,V>;
+
+const USAGE: &str = "usage: eyros-cli COMMAND DBPATH {...}
+
+  stats DBPATH                 print store sizes and record counts
+  query DBPATH BBOX            print features that intersect BBOX, given as
+                               xmin,ymin,xmax,ymax
+  import DBPATH geojson FILE   insert the features of a geojson file (or - for
+                               stdin) containing FeatureCollections or
+                               newline-delimited Features
+  export DBPATH                print every feature, one per line
+  compact DBPATH               rewrite the database with only live records,
+                               keeping its schema, crs, quantization and meta
+                               entries
+  check DBPATH                 read every tree and data block and report
+                               inconsistent records
+  gc DBPATH                    remove unreferenced tree stores and data
+                               blocks, or only report them with --dry-run
+
+  import and compact accept --direct to write with O_DIRECT, bypassing the
+  page cache during bulk loads";
+
+const ALL: <P as PointOrd>::Bounds = ((f32::MIN,f32::MIN),(f32::MAX,f32::MAX));
+const BATCH_SIZE: usize = 100_000;
+
+fn main() -> Result<(),Error> {
+  let (flags,args): (Vec<String>,Vec<String>) = env::args()
+    .partition(|a| a.starts_with("--"));
+  if args.len() < 3 {
+    bail!["{}", USAGE];
+  }
+  let path = PathBuf::from(&args[2]);
+  let mode = if flags.iter().any(|f| f == "--direct") { IoMode::Direct }
+    else { IoMode::Buffered };
+  match args[1].as_str() {
+    "stats" => stats(&path),
+    "query" => {
+      if args.len() < 4 { bail!["{}", USAGE] }
+      let mut db = open(&path)?;
+      let bbox = parse_bbox(&args[3])?;
+      let results = db.query(&bbox)?;
+      print_features(results)
+    },
+    "import" => {
+      if args.len() < 5 || args[3] != "geojson" { bail!["{}", USAGE] }
+      import(&path, &args[4], mode)
+    },
+    "export" => {
+      let mut db = open(&path)?;
+      print_features(db.query(&ALL)?)
+    },
+    "compact" => compact(&path, mode),
+    "check" => check(&path),
+    "gc" => gc(&path, flags.iter().any(|f| f == "--dry-run")),
+    _ => bail!["COMMAND not recognized\n\n{}", USAGE]
+  }
+}
+
+fn storage (path: &Path, mode: IoMode) -> Storage {
+  Box::new(disk_storage(path.to_path_buf(), mode))
+}
+
+fn open (path: &Path) -> Result<Db,Error> {
+  open_with(path, IoMode::Buffered)
+}
+
+fn open_with (path: &Path, mode: IoMode) -> Result<Db,Error> {
+  DB::open(storage(path, mode))
+}
+
+fn stats (path: &Path) -> Result<(),Error> {
+  let mut db = open(path)?;
+  let mut records = 0;
+  for result in db.query(&ALL)? {
+    result?;
+    records += 1;
+  }
+  println!["# records\n{}", records];
+  {
+    let mut dstore = db.data_store.try_borrow_mut()?;
+    let blocks = dstore.range.iter()?.count();
+    println!["# data\n{} bytes\n{} blocks", dstore.bytes()?, blocks];
+  }
+  println!["# staging\n{} bytes\n{} records",
+    db.staging.bytes()?, db.staging.len()?];
+  let amp = db.stats()?;
+  println!["# trees"];
+  for t in amp.trees.iter() {
+    println!["[{}] {} branch bytes\n    {} of {} data bytes live in {} records",
+      t.index, t.branch_bytes, t.live_bytes, t.total_bytes, t.records];
+  }
+  println!["# space amplification\n{:.2}", amp.space_amplification()];
+  let hash: String = db.root_hash()?.iter()
+    .map(|b| format!["{:02x}",b]).collect();
+  println!["# root hash\n{}", hash];
+  Ok(())
+}
+
+fn print_features<I> (results: I) -> Result<(),Error>
+where I: Iterator<Item=Result<(P,V,eyros::Location),Error>> {
+  let stdout = io::stdout();
+  let mut out = stdout.lock();
+  for result in results {
+    let (_,v,_) = result?;
+    out.write_all(&v)?;
+    out.write_all(b"\n")?;
+  }
+  Ok(())
+}
+
+fn import (path: &Path, file: &str, mode: IoMode) -> Result<(),Error> {
+  let mut db = open_with(path, mode)?;
+  let input: Box<dyn Read> = if file == "-" {
+    Box::new(io::stdin())
+  } else {
+    Box::new(fs::File::open(file)?)
+  };
+  let mut batch: Vec<Row<P,V>> = Vec::with_capacity(BATCH_SIZE);
+  let mut count = 0;
+  let mut skipped = 0;
+  let stream = serde_json::Deserializer::from_reader(BufReader::new(input))
+    .into_iter::<Json>();
+  for json in stream {
+    let mut json = json?;
+    let kind = json.get("type").and_then(|t| t.as_str()).map(String::from);
+    let features = match kind.as_deref() {
+      Some("FeatureCollection") => match json["features"].take() {
+        Json::Array(features) => features,
+        _ => bail!["FeatureCollection without a features array"]
+      },
+      Some("Feature") => vec![json],
+      _ => bail!["expected a geojson Feature or FeatureCollection"]
+    };
+    for feature in features {
+      let bbox = match feature.get("geometry").and_then(geometry_bbox) {
+        Some(bbox) => bbox,
+        None => { skipped += 1; continue }
+      };
+      batch.push(Row::Insert(bbox, serde_json::to_vec(&feature)?));
+      count += 1;
+      if batch.len() >= BATCH_SIZE {
+        db.batch(&batch)?;
+        batch.clear();
+      }
+    }
+  }
+  if !batch.is_empty() {
+    db.batch(&batch)?;
+  }
+  eprintln!["imported {} features, skipped {} without geometry",
+    count, skipped];
+  Ok(())
+}
+
+// interval bounds of every position in a geometry
+fn geometry_bbox (geometry: &Json) -> Option<P> {
+  let mut bbox: Option<P> = None;
+  match geometry.get("type").and_then(|t| t.as_str())? {
+    "GeometryCollection" => {
+      for g in geometry.get("geometries")?.as_array()?.iter() {
+        if let Some(b) = geometry_bbox(g) {
+          bbox = Some(union(bbox, b));
+        }
+      }
+    },
+    _ => positions(geometry.get("coordinates")?, &mut bbox)
+  }
+  bbox
+}
+
+fn positions (coords: &Json, bbox: &mut Option<P>) {
+  let items = match coords.as_array() {
+    Some(items) => items,
+    None => return
+  };
+  match (items.first().and_then(|x| x.as_f64()),
+  items.get(1).and_then(|y| y.as_f64())) {
+    (Some(x),Some(y)) => {
+      let (x,y) = (x as f32, y as f32);
+      *bbox = Some(union(*bbox, ((x,x),(y,y))));
+    },
+    _ => {
+      for c in items.iter() {
+        positions(c, bbox);
+      }
+    }
+  }
+}
+
+fn union (a: Option<P>, b: P) -> P {
+  match a {
+    None => b,
+    Some(((x0,x1),(y0,y1))) => (
+      (x0.min((b.0).0),x1.max((b.0).1)),
+      (y0.min((b.1).0),y1.max((b.1).1))
+    )
+  }
+}
+
+fn parse_bbox (s: &str) -> Result<<P as PointOrd>::Bounds,Error> {
+  let c = s.split(',').map(|x| x.trim().parse::<f32>())
+    .collect::<Result<Vec<f32>,_>>()?;
+  if c.len() != 4 {
+    bail!["bbox must be xmin,ymin,xmax,ymax, received {}", s];
+  }
+  Ok(((c[0],c[1]),(c[2],c[3])))
+}
+
+fn compact (path: &Path, mode: IoMode) -> Result<(),Error> {
+  let name = path.file_name()
+    .ok_or_else(|| format_err!["invalid database path {:?}", path])?
+    .to_string_lossy().to_string();
+  let tmp = path.with_file_name(format!["{}.compact", name]);
+  let old = path.with_file_name(format!["{}.old", name]);
+  if tmp.exists() || old.exists() {
+    bail!["{:?} or {:?} already exists", tmp, old];
+  }
+  fs::create_dir_all(&tmp)?;
+  let count = {
+    let mut src = open(path)?;
+    let mut dst = open_like(&src, &tmp, mode)?;
+    let mut batch: Vec<Row<P,V>> = Vec::with_capacity(BATCH_SIZE);
+    let mut count = 0;
+    for result in src.query(&ALL)? {
+      let (p,v,_) = result?;
+      batch.push(Row::Insert(p,v));
+      count += 1;
+      if batch.len() >= BATCH_SIZE {
+        dst.batch(&batch)?;
+        batch.clear();
+      }
+    }
+    if !batch.is_empty() {
+      dst.batch(&batch)?;
+    }
+    count
+  };
+  fs::rename(path, &old)?;
+  fs::rename(&tmp, path)?;
+  fs::remove_dir_all(&old)?;
+  eprintln!["compacted {} records", count];
+  Ok(())
+}
+
+// open a new database at `path` with the settings and meta entries stored in
+// `src`
+fn open_like (src: &Db, path: &Path, mode: IoMode) -> Result<Db,Error> {
+  let mut setup = Setup::new(storage(path, mode));
+  if let Some(schema) = src.schema() {
+    setup = setup.schema(schema.clone());
+  }
+  if let Some(crs) = src.crs() {
+    setup = setup.crs(crs);
+  }
+  if let Some(quantize) = src.quantization() {
+    setup = setup.quantize(quantize.to_vec());
+  }
+  let mut dst: Db = setup.build()?;
+  for key in src.meta_keys() {
+    if let Some(bytes) = src.meta_get(key) {
+      dst.meta_put(key, bytes)?;
+    }
+  }
+  Ok(dst)
+}
+
+fn gc (path: &Path, dry_run: bool) -> Result<(),Error> {
+  let mut db = open(path)?;
+  let report = db.gc(dry_run)?;
+  for (i,bytes) in report.trees.iter() {
+    println!["tree{} is unreferenced ({} bytes)", i, bytes];
+  }
+  println!["{} unreferenced data blocks ({} bytes)",
+    report.data_blocks, report.data_bytes];
+  println!["reclaimed {} bytes", report.reclaimed_bytes];
+  Ok(())
+}
+
+fn check (path: &Path) -> Result<(),Error> {
+  let mut problems = 0;
+  let mut records = 0;
+  {
+    let mut db = open(path)?;
+    for result in db.query(&ALL)? {
+      let (p,v,loc) = result?;
+      records += 1;
+      if (p.0).0 > (p.0).1 || (p.1).0 > (p.1).1 {
+        println!["inverted interval {:?} at {:?}", p, loc];
+        problems += 1;
+      }
+      if serde_json::from_slice::<Json>(&v).is_err() {
+        println!["invalid json value at {:?}", loc];
+        problems += 1;
+      }
+    }
+  }
+  let storage = storage(path, IoMode::Buffered);
+  let mut reader: Reader<_,P,V> = Reader::open(storage)?;
+  let entries = reader.ranges().iter()?.collect::<Result<Vec<_>,Error>>()?;
+  for (offset,range,len) in entries.iter() {
+    let rows = reader.block(*offset)?;
+    if rows.len() as u64 > *len {
+      println!["block at {} has {} rows, expected at most {}",
+        offset, rows.len(), len];
+      problems += 1;
+    }
+    let bbox = (((range.0).0,(range.1).0),((range.0).1,(range.1).1));
+    for (p,_,loc) in rows.iter() {
+      if !p.overlaps(&bbox) {
+        println!["record at {:?} outside of block range {:?}", loc, range];
+        problems += 1;
+      }
+    }
+  }
+  println!["checked {} records in {} data blocks", records, entries.len()];
+  if problems > 0 {
+    bail!["found {} problems", problems];
+  }
+  println!["ok"];
+  Ok(())
+}
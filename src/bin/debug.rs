@@ -7,7 +7,7 @@ use desert::CountBytes;
 #[path="../ensure.rs"]
 #[macro_use] mod ensure;
 
-use eyros::{Setup,DB};
+use eyros::{Setup,DB,TREE_ADAPTIVE};
 use failure::{Error,bail};
 use random_access_disk::RandomAccessDisk;
 use random_access_storage::RandomAccess;
@@ -17,9 +17,9 @@ use std::mem::size_of;
 use std::time;
 
 #[path="../read_block.rs"]
-mod read_block;
+#[allow(dead_code)] mod read_block;
 use read_block::read_block;
-use eyros::Point;
+use eyros::PointCodec;
 
 type P = ((f32,f32),(f32,f32));
 type V = u32;
@@ -83,6 +83,9 @@ fn main() -> Result<(),Error> {
         );
       }
     }
+  } else if args[2] == "dump" {
+    let i = args[3].parse::<usize>()?;
+    println!["{}", db.trees[i].try_borrow_mut()?.format()?];
   } else if args[2] == "data" {
     let i = args[3].parse::<u64>()?;
     let mut dstore = db.data_store.try_borrow_mut()?;
@@ -142,10 +145,18 @@ fn read_branch<S,U> (db: &mut DB<S,U,P,V>, tree_i: usize,
 offset: u64, depth: usize) -> Result<Branch,Error>
 where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>) {
   let len = db.trees[tree_i].try_borrow()?.store.len()? as u64;
-  let buf = read_block(
+  let mut buf = read_block(
     &mut db.trees[tree_i].try_borrow_mut()?.store, offset, len, 1024
   )?;
-  let bf = db.fields.branch_factor;
+  let levels = db.fields.levels();
+  let split = if db.trees[tree_i].try_borrow()?.format == TREE_ADAPTIVE {
+    let split = u16::from_be_bytes([buf[0],buf[1]]) as usize;
+    buf.drain(0..2);
+    split
+  } else {
+    levels.level(depth)
+  };
+  let bf = levels.branch_factor(split);
   let n = bf*2-3;
 
   let mut offset = 0;
@@ -154,7 +165,7 @@ where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>) {
     let size = P::count_from_bytes(&buf[offset..])?;
     str_pivots.push(P::format_at(
       &buf[offset..offset+size],
-      depth
+      split
     )?);
     offset += size;
   }
@@ -0,0 +1,37 @@
+use failure::{Error,ensure};
+use random_access_storage::RandomAccess;
+
+/// Serialized values too large to keep inline in data blocks, kept in the
+/// `blobs` store when enabled with `Setup::inline_threshold()`.
+///
+/// Each value is appended to the store as its serialized bytes and the data
+/// block row refers to it as `[offset: u64][length: u32]`. Blobs are never
+/// rewritten or reclaimed: the bytes of deleted values stay in the store and
+/// a merge that combines data blocks appends its large values again.
+pub struct BlobStore<S> where S: RandomAccess<Error=Error> {
+  pub store: S,
+  // serialized values larger than this many bytes are written as blobs
+  pub threshold: usize
+}
+
+impl<S> BlobStore<S> where S: RandomAccess<Error=Error> {
+  pub fn open (store: S, threshold: usize) -> Self {
+    Self { store, threshold }
+  }
+  /// Append `bytes` and return the `(offset, length)` to read them back.
+  pub fn write (&mut self, bytes: &[u8]) -> Result<(u64,u32),Error> {
+    ensure![bytes.len() <= u32::MAX as usize,
+      "blob of {} bytes is too large", bytes.len()];
+    let offset = self.store.len()?;
+    self.store.write(offset, bytes)?;
+    Ok((offset,bytes.len() as u32))
+  }
+  pub fn read (&mut self, offset: u64, len: u32) -> Result<Vec<u8>,Error> {
+    ensure![offset + len as u64 <= self.store.len()?,
+      "blob of {} bytes at {} is past the end of the blob store", len, offset];
+    self.store.read(offset, len as u64)
+  }
+  pub fn commit (&mut self) -> Result<(),Error> {
+    self.store.sync_all()
+  }
+}
@@ -0,0 +1,139 @@
+use crate::{DB,Point,Value};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+
+/// Bloom filters over the points in each data block, kept in the `bloom`
+/// store when enabled with `Setup::bloom_filter()`.
+///
+/// Each entry is appended as `[block offset: u64][filter length: u32][filter]`
+/// and an index of block offsets is loaded into memory when the store is
+/// opened. Filters are never updated for deletes, so a deleted point may still
+/// report a (false) positive.
+pub struct BloomStore<S> where S: RandomAccess<Error=Error> {
+  pub store: S,
+  bits_per_row: usize,
+  hashes: u32,
+  index: HashMap<u64,(u64,u32)>
+}
+
+impl<S> BloomStore<S> where S: RandomAccess<Error=Error> {
+  pub fn open (mut store: S, bits_per_row: usize) -> Result<Self,Error> {
+    let mut index = HashMap::new();
+    let len = store.len()?;
+    let mut offset = 0;
+    while offset < len {
+      let buf = store.read(offset, 12)?;
+      let block = u64::from_be_bytes([
+        buf[0], buf[1], buf[2], buf[3], buf[4], buf[5], buf[6], buf[7]
+      ]);
+      let size = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
+      index.insert(block, (offset+12,size));
+      offset += 12 + size as u64;
+    }
+    let hashes = ((bits_per_row as f64) * 0.69).round().max(1.0) as u32;
+    Ok(Self { store, bits_per_row, hashes, index })
+  }
+
+  /// Write a filter over `keys` (serialized points) for the data block at
+  /// `block`.
+  pub fn insert (&mut self, block: u64, keys: &[Vec<u8>]) -> Result<(),Error> {
+    let nbits = (keys.len()*self.bits_per_row).max(64);
+    let mut filter = vec![0u8;(nbits+7)/8];
+    let nbits = (filter.len()*8) as u64;
+    for key in keys.iter() {
+      for bit in probes(key, self.hashes, nbits) {
+        filter[(bit/8) as usize] |= 1 << (bit%8);
+      }
+    }
+    let offset = self.store.len()?;
+    let mut buf = Vec::with_capacity(12+filter.len());
+    buf.extend_from_slice(&block.to_be_bytes());
+    buf.extend_from_slice(&(filter.len() as u32).to_be_bytes());
+    buf.extend_from_slice(&filter);
+    self.store.write(offset, &buf)?;
+    self.index.insert(block, (offset+12,filter.len() as u32));
+    Ok(())
+  }
+
+  /// Check whether the data block at `block` may contain `key`. Returns `None`
+  /// if no filter was written for the block.
+  pub fn may_contain (&mut self, block: u64, key: &[u8])
+  -> Result<Option<bool>,Error> {
+    let (offset,size) = match self.index.get(&block) {
+      Some(entry) => *entry,
+      None => return Ok(None)
+    };
+    let filter = self.store.read(offset, size as u64)?;
+    let nbits = (filter.len()*8) as u64;
+    Ok(Some(probes(key, self.hashes, nbits).all(|bit| {
+      (filter[(bit/8) as usize] >> (bit%8)) & 1 == 1
+    })))
+  }
+
+  pub fn commit (&mut self) -> Result<(),Error> {
+    self.store.sync_all()
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Check whether a record exists at exactly `point`.
+  ///
+  /// With `Setup::bloom_filter()` enabled, data blocks whose filter rules out
+  /// `point` are skipped without being read. Without filters, every data block
+  /// that could contain `point` is read.
+  pub fn contains (&mut self, point: &P) -> Result<bool,Error> {
+    self.contains_row(point, None)
+  }
+
+  /// Check whether a record exists at exactly `point` and, if `value` is
+  /// given, with exactly that value.
+  pub(crate) fn contains_row (&mut self, point: &P, value: Option<&V>)
+  -> Result<bool,Error> {
+    let key = point.to_bytes()?;
+    let vkey = match value {
+      Some(v) => Some(v.to_bytes()?),
+      None => None
+    };
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    let mut found = false;
+    self.staging.for_each(|p,v,_| {
+      if found || p.to_bytes()? != key { return Ok(()) }
+      match &vkey {
+        Some(vk) if v.to_bytes()? != *vk => {},
+        _ => found = true
+      }
+      Ok(())
+    })?;
+    if found { return Ok(true) }
+    let bbox = match P::bounds(&vec![*point]) {
+      Some(bbox) => bbox,
+      None => bail!["failed to calculate bounds for {:?}", point]
+    };
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      if tree.contains(&bbox, &key, vkey.as_deref(), &deletes)? {
+        return Ok(true);
+      }
+    }
+    Ok(false)
+  }
+}
+
+// double hashing over a 64-bit fnv-1a hash
+fn probes (key: &[u8], hashes: u32, nbits: u64) -> impl Iterator<Item=u64> {
+  let mut h: u64 = 0xcbf29ce484222325;
+  for b in key {
+    h ^= *b as u64;
+    h = h.wrapping_mul(0x100000001b3);
+  }
+  let h1 = h;
+  let h2 = h.rotate_left(32) | 1;
+  (0..hashes as u64).map(move |i| {
+    h1.wrapping_add(i.wrapping_mul(h2)) % nbits
+  })
+}
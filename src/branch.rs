@@ -1,11 +1,11 @@
 use crate::{data::DataBatch,point::Point,Value,pivots};
-use crate::order::{order,order_len};
+use crate::order::{order,order_len,Levels};
 use std::cmp::Ordering;
 use std::mem::size_of;
 use std::rc::Rc;
 use std::cell::RefCell;
 use failure::{Error,bail,format_err};
-use desert::ToBytes;
+use desert::{ToBytes,CountBytes};
 
 #[derive(Clone)]
 pub enum Node<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
@@ -24,14 +24,23 @@ pub struct Data<P,V> where P: Point, V: Value {
 #[derive(Clone)]
 pub struct Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
   pub offset: u64,
+  // depth of the branch in the tree, starting from 0 at the root
   pub level: usize,
   pub index: usize,
   branch_factor: usize,
+  // level passed to the `Point` methods, which picks the split dimension
+  dim: usize,
+  levels: Rc<Levels>,
   max_data_size: usize,
+  // write the bounding box of each child after the child offsets
+  branch_bounds: bool,
   data_batch: Rc<RefCell<D>>,
   bucket: Vec<usize>,
   buckets: Vec<Vec<usize>>,
   rows: Rc<Vec<((P,V),u64)>>,
+  // partitions of each row as a bitmask, written after the bounds of each
+  // child
+  masks: Option<Rc<Vec<u64>>>,
   pivots: Vec<P>,
   sorted: Vec<usize>,
   intersecting: Vec<Vec<usize>>,
@@ -39,14 +48,26 @@ pub struct Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
 }
 
 impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
-  pub fn new (level: usize, index: usize, max_data_size: usize, bf: usize,
-  data_batch: Rc<RefCell<D>>, bucket: Vec<usize>, rows: Rc<Vec<((P,V),u64)>>)
-  -> Result<Self,Error> {
+  pub fn new (level: usize, index: usize, max_data_size: usize,
+  branch_bounds: bool, levels: Rc<Levels>,
+  data_batch: Rc<RefCell<D>>,
+  bucket: Vec<usize>, rows: Rc<Vec<((P,V),u64)>>,
+  masks: Option<Rc<Vec<u64>>>) -> Result<Self,Error> {
+    let dim = if levels.adaptive() {
+      widest_level(&levels, level, &rows, &bucket)
+    } else {
+      levels.level(level)
+    };
+    let bf = levels.branch_factor(dim);
     let n = order_len(bf);
+    // cmp_at() is not a total order for overlapping intervals, so sort by
+    // the upper bounds the pivots are taken from
+    let keys: Vec<P> = bucket.iter().map(|b| {
+      let p = &(rows[*b].0).0;
+      p.midpoint_upper(p)
+    }).collect();
     let mut sorted: Vec<usize> = (0..bucket.len()).collect();
-    sorted.sort_unstable_by(|a,b| {
-      (rows[bucket[*a]].0).0.cmp_at(&(rows[bucket[*b]].0).0, level)
-    });
+    sorted.sort_unstable_by(|a,b| keys[*a].cmp_at(&keys[*b], dim));
     let mut pivots: Vec<P> =
       if sorted.len() == 2 {
         let a = &rows[bucket[sorted[0]]].0;
@@ -64,7 +85,7 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
     // sometimes the sorted intervals overlap.
     // sort again to make sure the pivots are always in ascending order
     pivots.sort_unstable_by(|a,b| {
-      a.cmp_at(b, level)
+      a.cmp_at(b, dim)
     });
     if pivots.is_empty() {
       bail!["empty set of pivots"]
@@ -79,7 +100,7 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
       let mut i = 0;
       while i < pivots.len()-1 {
         while i < pivots.len()-1
-        && pivots[i].cmp_at(&pivots[i+1], level) == Ordering::Equal {
+        && pivots[i].cmp_at(&pivots[i+1], dim) == Ordering::Equal {
           pivots.remove(i+1);
         }
         i += 1;
@@ -93,13 +114,17 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
     Ok(Self {
       offset: 0,
       max_data_size,
+      branch_bounds,
       index,
       level,
       branch_factor: bf,
+      dim,
+      levels,
       data_batch,
       bucket,
       buckets: vec![vec![];bf],
       rows,
+      masks,
       pivots,
       sorted,
       intersecting: vec![vec![];n],
@@ -114,15 +139,31 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
     let bf = self.branch_factor;
     let n = order_len(bf);
     for p in self.pivots.iter() {
-      pivot_size += p.pivot_bytes_at(self.level % P::dim());
+      pivot_size += p.pivot_bytes_at(self.dim);
     }
     let bitfield_size = (n + bf + 7) / 8;
     let intersect_size = n*size_of::<u64>();
     let bucket_size = bf*size_of::<u64>();
-    4 + pivot_size + bitfield_size + intersect_size + bucket_size
+    4 + self.header_size() + pivot_size + bitfield_size + intersect_size
+      + bucket_size + (n+bf)*(self.bounds_size()+self.mask_size())
+  }
+  // the split level, written first with adaptive splits
+  fn header_size (&self) -> usize {
+    if self.levels.adaptive() { size_of::<u16>() } else { 0 }
   }
-  pub fn build (&mut self, alloc: &mut dyn FnMut (usize) -> u64)
-  -> Result<(Vec<u8>,Vec<Node<D,P,V>>),Error> {
+  fn bounds_size (&self) -> usize {
+    if !self.branch_bounds { return 0 }
+    let p = (self.rows[self.bucket[0]].0).0;
+    P::bounds(&vec![p]).map(|b| b.count_bytes()).unwrap_or(0)
+  }
+  fn mask_size (&self) -> usize {
+    if self.masks.is_some() { size_of::<u64>() } else { 0 }
+  }
+  /// Serialize this branch into `data`, which is cleared first so that the
+  /// same buffer can be reused for every branch of a tree, and return the
+  /// child nodes.
+  pub fn build (&mut self, alloc: &mut dyn FnMut (usize) -> u64,
+  data: &mut Vec<u8>) -> Result<Vec<Node<D,P,V>>,Error> {
     let n = order_len(self.branch_factor);
     let bf = self.branch_factor;
     for k in 0..n {
@@ -131,7 +172,7 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
       for j in self.sorted.iter() {
         let row = &self.rows[self.bucket[*j]];
         if self.matched[*j] { continue }
-        if (row.0).0.cmp_at(&pivot, self.level) == Ordering::Equal {
+        if (row.0).0.cmp_at(&pivot, self.dim) == Ordering::Equal {
           self.matched[*j] = true;
           self.intersecting[i].push(self.bucket[*j]);
         }
@@ -143,7 +184,7 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
       let mut j = 0;
       while j < bf-1 {
         let pivot = self.pivots[j*2];
-        match (row.0).0.cmp_at(&pivot, self.level) {
+        match (row.0).0.cmp_at(&pivot, self.dim) {
           Ordering::Less => { break },
           Ordering::Greater => j += 1,
           Ordering::Equal => bail!["bucket interval intersects pivot"]
@@ -175,9 +216,11 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
             self.level+1,
             self.index,
             self.max_data_size,
-            self.branch_factor,
+            self.branch_bounds,
+            Rc::clone(&self.levels),
             Rc::clone(&self.data_batch),
-            bucket.clone(), Rc::clone(&self.rows)
+            bucket.clone(), Rc::clone(&self.rows),
+            self.masks.as_ref().map(Rc::clone)
           )?;
           b.alloc(alloc);
           nodes.push(Node::Branch(b));
@@ -190,17 +233,25 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
 
     let bitfield_len = (n+bf+7)/8; // in bytes
     let node_len = (n+bf) * 8; // in bytes
-    let mut len = 4 + bitfield_len + node_len;
+    let bounds_size = self.bounds_size();
+    let mask_size = self.mask_size();
+    let mut len = 4 + self.header_size() + bitfield_len + node_len
+      + (n+bf)*(bounds_size+mask_size);
     for pivot in self.pivots.iter() {
-      len += pivot.pivot_bytes_at(self.level);
+      len += pivot.pivot_bytes_at(self.dim);
     }
-    let mut data = vec![0u8;len];
+    data.clear();
+    data.resize(len, 0);
     let mut offset = 0;
     // length
     offset += (len as u32).write_bytes(&mut data[offset..])?;
+    // split level
+    if self.levels.adaptive() {
+      offset += (self.dim as u16).write_bytes(&mut data[offset..])?;
+    }
     // pivots
     for pivot in self.pivots.iter() {
-      offset += pivot.serialize_at(self.level, &mut data[offset..])?;
+      offset += pivot.serialize_at(self.dim, &mut data[offset..])?;
     }
     // data bitfield
     for i in 0..bitfield_len {
@@ -219,6 +270,50 @@ impl<D,P,V> Branch<D,P,V> where D: DataBatch<P,V>, P: Point, V: Value {
         Node::Empty => 0u64
       }.write_bytes(&mut data[offset..])?;
     }
-    Ok((data,nodes))
+    // subtree bounds, each followed by the mask of the partitions below it
+    // if the rows have masks, left as zeros for empty nodes
+    if bounds_size > 0 {
+      for ref buckets in [&self.intersecting,&self.buckets].iter() {
+        for bucket in buckets.iter() {
+          if !bucket.is_empty() {
+            let points = bucket.iter().map(|b| (self.rows[*b].0).0).collect();
+            match P::bounds(&points) {
+              Some(b) => { b.write_bytes(&mut data[offset..])?; },
+              None => bail!["failed to calculate subtree bounds"]
+            }
+            if let Some(masks) = &self.masks {
+              let mask = bucket.iter().fold(0u64, |m,b| m | masks[*b]);
+              mask.write_bytes(&mut data[offset+bounds_size..])?;
+            }
+          }
+          offset += bounds_size + mask_size;
+        }
+      }
+    }
+    Ok(nodes)
+  }
+}
+
+// level of the dimension in which the rows of `bucket` have the greatest
+// spread, keeping the level of the cycle at `depth` on ties or when the spread
+// can not be measured
+fn widest_level<P,V> (levels: &Levels, depth: usize,
+rows: &[((P,V),u64)], bucket: &[usize]) -> usize where P: Point, V: Value {
+  let fallback = levels.level(depth);
+  let points: Vec<P> = bucket.iter().map(|b| (rows[*b].0).0).collect();
+  let bbox = match P::bounds(&points) {
+    Some(bbox) => bbox,
+    None => return fallback
+  };
+  let mut best = match P::spread_at(&bbox, fallback) {
+    Some(spread) => (fallback,spread),
+    None => return fallback
+  };
+  for level in levels.dims(P::dim()) {
+    match P::spread_at(&bbox, level) {
+      Some(spread) if spread > best.1 => best = (level,spread),
+      _ => {}
+    }
   }
+  best.0
 }
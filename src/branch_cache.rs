@@ -0,0 +1,110 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::cell::Cell;
+
+/// Hit rates of the branch and data block caches, returned by
+/// `db.cache_stats()`. Counters start at zero when the database is opened.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct CacheStats {
+  /// Branch blocks served from the pinned branches.
+  pub branch_hits: u64,
+  /// Branch blocks read from the tree stores.
+  pub branch_reads: u64,
+  /// Branch blocks pinned in memory across every tree.
+  pub pinned_branches: usize,
+  /// Bytes of the pinned branch blocks.
+  pub pinned_bytes: usize,
+  /// Data blocks served from the data block cache.
+  pub data_hits: u64,
+  /// Data blocks read from the `data` store.
+  pub data_reads: u64,
+  /// Data blocks in the data block cache.
+  pub data_cached: usize
+}
+
+impl CacheStats {
+  /// Share of the branch blocks that were served from memory, or 0 before
+  /// any branch was visited.
+  pub fn branch_hit_rate (&self) -> f64 {
+    rate(self.branch_hits, self.branch_reads)
+  }
+  /// Share of the data blocks that were served from the cache, or 0 before
+  /// any data block was visited.
+  pub fn data_hit_rate (&self) -> f64 {
+    rate(self.data_hits, self.data_reads)
+  }
+}
+
+fn rate (hits: u64, reads: u64) -> f64 {
+  if hits + reads == 0 { return 0.0 }
+  (hits as f64) / ((hits + reads) as f64)
+}
+
+// budget and counters for the branch blocks pinned by the trees of a
+// database, from Setup::pin_levels() and Setup::pin_bytes()
+#[derive(Debug,Default)]
+pub(crate) struct BranchCache {
+  // branch depths to pin, counting the root as depth 0
+  pub levels: usize,
+  // bytes of branch blocks to pin across every tree
+  pub max_bytes: usize,
+  bytes: Cell<usize>,
+  blocks: Cell<usize>,
+  hits: Cell<u64>,
+  reads: Cell<u64>
+}
+
+impl BranchCache {
+  pub fn new (levels: usize, max_bytes: usize) -> Self {
+    Self { levels, max_bytes, ..Self::default() }
+  }
+  // whether a branch block at `depth` of `bytes` may be pinned, taking it
+  // out of the budget if so
+  pub fn reserve (&self, depth: usize, bytes: usize) -> bool {
+    if depth >= self.levels || self.bytes.get() + bytes > self.max_bytes {
+      return false;
+    }
+    self.bytes.set(self.bytes.get() + bytes);
+    self.blocks.set(self.blocks.get() + 1);
+    true
+  }
+  // return `blocks` pinned blocks of `bytes` to the budget
+  pub fn release (&self, blocks: usize, bytes: usize) {
+    self.bytes.set(self.bytes.get().saturating_sub(bytes));
+    self.blocks.set(self.blocks.get().saturating_sub(blocks));
+  }
+  pub fn hit (&self) {
+    self.hits.set(self.hits.get() + 1);
+  }
+  pub fn read (&self, blocks: usize) {
+    self.reads.set(self.reads.get() + blocks as u64);
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Report how often branch and data blocks were served from memory since
+  /// the database was opened, to check whether `Setup::pin_levels()`,
+  /// `Setup::pin_bytes()` and `Setup::data_list_cache_size()` fit the
+  /// workload.
+  ///
+  /// Branch blocks are counted by queries and by `db.prefetch()`, while
+  /// maintenance traversals such as `db.query_explain()` always read them
+  /// from the stores.
+  pub fn cache_stats (&self) -> Result<CacheStats,Error> {
+    let cache = &self.branch_cache;
+    let dstore = self.data_store.try_borrow()?;
+    Ok(CacheStats {
+      branch_hits: cache.hits.get(),
+      branch_reads: cache.reads.get(),
+      pinned_branches: cache.blocks.get(),
+      pinned_bytes: cache.bytes.get(),
+      data_hits: dstore.cache_hits,
+      data_reads: dstore.cache_misses,
+      data_cached: dstore.cache_len()
+    })
+  }
+}
@@ -0,0 +1,28 @@
+// crc32 (IEEE 802.3, reflected polynomial 0xedb88320)
+
+const fn make_table () -> [u32;256] {
+  let mut table = [0u32;256];
+  let mut i = 0;
+  while i < 256 {
+    let mut c = i as u32;
+    let mut k = 0;
+    while k < 8 {
+      c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+      k += 1;
+    }
+    table[i] = c;
+    i += 1;
+  }
+  table
+}
+
+const TABLE: [u32;256] = make_table();
+
+/// Continue a crc32 checksum `crc` (use `0` to start) over `buf`.
+pub fn crc32_update (crc: u32, buf: &[u8]) -> u32 {
+  let mut c = !crc;
+  for b in buf {
+    c = TABLE[((c ^ (*b as u32)) & 0xff) as usize] ^ (c >> 8);
+  }
+  !c
+}
@@ -0,0 +1,29 @@
+use failure::Error;
+use std::time::{SystemTime,UNIX_EPOCH};
+
+/// Source of the current time for time-dependent behavior, such as the batch
+/// timestamps recorded with `Setup::timestamps()`.
+///
+/// Set a clock with `Setup::clock()` to make a database reproducible in tests
+/// and simulations. Any `Fn() -> u64` closure returning milliseconds since the
+/// unix epoch is a clock.
+pub trait Clock {
+  /// Milliseconds since the unix epoch.
+  fn now (&self) -> Result<u64,Error>;
+}
+
+/// Clock that reads the system time. This is the default clock.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now (&self) -> Result<u64,Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64)
+  }
+}
+
+impl<F> Clock for F where F: Fn() -> u64 {
+  fn now (&self) -> Result<u64,Error> {
+    Ok(self())
+  }
+}
@@ -0,0 +1,216 @@
+use crate::{DB,Point,Value,Location,Row};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::f64::consts::PI;
+
+/// Conversion between the coordinate reference system of an application and
+/// the one a database is stored in, set with `Setup::transform()` and applied
+/// by `db.batch_reprojected()` and `db.query_reprojected()`.
+pub trait Transform {
+  /// Name of the reference system of the application, such as `EPSG:4326`.
+  fn source (&self) -> &str;
+  /// Name of the reference system of the database, as set with
+  /// `Setup::crs()`.
+  fn target (&self) -> &str;
+  /// Convert `(x,y)` from `source()` to `target()`.
+  fn forward (&self, x: f64, y: f64) -> Result<(f64,f64),Error>;
+  /// Convert `(x,y)` from `target()` to `source()`.
+  fn inverse (&self, x: f64, y: f64) -> Result<(f64,f64),Error>;
+}
+
+const EARTH_RADIUS: f64 = 6_378_137.0;
+
+/// Longitude and latitude in degrees (`EPSG:4326`) to spherical web mercator
+/// meters (`EPSG:3857`). Latitudes are clamped to `eyros::MAX_LATITUDE`.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct WebMercator;
+
+impl Transform for WebMercator {
+  fn source (&self) -> &str { "EPSG:4326" }
+  fn target (&self) -> &str { "EPSG:3857" }
+  fn forward (&self, lon: f64, lat: f64) -> Result<(f64,f64),Error> {
+    let lat = lat.max(-crate::MAX_LATITUDE).min(crate::MAX_LATITUDE);
+    let y = (PI/4.0 + lat.to_radians()/2.0).tan().ln();
+    Ok((lon.to_radians()*EARTH_RADIUS, y*EARTH_RADIUS))
+  }
+  fn inverse (&self, x: f64, y: f64) -> Result<(f64,f64),Error> {
+    let lat = 2.0*(y/EARTH_RADIUS).exp().atan() - PI/2.0;
+    Ok(((x/EARTH_RADIUS).to_degrees(), lat.to_degrees()))
+  }
+}
+
+/// Transform between any two reference systems known to PROJ. Requires the
+/// `proj` feature.
+#[cfg(feature="proj")]
+pub struct ProjTransform {
+  source: String,
+  target: String,
+  forward: proj::Proj,
+  inverse: proj::Proj
+}
+
+#[cfg(feature="proj")]
+impl ProjTransform {
+  /// Create a transform from `source` to `target`, such as `"EPSG:4326"` and
+  /// `"EPSG:25832"`.
+  pub fn new (source: &str, target: &str) -> Result<Self,Error> {
+    Ok(Self {
+      source: source.to_string(),
+      target: target.to_string(),
+      forward: proj::Proj::new_known_crs(source, target, None)?,
+      inverse: proj::Proj::new_known_crs(target, source, None)?
+    })
+  }
+}
+
+#[cfg(feature="proj")]
+impl Transform for ProjTransform {
+  fn source (&self) -> &str { &self.source }
+  fn target (&self) -> &str { &self.target }
+  fn forward (&self, x: f64, y: f64) -> Result<(f64,f64),Error> {
+    Ok(self.forward.convert((x,y))?)
+  }
+  fn inverse (&self, x: f64, y: f64) -> Result<(f64,f64),Error> {
+    Ok(self.inverse.convert((x,y))?)
+  }
+}
+
+/// Two-dimensional point whose coordinates can be converted with a
+/// `Transform`. Implemented for `f32` and `f64` scalars and intervals.
+/// Intervals are converted to the bounds of their converted corners.
+pub trait Reproject: Sized {
+  fn reproject<F> (&self, f: F) -> Result<Self,Error>
+  where F: Fn(f64,f64) -> Result<(f64,f64),Error>;
+}
+
+// bounds of the converted corners of the box from (x0,y0) to (x1,y1)
+fn corners<F> (f: F, x0: f64, y0: f64, x1: f64, y1: f64)
+-> Result<((f64,f64),(f64,f64)),Error>
+where F: Fn(f64,f64) -> Result<(f64,f64),Error> {
+  let mut min = (f64::INFINITY,f64::INFINITY);
+  let mut max = (f64::NEG_INFINITY,f64::NEG_INFINITY);
+  for (x,y) in [(x0,y0),(x0,y1),(x1,y0),(x1,y1)].iter() {
+    let (x,y) = f(*x,*y)?;
+    min = (min.0.min(x), min.1.min(y));
+    max = (max.0.max(x), max.1.max(y));
+  }
+  Ok((min,max))
+}
+
+macro_rules! impl_reproject {
+  ($($T:ty),+) => {
+    $(
+      impl Reproject for ($T,$T) {
+        fn reproject<F> (&self, f: F) -> Result<Self,Error>
+        where F: Fn(f64,f64) -> Result<(f64,f64),Error> {
+          let (x,y) = f(self.0 as f64, self.1 as f64)?;
+          Ok((x as $T, y as $T))
+        }
+      }
+      impl Reproject for (($T,$T),($T,$T)) {
+        fn reproject<F> (&self, f: F) -> Result<Self,Error>
+        where F: Fn(f64,f64) -> Result<(f64,f64),Error> {
+          let ((x0,x1),(y0,y1)) = *self;
+          let (min,max) = corners(f, x0 as f64, y0 as f64,
+            x1 as f64, y1 as f64)?;
+          Ok(((min.0 as $T, max.0 as $T), (min.1 as $T, max.1 as $T)))
+        }
+      }
+      impl Reproject for (($T,$T),$T) {
+        fn reproject<F> (&self, f: F) -> Result<Self,Error>
+        where F: Fn(f64,f64) -> Result<(f64,f64),Error> {
+          let ((x0,x1),y) = *self;
+          let (min,max) = corners(f, x0 as f64, y as f64,
+            x1 as f64, y as f64)?;
+          Ok(((min.0 as $T, max.0 as $T), min.1 as $T))
+        }
+      }
+      impl Reproject for ($T,($T,$T)) {
+        fn reproject<F> (&self, f: F) -> Result<Self,Error>
+        where F: Fn(f64,f64) -> Result<(f64,f64),Error> {
+          let (x,(y0,y1)) = *self;
+          let (min,max) = corners(f, x as f64, y0 as f64,
+            x as f64, y1 as f64)?;
+          Ok((min.0 as $T, (min.1 as $T, max.1 as $T)))
+        }
+      }
+      impl ReprojectBounds for (($T,$T),($T,$T)) {
+        fn reproject_bounds<F> (&self, f: F) -> Result<Self,Error>
+        where F: Fn(f64,f64) -> Result<(f64,f64),Error> {
+          let ((x0,y0),(x1,y1)) = *self;
+          let (min,max) = corners(f, x0 as f64, y0 as f64,
+            x1 as f64, y1 as f64)?;
+          Ok(((min.0 as $T, min.1 as $T), (max.0 as $T, max.1 as $T)))
+        }
+      }
+    )+
+  }
+}
+
+/// Bounding box `((xmin,ymin),(xmax,ymax))` that can be converted with a
+/// `Transform`, to the bounds of its converted corners.
+pub trait ReprojectBounds: Sized {
+  fn reproject_bounds<F> (&self, f: F) -> Result<Self,Error>
+  where F: Fn(f64,f64) -> Result<(f64,f64),Error>;
+}
+
+impl_reproject![f32,f64];
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Coordinate reference system of the database, set with `Setup::crs()`.
+  pub fn crs (&self) -> Option<&str> {
+    if self.meta.crs.is_empty() { None } else { Some(&self.meta.crs) }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point+Reproject, P::Bounds: ReprojectBounds, V: Value {
+  /// Write `rows` like `db.batch()`, converting the points of inserts with
+  /// `Setup::transform()` from the reference system of the application to
+  /// that of the database.
+  pub fn batch_reprojected (&mut self, rows: &[Row<P,V>])
+  -> Result<(),Error> {
+    let rows = {
+      let transform = match &self.transform {
+        Some(t) => t,
+        None => bail!["batch_reprojected() requires Setup::transform()"]
+      };
+      let mut converted = Vec::with_capacity(rows.len());
+      for row in rows.iter() {
+        converted.push(match row {
+          Row::Insert(p,v) => {
+            Row::Insert(p.reproject(|x,y| transform.forward(x,y))?, v.clone())
+          },
+          row => row.clone()
+        });
+      }
+      converted
+    };
+    self.batch(&rows)
+  }
+  /// Query for the records that intersect `bbox`, given in the reference
+  /// system of the application and converted with `Setup::transform()`.
+  /// Points in the results are converted back.
+  ///
+  /// Converted boxes cover the converted corners, which is exact for
+  /// transforms between axis-aligned systems such as `WebMercator`.
+  pub fn query_reprojected (&mut self, bbox: &P::Bounds)
+  -> Result<Vec<(P,V,Location)>,Error> {
+    let transform = match &self.transform {
+      Some(t) => std::rc::Rc::clone(t),
+      None => bail!["query_reprojected() requires Setup::transform()"]
+    };
+    let bbox = bbox.reproject_bounds(|x,y| transform.forward(x,y))?;
+    let mut results = vec![];
+    for result in self.query(&bbox)? {
+      let (p,v,loc) = result?;
+      results.push((p.reproject(|x,y| transform.inverse(x,y))?, v, loc));
+    }
+    Ok(results)
+  }
+}
@@ -0,0 +1,160 @@
+use crate::{DB,Point,Value,Location,DeleteSet,Tree};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// what a TreeCursor points at
+#[derive(Clone,Copy,Debug,PartialEq)]
+enum Target {
+  Branch(u64),
+  Block(u64)
+}
+
+/// Position of a branch or a data block in a tree, for custom traversals
+/// such as visiting blocks from a priority queue or pruning with tests that
+/// do not fit in a bounding box.
+///
+/// Start from `db.root_cursors()` and descend with `cursor.children()`, then
+/// read the records of data blocks with `cursor.block()`:
+///
+/// ```rust,no_run
+/// use eyros::DB;
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// # fn main () -> Result<(),Error> {
+/// let mut db: DB<_,_,(f32,f32),u32> = DB::open(storage)?;
+/// let bbox = ((-10.0,-10.0),(10.0,10.0));
+/// let mut stack = db.root_cursors()?;
+/// while let Some(cursor) = stack.pop() {
+///   match cursor.block()? {
+///     Some(records) => println!["{} records", records.len()],
+///     None => stack.extend(cursor.children(&bbox)?)
+///   }
+/// }
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+///
+/// Cursors only cover the trees. Records in the staging area are returned by
+/// `db.query()` as usual. Like locations, cursors are only valid until the
+/// next `db.batch()`.
+pub struct TreeCursor<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  tree: Rc<RefCell<Tree<S,P,V>>>,
+  deletes: Rc<RefCell<DeleteSet>>,
+  target: Target,
+  depth: usize
+}
+
+impl<S,P,V> Clone for TreeCursor<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  fn clone (&self) -> Self {
+    Self {
+      tree: Rc::clone(&self.tree),
+      deletes: Rc::clone(&self.deletes),
+      target: self.target,
+      depth: self.depth
+    }
+  }
+}
+
+impl<S,P,V> TreeCursor<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  /// Number of branches above the cursor. The root branch is at depth 0.
+  pub fn depth (&self) -> usize {
+    self.depth
+  }
+  /// Whether the cursor points at a data block rather than a branch.
+  pub fn is_block (&self) -> bool {
+    match self.target { Target::Block(_) => true, Target::Branch(_) => false }
+  }
+  /// Cursors for the branches and data blocks below this branch that may
+  /// hold records intersecting `bbox`. Data blocks have no children.
+  pub fn children (&self, bbox: &P::Bounds) -> Result<Vec<Self>,Error> {
+    let offset = match self.target {
+      Target::Branch(offset) => offset,
+      Target::Block(_) => return Ok(vec![])
+    };
+    let (cursors,blocks) = self.tree.try_borrow_mut()?
+      .branch_children(offset, self.depth, bbox)?;
+    let child = |target, depth| Self {
+      tree: Rc::clone(&self.tree),
+      deletes: Rc::clone(&self.deletes),
+      target,
+      depth
+    };
+    let mut children: Vec<Self> = cursors.into_iter()
+      .map(|(offset,depth)| child(Target::Branch(offset), depth))
+      .collect();
+    children.extend(blocks.into_iter()
+      .map(|offset| child(Target::Block(offset), self.depth+1)));
+    Ok(children)
+  }
+  /// Records of a data block, without the records that have been deleted, or
+  /// `None` for a branch.
+  pub fn block (&self) -> Result<Option<Vec<(P,V,Location)>>,Error> {
+    let offset = match self.target {
+      Target::Block(offset) => offset,
+      Target::Branch(_) => return Ok(None)
+    };
+    let dstore = self.tree.try_borrow()?.data_store();
+    let mut dstore = dstore.try_borrow_mut()?;
+    let deletes = self.deletes.try_borrow()?;
+    let rows = dstore.list(offset)?.into_iter()
+      .filter(|(_,_,loc)| {
+        !deletes.contains(loc) && !dstore.is_deleted(loc.0-1, loc.1)
+      })
+      .collect();
+    Ok(Some(rows))
+  }
+  /// Bounds of the records of a data block, or `None` for a branch or an
+  /// empty block.
+  pub fn bounds (&self) -> Result<Option<P::Bounds>,Error> {
+    let offset = match self.target {
+      Target::Block(offset) => offset,
+      Target::Branch(_) => return Ok(None)
+    };
+    let dstore = self.tree.try_borrow()?.data_store();
+    let bbox = dstore.try_borrow_mut()?.bbox(offset)?;
+    Ok(bbox.map(|(b,_)| b))
+  }
+}
+
+impl<S,P,V> Tree<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  /// Cursor at the root branch of `tree`. Records deleted by `deletes` are
+  /// left out of `cursor.block()`.
+  pub fn root_cursor (tree: &Rc<RefCell<Self>>,
+  deletes: Rc<RefCell<DeleteSet>>) -> TreeCursor<S,P,V> {
+    TreeCursor {
+      tree: Rc::clone(tree),
+      deletes,
+      target: Target::Branch(0),
+      depth: 0
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Cursors at the root branch of every tree that is not empty, to start a
+  /// custom traversal. See `TreeCursor`.
+  pub fn root_cursors (&mut self) -> Result<Vec<TreeCursor<S,P,V>>,Error> {
+    let mut cursors = vec![];
+    for tree in self.trees.iter() {
+      if tree.try_borrow_mut()?.is_empty()? { continue }
+      let deletes = Rc::clone(&self.staging.delete_set);
+      cursors.push(Tree::root_cursor(tree, deletes));
+    }
+    Ok(cursors)
+  }
+}
@@ -0,0 +1,95 @@
+use crate::{Point,Value};
+
+/// Space-filling curve used to order rows before they are written into a
+/// tree, configured with `Setup::curve()`.
+///
+/// Rows that are close together along the curve are close together in space,
+/// so sorting a batch along a curve places spatially adjacent records in the
+/// same data blocks.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum Curve {
+  /// Keep rows in insertion order.
+  #[default]
+  None,
+  /// Sort rows by interleaving the bits of each coordinate (Morton order).
+  ZOrder,
+  /// Sort rows along a Hilbert curve, which has better locality than z-order
+  /// at the cost of a slightly more expensive key.
+  Hilbert
+}
+
+/// Sort `rows` along `curve`.
+///
+/// Point types are generic, so coordinates are mapped onto the curve by their
+/// rank in each dimension rather than by value. Intervals are ranked by their
+/// upper bound.
+pub fn sort<P,V> (curve: Curve, rows: &mut Vec<(P,V)>)
+where P: Point, V: Value {
+  if curve == Curve::None || rows.len() < 2 { return }
+  let dim = P::dim();
+  let bits = (128 / dim).min(16) as u32;
+  let n = rows.len();
+  let keys: Vec<P> = rows.iter().map(|(p,_)| p.midpoint_upper(p)).collect();
+  let mut coords = vec![vec![0u32;dim];n];
+  let mut sorted: Vec<usize> = (0..n).collect();
+  for d in 0..dim {
+    sorted.sort_by(|a,b| keys[*a].cmp_at(&keys[*b], d));
+    for (rank,i) in sorted.iter().enumerate() {
+      coords[*i][d] = (((rank as u64) << bits) / (n as u64)) as u32;
+    }
+  }
+  let index: Vec<u128> = coords.iter_mut().map(|c| {
+    if curve == Curve::Hilbert { hilbert_transpose(c, bits) }
+    interleave(c, bits)
+  }).collect();
+  let mut order: Vec<usize> = (0..n).collect();
+  order.sort_by_key(|i| index[*i]);
+  let mut taken: Vec<Option<(P,V)>> = rows.drain(..).map(Some).collect();
+  rows.extend(order.iter().map(|i| taken[*i].take().unwrap()));
+}
+
+// interleave the low `bits` bits of each coordinate, most significant first
+fn interleave (coords: &[u32], bits: u32) -> u128 {
+  let mut index = 0u128;
+  for b in (0..bits).rev() {
+    for c in coords.iter() {
+      index = (index << 1) | (((c >> b) & 1) as u128);
+    }
+  }
+  index
+}
+
+// convert coordinates in place to the transposed form of their hilbert index
+// (John Skilling, "Programming the Hilbert curve", 2004)
+fn hilbert_transpose (x: &mut [u32], bits: u32) {
+  let n = x.len();
+  let m = 1u32 << (bits-1);
+  let mut q = m;
+  while q > 1 {
+    let p = q-1;
+    for i in 0..n {
+      if x[i] & q != 0 {
+        x[0] ^= p;
+      } else {
+        let t = (x[0] ^ x[i]) & p;
+        x[0] ^= t;
+        x[i] ^= t;
+      }
+    }
+    q >>= 1;
+  }
+  for i in 1..n {
+    x[i] ^= x[i-1];
+  }
+  let mut t = 0;
+  let mut q = m;
+  while q > 1 {
+    if x[n-1] & q != 0 {
+      t ^= q-1;
+    }
+    q >>= 1;
+  }
+  for c in x.iter_mut() {
+    *c ^= t;
+  }
+}
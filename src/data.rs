@@ -1,12 +1,63 @@
-use crate::{Point,Value,Location,read_block::read_block};
+use crate::{Point,PointOrd,Value,Location,read_block::read_block,instrument};
+use crate::bloom::BloomStore;
+use crate::row_meta::BatchMeta;
+use crate::delete_bitmap::DeleteBitmap;
+use crate::blob::BlobStore;
+use crate::pack;
+use crate::quantize::{self,Quantize};
+use crate::backup::Preserved;
 use random_access_storage::RandomAccess;
-use failure::{Error,ensure,bail};
+use failure::{Error,ensure,bail,format_err};
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell,RefCell};
 use lru::LruCache;
 use std::collections::HashMap;
 use desert::{FromBytes,ToBytes,CountBytes};
 
+// flags in the high bits of the bitfield length of a data block: a bounds
+// header follows the bitfield as `[length: u16][bounds]`, the rows are
+// bit-packed as described in pack.rs, and a second bitfield after the bounds
+// marks the rows whose value is a `(u64,u32)` reference into the blob store
+pub(crate) const BOUNDS_FLAG: u16 = 0x8000;
+pub(crate) const PACKED_FLAG: u16 = 0x4000;
+pub(crate) const BLOB_FLAG: u16 = 0x2000;
+pub(crate) const BITFIELD_MASK: u16 = 0x1fff;
+
+// serialized value of a row, from DataStore::stored_value()
+pub(crate) enum StoredValue {
+  Inline(Vec<u8>),
+  // offset and length in the blob store
+  Blob(u64,u32)
+}
+
+// split a data block into its flags, its bitfield of live rows, the bitfield
+// of rows stored as blobs and the offset of the first row
+fn block_parts (buf: &[u8]) -> Result<(u16,&[u8],Option<&[u8]>,usize),Error> {
+  let mut offset = 0;
+  ensure![buf.len() >= 2, "data block too small for flags"];
+  let flags = u16::from_be_bytes([buf[0],buf[1]]);
+  let bitfield_len = (flags & BITFIELD_MASK) as usize;
+  offset += 2;
+  ensure![buf.len() >= offset+bitfield_len,
+    "data block too small for a bitfield of {} bytes", bitfield_len];
+  let bitfield: &[u8] = &buf[offset..offset+bitfield_len];
+  offset += bitfield_len;
+  if flags & BOUNDS_FLAG != 0 { // skip the bounds header
+    ensure![buf.len() >= offset+2, "data block too small for bounds"];
+    offset += 2 + u16::from_be_bytes([buf[offset],buf[offset+1]]) as usize;
+    ensure![buf.len() >= offset, "data block too small for bounds"];
+  }
+  let blob_bits = if flags & BLOB_FLAG != 0 {
+    ensure![buf.len() >= offset+bitfield_len,
+      "data block too small for a blob bitfield of {} bytes", bitfield_len];
+    offset += bitfield_len;
+    Some(&buf[offset-bitfield_len..offset])
+  } else {
+    None
+  };
+  Ok((flags,bitfield,blob_bits,offset))
+}
+
 pub trait DataBatch<P,V> where P: Point, V: Value {
   fn batch (&mut self, rows: &Vec<&(P,V)>) -> Result<u64,Error>;
 }
@@ -47,10 +98,38 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
 //#[derive(Debug,Clone)]
 pub struct DataStore<S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
-  store: S,
-  range: DataRange<S,P>,
-  list_cache: LruCache<u64,Vec<(P,V,Location)>>,
-  pub max_data_size: usize
+  pub store: S,
+  pub range: DataRange<S,P>,
+  // parsed rows of recently read blocks, shared with `query_ref()` results
+  list_cache: LruCache<u64,Rc<Vec<(P,V,Location)>>>,
+  pub bloom: Option<BloomStore<S>>,
+  pub batch_meta: Option<BatchMeta<S>>,
+  pub delete_bitmap: Option<DeleteBitmap<S>>,
+  pub blobs: Option<BlobStore<S>>,
+  pub max_data_size: usize,
+  // write a bounds header into each data block
+  pub block_bounds: bool,
+  // bit-pack the rows of each data block
+  pub pack_coords: bool,
+  // serialized size of every row, when known to be the same for all rows
+  pub fixed_width: Option<usize>,
+  // store coordinates as i32 steps, from the quantization in the meta store
+  pub quantize: Option<Rc<[Quantize]>>,
+  // whether commit() syncs the stores, per the durability policy
+  pub sync: Rc<Cell<bool>>,
+  // bytes of data blocks and bitfields written since the store was opened
+  pub written: u64,
+  // data blocks served from the cache and read from the store since the
+  // store was opened
+  pub cache_hits: u64,
+  pub cache_misses: u64,
+  // bitfields overwritten while a backup copies the store
+  pub(crate) backup: Option<Preserved>,
+  // serialization buffer reused between data blocks
+  scratch: Vec<u8>,
+  // points and overlap results reused between queries
+  points: Vec<P>,
+  mask: Vec<bool>
 }
 
 impl<S,P,V> DataBatch<P,V> for DataStore<S,P,V>
@@ -58,29 +137,89 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   fn batch (&mut self, rows: &Vec<&(P,V)>) -> Result<u64,Error> {
     ensure![rows.len() <= self.max_data_size,
       "data size limit exceeded in data merge"];
+    let bbox = match P::bounds(&rows.iter().map(|(p,_)| *p).collect()) {
+      None => bail!["failed to calculate bounds"],
+      Some(bbox) => bbox
+    };
+    let header = if self.block_bounds { bbox.to_bytes()? } else { vec![] };
     let bitfield_len = (rows.len()+7)/8;
+    ensure![bitfield_len <= BITFIELD_MASK as usize,
+      "too many rows for a data block: {}", rows.len()];
+    let blobs = self.write_blobs(rows)?;
+    let has_blobs = blobs.iter().any(|blob| blob.is_some());
+    let mut packed = vec![];
+    let quantize = self.quantize.clone();
+    let q = quantize.as_deref();
+    let is_packed = !has_blobs && self.pack_coords && q.is_none()
+      && pack::encode(rows, &mut packed)?;
+    let fixed_width = self.fixed_width.filter(|_| !has_blobs && q.is_none());
     let mut len = 6 + bitfield_len;
-    for row in rows.iter() {
-      len += row.count_bytes();
+    if self.block_bounds {
+      len += 2 + header.len();
+    }
+    if has_blobs {
+      len += bitfield_len;
+    }
+    if is_packed {
+      len += packed.len();
+    } else if let Some(width) = fixed_width {
+      len += rows.len()*width;
+    } else {
+      for (row,blob) in rows.iter().zip(blobs.iter()) {
+        len += quantize::point_size(q, &row.0) + match blob {
+          Some(blob) => blob.count_bytes(),
+          None => row.1.count_bytes()
+        };
+      }
     }
-    let mut data = vec![0u8;len];
+    let data = &mut self.scratch;
+    data.clear();
+    data.resize(len, 0);
     let mut offset = 0;
     offset += (len as u32).write_bytes(&mut data[offset..])?;
-    offset += (bitfield_len as u16).write_bytes(&mut data[offset..])?;
+    let mut flags = bitfield_len as u16;
+    if self.block_bounds { flags |= BOUNDS_FLAG }
+    if is_packed { flags |= PACKED_FLAG }
+    if has_blobs { flags |= BLOB_FLAG }
+    offset += flags.write_bytes(&mut data[offset..])?;
     for (i,_row) in rows.iter().enumerate() {
       data[6+i/8] |= 1<<(i%8);
     }
     offset += bitfield_len;
-    for row in rows.iter() {
-      offset += row.write_bytes(&mut data[offset..])?;
+    if self.block_bounds {
+      offset += (header.len() as u16).write_bytes(&mut data[offset..])?;
+      data[offset..offset+header.len()].copy_from_slice(&header);
+      offset += header.len();
+    }
+    if has_blobs {
+      for (i,blob) in blobs.iter().enumerate() {
+        if blob.is_some() { data[offset+i/8] |= 1<<(i%8) }
+      }
+      offset += bitfield_len;
+    }
+    if is_packed {
+      data[offset..offset+packed.len()].copy_from_slice(&packed);
+    } else {
+      for (row,blob) in rows.iter().zip(blobs.iter()) {
+        offset += quantize::write_point(q, &row.0, &mut data[offset..])?;
+        offset += match blob {
+          Some(blob) => blob.write_bytes(&mut data[offset..])?,
+          None => row.1.write_bytes(&mut data[offset..])?
+        };
+      }
     }
     let store_offset = self.store.len()?;
-    self.store.write(store_offset, &data)?;
-    let bbox = match P::bounds(&rows.iter().map(|(p,_)| *p).collect()) {
-      None => bail!["failed to calculate bounds"],
-      Some(bbox) => bbox
-    };
+    self.store.write(store_offset, &self.scratch)?;
+    self.written += self.scratch.len() as u64;
     self.range.write(&(store_offset,P::bounds_to_range(bbox),rows.len() as u64))?;
+    if let Some(bloom) = &mut self.bloom {
+      let keys = rows.iter().map(|(p,_)| p.to_bytes())
+        .collect::<Result<Vec<Vec<u8>>,Error>>()?;
+      bloom.insert(store_offset, &keys)?;
+    }
+    if let Some(meta) = &mut self.batch_meta {
+      meta.insert(store_offset)?;
+    }
     Ok(store_offset)
   }
 }
@@ -93,52 +232,264 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       store,
       range: DataRange::new(range_store, bbox_cache_size),
       list_cache: LruCache::new(list_cache_size),
-      max_data_size
+      bloom: None,
+      batch_meta: None,
+      delete_bitmap: None,
+      blobs: None,
+      max_data_size,
+      block_bounds: false,
+      pack_coords: false,
+      fixed_width: None,
+      quantize: None,
+      sync: Rc::new(Cell::new(true)),
+      written: 0,
+      cache_hits: 0,
+      cache_misses: 0,
+      backup: None,
+      scratch: vec![],
+      points: vec![],
+      mask: vec![]
     })
   }
   pub fn commit (&mut self) -> Result<(),Error> {
+    if !self.sync.get() { return Ok(()) }
+    // blobs are synced first so that no data block refers to missing bytes
+    if let Some(blobs) = &mut self.blobs {
+      blobs.commit()?;
+    }
     self.store.sync_all()?;
+    if let Some(bloom) = &mut self.bloom {
+      bloom.commit()?;
+    }
+    if let Some(meta) = &mut self.batch_meta {
+      meta.commit()?;
+    }
+    if let Some(bitmap) = &mut self.delete_bitmap {
+      bitmap.commit()?;
+    }
     Ok(())
   }
   pub fn query (&mut self, offset: u64, bbox: &P::Bounds)
   -> Result<Vec<(P,V,Location)>,Error> {
-    let rows = self.list(offset)?;
-    Ok(rows.iter().filter(|row| {
-      row.0.overlaps(bbox)
-    }).map(|row| { row.clone() }).collect())
+    if self.skip(offset, bbox)? { return Ok(vec![]) }
+    // filter the cached rows in place instead of copying the whole block,
+    // testing every point of the block in one batch
+    self.load(offset)?;
+    let rows = self.list_cache.peek(&offset)
+      .ok_or_else(|| format_err!["data block {} is not cached", offset])?;
+    self.points.clear();
+    self.points.extend(rows.iter().map(|row| row.0));
+    P::overlaps_batch(&self.points, bbox, &mut self.mask);
+    Ok(rows.iter().zip(self.mask.iter())
+      .filter(|(_,m)| **m)
+      .map(|(row,_)| row.clone())
+      .collect())
+  }
+  /// Cached rows of the data block at `offset` along with the indexes of the
+  /// rows that intersect `bbox`, without copying any row.
+  pub fn query_shared (&mut self, offset: u64, bbox: &P::Bounds)
+  -> Result<(Rc<Vec<(P,V,Location)>>,Vec<usize>),Error> {
+    if self.skip(offset, bbox)? { return Ok((Rc::default(),vec![])) }
+    self.load(offset)?;
+    let rows = self.list_cache.peek(&offset)
+      .ok_or_else(|| format_err!["data block {} is not cached", offset])?;
+    self.points.clear();
+    self.points.extend(rows.iter().map(|row| row.0));
+    P::overlaps_batch(&self.points, bbox, &mut self.mask);
+    let matches = self.mask.iter().enumerate()
+      .filter(|(_,m)| **m)
+      .map(|(i,_)| i)
+      .collect();
+    Ok((Rc::clone(rows),matches))
+  }
+  /// Read the data block at `offset` into the cache unless it is already
+  /// cached or its bounds header misses `bbox`, returning whether it was
+  /// read. A cached block is marked as recently used.
+  pub fn prefetch (&mut self, offset: u64, bbox: &P::Bounds)
+  -> Result<bool,Error> {
+    if self.list_cache.get(&offset).is_some() { return Ok(false) }
+    if self.skip(offset, bbox)? { return Ok(false) }
+    self.load(offset)?;
+    Ok(true)
+  }
+  /// Whether the data block at `offset` is in the cache.
+  pub fn is_cached (&self, offset: u64) -> bool {
+    self.list_cache.contains(&offset)
+  }
+  /// Number of data blocks that the cache holds.
+  pub fn cache_capacity (&self) -> usize {
+    self.list_cache.cap()
+  }
+  /// Number of data blocks in the cache.
+  pub fn cache_len (&self) -> usize {
+    self.list_cache.len()
+  }
+  // whether the bounds header of a block that is not cached misses `bbox`
+  fn skip (&mut self, offset: u64, bbox: &P::Bounds) -> Result<bool,Error> {
+    if !self.block_bounds || self.list_cache.peek(&offset).is_some() {
+      return Ok(false);
+    }
+    match self.header_bounds(offset)? {
+      Some(b) if !P::bounds_overlap(&b, bbox) => {
+        instrument::data_skip();
+        Ok(true)
+      },
+      _ => Ok(false)
+    }
   }
   pub fn list (&mut self, offset: u64) -> Result<Vec<(P,V,Location)>,Error> {
-    match self.list_cache.get(&offset) {
-      Some(rows) => return Ok(rows.to_vec()),
-      None => {}
+    self.load(offset)?;
+    match self.list_cache.peek(&offset) {
+      Some(rows) => Ok(rows.to_vec()),
+      None => bail!["data block {} is not cached", offset]
     }
-    let buf = self.read(offset)?;
-    let rows = self.parse(&buf)?.iter().map(|row| {
-      (row.0,row.1.clone(),(offset+1,row.2))
-    }).collect();
-    self.list_cache.put(offset, rows);
-    Ok(self.list_cache.peek(&offset).unwrap().to_vec())
   }
-  pub fn parse (&self, buf: &Vec<u8>) -> Result<Vec<(P,V,u32)>,Error> {
+  fn load (&mut self, offset: u64) -> Result<(),Error> {
+    if self.list_cache.get(&offset).is_some() {
+      instrument::data_cache_hit();
+      self.cache_hits += 1;
+    } else {
+      self.cache_misses += 1;
+      let buf = self.read(offset)?;
+      instrument::data_read(buf.len()+4);
+      let parsed = self.parse(&buf)?;
+      let deleted = self.delete_bitmap.as_ref().and_then(|d| d.get(offset));
+      let rows = parsed.into_iter()
+        .filter(|(_,_,i)| !deleted.map(|d| d.contains(*i)).unwrap_or(false))
+        .map(|(p,v,i)| (p,v,(offset+1,i)))
+        .collect();
+      self.list_cache.put(offset, Rc::new(rows));
+    }
+    Ok(())
+  }
+  /// Read the bounds header of the data block at `offset` without reading
+  /// its rows, or `None` if the block was written without a header.
+  pub fn header_bounds (&mut self, offset: u64)
+  -> Result<Option<P::Bounds>,Error> {
+    let head = self.store.read(offset, 6)?;
+    let flags = u16::from_be_bytes([head[4],head[5]]);
+    if flags & BOUNDS_FLAG == 0 { return Ok(None) }
+    let start = offset + 6 + (flags & BITFIELD_MASK) as u64;
+    let size = u16::from_bytes(&self.store.read(start, 2)?)?.1 as u64;
+    let buf = self.store.read(start+2, size)?;
+    Ok(Some(P::Bounds::from_bytes(&buf)?.1))
+  }
+  pub fn parse (&mut self, buf: &Vec<u8>) -> Result<Vec<(P,V,u32)>,Error> {
     let mut results = vec![];
-    let mut offset = 0;
-    let bitfield_len = u16::from_be_bytes([buf[0],buf[1]]) as usize;
-    offset += 2;
-    let bitfield: &[u8] = &buf[offset..offset+bitfield_len];
-    offset += bitfield_len;
+    let (flags,bitfield,blob_bits,mut offset) = block_parts(buf)?;
+    let bitfield_len = bitfield.len();
+    if flags & PACKED_FLAG != 0 {
+      let rows = pack::decode::<P,V>(&buf[offset..])?;
+      ensure![rows.len() <= bitfield_len*8,
+        "{} packed rows exceed the data block bitfield", rows.len()];
+      for (index,(p,v)) in rows.into_iter().enumerate() {
+        if ((bitfield[index/8]>>(index%8))&1) == 1 {
+          results.push((p,v,index as u32));
+        }
+      }
+      return Ok(results);
+    }
+    let quantize = self.quantize.clone();
+    let q = quantize.as_deref();
     let mut index = 0;
     while offset < buf.len() {
-      if ((bitfield[index/8]>>(index%8))&1) == 1 {
-        let (size,pv) = <(P,V)>::from_bytes(&buf[offset..])?;
-        results.push((pv.0,pv.1,index as u32));
-        offset += size;
+      ensure![index/8 < bitfield_len,
+        "rows exceed the data block bitfield at row {}", index];
+      let live = ((bitfield[index/8]>>(index%8))&1) == 1;
+      let is_blob = blob_bits
+        .map(|bits| ((bits[index/8]>>(index%8))&1) == 1)
+        .unwrap_or(false);
+      if is_blob {
+        let (psize,p) = quantize::read_point::<P>(q, &buf[offset..])?;
+        let (bsize,(at,len)) = <(u64,u32)>::from_bytes(&buf[offset+psize..])?;
+        if live {
+          results.push((p,self.read_blob(at,len)?,index as u32));
+        }
+        offset += psize + bsize;
+      } else if live {
+        let (psize,p) = quantize::read_point::<P>(q, &buf[offset..])?;
+        let (vsize,v) = V::from_bytes(&buf[offset+psize..])?;
+        results.push((p,v,index as u32));
+        offset += psize + vsize;
       } else {
-        offset += <(P,V)>::count_from_bytes(&buf[offset..])?;
+        let psize = quantize::count_point::<P>(q, &buf[offset..])?;
+        offset += psize + V::count_from_bytes(&buf[offset+psize..])?;
       }
       index += 1;
     }
     Ok(results)
   }
+  /// Serialized value of slot `index` in the data block at `offset`, either
+  /// inline or as the location of its bytes in the blob store, without
+  /// reading the blob. Returns `None` for deleted slots.
+  pub(crate) fn stored_value (&mut self, offset: u64, index: u32)
+  -> Result<Option<StoredValue>,Error> {
+    if self.is_deleted(offset, index) { return Ok(None) }
+    let buf = self.read(offset)?;
+    let (flags,bitfield,blob_bits,mut start) = block_parts(&buf)?;
+    let i = index as usize;
+    ensure![i/8 < bitfield.len(),
+      "slot {} is past the end of data block {}", index, offset];
+    if ((bitfield[i/8]>>(i%8))&1) == 0 { return Ok(None) }
+    if flags & PACKED_FLAG != 0 {
+      return match pack::decode::<P,V>(&buf[start..])?.get(i) {
+        Some((_,v)) => Ok(Some(StoredValue::Inline(v.to_bytes()?))),
+        None => bail!["slot {} is past the rows of data block {}",
+          index, offset]
+      };
+    }
+    let is_blob = |j: usize| blob_bits
+      .map(|bits| ((bits[j/8]>>(j%8))&1) == 1)
+      .unwrap_or(false);
+    let q = self.quantize.as_deref();
+    for j in 0..i {
+      ensure![start < buf.len(),
+        "slot {} is past the rows of data block {}", index, offset];
+      let psize = quantize::count_point::<P>(q, &buf[start..])?;
+      start += psize + if is_blob(j) {
+        <(u64,u32)>::count_from_bytes(&buf[start+psize..])?
+      } else {
+        V::count_from_bytes(&buf[start+psize..])?
+      };
+    }
+    ensure![start < buf.len(),
+      "slot {} is past the rows of data block {}", index, offset];
+    let psize = quantize::count_point::<P>(q, &buf[start..])?;
+    let start = start + psize;
+    if is_blob(i) {
+      let (_,(at,len)) = <(u64,u32)>::from_bytes(&buf[start..])?;
+      Ok(Some(StoredValue::Blob(at,len)))
+    } else {
+      let vsize = V::count_from_bytes(&buf[start..])?;
+      ensure![start+vsize <= buf.len(),
+        "value of slot {} is past the end of data block {}", index, offset];
+      Ok(Some(StoredValue::Inline(buf[start..start+vsize].to_vec())))
+    }
+  }
+  // write the values of `rows` larger than the inline threshold to the blob
+  // store, returning the reference of each row that was written
+  fn write_blobs (&mut self, rows: &[&(P,V)])
+  -> Result<Vec<Option<(u64,u32)>>,Error> {
+    let blobs = match &mut self.blobs {
+      Some(blobs) => blobs,
+      None => return Ok(vec![None;rows.len()])
+    };
+    let threshold = blobs.threshold;
+    rows.iter().map(|(_,v)| {
+      if v.count_bytes() > threshold {
+        Ok(Some(blobs.write(&v.to_bytes()?)?))
+      } else {
+        Ok(None)
+      }
+    }).collect()
+  }
+  fn read_blob (&mut self, offset: u64, len: u32) -> Result<V,Error> {
+    match &mut self.blobs {
+      Some(blobs) => Ok(V::from_bytes(&blobs.read(offset, len)?)?.1),
+      None => bail!["data block refers to a value in the blob store, which \
+        is only opened with Setup::inline_threshold()"]
+    }
+  }
   pub fn read (&mut self, offset: u64) -> Result<Vec<u8>,Error> {
     let len = self.store.len()? as u64;
     read_block(&mut self.store, offset, len, 1024)
@@ -168,30 +519,92 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
         "index length past the end of the block"];
       let mut header = self.store.read(*block, len)?;
       let block_size = u32::from_bytes(&header[0..])?.1 as u64;
-      let bitfield_len = u16::from_bytes(&header[4..])?.1;
+      let bitfield_len = u16::from_bytes(&header[4..])?.1 & BITFIELD_MASK;
       ensure![len <= (bitfield_len as u64) + 6,
         "read length {} from index {} past expected bitfield length {} \
         for block size {} at offset {}",
         len, max_i, bitfield_len, block_size, *block
       ];
       ensure![len <= block_size, "data block is too small"];
+      if let Some(backup) = &mut self.backup {
+        backup.preserve(&mut self.store, block+6, bitfield_len as u64)?;
+      }
       for index in indexes.iter() {
         let i = *index as usize;
         header[6+i/8] &= 0xff - (1<<(i%8));
       }
       self.store.write(block+6, &header[6..])?;
+      self.written += (header.len()-6) as u64;
+      self.range.cache.pop(block);
       match self.list_cache.get_mut(block) {
         Some(rows) => {
-          rows.retain(|row| !indexes.contains(&((row.2).1)));
+          Rc::make_mut(rows).retain(|row| !indexes.contains(&((row.2).1)));
         },
         None => {},
       }
     }
     Ok(())
   }
+  /// Add tree `locations` to the delete bitmaps, dropping them from cached
+  /// blocks.
+  pub fn mark_deleted (&mut self, locations: &[Location]) -> Result<(),Error> {
+    let changed = match &mut self.delete_bitmap {
+      Some(bitmap) => bitmap.insert(locations)?,
+      None => bail!["delete bitmaps are not enabled"]
+    };
+    for block in changed.iter() {
+      self.range.cache.pop(block);
+      if let (Some(rows),Some(bitmap)) = (
+        self.list_cache.get_mut(block),
+        self.delete_bitmap.as_ref().and_then(|d| d.get(*block))
+      ) {
+        Rc::make_mut(rows).retain(|row| !bitmap.contains((row.2).1));
+      }
+    }
+    Ok(())
+  }
+  /// Write every slot in the delete bitmaps into the data block bitfields and
+  /// clear the bitmaps.
+  pub fn apply_deletes (&mut self) -> Result<(),Error> {
+    let locations = match &self.delete_bitmap {
+      Some(bitmap) if !bitmap.is_empty() => bitmap.locations(),
+      _ => return Ok(())
+    };
+    self.delete(&locations)?;
+    if let Some(bitmap) = &mut self.delete_bitmap {
+      bitmap.clear()?;
+    }
+    Ok(())
+  }
+  /// Whether slot `index` of the data block at `offset` was deleted through a
+  /// delete bitmap.
+  pub fn is_deleted (&self, offset: u64, index: u32) -> bool {
+    self.delete_bitmap.as_ref()
+      .map(|d| d.contains(offset, index))
+      .unwrap_or(false)
+  }
   pub fn bytes (&mut self) -> Result<u64,Error> {
     Ok(self.store.len()? as u64)
   }
+  /// Drop the data blocks at and after `len` and the range entries at and
+  /// after `range_len`, which must describe the same blocks.
+  pub fn truncate (&mut self, len: u64, range_len: u64) -> Result<(),Error> {
+    if let Some(backup) = &self.backup {
+      ensure![len >= backup.len,
+        "data store can not be truncated while a backup is in progress"];
+    }
+    self.store.truncate(len)?;
+    self.range.store.truncate(range_len)?;
+    let stale: Vec<u64> = self.list_cache.iter().map(|(offset,_)| *offset)
+      .chain(self.range.cache.iter().map(|(offset,_)| *offset))
+      .filter(|offset| *offset >= len)
+      .collect();
+    for offset in stale {
+      self.list_cache.pop(&offset);
+      self.range.cache.pop(&offset);
+    }
+    self.commit()
+  }
   pub fn bbox (&mut self, offset: u64)
   -> Result<Option<(P::Bounds,u64)>,Error> {
     match self.range.cache.get(&offset) {
@@ -212,6 +625,36 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   }
 }
 
+/// Index of the data blocks in a `data` store, kept in the `range` store.
+///
+/// Every data block written to the `data` store appends an
+/// `(offset, range, len)` entry here: the byte offset of the block, the
+/// bounding range of its points and the number of rows it was written with.
+/// Blocks that were merged into larger blocks keep their entries, so the same
+/// rows can appear under more than one entry.
+///
+/// External tools can use a `DataRange` to find and read blocks from eyros
+/// data files without opening a `DB`:
+///
+/// ```rust,no_run
+/// # use eyros::DataRange;
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # fn main () -> Result<(),Error> {
+/// type P = ((f32,f32),(f32,f32));
+/// let mut ranges: DataRange<_,P> = DataRange::new(
+///   RandomAccessDisk::builder("/tmp/eyros-db/range".into()).build()?, 0
+/// );
+/// let mut data = RandomAccessDisk::builder("/tmp/eyros-db/data".into())
+///   .build()?;
+/// let bbox = ((-0.5,-0.5),(0.5,0.5));
+/// for entry in ranges.filter(&bbox)? {
+///   let (offset,_range,len) = entry?;
+///   let block = ranges.block(&mut data, offset)?;
+///   println!["{} rows in {} bytes at {}", len, block.len(), offset];
+/// }
+/// # Ok(()) }
+/// ```
 pub struct DataRange<S,P>
 where S: RandomAccess<Error=Error>, P: Point {
   pub store: S,
@@ -220,17 +663,23 @@ where S: RandomAccess<Error=Error>, P: Point {
 
 impl<S,P> DataRange<S,P>
 where S: RandomAccess<Error=Error>, P: Point {
+  /// Create a `DataRange` from a `range` store, with an lru cache of up to
+  /// `cache_size` block bounding boxes.
   pub fn new (store: S, cache_size: usize) -> Self {
     Self {
       store,
       cache: LruCache::new(cache_size)
     }
   }
+  /// Append an `(offset, range, len)` entry to the end of the store.
   pub fn write (&mut self, b: &(u64,P::Range,u64)) -> Result<(),Error> {
     let offset = self.store.len()?;
     let data = b.to_bytes()?;
     self.store.write(offset, &data)
   }
+  /// Read every entry, decoding ranges as `P`. This only works when
+  /// `P::Range` is `P`, as it is for points made entirely of intervals. Use
+  /// `iter()` for other point types.
   pub fn list (&mut self) -> Result<Vec<(u64,P,u64)>,Error> {
     let len = self.store.len()?;
     // TODO: read in chunks instead of all at once
@@ -244,4 +693,67 @@ where S: RandomAccess<Error=Error>, P: Point {
     }
     Ok(results)
   }
+  /// Iterate over every `(offset, range, len)` entry in the order the blocks
+  /// were written.
+  pub fn iter (&mut self) -> Result<DataRangeIterator<P>,Error> {
+    let len = self.store.len()?;
+    // TODO: read in chunks instead of all at once
+    let buf = if len > 0 { self.store.read(0, len)? } else { vec![] };
+    Ok(DataRangeIterator { buf, offset: 0, bbox: None })
+  }
+  /// Iterate over the entries whose range intersects `bbox`.
+  pub fn filter (&mut self, bbox: &<P::Range as PointOrd>::Bounds)
+  -> Result<DataRangeIterator<P>,Error> {
+    let mut iter = self.iter()?;
+    iter.bbox = Some(*bbox);
+    Ok(iter)
+  }
+  /// Read the bytes of the data block at `offset` in the `data` store, from
+  /// an entry's offset. The 4-byte length prefix is removed, so the result
+  /// starts with the `u16` length of the bitfield that marks which rows are
+  /// live, followed by the bitfield and the serialized `(P,V)` rows. If the
+  /// high bit of the bitfield length is set, a `[length: u16][bounds]` header
+  /// with the bounding box of the rows comes between the bitfield and the
+  /// rows. If the next bit is set, the rows are bit-packed, as written with
+  /// `Setup::pack_coords()`. If the third bit is set, a second bitfield
+  /// follows the bounds header and the rows marked in it hold the `u64`
+  /// offset and `u32` length of their serialized value in the `blobs` store
+  /// instead of the value, as written with `Setup::inline_threshold()`.
+  /// Databases with `Setup::quantize()` write each coordinate of the points
+  /// as an `i32` step instead, see `Quantize`.
+  pub fn block<D> (&self, data: &mut D, offset: u64) -> Result<Vec<u8>,Error>
+  where D: RandomAccess<Error=Error> {
+    let len = data.len()?;
+    read_block(data, offset, len, 1024)
+  }
+}
+
+/// Iterator of `Result<(offset, range, len)>` entries returned by
+/// `ranges.iter()` and `ranges.filter()`.
+pub struct DataRangeIterator<P> where P: Point {
+  buf: Vec<u8>,
+  offset: usize,
+  bbox: Option<<P::Range as PointOrd>::Bounds>
+}
+
+impl<P> Iterator for DataRangeIterator<P> where P: Point {
+  type Item = Result<(u64,P::Range,u64),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    while self.offset < self.buf.len() {
+      let entry = <(u64,P::Range,u64)>::from_bytes(&self.buf[self.offset..]);
+      let (size,entry) = match entry {
+        Ok(x) => x,
+        Err(e) => {
+          self.offset = self.buf.len();
+          return Some(Err(e));
+        }
+      };
+      self.offset += size;
+      match &self.bbox {
+        Some(bbox) if !entry.1.overlaps(bbox) => {},
+        _ => return Some(Ok(entry))
+      }
+    }
+    None
+  }
 }
@@ -0,0 +1,29 @@
+use crate::{DB,Point,Value,Row};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::collections::HashSet;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Drop inserts whose point and value are both identical to a record that is
+  /// already stored or that appeared earlier in `rows`, for
+  /// `Setup::dedup()`. Deletes are kept as-is.
+  pub(crate) fn dedup (&mut self, rows: &[Row<P,V>])
+  -> Result<Vec<Row<P,V>>,Error> {
+    let mut seen: HashSet<Vec<u8>> = HashSet::new();
+    let mut results = Vec::with_capacity(rows.len());
+    for row in rows.iter() {
+      if let Row::Insert(p,v) = row {
+        let mut key = p.to_bytes()?;
+        key.extend(v.to_bytes()?);
+        if !seen.insert(key) || self.contains_row(p, Some(v))? {
+          continue;
+        }
+      }
+      results.push(row.clone());
+    }
+    Ok(results)
+  }
+}
@@ -0,0 +1,118 @@
+use crate::Location;
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use roaring::RoaringBitmap;
+use std::collections::BTreeMap;
+
+/// Deleted row slots of each data block as roaring bitmaps, kept in the
+/// `delete_bitmap` store when enabled with `Setup::delete_bitmap()`.
+///
+/// Deleting a record from a tree only adds its slot to the bitmap of its data
+/// block, which queries consult when a block is loaded, so deletes are visible
+/// immediately without rewriting any data blocks. The bitmaps are applied to
+/// the data block bitfields when they grow past the base size, and rows
+/// deleted through a bitmap are dropped when their tree is merged.
+///
+/// The store is a log of `[block offset: u64][length: u32][bitmap]` entries
+/// where the latest entry for a block holds every deleted slot of that block.
+pub struct DeleteBitmap<S> where S: RandomAccess<Error=Error> {
+  pub store: S,
+  blocks: BTreeMap<u64,RoaringBitmap>,
+  count: u64
+}
+
+impl<S> DeleteBitmap<S> where S: RandomAccess<Error=Error> {
+  pub fn open (mut store: S) -> Result<Self,Error> {
+    let mut blocks = BTreeMap::new();
+    let len = store.len()?;
+    let buf = if len > 0 { store.read(0, len)? } else { vec![] };
+    let mut offset = 0;
+    while offset < buf.len() {
+      if offset + 12 > buf.len() {
+        bail!["truncated delete bitmap entry at {}", offset];
+      }
+      let mut b = [0u8;8];
+      b.copy_from_slice(&buf[offset..offset+8]);
+      let block = u64::from_be_bytes(b);
+      let size = u32::from_be_bytes([
+        buf[offset+8], buf[offset+9], buf[offset+10], buf[offset+11]
+      ]) as usize;
+      offset += 12;
+      if offset + size > buf.len() {
+        bail!["truncated delete bitmap entry at {}", offset-12];
+      }
+      let bitmap = RoaringBitmap::deserialize_from(&buf[offset..offset+size])?;
+      blocks.insert(block, bitmap);
+      offset += size;
+    }
+    let count = blocks.values().map(|b| b.len()).sum();
+    Ok(Self { store, blocks, count })
+  }
+
+  /// Number of deleted slots across every block.
+  pub fn len (&self) -> u64 {
+    self.count
+  }
+
+  pub fn is_empty (&self) -> bool {
+    self.count == 0
+  }
+
+  /// Whether slot `index` of the data block at `block` is deleted.
+  pub fn contains (&self, block: u64, index: u32) -> bool {
+    self.blocks.get(&block).map(|b| b.contains(index)).unwrap_or(false)
+  }
+
+  /// Deleted slots of the data block at `block`.
+  pub fn get (&self, block: u64) -> Option<&RoaringBitmap> {
+    self.blocks.get(&block)
+  }
+
+  /// Add tree `locations` to the bitmaps, returning the data block offsets
+  /// that changed. Staging locations are ignored.
+  pub fn insert (&mut self, locations: &[Location]) -> Result<Vec<u64>,Error> {
+    let mut changed = vec![];
+    for (block,index) in locations.iter() {
+      if *block == 0 { continue } // staging block
+      let bitmap = self.blocks.entry(*block-1)
+        .or_insert_with(RoaringBitmap::new);
+      if bitmap.insert(*index) {
+        self.count += 1;
+        changed.push(*block-1);
+      }
+    }
+    changed.sort_unstable();
+    changed.dedup();
+    let mut buf = vec![];
+    for block in changed.iter() {
+      let bitmap = &self.blocks[block];
+      buf.extend_from_slice(&block.to_be_bytes());
+      buf.extend_from_slice(&(bitmap.serialized_size() as u32).to_be_bytes());
+      bitmap.serialize_into(&mut buf)?;
+    }
+    if !buf.is_empty() {
+      let offset = self.store.len()?;
+      self.store.write(offset, &buf)?;
+    }
+    Ok(changed)
+  }
+
+  /// Every deleted slot as a tree location.
+  pub fn locations (&self) -> Vec<Location> {
+    let mut locations = Vec::with_capacity(self.count as usize);
+    for (block,bitmap) in self.blocks.iter() {
+      locations.extend(bitmap.iter().map(|i| (block+1,i)));
+    }
+    locations
+  }
+
+  pub fn clear (&mut self) -> Result<(),Error> {
+    self.blocks.clear();
+    self.count = 0;
+    self.store.truncate(0)
+  }
+
+  pub fn commit (&mut self) -> Result<(),Error> {
+    self.store.sync_all()
+  }
+}
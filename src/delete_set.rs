@@ -0,0 +1,94 @@
+use crate::Location;
+use roaring::RoaringBitmap;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+/// Set of deleted locations kept as one roaring bitmap of indexes for each
+/// block, where the block is `0` for the staging area or the data block offset
+/// plus one for a tree.
+///
+/// Deleted indexes within a block are dense, so the bitmaps take a few bits
+/// per delete instead of a hash table entry, and lookups touch a single
+/// compressed container.
+#[derive(Clone,Debug,Default)]
+pub struct DeleteSet {
+  blocks: HashMap<u64,RoaringBitmap>,
+  count: usize
+}
+
+impl DeleteSet {
+  pub fn new () -> Self {
+    Self::default()
+  }
+  pub fn len (&self) -> usize {
+    self.count
+  }
+  pub fn is_empty (&self) -> bool {
+    self.count == 0
+  }
+  pub fn contains (&self, loc: &Location) -> bool {
+    self.blocks.get(&loc.0).map(|b| b.contains(loc.1)).unwrap_or(false)
+  }
+  /// Add `loc`, returning whether it was not already present.
+  pub fn insert (&mut self, loc: Location) -> bool {
+    let added = self.blocks.entry(loc.0).or_default().insert(loc.1);
+    if added { self.count += 1 }
+    added
+  }
+  /// Remove `loc`, returning whether it was present.
+  pub fn remove (&mut self, loc: &Location) -> bool {
+    let removed = match self.blocks.get_mut(&loc.0) {
+      Some(bitmap) => bitmap.remove(loc.1),
+      None => false
+    };
+    if removed {
+      self.count -= 1;
+      if self.blocks[&loc.0].is_empty() {
+        self.blocks.remove(&loc.0);
+      }
+    }
+    removed
+  }
+  pub fn clear (&mut self) {
+    self.blocks.clear();
+    self.count = 0;
+  }
+  /// Keep only the locations for which `f` returns `true`.
+  pub fn retain<F> (&mut self, mut f: F) where F: FnMut(&Location) -> bool {
+    for (block,bitmap) in self.blocks.iter_mut() {
+      let removed: Vec<u32> = bitmap.iter()
+        .filter(|i| !f(&(*block,*i)))
+        .collect();
+      for i in removed {
+        bitmap.remove(i);
+      }
+    }
+    self.blocks.retain(|_,bitmap| !bitmap.is_empty());
+    self.count = self.blocks.values().map(|b| b.len() as usize).sum();
+  }
+  /// Blocks with at least one deleted location.
+  pub fn blocks (&self) -> impl Iterator<Item=u64>+'_ {
+    self.blocks.keys().copied()
+  }
+  pub fn iter (&self) -> impl Iterator<Item=Location>+'_ {
+    self.blocks.iter().flat_map(|(block,bitmap)| {
+      bitmap.iter().map(move |i| (*block,i))
+    })
+  }
+}
+
+impl Extend<Location> for DeleteSet {
+  fn extend<I> (&mut self, iter: I) where I: IntoIterator<Item=Location> {
+    for loc in iter {
+      self.insert(loc);
+    }
+  }
+}
+
+impl FromIterator<Location> for DeleteSet {
+  fn from_iter<I> (iter: I) -> Self where I: IntoIterator<Item=Location> {
+    let mut set = Self::new();
+    set.extend(iter);
+    set
+  }
+}
@@ -0,0 +1,48 @@
+use crate::{DB,Point,Value,Location};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query for the records that intersect `include` but not `exclude`, such
+  /// as the records that come into view when a map is panned from the
+  /// `exclude` viewport to the `include` one.
+  ///
+  /// Data blocks whose bounds are entirely within `exclude` are skipped
+  /// without being read, so the cost of the query scales with the newly
+  /// exposed area rather than with all of `include`. Results are collected
+  /// into a `Vec` instead of being returned as a lazy iterator.
+  pub fn query_difference (&mut self, include: &P::Bounds,
+  exclude: &P::Bounds) -> Result<Vec<(P,V,Location)>,Error> {
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    let mut results = vec![];
+    self.staging.for_each(|p,v,loc| {
+      if p.overlaps(include) && !p.overlaps(exclude) {
+        results.push((*p,v.clone(),loc));
+      }
+      Ok(())
+    })?;
+    let mut blocks = vec![];
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      blocks.extend(tree.query_blocks(include)?);
+    }
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    for offset in blocks {
+      match dstore.bbox(offset)? {
+        None => continue,
+        Some((b,_)) if P::bounds_within(&b, exclude) => continue,
+        Some(_) => {}
+      }
+      for (p,v,loc) in dstore.query(offset, include)? {
+        if p.overlaps(exclude) || deletes.contains(&loc)
+        || dstore.is_deleted(loc.0-1, loc.1) { continue }
+        results.push((p,v,loc));
+      }
+    }
+    Ok(results)
+  }
+}
@@ -0,0 +1,223 @@
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::fs::{self,File,OpenOptions};
+use std::io::{self,Read,Seek,SeekFrom,Write};
+use std::path::{Path,PathBuf};
+
+/// Block size that direct io offsets, lengths and buffers are aligned to.
+pub const DIRECT_ALIGN: usize = 4096;
+
+/// How a `DiskStore` moves data between memory and disk.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum IoMode {
+  /// Ordinary reads and writes through the OS page cache.
+  #[default]
+  Buffered,
+  /// Bypass the page cache with `O_DIRECT` and block-aligned io, so that a
+  /// large bulk load does not evict the cached working set of other
+  /// processes. Unaligned writes cost a read of the partial blocks at either
+  /// end, so this mode is meant for loading a database, not for serving
+  /// queries.
+  ///
+  /// Only available on linux. On other platforms and on filesystems that
+  /// reject `O_DIRECT` (such as tmpfs) the store falls back to buffered io;
+  /// check `store.io_mode()`.
+  Direct
+}
+
+#[repr(C, align(4096))]
+#[derive(Clone,Copy)]
+struct Block([u8;DIRECT_ALIGN]);
+
+// zeroed buffer with DIRECT_ALIGN alignment
+struct AlignedBuf {
+  blocks: Vec<Block>
+}
+
+impl AlignedBuf {
+  fn new (len: usize) -> Self {
+    Self { blocks: vec![Block([0;DIRECT_ALIGN]);len/DIRECT_ALIGN] }
+  }
+  fn as_slice (&self) -> &[u8] {
+    let len = self.blocks.len()*DIRECT_ALIGN;
+    // safety: Block is repr(C) over a byte array with no padding
+    unsafe {
+      std::slice::from_raw_parts(self.blocks.as_ptr() as *const u8, len)
+    }
+  }
+  fn as_mut_slice (&mut self) -> &mut [u8] {
+    let len = self.blocks.len()*DIRECT_ALIGN;
+    unsafe {
+      std::slice::from_raw_parts_mut(self.blocks.as_mut_ptr() as *mut u8, len)
+    }
+  }
+}
+
+/// File store with a configurable `IoMode`.
+///
+/// In `IoMode::Direct`, the file on disk may be padded with zeros up to the
+/// next block boundary while a write is in progress and is truncated back to
+/// the logical length afterward.
+pub struct DiskStore {
+  file: File,
+  mode: IoMode,
+  length: u64
+}
+
+impl DiskStore {
+  /// Open or create the file at `path` with `mode`, creating parent
+  /// directories as needed.
+  pub fn open<P> (path: P, mode: IoMode) -> Result<Self,Error>
+  where P: AsRef<Path> {
+    if let Some(dir) = path.as_ref().parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let (file,mode) = match mode {
+      IoMode::Buffered => (open_file(path.as_ref(), false)?, mode),
+      IoMode::Direct => match open_file(path.as_ref(), true) {
+        Ok(file) => (file, mode),
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+          (open_file(path.as_ref(), false)?, IoMode::Buffered)
+        },
+        Err(e) => return Err(e.into())
+      }
+    };
+    let length = file.metadata()?.len();
+    Ok(Self { file, mode, length })
+  }
+
+  /// Mode this store is using, which is `IoMode::Buffered` if direct io was
+  /// requested but is not supported for this file.
+  pub fn io_mode (&self) -> IoMode {
+    self.mode
+  }
+
+  // read the aligned range start..end, zero-filling past the end of the file
+  fn read_aligned (&mut self, start: u64, end: u64)
+  -> Result<AlignedBuf,Error> {
+    let mut buf = AlignedBuf::new((end-start) as usize);
+    let avail = self.length.saturating_sub(start).min(end-start) as usize;
+    if avail > 0 {
+      let n = align_up(avail as u64) as usize;
+      self.file.seek(SeekFrom::Start(start))?;
+      let slice = &mut buf.as_mut_slice()[..n];
+      let mut read = 0;
+      while read < n {
+        match self.file.read(&mut slice[read..])? {
+          0 => break,
+          m => read += m
+        }
+      }
+    }
+    Ok(buf)
+  }
+}
+
+impl RandomAccess for DiskStore {
+  type Error = Error;
+  fn write (&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    if data.is_empty() { return Ok(()) }
+    let end = offset + data.len() as u64;
+    if self.mode == IoMode::Buffered {
+      self.file.seek(SeekFrom::Start(offset))?;
+      self.file.write_all(data)?;
+    } else {
+      let start = align_down(offset);
+      let mut buf = self.read_aligned(start, align_up(end))?;
+      let i = (offset-start) as usize;
+      buf.as_mut_slice()[i..i+data.len()].copy_from_slice(data);
+      self.file.seek(SeekFrom::Start(start))?;
+      self.file.write_all(buf.as_slice())?;
+      let length = self.length.max(end);
+      if align_up(length) != length {
+        self.file.set_len(length)?;
+      }
+    }
+    self.length = self.length.max(end);
+    Ok(())
+  }
+  fn read (&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    let end = offset + length;
+    if end > self.length {
+      bail!["read {}..{} past the end of the file ({})",
+        offset, end, self.length];
+    }
+    if self.mode == IoMode::Buffered {
+      let mut buf = vec![0u8;length as usize];
+      self.file.seek(SeekFrom::Start(offset))?;
+      self.file.read_exact(&mut buf)?;
+      return Ok(buf);
+    }
+    let start = align_down(offset);
+    let buf = self.read_aligned(start, align_up(end))?;
+    let i = (offset-start) as usize;
+    Ok(buf.as_slice()[i..i+length as usize].to_vec())
+  }
+  fn read_to_writer (&mut self, offset: u64, length: u64,
+  buf: &mut impl Write) -> Result<(),Error> {
+    let data = self.read(offset, length)?;
+    buf.write_all(&data)?;
+    Ok(())
+  }
+  fn del (&mut self, _offset: u64, _length: u64) -> Result<(),Error> {
+    bail!["del is not supported on disk stores"]
+  }
+  fn truncate (&mut self, length: u64) -> Result<(),Error> {
+    self.file.set_len(length)?;
+    self.length = length;
+    Ok(())
+  }
+  fn len (&self) -> Result<u64,Error> {
+    Ok(self.length)
+  }
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    Ok(self.length == 0)
+  }
+  fn sync_all (&mut self) -> Result<(),Error> {
+    self.file.sync_all()?;
+    Ok(())
+  }
+}
+
+/// Build a storage function for `Setup::new()` or `DB::open()` that opens
+/// each store as a `DiskStore` in the directory `dir` with `mode`.
+///
+/// Use `IoMode::Direct` for an initial bulk load and reopen the database with
+/// `IoMode::Buffered` to serve queries.
+///
+/// A borrowed `dir` ties the storage function to the borrow, so pass an owned
+/// path such as a `PathBuf` where it must be `'static`, as with
+/// `db.executor()` and `db.query_into()`.
+pub fn disk_storage (dir: impl Into<PathBuf>, mode: IoMode)
+-> impl Fn(&str) -> Result<DiskStore,Error>+Clone+Send {
+  let dir: PathBuf = dir.into();
+  move |name: &str| DiskStore::open(dir.join(name), mode)
+}
+
+fn align_down (x: u64) -> u64 {
+  x / (DIRECT_ALIGN as u64) * (DIRECT_ALIGN as u64)
+}
+
+fn align_up (x: u64) -> u64 {
+  align_down(x + DIRECT_ALIGN as u64 - 1)
+}
+
+#[cfg(target_os="linux")]
+fn open_file (path: &Path, direct: bool) -> io::Result<File> {
+  use std::os::unix::fs::OpenOptionsExt;
+  let mut opts = OpenOptions::new();
+  opts.read(true).write(true).create(true);
+  if direct {
+    opts.custom_flags(libc::O_DIRECT);
+  }
+  opts.open(path)
+}
+
+#[cfg(not(target_os="linux"))]
+fn open_file (path: &Path, direct: bool) -> io::Result<File> {
+  if direct {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+      "direct io is only supported on linux"));
+  }
+  OpenOptions::new().read(true).write(true).create(true).open(path)
+}
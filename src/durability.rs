@@ -0,0 +1,102 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration,Instant};
+
+/// When a database calls `sync_all()` on its stores, configured with
+/// `Setup::durability()`.
+///
+/// The policy controls how often writes are flushed to durable storage, so
+/// batches written after the last sync may be lost if the process or machine
+/// crashes. Between syncs, staging writes are held in memory up to
+/// `Setup::write_cache_size()`.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum Durability {
+  /// Sync the stores written by every batch before it returns.
+  #[default]
+  Always,
+  /// Sync every store after every `n` batches.
+  EveryNBatches(usize),
+  /// Sync every store at the end of the first batch after `duration` has
+  /// passed since the last sync. Nothing is synced between batches.
+  Interval(Duration),
+  /// Only sync when `db.sync()` is called.
+  Never
+}
+
+// batch counter and clock for a durability policy
+pub(crate) struct SyncState {
+  pub policy: Durability,
+  // whether stores sync inline as they are written, shared with the data store
+  // and every tree
+  pub inline: Rc<Cell<bool>>,
+  batches: usize,
+  last: Instant
+}
+
+impl SyncState {
+  pub fn new (policy: Durability) -> Self {
+    Self {
+      policy,
+      inline: Rc::new(Cell::new(policy == Durability::Always)),
+      batches: 0,
+      last: Instant::now()
+    }
+  }
+  // record a batch and return whether every store should be synced now
+  fn after_batch (&mut self) -> bool {
+    self.batches += 1;
+    match self.policy {
+      Durability::Always | Durability::Never => false,
+      Durability::EveryNBatches(n) => self.batches >= n.max(1),
+      Durability::Interval(d) => self.last.elapsed() >= d
+    }
+  }
+  fn reset (&mut self) {
+    self.batches = 0;
+    self.last = Instant::now();
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Flush the staging write cache and sync the meta, data and tree stores
+  /// along with the bloom filter and batch metadata stores, regardless of the
+  /// `Setup::durability()` policy.
+  ///
+  /// With a policy other than `Durability::Always`, staging writes are held
+  /// in memory until the next sync (see `Setup::write_cache_size()`), so call
+  /// this before exiting to make sure that every batch is durable.
+  pub fn sync (&mut self) -> Result<(),Error> {
+    let inline = self.sync_state.inline.replace(true);
+    let result = self.sync_stores();
+    self.sync_state.inline.set(inline);
+    result?;
+    self.sync_state.reset();
+    Ok(())
+  }
+
+  fn sync_stores (&mut self) -> Result<(),Error> {
+    self.staging.commit()?;
+    self.data_store.try_borrow_mut()?.commit()?;
+    for tree in self.trees.iter() {
+      tree.try_borrow_mut()?.store.sync_all()?;
+    }
+    if let Some(ids) = &mut self.batch_ids {
+      ids.store.sync_all()?;
+    }
+    self.meta.store.sync_all()
+  }
+
+  // called at the end of every batch to apply the durability policy
+  pub(crate) fn sync_after_batch (&mut self) -> Result<(),Error> {
+    if self.sync_state.after_batch() {
+      self.sync()?;
+    }
+    Ok(())
+  }
+}
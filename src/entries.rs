@@ -0,0 +1,68 @@
+use crate::{DB,Point,Value};
+use crate::meta::FORMAT_ENTRIES;
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Store `bytes` under `key` in the `meta` store, replacing any previous
+  /// value, so that applications can keep attribution, import provenance or
+  /// their own schema versions alongside the records instead of in a side
+  /// file. Keys are free-form; prefix them with the name of the application
+  /// to avoid collisions.
+  ///
+  /// The whole meta store is rewritten on every change, so entries are meant
+  /// for small values. Entries need format version 8, which new databases are
+  /// written in and which earlier versions of eyros refuse to open. Upgrade an
+  /// existing database with `db.migrate()` first.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{DB,Setup,IoMode,disk_storage};
+  /// # use failure::Error;
+  /// # fn main () -> Result<(),Error> {
+  /// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+  /// let mut db: DB<_,_,(f32,f32),u32> = Setup::new(storage).build()?;
+  /// db.meta_put("attribution", b"(c) OpenStreetMap contributors")?;
+  /// if let Some(bytes) = db.meta_get("attribution") {
+  ///   println!["{}", String::from_utf8_lossy(bytes)];
+  /// }
+  /// # Ok(()) }
+  /// ```
+  pub fn meta_put (&mut self, key: &str, bytes: &[u8]) -> Result<(),Error> {
+    self.meta.require_format(FORMAT_ENTRIES, "db.meta_put()")?;
+    self.meta.entries.insert(key.to_string(), bytes.to_vec());
+    self.save_entries()
+  }
+
+  /// Bytes stored under `key` with `db.meta_put()`, if any.
+  pub fn meta_get (&self, key: &str) -> Option<&[u8]> {
+    self.meta.entries.get(key).map(|bytes| bytes.as_slice())
+  }
+
+  /// Remove the entry stored under `key` and return its bytes, if any.
+  pub fn meta_delete (&mut self, key: &str) -> Result<Option<Vec<u8>>,Error> {
+    let removed = self.meta.entries.remove(key);
+    if removed.is_some() {
+      self.save_entries()?;
+    }
+    Ok(removed)
+  }
+
+  /// Keys of every entry stored with `db.meta_put()`, in sorted order.
+  pub fn meta_keys (&self) -> impl Iterator<Item=&str> {
+    self.meta.entries.keys().map(|key| key.as_str())
+  }
+
+  // write the meta store, syncing it unless the durability policy defers
+  // syncs to db.sync()
+  fn save_entries (&mut self) -> Result<(),Error> {
+    self.invalidate();
+    self.save_meta()?;
+    if self.sync_state.inline.get() {
+      self.meta.store.sync_all()?;
+    }
+    Ok(())
+  }
+}
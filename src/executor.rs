@@ -0,0 +1,216 @@
+use crate::{DB,Setup,SetupFields,Point,Value,Location,DeleteSet};
+use failure::{Error,format_err};
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc,Mutex,atomic::{AtomicBool,AtomicUsize,Ordering}};
+use std::sync::mpsc::{self,Sender,SyncSender,Receiver};
+use std::thread::{self,JoinHandle};
+
+type ResultSender<P,V> = Sender<Result<(P,V,Location),Error>>;
+
+enum Job<P,V> where P: Point, V: Value {
+  Query(P::Bounds,ResultSender<P,V>),
+  Trees(TreeJob<P,V>)
+}
+
+// part of a db.query_parallel() query: the trees at the positions in `trees`
+// leaving out the records at the locations in `deletes`, sent until the
+// query is cancelled
+pub(crate) struct TreeJob<P,V> where P: Point, V: Value {
+  pub bbox: P::Bounds,
+  pub trees: Vec<usize>,
+  pub deletes: Arc<DeleteSet>,
+  pub cancel: Arc<AtomicBool>,
+  pub sender: SyncSender<Result<(P,V,Location),Error>>
+}
+
+impl<P,V> Job<P,V> where P: Point, V: Value {
+  fn fail (&self, err: Error) {
+    let _ = match self {
+      Job::Query(_,sender) => sender.send(Err(err)),
+      Job::Trees(job) => job.sender.send(Err(err))
+    };
+  }
+}
+
+/// Pool of worker threads that serve queries from a shared queue, each with
+/// its own read view of the database, created with `db.executor()`.
+///
+/// The database can not be shared between threads, so each worker opens the
+/// stores again with the storage function and settings of the database and
+/// keeps them open between queries. Views are opened when a worker takes
+/// its first query and do not see batches written after that until
+/// `executor.refresh()` is called.
+///
+/// ```rust,no_run
+/// use eyros::{DB,Setup,IoMode,disk_storage};
+/// # use failure::Error;
+///
+/// # fn main () -> Result<(),Error> {
+/// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+/// let mut db: DB<_,_,(f32,f32),u32> = Setup::new(storage).build()?;
+/// let executor = db.executor(4)?;
+/// let receivers: Vec<_> = (0..8).map(|i| {
+///   let x = i as f32 * 10.0;
+///   executor.query(((x,-10.0),(x+10.0,10.0)))
+/// }).collect();
+/// for receiver in receivers {
+///   let n = receiver.iter().collect::<Result<Vec<_>,Error>>()?.len();
+///   println!["{} records", n];
+/// }
+/// # Ok(()) }
+/// ```
+///
+/// Dropping the executor waits for the queued queries to finish.
+pub struct Executor<P,V> where P: Point, V: Value {
+  jobs: Option<Sender<Job<P,V>>>,
+  workers: Vec<JoinHandle<()>>,
+  version: Arc<AtomicUsize>
+}
+
+impl<P,V> Executor<P,V>
+where P: Point+Send+'static, V: Value+Send, P::Bounds: Send+'static {
+  /// Start `threads` workers that open their views with `open_store` and
+  /// `fields`. Prefer `db.executor()`, which syncs the database first.
+  pub fn new<S,U> (open_store: U, fields: SetupFields, threads: usize)
+  -> Self where
+  S: RandomAccess<Error=Error>+'static,
+  U: (Fn(&str) -> Result<S,Error>)+Clone+Send+'static {
+    Self::start(open_store, fields, threads, true)
+  }
+  // start the workers, whose views leave staging out unless `load_staging`
+  // is set
+  pub(crate) fn start<S,U> (open_store: U, fields: SetupFields,
+  threads: usize, load_staging: bool) -> Self where
+  S: RandomAccess<Error=Error>+'static,
+  U: (Fn(&str) -> Result<S,Error>)+Clone+Send+'static {
+    let (sender,receiver) = mpsc::channel();
+    let receiver = Arc::new(Mutex::new(receiver));
+    let version = Arc::new(AtomicUsize::new(0));
+    let workers = (0..threads.max(1)).map(|_| {
+      let open_store = open_store.clone();
+      let fields = fields.clone();
+      let receiver = Arc::clone(&receiver);
+      let version = Arc::clone(&version);
+      thread::spawn(move || {
+        work::<S,U,P,V>(open_store, fields, load_staging, receiver, version)
+      })
+    }).collect();
+    Self { jobs: Some(sender), workers, version }
+  }
+  /// Queue a query for `bbox` and return a receiver of its results. The
+  /// results are sent as they are read, so the channel is unbounded to keep
+  /// a slow consumer from holding up the workers. An error ends the results
+  /// of the query.
+  pub fn query (&self, bbox: P::Bounds)
+  -> Receiver<Result<(P,V,Location),Error>> {
+    let (sender,receiver) = mpsc::channel();
+    self.send(Job::Query(bbox,sender));
+    receiver
+  }
+  // queue a part of a db.query_parallel() query
+  pub(crate) fn query_trees (&self, job: TreeJob<P,V>) {
+    self.send(Job::Trees(job));
+  }
+  fn send (&self, job: Job<P,V>) {
+    if let Some(jobs) = &self.jobs {
+      if let Err(mpsc::SendError(job)) = jobs.send(job) {
+        job.fail(format_err!["executor workers have stopped"]);
+      }
+    }
+  }
+}
+
+impl<P,V> Executor<P,V> where P: Point, V: Value {
+  /// Reopen the view of each worker before its next query, to see the
+  /// batches written since. Sync the database with `db.sync()` first.
+  pub fn refresh (&self) {
+    self.version.fetch_add(1, Ordering::SeqCst);
+  }
+  /// Number of worker threads.
+  pub fn threads (&self) -> usize {
+    self.workers.len()
+  }
+}
+
+impl<P,V> Drop for Executor<P,V> where P: Point, V: Value {
+  fn drop (&mut self) {
+    // workers stop once the queue is closed and empty
+    self.jobs.take();
+    for worker in self.workers.drain(..) {
+      let _ = worker.join();
+    }
+  }
+}
+
+// serve queries from `jobs` until the queue is closed, reopening the view
+// whenever `version` changes
+fn work<S,U,P,V> (open_store: U, fields: SetupFields, load_staging: bool,
+jobs: Arc<Mutex<Receiver<Job<P,V>>>>, version: Arc<AtomicUsize>) where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>)+Clone,
+P: Point, V: Value {
+  let mut view: Option<(usize,DB<S,U,P,V>)> = None;
+  loop {
+    let job = match jobs.lock() {
+      Ok(jobs) => jobs.recv(),
+      Err(_) => return
+    };
+    let job = match job {
+      Ok(job) => job,
+      Err(_) => return
+    };
+    let current = version.load(Ordering::SeqCst);
+    if view.as_ref().map(|(v,_)| *v != current).unwrap_or(true) {
+      view = None;
+      let mut setup = Setup::new(open_store.clone());
+      setup.fields = fields.clone();
+      match DB::open_view(setup, load_staging) {
+        Ok(db) => view = Some((current,db)),
+        Err(err) => { job.fail(err); continue }
+      }
+    }
+    let db = match &mut view {
+      Some((_,db)) => db,
+      None => continue
+    };
+    match job {
+      Job::Query(bbox,sender) => match db.query(&bbox) {
+        Ok(results) => {
+          for result in results {
+            let failed = result.is_err();
+            if sender.send(result).is_err() || failed { break }
+          }
+        },
+        Err(err) => { let _ = sender.send(Err(err)); }
+      },
+      Job::Trees(job) => {
+        let deletes = Rc::new(RefCell::new((*job.deletes).clone()));
+        match db.query_trees(&job.bbox, &job.trees, deletes) {
+          Ok(results) => {
+            for result in results {
+              if job.cancel.load(Ordering::SeqCst) { break }
+              let failed = result.is_err();
+              if job.sender.send(result).is_err() || failed { break }
+            }
+          },
+          Err(err) => { let _ = job.sender.send(Err(err)); }
+        }
+      }
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>+'static,
+U: (Fn(&str) -> Result<S,Error>)+Clone+Send+'static,
+P: Point+Send+'static, V: Value+Send, P::Bounds: Send+'static {
+  /// Sync the database and start an `Executor` with `threads` workers that
+  /// serve queries in parallel. The storage function must be `Send` and
+  /// `Clone`, as with `db.query_into()`.
+  pub fn executor (&mut self, threads: usize) -> Result<Executor<P,V>,Error> {
+    self.sync()?;
+    Ok(Executor::new(self.open_store.clone(), self.fields.clone(), threads))
+  }
+}
@@ -0,0 +1,81 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+/// Trace of the work done to answer a query, returned by
+/// `db.query_explain()`.
+#[derive(Clone,Debug,Default)]
+pub struct Explain {
+  /// Number of records scanned in the staging area.
+  pub staging_rows: usize,
+  /// Number of staging records that matched the query.
+  pub staging_results: usize,
+  /// One trace for each non-empty tree, in tree order.
+  pub trees: Vec<TreeTrace>
+}
+
+/// Work done while walking a single tree.
+#[derive(Clone,Debug,Default)]
+pub struct TreeTrace {
+  /// Index of the tree in the forest (`tree0`, `tree1`, ...).
+  pub index: usize,
+  /// Branches and data blocks at each branch level, starting from the root.
+  pub levels: Vec<LevelTrace>,
+  /// Bytes read from branch and data blocks.
+  pub bytes: u64,
+  /// Number of records from this tree that matched the query.
+  pub results: usize
+}
+
+/// Work done at one branch level of a tree.
+#[derive(Clone,Debug,Default)]
+pub struct LevelTrace {
+  /// Branch blocks read at this level.
+  pub branches: usize,
+  /// Child branches skipped because they do not intersect the query.
+  pub branches_pruned: usize,
+  /// Data blocks read from branches at this level.
+  pub blocks: usize,
+  /// Data blocks skipped because they do not intersect the query.
+  pub blocks_pruned: usize
+}
+
+impl Explain {
+  /// Total bytes read from every tree.
+  pub fn bytes (&self) -> u64 {
+    self.trees.iter().map(|t| t.bytes).sum()
+  }
+  /// Total number of matching records, including staging.
+  pub fn results (&self) -> usize {
+    self.staging_results + self.trees.iter().map(|t| t.results).sum::<usize>()
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Run a query for `bbox` and return a trace of which trees, branch levels
+  /// and data blocks were visited, how many were pruned and how many bytes
+  /// were read, instead of the results themselves.
+  ///
+  /// Data blocks are read from storage directly so that `bytes` reflects the
+  /// IO a cold query would perform.
+  pub fn query_explain (&mut self, bbox: &P::Bounds) -> Result<Explain,Error> {
+    let mut explain = Explain::default();
+    self.staging.for_each(|point,_,_| {
+      explain.staging_rows += 1;
+      if point.overlaps(bbox) {
+        explain.staging_results += 1;
+      }
+      Ok(())
+    })?;
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      explain.trees.push(tree.explain(bbox, &deletes)?);
+    }
+    Ok(explain)
+  }
+}
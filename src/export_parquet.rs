@@ -0,0 +1,54 @@
+use crate::{DB,ArrowPoint,ArrowValue};
+use ::arrow::datatypes::{Field,Schema};
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::file::properties::WriterProperties;
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Rows in each row group written by `db.export_parquet()`.
+pub const PARQUET_ROW_GROUP_ROWS: usize = 64*1024;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: ArrowPoint, V: ArrowValue {
+  /// Write the records that intersect `bbox` to a parquet file at `path`,
+  /// returning the number of rows written. Requires the `parquet` feature.
+  ///
+  /// The columns are those of `db.query_arrow()`. Each `(column,name)` pair in
+  /// `schema_map` renames a column in the file. Rows are streamed from the
+  /// query into row groups of `PARQUET_ROW_GROUP_ROWS`, so regions larger than
+  /// memory can be exported.
+  pub fn export_parquet<F> (&mut self, bbox: &P::Bounds, path: F,
+  schema_map: &[(&str,&str)]) -> Result<u64,Error> where F: AsRef<Path> {
+    let batches = self.query_arrow(bbox)?;
+    let mut fields: Vec<Field> = batches.schema().fields().iter()
+      .map(|f| f.as_ref().clone()).collect();
+    for (column,name) in schema_map.iter() {
+      match fields.iter_mut().find(|f| f.name() == column) {
+        Some(field) => *field = field.clone().with_name(*name),
+        None => bail!["unknown column {} in schema map", column]
+      }
+    }
+    let schema = Arc::new(Schema::new(fields));
+    let props = WriterProperties::builder()
+      .set_max_row_group_size(PARQUET_ROW_GROUP_ROWS)
+      .build();
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, Arc::clone(&schema),
+      Some(props))?;
+    let mut rows = 0;
+    for batch in batches {
+      let batch = batch?;
+      rows += batch.num_rows() as u64;
+      let columns = batch.columns().to_vec();
+      writer.write(&RecordBatch::try_new(Arc::clone(&schema), columns)?)?;
+    }
+    writer.close()?;
+    Ok(rows)
+  }
+}
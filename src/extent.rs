@@ -0,0 +1,61 @@
+use crate::{DB,Point,PointOrd,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::collections::HashSet;
+
+/// Bounding box `(min,max)` of the records in a region, returned by
+/// `db.extent()`. For the built-in point types this has the same shape as the
+/// bounding box passed to `db.query()`.
+pub type Extent<P> = <<P as PointOrd>::Range as PointOrd>::Bounds;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Compute the minimum and maximum coordinate in each dimension over every
+  /// record that intersects `bbox`, or `None` if there are no such records.
+  ///
+  /// Data blocks whose cached bounds lie entirely within `bbox` contribute
+  /// their bounds directly without their records being filtered or read.
+  pub fn extent (&mut self, bbox: &P::Bounds)
+  -> Result<Option<Extent<P>>,Error> {
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    let mut ranges: Vec<P::Range> = vec![];
+    let mut points: Vec<P> = vec![];
+    self.staging.for_each(|p,_,_| {
+      if p.overlaps(bbox) { points.push(*p) }
+      Ok(())
+    })?;
+    if let Some(b) = P::bounds(&points) {
+      ranges.push(P::bounds_to_range(b));
+    }
+    let mut blocks = vec![];
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      blocks.extend(tree.query_blocks(bbox)?);
+    }
+    let pending: HashSet<u64> = deletes.blocks().collect();
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    for offset in blocks {
+      if !pending.contains(&(offset+1)) {
+        match dstore.bbox(offset)? {
+          None => continue,
+          Some((b,_)) if P::bounds_within(&b, bbox) => {
+            ranges.push(P::bounds_to_range(b));
+            continue;
+          },
+          Some(_) => {}
+        }
+      }
+      let points: Vec<P> = dstore.query(offset, bbox)?.into_iter()
+        .filter(|(_,_,loc)| !deletes.contains(loc))
+        .map(|(p,_,_)| p)
+        .collect();
+      if let Some(b) = P::bounds(&points) {
+        ranges.push(P::bounds_to_range(b));
+      }
+    }
+    Ok(<P::Range as PointOrd>::bounds(&ranges))
+  }
+}
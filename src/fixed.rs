@@ -0,0 +1,98 @@
+use crate::{DB,Point,Value,Row,Key,instrument};
+use failure::{Error,ensure};
+use random_access_storage::RandomAccess;
+
+/// Type that always serializes to `WIDTH` bytes, such as the scalar types
+/// and tuples of them. Points and values of this kind can be written with
+/// `db.batch_fixed()`.
+pub trait FixedWidth {
+  const WIDTH: usize;
+}
+
+macro_rules! impl_fixed_scalar {
+  ($($T:ty),+) => {
+    $(impl FixedWidth for $T {
+      const WIDTH: usize = std::mem::size_of::<$T>();
+    })+
+  }
+}
+
+impl_fixed_scalar![f32,f64,u8,u16,u32,u64,i8,i16,i32,i64];
+
+impl<const N: usize> FixedWidth for Key<N> {
+  const WIDTH: usize = N;
+}
+
+macro_rules! impl_fixed_tuple {
+  ($(($($T:tt),+));+) => {
+    $(impl<$($T),+> FixedWidth for ($($T),+) where $($T: FixedWidth),+ {
+      const WIDTH: usize = 0 $(+ $T::WIDTH)+;
+    })+
+  }
+}
+
+impl_fixed_tuple![
+  (A,B);
+  (A,B,C);
+  (A,B,C,D);
+  (A,B,C,D,E);
+  (A,B,C,D,E,F);
+  (A,B,C,D,E,F,G);
+  (A,B,C,D,E,F,G,H)
+];
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point+FixedWidth, V: Value+FixedWidth {
+  /// Insert `points[i]` with `values[i]` for every `i`, for bulk loads of
+  /// fixed-size records such as LiDAR point clouds with an intensity value.
+  ///
+  /// The records go straight to the staging area and the trees without
+  /// building a `Row` for each of them, and data blocks are sized from
+  /// `FixedWidth` instead of measuring every row. Pass as many records at a
+  /// time as memory allows: batches larger than `Setup::base_size()` are
+  /// built into trees without passing through the staging area.
+  ///
+  /// Databases with `Setup::dedup()`, `Setup::history()` or a replication
+  /// `Setup::log()` need the rows themselves, so this falls back to
+  /// `db.batch()` for them.
+  pub fn batch_fixed (&mut self, points: &[P], values: &[V])
+  -> Result<(),Error> {
+    ensure![points.len() == values.len(),
+      "batch_fixed() expects a value for each point ({} points, {} values)",
+      points.len(), values.len()];
+    let normalized;
+    let points = match self.validate_points(points)? {
+      Some(swapped) => { normalized = swapped; &normalized[..] },
+      None => points
+    };
+    let inserts: Vec<(P,V)> = points.iter().copied()
+      .zip(values.iter().cloned())
+      .collect();
+    if self.fields.dedup || self.history.is_some() || self.log.is_some() {
+      let rows: Vec<Row<P,V>> = inserts.into_iter()
+        .map(|(p,v)| Row::Insert(p,v))
+        .collect();
+      return self.batch(&rows);
+    }
+    let timer = instrument::timer();
+    let n = inserts.len();
+    self.invalidate();
+    {
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      if let Some(meta) = &mut dstore.batch_meta {
+        meta.begin()?;
+      }
+      dstore.fixed_width = Some(P::WIDTH + V::WIDTH);
+    }
+    let applied = self.apply_rows(inserts, vec![], false);
+    self.data_store.try_borrow_mut()?.fixed_width = None;
+    applied?;
+    self.logical_bytes += (n * (P::WIDTH + V::WIDTH)) as u64;
+    self.record_generation()?;
+    self.sync_after_batch()?;
+    instrument::batch(timer, n);
+    Ok(())
+  }
+}
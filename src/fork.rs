@@ -0,0 +1,241 @@
+use crate::{DB,Setup,Point,Value};
+use failure::{Error,bail,ensure,format_err};
+use random_access_storage::RandomAccess;
+use std::io::Write;
+use std::any::Any;
+use std::rc::Rc;
+
+/// Copy-on-write store layered over a read-only base store.
+///
+/// Reads come from the base store except for byte ranges that have been
+/// written since the fork, which come from the overlay store. The overlay
+/// keeps writes at their original offsets (as a sparse file on most
+/// filesystems) and each written range is appended to a separate extents
+/// store, so a fork costs nothing up front and only grows with the data
+/// written to it. `sync_all()` compacts the extents store down to the merged
+/// list of written ranges.
+///
+/// The base store must not be modified while forks of it are in use.
+pub struct ForkStore<S> where S: RandomAccess<Error=Error> {
+  base: S,
+  overlay: S,
+  extents: S,
+  extents_len: u64,
+  base_len: u64,
+  written: Vec<(u64,u64)>,
+  length: u64,
+  appended: usize
+}
+
+// the extents store holds the length of the base store when the fork was
+// created followed by a log of records: [kind: u8][a: u64][b: u64], either
+// a written range a..b or a truncation to length a
+const EXTENT_WRITE: u8 = 0;
+const EXTENT_TRUNCATE: u8 = 1;
+const RECORD_SIZE: usize = 17;
+
+fn record (kind: u8, a: u64, b: u64) -> [u8;RECORD_SIZE] {
+  let mut buf = [0u8;RECORD_SIZE];
+  buf[0] = kind;
+  buf[1..9].copy_from_slice(&a.to_be_bytes());
+  buf[9..17].copy_from_slice(&b.to_be_bytes());
+  buf
+}
+
+impl<S> ForkStore<S> where S: RandomAccess<Error=Error> {
+  /// Open a fork of `base` that writes into `overlay` and records the written
+  /// ranges in `extents`. An empty `extents` store starts a new fork of the
+  /// current contents of `base`.
+  pub fn open (base: S, overlay: S, mut extents: S) -> Result<Self,Error> {
+    if extents.is_empty()? {
+      let base_len = base.len()?;
+      extents.write(0, &base_len.to_be_bytes())?;
+      return Ok(Self {
+        base, overlay, extents,
+        extents_len: 8,
+        base_len,
+        written: vec![],
+        length: base_len,
+        appended: 0
+      });
+    }
+    let len = extents.len()?;
+    ensure![len >= 8 && (len-8) % (RECORD_SIZE as u64) == 0,
+      "extents store of {} bytes is not a fork log", len];
+    let buf = extents.read(0, len)?;
+    let mut base_len_bytes = [0u8;8];
+    base_len_bytes.copy_from_slice(&buf[0..8]);
+    let base_len = u64::from_be_bytes(base_len_bytes);
+    let mut fork = Self {
+      base, overlay, extents,
+      extents_len: len,
+      base_len,
+      written: vec![],
+      length: base_len,
+      appended: 0
+    };
+    for rec in buf[8..].chunks(RECORD_SIZE) {
+      let mut a = [0u8;8];
+      let mut b = [0u8;8];
+      a.copy_from_slice(&rec[1..9]);
+      b.copy_from_slice(&rec[9..17]);
+      let (a,b) = (u64::from_be_bytes(a), u64::from_be_bytes(b));
+      match rec[0] {
+        EXTENT_WRITE => fork.mark(a, b),
+        EXTENT_TRUNCATE => fork.cut(a),
+        kind => bail!["unknown fork extent record kind {}", kind]
+      }
+      fork.appended += 1;
+    }
+    Ok(fork)
+  }
+  // append a record to the extents log
+  fn append (&mut self, kind: u8, a: u64, b: u64) -> Result<(),Error> {
+    self.extents.write(self.extents_len, &record(kind, a, b))?;
+    self.extents_len += RECORD_SIZE as u64;
+    self.appended += 1;
+    Ok(())
+  }
+  // rewrite the extents log as the merged written ranges
+  fn compact (&mut self) -> Result<(),Error> {
+    let mut buf = Vec::with_capacity(8 + (self.written.len()+1)*RECORD_SIZE);
+    buf.extend_from_slice(&self.base_len.to_be_bytes());
+    for (start,end) in self.written.iter() {
+      buf.extend_from_slice(&record(EXTENT_WRITE, *start, *end));
+    }
+    buf.extend_from_slice(&record(EXTENT_TRUNCATE, self.length, 0));
+    self.extents.write(0, &buf)?;
+    if self.extents_len > buf.len() as u64 {
+      self.extents.truncate(buf.len() as u64)?;
+    }
+    self.extents_len = buf.len() as u64;
+    self.appended = self.written.len() + 1;
+    Ok(())
+  }
+  fn mark (&mut self, start: u64, end: u64) {
+    let mut range = (start,end);
+    self.written.retain(|(s,e)| {
+      if *s <= range.1 && range.0 <= *e {
+        range = (range.0.min(*s), range.1.max(*e));
+        false
+      } else {
+        true
+      }
+    });
+    let i = self.written.iter().position(|(s,_)| *s > range.0)
+      .unwrap_or(self.written.len());
+    self.written.insert(i, range);
+    self.length = self.length.max(end);
+  }
+  fn cut (&mut self, length: u64) {
+    self.base_len = self.base_len.min(length);
+    self.written.retain(|(s,_)| *s < length);
+    for range in self.written.iter_mut() {
+      range.1 = range.1.min(length);
+    }
+    self.length = length;
+  }
+}
+
+impl<S> RandomAccess for ForkStore<S> where S: RandomAccess<Error=Error> {
+  type Error = Error;
+  fn write (&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    if data.is_empty() { return Ok(()) }
+    let end = offset + data.len() as u64;
+    self.overlay.write(offset, data)?;
+    self.mark(offset, end);
+    self.append(EXTENT_WRITE, offset, end)
+  }
+  fn read (&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    let end = offset + length;
+    if end > self.length {
+      bail!["read {}..{} past the end of the fork ({})", offset, end, self.length];
+    }
+    let mut buf = vec![0u8;length as usize];
+    if offset < self.base_len {
+      let base_end = end.min(self.base_len);
+      let b = self.base.read(offset, base_end-offset)?;
+      buf[..b.len()].copy_from_slice(&b);
+    }
+    for (start,wend) in self.written.iter() {
+      if *start >= end || *wend <= offset { continue }
+      let s = (*start).max(offset);
+      let e = (*wend).min(end);
+      let d = self.overlay.read(s, e-s)?;
+      buf[(s-offset) as usize..(e-offset) as usize].copy_from_slice(&d);
+    }
+    Ok(buf)
+  }
+  fn read_to_writer (&mut self, offset: u64, length: u64,
+  buf: &mut impl Write) -> Result<(),Error> {
+    let data = self.read(offset, length)?;
+    buf.write_all(&data)?;
+    Ok(())
+  }
+  fn del (&mut self, _offset: u64, _length: u64) -> Result<(),Error> {
+    bail!["del is not supported on forked stores"]
+  }
+  fn truncate (&mut self, length: u64) -> Result<(),Error> {
+    self.cut(length);
+    if self.overlay.len()? > length {
+      self.overlay.truncate(length)?;
+    }
+    self.append(EXTENT_TRUNCATE, length, 0)
+  }
+  fn len (&self) -> Result<u64,Error> {
+    Ok(self.length)
+  }
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    Ok(self.length == 0)
+  }
+  fn sync_all (&mut self) -> Result<(),Error> {
+    self.overlay.sync_all()?;
+    if self.appended > self.written.len() + 1 {
+      self.compact()?;
+    }
+    self.extents.sync_all()
+  }
+}
+
+/// Build a storage function for `Setup::new()` that opens each store as a
+/// `ForkStore` reading from `base` and writing into `dest`.
+///
+/// Use this to reopen a fork created with `db.fork()`. The extents for a store
+/// named `name` are kept in `dest` under `name.fork`.
+pub fn fork_storage<S,F,G> (base: F, dest: G)
+-> impl Fn(&str) -> Result<ForkStore<S>,Error>
+where S: RandomAccess<Error=Error>,
+F: Fn(&str) -> Result<S,Error>,
+G: Fn(&str) -> Result<S,Error> {
+  move |name: &str| {
+    ForkStore::open(
+      base(name)?,
+      dest(name)?,
+      dest(&format!["{}.fork",name])?
+    )
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>)+Clone,
+P: Point, V: Value {
+  /// Create a copy-on-write fork of the database that writes into the stores
+  /// returned by `dest`.
+  ///
+  /// The fork reads unmodified data directly from this database's files, so
+  /// forking a large database is cheap. Only batches written to the fork are
+  /// stored in `dest`. Stop writing to this database while its forks are in
+  /// use: the forks expect the files they share to stay the same.
+  ///
+  /// Reopen a fork later with `Setup::new(fork_storage(base, dest))`.
+  pub fn fork<G> (&mut self, dest: G)
+  -> Result<DB<ForkStore<S>,impl Fn(&str) -> Result<ForkStore<S>,Error>,P,V>,Error>
+  where G: Fn(&str) -> Result<S,Error> {
+    self.sync()?;
+    let mut setup = Setup::new(fork_storage(self.open_store.clone(), dest));
+    setup.fields = self.fields.clone();
+    setup.partition = self.partition.clone().map(|p| p as Rc<dyn Any>);
+    setup.build().map_err(|e| format_err!["failed to open fork: {}", e])
+  }
+}
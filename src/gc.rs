@@ -0,0 +1,106 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use desert::CountBytes;
+use std::collections::HashSet;
+
+/// Unreferenced storage found by `db.gc()`.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct GcReport {
+  /// Index and size of each tree store that holds bytes but is past the last
+  /// tree of the database, so queries never read it.
+  pub trees: Vec<(usize,u64)>,
+  /// Data blocks that no tree references.
+  pub data_blocks: usize,
+  /// Bytes of the `data` store that no tree references, including partial
+  /// blocks at the end of the store.
+  pub data_bytes: u64,
+  /// Bytes removed from the tree, `data` and `range` stores. Always `0` for a
+  /// dry run.
+  pub reclaimed_bytes: u64
+}
+
+impl GcReport {
+  /// Total unreferenced bytes, whether or not they were reclaimed.
+  pub fn bytes (&self) -> u64 {
+    self.trees.iter().map(|(_,bytes)| bytes).sum::<u64>() + self.data_bytes
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Find tree stores and data blocks that are no longer referenced, such as
+  /// tree stores past the last tree of the database, data blocks replaced
+  /// when a merge combined them, data blocks whose records were all deleted
+  /// and blocks written by a batch that never completed. Trees count as live
+  /// exactly when queries read them: whenever their store holds bytes.
+  ///
+  /// Unless `dry_run` is set, orphaned tree stores are truncated and
+  /// unreferenced data blocks at the end of the `data` store are removed.
+  /// Unreferenced blocks in the middle of the store can only be reclaimed by
+  /// rewriting the database (see `eyros-cli compact`) because the locations
+  /// of later records would change; they are reported but kept.
+  ///
+  /// With `Setup::keep_generations()`, data blocks stay reachable through
+  /// `db.query_at()` and only tree stores are collected.
+  pub fn gc (&mut self, dry_run: bool) -> Result<GcReport,Error> {
+    let mut report = GcReport::default();
+    let mut referenced: HashSet<u64> = HashSet::new();
+    // queries read every tree with bytes in its store, so those are live
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if !tree.is_empty()? {
+        referenced.extend(tree.blocks()?);
+      }
+    }
+    // tree stores past the last tree are never opened, so never queried
+    for i in self.trees.len().. {
+      let mut store = (self.open_store)(&format!("tree{}",i))?;
+      let bytes = store.len()?;
+      if bytes == 0 { break }
+      report.trees.push((i,bytes));
+      if !dry_run {
+        report.reclaimed_bytes += bytes;
+        store.truncate(0)?;
+        store.sync_all()?;
+      }
+    }
+    if self.generations.is_some() { return Ok(report) }
+    // pending deletes still point into their blocks
+    for loc in self.staging.deletes.try_borrow()?.iter() {
+      if loc.0 > 0 { referenced.insert(loc.0-1); }
+    }
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    if let Some(bitmap) = &dstore.delete_bitmap {
+      referenced.extend(bitmap.locations().iter().map(|loc| loc.0-1));
+    }
+    let data_len = dstore.bytes()?;
+    let entries = dstore.range.iter()?.collect::<Result<Vec<_>,Error>>()?;
+    // keep everything up to the end of the last referenced block
+    let (mut cut, mut range_cut) = (0, 0);
+    let (mut end, mut range_end) = (0, 0);
+    for entry in entries.iter() {
+      let offset = entry.0;
+      let size = (dstore.read(offset)?.len() + 4) as u64;
+      end = end.max(offset + size);
+      range_end += entry.count_bytes() as u64;
+      if referenced.contains(&offset) {
+        cut = end;
+        range_cut = range_end;
+      } else {
+        report.data_blocks += 1;
+        report.data_bytes += size;
+      }
+    }
+    report.data_bytes += data_len.saturating_sub(end);
+    if !dry_run && cut < data_len {
+      report.reclaimed_bytes += data_len - cut;
+      dstore.truncate(cut, range_cut)?;
+      drop(dstore);
+      self.invalidate();
+    }
+    Ok(report)
+  }
+}
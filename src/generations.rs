@@ -0,0 +1,319 @@
+use crate::{DB,Point,Value,Location,Log,StorageLog,DeleteSet};
+use crate::data::{DataStore,BITFIELD_MASK};
+use crate::order::Levels;
+use crate::tree::{read_branch,query_branch_at};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+use desert::{ToBytes,FromBytes};
+use std::collections::{HashMap,HashSet,hash_map::Entry};
+use std::rc::Rc;
+
+type Versions = Vec<(u32,u64)>;
+type Undo = Vec<(u64,Vec<u8>)>;
+
+/// Superseded tree versions and per-batch undo records for `db.query_at()`,
+/// enabled with `Setup::keep_generations()`.
+///
+/// Generation `g` is the state of the database after `g` batches. Data blocks
+/// are only ever appended, so a generation is fully described by:
+///
+/// * the version of each non-empty tree (the generation that wrote it)
+/// * the contents of the staging area
+/// * the delete bitfields of data blocks that later batches modified
+///
+/// Before a batch rebuilds or clears a tree, the current version is copied to
+/// a `tree{index}@{version}` store. Before a batch clears bits in a data block
+/// bitfield, the previous bitfield is recorded in the undo list of the new
+/// generation.
+pub struct Generations<S> where S: RandomAccess<Error=Error> {
+  pub log: StorageLog<S>,
+  pub trees: S,
+  pub preserved: Vec<(usize,u64)>,
+  pub versions: Vec<Option<u64>>,
+  stores: HashMap<(usize,u64),S>,
+  written: HashSet<usize>,
+  undo: Undo
+}
+
+impl<S> Generations<S> where S: RandomAccess<Error=Error> {
+  pub fn open (data: S, index: S, mut trees: S) -> Result<Self,Error> {
+    let mut log = StorageLog::open(data, index)?;
+    let len = trees.len()?;
+    ensure_eq!(len % 12, 0, "generation tree list has a partial entry");
+    let buf = if len > 0 { trees.read(0, len)? } else { vec![] };
+    let mut preserved = Vec::with_capacity((len/12) as usize);
+    for chunk in buf.chunks(12) {
+      let (_,(i,v)) = <(u32,u64)>::from_bytes(chunk)?;
+      preserved.push((i as usize,v));
+    }
+    let mut versions = vec![];
+    let n = log.len()?;
+    if n > 0 {
+      let (_,(vs,_)) = <(Versions,Undo)>::from_bytes(&log.get(n-1)?)?;
+      for (i,v) in vs {
+        for _ in versions.len()..(i as usize)+1 {
+          versions.push(None);
+        }
+        versions[i as usize] = Some(v);
+      }
+    }
+    Ok(Self {
+      log, trees, preserved, versions,
+      stores: HashMap::new(),
+      written: HashSet::new(),
+      undo: vec![]
+    })
+  }
+  fn get<P,V> (&mut self, g: u64)
+  -> Result<(Versions,Undo,Vec<(P,V)>,Vec<Location>),Error>
+  where P: Point, V: Value {
+    let buf = self.log.get(g)?;
+    Ok(<(Versions,Undo,Vec<(P,V)>,Vec<Location>)>::from_bytes(&buf)?.1)
+  }
+  fn undo_at (&mut self, g: u64) -> Result<Undo,Error> {
+    let buf = self.log.get(g)?;
+    Ok(<(Versions,Undo)>::from_bytes(&buf)?.1 .1)
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query the database as it was after the first `seq` batches were written
+  /// with `Setup::keep_generations(true)`. `seq` ranges from `0` (the state
+  /// when generations were enabled) to `db.generation()`.
+  ///
+  /// Results are collected into a `Vec` instead of being returned as a lazy
+  /// iterator. The locations in the results refer to the historical state and
+  /// must not be used to delete records.
+  pub fn query_at (&mut self, seq: u64, bbox: &P::Bounds)
+  -> Result<Vec<(P,V,Location)>,Error> {
+    let current = match self.generation()? {
+      Some(g) => g,
+      None => bail!["query_at requires Setup::keep_generations(true)"]
+    };
+    if seq > current {
+      bail!["generation {} is newer than the current generation {}",
+        seq, current];
+    }
+    let gens = self.generations.as_mut().unwrap();
+    let (versions,_,inserts,deletes) = gens.get::<P,V>(seq)?;
+    let mut bitfields: HashMap<u64,Vec<u8>> = HashMap::new();
+    for g in (seq+1..current+1).rev() {
+      for (block,bits) in gens.undo_at(g)?.into_iter().rev() {
+        bitfields.insert(block, bits);
+      }
+    }
+    let delete_set: DeleteSet = deletes.into_iter().collect();
+    let mut results = vec![];
+    for (i,(p,v)) in inserts.into_iter().enumerate() {
+      let loc = (0,i as u32);
+      if p.overlaps(bbox) && !delete_set.contains(&loc) {
+        results.push((p,v,loc));
+      }
+    }
+    let levels = self.fields.levels();
+    let data_store = Rc::clone(&self.data_store);
+    let mut dstore = data_store.try_borrow_mut()?;
+    for (i,v) in versions {
+      let i = i as usize;
+      if self.generations.as_ref().unwrap().versions.get(i) == Some(&Some(v)) {
+        let mut tree = self.trees[i].try_borrow_mut()?;
+        let format = tree.format;
+        query_tree(&mut tree.store, format, &levels, bbox, &mut dstore,
+          &bitfields, &delete_set, &mut results)?;
+      } else {
+        let store = self.preserved_store(i,v)?;
+        // preserved trees are not tagged, so they are assumed to have been
+        // built with the current setup
+        query_tree(store, levels.tree_format(), &levels, bbox, &mut dstore,
+          &bitfields, &delete_set, &mut results)?;
+      }
+    }
+    Ok(results)
+  }
+
+  /// Current generation (the number of batches written since generations
+  /// were enabled), or `None` if `Setup::keep_generations()` is not set.
+  pub fn generation (&mut self) -> Result<Option<u64>,Error> {
+    match &mut self.generations {
+      Some(gens) => Ok(Some(gens.log.len()?.max(1)-1)),
+      None => Ok(None)
+    }
+  }
+
+  pub(crate) fn init_generations (&mut self) -> Result<(),Error> {
+    let empty = match &mut self.generations {
+      Some(gens) => gens.log.is_empty()?,
+      None => return Ok(())
+    };
+    if empty {
+      let mut versions = vec![];
+      for tree in self.trees.iter() {
+        versions.push(if tree.try_borrow_mut()?.is_empty()? {
+          None
+        } else {
+          Some(0)
+        });
+      }
+      self.generations.as_mut().unwrap().versions = versions;
+      self.record_generation()?;
+    }
+    Ok(())
+  }
+
+  /// Copy the current version of tree `index` to its own store before the
+  /// running batch rebuilds or clears it.
+  pub(crate) fn preserve_tree (&mut self, index: usize) -> Result<(),Error> {
+    let version = match &mut self.generations {
+      None => return Ok(()),
+      Some(gens) => {
+        if !gens.written.insert(index) { return Ok(()) }
+        match gens.versions.get(index) {
+          Some(Some(v)) => *v,
+          _ => return Ok(())
+        }
+      }
+    };
+    let buf = {
+      let mut tree = self.trees[index].try_borrow_mut()?;
+      let len = tree.store.len()?;
+      if len == 0 { return Ok(()) }
+      tree.store.read(0, len)?
+    };
+    let mut store = (self.open_store)(&format!["tree{}@{}",index,version])?;
+    store.write(0, &buf)?;
+    store.sync_all()?;
+    let gens = self.generations.as_mut().unwrap();
+    gens.stores.insert((index,version), store);
+    gens.preserved.push((index,version));
+    let offset = gens.trees.len()?;
+    gens.trees.write(offset, &(index as u32,version).to_bytes()?)?;
+    gens.trees.sync_all()
+  }
+
+  /// Record the bitfields of the data blocks that `deletes` will modify.
+  pub(crate) fn journal_deletes (&mut self, deletes: &[Location])
+  -> Result<(),Error> {
+    if let Some(gens) = &mut self.generations {
+      let mut blocks: Vec<u64> = deletes.iter()
+        .filter(|(block,_)| *block > 0)
+        .map(|(block,_)| *block-1)
+        .collect();
+      blocks.sort_unstable();
+      blocks.dedup();
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      for block in blocks {
+        let buf = dstore.read(block)?;
+        let flags = u16::from_be_bytes([buf[0],buf[1]]);
+        let len = (flags & BITFIELD_MASK) as usize;
+        gens.undo.push((block, buf[2..2+len].to_vec()));
+      }
+    }
+    Ok(())
+  }
+
+  /// Append the state after the batch that was just written as a new
+  /// generation.
+  pub(crate) fn record_generation (&mut self) -> Result<(),Error> {
+    let gens = match &mut self.generations {
+      None => return Ok(()),
+      Some(gens) => gens
+    };
+    let g = gens.log.len()?;
+    for i in gens.versions.len()..self.trees.len() {
+      gens.written.insert(i);
+    }
+    gens.versions.resize(self.trees.len(), None);
+    let mut versions = vec![];
+    for (i,tree) in self.trees.iter().enumerate() {
+      if tree.try_borrow_mut()?.is_empty()? {
+        gens.versions[i] = None;
+      } else if gens.written.contains(&i) {
+        gens.versions[i] = Some(g);
+      }
+      if let Some(v) = gens.versions[i] {
+        versions.push((i as u32,v));
+      }
+    }
+    let inserts = self.staging.inserts.try_borrow()?.clone();
+    let deletes = self.staging.deletes.try_borrow()?.clone();
+    let undo = std::mem::take(&mut gens.undo);
+    gens.log.append(&(versions,undo,inserts,deletes).to_bytes()?)?;
+    gens.written.clear();
+    Ok(())
+  }
+
+  pub(crate) fn generation_store_names (&self) -> Vec<String> {
+    let mut names = vec![];
+    if let Some(gens) = &self.generations {
+      for name in ["generations","generations_index","generations_trees"].iter() {
+        names.push(name.to_string());
+      }
+      for (i,v) in gens.preserved.iter() {
+        names.push(format!["tree{}@{}",i,v]);
+      }
+    }
+    names
+  }
+
+  pub(crate) fn generation_store (&mut self, name: &str)
+  -> Result<&mut S,Error> {
+    if let Some(rest) = name.strip_prefix("tree") {
+      let parts: Vec<&str> = rest.split('@').collect();
+      if parts.len() == 2 {
+        return self.preserved_store(parts[0].parse()?, parts[1].parse()?);
+      }
+    }
+    match (&mut self.generations, name) {
+      (Some(gens), "generations") => Ok(&mut gens.log.data),
+      (Some(gens), "generations_index") => Ok(&mut gens.log.index),
+      (Some(gens), "generations_trees") => Ok(&mut gens.trees),
+      _ => bail!["unknown store {}", name]
+    }
+  }
+
+  fn preserved_store (&mut self, index: usize, version: u64)
+  -> Result<&mut S,Error> {
+    let gens = match &mut self.generations {
+      Some(gens) => gens,
+      None => bail!["generations are not enabled"]
+    };
+    Ok(match gens.stores.entry((index,version)) {
+      Entry::Occupied(e) => e.into_mut(),
+      Entry::Vacant(e) => {
+        e.insert((self.open_store)(&format!["tree{}@{}",index,version])?)
+      }
+    })
+  }
+}
+
+fn query_tree<S,P,V> (store: &mut S, format: u16, levels: &Levels,
+bbox: &P::Bounds, dstore: &mut DataStore<S,P,V>,
+bitfields: &HashMap<u64,Vec<u8>>, deletes: &DeleteSet,
+results: &mut Vec<(P,V,Location)>) -> Result<(),Error>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  let tree_size = store.len()?;
+  let mut cursors = vec![(0,0)];
+  while let Some((cursor,depth)) = cursors.pop() {
+    if cursor >= tree_size { continue }
+    let (buf,split) =
+      read_branch(store, format, levels, cursor, depth, tree_size)?;
+    let (c,blocks) = query_branch_at::<P>(&buf, bbox, levels, split, depth)?;
+    cursors.extend(c);
+    for offset in blocks {
+      let mut buf = dstore.read(offset)?;
+      if let Some(bits) = bitfields.get(&offset) {
+        buf[2..2+bits.len()].copy_from_slice(bits);
+      }
+      for (p,v,i) in dstore.parse(&buf)? {
+        let loc = (offset+1,i);
+        if p.overlaps(bbox) && !deletes.contains(&loc) {
+          results.push((p,v,loc));
+        }
+      }
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::{DB,Point,Value,Row,Log,StorageLog};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use desert::{ToBytes,FromBytes};
+
+/// Summary of one applied batch, returned by `db.changes_since()`.
+#[derive(Clone,Debug)]
+pub struct Change<P> where P: Point {
+  /// Sequence number of the batch. The first batch written with history
+  /// enabled is 0.
+  pub seq: u64,
+  /// Number of `Row::Insert` records in the batch.
+  pub inserts: u64,
+  /// Number of `Row::Delete` records in the batch.
+  pub deletes: u64,
+  /// Bounding box of the inserted points, or `None` if the batch had no
+  /// inserts.
+  pub bbox: Option<P::Bounds>
+}
+
+impl<P> Change<P> where P: Point {
+  fn from_rows<V> (seq: u64, rows: &[Row<P,V>]) -> Self where V: Value {
+    let points: Vec<P> = rows.iter().filter_map(|row| match row {
+      Row::Insert(p,_) => Some(*p),
+      Row::Delete(_) => None
+    }).collect();
+    Self {
+      seq,
+      inserts: points.len() as u64,
+      deletes: (rows.len() - points.len()) as u64,
+      bbox: P::bounds(&points)
+    }
+  }
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    let mut buf = (self.inserts,self.deletes).to_bytes()?;
+    match &self.bbox {
+      Some(bbox) => {
+        buf.push(1);
+        buf.extend_from_slice(&bbox.to_bytes()?);
+      },
+      None => buf.push(0)
+    }
+    Ok(buf)
+  }
+  fn from_bytes (seq: u64, buf: &[u8]) -> Result<Self,Error> {
+    let (size,(inserts,deletes)) = <(u64,u64)>::from_bytes(buf)?;
+    let bbox = match buf.get(size) {
+      Some(0) => None,
+      Some(1) => Some(P::Bounds::from_bytes(&buf[size+1..])?.1),
+      _ => bail!["invalid history entry {}", seq]
+    };
+    Ok(Self { seq, inserts, deletes, bbox })
+  }
+}
+
+/// Persistent list of batch summaries, kept in the `history` and
+/// `history_index` stores when enabled with `Setup::history()`.
+pub struct History<S> where S: RandomAccess<Error=Error> {
+  pub log: StorageLog<S>
+}
+
+impl<S> History<S> where S: RandomAccess<Error=Error> {
+  pub fn open (data: S, index: S) -> Result<Self,Error> {
+    Ok(Self { log: StorageLog::open(data, index)? })
+  }
+  pub fn append<P,V> (&mut self, rows: &[Row<P,V>]) -> Result<u64,Error>
+  where P: Point, V: Value {
+    let seq = self.log.len()?;
+    let change = Change::from_rows(seq, rows);
+    self.log.append(&change.to_bytes()?)
+  }
+  pub fn get<P> (&mut self, seq: u64) -> Result<Change<P>,Error>
+  where P: Point {
+    Change::from_bytes(seq, &self.log.get(seq)?)
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Summaries of every batch with a sequence number of `seq` or greater, in
+  /// the order they were written. Requires `Setup::history(true)`.
+  ///
+  /// Downstream consumers (search indexes, caches) can follow the database
+  /// incrementally by remembering the sequence number after the last change
+  /// they processed and querying the `bbox` of each new change instead of
+  /// scanning the whole database.
+  pub fn changes_since (&mut self, seq: u64) -> Result<Vec<Change<P>>,Error> {
+    let history = match &mut self.history {
+      Some(history) => history,
+      None => bail!["changes_since requires Setup::history(true)"]
+    };
+    let len = history.log.len()?;
+    let mut changes = Vec::with_capacity(len.saturating_sub(seq) as usize);
+    for i in seq..len {
+      changes.push(history.get(i)?);
+    }
+    Ok(changes)
+  }
+
+  /// Sequence number that the next batch will be recorded with, or `None` if
+  /// history is not enabled.
+  pub fn history_seq (&mut self) -> Result<Option<u64>,Error> {
+    match &mut self.history {
+      Some(history) => Ok(Some(history.log.len()?)),
+      None => Ok(None)
+    }
+  }
+}
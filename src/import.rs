@@ -0,0 +1,186 @@
+//! Build batches of rows from newline-delimited JSON or CSV input.
+//!
+//! Each line is handed to a mapping closure that returns the row to insert,
+//! or `None` to skip the line. The rows are grouped into batches of
+//! `DEFAULT_BATCH_SIZE` rows, or the size set with `batches.batch_size()`,
+//! that can be written with `db.batch()` or all at once with
+//! `batches.load(&mut db)`:
+//!
+//! ```rust,no_run
+//! use eyros::{DB,Row,import};
+//! # use failure::{Error,format_err};
+//! # use random_access_disk::RandomAccessDisk;
+//! # use std::path::PathBuf;
+//!
+//! type P = (f32,f32);
+//! type V = u64;
+//!
+//! # fn main () -> Result<(),Error> {
+//! let mut db: DB<_,_,P,V> = DB::open(storage)?;
+//! let file = std::fs::File::open("/tmp/stations.csv")?;
+//! let count = import::csv(file, |record| {
+//!   let lon = record.get("lon").ok_or_else(|| format_err!["missing lon"])?;
+//!   let lat = record.get("lat").ok_or_else(|| format_err!["missing lat"])?;
+//!   let id = record.get("id").ok_or_else(|| format_err!["missing id"])?;
+//!   Ok(Some(Row::Insert((lon.parse()?,lat.parse()?), id.parse()?)))
+//! }).batch_size(50_000).load(&mut db)?;
+//! println!["imported {} stations", count];
+//! # Ok(()) }
+//! # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+//! #   let mut p = PathBuf::from("/tmp/eyros-db/");
+//! #   p.push(name);
+//! #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+//! # }
+//! ```
+//!
+//! `ndjson()` parses each line as JSON and requires the `ndjson` feature.
+//! Errors name the line of the input they occurred on.
+
+use crate::{DB,Point,Value,Row};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+use std::io::{BufRead,BufReader,Lines,Read};
+use std::marker::PhantomData;
+
+/// Rows in each batch unless set with `batches.batch_size()`.
+pub const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// Iterator over batches of rows mapped from the lines of an input, returned
+/// by `import::ndjson()` and `import::csv()`.
+pub struct Batches<R,F,P,V> where
+R: Read, F: FnMut(&str) -> Result<Option<Row<P,V>>,Error>,
+P: Point, V: Value {
+  lines: Lines<BufReader<R>>,
+  map: F,
+  batch_size: usize,
+  line: u64,
+  _marker: PhantomData<(P,V)>
+}
+
+impl<R,F,P,V> Batches<R,F,P,V> where
+R: Read, F: FnMut(&str) -> Result<Option<Row<P,V>>,Error>,
+P: Point, V: Value {
+  fn new (reader: R, map: F) -> Self {
+    Self {
+      lines: BufReader::new(reader).lines(),
+      map,
+      batch_size: DEFAULT_BATCH_SIZE,
+      line: 0,
+      _marker: PhantomData
+    }
+  }
+  /// Set the number of rows in each batch.
+  pub fn batch_size (mut self, size: usize) -> Self {
+    self.batch_size = size.max(1);
+    self
+  }
+  /// Write every batch to `db` and return the number of rows written.
+  pub fn load<S,U> (self, db: &mut DB<S,U,P,V>) -> Result<u64,Error>
+  where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>) {
+    let mut count = 0;
+    for batch in self {
+      let batch = batch?;
+      db.batch(&batch)?;
+      count += batch.len() as u64;
+    }
+    Ok(count)
+  }
+}
+
+impl<R,F,P,V> Iterator for Batches<R,F,P,V> where
+R: Read, F: FnMut(&str) -> Result<Option<Row<P,V>>,Error>,
+P: Point, V: Value {
+  type Item = Result<Vec<Row<P,V>>,Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    let mut batch = Vec::with_capacity(self.batch_size);
+    while batch.len() < self.batch_size {
+      let line = match self.lines.next() {
+        Some(Ok(line)) => line,
+        Some(Err(e)) => return Some(Err(e.into())),
+        None => break
+      };
+      self.line += 1;
+      if line.trim().is_empty() { continue }
+      match (self.map)(&line) {
+        Ok(Some(row)) => batch.push(row),
+        Ok(None) => {},
+        Err(e) => return Some(Err(format_err!["line {}: {}", self.line, e]))
+      }
+    }
+    if batch.is_empty() { None } else { Some(Ok(batch)) }
+  }
+}
+
+/// Map each line of newline-delimited JSON from `reader` to a row with
+/// `mapper`. Requires the `ndjson` feature.
+#[cfg(feature="ndjson")]
+pub fn ndjson<R,M,P,V> (reader: R, mut mapper: M)
+-> Batches<R,impl FnMut(&str) -> Result<Option<Row<P,V>>,Error>,P,V>
+where R: Read, P: Point, V: Value,
+M: FnMut(serde_json::Value) -> Result<Option<Row<P,V>>,Error> {
+  Batches::new(reader, move |line: &str| mapper(serde_json::from_str(line)?))
+}
+
+/// Fields of a line of CSV, passed to the mapping closure of `import::csv()`.
+pub struct CsvRecord<'a> {
+  header: &'a [String],
+  fields: Vec<String>
+}
+
+impl<'a> CsvRecord<'a> {
+  /// Field in the column named `name` in the header line.
+  pub fn get (&self, name: &str) -> Option<&str> {
+    let i = self.header.iter().position(|h| h == name)?;
+    self.fields.get(i).map(|f| f.as_str())
+  }
+  /// Field in column `i`.
+  pub fn field (&self, i: usize) -> Option<&str> {
+    self.fields.get(i).map(|f| f.as_str())
+  }
+  /// Every field, in column order.
+  pub fn fields (&self) -> &[String] {
+    &self.fields
+  }
+}
+
+/// Map each line of CSV from `reader` to a row with `mapper`. The first line
+/// is the header that names the columns for `record.get()`.
+///
+/// Fields may be quoted with `"`, with `""` for a quote inside a quoted field.
+/// Quoted fields can not span several lines.
+pub fn csv<R,M,P,V> (reader: R, mut mapper: M)
+-> Batches<R,impl FnMut(&str) -> Result<Option<Row<P,V>>,Error>,P,V>
+where R: Read, P: Point, V: Value,
+M: FnMut(&CsvRecord) -> Result<Option<Row<P,V>>,Error> {
+  let mut header: Option<Vec<String>> = None;
+  Batches::new(reader, move |line: &str| {
+    let fields = split_csv(line)?;
+    if let Some(header) = &header {
+      return mapper(&CsvRecord { header, fields })
+    }
+    header = Some(fields);
+    Ok(None)
+  })
+}
+
+fn split_csv (line: &str) -> Result<Vec<String>,Error> {
+  let mut fields = vec![];
+  let mut field = String::new();
+  let mut quoted = false;
+  let mut chars = line.trim_end_matches('\r').chars().peekable();
+  while let Some(c) = chars.next() {
+    match (c, quoted) {
+      ('"', true) if chars.peek() == Some(&'"') => {
+        chars.next();
+        field.push('"');
+      },
+      ('"', true) => quoted = false,
+      ('"', false) if field.is_empty() => quoted = true,
+      (',', false) => fields.push(std::mem::take(&mut field)),
+      (c, _) => field.push(c)
+    }
+  }
+  if quoted { bail!["unterminated quoted field"] }
+  fields.push(field);
+  Ok(fields)
+}
@@ -0,0 +1,85 @@
+// Counters and histograms reported through the `metrics` facade when the
+// `metrics` feature is enabled, and spans and events reported through
+// `tracing` when the `tracing` feature is enabled. Without the features every
+// hook is a no-op and the field expressions are not evaluated.
+
+/// Enter a debug-level span until the returned guard is dropped.
+macro_rules! span {
+  ($($args:tt)*) => {{
+    #[cfg(feature="tracing")] { ::tracing::debug_span!($($args)*).entered() }
+    #[cfg(not(feature="tracing"))] { $crate::instrument::NoSpan }
+  }};
+}
+
+/// Emit a trace-level event.
+macro_rules! event {
+  ($($args:tt)*) => {
+    #[cfg(feature="tracing")]
+    ::tracing::trace!($($args)*);
+  };
+}
+
+#[cfg(not(feature="tracing"))]
+pub struct NoSpan;
+
+#[cfg(feature="metrics")]
+use std::time::Instant;
+
+/// Start time of an operation, used to record its duration.
+#[cfg(feature="metrics")]
+pub struct Timer(Instant);
+#[cfg(not(feature="metrics"))]
+pub struct Timer;
+
+pub fn timer () -> Timer {
+  #[cfg(feature="metrics")] { Timer(Instant::now()) }
+  #[cfg(not(feature="metrics"))] { Timer }
+}
+
+pub fn branch_read (_bytes: usize) {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_branch_reads").increment(1);
+    ::metrics::counter!("eyros_branch_bytes").increment(_bytes as u64);
+  }
+}
+
+pub fn data_read (_bytes: usize) {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_data_reads").increment(1);
+    ::metrics::counter!("eyros_data_bytes").increment(_bytes as u64);
+  }
+}
+
+pub fn data_cache_hit () {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_data_cache_hits").increment(1);
+  }
+}
+
+pub fn data_skip () {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_data_skips").increment(1);
+  }
+}
+
+pub fn staging_hit () {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_staging_hits").increment(1);
+  }
+}
+
+pub fn merge (_trees: usize) {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_merges").increment(1);
+    ::metrics::histogram!("eyros_merge_trees").record(_trees as f64);
+  }
+}
+
+pub fn batch (_timer: Timer, _rows: usize) {
+  #[cfg(feature="metrics")] {
+    ::metrics::counter!("eyros_batches").increment(1);
+    ::metrics::counter!("eyros_batch_rows").increment(_rows as u64);
+    ::metrics::histogram!("eyros_batch_seconds")
+      .record(_timer.0.elapsed().as_secs_f64());
+  }
+}
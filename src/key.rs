@@ -0,0 +1,165 @@
+use crate::point::Scalar;
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::{Error,bail};
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add,Div};
+
+/// Fixed-width byte key that can be used as a coordinate alongside numeric
+/// types, such as a geohash prefix or the first 8 bytes of a hash of a name.
+///
+/// Keys are ordered lexicographically, which is the same as comparing them as
+/// big-endian unsigned integers. Pivots are calculated with that integer
+/// interpretation, so the arithmetic below only exists to find a key between
+/// two other keys and is not useful on its own.
+///
+/// ```rust,no_run
+/// use eyros::{DB,Row,Key};
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// // (geohash prefix, time)
+/// type P = (Key<4>,f32);
+/// type V = u32;
+///
+/// # fn main () -> Result<(),Error> {
+/// let mut db: DB<_,_,P,V> = DB::open(storage)?;
+/// db.batch(&[
+///   Row::Insert((Key::from_slice(b"u4pr"), 12.5), 1),
+///   Row::Insert((Key::from_slice(b"9q8y"), 30.0), 2)
+/// ])?;
+/// let (kmin,kmax) = Key::prefix_range(b"u4");
+/// for result in db.query(&((kmin,0.0),(kmax,100.0)))? {
+///   println!["{:?}", result?];
+/// }
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+#[derive(Clone,Copy,PartialEq,Eq,Hash)]
+pub struct Key<const N: usize> {
+  bytes: [u8;N],
+  // bit above the most significant byte, set by `+` so that `(a+b)/2` does
+  // not overflow. Keys that are stored or compared by users never carry.
+  carry: bool
+}
+
+impl<const N: usize> Key<N> {
+  /// Create a key from exactly `N` bytes.
+  pub fn new (bytes: [u8;N]) -> Self {
+    Self { bytes, carry: false }
+  }
+  /// Create a key from the first `N` bytes of `src`, padded with zeros if
+  /// `src` is shorter.
+  pub fn from_slice (src: &[u8]) -> Self {
+    let mut bytes = [0u8;N];
+    let n = src.len().min(N);
+    bytes[..n].copy_from_slice(&src[..n]);
+    Self::new(bytes)
+  }
+  /// Smallest and largest keys that start with `prefix`, for use as the
+  /// minimum and maximum of a query bounding box.
+  pub fn prefix_range (prefix: &[u8]) -> (Self,Self) {
+    let mut max = [0xffu8;N];
+    let n = prefix.len().min(N);
+    max[..n].copy_from_slice(&prefix[..n]);
+    (Self::from_slice(prefix), Self::new(max))
+  }
+  pub fn as_bytes (&self) -> &[u8;N] {
+    &self.bytes
+  }
+}
+
+impl<const N: usize> Scalar for Key<N> {}
+
+impl<const N: usize> PartialOrd for Key<N> {
+  fn partial_cmp (&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl<const N: usize> Ord for Key<N> {
+  fn cmp (&self, other: &Self) -> Ordering {
+    (self.carry,self.bytes).cmp(&(other.carry,other.bytes))
+  }
+}
+
+impl<const N: usize> From<u8> for Key<N> {
+  fn from (x: u8) -> Self {
+    let mut bytes = [0u8;N];
+    if N > 0 { bytes[N-1] = x }
+    Self::new(bytes)
+  }
+}
+
+impl<const N: usize> Add<Key<N>> for Key<N> {
+  type Output = Key<N>;
+  fn add (self, other: Self) -> Self {
+    let mut bytes = [0u8;N];
+    let mut c = 0u16;
+    for i in (0..N).rev() {
+      let x = (self.bytes[i] as u16) + (other.bytes[i] as u16) + c;
+      bytes[i] = x as u8;
+      c = x >> 8;
+    }
+    Self { bytes, carry: c == 1 || self.carry || other.carry }
+  }
+}
+
+// long division of the key, including the carry bit, by the small divisors
+// produced with `From<u8>`
+impl<const N: usize> Div<Key<N>> for Key<N> {
+  type Output = Key<N>;
+  fn div (self, other: Self) -> Self {
+    let divisor = other.bytes.iter().fold(0u32, |d,b| {
+      d.saturating_mul(256).saturating_add(*b as u32)
+    }).max(1);
+    let mut bytes = [0u8;N];
+    let mut r = self.carry as u64;
+    for i in 0..N {
+      let x = (r << 8) | (self.bytes[i] as u64);
+      bytes[i] = (x / divisor as u64) as u8;
+      r = x % divisor as u64;
+    }
+    Self::new(bytes)
+  }
+}
+
+impl<const N: usize> fmt::Debug for Key<N> {
+  fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write![f, "Key("]?;
+    for b in self.bytes.iter() {
+      write![f, "{:02x}", b]?;
+    }
+    write![f, ")"]
+  }
+}
+
+impl<const N: usize> CountBytes for Key<N> {
+  fn count_bytes (&self) -> usize { N }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    if buf.len() < N { bail!["buffer too small for key in count"] }
+    Ok(N)
+  }
+}
+
+impl<const N: usize> ToBytes for Key<N> {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    Ok(self.bytes.to_vec())
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    if dst.len() < N { bail!["dst buffer too small for key"] }
+    dst[..N].copy_from_slice(&self.bytes);
+    Ok(N)
+  }
+}
+
+impl<const N: usize> FromBytes for Key<N> {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    if src.len() < N { bail!["buffer too small while loading key"] }
+    Ok((N, Self::from_slice(&src[..N])))
+  }
+}
@@ -17,7 +17,8 @@
 //!
 //! This is an early release missing important features such as atomicity and
 //! concurrency. The data format is still in flux and will likely change in the
-//! future, requiring data migrations.
+//! future, requiring data migrations. Databases record their format version
+//! and `db.migrate()` upgrades them to the current `FORMAT_VERSION`.
 //!
 //! [bkd]: https://users.cs.duke.edu/~pankaj/publications/papers/bkd-sstd.pdf
 //! [interval]: http://www.dgp.toronto.edu/~jstewart/378notes/22intervals/
@@ -164,14 +165,114 @@
 //!     .build()?)
 //! }
 //! ```
+//!
+//! # metrics
+//!
+//! Build with the `metrics` feature to report counters and histograms through
+//! the [metrics][] facade to whichever recorder your application installs:
+//!
+//! * `eyros_branch_reads`, `eyros_branch_bytes`: branch blocks read by queries
+//! * `eyros_data_reads`, `eyros_data_bytes`: data blocks read from storage
+//! * `eyros_data_cache_hits`: data blocks served from the list cache
+//! * `eyros_data_skips`: data blocks skipped by their block bounds header
+//! * `eyros_staging_hits`: query results found in the staging area
+//! * `eyros_batches`, `eyros_batch_rows`, `eyros_batch_seconds`: batch writes
+//! * `eyros_merges`, `eyros_merge_trees`: tree merges and their fan-in
+//!
+//! [metrics]: https://docs.rs/metrics
+//!
+//! # tracing
+//!
+//! Build with the `tracing` feature to emit [tracing][] spans for `batch`,
+//! `staging`, `build`, `merge` and `query` with record counts as fields, and
+//! trace-level events for planner decisions (`eyros::planner`) and each branch
+//! and data block visited by a query (`eyros::query`).
+//!
+//! [tracing]: https://docs.rs/tracing
+//!
+//! # cli
+//!
+//! Build with the `cli` feature for the `eyros-cli` binary, which operates on
+//! databases of 2-dimensional geojson features without writing a program:
+//!
+//! ```sh
+//! $ cargo install eyros --features cli
+//! $ eyros-cli import /tmp/db geojson features.json
+//! $ eyros-cli query /tmp/db -0.5,-0.5,0.5,0.5
+//! $ eyros-cli stats /tmp/db
+//! ```
+//!
+//! Run `eyros-cli` without arguments for the full list of commands.
+//!
+//! # derive
+//!
+//! Build with the `derive` feature to implement `Point` for your own structs,
+//! with one dimension per field. Fields can be scalars, `(min,max)` intervals
+//! or, to choose between the two for each record, `Mix<T>`:
+//!
+//! ```rust,ignore
+//! use eyros::{Point,Mix};
+//!
+//! #[derive(Point,Clone,Copy,Debug)]
+//! struct Feature { x: Mix<f32>, y: Mix<f32>, t: Mix<f64> }
+//!
+//! #[derive(Point,Clone,Copy,Debug)]
+//! struct Event { x: f32, y: f32, t: (f64,f64) }
+//! ```
+//!
+//! Query bounding boxes use the same `((min...),(max...))` form as tuples.
+//!
+//! # mmap
+//!
+//! Build with the `mmap` feature for `MmapStore`, a file store that reads
+//! through a memory map. Open a database with
+//! `DB::open(eyros::mmap_storage("/tmp/eyros-db"))` to serve queries from the
+//! OS page cache in read-mostly deployments.
+//!
+//! # arrow
+//!
+//! Build with the `arrow` feature for `db.query_arrow(&bbox)`, which returns
+//! query results as arrow `RecordBatch`es with a column per coordinate and a
+//! `value` column, ready for DataFusion or Polars.
+//!
+//! The `parquet` feature adds `db.export_parquet(&bbox, path, &[])` to write
+//! the same columns for a region to a parquet file.
+//!
+//! # osm
+//!
+//! Build with the `osm` feature to load the nodes and ways of an
+//! OpenStreetMap `.osm.pbf` extract with `eyros::osm::OsmImport`.
+//!
+//! # proj
+//!
+//! `Setup::crs()` records the coordinate reference system of a database and
+//! `Setup::transform()` converts points at the boundary with
+//! `db.batch_reprojected()` and `db.query_reprojected()`. `WebMercator` is
+//! built in. Build with the `proj` feature for `ProjTransform`, which
+//! converts between any reference systems known to PROJ.
+//!
+//! # bulk loads
+//!
+//! `disk_storage(dir, IoMode::Direct)` opens each store with `O_DIRECT` on
+//! linux so that a large initial import writes around the page cache instead
+//! of evicting the working set of other services. Reopen the database with
+//! `IoMode::Buffered` to serve queries.
+//!
+//! # testing
+//!
+//! `memory_storage()` keeps every store in memory and hands out the same bytes
+//! each time a store is opened, so tests and simulations can open, drop and
+//! reopen many small databases quickly and without touching the filesystem.
 
 #![recursion_limit="1024"]
 
 #[macro_use] mod ensure;
+#[macro_use] mod instrument;
 mod setup;
 mod meta;
 mod point;
 mod mix;
+mod key;
 #[macro_use] mod tree;
 mod branch;
 mod staging;
@@ -182,25 +283,173 @@ mod data;
 mod read_block;
 mod pivots;
 mod write_cache;
+mod replicate;
+mod checksum;
+mod snapshot;
+mod fork;
+mod history;
+mod generations;
+mod root_hash;
+mod explain;
+mod curve;
+mod bloom;
+mod blob;
+mod strata;
+mod dedup;
+mod multi;
+mod sorted;
+mod extent;
+mod aggregate;
+mod row_meta;
+mod durability;
+mod spill;
+mod staging_index;
+mod delete_bitmap;
+mod delete_set;
+mod pack;
+mod query_options;
+mod summary;
+mod migrate;
+mod recovery;
+mod clock;
+mod rng;
+mod sample;
+mod merge_policy;
+mod stats;
+mod gc;
+mod backup;
+mod provenance;
+mod replace;
+mod analyze;
+mod batch_ids;
+mod record;
+mod tiles;
+mod crs;
+mod entries;
+mod volume;
+mod fixed;
+mod trajectory;
+mod nearest;
+mod ray;
+mod polygon;
+mod difference;
+mod cursor;
+mod query_ref;
+mod query_into;
+mod executor;
+mod workspace;
+mod partition;
+mod value_reader;
+mod tuning;
+mod prefetch;
+mod parallel;
+mod branch_cache;
+mod quantize;
+mod region;
+mod validate;
+mod varint;
+pub mod reader;
+pub mod import;
+#[cfg(feature="osm")] pub mod osm;
+#[cfg(feature="mmap")] mod mmap;
+#[cfg(feature="arrow")] mod query_arrow;
+#[cfg(feature="parquet")] mod export_parquet;
+mod disk;
+mod memory;
 
 pub use crate::setup::{Setup,SetupFields};
 use crate::staging::{Staging,StagingIterator};
-use crate::planner::plan;
-pub use crate::point::{Point,Scalar,Cursor,Block};
+use crate::merge_policy::check_plan;
+pub use crate::point::{Point,PointOrd,PointCodec,BranchQuery,Scalar,
+  Cursor,Block,query_branch};
 pub use crate::mix::{Mix,Mix2,Mix3,Mix4,Mix5,Mix6,Mix7,Mix8};
+pub use crate::key::Key;
+#[cfg(feature="derive")] pub use eyros_derive::Point;
 #[doc(hidden)] pub use crate::tree::{Tree,TreeIterator,TreeOpts};
 #[doc(hidden)] pub use crate::branch::Branch;
-#[doc(hidden)] pub use crate::data::{DataStore,DataRange};
-use crate::meta::Meta;
-pub use order::{order,order_len};
+#[doc(hidden)] pub use crate::data::DataStore;
+pub use crate::data::{DataRange,DataRangeIterator};
+use crate::meta::{Meta,FORMAT_PACKED,FORMAT_TREES,FORMAT_SCHEMA,FORMAT_CRS,
+  FORMAT_VARINT_DELETES,FORMAT_BLOBS};
+pub use crate::meta::{FORMAT_VERSION,TREE_FORMAT,TREE_V1,TREE_ADAPTIVE};
+pub use order::{order,order_len,branch_factor_at,Levels};
+pub use crate::replicate::{Log,StorageLog};
+pub use crate::fork::{ForkStore,fork_storage};
+#[cfg(feature="mmap")] pub use crate::mmap::{MmapStore,mmap_storage};
+#[cfg(feature="arrow")] pub use crate::query_arrow::{ArrowBatches,
+  ArrowPoint,ArrowValue,ArrowCoord,ArrowScalar,ARROW_BATCH_ROWS};
+#[cfg(feature="parquet")]
+pub use crate::export_parquet::PARQUET_ROW_GROUP_ROWS;
+pub use crate::disk::{DiskStore,IoMode,disk_storage,DIRECT_ALIGN};
+pub use crate::memory::{MemoryStore,memory_storage};
+pub use crate::history::Change;
+use crate::history::History;
+use crate::batch_ids::BatchIds;
+use crate::generations::Generations;
+use crate::bloom::BloomStore;
+use crate::blob::BlobStore;
+pub use crate::root_hash::Hash;
+pub use crate::curve::Curve;
+pub use crate::sorted::{Sort,SortedIterator};
+pub use crate::extent::Extent;
+pub use crate::aggregate::Agg;
+pub use crate::row_meta::{RowMeta,MetaResult,MetaIterator};
+use crate::row_meta::BatchMeta;
+pub use crate::durability::Durability;
+pub use crate::validate::InvertedIntervals;
+pub use crate::delete_set::DeleteSet;
+pub use crate::query_options::QueryOptions;
+pub use crate::summary::Summary;
+pub use crate::recovery::RecoveryReport;
+pub use crate::clock::{Clock,SystemClock};
+use crate::rng::Rng;
+pub use crate::merge_policy::{MergePolicy,Merge,SizeTiered,Leveled,Lazy};
+pub use crate::stats::{Stats,TreeStats};
+pub use crate::gc::GcReport;
+pub use crate::backup::Backup;
+pub use crate::provenance::Sourced;
+pub use crate::analyze::{Analysis,HotBlock};
+pub use crate::batch_ids::BatchId;
+pub use crate::record::{Record,Schema,Field,FieldType,Projection};
+pub use crate::tiles::{Tile,MAX_LATITUDE};
+pub use crate::crs::{Transform,WebMercator,Reproject,ReprojectBounds};
+#[cfg(feature="proj")] pub use crate::crs::ProjTransform;
+pub use crate::volume::{Row3,Point3,Box3,Bounds3,bbox3,cube};
+pub use crate::fixed::FixedWidth;
+pub use crate::trajectory::{Trajectory,Segment,SegmentPoint};
+pub use crate::nearest::{Nearest,NearestCoord};
+pub use crate::ray::{SegmentQuery,SEGMENT_PIECES};
+pub use crate::polygon::{QueryPolygon,PolygonIterator};
+pub use crate::cursor::TreeCursor;
+pub use crate::query_ref::QueryRef;
+pub use crate::executor::Executor;
+pub use crate::workspace::{Workspace,WorkspaceIterator,LayerStore};
+pub use crate::partition::PARTITIONS;
+pub use crate::value_reader::ValueReader;
+pub use crate::tuning::TuningReport;
+pub use crate::prefetch::PrefetchReport;
+pub use crate::parallel::ParallelQuery;
+pub use crate::branch_cache::CacheStats;
+pub use crate::quantize::Quantize;
+pub use crate::region::{QueryRegion,OpenBounds};
+use crate::partition::{Partition,partition_bit};
+use crate::tuning::Tuning;
+use crate::branch_cache::BranchCache;
+use crate::durability::SyncState;
+use crate::spill::Runs;
+use crate::delete_bitmap::DeleteBitmap;
+use crate::query_options::{LatestBy,DedupBy};
+pub use crate::strata::{Strata,StrataIterator,TierStore,TierLocation};
+pub use crate::explain::{Explain,TreeTrace,LevelTrace};
 
 use random_access_storage::RandomAccess;
-use failure::{Error,format_err};
+use failure::{Error,format_err,bail};
 use desert::{ToBytes,FromBytes,CountBytes};
 use std::fmt::Debug;
 use std::cell::RefCell;
 use std::rc::Rc;
-use std::collections::HashSet;
+use std::collections::{HashMap,HashSet};
+use std::time::Instant;
 
 #[doc(hidden)]
 pub enum SubIterator<'b,S,P,V>
@@ -209,11 +458,26 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   Staging(StagingIterator<'b,P,V>)
 }
 
+#[doc(hidden)]
+pub mod __derive {
+  pub use desert::{ToBytes,FromBytes,CountBytes};
+  pub use failure::Error;
+}
+
 /// Data to use for the payload portion stored at a coordinate.
 pub trait Value: Debug+Clone+ToBytes+FromBytes+CountBytes+'static {}
 impl<T> Value for T where T: Debug+Clone+ToBytes+FromBytes+CountBytes+'static {}
 
 /// Stores where a record is stored to avoid additional queries during deletes.
+///
+/// The first field is one more than the byte offset of the data block that
+/// holds the record in the `data` store, or `0` for staged records, and the
+/// second is the index of the record within its data block or within staging.
+/// Locations do not name a tree, so there is no limit on the number of trees,
+/// and since data blocks are addressed by byte offset a tree may hold any
+/// number of rows: only a single data block or the staging area is limited
+/// to 2^32 rows, far more than `max_data_size()` or `base_size()` allow.
+///
 /// Locations are only valid until the next `batch()`. There is no runtime check
 /// yet to ensure that batches will invalidate existing locations, so you will
 /// need to be careful of this yourself. Otherwise the wrong data could be
@@ -237,6 +501,28 @@ P: Point, V: Value {
   pub staging: Staging<S,P,V>,
   pub data_store: Rc<RefCell<DataStore<S,P,V>>>,
   meta: Meta<S>,
+  log: Option<Box<dyn Log>>,
+  history: Option<History<S>>,
+  batch_ids: Option<BatchIds<S>>,
+  schema: Option<Schema>,
+  transform: Option<Rc<dyn Transform>>,
+  generations: Option<Generations<S>>,
+  root_hash: Option<Hash>,
+  sync_state: SyncState,
+  rng: Rng,
+  merge_policy: Rc<dyn MergePolicy>,
+  // partition key extractor from Setup::partition()
+  partition: Option<Rc<Partition<V>>>,
+  // serialized size of the records inserted since the database was opened
+  logical_bytes: u64,
+  // write costs measured since the database was opened
+  tuning: Tuning,
+  // budget and counters of the branch blocks pinned by the trees
+  branch_cache: Rc<BranchCache>,
+  // quantization of each dimension stored in the meta store
+  quantize: Option<Rc<[Quantize]>>,
+  // workers of db.query_parallel(), started by its first call
+  parallel: Option<Executor<P,V>>,
   pub fields: SetupFields
 }
 
@@ -329,35 +615,302 @@ P: Point, V: Value {
   /// change . There is no runtime check yet to ensure a database is opened with
   /// the same configuration that it was created with.
   pub fn open_from_setup(setup: Setup<S,U>) -> Result<Self,Error> {
-    let meta = Meta::open((setup.open_store)("meta")?)?;
-    let staging = Staging::open(
-      (setup.open_store)("staging_inserts")?,
-      (setup.open_store)("staging_deletes")?
-    )?;
-    let data_store = DataStore::open(
+    Self::open_view(setup, true)
+  }
+
+  // open the database, leaving staging empty unless `load_staging` is set
+  // for read views that only query the trees
+  pub(crate) fn open_view (setup: Setup<S,U>, load_staging: bool)
+  -> Result<Self,Error> {
+    let bfs = &setup.fields.branch_factors;
+    if !bfs.is_empty() && bfs.len() != P::dim() {
+      bail!["expected {} branch factors (one per dimension), found {}",
+        P::dim(), bfs.len()];
+    }
+    for bf in bfs.iter() {
+      if *bf < 3 || !(*bf-1).is_power_of_two() {
+        bail!["branch factor must be a power of 2 plus 1, found {}", bf];
+      }
+    }
+    for d in setup.fields.dimension_cycle.iter() {
+      if *d >= P::dim() {
+        bail!["dimension cycle entry {} out of bounds for {} dimensions",
+          d, P::dim()];
+      }
+    }
+    let mut meta = Meta::open((setup.open_store)("meta")?)?;
+    let is_new = meta.store.is_empty()?;
+    let mut data_store = DataStore::open(
       (setup.open_store)("data")?,
       (setup.open_store)("range")?,
       setup.fields.max_data_size,
       setup.fields.bbox_cache_size,
       setup.fields.data_list_cache_size
     )?;
+    data_store.block_bounds = setup.fields.block_bounds;
+    if setup.fields.pack_coords && meta.store.is_empty()? {
+      meta.format = FORMAT_VERSION;
+      meta.save()?;
+    }
+    if setup.fields.adaptive_split && meta.format < FORMAT_TREES {
+      // the format of each tree is needed to tell which ones have headers
+      meta.require_format(FORMAT_TREES, "Setup::adaptive_split()")?;
+      meta.save()?;
+    }
+    if let Some(schema) = &setup.fields.schema {
+      let bytes = schema.to_bytes();
+      meta.require_format(FORMAT_SCHEMA, "Setup::schema()")?;
+      if meta.schema.is_empty() {
+        meta.schema = bytes;
+        meta.save()?;
+      } else if meta.schema != bytes {
+        bail!["schema does not match the schema stored in the database"];
+      }
+    }
+    if let Some(crs) = &setup.fields.crs {
+      meta.require_format(FORMAT_CRS, "Setup::crs()")?;
+      if meta.crs.is_empty() {
+        meta.crs = crs.clone();
+        meta.save()?;
+      } else if meta.crs != *crs {
+        bail!["crs {} does not match the crs of the database ({})",
+          crs, meta.crs];
+      }
+    }
+    if let Some(transform) = &setup.transform {
+      if !meta.crs.is_empty() && transform.target() != meta.crs {
+        bail!["transform to {} does not match the crs of the database ({})",
+          transform.target(), meta.crs];
+      }
+    }
+    if setup.fields.varint_deletes && meta.format < FORMAT_VARINT_DELETES {
+      meta.require_format(FORMAT_VARINT_DELETES, "Setup::varint_deletes()")?;
+      meta.save()?;
+    }
+    if let Some(threshold) = setup.fields.inline_threshold {
+      if meta.format < FORMAT_BLOBS {
+        meta.require_format(FORMAT_BLOBS, "Setup::inline_threshold()")?;
+        meta.save()?;
+      }
+      data_store.blobs = Some(BlobStore::open(
+        (setup.open_store)("blobs")?,
+        threshold
+      ));
+    }
+    if let Some(quantize) = &setup.fields.quantize {
+      Quantize::check::<P>(quantize)?;
+      if meta.quantize.is_empty() {
+        // points already written at full precision would be misread
+        if !is_new {
+          bail!["Setup::quantize() can only be set on a new database"];
+        }
+        meta.format = FORMAT_VERSION;
+        meta.quantize = quantize.clone();
+        meta.save()?;
+      } else if meta.quantize != *quantize {
+        bail!["quantization does not match the quantization of the \
+          database"];
+      }
+    }
+    let quantize: Option<Rc<[Quantize]>> = if meta.quantize.is_empty() { None }
+      else { Some(Rc::from(meta.quantize.clone())) };
+    data_store.quantize = quantize.clone();
+    // open staging once the format is settled, which sets how deletes are
+    // encoded
+    let mut staging = Staging::open(
+      (setup.open_store)("staging_inserts")?,
+      (setup.open_store)("staging_deletes")?,
+      meta.format >= FORMAT_VARINT_DELETES,
+      setup.fields.strict_open,
+      load_staging
+    )?;
+    let schema = if meta.schema.is_empty() { None }
+      else { Some(Schema::from_bytes(&meta.schema)?) };
+    data_store.pack_coords = meta.format >= FORMAT_PACKED;
+    if setup.fields.bloom_bits > 0 {
+      data_store.bloom = Some(BloomStore::open(
+        (setup.open_store)("bloom")?,
+        setup.fields.bloom_bits
+      )?);
+    }
+    if setup.fields.delete_bitmap {
+      if setup.fields.generations {
+        bail!["Setup::delete_bitmap() is not supported with generations"];
+      }
+      data_store.delete_bitmap = Some(DeleteBitmap::open(
+        (setup.open_store)("delete_bitmap")?
+      )?);
+    }
+    if setup.fields.row_meta || setup.fields.timestamps {
+      data_store.batch_meta = Some(BatchMeta::open(
+        (setup.open_store)("batch_meta")?,
+        (setup.open_store)("staging_meta")?,
+        setup.fields.timestamps,
+        setup.clock.unwrap_or_else(|| Rc::new(SystemClock))
+      )?);
+    }
+    let history = if setup.fields.history {
+      Some(History::open(
+        (setup.open_store)("history")?,
+        (setup.open_store)("history_index")?
+      )?)
+    } else {
+      None
+    };
+    let batch_ids = if setup.fields.batch_ids > 0 {
+      Some(BatchIds::open(
+        (setup.open_store)("batch_ids")?,
+        setup.fields.batch_ids
+      )?)
+    } else {
+      None
+    };
+    let generations = if setup.fields.generations {
+      Some(Generations::open(
+        (setup.open_store)("generations")?,
+        (setup.open_store)("generations_index")?,
+        (setup.open_store)("generations_trees")?
+      )?)
+    } else {
+      None
+    };
+    let partition = match setup.partition {
+      None => None,
+      Some(p) => match p.downcast::<Partition<V>>() {
+        Ok(p) => Some(p),
+        Err(_) => bail!["Setup::partition() extracts from a different value \
+          type than the database has"]
+      }
+    };
+    let sync_state = SyncState::new(setup.fields.durability);
+    data_store.sync = Rc::clone(&sync_state.inline);
+    staging.sync = Rc::clone(&sync_state.inline);
+    staging.set_cache_limit(setup.fields.write_cache_size)?;
+    if setup.fields.staging_spill > 0 {
+      if setup.fields.row_meta || setup.fields.timestamps
+      || setup.fields.generations {
+        bail!["Setup::staging_spill() is not supported with row metadata or \
+          generations"];
+      }
+      staging.runs = Some(Runs::open(
+        (setup.open_store)("staging_runs")?,
+        setup.fields.staging_spill
+      )?);
+    }
     let mut db = Self {
       open_store: setup.open_store,
       staging,
       data_store: Rc::new(RefCell::new(data_store)),
       meta: meta,
       trees: vec![],
+      log: setup.log,
+      history,
+      batch_ids,
+      schema,
+      transform: setup.transform,
+      generations,
+      root_hash: None,
+      sync_state,
+      rng: setup.fields.seed.map(Rng::new).unwrap_or_else(Rng::from_entropy),
+      merge_policy: setup.merge_policy.unwrap_or_else(|| Rc::new(SizeTiered)),
+      partition,
+      logical_bytes: 0,
+      tuning: Tuning::default(),
+      branch_cache: Rc::new(BranchCache::new(
+        setup.fields.pin_levels, setup.fields.pin_bytes)),
+      quantize,
+      parallel: None,
       fields: setup.fields
     };
     for i in 0..db.meta.mask.len() {
       db.create_tree(i)?;
     }
+    db.init_generations()?;
     Ok(db)
   }
 
   /// Write a collection of updates to the database. Each update can be a
   /// `Row::Insert(point,value)` or a `Row::Delete(location)`.
+  ///
+  /// If a log was configured with `Setup::log()`, the batch is appended to the
+  /// log after it has been written. If history is enabled with
+  /// `Setup::history()`, a summary of the batch is recorded for
+  /// `db.changes_since()`. If generations are kept with
+  /// `Setup::keep_generations()`, the batch starts a new generation for
+  /// `db.query_at()`. The cached `db.root_hash()` is invalidated.
+  ///
+  /// With `Setup::dedup()`, inserts of a point and value that are already
+  /// stored are dropped before any of this happens.
+  ///
+  /// Stores are synced according to the `Setup::durability()` policy.
   pub fn batch (&mut self, rows: &[Row<P,V>]) -> Result<(),Error> {
+    let normalized;
+    let rows = match self.validate_rows(rows)? {
+      Some(swapped) => { normalized = swapped; &normalized[..] },
+      None => rows
+    };
+    let deduped;
+    let rows = if self.fields.dedup {
+      deduped = self.dedup(rows)?;
+      &deduped[..]
+    } else {
+      rows
+    };
+    let timer = instrument::timer();
+    let started = Instant::now();
+    let flushes = self.tuning.flushes;
+    let _span = span!("batch", rows = rows.len());
+    self.invalidate();
+    if let Some(meta) = &mut self.data_store.try_borrow_mut()?.batch_meta {
+      meta.begin()?;
+    }
+    self.apply_batch(rows)?;
+    for row in rows.iter() {
+      if let Row::Insert(p,v) = row {
+        self.logical_bytes += (p.count_bytes() + v.count_bytes()) as u64;
+      }
+    }
+    self.record_generation()?;
+    if let Some(log) = &mut self.log {
+      log.append(&rows.to_bytes()?)?;
+    }
+    if let Some(history) = &mut self.history {
+      history.append(rows)?;
+    }
+    self.sync_after_batch()?;
+    let flushed = self.tuning.flushes != flushes;
+    self.tuning.batch(rows.len(), started.elapsed(), flushed);
+    instrument::batch(timer, rows.len());
+    Ok(())
+  }
+
+  // forget what was derived from the stores before they change: the cached
+  // root hash and the views of the db.query_parallel() workers
+  pub(crate) fn invalidate (&mut self) {
+    self.root_hash = None;
+    if let Some(executor) = &self.parallel {
+      executor.refresh();
+    }
+  }
+
+  /// Build every staged insert into the trees and apply the staged deletes,
+  /// leaving staging empty, without waiting for staging to reach the
+  /// thresholds set with `Setup::staging_threshold_rows()` and
+  /// `Setup::staging_threshold_bytes()`. Queries are fastest with nothing
+  /// staged, at the cost of a smaller tree that is merged again later.
+  ///
+  /// The cached `db.root_hash()` is invalidated, a generation is recorded if
+  /// generations are kept, and stores are synced according to the
+  /// `Setup::durability()` policy.
+  pub fn flush_staging (&mut self) -> Result<(),Error> {
+    if self.staging.len()? == 0 { return Ok(()) }
+    self.invalidate();
+    self.apply_rows(vec![], vec![], true)?;
+    self.record_generation()?;
+    self.sync_after_batch()
+  }
+
+  fn apply_batch (&mut self, rows: &[Row<P,V>]) -> Result<(),Error> {
     let inserts: Vec<(P,V)> = rows.iter()
       .filter(|r| match r { Row::Insert(_p,_v) => true, _ => false })
       .map(|r| match r {
@@ -365,49 +918,122 @@ P: Point, V: Value {
         _ => panic!["unexpected non-insert row type"]
       })
       .collect();
-    let mut deletes: Vec<Location> = rows.iter()
+    let deletes: Vec<Location> = rows.iter()
       .filter(|r| match r { Row::Delete(_loc) => true, _ => false })
       .map(|r| match r {
         Row::Delete(loc) => *loc,
         _ => panic!["unexpected non-delete row type"]
       })
       .collect();
-    let n = (self.staging.inserts.try_borrow()?.len()+inserts.len()) as u64;
-    let ndel = (self.staging.deletes.try_borrow()?.len()+deletes.len()) as u64;
+    self.apply_rows(inserts, deletes, false)
+  }
+
+  // stage the rows or build staged rows into trees once staging grows past
+  // its thresholds. with `force`, every staged row is built into a tree
+  pub(crate) fn apply_rows (&mut self, inserts: Vec<(P,V)>,
+  mut deletes: Vec<Location>, force: bool) -> Result<(),Error> {
+    let sync = self.sync_state.inline.get();
     let base = self.fields.base_size as u64;
-    if ndel >= base && n <= base {
+    if self.fields.delete_bitmap && deletes.iter().any(|loc| loc.0 != 0) {
+      // tree deletes go straight into the delete bitmaps instead of staging
+      let tree_deletes: Vec<Location> = deletes.iter()
+        .filter(|loc| loc.0 != 0).copied().collect();
+      deletes.retain(|loc| loc.0 == 0);
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      dstore.mark_deleted(&tree_deletes)?;
+      let count = dstore.delete_bitmap.as_ref().map(|d| d.len()).unwrap_or(0);
+      if count >= base {
+        dstore.apply_deletes()?;
+      }
+      dstore.commit()?;
+    }
+    let n = (self.staging.inserts_len()?+inserts.len()) as u64;
+    let flush = force || n > self.staging_rows()
+      || self.staging_over_bytes(&inserts)?;
+    if flush {
+      // spilled runs are merged back into memory to be built into a tree
+      self.staging.merge_runs()?;
+      // staged rows that were deleted, before or in this batch, are dropped
+      // instead of built. their indexes do not hold for the rows left in
+      // staging after the build, so only tree deletes are kept
+      let staged: Vec<Location> = self.staging.deletes.try_borrow()?.iter()
+        .chain(deletes.iter())
+        .filter(|loc| loc.0 == 0)
+        .copied()
+        .collect();
+      deletes.retain(|loc| loc.0 != 0);
+      self.staging.deletes.try_borrow_mut()?.retain(|loc| loc.0 != 0);
+      self.staging.delete(&staged)?;
+      if let Some(meta) = &mut self.data_store.try_borrow_mut()?.batch_meta {
+        meta.unstage(&staged);
+      }
+    }
+    let n = (self.staging.inserts_len()?+inserts.len()) as u64;
+    let ndel = (self.staging.deletes.try_borrow()?.len()+deletes.len()) as u64;
+    if (ndel >= base || flush) && (n == 0 || !flush) {
+      let _span = span!("staging", inserts = n, deletes = ndel, flush = true);
       deletes.extend_from_slice(&self.staging.deletes.try_borrow()?);
+      self.journal_deletes(&deletes)?;
       let mut dstore = self.data_store.try_borrow_mut()?;
       dstore.delete(&deletes)?;
       dstore.commit()?;
       self.staging.batch(&inserts, &vec![])?;
       self.staging.delete(&deletes)?;
       self.staging.clear_deletes()?;
+      if n == 0 {
+        // every staged insert was deleted, so none are left to read back
+        self.staging.clear_inserts()?;
+      }
       self.staging.commit()?;
+      if let Some(meta) = &mut dstore.batch_meta {
+        meta.stage(inserts.len())?;
+        meta.unstage(&deletes);
+        if sync { meta.commit()?; }
+      }
       return Ok(())
-    } else if n <= base {
+    } else if !flush {
+      let _span = span!("staging", inserts = n, deletes = ndel);
       self.staging.batch(&inserts, &deletes)?;
       self.staging.commit()?;
+      if let Some(meta) = &mut self.data_store.try_borrow_mut()?.batch_meta {
+        meta.stage(inserts.len())?;
+        if sync { meta.commit()?; }
+      }
       return Ok(())
     }
-    let count = (n/base)*base;
+    let started = Instant::now();
+    // tree deletes are applied before the build, since merged trees copy the
+    // rows of the trees they replace into new blocks
+    deletes.extend_from_slice(&self.staging.deletes.try_borrow()?);
+    self.staging.clear_deletes()?;
+    if !deletes.is_empty() {
+      self.journal_deletes(&deletes)?;
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      dstore.delete(&deletes)?;
+      dstore.commit()?;
+    }
+    // staged rows short of a whole tree are only built into a tree when
+    // staging is flushed before it holds `base_size()` rows
+    let units = if force || n < base { (n+base-1)/base } else { n/base };
+    let count = (units*base).min(n);
     let rem = n - count;
     let mut mask = vec![];
     for tree in self.trees.iter_mut() {
       mask.push(!tree.try_borrow_mut()?.is_empty()?);
     }
-    let p = plan(
-      &bits::num_to_bits(n/base),
-      &mask
-    );
+    let chunks = bits::num_to_bits(units);
+    let p = self.merge_policy.plan(&chunks, &mask);
+    check_plan(&p, &chunks, &mask)?;
+    event!(target: "eyros::planner", rows = n, trees = p.len(), "plan");
     let mut offset = 0;
     let slen = self.staging.inserts.try_borrow()?.len();
-    for (i,staging,trees) in p {
+    for Merge { tree: i, staging, trees } in p {
       let mut irows: Vec<(usize,usize)> = vec![];
       for j in staging {
         let size = (2u64.pow(j as u32) * base) as usize;
-        irows.push((offset,offset+size));
-        offset += size;
+        let end = (offset+size).min(count as usize);
+        irows.push((offset,end));
+        offset = end;
       }
       for t in trees.iter() {
         self.create_tree(*t)?;
@@ -425,19 +1051,29 @@ P: Point, V: Value {
           );
         }
       }
+      curve::sort(self.fields.curve, &mut srows);
+      self.preserve_tree(i)?;
+      for t in trees.iter() {
+        self.preserve_tree(*t)?;
+      }
       if trees.is_empty() {
+        let _span = span!("build", tree = i, rows = srows.len());
         self.meta.mask[i] = true;
         self.trees[i].try_borrow_mut()?.build(&srows)?;
       } else {
-        self.meta.mask[i] = true;
         for t in trees.iter() {
           self.meta.mask[*t] = false;
         }
+        self.meta.mask[i] = true;
         Tree::merge(&mut self.trees, i, trees, &srows)?;
       }
     }
     ensure_eq!(n-(offset as u64), rem, "offset-n ({}-{}={}) != rem ({}) ",
       offset, n, (offset as u64)-n, rem);
+    let rem_meta = self.data_store.try_borrow()?.batch_meta.as_ref()
+      .map(|meta| (offset..n as usize).map(|k| {
+        meta.staging(k).filter(|_| k < slen).unwrap_or(meta.current())
+      }).collect::<Vec<_>>());
     let mut rem_rows = vec![];
     for k in offset..n as usize {
       rem_rows.push(
@@ -448,20 +1084,50 @@ P: Point, V: Value {
     ensure_eq!(rem_rows.len(), rem as usize,
       "unexpected number of remaining rows (expected {}, actual {})",
       rem, rem_rows.len());
-    deletes.extend_from_slice(&self.staging.deletes.try_borrow()?);
     self.staging.clear()?;
     self.staging.batch(&rem_rows, &vec![])?;
     self.staging.delete(&deletes)?;
     self.staging.commit()?;
-    if !deletes.is_empty() {
-      let mut dstore = self.data_store.try_borrow_mut()?;
-      dstore.delete(&deletes)?;
-      dstore.commit()?;
+    if let Some(metas) = rem_meta {
+      if let Some(meta) = &mut self.data_store.try_borrow_mut()?.batch_meta {
+        meta.restage(metas)?;
+        meta.unstage(&deletes);
+        if sync { meta.commit()?; }
+      }
     }
-    self.meta.save()?;
+    self.save_meta()?;
+    self.tuning.flushed(offset, started.elapsed());
+    self.retune();
     Ok(())
   }
 
+  // staged inserts to hold before they are built into trees
+  fn staging_rows (&self) -> u64 {
+    match self.fields.staging_threshold_rows {
+      0 => self.fields.base_size as u64,
+      rows => rows as u64
+    }
+  }
+
+  // whether staging would hold more than `Setup::staging_threshold_bytes()`
+  // with `inserts` written
+  fn staging_over_bytes (&mut self, inserts: &[(P,V)]) -> Result<bool,Error> {
+    let max = self.fields.staging_threshold_bytes as u64;
+    if max == 0 { return Ok(false) }
+    let bytes: u64 = inserts.iter().map(|row| row.count_bytes() as u64).sum();
+    Ok(self.staging.bytes()? + bytes > max)
+  }
+
+  // record the format tag of each tree along with the tree mask
+  pub(crate) fn save_meta (&mut self) -> Result<(),Error> {
+    let mut tags = Vec::with_capacity(self.trees.len());
+    for tree in self.trees.iter() {
+      tags.push(tree.try_borrow()?.format);
+    }
+    self.meta.trees = tags;
+    self.meta.save()
+  }
+
   fn create_tree (&mut self, index: usize) -> Result<(),Error> {
     for i in self.trees.len()..index+1 {
       let store = (self.open_store)(&format!("tree{}",i))?;
@@ -469,13 +1135,163 @@ P: Point, V: Value {
         store,
         index,
         data_store: Rc::clone(&self.data_store),
-        branch_factor: self.fields.branch_factor,
+        levels: self.fields.levels(),
         max_data_size: self.fields.max_data_size,
+        sync: Rc::clone(&self.sync_state.inline),
+        branch_bounds: self.fields.branch_bounds,
+        branch_prefetch: self.fields.branch_prefetch,
+        partition: self.partition.clone(),
+        branch_cache: Rc::clone(&self.branch_cache),
+        format: self.meta.trees.get(i).copied().unwrap_or(TREE_V1)
       })?)));
     }
     Ok(())
   }
 
+  /// Bytes of staging writes held in memory that have not been written to
+  /// the staging stores. Writes are flushed when the stores are synced
+  /// according to `Setup::durability()` and as needed to stay under
+  /// `Setup::write_cache_size()`.
+  pub fn write_cache_bytes (&self) -> usize {
+    self.staging.cached_bytes()
+  }
+
+  /// Names of every store that holds database state, in a stable order.
+  pub(crate) fn store_names (&self) -> Vec<String> {
+    let mut names: Vec<String> = [
+      "meta", "staging_inserts", "staging_deletes", "data", "range"
+    ].iter().map(|s| s.to_string()).collect();
+    if self.staging.runs.is_some() {
+      names.push("staging_runs".to_string());
+    }
+    if self.fields.bloom_bits > 0 {
+      names.push("bloom".to_string());
+    }
+    if self.fields.inline_threshold.is_some() {
+      names.push("blobs".to_string());
+    }
+    if self.fields.delete_bitmap {
+      names.push("delete_bitmap".to_string());
+    }
+    if self.fields.row_meta || self.fields.timestamps {
+      names.push("batch_meta".to_string());
+      names.push("staging_meta".to_string());
+    }
+    if self.history.is_some() {
+      names.push("history".to_string());
+      names.push("history_index".to_string());
+    }
+    if self.batch_ids.is_some() {
+      names.push("batch_ids".to_string());
+    }
+    for i in 0..self.trees.len() {
+      names.push(format!("tree{}",i));
+    }
+    names.extend(self.generation_store_names());
+    names
+  }
+
+  pub(crate) fn store_len (&mut self, name: &str) -> Result<u64,Error> {
+    match name {
+      "meta" => self.meta.store.len(),
+      "staging_inserts" => self.staging.insert_store.len(),
+      "staging_deletes" => self.staging.delete_store.len(),
+      "staging_runs" => match &self.staging.runs {
+        Some(runs) => runs.store.len(),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "data" => self.data_store.try_borrow()?.store.len(),
+      "range" => self.data_store.try_borrow()?.range.store.len(),
+      "bloom" => match &self.data_store.try_borrow()?.bloom {
+        Some(bloom) => bloom.store.len(),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "blobs" => match &self.data_store.try_borrow()?.blobs {
+        Some(blobs) => blobs.store.len(),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "delete_bitmap" => match &self.data_store.try_borrow()?.delete_bitmap {
+        Some(bitmap) => bitmap.store.len(),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "batch_meta" | "staging_meta" => {
+        match &self.data_store.try_borrow()?.batch_meta {
+          Some(meta) if name == "batch_meta" => meta.store.len(),
+          Some(meta) => meta.staging_store.len(),
+          None => Err(format_err!["unknown store {}", name])
+        }
+      },
+      "history" | "history_index" => self.history_store(name)?.len(),
+      "batch_ids" => match &self.batch_ids {
+        Some(ids) => ids.store.len(),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      _ if is_generation_store(name) => self.generation_store(name)?.len(),
+      _ => self.trees[tree_index(name)?].try_borrow()?.store.len()
+    }
+  }
+
+  pub(crate) fn read_store (&mut self, name: &str, offset: u64, length: u64)
+  -> Result<Vec<u8>,Error> {
+    if length == 0 { return Ok(vec![]) }
+    match name {
+      "meta" => self.meta.store.read(offset, length),
+      "staging_inserts" => self.staging.insert_store.read(offset, length),
+      "staging_deletes" => self.staging.delete_store.read(offset, length),
+      "staging_runs" => match &mut self.staging.runs {
+        Some(runs) => runs.store.read(offset, length),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "data" => self.data_store.try_borrow_mut()?.store.read(offset, length),
+      "range" => {
+        self.data_store.try_borrow_mut()?.range.store.read(offset, length)
+      },
+      "bloom" => match &mut self.data_store.try_borrow_mut()?.bloom {
+        Some(bloom) => bloom.store.read(offset, length),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "blobs" => match &mut self.data_store.try_borrow_mut()?.blobs {
+        Some(blobs) => blobs.store.read(offset, length),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      "delete_bitmap" => {
+        match &mut self.data_store.try_borrow_mut()?.delete_bitmap {
+          Some(bitmap) => bitmap.store.read(offset, length),
+          None => Err(format_err!["unknown store {}", name])
+        }
+      },
+      "batch_meta" | "staging_meta" => {
+        match &mut self.data_store.try_borrow_mut()?.batch_meta {
+          Some(meta) if name == "batch_meta" => meta.store.read(offset, length),
+          Some(meta) => meta.staging_store.read(offset, length),
+          None => Err(format_err!["unknown store {}", name])
+        }
+      },
+      "history" | "history_index" => {
+        self.history_store(name)?.read(offset, length)
+      },
+      "batch_ids" => match &mut self.batch_ids {
+        Some(ids) => ids.store.read(offset, length),
+        None => Err(format_err!["unknown store {}", name])
+      },
+      _ if is_generation_store(name) => {
+        self.generation_store(name)?.read(offset, length)
+      },
+      _ => {
+        let i = tree_index(name)?;
+        self.trees[i].try_borrow_mut()?.store.read(offset, length)
+      }
+    }
+  }
+
+  fn history_store (&mut self, name: &str) -> Result<&mut S,Error> {
+    match (&mut self.history, name) {
+      (Some(history), "history") => Ok(&mut history.log.data),
+      (Some(history), "history_index") => Ok(&mut history.log.index),
+      _ => Err(format_err!["unknown store {}", name])
+    }
+  }
+
   /// Query the database for all records that intersect the bounding box.
   ///
   /// The bounding box is a 2-tuple of n-tuples (for an n-dimensional point
@@ -509,20 +1325,82 @@ P: Point, V: Value {
   /// If you want to delete records, you will need to use the `Location` records
   /// you get from a query. However, these locations are only valid until the
   /// next `.batch()`.
+  ///
+  /// With `Setup::dedup_results()`, results with the same point and value as
+  /// an earlier result are skipped.
   pub fn query<'b> (&mut self, bbox: &'b P::Bounds)
   -> Result<QueryIterator<'b,S,P,V>,Error> {
+    self.query_with(bbox, &QueryOptions::default())
+  }
+
+  /// Query the database like `db.query()` with the options in `opts`.
+  pub fn query_with<'b> (&mut self, bbox: &'b P::Bounds, opts: &QueryOptions)
+  -> Result<QueryIterator<'b,S,P,V>,Error> {
+    self.query_filtered(bbox, opts, None)
+  }
+
+  // query with `opts`, skipping the children of tree branches whose bounds
+  // fail `filter`
+  pub(crate) fn query_filtered<'b> (&mut self, bbox: &'b P::Bounds,
+  opts: &QueryOptions, filter: Option<Rc<dyn Fn(&P::Bounds) -> bool+'b>>)
+  -> Result<QueryIterator<'b,S,P,V>,Error> {
+    if opts.max_depth.is_some() {
+      bail!["use db.query_summary() for queries with QueryOptions::max_depth()"];
+    }
+    if opts.partitions.is_some() && self.partition.is_none() {
+      bail!["QueryOptions::partitions() needs a database opened with \
+        Setup::partition()"];
+    }
     let mut mask: Vec<bool> = vec![];
     for tree in self.trees.iter_mut() {
       mask.push(!tree.try_borrow_mut()?.is_empty()?);
     }
+    let _span = span!("query", trees = mask.iter().filter(|m| **m).count());
     let mut queries = Vec::with_capacity(1+self.trees.len());
-    queries.push(SubIterator::Staging(self.staging.query(bbox)));
+    queries.push(SubIterator::Staging(self.staging.query(bbox)?));
     for (i,tree) in self.trees.iter_mut().enumerate() {
       if !mask[i] { continue }
-      queries.push(SubIterator::Tree(Tree::query(Rc::clone(tree),bbox)?));
+      let mut iter = Tree::query(Rc::clone(tree),bbox)?;
+      if opts.interleave { iter = iter.interleave() }
+      if let Some(bytes) = opts.memory_budget {
+        iter = iter.memory_budget(bytes);
+      }
+      if let Some(mask) = opts.partitions {
+        iter = iter.partitions(mask);
+      }
+      if let Some(filter) = &filter {
+        iter = iter.filter_bounds(Rc::clone(filter));
+      }
+      queries.push(SubIterator::Tree(iter));
+    }
+    let deletes = Rc::clone(&self.staging.delete_set);
+    let mut iter = QueryIterator::new(queries, deletes)?
+      .window(opts.offset, opts.limit);
+    if let (Some(partition), Some(mask)) = (&self.partition, opts.partitions) {
+      iter = iter.partitions(Rc::clone(partition), mask);
+    }
+    if let Some(latest) = opts.latest::<V>()? {
+      iter = iter.latest(latest);
     }
-    QueryIterator::new(queries, Rc::clone(&self.staging.delete_set))
+    if let Some(key) = opts.dedup_key::<V>()? {
+      iter = iter.dedup_by(key);
+    }
+    if self.fields.delete_bitmap {
+      iter = iter.check_bitmaps(Rc::clone(&self.data_store));
+    }
+    Ok(if self.fields.dedup_results { iter.dedup() } else { iter })
+  }
+}
+
+fn is_generation_store (name: &str) -> bool {
+  name.starts_with("generations") || name.contains('@')
+}
+
+fn tree_index (name: &str) -> Result<usize,Error> {
+  if !name.starts_with("tree") {
+    return Err(format_err!["unknown store {}", name]);
   }
+  Ok(name[4..].parse::<usize>()?)
 }
 
 /// Iterator of `Result<(Point,Value,Location)>` data returned by `db.query()`.
@@ -530,21 +1408,100 @@ pub struct QueryIterator<'b,S,P,V> where
 S: RandomAccess<Error=Error>, P: Point, V: Value {
   index: usize,
   queries: Vec<SubIterator<'b,S,P,V>>,
-  deletes: Rc<RefCell<HashSet<Location>>>
+  deletes: Rc<RefCell<DeleteSet>>,
+  // delete bitmaps are checked again as results are returned, for rows that
+  // were read before a later batch deleted them
+  data_store: Option<Rc<RefCell<DataStore<S,P,V>>>>,
+  seen: Option<HashSet<Vec<u8>>>,
+  // key extractor from QueryOptions::dedup_by() and the keys returned so far
+  seen_keys: Option<(Rc<DedupBy<V>>,HashSet<Vec<u8>>)>,
+  // results left to skip and to return, from QueryOptions
+  offset: usize,
+  limit: Option<usize>,
+  latest: Option<Rc<LatestBy<V>>>,
+  // latest record for each id, once every result has been read
+  pending: Option<std::vec::IntoIter<(P,V,Location)>>,
+  // partition key extractor and the partitions to return as a bitmask, from
+  // QueryOptions::partitions()
+  partitions: Option<(Rc<Partition<V>>,u64)>
 }
 
 impl<'b,S,P,V> QueryIterator<'b,S,P,V> where
 S: RandomAccess<Error=Error>, P: Point, V: Value {
   pub fn new (queries: Vec<SubIterator<'b,S,P,V>>,
-  deletes: Rc<RefCell<HashSet<Location>>>) -> Result<Self,Error> {
-    Ok(Self { deletes, queries, index: 0 })
+  deletes: Rc<RefCell<DeleteSet>>) -> Result<Self,Error> {
+    Ok(Self {
+      deletes, queries, index: 0, data_store: None, seen: None,
+      seen_keys: None, offset: 0, limit: None, latest: None, pending: None,
+      partitions: None
+    })
   }
-}
-
-impl<'b,S,P,V> Iterator for QueryIterator<'b,S,P,V> where
-S: RandomAccess<Error=Error>, P: Point, V: Value {
-  type Item = Result<(P,V,Location),Error>;
-  fn next (&mut self) -> Option<Self::Item> {
+  // skip tree results that are in the delete bitmaps of `data_store`
+  pub(crate) fn check_bitmaps (mut self,
+  data_store: Rc<RefCell<DataStore<S,P,V>>>) -> Self {
+    self.data_store = Some(data_store);
+    self
+  }
+  /// Skip results whose point and value are identical to a result that was
+  /// already returned, such as a record that is still present in both the
+  /// staging area and a tree. The serialized form of every result is kept
+  /// in memory until the iterator is dropped.
+  pub fn dedup (mut self) -> Self {
+    self.seen = Some(HashSet::new());
+    self
+  }
+  /// Skip the first `offset` results and stop after `limit` more. The
+  /// sub-queries are dropped as soon as the limit is reached, so no further
+  /// blocks are read.
+  pub fn window (mut self, offset: usize, limit: Option<usize>) -> Self {
+    self.offset = offset;
+    self.limit = limit;
+    self
+  }
+  // skip results with a key that was already returned, from
+  // QueryOptions::dedup_by()
+  pub(crate) fn dedup_by (mut self, key: Rc<DedupBy<V>>) -> Self {
+    self.seen_keys = Some((key,HashSet::new()));
+    self
+  }
+  // return only the latest record for each id, from QueryOptions::latest_by()
+  pub(crate) fn latest (mut self, latest: Rc<LatestBy<V>>) -> Self {
+    self.latest = Some(latest);
+    self
+  }
+  // return only records in the partitions of `mask`, from
+  // QueryOptions::partitions()
+  pub(crate) fn partitions (mut self, partition: Rc<Partition<V>>,
+  mask: u64) -> Self {
+    self.partitions = Some((partition,mask));
+    self
+  }
+  fn next_latest (&mut self) -> Option<Result<(P,V,Location),Error>> {
+    let latest = match &self.latest {
+      None => return self.next_result(),
+      Some(latest) => Rc::clone(latest)
+    };
+    if self.pending.is_none() {
+      let mut index: HashMap<Vec<u8>,usize> = HashMap::new();
+      let mut best: Vec<(P,V,Location)> = vec![];
+      while let Some(result) = self.next_result() {
+        let (p,v,loc) = iwrap![result];
+        let id = iwrap![(latest.id)(&v)];
+        match index.get(&id) {
+          Some(i) => {
+            if (latest.newer)(&v, &best[*i].1) { best[*i] = (p,v,loc) }
+          },
+          None => {
+            index.insert(id, best.len());
+            best.push((p,v,loc));
+          }
+        }
+      }
+      self.pending = Some(best.into_iter());
+    }
+    self.pending.as_mut().and_then(|pending| pending.next()).map(Ok)
+  }
+  fn next_result (&mut self) -> Option<Result<(P,V,Location),Error>> {
     while !self.queries.is_empty() {
       let len = self.queries.len();
       {
@@ -554,7 +1511,7 @@ S: RandomAccess<Error=Error>, P: Point, V: Value {
             let result = x.next();
             match &result {
               Some(Ok((_,_,loc))) => {
-                if iwrap![self.deletes.try_borrow()].contains(loc) {
+                if iwrap![is_deleted(&self.deletes, &self.data_store, loc)] {
                   self.index = (self.index+1) % len;
                   continue;
                 }
@@ -565,6 +1522,13 @@ S: RandomAccess<Error=Error>, P: Point, V: Value {
           },
           SubIterator::Staging(x) => x.next()
         };
+        if let (Some((partition,mask)), Some(Ok((_,v,_)))) =
+        (&self.partitions, &next) {
+          if iwrap![partition_bit(partition, v)] & mask == 0 {
+            self.index = (self.index+1) % len;
+            continue;
+          }
+        }
         match next {
           Some(result) => {
             self.index = (self.index+1) % len;
@@ -581,3 +1545,47 @@ S: RandomAccess<Error=Error>, P: Point, V: Value {
     None
   }
 }
+
+// whether a tree result was deleted by a staged delete or a delete bitmap
+fn is_deleted<S,P,V> (deletes: &Rc<RefCell<DeleteSet>>,
+data_store: &Option<Rc<RefCell<DataStore<S,P,V>>>>, loc: &Location)
+-> Result<bool,Error> where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  if deletes.try_borrow()?.contains(loc) { return Ok(true) }
+  Ok(match (data_store, loc) {
+    (Some(dstore), (block,index)) if *block > 0 => {
+      dstore.try_borrow()?.is_deleted(*block-1, *index)
+    },
+    _ => false
+  })
+}
+
+impl<'b,S,P,V> Iterator for QueryIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  type Item = Result<(P,V,Location),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    if self.limit == Some(0) { return None }
+    loop {
+      let result = self.next_latest();
+      if let (Some(seen), Some(Ok((p,v,_)))) = (&mut self.seen, &result) {
+        let mut key = iwrap![p.to_bytes()];
+        key.extend(iwrap![v.to_bytes()]);
+        if !seen.insert(key) { continue }
+      }
+      if let (Some((key,keys)), Some(Ok((_,v,_)))) =
+      (&mut self.seen_keys, &result) {
+        if !keys.insert(iwrap![(key)(v)]) { continue }
+      }
+      if let Some(Ok(_)) = &result {
+        if self.offset > 0 {
+          self.offset -= 1;
+          continue;
+        }
+        if let Some(limit) = &mut self.limit {
+          *limit -= 1;
+          if *limit == 0 { self.queries.clear() }
+        }
+      }
+      return result;
+    }
+  }
+}
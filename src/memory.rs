@@ -0,0 +1,80 @@
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+
+/// Store that keeps its bytes in memory.
+///
+/// Clones share the same bytes, so a store handed out by `memory_storage()`
+/// still holds its data after the database that opened it is dropped. This
+/// makes it easy to run many small databases in tests and simulations without
+/// touching the filesystem.
+#[derive(Clone,Debug,Default)]
+pub struct MemoryStore {
+  data: Rc<RefCell<Vec<u8>>>
+}
+
+impl MemoryStore {
+  pub fn new () -> Self {
+    Self::default()
+  }
+}
+
+impl RandomAccess for MemoryStore {
+  type Error = Error;
+  fn write (&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    let mut buf = self.data.try_borrow_mut()?;
+    let end = offset as usize + data.len();
+    if buf.len() < end {
+      buf.resize(end, 0);
+    }
+    buf[offset as usize..end].copy_from_slice(data);
+    Ok(())
+  }
+  fn read (&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    let buf = self.data.try_borrow()?;
+    let end = offset + length;
+    if end > buf.len() as u64 {
+      bail!["read {}..{} past the end of the store ({})",
+        offset, end, buf.len()];
+    }
+    Ok(buf[offset as usize..end as usize].to_vec())
+  }
+  fn read_to_writer (&mut self, offset: u64, length: u64,
+  buf: &mut impl Write) -> Result<(),Error> {
+    let data = self.read(offset, length)?;
+    buf.write_all(&data)?;
+    Ok(())
+  }
+  fn del (&mut self, _offset: u64, _length: u64) -> Result<(),Error> {
+    bail!["del is not supported on memory stores"]
+  }
+  fn truncate (&mut self, length: u64) -> Result<(),Error> {
+    self.data.try_borrow_mut()?.resize(length as usize, 0);
+    Ok(())
+  }
+  fn len (&self) -> Result<u64,Error> {
+    Ok(self.data.try_borrow()?.len() as u64)
+  }
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    Ok(self.data.try_borrow()?.is_empty())
+  }
+  fn sync_all (&mut self) -> Result<(),Error> {
+    Ok(())
+  }
+}
+
+/// Build a storage function for `Setup::new()` or `DB::open()` that keeps
+/// every store in memory.
+///
+/// Opening the same store name twice returns the same bytes, so a database
+/// can be dropped and reopened from clones of the storage function, which is
+/// how model and simulation tests check what survives a restart.
+pub fn memory_storage () -> impl Fn(&str) -> Result<MemoryStore,Error>+Clone {
+  let stores: Rc<RefCell<HashMap<String,MemoryStore>>> = Rc::default();
+  move |name: &str| {
+    Ok(stores.try_borrow_mut()?.entry(name.to_string()).or_default().clone())
+  }
+}
@@ -0,0 +1,115 @@
+use crate::planner::plan;
+use failure::{Error,bail};
+
+/// One step of a merge plan: build `tree` from the staged chunks listed in
+/// `staging` along with the records of `trees`, which are cleared afterward.
+///
+/// Staged chunk `j` holds `2^j * base_size` records. `tree` must be empty or
+/// listed in `trees`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Merge {
+  pub tree: usize,
+  pub staging: Vec<usize>,
+  pub trees: Vec<usize>
+}
+
+/// Strategy that decides which trees are merged when the staging area is
+/// written out, set with `Setup::merge_policy()`.
+///
+/// `staging[j]` is set for each staged chunk of `2^j * base_size` records that
+/// must be written and `trees[i]` is set for each tree that holds records.
+/// Every staged chunk must appear in exactly one `Merge` and no tree may be
+/// used by more than one.
+pub trait MergePolicy {
+  fn plan (&self, staging: &[bool], trees: &[bool]) -> Vec<Merge>;
+}
+
+/// Keep tree `i` at `2^i * base_size` records, merging trees like the carries
+/// of a binary counter. Each record is rewritten about `log2(n/base_size)`
+/// times and there are at most as many trees. This is the default.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct SizeTiered;
+
+impl MergePolicy for SizeTiered {
+  fn plan (&self, staging: &[bool], trees: &[bool]) -> Vec<Merge> {
+    plan(&staging.to_vec(), &trees.to_vec()).into_iter()
+      .map(|(tree,staging,trees)| Merge { tree, staging, trees })
+      .collect()
+  }
+}
+
+/// Merge the staging area and every tree into a single tree each time
+/// staging is written out. Queries only walk one tree, but every write
+/// rewrites the whole database.
+#[derive(Clone,Copy,Debug,Default)]
+pub struct Leveled;
+
+impl MergePolicy for Leveled {
+  fn plan (&self, staging: &[bool], trees: &[bool]) -> Vec<Merge> {
+    let chunks = set_bits(staging);
+    if chunks.is_empty() { return vec![] }
+    vec![Merge { tree: 0, staging: chunks, trees: set_bits(trees) }]
+  }
+}
+
+/// Write the staging area into a new tree without merging until there are
+/// `max_trees` trees, then merge everything into one tree. Records are
+/// rewritten once every `max_trees` writes at most, at the cost of queries
+/// walking up to `max_trees` trees.
+#[derive(Clone,Copy,Debug)]
+pub struct Lazy {
+  pub max_trees: usize
+}
+
+impl MergePolicy for Lazy {
+  fn plan (&self, staging: &[bool], trees: &[bool]) -> Vec<Merge> {
+    let chunks = set_bits(staging);
+    if chunks.is_empty() { return vec![] }
+    let full = set_bits(trees);
+    if full.len() >= self.max_trees.max(1) {
+      return vec![Merge { tree: 0, staging: chunks, trees: full }];
+    }
+    let tree = (0..).find(|i| !trees.get(*i).copied().unwrap_or(false))
+      .unwrap();
+    vec![Merge { tree, staging: chunks, trees: vec![] }]
+  }
+}
+
+fn set_bits (bits: &[bool]) -> Vec<usize> {
+  (0..bits.len()).filter(|i| bits[*i]).collect()
+}
+
+// Reject plans that drop staged chunks or overwrite trees.
+pub(crate) fn check_plan (merges: &[Merge], staging: &[bool], trees: &[bool])
+-> Result<(),Error> {
+  let mut chunks = vec![false;staging.len()];
+  let mut used = vec![false;trees.len()];
+  for m in merges.iter() {
+    for j in m.staging.iter() {
+      if !staging.get(*j).copied().unwrap_or(false) || chunks[*j] {
+        bail!["merge plan writes staged chunk {} more than once or \
+          without records", j];
+      }
+      chunks[*j] = true;
+    }
+    let mut targets = m.trees.clone();
+    targets.push(m.tree);
+    targets.sort_unstable();
+    targets.dedup();
+    for t in targets {
+      if used.len() <= t { used.resize(t+1, false) }
+      if used[t] {
+        bail!["merge plan uses tree {} more than once", t];
+      }
+      used[t] = true;
+    }
+    if trees.get(m.tree).copied().unwrap_or(false)
+    && !m.trees.contains(&m.tree) {
+      bail!["merge plan overwrites tree {}", m.tree];
+    }
+  }
+  if chunks != staging {
+    bail!["merge plan does not write every staged chunk"];
+  }
+  Ok(())
+}
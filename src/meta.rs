@@ -1,12 +1,71 @@
+use crate::quantize::Quantize;
 use failure::{Error,bail};
 //use std::mem::size_of;
 use random_access_storage::RandomAccess;
+use std::collections::BTreeMap;
+
+/// Format of databases written without a format version.
+pub const FORMAT_V1: u16 = 1;
+/// Data blocks may hold bit-packed rows, written with `Setup::pack_coords()`.
+pub const FORMAT_PACKED: u16 = 2;
+/// Each tree has a format tag, written after the format version.
+pub const FORMAT_TREES: u16 = 3;
+/// The schema of `Record` values, if any, is written after the tree formats.
+pub const FORMAT_SCHEMA: u16 = 4;
+/// The coordinate reference system, if any, is written after the schema.
+pub const FORMAT_CRS: u16 = 5;
+/// Staged deletes are written as LEB128 varints instead of a fixed 12 bytes.
+pub const FORMAT_VARINT_DELETES: u16 = 6;
+/// Data blocks may refer to values in the `blobs` store, written with
+/// `Setup::inline_threshold()`.
+pub const FORMAT_BLOBS: u16 = 7;
+/// Application key-value entries, set with `db.meta_put()`, are written after
+/// the coordinate reference system.
+pub const FORMAT_ENTRIES: u16 = 8;
+/// The quantization of each dimension, if any, is written after the
+/// application entries. Data blocks of databases with a quantization hold
+/// `i32` steps instead of coordinates, written with `Setup::quantize()`.
+pub const FORMAT_QUANTIZED: u16 = 9;
+/// Newest format this version of eyros can read and write. `db.migrate()`
+/// upgrades older databases to this format.
+pub const FORMAT_VERSION: u16 = FORMAT_QUANTIZED;
+
+/// Format of trees written before trees were tagged. Branch blocks may be
+/// followed by child bounds, detected from the length of each block.
+pub const TREE_V1: u16 = 1;
+/// Each branch block starts with the level its pivots split on as a u16,
+/// written by trees built with `Setup::adaptive_split()`.
+pub const TREE_ADAPTIVE: u16 = 2;
+/// Newest tree format this version of eyros can read and write. Trees are
+/// rebuilt in `TREE_ADAPTIVE` with `Setup::adaptive_split()` and in `TREE_V1`
+/// otherwise, so that older versions of eyros can still read them.
+pub const TREE_FORMAT: u16 = TREE_ADAPTIVE;
 
 #[derive(Debug)]
 pub struct Meta<S> where S: RandomAccess<Error=Error> {
-  store: S,
+  pub store: S,
   pub mask: Vec<bool>,
-  pub branch_factor: u16
+  pub branch_factor: u16,
+  // written after the mask as a u16 when greater than FORMAT_V1, so older
+  // versions reject the meta store
+  pub format: u16,
+  // format tag of each tree, written after the format as a u16 per mask entry
+  // from FORMAT_TREES on
+  pub trees: Vec<u16>,
+  // serialized `Schema`, written after the tree formats with a u32 length
+  // from FORMAT_SCHEMA on. empty when no schema was set
+  pub schema: Vec<u8>,
+  // name of the coordinate reference system, written after the schema with a
+  // u32 length from FORMAT_CRS on. empty when no crs was set
+  pub crs: String,
+  // application entries, written after the crs from FORMAT_ENTRIES on as a
+  // u32 count followed by the key and the value of each entry, both with a
+  // u32 length
+  pub entries: BTreeMap<String,Vec<u8>>,
+  // quantization of each dimension, written after the entries from
+  // FORMAT_QUANTIZED on as a u32 count followed by the f64 scale and offset
+  // of each dimension. empty when coordinates are stored at full precision
+  pub quantize: Vec<Quantize>
 }
 
 impl<S> Meta<S> where S: RandomAccess<Error=Error> {
@@ -14,7 +73,13 @@ impl<S> Meta<S> where S: RandomAccess<Error=Error> {
     let mut meta = Self {
       store,
       mask: vec![],
-      branch_factor: 9
+      branch_factor: 9,
+      format: FORMAT_V1,
+      trees: vec![],
+      schema: vec![],
+      crs: String::new(),
+      entries: BTreeMap::new(),
+      quantize: vec![]
     };
     if !meta.store.is_empty()? {
       let len = meta.store.len()?;
@@ -35,14 +100,116 @@ impl<S> Meta<S> where S: RandomAccess<Error=Error> {
       b
     }).collect();
     bytes.extend(&mbytes);
+    if self.format > FORMAT_V1 {
+      bytes.extend(&self.format.to_be_bytes());
+    }
+    if self.format >= FORMAT_TREES {
+      for i in 0..self.mask.len() {
+        let tag = self.trees.get(i).copied().unwrap_or(TREE_V1);
+        bytes.extend(&tag.to_be_bytes());
+      }
+    }
+    if self.format >= FORMAT_SCHEMA {
+      bytes.extend(&(self.schema.len() as u32).to_be_bytes());
+      bytes.extend(&self.schema);
+    }
+    if self.format >= FORMAT_CRS {
+      bytes.extend(&(self.crs.len() as u32).to_be_bytes());
+      bytes.extend(self.crs.as_bytes());
+    }
+    if self.format >= FORMAT_ENTRIES {
+      bytes.extend(&(self.entries.len() as u32).to_be_bytes());
+      for (key,value) in self.entries.iter() {
+        bytes.extend(&(key.len() as u32).to_be_bytes());
+        bytes.extend(key.as_bytes());
+        bytes.extend(&(value.len() as u32).to_be_bytes());
+        bytes.extend(value);
+      }
+    }
+    if self.format >= FORMAT_QUANTIZED {
+      bytes.extend(&(self.quantize.len() as u32).to_be_bytes());
+      for q in self.quantize.iter() {
+        bytes.extend(&q.scale.to_be_bytes());
+        bytes.extend(&q.offset.to_be_bytes());
+      }
+    }
     self.store.write(0, &bytes)?;
+    // entries can be replaced with shorter ones
+    if self.store.len()? > bytes.len() as u64 {
+      self.store.truncate(bytes.len() as u64)?;
+    }
+    Ok(())
+  }
+  // upgrade a new database to the newest format for a Setup option that is
+  // stored in meta from `format` on
+  pub fn require_format (&mut self, format: u16, option: &str)
+  -> Result<(),Error> {
+    if self.format >= format { return Ok(()) }
+    if !self.store.is_empty()? {
+      bail!["{} requires format version {}, \
+        upgrade the database with db.migrate() first", option, format];
+    }
+    self.format = FORMAT_VERSION;
     Ok(())
   }
   fn load_buffer(&mut self, buf: &Vec<u8>) -> Result<(),Error> {
     self.branch_factor = u16::from_be_bytes([buf[0],buf[1]]);
     self.mask.clear();
     let len = u32::from_be_bytes([buf[2],buf[3],buf[4],buf[5]]) as usize;
-    if (len+7)/8+6 != buf.len() {
+    let end = (len+7)/8+6;
+    self.trees.clear();
+    self.schema.clear();
+    self.crs.clear();
+    self.entries.clear();
+    self.quantize.clear();
+    if end == buf.len() {
+      self.format = FORMAT_V1;
+    } else if end+2 <= buf.len() {
+      self.format = u16::from_be_bytes([buf[end],buf[end+1]]);
+      if self.format > FORMAT_VERSION {
+        bail!["database format version {} is newer than the newest version \
+          supported by this version of eyros ({}), upgrade eyros to open it",
+          self.format, FORMAT_VERSION];
+      }
+      if self.format <= FORMAT_V1 {
+        bail!["unexpected database format version {}", self.format];
+      }
+      let tags = if self.format >= FORMAT_TREES { len } else { 0 };
+      let mut k = end+2+tags*2;
+      if self.format >= FORMAT_SCHEMA {
+        self.schema = section(buf, &mut k)?.to_vec();
+      }
+      if self.format >= FORMAT_CRS {
+        self.crs = String::from_utf8(section(buf, &mut k)?.to_vec())?;
+      }
+      if self.format >= FORMAT_ENTRIES {
+        if k+4 > buf.len() {
+          bail!("unexpected buffer length");
+        }
+        let n = u32::from_be_bytes([buf[k],buf[k+1],buf[k+2],buf[k+3]]);
+        k += 4;
+        for _ in 0..n {
+          let key = String::from_utf8(section(buf, &mut k)?.to_vec())?;
+          let value = section(buf, &mut k)?.to_vec();
+          self.entries.insert(key, value);
+        }
+      }
+      if self.format >= FORMAT_QUANTIZED {
+        let n = u32::from_be_bytes(array(buf, &mut k)?);
+        for _ in 0..n {
+          let scale = f64::from_be_bytes(array(buf, &mut k)?);
+          let offset = f64::from_be_bytes(array(buf, &mut k)?);
+          self.quantize.push(Quantize { scale, offset });
+        }
+      }
+      if k != buf.len() {
+        bail!("unexpected buffer length");
+      }
+      for i in 0..tags {
+        let k = end+2+i*2;
+        self.trees.push(u16::from_be_bytes([buf[k],buf[k+1]]));
+      }
+    } else {
       bail!("unexpected buffer length");
     }
     for i in 0..(len+7)/8 {
@@ -58,3 +225,28 @@ impl<S> Meta<S> where S: RandomAccess<Error=Error> {
     Ok(())
   }
 }
+
+// read the bytes at `*k` that follow a u32 length and move `k` past them
+fn section<'a> (buf: &'a [u8], k: &mut usize) -> Result<&'a [u8],Error> {
+  if *k+4 > buf.len() {
+    bail!("unexpected buffer length");
+  }
+  let n = u32::from_be_bytes([buf[*k],buf[*k+1],buf[*k+2],buf[*k+3]]) as usize;
+  if *k+4+n > buf.len() {
+    bail!("unexpected buffer length");
+  }
+  let bytes = &buf[*k+4..*k+4+n];
+  *k += 4+n;
+  Ok(bytes)
+}
+
+// read the N bytes at `*k` and move `k` past them
+fn array<const N: usize> (buf: &[u8], k: &mut usize) -> Result<[u8;N],Error> {
+  if *k+N > buf.len() {
+    bail!("unexpected buffer length");
+  }
+  let mut bytes = [0u8;N];
+  bytes.copy_from_slice(&buf[*k..*k+N]);
+  *k += N;
+  Ok(bytes)
+}
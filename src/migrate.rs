@@ -0,0 +1,101 @@
+use crate::{DB,Point,Value};
+use crate::meta::{FORMAT_V1,FORMAT_PACKED,FORMAT_TREES,FORMAT_SCHEMA,
+  FORMAT_CRS,FORMAT_VARINT_DELETES,FORMAT_BLOBS,FORMAT_ENTRIES,FORMAT_QUANTIZED,
+  FORMAT_VERSION};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// On-disk format version of the database, stored in the `meta` store.
+  ///
+  /// Databases are opened in the format they were written with as long as it
+  /// is not newer than `eyros::FORMAT_VERSION`. Opening a newer database fails
+  /// with an error instead of misreading its stores.
+  pub fn format (&self) -> u16 {
+    self.meta.format
+  }
+
+  /// Format tag of each tree, in the same order as `db.trees`.
+  ///
+  /// Trees keep the format they were written in until a merge rebuilds them,
+  /// in `eyros::TREE_ADAPTIVE` with `Setup::adaptive_split()` and in
+  /// `eyros::TREE_V1` otherwise, so a database may hold trees of several
+  /// formats.
+  /// Trees written before the database was migrated to format version 3 are
+  /// tagged `1`.
+  pub fn tree_formats (&self) -> Result<Vec<u16>,Error> {
+    let mut formats = Vec::with_capacity(self.trees.len());
+    for tree in self.trees.iter() {
+      formats.push(tree.try_borrow()?.format);
+    }
+    Ok(formats)
+  }
+
+  /// Upgrade the database to `eyros::FORMAT_VERSION`, one format version at a
+  /// time, and return the format version it was upgraded from. Databases that
+  /// are already current are left alone.
+  ///
+  /// Once migrated, earlier versions of eyros refuse to open the database.
+  /// Each step only changes what is written from then on; data written in an
+  /// older format remains readable:
+  ///
+  /// * version 1 to 2: new data blocks are bit-packed, as with
+  ///   `Setup::pack_coords()`.
+  /// * version 2 to 3: the format of each tree is recorded, see
+  ///   `db.tree_formats()`.
+  /// * version 3 to 4: a schema for `Record` values can be stored, see
+  ///   `Setup::schema()`.
+  /// * version 4 to 5: a coordinate reference system can be stored, see
+  ///   `Setup::crs()`.
+  /// * version 5 to 6: staged deletes are written as varints, see
+  ///   `Setup::varint_deletes()`. The deletes already staged are rewritten.
+  /// * version 6 to 7: large values can be kept in the `blobs` store, see
+  ///   `Setup::inline_threshold()`.
+  /// * version 7 to 8: application entries can be stored in the `meta`
+  ///   store, see `db.meta_put()`.
+  /// * version 8 to 9: a quantization of the coordinates can be stored, see
+  ///   `Setup::quantize()`. Only new databases are quantized, so migrated
+  ///   databases keep their full precision.
+  pub fn migrate (&mut self) -> Result<u16,Error> {
+    let from = self.meta.format;
+    while self.meta.format < FORMAT_VERSION {
+      match self.meta.format {
+        FORMAT_V1 => {
+          self.data_store.try_borrow_mut()?.pack_coords = true;
+          self.meta.format = FORMAT_PACKED;
+        },
+        FORMAT_PACKED => {
+          self.meta.format = FORMAT_TREES;
+        },
+        FORMAT_TREES => {
+          self.meta.format = FORMAT_SCHEMA;
+        },
+        FORMAT_SCHEMA => {
+          self.meta.format = FORMAT_CRS;
+        },
+        FORMAT_CRS => {
+          self.staging.migrate_deletes()?;
+          self.meta.format = FORMAT_VARINT_DELETES;
+        },
+        FORMAT_VARINT_DELETES => {
+          self.meta.format = FORMAT_BLOBS;
+        },
+        FORMAT_BLOBS => {
+          self.meta.format = FORMAT_ENTRIES;
+        },
+        FORMAT_ENTRIES => {
+          self.meta.format = FORMAT_QUANTIZED;
+        },
+        format => bail!["no migration from database format version {}", format]
+      }
+      // the meta store and possibly the staged deletes were rewritten
+      self.invalidate();
+      self.save_meta()?;
+      self.meta.store.sync_all()?;
+    }
+    Ok(from)
+  }
+}
@@ -1,6 +1,5 @@
-use crate::{Point,Cursor,Block,order,order_len};
+use crate::{Point,PointOrd,PointCodec,BranchQuery,Scalar};
 use failure::{Error,bail};
-use std::mem::size_of;
 
 use std::cmp::{Ordering,PartialOrd};
 use std::ops::{Add,Div};
@@ -65,6 +64,24 @@ pub enum Mix<T> {
   Interval(T,T)
 }
 
+fn lower<T> (x: &Mix<T>) -> &T {
+  match x {
+    Mix::Scalar(x) => x,
+    Mix::Interval(x,_) => x
+  }
+}
+
+fn upper<T> (x: &Mix<T>) -> &T {
+  match x {
+    Mix::Scalar(x) => x,
+    Mix::Interval(_,x) => x
+  }
+}
+
+fn comparable<T> (x: &T) -> bool where T: PartialOrd {
+  x.partial_cmp(x).is_some()
+}
+
 macro_rules! impl_mix {
   ($M:ident,$dim:expr,($($T:tt),+),($($v:tt),+),($($i:tt),+)) => {
     #[derive(Copy,Clone,Debug,Eq,PartialEq)]
@@ -161,16 +178,16 @@ macro_rules! impl_mix {
       }
     }
 
-    impl<$($T),+> Point for $M<$($T),+> where ($(($T,$T)),+): Point,
-    $($T: ToBytes+FromBytes+CountBytes+Copy+Debug+PartialOrd
+    impl<$($T),+> PointOrd for $M<$($T),+> where ($(($T,$T)),+): Point,
+    $($T: ToBytes+FromBytes+CountBytes+Copy+Debug+PartialOrd+Scalar
     +Add<Output=$T>+Div<Output=$T>+From<u8>),+ {
       type Bounds = (($($T),+),($($T),+));
       type Range = ($(($T,$T)),+);
 
       fn cmp_at (&self, other: &Self, level: usize) -> Ordering where Self: Sized {
-        let order = match level % Self::dim() {
+        match level % Self::dim() {
           $($i => {
-            match (self.$v, other.$v) {
+            let order = match (self.$v, other.$v) {
               (Mix::Scalar(a),Mix::Scalar(b)) => a.partial_cmp(&b),
               (Mix::Interval(a0,a1),Mix::Scalar(b)) => {
                 if b >= a0 && b <= a1 {
@@ -193,11 +210,13 @@ macro_rules! impl_mix {
                   a0.partial_cmp(&b0)
                 }
               },
-            }
+            };
+            order.unwrap_or_else(|| {
+              lower(&self.$v).total_order(lower(&other.$v))
+            })
           },)+
-          _ => panic!["match case beyond dimension"]
-        };
-        match order { Some(x) => x, None => Ordering::Less }
+          _ => Ordering::Equal
+        }
       }
 
       fn midpoint_upper (&self, other: &Self) -> Self where Self: Sized {
@@ -210,16 +229,6 @@ macro_rules! impl_mix {
         Self { $($v),+ }
       }
 
-      fn serialize_at (&self, level: usize, dst: &mut [u8]) -> Result<usize,Error> {
-        match level % Self::dim() {
-          $($i => match self.$v {
-            Mix::Scalar(x) => x.write_bytes(dst),
-            Mix::Interval(_,x) => x.write_bytes(dst),
-          }),+
-          _ => panic!["match case beyond dimension"]
-        }
-      }
-
       fn dim () -> usize { $dim }
 
       fn overlaps (&self, bbox: &Self::Bounds) -> bool {
@@ -229,118 +238,30 @@ macro_rules! impl_mix {
         }))+
       }
 
-      fn query_branch (buf: &[u8], bbox: &Self::Bounds, bf: usize, level: usize)
-      -> Result<(Vec<Cursor>,Vec<Block>),Error> {
-        let mut cursors = vec![];
-        let mut blocks = vec![];
-        let n = order_len(bf);
-        let dim = level % Self::dim();
-        let mut pivots: ($(Vec<$T>),+) = ($({ let v: Vec<$T> = vec![]; v }),+);
-        let mut offset = 0;
-        for _i in 0..n {
-          match dim {
-            $($i => {
-              let (size,pivot) = $T::from_bytes(&buf[offset..])?;
-              pivots.$i.push(pivot);
-              offset += size;
-            },)+
-            _ => panic!["dimension not expected"]
-          }
-        }
-        let d_start = offset; // data bitfield
-        let i_start = d_start + (n+bf+7)/8; // intersections
-        let b_start = i_start + n*size_of::<u64>(); // buckets
-
-        let mut bcursors = vec![0];
-        let mut bitfield: Vec<bool> = vec![false;bf]; // which buckets
-        while !bcursors.is_empty() {
-          let c = bcursors.pop().unwrap();
-          let i = order(bf, c);
-          let cmp = match dim {
-            $($i => {
-              let pivot = pivots.$i[i];
-              ((bbox.0).$i <= pivot, pivot <= (bbox.1).$i)
-            },)+
-            _ => panic!["dimension not expected"]
-          };
-          let is_data = ((buf[d_start+i/8]>>(i%8))&1) == 1;
-          let i_offset = i_start + i*8;
-          // intersection:
-          let offset = u64::from_be_bytes([
-            buf[i_offset+0], buf[i_offset+1],
-            buf[i_offset+2], buf[i_offset+3],
-            buf[i_offset+4], buf[i_offset+5],
-            buf[i_offset+6], buf[i_offset+7],
-          ]);
-          if is_data && offset > 0 {
-            blocks.push(offset-1);
-          } else if offset > 0 {
-            cursors.push((offset-1,level+1));
-          }
-          // internal branches:
-          if cmp.0 && c*2+1 < n { // left internal
-            bcursors.push(c*2+1);
-          } else if cmp.0 { // left branch
-            bitfield[i/2] = true;
-          }
-          if cmp.1 && c*2+2 < n { // right internal
-            bcursors.push(c*2+2);
-          } else if cmp.1 { // right branch
-            bitfield[i/2+1] = true;
-          }
-        }
-        for (i,b) in bitfield.iter().enumerate() {
-          if !b { continue }
-          let j = i+n;
-          let is_data = (buf[d_start+j/8]>>(j%8))&1 == 1;
-          let offset = u64::from_be_bytes([
-            buf[b_start+i*8+0], buf[b_start+i*8+1],
-            buf[b_start+i*8+2], buf[b_start+i*8+3],
-            buf[b_start+i*8+4], buf[b_start+i*8+5],
-            buf[b_start+i*8+6], buf[b_start+i*8+7]
-          ]);
-          if offset > 0 && is_data {
-            blocks.push(offset-1);
-          } else if offset > 0 {
-            cursors.push((offset-1,level+1));
-          }
-        }
-        Ok((cursors,blocks))
+      fn is_finite (&self) -> bool {
+        $(lower(&self.$v).finite() && upper(&self.$v).finite() &&)+ true
       }
 
-      fn pivot_bytes_at (&self, level: usize) -> usize {
-        match level % Self::dim() {
-          $($i => match self.$v {
-            Mix::Scalar(x) => x.count_bytes(),
-            Mix::Interval(_,x) => x.count_bytes(),
-          },)+
-          _ => panic!["dimension not expected"]
-        }
+      fn is_ordered (&self) -> bool {
+        $(lower(&self.$v).partial_cmp(upper(&self.$v))
+          != Some(Ordering::Greater) &&)+ true
       }
 
-      fn count_bytes_at (buf: &[u8], level: usize) -> Result<usize,Error> {
-        Ok(match level % Self::dim() {
-          $($i => $T::count_from_bytes(buf)?,)+
-          _ => panic!["dimension not expected"]
-        })
+      fn normalized (&self) -> Self {
+        $(let $v = match self.$v {
+          Mix::Interval(a,b) if a > b => Mix::Interval(b,a),
+          x => x
+        };)+
+        Self { $($v),+ }
       }
 
       fn bounds (points: &Vec<Self>) -> Option<Self::Bounds> {
-        if points.is_empty() { return None }
-        fn lower<T> (x: &Mix<T>) -> &T {
-          match x {
-            Mix::Scalar(x) => x,
-            Mix::Interval(x,_) => x
-          }
-        }
-        fn upper<T> (x: &Mix<T>) -> &T {
-          match x {
-            Mix::Scalar(x) => x,
-            Mix::Interval(_,x) => x
-          }
-        }
-        let mut iter = points.iter();
-        let first = iter.next()?;
+        // points with coordinates that do not compare to themselves (NaN) are
+        // left out of the bounds unless there is nothing else
+        let mut iter = points.iter().filter(|m| {
+          $(comparable(lower(&m.$v)) && comparable(upper(&m.$v)) &&)+ true
+        });
+        let first = iter.next().or(points.first())?;
         let mut bbox = (
           ($(*lower(&first.$v)),+),
           ($(*upper(&first.$v)),+),
@@ -364,11 +285,79 @@ macro_rules! impl_mix {
         ($(((bbox.0).$i,(bbox.1).$i)),+)
       }
 
-      fn format_at (_buf: &[u8], _level: usize)
+      fn spread_at (bbox: &Self::Bounds, level: usize) -> Option<f64> {
+        match level % Self::dim() {
+          $($i => Some((bbox.1).$i.as_f64()? - (bbox.0).$i.as_f64()?),)+
+          _ => None
+        }
+      }
+
+      fn bounds_within (inner: &Self::Bounds, outer: &Self::Bounds) -> bool {
+        $((outer.0).$i <= (inner.0).$i && (inner.1).$i <= (outer.1).$i &&)+
+        true
+      }
+
+      fn bounds_overlap (a: &Self::Bounds, b: &Self::Bounds) -> bool {
+        $((a.0).$i <= (b.1).$i && (b.0).$i <= (a.1).$i &&)+
+        true
+      }
+    }
+
+    impl<$($T),+> PointCodec for $M<$($T),+> where ($(($T,$T)),+): Point,
+    $($T: ToBytes+FromBytes+CountBytes+Copy+Debug+PartialOrd+Scalar
+    +Add<Output=$T>+Div<Output=$T>+From<u8>),+ {
+      fn serialize_at (&self, level: usize, dst: &mut [u8]) -> Result<usize,Error> {
+        match level % Self::dim() {
+          $($i => match self.$v {
+            Mix::Scalar(x) => x.write_bytes(dst),
+            Mix::Interval(_,x) => x.write_bytes(dst),
+          }),+
+          _ => bail!["dimension {} out of bounds", level % Self::dim()]
+        }
+      }
+
+      fn pivot_bytes_at (&self, level: usize) -> usize {
+        [$(match self.$v {
+          Mix::Scalar(x) => x.count_bytes(),
+          Mix::Interval(_,x) => x.count_bytes(),
+        }),+][level % Self::dim()]
+      }
+
+      fn count_bytes_at (buf: &[u8], level: usize) -> Result<usize,Error> {
+        Ok(match level % Self::dim() {
+          $($i => $T::count_from_bytes(buf)?,)+
+          _ => bail!["dimension {} out of bounds", level % Self::dim()]
+        })
+      }
+
+      fn pivot_cmp_at (buf: &[u8], bbox: &Self::Bounds, level: usize)
+      -> Result<(bool,bool),Error> {
+        Ok(match level % Self::dim() {
+          $($i => {
+            let (_,pivot) = $T::from_bytes(buf)?;
+            // NaN pivots from stored NaN coordinates prune neither side
+            let nan = !comparable(&pivot);
+            ((bbox.0).$i <= pivot || nan, pivot <= (bbox.1).$i || nan)
+          },)+
+          _ => bail!["dimension {} out of bounds", level % Self::dim()]
+        })
+      }
+
+      fn format_at (buf: &[u8], level: usize)
       -> Result<String,Error> {
-        unimplemented![]
+        Ok(match level % Self::dim() {
+          $($i => {
+            let (_,pivot) = $T::from_bytes(buf)?;
+            format!["{:?}", pivot]
+          },)+
+          _ => bail!["dimension {} out of bounds", level % Self::dim()]
+        })
       }
     }
+
+    impl<$($T),+> BranchQuery for $M<$($T),+> where ($(($T,$T)),+): Point,
+    $($T: ToBytes+FromBytes+CountBytes+Copy+Debug+PartialOrd+Scalar
+    +Add<Output=$T>+Div<Output=$T>+From<u8>),+ {}
   }
 }
 
@@ -0,0 +1,128 @@
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use memmap2::Mmap;
+use std::fs::{self,File,OpenOptions};
+use std::io::{Read,Seek,SeekFrom,Write};
+use std::path::{Path,PathBuf};
+
+/// File store that serves reads from a read-only memory map.
+///
+/// Reads copy straight out of the mapping, so they cost no syscalls and
+/// repeated reads of the same blocks are cached by the OS page cache instead
+/// of the process. Writes go through the file and invalidate the mapping,
+/// which is recreated on the next read, so this store suits read-mostly
+/// deployments: a database that is built once and then queried.
+///
+/// If a file cannot be mapped (for example on platforms or filesystems
+/// without mmap support), reads fall back to ordinary file reads.
+pub struct MmapStore {
+  file: File,
+  map: Option<Mmap>,
+  stale: bool,
+  mappable: bool,
+  length: u64
+}
+
+impl MmapStore {
+  /// Open or create the file at `path`, creating parent directories as
+  /// needed.
+  pub fn open<P> (path: P) -> Result<Self,Error> where P: AsRef<Path> {
+    if let Some(dir) = path.as_ref().parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+      .read(true).write(true).create(true)
+      .open(path.as_ref())?;
+    let length = file.metadata()?.len();
+    Ok(Self { file, map: None, stale: true, mappable: true, length })
+  }
+
+  // map the file if it changed since the last mapping, returning whether
+  // reads can use the mapping
+  fn remap (&mut self) -> bool {
+    if !self.mappable { return false }
+    if self.stale {
+      self.map = None;
+      if self.length > 0 {
+        // safety: the mapping is dropped before this store writes to or
+        // truncates the file. Other processes must not modify the file.
+        match unsafe { Mmap::map(&self.file) } {
+          Ok(map) => self.map = Some(map),
+          Err(_) => {
+            self.mappable = false;
+            return false;
+          }
+        }
+      }
+      self.stale = false;
+    }
+    true
+  }
+
+  fn invalidate (&mut self) {
+    self.map = None;
+    self.stale = true;
+  }
+}
+
+impl RandomAccess for MmapStore {
+  type Error = Error;
+  fn write (&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    if data.is_empty() { return Ok(()) }
+    self.invalidate();
+    self.file.seek(SeekFrom::Start(offset))?;
+    self.file.write_all(data)?;
+    self.length = self.length.max(offset + data.len() as u64);
+    Ok(())
+  }
+  fn read (&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    let end = offset + length;
+    if end > self.length {
+      bail!["read {}..{} past the end of the file ({})",
+        offset, end, self.length];
+    }
+    if length == 0 { return Ok(vec![]) }
+    if self.remap() {
+      if let Some(map) = &self.map {
+        return Ok(map[offset as usize..end as usize].to_vec());
+      }
+    }
+    let mut buf = vec![0u8;length as usize];
+    self.file.seek(SeekFrom::Start(offset))?;
+    self.file.read_exact(&mut buf)?;
+    Ok(buf)
+  }
+  fn read_to_writer (&mut self, offset: u64, length: u64,
+  buf: &mut impl Write) -> Result<(),Error> {
+    let data = self.read(offset, length)?;
+    buf.write_all(&data)?;
+    Ok(())
+  }
+  fn del (&mut self, _offset: u64, _length: u64) -> Result<(),Error> {
+    bail!["del is not supported on mmap stores"]
+  }
+  fn truncate (&mut self, length: u64) -> Result<(),Error> {
+    self.invalidate();
+    self.file.set_len(length)?;
+    self.length = length;
+    Ok(())
+  }
+  fn len (&self) -> Result<u64,Error> {
+    Ok(self.length)
+  }
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    Ok(self.length == 0)
+  }
+  fn sync_all (&mut self) -> Result<(),Error> {
+    self.file.sync_all()?;
+    Ok(())
+  }
+}
+
+/// Build a storage function for `Setup::new()` or `DB::open()` that opens
+/// each store as an `MmapStore` in the directory `dir`.
+pub fn mmap_storage<P> (dir: P) -> impl Fn(&str) -> Result<MmapStore,Error>
+where P: AsRef<Path> {
+  let dir = PathBuf::from(dir.as_ref());
+  move |name: &str| MmapStore::open(dir.join(name))
+}
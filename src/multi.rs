@@ -0,0 +1,38 @@
+use crate::{DB,Point,Value,Location};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query the database for all records that intersect any of `bboxes`.
+  ///
+  /// Each tree is traversed once for the whole set of bounding boxes, so
+  /// branches near the root are read once instead of once per box. Every
+  /// result is tagged with the index into `bboxes` of the box it matched, and
+  /// a record that matches several boxes is returned once for each box.
+  ///
+  /// Results are collected into a `Vec` instead of being returned as a lazy
+  /// iterator.
+  pub fn query_multi (&mut self, bboxes: &[P::Bounds])
+  -> Result<Vec<(usize,P,V,Location)>,Error> {
+    let mut results = vec![];
+    if bboxes.is_empty() { return Ok(results) }
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    self.staging.for_each(|p,v,loc| {
+      for (i,bbox) in bboxes.iter().enumerate() {
+        if p.overlaps(bbox) {
+          results.push((i,*p,v.clone(),loc));
+        }
+      }
+      Ok(())
+    })?;
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      results.extend(tree.query_multi(bboxes, &deletes)?);
+    }
+    Ok(results)
+  }
+}
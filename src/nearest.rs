@@ -0,0 +1,156 @@
+use crate::{DB,Point,Value,Location};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+
+/// Scalar or interval coordinate in one dimension of a `Nearest` or
+/// `SegmentQuery` point. Implemented for `f32` and `f64` scalars and
+/// intervals.
+pub trait NearestCoord: Copy {
+  type Scalar: Copy;
+  /// Distance from `x` to the coordinate, or `0.0` if `x` is inside of an
+  /// interval.
+  fn gap (&self, x: Self::Scalar) -> f64;
+  /// `x` moved by `d`.
+  fn shift (x: Self::Scalar, d: f64) -> Self::Scalar;
+  /// Lower and upper bound of the coordinate. Both are the same for scalars.
+  fn span (&self) -> (f64,f64);
+  /// Scalar closest to `x`.
+  fn scalar (x: f64) -> Self::Scalar;
+  /// `x` as an `f64`.
+  fn value (x: Self::Scalar) -> f64;
+}
+
+macro_rules! impl_nearest_coord {
+  ($($T:ty),+) => {
+    $(
+      impl NearestCoord for $T {
+        type Scalar = $T;
+        fn gap (&self, x: $T) -> f64 {
+          (*self as f64 - x as f64).abs()
+        }
+        fn shift (x: $T, d: f64) -> $T {
+          (x as f64 + d) as $T
+        }
+        fn span (&self) -> (f64,f64) {
+          (*self as f64, *self as f64)
+        }
+        fn scalar (x: f64) -> $T {
+          x as $T
+        }
+        fn value (x: $T) -> f64 {
+          x as f64
+        }
+      }
+      impl NearestCoord for ($T,$T) {
+        type Scalar = $T;
+        fn gap (&self, x: $T) -> f64 {
+          if x < self.0 { self.0 as f64 - x as f64 }
+          else if x > self.1 { x as f64 - self.1 as f64 }
+          else { 0.0 }
+        }
+        fn shift (x: $T, d: f64) -> $T {
+          (x as f64 + d) as $T
+        }
+        fn span (&self) -> (f64,f64) {
+          (self.0 as f64, self.1 as f64)
+        }
+        fn scalar (x: f64) -> $T {
+          x as $T
+        }
+        fn value (x: $T) -> f64 {
+          x as f64
+        }
+      }
+    )+
+  }
+}
+
+impl_nearest_coord![f32,f64];
+
+/// Point with a euclidean distance to a position, for `db.nearest_within()`.
+/// Implemented for two and three dimensional points whose coordinates are
+/// `f32` or `f64` scalars or intervals.
+pub trait Nearest: Point {
+  /// Position to measure from, with a scalar for each dimension.
+  type Position: Copy;
+  /// Distance from `position` to the closest part of the point.
+  fn distance (&self, position: &Self::Position) -> f64;
+  /// Bounds of the box with half-width `radius` around `position`.
+  fn around (position: &Self::Position, radius: f64) -> Self::Bounds;
+}
+
+impl<A,B> Nearest for (A,B) where
+A: NearestCoord, B: NearestCoord,
+(A,B): Point<Bounds=((A::Scalar,B::Scalar),(A::Scalar,B::Scalar))> {
+  type Position = (A::Scalar,B::Scalar);
+  fn distance (&self, p: &Self::Position) -> f64 {
+    self.0.gap(p.0).hypot(self.1.gap(p.1))
+  }
+  fn around (p: &Self::Position, r: f64) -> Self::Bounds {
+    ((A::shift(p.0,-r),B::shift(p.1,-r)),(A::shift(p.0,r),B::shift(p.1,r)))
+  }
+}
+
+impl<A,B,C> Nearest for (A,B,C) where
+A: NearestCoord, B: NearestCoord, C: NearestCoord,
+(A,B,C): Point<Bounds=(
+  (A::Scalar,B::Scalar,C::Scalar),
+  (A::Scalar,B::Scalar,C::Scalar)
+)> {
+  type Position = (A::Scalar,B::Scalar,C::Scalar);
+  fn distance (&self, p: &Self::Position) -> f64 {
+    let (x,y,z) = (self.0.gap(p.0), self.1.gap(p.1), self.2.gap(p.2));
+    (x*x + y*y + z*z).sqrt()
+  }
+  fn around (p: &Self::Position, r: f64) -> Self::Bounds {
+    (
+      (A::shift(p.0,-r),B::shift(p.1,-r),C::shift(p.2,-r)),
+      (A::shift(p.0,r),B::shift(p.1,r),C::shift(p.2,r))
+    )
+  }
+}
+
+// the first search covers this fraction of the radius and each following
+// search grows by NEAREST_GROWTH until the whole radius is covered
+const NEAREST_START: f64 = 1.0/64.0;
+const NEAREST_GROWTH: f64 = 4.0;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Nearest, V: Value {
+  /// Return the record closest to `position` within a distance of
+  /// `max_dist`, or `None` if there are no records that close, such as to
+  /// snap a GPS position to the nearest road. Records that are intervals are
+  /// measured to their closest edge.
+  ///
+  /// The search starts with a small box around `position` and only grows it
+  /// while no record has been found within the box, so dense areas are
+  /// answered without reading the blocks for the whole radius. A record found
+  /// in a box is the closest one if its distance fits in the box, because
+  /// every closer record is in the box too.
+  pub fn nearest_within (&mut self, position: &P::Position, max_dist: f64)
+  -> Result<Option<(P,V,Location)>,Error> {
+    if !max_dist.is_finite() || max_dist < 0.0 {
+      bail!["nearest_within() expects a finite max_dist of 0 or more"];
+    }
+    let mut radius = max_dist * NEAREST_START;
+    loop {
+      let bbox = P::around(position, radius);
+      let mut best: Option<((P,V,Location),f64)> = None;
+      for result in self.query(&bbox)? {
+        let (p,v,loc) = result?;
+        let d = p.distance(position);
+        let closer = match &best {
+          Some((_,b)) => d < *b,
+          None => d <= radius
+        };
+        if closer { best = Some(((p,v,loc),d)) }
+      }
+      if best.is_some() || radius >= max_dist {
+        return Ok(best.map(|(r,_)| r));
+      }
+      radius = (radius * NEAREST_GROWTH).min(max_dist);
+    }
+  }
+}
@@ -17,3 +17,55 @@ pub fn order (bf: usize, i: usize) -> usize {
 }
 
 pub fn order_len (bf: usize) -> usize { bf*2-3 }
+
+/// Branch factor to use for a tree `level` given one branch factor for each
+/// dimension (or a single branch factor for every dimension).
+pub fn branch_factor_at (bfs: &[usize], level: usize) -> usize {
+  bfs[level % bfs.len()]
+}
+
+/// Branch factor and split dimension for each depth of a tree, from the
+/// branch factors, the cycle of dimensions and the adaptive split option set
+/// with `Setup`.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Levels {
+  branch_factors: Vec<usize>,
+  // empty for round-robin through every dimension
+  cycle: Vec<usize>,
+  adaptive: bool
+}
+
+impl Levels {
+  pub fn new (branch_factors: Vec<usize>, cycle: Vec<usize>, adaptive: bool)
+  -> Self {
+    Self { branch_factors, cycle, adaptive }
+  }
+  /// Level to pass to the `Point` methods for branches at tree `depth`. The
+  /// methods split on dimension `level % P::dim()`. Branches of trees built
+  /// with adaptive splits record their own level instead.
+  pub fn level (&self, depth: usize) -> usize {
+    if self.cycle.is_empty() {
+      depth
+    } else {
+      self.cycle[depth % self.cycle.len()]
+    }
+  }
+  /// Branch factor of the branches that split on `level`, which is the
+  /// branch factor of its dimension.
+  pub fn branch_factor (&self, level: usize) -> usize {
+    branch_factor_at(&self.branch_factors, level)
+  }
+  /// Whether each branch picks the dimension with the greatest spread.
+  pub fn adaptive (&self) -> bool {
+    self.adaptive
+  }
+  /// Levels that a branch may split on for a point type with `dim`
+  /// dimensions: the dimensions of the cycle, or every dimension.
+  pub fn dims (&self, dim: usize) -> Vec<usize> {
+    if self.cycle.is_empty() { return (0..dim).collect() }
+    let mut dims = self.cycle.clone();
+    dims.sort_unstable();
+    dims.dedup();
+    dims
+  }
+}
@@ -0,0 +1,296 @@
+//! Load OpenStreetMap nodes and ways from an `.osm.pbf` extract. Requires the
+//! `osm` feature.
+//!
+//! Nodes are stored as points with zero-width intervals and ways with the
+//! bounds of their nodes. Each record keeps the id, the tags and, for ways,
+//! the ids of the nodes of the way, so features can be rebuilt from query
+//! results:
+//!
+//! ```rust,no_run
+//! use eyros::DB;
+//! use eyros::osm::{OsmImport,OsmPoint,OsmFeature};
+//! # use failure::Error;
+//! # use random_access_disk::RandomAccessDisk;
+//! # use std::path::PathBuf;
+//!
+//! # fn main () -> Result<(),Error> {
+//! let mut db: DB<_,_,OsmPoint,OsmFeature> = DB::open(storage)?;
+//! let report = OsmImport::new().load_path(&mut db, "/tmp/extract.osm.pbf")?;
+//! println!["{} nodes, {} ways", report.nodes, report.ways];
+//! for result in db.query(&((-147.9,64.8),(-147.6,64.9)))? {
+//!   let (_,feature,_) = result?;
+//!   println!["{:?} {} {:?}", feature.kind, feature.id, feature.tags];
+//! }
+//! # Ok(()) }
+//! # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+//! #   let mut p = PathBuf::from("/tmp/eyros-db/");
+//! #   p.push(name);
+//! #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+//! # }
+//! ```
+//!
+//! Way bounds are computed from the coordinates of nodes that appear earlier
+//! in the file, as they do in sorted extracts. The coordinates of every node
+//! are kept in memory during the import, about 20 bytes per node.
+
+use crate::{DB,Row};
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::{Error,bail};
+use osmpbf::{ElementReader,Element};
+use random_access_storage::RandomAccess;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+/// `((lon_min,lon_max),(lat_min,lat_max))`
+pub type OsmPoint = ((f32,f32),(f32,f32));
+
+/// Type of an OpenStreetMap element.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum OsmKind {
+  Node,
+  Way
+}
+
+/// Value of an OpenStreetMap node or way.
+#[derive(Clone,Debug,PartialEq)]
+pub struct OsmFeature {
+  pub kind: OsmKind,
+  pub id: i64,
+  pub tags: Vec<(String,String)>,
+  /// Ids of the nodes of a way, in order. Empty for nodes.
+  pub refs: Vec<i64>
+}
+
+impl CountBytes for OsmFeature {
+  fn count_bytes (&self) -> usize {
+    1 + 8 + 4 + self.refs.len()*8 + 4
+      + self.tags.iter().map(|(k,v)| 4 + k.len() + v.len()).sum::<usize>()
+  }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    if buf.len() < 13 { bail!["buffer too small for osm feature in count"] }
+    let nrefs = u32::from_be_bytes([buf[9],buf[10],buf[11],buf[12]]) as usize;
+    let mut offset = 13 + nrefs*8;
+    if buf.len() < offset+4 {
+      bail!["buffer too small for osm feature in count"]
+    }
+    let ntags = u32::from_be_bytes([
+      buf[offset],buf[offset+1],buf[offset+2],buf[offset+3]
+    ]) as usize;
+    offset += 4;
+    for _ in 0..ntags*2 {
+      if buf.len() < offset+2 {
+        bail!["buffer too small for osm feature in count"]
+      }
+      offset += 2 + u16::from_be_bytes([buf[offset],buf[offset+1]]) as usize;
+    }
+    if buf.len() < offset {
+      bail!["buffer too small for osm feature in count"]
+    }
+    Ok(offset)
+  }
+}
+
+impl ToBytes for OsmFeature {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    let mut buf = vec![0u8;self.count_bytes()];
+    self.write_bytes(&mut buf)?;
+    Ok(buf)
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    let size = self.count_bytes();
+    if dst.len() < size { bail!["dst buffer too small for osm feature"] }
+    dst[0] = match self.kind { OsmKind::Node => 0, OsmKind::Way => 1 };
+    dst[1..9].copy_from_slice(&self.id.to_be_bytes());
+    dst[9..13].copy_from_slice(&(self.refs.len() as u32).to_be_bytes());
+    let mut offset = 13;
+    for r in self.refs.iter() {
+      dst[offset..offset+8].copy_from_slice(&r.to_be_bytes());
+      offset += 8;
+    }
+    dst[offset..offset+4]
+      .copy_from_slice(&(self.tags.len() as u32).to_be_bytes());
+    offset += 4;
+    for (k,v) in self.tags.iter() {
+      for s in [k,v].iter() {
+        if s.len() > u16::MAX as usize { bail!["osm tag too long"] }
+        dst[offset..offset+2].copy_from_slice(&(s.len() as u16).to_be_bytes());
+        dst[offset+2..offset+2+s.len()].copy_from_slice(s.as_bytes());
+        offset += 2 + s.len();
+      }
+    }
+    Ok(size)
+  }
+}
+
+impl FromBytes for OsmFeature {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    let size = Self::count_from_bytes(src)?;
+    let kind = match src[0] {
+      0 => OsmKind::Node,
+      1 => OsmKind::Way,
+      k => bail!["unknown osm kind {}", k]
+    };
+    let read_i64 = |i: usize| {
+      let mut b = [0u8;8];
+      b.copy_from_slice(&src[i..i+8]);
+      i64::from_be_bytes(b)
+    };
+    let nrefs = u32::from_be_bytes([src[9],src[10],src[11],src[12]]) as usize;
+    let refs = (0..nrefs).map(|i| read_i64(13+i*8)).collect();
+    let mut offset = 13 + nrefs*8;
+    let ntags = u32::from_be_bytes([
+      src[offset],src[offset+1],src[offset+2],src[offset+3]
+    ]) as usize;
+    offset += 4;
+    let mut strings = Vec::with_capacity(ntags*2);
+    for _ in 0..ntags*2 {
+      let len = u16::from_be_bytes([src[offset],src[offset+1]]) as usize;
+      strings.push(String::from_utf8(src[offset+2..offset+2+len].to_vec())?);
+      offset += 2 + len;
+    }
+    let mut tags = Vec::with_capacity(ntags);
+    let mut strings = strings.into_iter();
+    while let (Some(k),Some(v)) = (strings.next(),strings.next()) {
+      tags.push((k,v));
+    }
+    Ok((size, Self { kind, id: read_i64(1), tags, refs }))
+  }
+}
+
+/// Counts from `OsmImport::load()`.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct OsmReport {
+  /// Nodes written to the database.
+  pub nodes: u64,
+  /// Ways written to the database.
+  pub ways: u64,
+  /// Ways skipped because none of their nodes were found.
+  pub missing_ways: u64,
+  /// Relations, which are not imported.
+  pub relations: u64
+}
+
+/// Options to load an `.osm.pbf` extract into a database.
+#[derive(Clone,Debug)]
+pub struct OsmImport {
+  batch_size: usize,
+  untagged_nodes: bool
+}
+
+impl Default for OsmImport {
+  fn default () -> Self {
+    Self::new()
+  }
+}
+
+impl OsmImport {
+  pub fn new () -> Self {
+    Self { batch_size: 50_000, untagged_nodes: false }
+  }
+  /// Number of rows written in each batch. The default is 50,000.
+  pub fn batch_size (mut self, size: usize) -> Self {
+    self.batch_size = size.max(1);
+    self
+  }
+  /// Also write nodes without tags, which are usually only the vertices of
+  /// ways. By default they are skipped.
+  pub fn untagged_nodes (mut self, enabled: bool) -> Self {
+    self.untagged_nodes = enabled;
+    self
+  }
+  /// Load the extract at `path` into `db`.
+  pub fn load_path<S,U,F> (&self, db: &mut DB<S,U,OsmPoint,OsmFeature>,
+  path: F) -> Result<OsmReport,Error>
+  where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>),
+  F: AsRef<Path> {
+    self.load_elements(db, ElementReader::from_path(path)?)
+  }
+  /// Load the extract read from `reader` into `db`.
+  pub fn load<S,U,R> (&self, db: &mut DB<S,U,OsmPoint,OsmFeature>,
+  reader: R) -> Result<OsmReport,Error>
+  where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>),
+  R: Read+Send {
+    self.load_elements(db, ElementReader::new(reader))
+  }
+  fn load_elements<S,U,R> (&self, db: &mut DB<S,U,OsmPoint,OsmFeature>,
+  reader: ElementReader<R>) -> Result<OsmReport,Error>
+  where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>),
+  R: Read+Send {
+    let mut report = OsmReport::default();
+    let mut coords: HashMap<i64,(f32,f32)> = HashMap::new();
+    let mut batch: Vec<Row<OsmPoint,OsmFeature>> =
+      Vec::with_capacity(self.batch_size);
+    let mut error: Option<Error> = None;
+    reader.for_each(|element| {
+      if error.is_some() { return }
+      match element {
+        Element::Node(n) => {
+          let tags = owned_tags(n.tags());
+          let (id,lon,lat) = (n.id(), n.lon(), n.lat());
+          if self.node(&mut batch, &mut coords, id, lon, lat, tags) {
+            report.nodes += 1;
+          }
+        },
+        Element::DenseNode(n) => {
+          let tags = owned_tags(n.tags());
+          let (id,lon,lat) = (n.id(), n.lon(), n.lat());
+          if self.node(&mut batch, &mut coords, id, lon, lat, tags) {
+            report.nodes += 1;
+          }
+        },
+        Element::Way(w) => {
+          let refs: Vec<i64> = w.refs().collect();
+          let points: Vec<(f32,f32)> = refs.iter()
+            .filter_map(|r| coords.get(r).copied())
+            .collect();
+          if points.is_empty() {
+            report.missing_ways += 1;
+            return
+          }
+          let mut bbox = ((points[0].0,points[0].0),(points[0].1,points[0].1));
+          for (lon,lat) in points.iter() {
+            bbox = (
+              ((bbox.0).0.min(*lon),(bbox.0).1.max(*lon)),
+              ((bbox.1).0.min(*lat),(bbox.1).1.max(*lat))
+            );
+          }
+          batch.push(Row::Insert(bbox, OsmFeature {
+            kind: OsmKind::Way, id: w.id(), tags: owned_tags(w.tags()), refs
+          }));
+          report.ways += 1;
+        },
+        Element::Relation(_) => {
+          report.relations += 1;
+        }
+      }
+      if batch.len() >= self.batch_size {
+        if let Err(e) = db.batch(&batch) { error = Some(e) }
+        batch.clear();
+      }
+    })?;
+    if let Some(e) = error { return Err(e) }
+    if !batch.is_empty() {
+      db.batch(&batch)?;
+    }
+    Ok(report)
+  }
+  // record the coordinates of a node and add it to the batch unless it is
+  // skipped, returning whether it was added
+  fn node (&self, batch: &mut Vec<Row<OsmPoint,OsmFeature>>,
+  coords: &mut HashMap<i64,(f32,f32)>, id: i64, lon: f64, lat: f64,
+  tags: Vec<(String,String)>) -> bool {
+    let (lon,lat) = (lon as f32, lat as f32);
+    coords.insert(id, (lon,lat));
+    if tags.is_empty() && !self.untagged_nodes { return false }
+    batch.push(Row::Insert(((lon,lon),(lat,lat)), OsmFeature {
+      kind: OsmKind::Node, id, tags, refs: vec![]
+    }));
+    true
+  }
+}
+
+fn owned_tags<'a,I> (tags: I) -> Vec<(String,String)>
+where I: Iterator<Item=(&'a str,&'a str)> {
+  tags.map(|(k,v)| (k.to_string(),v.to_string())).collect()
+}
@@ -0,0 +1,108 @@
+use crate::{Point,Value};
+use failure::{Error,bail};
+
+// Bit-packed rows of a data block, written when `Setup::pack_coords()` is
+// enabled for a new database.
+//
+// Points in a block are spatially clustered, so their serialized forms share
+// most of their high bytes. Every point is serialized to the same number of
+// bytes and each byte column is stored as the bits that differ from the first
+// point, packed with the fewest bits that hold every row of the column:
+//
+//   [rows: u32][point size: u16][reference point][bits per column]
+//   [packed columns...][values...]
+//
+// Columns are padded to a byte boundary and values follow in row order.
+
+/// Encode `rows` into `out`, returning `false` without writing anything if the
+/// points do not all serialize to the same size.
+pub fn encode<P,V> (rows: &[&(P,V)], out: &mut Vec<u8>) -> Result<bool,Error>
+where P: Point, V: Value {
+  if rows.is_empty() { return Ok(false) }
+  let k = rows[0].0.count_bytes();
+  if k == 0 || k > u16::MAX as usize
+  || rows.iter().any(|(p,_)| p.count_bytes() != k) {
+    return Ok(false);
+  }
+  let n = rows.len();
+  let mut points = vec![0u8;n*k];
+  for (i,(p,_)) in rows.iter().enumerate() {
+    p.write_bytes(&mut points[i*k..(i+1)*k])?;
+  }
+  let reference = points[0..k].to_vec();
+  let bits: Vec<u8> = (0..k).map(|j| {
+    let diff = (0..n).fold(0u8, |acc,i| acc | (points[i*k+j] ^ reference[j]));
+    (8 - diff.leading_zeros()) as u8
+  }).collect();
+  out.extend_from_slice(&(n as u32).to_be_bytes());
+  out.extend_from_slice(&(k as u16).to_be_bytes());
+  out.extend_from_slice(&reference);
+  out.extend_from_slice(&bits);
+  for j in 0..k {
+    if bits[j] == 0 { continue }
+    let mut acc = 0u32;
+    let mut nbits = 0;
+    for i in 0..n {
+      acc = (acc << bits[j]) | ((points[i*k+j] ^ reference[j]) as u32);
+      nbits += bits[j] as u32;
+      while nbits >= 8 {
+        nbits -= 8;
+        out.push((acc >> nbits) as u8);
+      }
+    }
+    if nbits > 0 {
+      out.push((acc << (8-nbits)) as u8);
+    }
+  }
+  for (_,v) in rows.iter() {
+    out.extend(v.to_bytes()?);
+  }
+  Ok(true)
+}
+
+/// Decode every row written by `encode()` at the start of `buf`.
+pub fn decode<P,V> (buf: &[u8]) -> Result<Vec<(P,V)>,Error>
+where P: Point, V: Value {
+  if buf.len() < 6 { bail!["packed data block is too short"] }
+  let n = u32::from_be_bytes([buf[0],buf[1],buf[2],buf[3]]) as usize;
+  let k = u16::from_be_bytes([buf[4],buf[5]]) as usize;
+  let mut offset = 6;
+  if buf.len() < offset+2*k { bail!["packed data block is too short"] }
+  let reference = &buf[offset..offset+k];
+  offset += k;
+  let bits = &buf[offset..offset+k];
+  offset += k;
+  let mut points = vec![0u8;n*k];
+  for i in 0..n {
+    points[i*k..(i+1)*k].copy_from_slice(reference);
+  }
+  for j in 0..k {
+    let b = bits[j] as u32;
+    if b == 0 { continue }
+    if b > 8 { bail!["unexpected bit width {} in packed data block", b] }
+    let len = (n*(b as usize)+7)/8;
+    if buf.len() < offset+len { bail!["packed data block is too short"] }
+    let column = &buf[offset..offset+len];
+    let mut acc = 0u32;
+    let mut nbits = 0;
+    let mut c = 0;
+    for i in 0..n {
+      while nbits < b {
+        acc = (acc << 8) | (column[c] as u32);
+        c += 1;
+        nbits += 8;
+      }
+      nbits -= b;
+      points[i*k+j] ^= ((acc >> nbits) & ((1 << b) - 1)) as u8;
+    }
+    offset += len;
+  }
+  let mut rows = Vec::with_capacity(n);
+  for i in 0..n {
+    let (_,p) = P::from_bytes(&points[i*k..(i+1)*k])?;
+    let (size,v) = V::from_bytes(&buf[offset..])?;
+    offset += size;
+    rows.push((p,v));
+  }
+  Ok(rows)
+}
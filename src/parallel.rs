@@ -0,0 +1,165 @@
+use crate::{DB,Point,Value,Location,QueryIterator,SubIterator,Tree,
+  DeleteSet};
+use crate::executor::{Executor,TreeJob};
+use crate::staging::StagingIterator;
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool,Ordering};
+use std::sync::mpsc::{self,Receiver};
+
+// results buffered between the tree workers and the consumer
+const CHANNEL_SIZE: usize = 1_000;
+
+/// Iterator of `Result<(Point,Value,Location)>` records returned by
+/// `db.query_parallel()`. The staged results come first, followed by the
+/// tree results in the order the workers send them.
+///
+/// Dropping the iterator stops the workers from reading further and waits
+/// for them to let go of the query.
+pub struct ParallelQuery<'b,P,V> where P: Point, V: Value {
+  staging: StagingIterator<'b,P,V>,
+  results: Option<Receiver<Result<(P,V,Location),Error>>>,
+  cancel: Arc<AtomicBool>,
+  // serialized results returned so far, with Setup::dedup_results()
+  seen: Option<HashSet<Vec<u8>>>
+}
+
+impl<'b,P,V> ParallelQuery<'b,P,V> where P: Point, V: Value {
+  fn next_result (&mut self) -> Option<Result<(P,V,Location),Error>> {
+    if let Some(result) = self.staging.next() {
+      return Some(result);
+    }
+    self.results.as_ref()?.recv().ok()
+  }
+}
+
+impl<'b,P,V> Iterator for ParallelQuery<'b,P,V> where P: Point, V: Value {
+  type Item = Result<(P,V,Location),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    loop {
+      let result = self.next_result()?;
+      if let (Some(seen), Ok((p,v,_))) = (&mut self.seen, &result) {
+        let mut key = match p.to_bytes() {
+          Ok(key) => key,
+          Err(e) => return Some(Err(e.into()))
+        };
+        match v.to_bytes() {
+          Ok(bytes) => key.extend(bytes),
+          Err(e) => return Some(Err(e.into()))
+        }
+        if !seen.insert(key) { continue }
+      }
+      return Some(result);
+    }
+  }
+}
+
+impl<'b,P,V> Drop for ParallelQuery<'b,P,V> where P: Point, V: Value {
+  fn drop (&mut self) {
+    // workers stop at their next result once cancelled, and the channel is
+    // drained so that none of them stay blocked on a full channel. the
+    // channel disconnects once every worker is done with the query
+    self.cancel.store(true, Ordering::SeqCst);
+    if let Some(results) = self.results.take() {
+      while results.recv().is_ok() {}
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  // query the trees at the positions in `trees`, leaving out staging and the
+  // records at the locations in `deletes`
+  pub(crate) fn query_trees<'b> (&mut self, bbox: &'b P::Bounds,
+  trees: &[usize], deletes: Rc<RefCell<DeleteSet>>)
+  -> Result<QueryIterator<'b,S,P,V>,Error> {
+    let mut queries = Vec::with_capacity(trees.len());
+    for i in trees.iter() {
+      if let Some(tree) = self.trees.get(*i) {
+        queries.push(SubIterator::Tree(Tree::query(Rc::clone(tree),bbox)?));
+      }
+    }
+    let iter = QueryIterator::new(queries, deletes)?;
+    Ok(if self.fields.delete_bitmap {
+      iter.check_bitmaps(Rc::clone(&self.data_store))
+    } else {
+      iter
+    })
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>+'static,
+U: (Fn(&str) -> Result<S,Error>)+Clone+Send+'static,
+P: Point+Send+'static, V: Value+Send, P::Bounds: Send+'static {
+  /// Query the database like `db.query()`, with the trees divided between
+  /// up to `threads` worker threads that read them at the same time. Each
+  /// tree is traversed independently, so databases with many trees on
+  /// storage that serves parallel reads well, such as SSDs, return the
+  /// results of a large query much sooner.
+  ///
+  /// The workers are started by the first call and kept for later calls,
+  /// like the workers of `db.executor()`. The stores are synced and each
+  /// worker opens its own view of the trees with the storage function and
+  /// settings of this database, which must be `Send` and `Clone`, reopening
+  /// it after the database changes. Staging is queried on this thread.
+  /// Results are returned in no particular order, and an error from a
+  /// worker ends the results of the trees it was given. Consume or drop the
+  /// iterator before the next `db.batch()` or `db.query_parallel()`, since
+  /// the workers are shared between queries.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{DB,Setup,IoMode,disk_storage};
+  /// # use failure::Error;
+  /// # fn main () -> Result<(),Error> {
+  /// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+  /// let mut db: DB<_,_,(f32,f32),u32> = Setup::new(storage).build()?;
+  /// let bbox = ((-10.0,-10.0),(10.0,10.0));
+  /// for result in db.query_parallel(&bbox, 4)? {
+  ///   let (point,value,_location) = result?;
+  ///   println!["{:?} {}", point, value];
+  /// }
+  /// # Ok(()) }
+  /// ```
+  pub fn query_parallel<'b> (&mut self, bbox: &'b P::Bounds, threads: usize)
+  -> Result<ParallelQuery<'b,P,V>,Error> {
+    self.sync()?;
+    let mut trees = vec![];
+    for (i,tree) in self.trees.iter().enumerate() {
+      if !tree.try_borrow_mut()?.is_empty()? { trees.push(i) }
+    }
+    let n = threads.max(1).min(trees.len());
+    let started = self.parallel.as_ref().map(|e| e.threads()).unwrap_or(0);
+    if n > started {
+      // views leave out staging, which is read here instead
+      self.parallel = Some(Executor::start(self.open_store.clone(),
+        self.fields.clone(), n, false));
+    }
+    let deletes = Arc::new(self.staging.delete_set.try_borrow()?.clone());
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (sender,receiver) = mpsc::sync_channel(CHANNEL_SIZE);
+    if let Some(executor) = &self.parallel {
+      for w in 0..n {
+        executor.query_trees(TreeJob {
+          bbox: *bbox,
+          trees: trees.iter().copied().skip(w).step_by(n).collect(),
+          deletes: Arc::clone(&deletes),
+          cancel: Arc::clone(&cancel),
+          sender: sender.clone()
+        });
+      }
+    }
+    Ok(ParallelQuery {
+      staging: self.staging.query(bbox)?,
+      results: Some(receiver),
+      cancel,
+      seen: if self.fields.dedup_results { Some(HashSet::new()) } else { None }
+    })
+  }
+}
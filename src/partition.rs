@@ -0,0 +1,18 @@
+use crate::Value;
+use failure::{Error,ensure};
+
+/// Number of partitions that `Setup::partition()` can sort records into.
+/// Partition keys run from `0` to `PARTITIONS-1`.
+pub const PARTITIONS: u8 = 64;
+
+// partition key extractor from Setup::partition()
+pub(crate) type Partition<V> = Box<dyn Fn(&V) -> u8>;
+
+// bit of the partition of `value` in a partition mask
+pub(crate) fn partition_bit<V> (partition: &Partition<V>, value: &V)
+-> Result<u64,Error> where V: Value {
+  let key = partition(value);
+  ensure![key < PARTITIONS, "partition {} out of range, partition keys must \
+    be below {}", key, PARTITIONS];
+  Ok(1 << key)
+}
@@ -1,14 +1,26 @@
 use std::cmp::Ordering;
 use std::ops::{Div,Add};
-use failure::{Error,format_err};
+use failure::{Error,format_err,bail,ensure};
 use std::fmt::Debug;
 use std::mem::size_of;
 use crate::order;
 use desert::{ToBytes,FromBytes,CountBytes};
 
-/// Points (scalar or interval) must implement these methods.
+pub type Cursor = (u64,usize);
+pub type Block = u64;
+
+/// Points (scalar or interval) used as keys in the trees. `Point` is
+/// implemented for every type that implements its three parts:
+///
+/// * `PointOrd`: comparisons and bounding boxes
+/// * `PointCodec`: serialization of points and of the pivots in branch blocks
+/// * `BranchQuery`: traversal of branch blocks during queries
+///
 /// There's a lot going on here, so you'll most likely want to use one of the
-/// built-in implementations rather than write your own.
+/// built-in implementations or `#[derive(Point)]` rather than write your
+/// own. `BranchQuery` has a default implementation that reads the branch
+/// blocks written by eyros, so a new point type only needs to implement the
+/// first two traits and an empty `impl BranchQuery for T {}`.
 ///
 /// Below, the term "element" refs to a value contained in a point which could
 /// be a scalar or interval.
@@ -16,11 +28,12 @@ use desert::{ToBytes,FromBytes,CountBytes};
 /// each of `(-2.0,4.5)`, `6.0`, and `(9.0,11.0)` is an "element".
 ///
 /// Presently only types with static sizes are supported.
+pub trait Point: PointOrd+PointCodec+BranchQuery {}
+impl<T> Point for T where T: PointOrd+PointCodec+BranchQuery {}
 
-pub type Cursor = (u64,usize);
-pub type Block = u64;
-
-pub trait Point: Copy+Clone+Debug+ToBytes+FromBytes+CountBytes {
+/// Comparisons and bounding boxes of points, the part of `Point` that does
+/// not depend on how points are stored.
+pub trait PointOrd: Copy+Clone+Debug {
   /// Bounding-box corresponding to `(min,max)` as used by `db.query(bbox)`.
   type Bounds: Copy+Clone+Debug+ToBytes+FromBytes+CountBytes;
 
@@ -29,7 +42,11 @@ pub trait Point: Copy+Clone+Debug+ToBytes+FromBytes+CountBytes {
 
   /// Compare elements at a level of tree depth. The dimension under
   /// consideration alternates each level, so you'll likely want the element
-  /// at an index corresponding to `level % dimension`.
+  /// at an index corresponding to `level % dimension`. Intervals compare as
+  /// `Equal` when they overlap, which is how rows that intersect a pivot are
+  /// found, so this is not a total order for intervals. Values like NaN that
+  /// `partial_cmp()` can not compare must still get a consistent order, or
+  /// rows that contain them can be misplaced in the tree.
   fn cmp_at (&self, other: &Self, level: usize) -> Ordering where Self: Sized;
 
   /// For intervals, calculate the midpoint of the greater (upper) interval
@@ -38,33 +55,15 @@ pub trait Point: Copy+Clone+Debug+ToBytes+FromBytes+CountBytes {
   /// For scalars, return the midpoint of two scalars as a scalar.
   fn midpoint_upper (&self, other: &Self) -> Self where Self: Sized;
 
-  /// Return the byte presentation for the element corresponding to the tree
-  /// depth `level` for the purpose of making a pivot. If you have an interval
-  /// type, return the upper bound.
-  fn serialize_at (&self, level: usize, dst: &mut [u8]) -> Result<usize,Error>;
-
   /// Get the number of dimensions for this point type.
   fn dim () -> usize;
 
   /// Return whether the current point intersects with a bounding box.
   fn overlaps (&self, bbox: &Self::Bounds) -> bool;
 
-  /// Return the size in bytes of the pivot-form of the element corresponding to
-  /// the tree depth `level`.
-  fn pivot_bytes_at (&self, level: usize) -> usize;
-
-  /// Calculate the number of bytes to read from `buf` for the tree depth
-  /// `level`.
-  fn count_bytes_at (buf: &[u8], level: usize) -> Result<usize,Error>;
-
-  /// Return a set of `(branch_offset,tree_depth)` tuples (`Cursors`) for
-  /// sub-branches to load next and a set of `u64` (`Blocks`) to read data from
-  /// according to a traversal of the branch data in `buf` at the tree depth
-  /// `level` and subject to the bounds given in `bbox`.
-  fn query_branch (buf: &[u8], bbox: &Self::Bounds, branch_factor: usize,
-    level: usize) -> Result<(Vec<Cursor>,Vec<Block>),Error>;
-
   /// Return a bounding box for a set of coordinates, if possible.
+  /// Every coordinate must overlap the returned box: the staging index and
+  /// the optional block and branch bounds prune with it.
   fn bounds (coords: &Vec<Self>) -> Option<Self::Bounds>;
 
   /// Return a Range corresponding to a bounding box.
@@ -77,10 +76,222 @@ pub trait Point: Copy+Clone+Debug+ToBytes+FromBytes+CountBytes {
   /// `((-1.0,3.0),(0.0,0.8),(-4.0,2.5))` (range)
   fn bounds_to_range (bbox: Self::Bounds) -> Self::Range;
 
+  /// Set `mask[i]` to whether `points[i]` intersects `bbox`, replacing the
+  /// contents of `mask`. The default implementation calls `overlaps()` for
+  /// each point. The built-in tuple points copy each dimension into a
+  /// contiguous column and test a whole column at a time without branches,
+  /// which the compiler can vectorize for `f32` and `f64` coordinates.
+  fn overlaps_batch (points: &[Self], bbox: &Self::Bounds, mask: &mut Vec<bool>)
+  where Self: Sized {
+    mask.clear();
+    mask.extend(points.iter().map(|p| p.overlaps(bbox)));
+  }
+
+  /// Return whether every coordinate of the point is finite, for rejecting
+  /// NaN and infinite coordinates in `db.batch()`. See
+  /// `Setup::finite_coords()`. The default implementation returns `true`.
+  fn is_finite (&self) -> bool { true }
+
+  /// Return whether the minimum of every interval element is less than or
+  /// equal to its maximum. Points with inverted intervals never match a
+  /// query. The default implementation returns `true`.
+  fn is_ordered (&self) -> bool { true }
+
+  /// Return the point with the bounds of every inverted interval element
+  /// swapped, so that `is_ordered()` holds. The default implementation
+  /// returns the point unchanged.
+  fn normalized (&self) -> Self where Self: Sized { *self }
+
+  /// Return the extent of `bounds` in the dimension for the tree depth
+  /// `level`, for picking the dimension with the greatest spread to split on
+  /// with `Setup::adaptive_split()`. The default implementation returns
+  /// `None`, which keeps the dimension of the configured cycle.
+  fn spread_at (_bounds: &Self::Bounds, _level: usize) -> Option<f64> {
+    None
+  }
+
+  /// Return whether the bounding box `inner` lies entirely within `outer`.
+  /// This is only used to skip work, so the default implementation
+  /// conservatively returns `false`.
+  fn bounds_within (_inner: &Self::Bounds, _outer: &Self::Bounds) -> bool {
+    false
+  }
+
+  /// Return whether the bounding boxes `a` and `b` intersect. This is only
+  /// used to skip work, so the default implementation conservatively returns
+  /// `true`.
+  fn bounds_overlap (_a: &Self::Bounds, _b: &Self::Bounds) -> bool {
+    true
+  }
+}
+
+/// Serialization of the elements of points as the pivots of branch blocks,
+/// the part of `Point` that defines the on-disk form of pivots.
+pub trait PointCodec: PointOrd+ToBytes+FromBytes+CountBytes {
+  /// Return the byte presentation for the element corresponding to the tree
+  /// depth `level` for the purpose of making a pivot. If you have an interval
+  /// type, return the upper bound.
+  fn serialize_at (&self, level: usize, dst: &mut [u8]) -> Result<usize,Error>;
+
+  /// Return the size in bytes of the pivot-form of the element corresponding to
+  /// the tree depth `level`.
+  fn pivot_bytes_at (&self, level: usize) -> usize;
+
+  /// Calculate the number of bytes to read from `buf` for the tree depth
+  /// `level`.
+  fn count_bytes_at (buf: &[u8], level: usize) -> Result<usize,Error>;
+
+  /// Decode the pivot for the tree depth `level` at the start of `buf`, as
+  /// written by `serialize_at()`, and return whether the lower and upper
+  /// bounds of `bbox` in the same dimension are below and above it:
+  /// `(min <= pivot, pivot <= max)`.
+  fn pivot_cmp_at (buf: &[u8], bbox: &Self::Bounds, level: usize)
+    -> Result<(bool,bool),Error>;
+
   /// Return a string representation of the element in a buffer slice
   /// corresponding to the tree depth level.
   fn format_at (buf: &[u8], level: usize)
     -> Result<String,Error>;
+
+  /// Serialized size of the point as written by `write_quantized()`.
+  fn count_quantized (&self) -> usize { 0 }
+
+  /// Write every coordinate as its `i32` step of the quantization `q` for its
+  /// dimension, for data blocks written with `Setup::quantize()`.
+  fn write_quantized (&self, _q: &[Quantize], _dst: &mut [u8])
+  -> Result<usize,Error> {
+    bail!["point type does not support Setup::quantize()"]
+  }
+
+  /// Read a point written by `write_quantized()` from the start of `buf`,
+  /// returning the number of bytes read along with the point.
+  fn read_quantized (_buf: &[u8], _q: &[Quantize])
+  -> Result<(usize,Self),Error> where Self: Sized {
+    bail!["point type does not support Setup::quantize()"]
+  }
+}
+
+/// Traversal of branch blocks during queries, the part of `Point` that
+/// parses raw blocks. The default implementation reads the layout that eyros
+/// writes with `point::query_branch()`, so only types with their own branch
+/// layout need to override it.
+pub trait BranchQuery: PointCodec {
+  /// Return a set of `(branch_offset,tree_depth)` tuples (`Cursors`) for
+  /// sub-branches to load next and a set of `u64` (`Blocks`) to read data from
+  /// according to a traversal of the branch data in `buf` at the tree depth
+  /// `level` and subject to the bounds given in `bbox`.
+  fn query_branch (buf: &[u8], bbox: &Self::Bounds, branch_factor: usize,
+  level: usize) -> Result<(Vec<Cursor>,Vec<Block>),Error> where Self: Sized {
+    query_branch::<Self>(buf, bbox, branch_factor, level)
+  }
+}
+
+/// Locate the subtree bounding boxes that follow the child offsets (ending at
+/// `b_end`) of a branch block with `nodes` children, returning the start of
+/// the bounds and the size of each entry, or `None` if the block was written
+/// without bounds. See `Setup::branch_bounds()`.
+pub(crate) fn branch_bounds (buf: &[u8], b_end: usize, nodes: usize)
+-> Result<Option<(usize,usize)>,Error> {
+  if buf.len() == b_end { return Ok(None) }
+  if buf.len() < b_end || (buf.len()-b_end) % nodes != 0 {
+    return Err(format_err!["unexpected block length"]);
+  }
+  Ok(Some((b_end,(buf.len()-b_end)/nodes)))
+}
+
+/// Return whether the child in node slot `j` of a branch block may hold
+/// records that intersect `bbox`, according to the bounds located with
+/// `branch_bounds()`.
+pub(crate) fn child_overlaps<P> (buf: &[u8], bounds: Option<(usize,usize)>,
+j: usize, bbox: &P::Bounds) -> Result<bool,Error> where P: PointOrd {
+  match bounds {
+    None => Ok(true),
+    Some((start,size)) => {
+      let (_,b) = <P::Bounds as FromBytes>::from_bytes(&buf[start+j*size..])?;
+      Ok(P::bounds_overlap(&b, bbox))
+    }
+  }
+}
+
+/// Traverse a branch block in the layout written by eyros, the default
+/// implementation of `BranchQuery::query_branch()`. Pivots are decoded with
+/// `PointCodec::count_bytes_at()` and `PointCodec::pivot_cmp_at()`.
+pub fn query_branch<P> (buf: &[u8], bbox: &P::Bounds, bf: usize, level: usize)
+-> Result<(Vec<Cursor>,Vec<Block>),Error> where P: PointCodec {
+  let mut cursors = vec![];
+  let mut blocks = vec![];
+
+  let n = order::order_len(bf);
+  // pivots have a fixed size, so only the pivots that are visited below
+  // are decoded, directly from the block buffer
+  let psize = P::count_bytes_at(buf, level)?;
+  let d_start = n*psize; // data bitfield
+  let i_start = d_start + (n+bf+7)/8; // intersections
+  let b_start = i_start + n*size_of::<u64>(); // buckets
+  let b_end = b_start+bf*size_of::<u64>();
+  let bounds = branch_bounds(buf, b_end, n+bf)?;
+
+  let mut bcursors = vec![0];
+  let mut bitfield: Vec<bool> = vec![false;bf]; // which buckets
+  while let Some(c) = bcursors.pop() {
+    let i = order::order(bf, c);
+    let cmp = P::pivot_cmp_at(&buf[i*psize..], bbox, level)?;
+    let is_data = ((buf[d_start+i/8]>>(i%8))&1) == 1;
+    let i_offset = i_start + i*8;
+    // intersection:
+    let offset = u64::from_be_bytes([
+      buf[i_offset+0], buf[i_offset+1],
+      buf[i_offset+2], buf[i_offset+3],
+      buf[i_offset+4], buf[i_offset+5],
+      buf[i_offset+6], buf[i_offset+7],
+    ]);
+    if offset > 0 && !child_overlaps::<P>(buf, bounds, i, bbox)? {
+      // subtree bounds miss bbox
+    } else if is_data && offset > 0 {
+      blocks.push(offset-1);
+    } else if offset > 0 {
+      cursors.push((offset-1,level+1));
+    }
+    // internal branches:
+    if cmp.0 && c*2+1 < n { // left internal
+      bcursors.push(c*2+1);
+    } else if cmp.0 { // left branch
+      bitfield[i/2] = true;
+    }
+    if cmp.1 && c*2+2 < n { // right internal
+      bcursors.push(c*2+2);
+    } else if cmp.1 { // right branch
+      bitfield[i/2+1] = true;
+    }
+    // internal leaves are even integers in (0..n)
+    // which map to buckets `i/2+0` and/or `i/2+1`
+    // depending on left/right comparisons
+    /*                7
+               3             11
+            1     5       9      13
+          0   2 4  6    8  10  12  14
+      B: 0  1  2  3   4  5   6   7   8
+    */
+  }
+  for (i,b) in bitfield.iter().enumerate() {
+    if !b { continue }
+    let j = i+n;
+    let is_data = (buf[d_start+j/8]>>(j%8))&1 == 1;
+    let offset = u64::from_be_bytes([
+      buf[b_start+i*8+0], buf[b_start+i*8+1],
+      buf[b_start+i*8+2], buf[b_start+i*8+3],
+      buf[b_start+i*8+4], buf[b_start+i*8+5],
+      buf[b_start+i*8+6], buf[b_start+i*8+7]
+    ]);
+    if offset > 0 && !child_overlaps::<P>(buf, bounds, j, bbox)? {
+      continue;
+    } else if offset > 0 && is_data {
+      blocks.push(offset-1);
+    } else if offset > 0 {
+      cursors.push((offset-1,level+1));
+    }
+  }
+  Ok((cursors,blocks))
 }
 
 pub trait Num<T>: PartialOrd+Copy+ToBytes+FromBytes+CountBytes
@@ -91,31 +302,119 @@ impl<T> Num<T> for T where T: PartialOrd+Copy+ToBytes+FromBytes+CountBytes
 /// Types representing a single value (as opposed to an interval, which has
 /// minimum and maximum values).
 ///
-/// This trait has no required methods.
-pub trait Scalar: Copy+Sized+'static {}
-impl Scalar for f32 {}
-impl Scalar for f64 {}
-impl Scalar for u8 {}
-impl Scalar for u16 {}
-impl Scalar for u32 {}
-impl Scalar for u64 {}
-impl Scalar for i8 {}
-impl Scalar for i16 {}
-impl Scalar for i32 {}
-impl Scalar for i64 {}
+/// This trait has no required methods. The floating point types override
+/// `finite()` and `total_order()` so that NaN values can be rejected on insert
+/// and sorted deterministically when they are already stored. The built-in
+/// numeric types override `as_f64()`, `from_f64()`, `lowest()` and
+/// `highest()`.
+pub trait Scalar: Copy+Sized+'static {
+  /// Whether the value is a usable coordinate: not NaN or infinite.
+  fn finite (&self) -> bool { true }
+  /// Order for values that `partial_cmp()` can not compare, such as NaN.
+  fn total_order (&self, other: &Self) -> Ordering where Self: PartialOrd {
+    self.partial_cmp(other).unwrap_or(Ordering::Equal)
+  }
+  /// The value as a float, for measuring the spread of coordinates with
+  /// `PointOrd::spread_at()`, or `None` if there is no such conversion.
+  fn as_f64 (&self) -> Option<f64> { None }
+  /// The nearest value to a float, for reading coordinates stored with
+  /// `Setup::quantize()`, or `None` if there is no such conversion.
+  fn from_f64 (_x: f64) -> Option<Self> { None }
+  /// The value that every other value is greater than or equal to, for the
+  /// open lower sides of a `QueryRegion`, or `None` if there is none.
+  fn lowest () -> Option<Self> { None }
+  /// The value that every other value is less than or equal to, for the open
+  /// upper sides of a `QueryRegion`, or `None` if there is none.
+  fn highest () -> Option<Self> { None }
+}
+impl Scalar for f32 {
+  fn finite (&self) -> bool { self.is_finite() }
+  fn total_order (&self, other: &Self) -> Ordering { self.total_cmp(other) }
+  fn as_f64 (&self) -> Option<f64> { Some(*self as f64) }
+  fn from_f64 (x: f64) -> Option<Self> { Some(x as f32) }
+  fn lowest () -> Option<Self> { Some(f32::NEG_INFINITY) }
+  fn highest () -> Option<Self> { Some(f32::INFINITY) }
+}
+impl Scalar for f64 {
+  fn finite (&self) -> bool { self.is_finite() }
+  fn total_order (&self, other: &Self) -> Ordering { self.total_cmp(other) }
+  fn as_f64 (&self) -> Option<f64> { Some(*self) }
+  fn from_f64 (x: f64) -> Option<Self> { Some(x) }
+  fn lowest () -> Option<Self> { Some(f64::NEG_INFINITY) }
+  fn highest () -> Option<Self> { Some(f64::INFINITY) }
+}
+macro_rules! impl_int_scalar {
+  ($($T:ty),+) => {
+    $(impl Scalar for $T {
+      fn as_f64 (&self) -> Option<f64> { Some(*self as f64) }
+      fn from_f64 (x: f64) -> Option<Self> { Some(x.round() as $T) }
+      fn lowest () -> Option<Self> { Some(<$T>::MIN) }
+      fn highest () -> Option<Self> { Some(<$T>::MAX) }
+    })+
+  }
+}
+impl_int_scalar![u8,u16,u32,u64,i8,i16,i32,i64];
+
+/// Fixed-precision storage for the coordinates of one dimension, set with
+/// `Setup::quantize()`.
+///
+/// Each coordinate `x` is stored in data blocks as the `i32` step
+/// `round((x - offset) / scale)` and read back as `offset + step * scale`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Quantize {
+  /// Distance between two neighbouring steps.
+  pub scale: f64,
+  /// Coordinate of step `0`.
+  pub offset: f64
+}
+
+impl Quantize {
+  pub fn new (scale: f64, offset: f64) -> Self {
+    Self { scale, offset }
+  }
+  /// Nearest step to the coordinate `x`, or an error if `x` is too far from
+  /// `offset` for an `i32` step.
+  pub fn step (&self, x: f64) -> Result<i32,Error> {
+    let step = ((x - self.offset) / self.scale).round();
+    ensure![step >= i32::MIN as f64 && step <= i32::MAX as f64,
+      "coordinate {} is out of range for quantization with scale {} and \
+      offset {}", x, self.scale, self.offset];
+    Ok(step as i32)
+  }
+  /// Coordinate of `step`.
+  pub fn value (&self, step: i32) -> f64 {
+    self.offset + (step as f64) * self.scale
+  }
+}
 
 trait Coord<T> {
   fn cmp (&self, other: &Self) -> Option<Ordering>;
+  fn total_cmp (&self, other: &Self) -> Ordering;
+  fn finite (&self) -> bool;
+  fn ordered (&self) -> bool;
+  fn normalized (&self) -> Self;
   fn midpoint_upper (&self, other: &Self) -> Self;
   fn upper (&self) -> T;
   fn overlaps (&self, a: &T, b: &T) -> bool;
   fn bounds (coords: Vec<&Self>) -> Option<(T,T)>;
+  fn mask_overlaps (column: &[Self], min: &T, max: &T, mask: &mut [bool])
+    where Self: Sized;
+  fn count_quantized (&self) -> usize;
+  fn quantize (&self, q: &Quantize, dst: &mut [u8]) -> Result<usize,Error>;
+  fn dequantize (buf: &[u8], q: &Quantize) -> Result<(usize,Self),Error>
+    where Self: Sized;
 }
 
 impl<T> Coord<T> for T where T: Scalar+PartialOrd+Num<T> {
   fn cmp (&self, other: &T) -> Option<Ordering> {
     self.partial_cmp(&other)
   }
+  fn total_cmp (&self, other: &T) -> Ordering {
+    self.partial_cmp(&other).unwrap_or_else(|| self.total_order(other))
+  }
+  fn finite (&self) -> bool { Scalar::finite(self) }
+  fn ordered (&self) -> bool { true }
+  fn normalized (&self) -> Self { *self }
   fn midpoint_upper (&self, other: &Self) -> Self {
     (*self + *other) / 2.into()
   }
@@ -123,12 +422,36 @@ impl<T> Coord<T> for T where T: Scalar+PartialOrd+Num<T> {
   fn overlaps (&self, min: &T, max: &T) -> bool {
     *min <= *self && *self <= *max
   }
+  fn mask_overlaps (column: &[Self], min: &T, max: &T, mask: &mut [bool]) {
+    for (m,x) in mask.iter_mut().zip(column.iter()) {
+      *m &= (*min <= *x) & (*x <= *max);
+    }
+  }
+  fn count_quantized (&self) -> usize { 4 }
+  fn quantize (&self, q: &Quantize, dst: &mut [u8]) -> Result<usize,Error> {
+    match self.as_f64() {
+      Some(x) => Ok(q.step(x)?.write_bytes(dst)?),
+      None => bail!["coordinate type can not be quantized"]
+    }
+  }
+  fn dequantize (buf: &[u8], q: &Quantize) -> Result<(usize,Self),Error> {
+    let (size,step) = i32::from_bytes(buf)?;
+    match T::from_f64(q.value(step)) {
+      Some(x) => Ok((size,x)),
+      None => bail!["coordinate type can not be quantized"]
+    }
+  }
   fn bounds (coords: Vec<&Self>) -> Option<(T,T)> {
-    if coords.len() == 0 { return None }
-    let mut min = coords[0];
-    let mut max = coords[0];
-    for i in 1..coords.len() {
-      let c = coords[i];
+    // values that do not compare to themselves (NaN) are left out of the
+    // bounds unless there is nothing else. NaN bounds match no query, just
+    // like the NaN values themselves.
+    let first = *coords.first()?;
+    let mut iter = coords.into_iter().filter(|c| Coord::cmp(*c, *c).is_some());
+    let (mut min, mut max) = match iter.next() {
+      None => return Some((*first,*first)),
+      Some(c) => (c,c)
+    };
+    for c in iter {
       match c.cmp(min) {
         None => { return None },
         Some(Ordering::Less) => { min = c },
@@ -152,6 +475,16 @@ impl<T> Coord<T> for (T,T) where T: Scalar+PartialOrd+Num<T> {
       self.0.partial_cmp(&other.0)
     }
   }
+  fn total_cmp (&self, other: &Self) -> Ordering {
+    self.cmp(other).unwrap_or_else(|| self.0.total_order(&other.0))
+  }
+  fn finite (&self) -> bool { self.0.finite() && self.1.finite() }
+  fn ordered (&self) -> bool {
+    self.0.partial_cmp(&self.1) != Some(Ordering::Greater)
+  }
+  fn normalized (&self) -> Self {
+    if self.0 > self.1 { (self.1,self.0) } else { *self }
+  }
   fn midpoint_upper (&self, other: &Self) -> Self {
     let x = self.1/2.into() + other.1/2.into();
     (x,x)
@@ -160,12 +493,31 @@ impl<T> Coord<T> for (T,T) where T: Scalar+PartialOrd+Num<T> {
   fn overlaps (&self, min: &T, max: &T) -> bool {
     *min <= self.1 && self.0 <= *max
   }
+  fn mask_overlaps (column: &[Self], min: &T, max: &T, mask: &mut [bool]) {
+    for (m,x) in mask.iter_mut().zip(column.iter()) {
+      *m &= (*min <= x.1) & (x.0 <= *max);
+    }
+  }
+  fn count_quantized (&self) -> usize { 8 }
+  fn quantize (&self, q: &Quantize, dst: &mut [u8]) -> Result<usize,Error> {
+    let size = <T as Coord<T>>::quantize(&self.0, q, dst)?;
+    Ok(size + <T as Coord<T>>::quantize(&self.1, q, &mut dst[size..])?)
+  }
+  fn dequantize (buf: &[u8], q: &Quantize) -> Result<(usize,Self),Error> {
+    let (s0,x0) = <T as Coord<T>>::dequantize(buf, q)?;
+    let (s1,x1) = <T as Coord<T>>::dequantize(&buf[s0..], q)?;
+    Ok((s0+s1,(x0,x1)))
+  }
   fn bounds (coords: Vec<&Self>) -> Option<(T,T)> {
-    if coords.len() == 0 { return None }
-    let mut min = coords[0].0;
-    let mut max = coords[0].1;
-    for i in 1..coords.len() {
-      let c = coords[i];
+    let first = *coords.first()?;
+    let mut iter = coords.into_iter()
+      .filter(|c| c.0.partial_cmp(&c.0).is_some())
+      .filter(|c| c.1.partial_cmp(&c.1).is_some());
+    let (mut min, mut max) = match iter.next() {
+      None => return Some((first.0,first.1)),
+      Some(c) => (c.0,c.1)
+    };
+    for c in iter {
       match (c.0).cmp(&min) {
         None => { return None },
         Some(Ordering::Less) => { min = c.0 },
@@ -183,136 +535,33 @@ impl<T> Coord<T> for (T,T) where T: Scalar+PartialOrd+Num<T> {
 
 macro_rules! impl_point {
   (($($T:tt),+),($($U:tt),+),($($i:tt),+),$dim:expr) => {
-    impl<$($T),+> Point for ($($U),+)
+    impl<$($T),+> PointOrd for ($($U),+)
     where $($T: Num<$T>),+ {
       type Bounds = (($($T,)+),($($T,)+));
       type Range = ($(($T,$T),)+);
       fn cmp_at (&self, other: &Self, level: usize) -> Ordering {
-        let order = match level%Self::dim() {
-          $($i => Coord::cmp(&self.$i, &other.$i),)+
-          _ => panic!("match case beyond dimension")
-        };
-        match order { Some(x) => x, None => Ordering::Less }
+        match level%Self::dim() {
+          $($i => Coord::total_cmp(&self.$i, &other.$i),)+
+          _ => Ordering::Equal
+        }
       }
       fn midpoint_upper (&self, other: &Self) -> Self {
         ($(
           Coord::midpoint_upper(&self.$i, &other.$i)
         ),+)
       }
-      fn serialize_at (&self, level: usize, dst: &mut [u8])
-      -> Result<usize,Error> {
-        match level%Self::dim() {
-          $($i => self.$i.upper().write_bytes(dst),)+
-          _ => panic!("match case beyond dimension")
-        }
-      }
       fn dim () -> usize { $dim }
       fn overlaps (&self, bbox: &Self::Bounds) -> bool {
         $(Coord::overlaps(&self.$i, &(bbox.0).$i, &(bbox.1).$i) &&)+ true
       }
-      fn pivot_bytes_at (&self, i: usize) -> usize {
-        match i % $dim {
-          $($i => size_of::<$T>(),)+
-          _ => panic!("dimension out of bounds")
-        }
+      fn is_finite (&self) -> bool {
+        $(Coord::finite(&self.$i) &&)+ true
       }
-      fn count_bytes_at (buf: &[u8], i: usize) -> Result<usize,Error> {
-        match i % $dim {
-          $($i => $T::count_from_bytes(buf),)+
-          _ => panic!("dimension out of bounds")
-        }
+      fn is_ordered (&self) -> bool {
+        $(Coord::ordered(&self.$i) &&)+ true
       }
-      fn query_branch (buf: &[u8], bbox: &Self::Bounds, bf: usize, level: usize)
-      -> Result<(Vec<Cursor>,Vec<Block>),Error> {
-        let mut cursors = vec![];
-        let mut blocks = vec![];
-
-        let n = order::order_len(bf);
-        let mut offset = 0;
-        let mut pivots = ($({ $i; vec![] }),+);
-        for _i in 0..n {
-          match level % $dim {
-            $($i => {
-              let (size,x) = $T::from_bytes(&buf[offset..])?;
-              (pivots.$i).push(x);
-              offset += size;
-            },)+
-            _ => panic!["dimension out of bounds"]
-          };
-        }
-        let d_start = offset; // data bitfield
-        let i_start = d_start + (n+bf+7)/8; // intersections
-        let b_start = i_start + n*size_of::<u64>(); // buckets
-        let b_end = b_start+bf*size_of::<u64>();
-        ensure_eq!(b_end, buf.len(), "unexpected block length");
-
-        let mut bcursors = vec![0];
-        let mut bitfield: Vec<bool> = vec![false;bf]; // which buckets
-        while !bcursors.is_empty() {
-          let c = bcursors.pop().unwrap();
-          let i = order::order(bf, c);
-          let cmp = match level % $dim {
-            $($i => {
-              let pivot = (pivots.$i)[i];
-              (
-                (bbox.0).$i <= pivot,
-                pivot <= (bbox.1).$i
-              )
-            },)+
-            _ => panic!["dimension out of bounds"]
-          };
-          let is_data = ((buf[d_start+i/8]>>(i%8))&1) == 1;
-          let i_offset = i_start + i*8;
-          // intersection:
-          let offset = u64::from_be_bytes([
-            buf[i_offset+0], buf[i_offset+1],
-            buf[i_offset+2], buf[i_offset+3],
-            buf[i_offset+4], buf[i_offset+5],
-            buf[i_offset+6], buf[i_offset+7],
-          ]);
-          if is_data && offset > 0 {
-            blocks.push(offset-1);
-          } else if offset > 0 {
-            cursors.push((offset-1,level+1));
-          }
-          // internal branches:
-          if cmp.0 && c*2+1 < n { // left internal
-            bcursors.push(c*2+1);
-          } else if cmp.0 { // left branch
-            bitfield[i/2] = true;
-          }
-          if cmp.1 && c*2+2 < n { // right internal
-            bcursors.push(c*2+2);
-          } else if cmp.1 { // right branch
-            bitfield[i/2+1] = true;
-          }
-          // internal leaves are even integers in (0..n)
-          // which map to buckets `i/2+0` and/or `i/2+1`
-          // depending on left/right comparisons
-          /*                7
-                     3             11
-                  1     5       9      13
-                0   2 4  6    8  10  12  14
-            B: 0  1  2  3   4  5   6   7   8
-          */
-        }
-        for (i,b) in bitfield.iter().enumerate() {
-          if !b { continue }
-          let j = i+n;
-          let is_data = (buf[d_start+j/8]>>(j%8))&1 == 1;
-          let offset = u64::from_be_bytes([
-            buf[b_start+i*8+0], buf[b_start+i*8+1],
-            buf[b_start+i*8+2], buf[b_start+i*8+3],
-            buf[b_start+i*8+4], buf[b_start+i*8+5],
-            buf[b_start+i*8+6], buf[b_start+i*8+7]
-          ]);
-          if offset > 0 && is_data {
-            blocks.push(offset-1);
-          } else if offset > 0 {
-            cursors.push((offset-1,level+1));
-          }
-        }
-        Ok((cursors,blocks))
+      fn normalized (&self) -> Self {
+        ($(Coord::normalized(&self.$i)),+)
       }
       fn bounds (points: &Vec<Self>) -> Option<Self::Bounds> {
         if points.is_empty() { return None }
@@ -332,16 +581,97 @@ macro_rules! impl_point {
       fn bounds_to_range (bounds: Self::Bounds) -> Self::Range {
         ($(((bounds.0).$i,(bounds.1).$i)),+)
       }
+      fn spread_at (bounds: &Self::Bounds, level: usize) -> Option<f64> {
+        match level % $dim {
+          $($i => Some((bounds.1).$i.as_f64()? - (bounds.0).$i.as_f64()?),)+
+          _ => None
+        }
+      }
+      fn bounds_within (inner: &Self::Bounds, outer: &Self::Bounds) -> bool {
+        $((outer.0).$i <= (inner.0).$i && (inner.1).$i <= (outer.1).$i &&)+
+        true
+      }
+      fn bounds_overlap (a: &Self::Bounds, b: &Self::Bounds) -> bool {
+        $((a.0).$i <= (b.1).$i && (b.0).$i <= (a.1).$i &&)+
+        true
+      }
+      fn overlaps_batch (points: &[Self], bbox: &Self::Bounds,
+      mask: &mut Vec<bool>) {
+        mask.clear();
+        mask.resize(points.len(), true);
+        $({
+          let column: Vec<_> = points.iter().map(|p| p.$i).collect();
+          Coord::mask_overlaps(&column, &(bbox.0).$i, &(bbox.1).$i, mask);
+        })+
+      }
+    }
+    impl<$($T),+> PointCodec for ($($U),+)
+    where $($T: Num<$T>),+ {
+      fn serialize_at (&self, level: usize, dst: &mut [u8])
+      -> Result<usize,Error> {
+        match level%Self::dim() {
+          $($i => self.$i.upper().write_bytes(dst),)+
+          _ => bail!["dimension {} out of bounds", level%Self::dim()]
+        }
+      }
+      fn pivot_bytes_at (&self, i: usize) -> usize {
+        [$(self.$i.upper().count_bytes()),+][i % $dim]
+      }
+      fn count_bytes_at (buf: &[u8], i: usize) -> Result<usize,Error> {
+        match i % $dim {
+          $($i => $T::count_from_bytes(buf),)+
+          _ => bail!["dimension {} out of bounds", i % $dim]
+        }
+      }
+      fn pivot_cmp_at (buf: &[u8], bbox: &Self::Bounds, level: usize)
+      -> Result<(bool,bool),Error> {
+        Ok(match level % $dim {
+          $($i => {
+            let (_,pivot) = $T::from_bytes(buf)?;
+            // NaN pivots from stored NaN coordinates prune neither side
+            let nan = pivot.partial_cmp(&pivot).is_none();
+            ((bbox.0).$i <= pivot || nan, pivot <= (bbox.1).$i || nan)
+          },)+
+          _ => bail!["dimension {} out of bounds", level % $dim]
+        })
+      }
       fn format_at (buf: &[u8], level: usize) -> Result<String,Error> {
         Ok(match level % Self::dim() {
           $($i => {
             let (_,p) = $T::from_bytes(buf)?;
             format!["{:?}", p]
           }),+
-          _ => panic!("match case beyond dimension")
+          _ => bail!["dimension {} out of bounds", level % Self::dim()]
         })
       }
+      fn count_quantized (&self) -> usize {
+        $(Coord::count_quantized(&self.$i) +)+ 0
+      }
+      fn write_quantized (&self, q: &[Quantize], dst: &mut [u8])
+      -> Result<usize,Error> {
+        if q.len() != $dim {
+          bail!["expected {} quantizations, found {}", $dim, q.len()];
+        }
+        let mut size = 0;
+        $(size += Coord::quantize(&self.$i, &q[$i], &mut dst[size..])?;)+
+        Ok(size)
+      }
+      fn read_quantized (buf: &[u8], q: &[Quantize])
+      -> Result<(usize,Self),Error> {
+        if q.len() != $dim {
+          bail!["expected {} quantizations, found {}", $dim, q.len()];
+        }
+        let mut size = 0;
+        let point = ($({
+          let (n,x) = <$U as Coord<$T>>::dequantize(&buf[size..], &q[$i])?;
+          size += n;
+          x
+        }),+);
+        Ok((size,point))
+      }
     }
+    impl<$($T),+> BranchQuery for ($($U),+)
+    where $($T: Num<$T>),+ {}
   }
 }
 
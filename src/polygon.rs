@@ -0,0 +1,189 @@
+use crate::{DB,Value,Location,SegmentQuery,QueryIterator,QueryOptions,
+  ray::pad};
+use failure::{Error,ensure};
+use random_access_storage::RandomAccess;
+use std::rc::Rc;
+
+// convex polygon with the projections of its vertices onto the normal of
+// each of its edges, for separating axis tests against boxes
+struct Polygon {
+  min: (f64,f64),
+  max: (f64,f64),
+  axes: Vec<((f64,f64),f64,f64)>
+}
+
+impl Polygon {
+  fn new (hull: &[(f64,f64)]) -> Self {
+    let mut min = hull[0];
+    let mut max = hull[0];
+    for (x,y) in hull.iter() {
+      min = (min.0.min(*x), min.1.min(*y));
+      max = (max.0.max(*x), max.1.max(*y));
+    }
+    let mut axes = Vec::with_capacity(hull.len());
+    for (i,a) in hull.iter().enumerate() {
+      let b = hull[(i+1)%hull.len()];
+      let axis = (a.1-b.1, b.0-a.0);
+      if axis == (0.0,0.0) { continue }
+      let (lo,hi) = project(axis, hull.iter());
+      axes.push((axis,lo,hi));
+    }
+    Self { min, max, axes }
+  }
+  // whether the box from `min` to `max` intersects the polygon: convex shapes
+  // are disjoint if and only if their projections onto one of the box axes or
+  // edge normals are disjoint
+  fn overlaps (&self, min: (f64,f64), max: (f64,f64)) -> bool {
+    if max.0 < self.min.0 || min.0 > self.max.0
+    || max.1 < self.min.1 || min.1 > self.max.1 { return false }
+    let corners = [min, (min.0,max.1), (max.0,min.1), max];
+    self.axes.iter().all(|(axis,lo,hi)| {
+      let (clo,chi) = project(*axis, corners.iter());
+      chi >= *lo && clo <= *hi
+    })
+  }
+}
+
+fn project<'a,I> (axis: (f64,f64), points: I) -> (f64,f64)
+where I: Iterator<Item=&'a (f64,f64)> {
+  points.fold((f64::INFINITY,f64::NEG_INFINITY), |(lo,hi),(x,y)| {
+    let d = x*axis.0 + y*axis.1;
+    (lo.min(d), hi.max(d))
+  })
+}
+
+// convex hull of `points` in counter-clockwise order, with the monotone chain
+// algorithm
+fn convex_hull (points: &[(f64,f64)]) -> Vec<(f64,f64)> {
+  let mut points = points.to_vec();
+  points.sort_by(|a,b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+  points.dedup();
+  if points.len() < 3 { return points }
+  let mut lower = chain(points.iter());
+  let mut upper = chain(points.iter().rev());
+  lower.pop();
+  upper.pop();
+  lower.extend(upper);
+  lower
+}
+
+// half of a convex hull from points sorted along x
+fn chain<'a,I> (points: I) -> Vec<(f64,f64)>
+where I: Iterator<Item=&'a (f64,f64)> {
+  let cross = |o: (f64,f64), a: (f64,f64), b: (f64,f64)| {
+    (a.0-o.0)*(b.1-o.1) - (a.1-o.1)*(b.0-o.0)
+  };
+  let mut hull: Vec<(f64,f64)> = vec![];
+  for p in points {
+    while hull.len() >= 2
+    && cross(hull[hull.len()-2], hull[hull.len()-1], *p) <= 0.0 {
+      hull.pop();
+    }
+    hull.push(*p);
+  }
+  hull
+}
+
+/// Convex polygon to query a two-dimensional database with
+/// `db.query_polygon()`, from the convex hull of a list of `(x,y)` vertices.
+///
+/// ```rust,no_run
+/// # use eyros::{DB,Setup,QueryPolygon,IoMode,disk_storage};
+/// # use failure::Error;
+/// # fn main () -> Result<(),Error> {
+/// type P = ((f32,f32),(f32,f32));
+/// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+/// let mut db: DB<_,_,P,u32> = Setup::new(storage)
+///   .branch_bounds(true)
+///   .build()?;
+/// let polygon: QueryPolygon<P> = QueryPolygon::new(
+///   &[(-90.0,-80.0),(80.0,90.0),(75.0,95.0),(-95.0,-75.0)]
+/// )?;
+/// for result in db.query_polygon(&polygon)? {
+///   let (point,value,_location) = result?;
+///   println!["{:?} {}", point, value];
+/// }
+/// # Ok(()) }
+/// ```
+pub struct QueryPolygon<P> where P: SegmentQuery {
+  polygon: Polygon,
+  bbox: P::Bounds
+}
+
+impl<P> QueryPolygon<P> where P: SegmentQuery {
+  /// Create a polygon from the convex hull of `points`.
+  pub fn new (points: &[(f64,f64)]) -> Result<Self,Error> {
+    ensure![P::dim() == 2, "QueryPolygon expects 2 dimensions, not {}",
+      P::dim()];
+    ensure![!points.is_empty(), "QueryPolygon expects at least one point"];
+    ensure![points.iter().all(|(x,y)| x.is_finite() && y.is_finite()),
+      "QueryPolygon expects finite coordinates"];
+    let polygon = Polygon::new(&convex_hull(points));
+    let bbox = P::bounds_from(
+      &[pad(polygon.min.0,-1.0), pad(polygon.min.1,-1.0)],
+      &[pad(polygon.max.0,1.0), pad(polygon.max.1,1.0)]
+    );
+    Ok(Self { polygon, bbox })
+  }
+  /// Bounding box of the polygon.
+  pub fn bounds (&self) -> &P::Bounds {
+    &self.bbox
+  }
+  // whether the bounds of `p` intersect the polygon
+  fn overlaps (&self, p: &P) -> bool {
+    let ((x0,x1),(y0,y1)) = (p.span_at(0), p.span_at(1));
+    self.polygon.overlaps((x0,y0),(x1,y1))
+  }
+  // whether `bbox` intersects the polygon
+  fn overlaps_bounds (&self, bbox: &P::Bounds) -> bool {
+    let (x0,x1) = P::bounds_span_at(bbox, 0);
+    let (y0,y1) = P::bounds_span_at(bbox, 1);
+    self.polygon.overlaps((x0,y0),(x1,y1))
+  }
+}
+
+/// Iterator of `Result<(Point,Value,Location)>` records returned by
+/// `db.query_polygon()`.
+pub struct PolygonIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: SegmentQuery, V: Value {
+  polygon: &'b QueryPolygon<P>,
+  results: QueryIterator<'b,S,P,V>
+}
+
+impl<'b,S,P,V> Iterator for PolygonIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: SegmentQuery, V: Value {
+  type Item = Result<(P,V,Location),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    loop {
+      match self.results.next()? {
+        Ok((p,v,loc)) => {
+          if self.polygon.overlaps(&p) { return Some(Ok((p,v,loc))) }
+        },
+        Err(e) => return Some(Err(e))
+      }
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: SegmentQuery, V: Value {
+  /// Query a two-dimensional database for the records whose bounds intersect
+  /// `polygon`.
+  ///
+  /// The trees are traversed within the bounding box of the polygon, and with
+  /// `Setup::branch_bounds()`, the children of each branch whose bounds miss
+  /// the polygon itself are skipped with separating axis tests on its edges,
+  /// so branches outside of a diagonal or irregular region are not read.
+  /// Trees written without bounds are read across the whole bounding box.
+  /// Records are then tested against the polygon in the same way.
+  pub fn query_polygon<'b> (&mut self, polygon: &'b QueryPolygon<P>)
+  -> Result<PolygonIterator<'b,S,P,V>,Error> {
+    let filter: Rc<dyn Fn(&P::Bounds) -> bool+'b> =
+      Rc::new(move |bbox: &P::Bounds| polygon.overlaps_bounds(bbox));
+    let results = self.query_filtered(&polygon.bbox, &QueryOptions::default(),
+      Some(filter))?;
+    Ok(PolygonIterator { polygon, results })
+  }
+}
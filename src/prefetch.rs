@@ -0,0 +1,72 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+/// Work done by `db.prefetch()`.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct PrefetchReport {
+  /// Data blocks read into the cache.
+  pub blocks: usize,
+  /// Data blocks that were already cached.
+  pub cached: usize,
+  /// Data blocks whose bounds header showed that they miss the region.
+  pub skipped: usize,
+  /// Data blocks left unread because the cache was full.
+  pub over_capacity: usize
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Warm the data block cache with the blocks that a query for `bbox` would
+  /// read, without building any results, so that a map client can load the
+  /// region around the viewport during idle time and answer the queries for
+  /// it once the view moves there from memory.
+  ///
+  /// The branch blocks of every tree are read along the way, which also
+  /// warms the page cache of the storage. At most
+  /// `Setup::data_list_cache_size()` blocks are read, since more would evict
+  /// the first ones; prefetch a smaller region or raise the cache size if
+  /// `over_capacity` is not `0`.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{DB,Setup,IoMode,disk_storage};
+  /// # use failure::Error;
+  /// # fn main () -> Result<(),Error> {
+  /// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+  /// let mut db: DB<_,_,(f32,f32),u32> = Setup::new(storage).build()?;
+  /// let viewport = ((-0.1,-0.1),(0.1,0.1));
+  /// let around = ((-0.3,-0.3),(0.3,0.3));
+  /// let results = db.query(&viewport)?.collect::<Result<Vec<_>,Error>>()?;
+  /// // ... draw the results, then when idle:
+  /// let report = db.prefetch(&around)?;
+  /// println!["{} blocks read", report.blocks];
+  /// # Ok(()) }
+  /// ```
+  pub fn prefetch (&mut self, bbox: &P::Bounds)
+  -> Result<PrefetchReport,Error> {
+    let mut report = PrefetchReport::default();
+    let mut offsets = vec![];
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      offsets.extend(tree.query_blocks(bbox)?);
+    }
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    let capacity = dstore.cache_capacity();
+    for offset in offsets {
+      if dstore.is_cached(offset) {
+        dstore.prefetch(offset, bbox)?;
+        report.cached += 1;
+      } else if report.blocks + report.cached >= capacity {
+        report.over_capacity += 1;
+      } else if dstore.prefetch(offset, bbox)? {
+        report.blocks += 1;
+      } else {
+        report.skipped += 1;
+      }
+    }
+    Ok(report)
+  }
+}
@@ -0,0 +1,157 @@
+use crate::{DB,Point,Value,Location,Row};
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::collections::BTreeMap;
+
+/// Value that records where a record came from: the id of a source dataset
+/// and the offset of the record within it, alongside the record's own value.
+///
+/// Use `Sourced<V>` as the value type of a database that combines several
+/// datasets to query or purge every record from one of them:
+///
+/// ```rust,no_run
+/// use eyros::{DB,Row,Sourced};
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// type P = ((f32,f32),(f32,f32));
+/// type V = Sourced<u32>;
+///
+/// # fn main () -> Result<(),Error> {
+/// let mut db: DB<_,_,P,V> = DB::open(storage)?;
+/// db.batch(&[
+///   Row::Insert(((0.1,0.2),(0.3,0.4)), Sourced::new(1, 0, 500)),
+///   Row::Insert(((0.5,0.5),(0.6,0.7)), Sourced::new(2, 0, 600))
+/// ])?;
+/// let bbox = ((-1.0,-1.0),(1.0,1.0));
+/// for (p,v,_) in db.query_source(&bbox, 2)? {
+///   println!["{:?} {} at {} in source 2", p, v.value, v.offset];
+/// }
+/// db.purge_source(1)?;
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+///
+/// The source id and offset take 12 bytes in front of the value.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Sourced<V> {
+  pub source: u32,
+  pub offset: u64,
+  pub value: V
+}
+
+impl<V> Sourced<V> {
+  pub fn new (source: u32, offset: u64, value: V) -> Self {
+    Self { source, offset, value }
+  }
+}
+
+impl<V> CountBytes for Sourced<V> where V: CountBytes {
+  fn count_bytes (&self) -> usize {
+    12 + self.value.count_bytes()
+  }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    if buf.len() < 12 { bail!["buffer too small for source in count"] }
+    Ok(12 + V::count_from_bytes(&buf[12..])?)
+  }
+}
+
+impl<V> ToBytes for Sourced<V> where V: ToBytes+CountBytes {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    let mut buf = vec![0u8;self.count_bytes()];
+    self.write_bytes(&mut buf)?;
+    Ok(buf)
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    if dst.len() < 12 { bail!["dst buffer too small for source"] }
+    dst[0..4].copy_from_slice(&self.source.to_be_bytes());
+    dst[4..12].copy_from_slice(&self.offset.to_be_bytes());
+    Ok(12 + self.value.write_bytes(&mut dst[12..])?)
+  }
+}
+
+impl<V> FromBytes for Sourced<V> where V: FromBytes {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    if src.len() < 12 { bail!["buffer too small while loading source"] }
+    let mut source = [0u8;4];
+    source.copy_from_slice(&src[0..4]);
+    let mut offset = [0u8;8];
+    offset.copy_from_slice(&src[4..12]);
+    let (size,value) = V::from_bytes(&src[12..])?;
+    Ok((12 + size, Self {
+      source: u32::from_be_bytes(source),
+      offset: u64::from_be_bytes(offset),
+      value
+    }))
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  // Call `f` with every live record in staging and in the trees.
+  pub(crate) fn for_each_record<F> (&mut self, mut f: F) -> Result<(),Error>
+  where F: FnMut(&P,&V,Location) -> Result<(),Error> {
+    self.staging.for_each(|p,v,loc| f(p,v,loc))?;
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      let blocks = tree.blocks()?;
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      for offset in blocks {
+        for (p,v,loc) in dstore.list(offset)? {
+          if !deletes.contains(&loc) { f(&p,&v,loc)? }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,Sourced<V>> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query for the records from `source` that intersect `bbox`.
+  pub fn query_source (&mut self, bbox: &P::Bounds, source: u32)
+  -> Result<Vec<(P,Sourced<V>,Location)>,Error> {
+    let mut results = vec![];
+    for result in self.query(bbox)? {
+      let row = result?;
+      if row.1.source == source { results.push(row) }
+    }
+    Ok(results)
+  }
+  /// Delete every record from `source` in a single batch, returning the
+  /// number of records deleted.
+  ///
+  /// This reads every record in the database to find them.
+  pub fn purge_source (&mut self, source: u32) -> Result<usize,Error> {
+    let mut deletes = vec![];
+    self.for_each_record(|_,v,loc| {
+      if v.source == source { deletes.push(Row::Delete(loc)) }
+      Ok(())
+    })?;
+    if !deletes.is_empty() {
+      self.batch(&deletes)?;
+    }
+    Ok(deletes.len())
+  }
+  /// Count the live records from each source.
+  pub fn sources (&mut self) -> Result<BTreeMap<u32,u64>,Error> {
+    let mut counts = BTreeMap::new();
+    self.for_each_record(|_,v,_| {
+      *counts.entry(v.source).or_insert(0) += 1;
+      Ok(())
+    })?;
+    Ok(counts)
+  }
+}
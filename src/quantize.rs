@@ -0,0 +1,78 @@
+use crate::{DB,Point,Value};
+use failure::{Error,ensure,bail};
+use random_access_storage::RandomAccess;
+pub use crate::point::Quantize;
+
+impl Quantize {
+  // check the quantization of every dimension of `P`
+  pub(crate) fn check<P> (quantize: &[Quantize]) -> Result<(),Error>
+  where P: Point {
+    ensure![quantize.len() == P::dim(),
+      "expected {} quantizations (one per dimension), found {}",
+      P::dim(), quantize.len()];
+    for q in quantize.iter() {
+      ensure![q.scale.is_finite() && q.scale > 0.0 && q.offset.is_finite(),
+        "quantization scale must be positive and finite with a finite \
+        offset, found scale {} and offset {}", q.scale, q.offset];
+    }
+    Ok(())
+  }
+}
+
+// points of data blocks are serialized as usual, or as the i32 steps of each
+// coordinate with Setup::quantize()
+
+pub(crate) fn point_size<P> (quantize: Option<&[Quantize]>, p: &P) -> usize
+where P: Point {
+  match quantize {
+    Some(_) => p.count_quantized(),
+    None => p.count_bytes()
+  }
+}
+
+pub(crate) fn write_point<P> (quantize: Option<&[Quantize]>, p: &P,
+dst: &mut [u8]) -> Result<usize,Error> where P: Point {
+  match quantize {
+    Some(q) => p.write_quantized(q, dst),
+    None => Ok(p.write_bytes(dst)?)
+  }
+}
+
+pub(crate) fn read_point<P> (quantize: Option<&[Quantize]>, buf: &[u8])
+-> Result<(usize,P),Error> where P: Point {
+  match quantize {
+    Some(q) => P::read_quantized(buf, q),
+    None => Ok(P::from_bytes(buf)?)
+  }
+}
+
+pub(crate) fn count_point<P> (quantize: Option<&[Quantize]>, buf: &[u8])
+-> Result<usize,Error> where P: Point {
+  match quantize {
+    Some(q) => Ok(P::read_quantized(buf, q)?.0),
+    None => Ok(P::count_from_bytes(buf)?)
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Quantization of each dimension stored in the `meta` store, or `None`
+  /// if coordinates are stored at full precision.
+  pub fn quantization (&self) -> Option<&[Quantize]> {
+    self.quantize.as_deref()
+  }
+  // round `p` to the nearest point that its quantized form reads back as
+  pub(crate) fn quantized (&self, i: usize, p: &P) -> Result<P,Error> {
+    let q = match &self.quantize {
+      Some(q) => q,
+      None => return Ok(*p)
+    };
+    let mut buf = vec![0u8;p.count_quantized()];
+    match p.write_quantized(q, &mut buf) {
+      Ok(_) => Ok(P::read_quantized(&buf, q)?.1),
+      Err(e) => bail!["row {}: {}", i, e]
+    }
+  }
+}
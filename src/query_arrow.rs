@@ -0,0 +1,194 @@
+use crate::{DB,Point,Value,QueryIterator,point::Scalar};
+use ::arrow::array::{ArrayRef,PrimitiveArray,BinaryArray};
+use ::arrow::datatypes::{ArrowPrimitiveType,DataType,Field,Schema,SchemaRef,
+  Float32Type,Float64Type,UInt8Type,UInt16Type,UInt32Type,UInt64Type,
+  Int8Type,Int16Type,Int32Type,Int64Type};
+use ::arrow::record_batch::RecordBatch;
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::sync::Arc;
+
+/// Rows in each `RecordBatch` returned by `db.query_arrow()`.
+pub const ARROW_BATCH_ROWS: usize = 8192;
+
+/// Scalar coordinate or value type with a matching arrow primitive type.
+pub trait ArrowScalar: Scalar {
+  type Arrow: ArrowPrimitiveType<Native=Self>;
+}
+
+macro_rules! impl_arrow_scalar {
+  ($(($T:ty,$A:ty)),+) => {
+    $(impl ArrowScalar for $T { type Arrow = $A; })+
+  }
+}
+
+impl_arrow_scalar![(f32,Float32Type),(f64,Float64Type),
+  (u8,UInt8Type),(u16,UInt16Type),(u32,UInt32Type),(u64,UInt64Type),
+  (i8,Int8Type),(i16,Int16Type),(i32,Int32Type),(i64,Int64Type)];
+
+/// Element of a point as arrow columns: one column for a scalar, or a `_min`
+/// and a `_max` column for an interval.
+pub trait ArrowCoord: Copy+Sized {
+  fn arrow_fields (name: &str) -> Vec<Field>;
+  fn arrow_columns (coords: &[Self]) -> Vec<ArrayRef>;
+}
+
+impl<T> ArrowCoord for T where T: ArrowScalar {
+  fn arrow_fields (name: &str) -> Vec<Field> {
+    vec![Field::new(name, T::Arrow::DATA_TYPE, false)]
+  }
+  fn arrow_columns (coords: &[Self]) -> Vec<ArrayRef> {
+    let column = PrimitiveArray::<T::Arrow>::from_iter_values(
+      coords.iter().copied()
+    );
+    vec![Arc::new(column)]
+  }
+}
+
+impl<T> ArrowCoord for (T,T) where T: ArrowScalar {
+  fn arrow_fields (name: &str) -> Vec<Field> {
+    vec![
+      Field::new(&format!["{}_min",name], T::Arrow::DATA_TYPE, false),
+      Field::new(&format!["{}_max",name], T::Arrow::DATA_TYPE, false)
+    ]
+  }
+  fn arrow_columns (coords: &[Self]) -> Vec<ArrayRef> {
+    let min = PrimitiveArray::<T::Arrow>::from_iter_values(
+      coords.iter().map(|c| c.0)
+    );
+    let max = PrimitiveArray::<T::Arrow>::from_iter_values(
+      coords.iter().map(|c| c.1)
+    );
+    vec![Arc::new(min), Arc::new(max)]
+  }
+}
+
+/// Point that can be written as arrow columns, named `dim0`, `dim1` and so
+/// on, or `dim0_min` and `dim0_max` for interval dimensions. Implemented for
+/// the built-in tuple points.
+pub trait ArrowPoint: Point {
+  fn arrow_fields () -> Vec<Field>;
+  fn arrow_columns (points: &[Self]) -> Vec<ArrayRef>;
+}
+
+macro_rules! impl_arrow_point {
+  ($(($($T:tt),+),($($i:tt),+));+) => {
+    $(impl<$($T),+> ArrowPoint for ($($T),+)
+    where ($($T),+): Point, $($T: ArrowCoord),+ {
+      fn arrow_fields () -> Vec<Field> {
+        let mut fields = vec![];
+        $(fields.extend($T::arrow_fields(&format!["dim{}",$i]));)+
+        fields
+      }
+      fn arrow_columns (points: &[Self]) -> Vec<ArrayRef> {
+        let mut columns = vec![];
+        $(columns.extend($T::arrow_columns(
+          &points.iter().map(|p| p.$i).collect::<Vec<_>>()
+        ));)+
+        columns
+      }
+    })+
+  }
+}
+
+impl_arrow_point![
+  (A,B),(0,1);
+  (A,B,C),(0,1,2);
+  (A,B,C,D),(0,1,2,3);
+  (A,B,C,D,E),(0,1,2,3,4);
+  (A,B,C,D,E,F),(0,1,2,3,4,5);
+  (A,B,C,D,E,F,G),(0,1,2,3,4,5,6);
+  (A,B,C,D,E,F,G,H),(0,1,2,3,4,5,6,7)
+];
+
+/// Value that can be written as arrow columns. Implemented for the scalar
+/// types and `Vec<u8>` as a single `value` column.
+pub trait ArrowValue: Value {
+  fn arrow_fields () -> Vec<Field>;
+  fn arrow_columns (values: &[Self]) -> Vec<ArrayRef>;
+}
+
+impl<T> ArrowValue for T where T: ArrowScalar+Value {
+  fn arrow_fields () -> Vec<Field> {
+    vec![Field::new("value", T::Arrow::DATA_TYPE, false)]
+  }
+  fn arrow_columns (values: &[Self]) -> Vec<ArrayRef> {
+    let column = PrimitiveArray::<T::Arrow>::from_iter_values(
+      values.iter().copied()
+    );
+    vec![Arc::new(column)]
+  }
+}
+
+impl ArrowValue for Vec<u8> {
+  fn arrow_fields () -> Vec<Field> {
+    vec![Field::new("value", DataType::Binary, false)]
+  }
+  fn arrow_columns (values: &[Self]) -> Vec<ArrayRef> {
+    vec![Arc::new(BinaryArray::from_iter_values(values.iter()))]
+  }
+}
+
+/// Iterator over query results as arrow `RecordBatch`es of up to
+/// `ARROW_BATCH_ROWS` rows, returned by `db.query_arrow()`.
+pub struct ArrowBatches<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: ArrowPoint, V: ArrowValue {
+  iter: QueryIterator<'b,S,P,V>,
+  schema: SchemaRef,
+  points: Vec<P>,
+  values: Vec<V>
+}
+
+impl<'b,S,P,V> ArrowBatches<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: ArrowPoint, V: ArrowValue {
+  /// Arrow schema of every batch: the point columns then the value columns.
+  pub fn schema (&self) -> SchemaRef {
+    Arc::clone(&self.schema)
+  }
+  fn flush (&mut self) -> Result<RecordBatch,Error> {
+    let mut columns = P::arrow_columns(&self.points);
+    columns.extend(V::arrow_columns(&self.values));
+    self.points.clear();
+    self.values.clear();
+    Ok(RecordBatch::try_new(Arc::clone(&self.schema), columns)?)
+  }
+}
+
+impl<'b,S,P,V> Iterator for ArrowBatches<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: ArrowPoint, V: ArrowValue {
+  type Item = Result<RecordBatch,Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    while self.points.len() < ARROW_BATCH_ROWS {
+      match self.iter.next() {
+        Some(Ok((p,v,_))) => {
+          self.points.push(p);
+          self.values.push(v);
+        },
+        Some(Err(e)) => return Some(Err(e)),
+        None => break
+      }
+    }
+    if self.points.is_empty() { return None }
+    Some(self.flush())
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: ArrowPoint, V: ArrowValue {
+  /// Query for the records that intersect `bbox` as arrow `RecordBatch`es
+  /// with a column per coordinate and per value field, to hand results to
+  /// columnar engines. Requires the `arrow` feature.
+  pub fn query_arrow<'b> (&mut self, bbox: &'b P::Bounds)
+  -> Result<ArrowBatches<'b,S,P,V>,Error> {
+    let mut fields = P::arrow_fields();
+    fields.extend(V::arrow_fields());
+    Ok(ArrowBatches {
+      iter: self.query(bbox)?,
+      schema: Arc::new(Schema::new(fields)),
+      points: Vec::with_capacity(ARROW_BATCH_ROWS),
+      values: Vec::with_capacity(ARROW_BATCH_ROWS)
+    })
+  }
+}
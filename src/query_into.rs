@@ -0,0 +1,50 @@
+use crate::{DB,Setup,Point,Value,Location};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::sync::mpsc::SyncSender;
+use std::thread::{self,JoinHandle};
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>+'static,
+U: (Fn(&str) -> Result<S,Error>)+Clone+Send+'static,
+P: Point+Send+'static, V: Value+Send, P::Bounds: Send+'static {
+  /// Run a query for `bbox` on a new thread and send each result into
+  /// `sender`, so that the consumer can decode or render results while the
+  /// next ones are read. Create the channel with `sync_channel()` from
+  /// `std::sync::mpsc`: the query waits whenever the channel is full.
+  ///
+  /// The database and its iterators can not move between threads, so the
+  /// stores are synced with `db.sync()` and opened again on the thread with
+  /// the storage function and settings of this database. The storage
+  /// function must be `Send` and `Clone`, like the one returned by
+  /// `disk_storage()`, which rules out `memory_storage()`. An error opening
+  /// the stores or reading a block is sent into the channel and ends the
+  /// query, as does dropping the receiver.
+  ///
+  /// Wait for the returned handle before the next `db.batch()`, or the query
+  /// may see some of the stores written by the batch.
+  pub fn query_into (&mut self, bbox: &P::Bounds,
+  sender: SyncSender<Result<(P,V,Location),Error>>)
+  -> Result<JoinHandle<()>,Error> {
+    self.sync()?;
+    let open_store = self.open_store.clone();
+    let fields = self.fields.clone();
+    let bbox = *bbox;
+    Ok(thread::spawn(move || {
+      let mut setup = Setup::new(open_store);
+      setup.fields = fields;
+      let mut db: DB<S,U,P,V> = match setup.build() {
+        Ok(db) => db,
+        Err(err) => { let _ = sender.send(Err(err)); return }
+      };
+      let results = match db.query(&bbox) {
+        Ok(results) => results,
+        Err(err) => { let _ = sender.send(Err(err)); return }
+      };
+      for result in results {
+        let failed = result.is_err();
+        if sender.send(result).is_err() || failed { return }
+      }
+    }))
+  }
+}
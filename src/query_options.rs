@@ -0,0 +1,170 @@
+use crate::Value;
+use desert::ToBytes;
+use failure::{Error,bail};
+use std::any::Any;
+use std::rc::Rc;
+
+/// Options for a single query, passed to `db.query_with()`.
+///
+/// ```rust,no_run
+/// # use eyros::{DB,QueryOptions};
+/// # use failure::Error;
+/// # use std::path::PathBuf;
+/// # use random_access_disk::RandomAccessDisk;
+/// # fn main () -> Result<(),Error> {
+/// # let mut db: DB<_,_,((f32,f32),(f32,f32)),u32> = DB::open(storage)?;
+/// let bbox = ((-180.0,-90.0),(180.0,90.0));
+/// let opts = QueryOptions::new().interleave(true).limit(1000);
+/// for result in db.query_with(&bbox, &opts)? {
+///   let (point,value,location) = result?;
+///   // ...
+/// }
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+#[derive(Clone,Debug,Default)]
+pub struct QueryOptions {
+  pub interleave: bool,
+  pub max_depth: Option<usize>,
+  pub offset: usize,
+  pub limit: Option<usize>,
+  pub memory_budget: Option<usize>,
+  pub partitions: Option<u64>,
+  // LatestBy<V> and DedupBy<V> for the value type of the query
+  pub(crate) latest: Option<Rc<dyn Any>>,
+  pub(crate) dedup_by: Option<Rc<dyn Any>>
+}
+
+// id and time extractors from QueryOptions::latest_by()
+pub(crate) struct LatestBy<V> {
+  pub id: Box<dyn Fn(&V) -> Result<Vec<u8>,Error>>,
+  pub newer: Box<dyn Fn(&V,&V) -> bool>
+}
+
+// key extractor from QueryOptions::dedup_by()
+pub(crate) type DedupBy<V> = Box<dyn Fn(&V) -> Result<Vec<u8>,Error>>;
+
+impl QueryOptions {
+  pub fn new () -> Self {
+    Self::default()
+  }
+  /// Return results in a spatially representative order instead of walking
+  /// each tree depth-first, so that streaming consumers such as progressive
+  /// map renderers see results from across the whole bounding box early.
+  ///
+  /// Each tree reads every matching branch block before its first data block
+  /// and then visits data blocks in a strided order. The staging area and the
+  /// trees take turns returning results in either mode.
+  pub fn interleave (mut self, enabled: bool) -> Self {
+    self.interleave = enabled;
+    self
+  }
+  /// Stop descending after `levels` branch levels of each tree and summarize
+  /// the records below each branch at that depth instead of returning them,
+  /// for coarse-to-fine progressive loading. Use `db.query_summary()` to run
+  /// a depth-bounded query: `db.query_with()` rejects this option.
+  pub fn max_depth (mut self, levels: usize) -> Self {
+    self.max_depth = Some(levels);
+    self
+  }
+  /// Skip the first `n` results.
+  pub fn offset (mut self, n: usize) -> Self {
+    self.offset = n;
+    self
+  }
+  /// Return at most `n` results. The query stops reading blocks once `n`
+  /// results have been returned, instead of leaving a partly consumed
+  /// iterator behind as `.take(n)` does.
+  ///
+  /// Results are returned in traversal order, so pages from `offset()` and
+  /// `limit()` are only stable while the database is not written to.
+  pub fn limit (mut self, n: usize) -> Self {
+    self.limit = Some(n);
+    self
+  }
+  /// Keep the work queued by the query for each tree under about `bytes`:
+  /// the branches and data blocks left to visit, prefetched branches and the
+  /// rows of the current data block. Queries over huge regions otherwise
+  /// queue every matching branch and data block, especially with
+  /// `interleave()`, which then starts returning rows from the data blocks
+  /// found so far before the whole tree has been walked.
+  ///
+  /// The keys kept by `dedup_by()`, `latest_by()` and
+  /// `Setup::dedup_results()` are not covered by the budget.
+  pub fn memory_budget (mut self, bytes: usize) -> Self {
+    self.memory_budget = Some(bytes);
+    self
+  }
+  /// Return only records in the partitions set in `mask`, where bit `k`
+  /// stands for the partition key `k` from `Setup::partition()`. Subtrees
+  /// are skipped during traversal when the partition mask in their parent
+  /// branch shares no bits with `mask`, so excluded partitions cost little
+  /// to leave out once they have been merged into trees. Records in the
+  /// staging area and in trees written without masks are filtered as they
+  /// are read.
+  pub fn partitions (mut self, mask: u64) -> Self {
+    self.partitions = Some(mask);
+    self
+  }
+  /// Return only the latest record for each id, for datasets where the same
+  /// entity is inserted again with a newer timestamp instead of replacing the
+  /// old record. `id` extracts the id of an entity from a value and `time`
+  /// extracts the time to compare. Of records with the same id and time, the
+  /// first one found is kept.
+  ///
+  /// Only records inside the bounding box are compared, so an older record
+  /// inside the box is returned when the newer one is outside of it.
+  ///
+  /// Every result is read before the first one is returned, keeping the
+  /// latest record for each id seen so far in memory. Results are returned in
+  /// the order their ids were first found, and `offset()` and `limit()` apply
+  /// to the latest records.
+  pub fn latest_by<V,K,T,F,G> (mut self, id: F, time: G) -> Self
+  where V: Value, K: ToBytes, T: PartialOrd,
+  F: Fn(&V) -> K + 'static, G: Fn(&V) -> T + 'static {
+    let latest: LatestBy<V> = LatestBy {
+      id: Box::new(move |v| id(v).to_bytes()),
+      newer: Box::new(move |a,b| time(a) > time(b))
+    };
+    self.latest = Some(Rc::new(latest));
+    self
+  }
+  /// Skip results whose value has the same key as a result that was already
+  /// returned, where `key` extracts the key from a value, such as the id of
+  /// an interval feature that was split across several records. The key of
+  /// every result is kept in memory until the iterator is dropped.
+  ///
+  /// Unlike `Setup::dedup_results()`, which only skips identical records,
+  /// the first record found for each key is returned even when later ones
+  /// have other points or values.
+  pub fn dedup_by<V,K,F> (mut self, key: F) -> Self
+  where V: Value, K: ToBytes, F: Fn(&V) -> K + 'static {
+    let dedup_by: DedupBy<V> = Box::new(move |v| key(v).to_bytes());
+    self.dedup_by = Some(Rc::new(dedup_by));
+    self
+  }
+  pub(crate) fn latest<V> (&self) -> Result<Option<Rc<LatestBy<V>>>,Error>
+  where V: Value {
+    downcast(&self.latest, "latest_by")
+  }
+  pub(crate) fn dedup_key<V> (&self) -> Result<Option<Rc<DedupBy<V>>>,Error>
+  where V: Value {
+    downcast(&self.dedup_by, "dedup_by")
+  }
+}
+
+fn downcast<T> (option: &Option<Rc<dyn Any>>, name: &str)
+-> Result<Option<Rc<T>>,Error> where T: 'static {
+  match option {
+    None => Ok(None),
+    Some(x) => match Rc::clone(x).downcast::<T>() {
+      Ok(x) => Ok(Some(x)),
+      Err(_) => bail!["QueryOptions::{}() extracts from a different value \
+        type than the database has", name]
+    }
+  }
+}
@@ -0,0 +1,125 @@
+use crate::{DB,Point,Value,Location,DeleteSet,Tree,data::DataStore};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Query results that borrow each value from the data block cache instead of
+/// cloning it, returned by `db.query_ref()`.
+///
+/// Each result borrows the iterator, so this is not an `Iterator`: call
+/// `next_ref()` in a `while let` loop and clone any value that needs to
+/// outlive the next call:
+///
+/// ```rust,no_run
+/// use eyros::DB;
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// # fn main () -> Result<(),Error> {
+/// let mut db: DB<_,_,(f32,f32),Vec<u8>> = DB::open(storage)?;
+/// let bbox = ((-10.0,-10.0),(10.0,10.0));
+/// let mut results = db.query_ref(&bbox)?;
+/// let mut bytes = 0;
+/// while let Some(result) = results.next_ref() {
+///   let (_point,value,_location) = result?;
+///   bytes += value.len();
+/// }
+/// println!["{} bytes", bytes];
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+///
+/// Staged records are copied once when the query starts. A data block stays
+/// in memory while its results are read, even if it is evicted from the
+/// cache.
+pub struct QueryRef<'b,S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  bbox: &'b P::Bounds,
+  trees: Vec<Rc<RefCell<Tree<S,P,V>>>>,
+  data_store: Rc<RefCell<DataStore<S,P,V>>>,
+  deletes: Rc<RefCell<DeleteSet>>,
+  // data blocks of the current tree left to read
+  blocks: Vec<u64>,
+  // rows of the current data block or of staging, and the indexes of the
+  // matching rows left to return
+  rows: Rc<Vec<(P,V,Location)>>,
+  matches: std::vec::IntoIter<usize>
+}
+
+impl<'b,S,P,V> QueryRef<'b,S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  /// Next result, which borrows its value from the iterator.
+  pub fn next_ref (&mut self) -> Option<Result<(P,&V,Location),Error>> {
+    match self.advance() {
+      Ok(Some(i)) => {
+        let (point,value,location) = &self.rows[i];
+        Some(Ok((*point,value,*location)))
+      },
+      Ok(None) => None,
+      Err(err) => Some(Err(err))
+    }
+  }
+  // move to the next result that was not deleted and return its index in
+  // `self.rows`
+  fn advance (&mut self) -> Result<Option<usize>,Error> {
+    loop {
+      while let Some(i) = self.matches.next() {
+        let loc = self.rows[i].2;
+        if self.deletes.try_borrow()?.contains(&loc) { continue }
+        let dstore = self.data_store.try_borrow()?;
+        if loc.0 > 0 && dstore.is_deleted(loc.0-1, loc.1) { continue }
+        return Ok(Some(i));
+      }
+      if let Some(offset) = self.blocks.pop() {
+        let (rows,matches) = self.data_store.try_borrow_mut()?
+          .query_shared(offset, self.bbox)?;
+        self.rows = rows;
+        self.matches = matches.into_iter();
+        continue;
+      }
+      match self.trees.pop() {
+        Some(tree) => {
+          let mut blocks = tree.try_borrow_mut()?.query_blocks(self.bbox)?;
+          blocks.reverse();
+          self.blocks = blocks;
+        },
+        None => return Ok(None)
+      }
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query the database like `db.query()`, but borrow each value from the
+  /// data block cache instead of cloning it, which saves copying large
+  /// values such as `Vec<u8>` when they are only inspected. See `QueryRef`.
+  pub fn query_ref<'b> (&mut self, bbox: &'b P::Bounds)
+  -> Result<QueryRef<'b,S,P,V>,Error> {
+    let staged = self.staging.query(bbox)?
+      .collect::<Result<Vec<(P,V,Location)>,Error>>()?;
+    let mut trees = vec![];
+    for tree in self.trees.iter().rev() {
+      if !tree.try_borrow_mut()?.is_empty()? {
+        trees.push(Rc::clone(tree));
+      }
+    }
+    Ok(QueryRef {
+      bbox,
+      trees,
+      data_store: Rc::clone(&self.data_store),
+      deletes: Rc::clone(&self.staging.delete_set),
+      blocks: vec![],
+      matches: (0..staged.len()).collect::<Vec<usize>>().into_iter(),
+      rows: Rc::new(staged)
+    })
+  }
+}
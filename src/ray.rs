@@ -0,0 +1,154 @@
+use crate::{DB,Point,Value,Location,NearestCoord};
+use failure::{Error,ensure};
+use random_access_storage::RandomAccess;
+use std::collections::HashSet;
+
+/// Number of boxes that cover the segment of `db.query_segment()`.
+pub const SEGMENT_PIECES: usize = 32;
+
+/// Point that can be tested against a line segment, for
+/// `db.query_segment()` and `db.query_ray()`. Implemented for two and three
+/// dimensional points whose coordinates are `f32` or `f64` scalars or
+/// intervals.
+pub trait SegmentQuery: Point {
+  /// Lower and upper bound of the point in dimension `i`.
+  fn span_at (&self, i: usize) -> (f64,f64);
+  /// Bounds with the corners `min` and `max`, which have a coordinate for
+  /// each dimension.
+  fn bounds_from (min: &[f64], max: &[f64]) -> Self::Bounds;
+  /// Lower and upper bound of `bbox` in dimension `i`.
+  fn bounds_span_at (bbox: &Self::Bounds, i: usize) -> (f64,f64);
+}
+
+impl<A,B> SegmentQuery for (A,B) where
+A: NearestCoord, B: NearestCoord,
+(A,B): Point<Bounds=((A::Scalar,B::Scalar),(A::Scalar,B::Scalar))> {
+  fn span_at (&self, i: usize) -> (f64,f64) {
+    match i { 0 => self.0.span(), _ => self.1.span() }
+  }
+  fn bounds_from (min: &[f64], max: &[f64]) -> Self::Bounds {
+    (
+      (A::scalar(min[0]),B::scalar(min[1])),
+      (A::scalar(max[0]),B::scalar(max[1]))
+    )
+  }
+  fn bounds_span_at (bbox: &Self::Bounds, i: usize) -> (f64,f64) {
+    match i {
+      0 => (A::value((bbox.0).0), A::value((bbox.1).0)),
+      _ => (B::value((bbox.0).1), B::value((bbox.1).1))
+    }
+  }
+}
+
+impl<A,B,C> SegmentQuery for (A,B,C) where
+A: NearestCoord, B: NearestCoord, C: NearestCoord,
+(A,B,C): Point<Bounds=(
+  (A::Scalar,B::Scalar,C::Scalar),
+  (A::Scalar,B::Scalar,C::Scalar)
+)> {
+  fn span_at (&self, i: usize) -> (f64,f64) {
+    match i { 0 => self.0.span(), 1 => self.1.span(), _ => self.2.span() }
+  }
+  fn bounds_from (min: &[f64], max: &[f64]) -> Self::Bounds {
+    (
+      (A::scalar(min[0]),B::scalar(min[1]),C::scalar(min[2])),
+      (A::scalar(max[0]),B::scalar(max[1]),C::scalar(max[2]))
+    )
+  }
+  fn bounds_span_at (bbox: &Self::Bounds, i: usize) -> (f64,f64) {
+    match i {
+      0 => (A::value((bbox.0).0), A::value((bbox.1).0)),
+      1 => (B::value((bbox.0).1), B::value((bbox.1).1)),
+      _ => (C::value((bbox.0).2), C::value((bbox.1).2))
+    }
+  }
+}
+
+// fraction of the way from `from` to `to` where the segment enters the bounds
+// of `p`, or None if it misses them
+fn enter<P> (p: &P, from: &[f64], to: &[f64]) -> Option<f64>
+where P: SegmentQuery {
+  let (mut tmin, mut tmax) = (0.0f64, 1.0f64);
+  for (i,(a,b)) in from.iter().zip(to.iter()).enumerate() {
+    let (lo,hi) = p.span_at(i);
+    let d = b - a;
+    if d == 0.0 {
+      if *a < lo || *a > hi { return None }
+      continue;
+    }
+    let (t0,t1) = ((lo-a)/d, (hi-a)/d);
+    tmin = tmin.max(t0.min(t1));
+    tmax = tmax.min(t0.max(t1));
+    if tmin > tmax { return None }
+  }
+  Some(tmin)
+}
+
+// grow `x` slightly in the direction of `sign` so that boxes converted to
+// lower precision scalars still cover the query shape
+pub(crate) fn pad (x: f64, sign: f64) -> f64 {
+  x + sign * (x.abs() + 1.0) * 1e-6
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: SegmentQuery, V: Value {
+  /// Query for the records whose bounds intersect the line segment from
+  /// `from` to `to`, which have a coordinate for each dimension. Results are
+  /// sorted by where the segment enters them, so the first result is the
+  /// first record in the way for line-of-sight checks.
+  ///
+  /// The segment is covered with `SEGMENT_PIECES` boxes that are traversed
+  /// together like `db.query_multi()`, so branches beside a diagonal segment
+  /// are skipped instead of reading everything in the bounding box of the
+  /// whole segment. Records in the boxes are then tested against the segment
+  /// itself.
+  pub fn query_segment (&mut self, from: &[f64], to: &[f64])
+  -> Result<Vec<(P,V,Location)>,Error> {
+    let dim = P::dim();
+    ensure![from.len() == dim && to.len() == dim,
+      "query_segment() expects {} coordinates for each end", dim];
+    ensure![from.iter().chain(to.iter()).all(|x| x.is_finite()),
+      "query_segment() expects finite coordinates"];
+    let pieces = if from == to { 1 } else { SEGMENT_PIECES };
+    let mut boxes = Vec::with_capacity(pieces);
+    let (mut min, mut max) = (vec![0.0;dim], vec![0.0;dim]);
+    for k in 0..pieces {
+      let t0 = k as f64 / pieces as f64;
+      let t1 = (k+1) as f64 / pieces as f64;
+      for (i,(f,t)) in from.iter().zip(to.iter()).enumerate() {
+        let (a,b) = (f + (t-f)*t0, f + (t-f)*t1);
+        min[i] = pad(a.min(b), -1.0);
+        max[i] = pad(a.max(b), 1.0);
+      }
+      boxes.push(P::bounds_from(&min, &max));
+    }
+    let mut seen: HashSet<Location> = HashSet::new();
+    let mut results = vec![];
+    for (_,p,v,loc) in self.query_multi(&boxes)? {
+      if !seen.insert(loc) { continue }
+      if let Some(t) = enter(&p, from, to) {
+        results.push((t,(p,v,loc)));
+      }
+    }
+    results.sort_by(|a,b| a.0.total_cmp(&b.0));
+    Ok(results.into_iter().map(|(_,r)| r).collect())
+  }
+  /// Query for the records whose bounds intersect the ray from `origin` in
+  /// `direction`, up to a distance of `length`, like `db.query_segment()`.
+  pub fn query_ray (&mut self, origin: &[f64], direction: &[f64],
+  length: f64) -> Result<Vec<(P,V,Location)>,Error> {
+    ensure![origin.len() == direction.len(),
+      "query_ray() expects as many coordinates for the direction as for the \
+      origin"];
+    ensure![length.is_finite() && length >= 0.0,
+      "query_ray() expects a finite length of 0 or more"];
+    let norm = direction.iter().map(|d| d*d).sum::<f64>().sqrt();
+    ensure![norm > 0.0, "query_ray() expects a direction other than zero"];
+    let to: Vec<f64> = origin.iter().zip(direction.iter())
+      .map(|(o,d)| o + d/norm*length)
+      .collect();
+    self.query_segment(origin, &to)
+  }
+}
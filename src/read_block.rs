@@ -10,6 +10,66 @@ where S: RandomAccess<Error=Error> {
   let fbuf: Vec<u8> = store.read(offset, size_guess)?;
   ensure_eq![fbuf.len() as u64, size_guess, "requested {} bytes, received {}",
     size_guess, fbuf.len()];
+  finish_block(store, offset, max_size, fbuf)
+}
+
+/// Read the length-prefixed blocks at each of `offsets` like `read_block()`,
+/// with the first `guess` bytes of every block fetched by a single
+/// `read_many()` so that neighbouring blocks share reads.
+pub fn read_blocks<S> (store: &mut S, offsets: &[u64], max_size: u64,
+guess: u64) -> Result<Vec<Vec<u8>>,Error>
+where S: RandomAccess<Error=Error> {
+  let mut ranges = Vec::with_capacity(offsets.len());
+  for offset in offsets.iter() {
+    let size_guess = guess.min(max_size - (*offset).min(max_size));
+    if size_guess < 4 { bail!["block too small for length field"] }
+    ranges.push((*offset,size_guess));
+  }
+  let fbufs = read_many(store, &ranges)?;
+  offsets.iter().zip(fbufs).map(|(offset,fbuf)| {
+    finish_block(store, *offset, max_size, fbuf)
+  }).collect()
+}
+
+/// Read each `(offset,length)` range of `ranges` from `store`, returning the
+/// bytes in the same order. Ranges that overlap or touch are coalesced into a
+/// single read, so a batch of nearby reads costs fewer syscalls or range
+/// requests than reading each range in turn.
+pub fn read_many<S> (store: &mut S, ranges: &[(u64,u64)])
+-> Result<Vec<Vec<u8>>,Error>
+where S: RandomAccess<Error=Error> {
+  let mut order: Vec<usize> = (0..ranges.len()).collect();
+  order.sort_unstable_by_key(|i| ranges[*i].0);
+  let mut results = vec![vec![];ranges.len()];
+  let mut i = 0;
+  while i < order.len() {
+    // extend the span over every following range that starts inside it
+    let start = ranges[order[i]].0;
+    let mut end = start + ranges[order[i]].1;
+    let mut j = i+1;
+    while j < order.len() && ranges[order[j]].0 <= end {
+      end = end.max(ranges[order[j]].0 + ranges[order[j]].1);
+      j += 1;
+    }
+    let span = store.read(start, end-start)?;
+    ensure_eq![span.len() as u64, end-start, "requested {} bytes, received {}",
+      end-start, span.len()];
+    for k in order[i..j].iter() {
+      let (offset,len) = ranges[*k];
+      let a = (offset-start) as usize;
+      results[*k] = span[a..a+(len as usize)].to_vec();
+    }
+    i = j;
+  }
+  Ok(results)
+}
+
+// parse the length of the block at `offset` from its first bytes `fbuf` and
+// read the rest of the block if `fbuf` was too short
+fn finish_block<S> (store: &mut S, offset: u64, max_size: u64, fbuf: Vec<u8>)
+-> Result<Vec<u8>,Error>
+where S: RandomAccess<Error=Error> {
+  let size_guess = fbuf.len() as u64;
   let len = u32::from_be_bytes([fbuf[0],fbuf[1],fbuf[2],fbuf[3]]) as u64;
   if len < 4 {
     bail!["length field must be at least 4 (at offset {})",offset]
@@ -0,0 +1,137 @@
+//! Read the `data` and `range` stores of a database without opening a `DB`.
+//!
+//! A `Reader` does not open the meta, staging or tree stores, so it is cheap
+//! to construct and only needs the two files that hold the records. This is
+//! useful for lightweight consumers of peermaps archives and for tools like
+//! the merge example that only care about data blocks.
+//!
+//! The trees decide which data blocks are live, so a `Reader` sees every
+//! block that was ever written: records in the staging area are not visible
+//! and records from blocks that were combined during a tree merge are
+//! returned once for the original block and once for the combined block.
+//! Deleted records are skipped.
+//!
+//! ```rust,no_run
+//! use eyros::reader::Reader;
+//! use failure::Error;
+//! use random_access_disk::RandomAccessDisk;
+//! use std::path::PathBuf;
+//!
+//! type P = ((f32,f32),(f32,f32));
+//! type V = u32;
+//!
+//! fn main() -> Result<(),Error> {
+//!   let mut reader: Reader<_,P,V> = Reader::open(storage)?;
+//!   for result in reader.query(&((-0.5,-0.5),(0.5,0.5)))? {
+//!     println!("{:?}", result?);
+//!   }
+//!   Ok(())
+//! }
+//!
+//! fn storage(name:&str) -> Result<RandomAccessDisk,Error> {
+//!   let mut p = PathBuf::from("/tmp/eyros-db/");
+//!   p.push(name);
+//!   Ok(RandomAccessDisk::builder(p).build()?)
+//! }
+//! ```
+
+use crate::{Point,PointOrd,Value,Location,DataRange,DataRangeIterator};
+use crate::data::DataStore;
+use crate::blob::BlobStore;
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+
+/// Reader over the `data` and `range` stores of a database.
+pub struct Reader<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  data_store: DataStore<S,P,V>
+}
+
+impl<S,P,V> Reader<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  /// Open the `data` and `range` stores with the same storage function that
+  /// was given to `DB::open()`.
+  pub fn open<U> (open_store: U) -> Result<Self,Error>
+  where U: Fn(&str) -> Result<S,Error> {
+    Self::from_stores(open_store("data")?, open_store("range")?)
+  }
+  /// Create a reader from an already opened `data` and `range` store.
+  pub fn from_stores (data: S, range: S) -> Result<Self,Error> {
+    Ok(Self { data_store: DataStore::open(data, range, usize::MAX, 0, 1)? })
+  }
+  /// Read large values from the `blobs` store of a database written with
+  /// `Setup::inline_threshold()`. Blocks that refer to blobs fail to read
+  /// without it.
+  pub fn blobs (mut self, blobs: S) -> Self {
+    self.data_store.blobs = Some(BlobStore::open(blobs, usize::MAX));
+    self
+  }
+  /// Index of the data blocks, with one `(offset, range, len)` entry for every
+  /// block written to the `data` store.
+  pub fn ranges (&mut self) -> &mut DataRange<S,P> {
+    &mut self.data_store.range
+  }
+  /// Read the live records in the data block at `offset`.
+  pub fn block (&mut self, offset: u64)
+  -> Result<Vec<(P,V,Location)>,Error> {
+    self.data_store.list(offset)
+  }
+  /// Iterate over the records in every data block.
+  pub fn scan (&mut self) -> Result<ReaderIterator<'_,S,P,V>,Error> {
+    let entries = self.data_store.range.iter()?;
+    Ok(ReaderIterator::new(&mut self.data_store, entries, None))
+  }
+  /// Iterate over the records that intersect `bbox`. Only data blocks whose
+  /// range intersects `bbox` are read.
+  pub fn query (&mut self, bbox: &P::Bounds)
+  -> Result<ReaderIterator<'_,S,P,V>,Error> {
+    let range = P::bounds_to_range(*bbox);
+    let rbox = match <P::Range as PointOrd>::bounds(&vec![range]) {
+      Some(rbox) => rbox,
+      None => bail!["failed to calculate bounds for {:?}", bbox]
+    };
+    let entries = self.data_store.range.filter(&rbox)?;
+    Ok(ReaderIterator::new(&mut self.data_store, entries, Some(*bbox)))
+  }
+}
+
+/// Iterator of `Result<(Point,Value,Location)>` records returned by
+/// `reader.scan()` and `reader.query()`.
+pub struct ReaderIterator<'a,S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  data_store: &'a mut DataStore<S,P,V>,
+  entries: DataRangeIterator<P>,
+  bbox: Option<P::Bounds>,
+  rows: std::vec::IntoIter<(P,V,Location)>
+}
+
+impl<'a,S,P,V> ReaderIterator<'a,S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  fn new (data_store: &'a mut DataStore<S,P,V>, entries: DataRangeIterator<P>,
+  bbox: Option<P::Bounds>) -> Self {
+    Self { data_store, entries, bbox, rows: vec![].into_iter() }
+  }
+}
+
+impl<'a,S,P,V> Iterator for ReaderIterator<'a,S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  type Item = Result<(P,V,Location),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    loop {
+      for row in self.rows.by_ref() {
+        match &self.bbox {
+          Some(bbox) if !row.0.overlaps(bbox) => {},
+          _ => return Some(Ok(row))
+        }
+      }
+      let offset = match self.entries.next()? {
+        Ok((offset,_,_)) => offset,
+        Err(e) => return Some(Err(e))
+      };
+      match self.data_store.list(offset) {
+        Ok(rows) => self.rows = rows.into_iter(),
+        Err(e) => return Some(Err(e))
+      }
+    }
+  }
+}
@@ -0,0 +1,382 @@
+use crate::{DB,Point,Value,Location,QueryIterator};
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+
+/// Type of a field in a `Schema`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum FieldType {
+  Bool,
+  U32,
+  U64,
+  I32,
+  I64,
+  F32,
+  F64,
+  String,
+  Bytes
+}
+
+impl FieldType {
+  fn tag (&self) -> u8 {
+    match self {
+      FieldType::Bool => 1,
+      FieldType::U32 => 2,
+      FieldType::U64 => 3,
+      FieldType::I32 => 4,
+      FieldType::I64 => 5,
+      FieldType::F32 => 6,
+      FieldType::F64 => 7,
+      FieldType::String => 8,
+      FieldType::Bytes => 9
+    }
+  }
+  fn from_tag (tag: u8) -> Result<Self,Error> {
+    Ok(match tag {
+      1 => FieldType::Bool,
+      2 => FieldType::U32,
+      3 => FieldType::U64,
+      4 => FieldType::I32,
+      5 => FieldType::I64,
+      6 => FieldType::F32,
+      7 => FieldType::F64,
+      8 => FieldType::String,
+      9 => FieldType::Bytes,
+      _ => bail!["unknown field type {}", tag]
+    })
+  }
+}
+
+/// Value of a single field of a `Record`. `Null` is allowed for any type.
+#[derive(Clone,Debug,PartialEq)]
+pub enum Field {
+  Null,
+  Bool(bool),
+  U32(u32),
+  U64(u64),
+  I32(i32),
+  I64(i64),
+  F32(f32),
+  F64(f64),
+  String(String),
+  Bytes(Vec<u8>)
+}
+
+impl Field {
+  /// Type of the field, or `None` for `Field::Null`.
+  pub fn field_type (&self) -> Option<FieldType> {
+    Some(match self {
+      Field::Null => return None,
+      Field::Bool(_) => FieldType::Bool,
+      Field::U32(_) => FieldType::U32,
+      Field::U64(_) => FieldType::U64,
+      Field::I32(_) => FieldType::I32,
+      Field::I64(_) => FieldType::I64,
+      Field::F32(_) => FieldType::F32,
+      Field::F64(_) => FieldType::F64,
+      Field::String(_) => FieldType::String,
+      Field::Bytes(_) => FieldType::Bytes
+    })
+  }
+  // tag byte followed by the payload. the length of strings and bytes comes
+  // from the field offsets of the record
+  fn write (&self, buf: &mut Vec<u8>) {
+    buf.push(self.field_type().map(|t| t.tag()).unwrap_or(0));
+    match self {
+      Field::Null => {},
+      Field::Bool(x) => buf.push(*x as u8),
+      Field::U32(x) => buf.extend(&x.to_be_bytes()),
+      Field::U64(x) => buf.extend(&x.to_be_bytes()),
+      Field::I32(x) => buf.extend(&x.to_be_bytes()),
+      Field::I64(x) => buf.extend(&x.to_be_bytes()),
+      Field::F32(x) => buf.extend(&x.to_be_bytes()),
+      Field::F64(x) => buf.extend(&x.to_be_bytes()),
+      Field::String(x) => buf.extend(x.as_bytes()),
+      Field::Bytes(x) => buf.extend(x)
+    }
+  }
+  fn read (src: &[u8]) -> Result<Self,Error> {
+    if src.is_empty() { bail!["empty field"] }
+    if src[0] == 0 { return Ok(Field::Null) }
+    let b = &src[1..];
+    let fixed = |n: usize| -> Result<&[u8],Error> {
+      if b.len() != n { bail!["unexpected field length {}", b.len()] }
+      Ok(b)
+    };
+    Ok(match FieldType::from_tag(src[0])? {
+      FieldType::Bool => Field::Bool(fixed(1)?[0] != 0),
+      FieldType::U32 => Field::U32(u32::from_be_bytes(array(fixed(4)?))),
+      FieldType::U64 => Field::U64(u64::from_be_bytes(array(fixed(8)?))),
+      FieldType::I32 => Field::I32(i32::from_be_bytes(array(fixed(4)?))),
+      FieldType::I64 => Field::I64(i64::from_be_bytes(array(fixed(8)?))),
+      FieldType::F32 => Field::F32(f32::from_be_bytes(array(fixed(4)?))),
+      FieldType::F64 => Field::F64(f64::from_be_bytes(array(fixed(8)?))),
+      FieldType::String => Field::String(String::from_utf8(b.to_vec())?),
+      FieldType::Bytes => Field::Bytes(b.to_vec())
+    })
+  }
+}
+
+fn array<const N: usize> (src: &[u8]) -> [u8;N] {
+  let mut buf = [0u8;N];
+  buf.copy_from_slice(src);
+  buf
+}
+
+/// Names and types of the fields of the `Record` values of a database, set
+/// with `Setup::schema()` and stored in the `meta` store.
+#[derive(Clone,Debug,PartialEq,Default)]
+pub struct Schema {
+  fields: Vec<(String,FieldType)>
+}
+
+impl Schema {
+  pub fn new () -> Self {
+    Self { fields: vec![] }
+  }
+  /// Add a field named `name` after the existing fields.
+  pub fn field (mut self, name: &str, field_type: FieldType) -> Self {
+    self.fields.push((name.to_string(), field_type));
+    self
+  }
+  /// Names and types of the fields, in record order.
+  pub fn fields (&self) -> &[(String,FieldType)] {
+    &self.fields
+  }
+  /// Position of the field named `name`.
+  pub fn index (&self, name: &str) -> Option<usize> {
+    self.fields.iter().position(|(n,_)| n == name)
+  }
+  /// Positions of the fields in `names`, failing on unknown names.
+  pub fn indexes (&self, names: &[&str]) -> Result<Vec<usize>,Error> {
+    names.iter().map(|name| {
+      self.index(name).ok_or_else(|| format_err!["unknown field {}", name])
+    }).collect()
+  }
+  pub(crate) fn to_bytes (&self) -> Vec<u8> {
+    let mut buf = vec![];
+    buf.extend(&(self.fields.len() as u16).to_be_bytes());
+    for (name,field_type) in self.fields.iter() {
+      buf.push(field_type.tag());
+      buf.extend(&(name.len() as u16).to_be_bytes());
+      buf.extend(name.as_bytes());
+    }
+    buf
+  }
+  pub(crate) fn from_bytes (src: &[u8]) -> Result<Self,Error> {
+    if src.len() < 2 { bail!["schema too short"] }
+    let n = u16::from_be_bytes([src[0],src[1]]) as usize;
+    let mut fields = Vec::with_capacity(n);
+    let mut offset = 2;
+    for _ in 0..n {
+      if offset+3 > src.len() { bail!["schema too short"] }
+      let field_type = FieldType::from_tag(src[offset])?;
+      let len = u16::from_be_bytes([src[offset+1],src[offset+2]]) as usize;
+      offset += 3;
+      if offset+len > src.len() { bail!["schema too short"] }
+      let name = String::from_utf8(src[offset..offset+len].to_vec())?;
+      offset += len;
+      fields.push((name,field_type));
+    }
+    if offset != src.len() { bail!["unexpected schema length"] }
+    Ok(Self { fields })
+  }
+}
+
+/// Value made of typed fields, as described by a `Schema`.
+///
+/// A record keeps its fields serialized with the end offset of each field up
+/// front, so a query can read or `select()` a few fields without decoding the
+/// others:
+///
+/// ```rust,no_run
+/// use eyros::{DB,Setup,Row,Schema,Record,Field,FieldType};
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// type P = (f32,f32);
+///
+/// # fn main () -> Result<(),Error> {
+/// let schema = Schema::new()
+///   .field("name", FieldType::String)
+///   .field("height", FieldType::F32)
+///   .field("photo", FieldType::Bytes);
+/// let mut db: DB<_,_,P,Record> = Setup::new(storage)
+///   .schema(schema.clone())
+///   .build()?;
+/// db.batch(&[
+///   Row::Insert((0.1,0.2), Record::new(&schema, &[
+///     Field::String("oak".into()),
+///     Field::F32(21.5),
+///     Field::Bytes(vec![0;4096])
+///   ])?)
+/// ])?;
+/// for result in db.query_select(&((-1.0,-1.0),(1.0,1.0)), &["name"])? {
+///   let (p,record,_) = result?;
+///   println!["{:?} {:?}", p, record.get(0)?];
+/// }
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+#[derive(Clone,Debug,PartialEq)]
+pub struct Record {
+  // u16 number of fields, u32 end offset of each field within the field
+  // bytes, then the field bytes
+  buf: Vec<u8>
+}
+
+impl Record {
+  /// Create a record with `fields` in the order of `schema`, failing if a
+  /// field does not have the type of the schema.
+  pub fn new (schema: &Schema, fields: &[Field]) -> Result<Self,Error> {
+    if fields.len() != schema.fields.len() {
+      bail!["expected {} fields, found {}", schema.fields.len(), fields.len()];
+    }
+    for (field,(name,field_type)) in fields.iter().zip(schema.fields.iter()) {
+      match field.field_type() {
+        Some(t) if t != *field_type => {
+          bail!["expected {:?} for field {}, found {:?}", field_type, name, t]
+        },
+        _ => {}
+      }
+    }
+    Ok(Self::from_fields(fields))
+  }
+  /// Create a record from `fields` without a schema.
+  pub fn from_fields (fields: &[Field]) -> Self {
+    let mut data = vec![];
+    let mut ends = Vec::with_capacity(fields.len());
+    for field in fields.iter() {
+      field.write(&mut data);
+      ends.push(data.len() as u32);
+    }
+    let mut buf = Vec::with_capacity(2+ends.len()*4+data.len());
+    buf.extend(&(fields.len() as u16).to_be_bytes());
+    for end in ends.iter() {
+      buf.extend(&end.to_be_bytes());
+    }
+    buf.extend(data);
+    Self { buf }
+  }
+  /// Number of fields.
+  pub fn len (&self) -> usize {
+    u16::from_be_bytes([self.buf[0],self.buf[1]]) as usize
+  }
+  pub fn is_empty (&self) -> bool {
+    self.len() == 0
+  }
+  /// Decode the field at position `i`.
+  pub fn get (&self, i: usize) -> Result<Field,Error> {
+    Field::read(self.field_bytes(i)?)
+  }
+  /// Decode every field.
+  pub fn fields (&self) -> Result<Vec<Field>,Error> {
+    (0..self.len()).map(|i| self.get(i)).collect()
+  }
+  /// Record with only the fields at the positions in `indexes`, in that
+  /// order, copied without decoding them.
+  pub fn select (&self, indexes: &[usize]) -> Result<Self,Error> {
+    let mut data = vec![];
+    let mut buf = Vec::with_capacity(2+indexes.len()*4);
+    buf.extend(&(indexes.len() as u16).to_be_bytes());
+    for i in indexes.iter() {
+      data.extend_from_slice(self.field_bytes(*i)?);
+      buf.extend(&(data.len() as u32).to_be_bytes());
+    }
+    buf.extend(data);
+    Ok(Self { buf })
+  }
+  fn field_bytes (&self, i: usize) -> Result<&[u8],Error> {
+    let n = self.len();
+    if i >= n { bail!["field {} out of range for {} fields", i, n] }
+    let start = 2+n*4;
+    let end_of = |j: usize| {
+      let k = 2+j*4;
+      u32::from_be_bytes(array(&self.buf[k..k+4])) as usize
+    };
+    let begin = if i == 0 { 0 } else { end_of(i-1) };
+    Ok(&self.buf[start+begin..start+end_of(i)])
+  }
+}
+
+impl CountBytes for Record {
+  fn count_bytes (&self) -> usize {
+    self.buf.len()
+  }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    if buf.len() < 2 { bail!["buffer too small for record in count"] }
+    let n = u16::from_be_bytes([buf[0],buf[1]]) as usize;
+    if n == 0 { return Ok(2) }
+    let k = 2+(n-1)*4;
+    if buf.len() < k+4 { bail!["buffer too small for record in count"] }
+    Ok(2 + n*4 + u32::from_be_bytes(array(&buf[k..k+4])) as usize)
+  }
+}
+
+impl ToBytes for Record {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    Ok(self.buf.clone())
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    if dst.len() < self.buf.len() { bail!["dst buffer too small for record"] }
+    dst[0..self.buf.len()].copy_from_slice(&self.buf);
+    Ok(self.buf.len())
+  }
+}
+
+impl FromBytes for Record {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    let size = Self::count_from_bytes(src)?;
+    if src.len() < size { bail!["buffer too small while loading record"] }
+    Ok((size, Self { buf: src[0..size].to_vec() }))
+  }
+}
+
+/// Iterator over query results with only the selected fields of each record,
+/// returned by `db.query_select()`.
+pub struct Projection<'b,S,P> where
+S: RandomAccess<Error=Error>, P: Point {
+  iter: QueryIterator<'b,S,P,Record>,
+  indexes: Vec<usize>
+}
+
+impl<'b,S,P> Iterator for Projection<'b,S,P> where
+S: RandomAccess<Error=Error>, P: Point {
+  type Item = Result<(P,Record,Location),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    Some(self.iter.next()?.and_then(|(p,v,loc)| {
+      Ok((p, v.select(&self.indexes)?, loc))
+    }))
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Schema stored in the `meta` store, set with `Setup::schema()`.
+  pub fn schema (&self) -> Option<&Schema> {
+    self.schema.as_ref()
+  }
+}
+
+impl<S,U,P> DB<S,U,P,Record> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point {
+  /// Query for the records that intersect `bbox` with only the fields named
+  /// in `names`, in that order. Requires `Setup::schema()`.
+  pub fn query_select<'b> (&mut self, bbox: &'b P::Bounds, names: &[&str])
+  -> Result<Projection<'b,S,P>,Error> {
+    let indexes = match &self.schema {
+      Some(schema) => schema.indexes(names)?,
+      None => bail!["query_select() requires Setup::schema()"]
+    };
+    Ok(Projection { iter: self.query(bbox)?, indexes })
+  }
+}
@@ -0,0 +1,43 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+/// Repairs made to the staging stores while opening a database, returned by
+/// `db.recovery_report()`.
+///
+/// A crash in the middle of a batch can leave a partial record at the end of
+/// the staging stores. Unless `Setup::strict_open()` is set, these tail bytes
+/// are truncated when the database is opened and the complete records before
+/// them are kept.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct RecoveryReport {
+  /// Bytes truncated from the end of the `staging_inserts` store.
+  pub insert_bytes_discarded: u64,
+  /// Bytes truncated from the end of the `staging_deletes` store.
+  pub delete_bytes_discarded: u64,
+  /// Staged inserts read back from the `staging_inserts` store.
+  pub inserts_recovered: u64,
+  /// Staged deletes read back from the `staging_deletes` store.
+  pub deletes_recovered: u64
+}
+
+impl RecoveryReport {
+  /// Total bytes truncated from the staging stores.
+  pub fn bytes_discarded (&self) -> u64 {
+    self.insert_bytes_discarded + self.delete_bytes_discarded
+  }
+  /// Whether the database was opened without discarding anything.
+  pub fn is_clean (&self) -> bool {
+    self.bytes_discarded() == 0
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Repairs made to the staging stores when the database was opened.
+  pub fn recovery_report (&self) -> &RecoveryReport {
+    &self.staging.recovery
+  }
+}
@@ -0,0 +1,123 @@
+use crate::{DB,Point,PointOrd,Scalar,Value,QueryIterator};
+use crate::point::Num;
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+
+/// Bounding boxes whose sides may be left open, for `QueryRegion`.
+///
+/// For a point type with bounds `((A,B,C),(A,B,C))`, the open form is
+/// `((Option<A>,Option<B>,Option<C>),(Option<A>,Option<B>,Option<C>))` and a
+/// `None` side reaches the lowest or highest value of its type, as given by
+/// `Scalar::lowest()` and `Scalar::highest()`. This is implemented for the
+/// built-in point types of up to 8 dimensions.
+pub trait OpenBounds: PointOrd {
+  type Open: Copy+std::fmt::Debug;
+  /// Replace the open sides of `open` with the extreme values of their type.
+  fn close_bounds (open: &Self::Open) -> Result<Self::Bounds,Error>;
+}
+
+// a side of an open bounding box, reaching `extreme` when left open
+pub(crate) fn close<T> (side: Option<T>, extreme: Option<T>)
+-> Result<T,Error> {
+  match side.or(extreme) {
+    Some(x) => Ok(x),
+    None => bail!["coordinate type has no extreme value for an open bound"]
+  }
+}
+
+// implemented here rather than with the other traits in point.rs, which
+// tests include without the rest of the crate
+macro_rules! impl_open {
+  (($($T:tt),+),($($U:tt),+),($($i:tt),+)) => {
+    impl<$($T),+> OpenBounds for ($($U),+)
+    where $($T: Num<$T>),+ {
+      type Open = (($(Option<$T>,)+),($(Option<$T>,)+));
+      fn close_bounds (open: &Self::Open) -> Result<Self::Bounds,Error> {
+        Ok((
+          ($(close((open.0).$i, <$T as Scalar>::lowest())?,)+),
+          ($(close((open.1).$i, <$T as Scalar>::highest())?,)+)
+        ))
+      }
+    }
+  }
+}
+
+macro_rules! impl_open_comb {
+  ($types:tt, ($H:tt,$($T:tt),*), $ix:tt, ($($x:tt),*)) => {
+    impl_open_comb!($types, ($($T),*), $ix, ($($x,)*$H));
+    impl_open_comb!($types, ($($T),*), $ix, ($($x,)*($H,$H)));
+  };
+  ($types:tt, ($H:tt), $ix:tt, ($($x:tt),*)) => {
+    impl_open!($types, ($($x),*,$H), $ix);
+    impl_open!($types, ($($x),*,($H,$H)), $ix);
+  };
+}
+
+macro_rules! impl_open_dim {
+  ($t:tt,$i:tt) => {
+    impl_open_comb![$t,$t,$i,()];
+  }
+}
+
+impl_open_dim![(A,B),(0,1)];
+impl_open_dim![(A,B,C),(0,1,2)];
+impl_open_dim![(A,B,C,D),(0,1,2,3)];
+impl_open_dim![(A,B,C,D,E),(0,1,2,3,4)];
+impl_open_dim![(A,B,C,D,E,F),(0,1,2,3,4,5)];
+impl_open_dim![(A,B,C,D,E,F,G),(0,1,2,3,4,5,6)];
+impl_open_dim![(A,B,C,D,E,F,G,H),(0,1,2,3,4,5,6,7)];
+
+/// Region to query whose bounds may be open on either side of any dimension,
+/// for queries such as "every x, y between a and b, t from t0 on" without
+/// fabricating sentinel values for the open sides.
+///
+/// Open sides of floating point dimensions reach to infinity, so every
+/// coordinate matches them except for NaN, which matches no query. Open sides
+/// of integer dimensions reach the minimum or maximum value of the type.
+///
+/// ```rust,no_run
+/// # use eyros::{DB,Setup,QueryRegion,IoMode,disk_storage};
+/// # use failure::Error;
+/// # fn main () -> Result<(),Error> {
+/// type P = (f32,f32,u64);
+/// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+/// let mut db: DB<_,_,P,u32> = Setup::new(storage).build()?;
+/// let (a,b,t0) = (-1.0,1.0,1_600_000_000);
+/// let region: QueryRegion<P> = QueryRegion::new(
+///   &((None,Some(a),Some(t0)),(None,Some(b),None))
+/// )?;
+/// for result in db.query_region(&region)? {
+///   let (point,value,_location) = result?;
+///   println!["{:?} {}", point, value];
+/// }
+/// # Ok(()) }
+/// ```
+#[derive(Clone,Copy,Debug)]
+pub struct QueryRegion<P> where P: OpenBounds {
+  bbox: P::Bounds
+}
+
+impl<P> QueryRegion<P> where P: OpenBounds {
+  /// Create a region from bounds whose open sides are `None`.
+  pub fn new (open: &P::Open) -> Result<Self,Error> {
+    Ok(Self { bbox: P::close_bounds(open)? })
+  }
+  /// Bounding box of the region, with the open sides replaced by the extreme
+  /// values of their type, to pass to the other query methods such as
+  /// `db.query_with()` or `db.extent()`.
+  pub fn bounds (&self) -> &P::Bounds {
+    &self.bbox
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point+OpenBounds, V: Value {
+  /// Query for the records that intersect `region`, like `db.query()` with
+  /// the bounding box of the region.
+  pub fn query_region<'b> (&mut self, region: &'b QueryRegion<P>)
+  -> Result<QueryIterator<'b,S,P,V>,Error> {
+    self.query(&region.bbox)
+  }
+}
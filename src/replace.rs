@@ -0,0 +1,29 @@
+use crate::{DB,Point,Value,Row};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Delete every record that intersects `bbox` and insert `rows` in its
+  /// place, returning the number of records deleted.
+  ///
+  /// The deletes and the inserts are written as a single batch, so a log,
+  /// history or generation records the replacement as one change. This is
+  /// useful to refresh one region of a dataset, such as a new extract of a
+  /// city, without comparing the old and new records. Records in `rows` do
+  /// not need to be inside `bbox`.
+  pub fn replace_region (&mut self, bbox: &P::Bounds, rows: &[Row<P,V>])
+  -> Result<usize,Error> {
+    let mut batch = vec![];
+    for result in self.query(bbox)? {
+      let (_,_,loc) = result?;
+      batch.push(Row::Delete(loc));
+    }
+    let deleted = batch.len();
+    batch.extend_from_slice(rows);
+    self.batch(&batch)?;
+    Ok(deleted)
+  }
+}
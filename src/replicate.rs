@@ -0,0 +1,166 @@
+use crate::{DB,Point,Value,Row,Location};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+use desert::{ToBytes,FromBytes,CountBytes};
+
+/// Append-only log of encoded batches.
+///
+/// Every successful `db.batch()` is appended to the log attached with
+/// `Setup::log()`. Implement this trait to ship batches over your own transport
+/// (a hypercore feed, a socket, an object store, ...). `StorageLog` is a
+/// default implementation backed by a pair of random access stores.
+///
+/// Entries are opaque byte strings. Sequence numbers start at 0 and increase by
+/// 1 for each appended entry.
+pub trait Log {
+  /// Append an entry to the end of the log, returning its sequence number.
+  fn append (&mut self, entry: &[u8]) -> Result<u64,Error>;
+  /// Read the entry at sequence number `seq`.
+  fn get (&mut self, seq: u64) -> Result<Vec<u8>,Error>;
+  /// Number of entries in the log.
+  fn len (&mut self) -> Result<u64,Error>;
+  /// Whether the log has no entries.
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    Ok(self.len()? == 0)
+  }
+}
+
+/// Append-only log stored in two random access stores: one holding the entry
+/// bytes and an index holding the big endian `u64` end offset of each entry.
+///
+/// This is the same layout hypercore uses for its data and tree files, which
+/// keeps entries addressable by sequence number without a scan.
+pub struct StorageLog<S> where S: RandomAccess<Error=Error> {
+  pub(crate) data: S,
+  pub(crate) index: S
+}
+
+impl<S> StorageLog<S> where S: RandomAccess<Error=Error> {
+  pub fn open (data: S, index: S) -> Result<Self,Error> {
+    let mut log = Self { data, index };
+    ensure_eq!(log.index.len()? % 8, 0, "log index has a partial entry");
+    let n = log.len()?;
+    if n > 0 {
+      let end = log.end(n-1)?;
+      ensure_eq!(end, log.data.len()?, "log data length does not match index");
+    }
+    Ok(log)
+  }
+  fn end (&mut self, seq: u64) -> Result<u64,Error> {
+    let buf = self.index.read(seq*8, 8)?;
+    Ok(u64::from_bytes(&buf)?.1)
+  }
+}
+
+impl<S> Log for StorageLog<S> where S: RandomAccess<Error=Error> {
+  fn append (&mut self, entry: &[u8]) -> Result<u64,Error> {
+    let seq = self.len()?;
+    let offset = self.data.len()?;
+    self.data.write(offset, entry)?;
+    self.index.write(seq*8, &(offset + entry.len() as u64).to_bytes()?)?;
+    self.data.sync_all()?;
+    self.index.sync_all()?;
+    Ok(seq)
+  }
+  fn get (&mut self, seq: u64) -> Result<Vec<u8>,Error> {
+    let n = self.len()?;
+    if seq >= n {
+      bail!["log entry {} out of bounds (length {})", seq, n];
+    }
+    let start = if seq == 0 { 0 } else { self.end(seq-1)? };
+    let end = self.end(seq)?;
+    self.data.read(start, end-start)
+  }
+  fn len (&mut self) -> Result<u64,Error> {
+    Ok(self.index.len()?/8)
+  }
+}
+
+impl<P,V> CountBytes for Row<P,V> where P: Point, V: Value {
+  fn count_bytes (&self) -> usize {
+    1 + match self {
+      Row::Insert(p,v) => p.count_bytes() + v.count_bytes(),
+      Row::Delete(loc) => loc.count_bytes()
+    }
+  }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    if buf.is_empty() { bail!["buffer too small for row in count"] }
+    Ok(1 + match buf[0] {
+      0 => <(P,V)>::count_from_bytes(&buf[1..])?,
+      1 => Location::count_from_bytes(&buf[1..])?,
+      t => bail!["unexpected row type {}", t]
+    })
+  }
+}
+
+impl<P,V> ToBytes for Row<P,V> where P: Point, V: Value {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    let mut buf = vec![0u8;self.count_bytes()];
+    self.write_bytes(&mut buf)?;
+    Ok(buf)
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    if dst.is_empty() { bail!["dst buffer too small"] }
+    Ok(1 + match self {
+      Row::Insert(p,v) => {
+        dst[0] = 0;
+        let size = p.write_bytes(&mut dst[1..])?;
+        size + v.write_bytes(&mut dst[1+size..])?
+      },
+      Row::Delete(loc) => {
+        dst[0] = 1;
+        loc.write_bytes(&mut dst[1..])?
+      }
+    })
+  }
+}
+
+impl<P,V> FromBytes for Row<P,V> where P: Point, V: Value {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    if src.is_empty() { bail!["buffer too small while loading row"] }
+    match src[0] {
+      0 => {
+        let (size,(p,v)) = <(P,V)>::from_bytes(&src[1..])?;
+        Ok((1+size, Row::Insert(p,v)))
+      },
+      1 => {
+        let (size,loc) = Location::from_bytes(&src[1..])?;
+        Ok((1+size, Row::Delete(loc)))
+      },
+      t => bail!["unexpected row type {}", t]
+    }
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Apply every batch from `log` that has not yet been applied locally.
+  ///
+  /// The local log (set with `Setup::log()`) tracks the position: entries are
+  /// read from `log` starting at the length of the local log and each applied
+  /// batch is appended to the local log, so a replica can itself serve as the
+  /// source for further replicas.
+  ///
+  /// Tree construction is deterministic, so `Row::Delete` locations recorded
+  /// on the source refer to the same records on a replica that has applied
+  /// the same sequence of batches with the same `Setup` parameters.
+  ///
+  /// Returns the number of batches applied.
+  pub fn replicate_from (&mut self, log: &mut dyn Log) -> Result<u64,Error> {
+    let start = match &mut self.log {
+      Some(local) => local.len()?,
+      None => bail!["replicate_from requires a local log (see Setup::log)"]
+    };
+    let end = log.len()?;
+    for seq in start..end {
+      let buf = log.get(seq)?;
+      let rows = <Vec<Row<P,V>>>::from_bytes(&buf)
+        .map_err(|e| format_err!["failed to decode log entry {}: {}", seq, e])?
+        .1;
+      self.batch(&rows)?;
+    }
+    Ok(end.max(start)-start)
+  }
+}
@@ -0,0 +1,30 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher,Hasher};
+
+// Small splitmix64 generator for the randomized choices a database makes,
+// seeded with `Setup::seed()` so those choices can be replayed.
+#[derive(Clone,Debug)]
+pub(crate) struct Rng {
+  state: u64
+}
+
+impl Rng {
+  pub fn new (seed: u64) -> Self {
+    Self { state: seed }
+  }
+  // seed from the per-process random keys of the std hasher
+  pub fn from_entropy () -> Self {
+    Self::new(RandomState::new().build_hasher().finish())
+  }
+  pub fn next_u64 (&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+  }
+  // uniform integer in 0..n, for n > 0
+  pub fn below (&mut self, n: u64) -> u64 {
+    ((self.next_u64() as u128 * n as u128) >> 64) as u64
+  }
+}
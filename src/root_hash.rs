@@ -0,0 +1,66 @@
+use crate::{DB,Point,Value,is_generation_store};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use sha2::{Sha256,Digest};
+
+const CHUNK_SIZE: u64 = 1024*1024;
+
+/// sha256 digest identifying the state of a database.
+pub type Hash = [u8;32];
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Compute a deterministic sha256 root hash over the meta, staging, data and
+  /// tree stores. Two databases that were built from the same sequence of
+  /// batches have the same root hash, so replicas can compare hashes to check
+  /// that they hold identical state.
+  ///
+  /// Each non-empty store is hashed into a leaf and the root is the hash of
+  /// every leaf in store order:
+  ///
+  /// ```text
+  /// leaf = sha256(0x00 [name length: u16][name][data length: u64][data])
+  /// root = sha256(0x01 [leaf0][leaf1]...)
+  /// ```
+  ///
+  /// Bloom filter, batch metadata, history and generation stores are not
+  /// included. The hash is cached until the next change to these stores.
+  pub fn root_hash (&mut self) -> Result<Hash,Error> {
+    if let Some(hash) = self.root_hash {
+      return Ok(hash);
+    }
+    self.staging.commit()?;
+    self.data_store.try_borrow_mut()?.commit()?;
+    let names: Vec<String> = self.store_names().into_iter()
+      .filter(|name| {
+        name != "bloom" && name != "batch_meta" && name != "staging_meta"
+          && !name.starts_with("history")
+          && !is_generation_store(name)
+      })
+      .collect();
+    let mut root = Sha256::new();
+    root.update(&[1]);
+    for name in names.iter() {
+      let len = self.store_len(name)?;
+      if len == 0 { continue }
+      let mut leaf = Sha256::new();
+      leaf.update(&[0]);
+      leaf.update(&(name.len() as u16).to_be_bytes());
+      leaf.update(name.as_bytes());
+      leaf.update(&len.to_be_bytes());
+      let mut offset = 0;
+      while offset < len {
+        let size = CHUNK_SIZE.min(len-offset);
+        leaf.update(&self.read_store(name, offset, size)?);
+        offset += size;
+      }
+      root.update(&leaf.finalize());
+    }
+    let mut hash = [0u8;32];
+    hash.copy_from_slice(&root.finalize());
+    self.root_hash = Some(hash);
+    Ok(hash)
+  }
+}
@@ -0,0 +1,222 @@
+use crate::{DB,Point,Value,Location,QueryIterator,Clock,data::DataStore};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::collections::{HashMap,HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Batch that wrote a record, returned alongside each result of
+/// `db.query_meta()`.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct RowMeta {
+  /// Sequence number of the batch. The first batch written with row metadata
+  /// enabled is 1.
+  pub seq: u64,
+  /// Milliseconds since the unix epoch when the batch was written, if
+  /// `Setup::timestamps()` is enabled.
+  pub time: Option<u64>
+}
+
+/// Result of `db.query_meta()`: a point, value and location with the metadata
+/// of the batch that wrote the record, or `None` if the record was written
+/// before row metadata was enabled.
+pub type MetaResult<P,V> = (P,V,Location,Option<RowMeta>);
+
+/// Batch metadata for data blocks and staged rows, kept in the `batch_meta`
+/// and `staging_meta` stores when enabled with `Setup::row_meta()`.
+///
+/// `batch_meta` starts with the `u64` sequence number of the latest batch,
+/// followed by a `[block offset: u64][seq: u64][time: u64]` entry for every
+/// data block. `staging_meta` has a `[seq: u64][time: u64]` entry for every
+/// row in `staging_inserts`, written in the same order. A time of `0` means
+/// no timestamp was recorded.
+pub struct BatchMeta<S> where S: RandomAccess<Error=Error> {
+  pub store: S,
+  pub staging_store: S,
+  timestamps: bool,
+  clock: Rc<dyn Clock>,
+  current: RowMeta,
+  blocks: HashMap<u64,RowMeta>,
+  staging: Vec<RowMeta>
+}
+
+impl<S> BatchMeta<S> where S: RandomAccess<Error=Error> {
+  pub fn open (mut store: S, mut staging_store: S, timestamps: bool,
+  clock: Rc<dyn Clock>) -> Result<Self,Error> {
+    let mut current = RowMeta { seq: 0, time: None };
+    let mut blocks = HashMap::new();
+    let len = store.len()?;
+    if len > 0 {
+      let buf = store.read(0, len)?;
+      current.seq = read_u64(&buf, 0)?;
+      let mut offset = 8;
+      while offset < buf.len() {
+        let block = read_u64(&buf, offset)?;
+        blocks.insert(block, read_meta(&buf, offset+8)?);
+        offset += 24;
+      }
+    }
+    let mut staging = vec![];
+    let len = staging_store.len()?;
+    if len > 0 {
+      let buf = staging_store.read(0, len)?;
+      let mut offset = 0;
+      while offset < buf.len() {
+        staging.push(read_meta(&buf, offset)?);
+        offset += 16;
+      }
+    }
+    Ok(Self {
+      store, staging_store, timestamps, clock, current, blocks, staging
+    })
+  }
+
+  /// Start a new batch, assigning it the next sequence number.
+  pub fn begin (&mut self) -> Result<(),Error> {
+    self.current.seq += 1;
+    self.current.time = if self.timestamps {
+      Some(self.clock.now()?)
+    } else {
+      None
+    };
+    self.store.write(0, &self.current.seq.to_be_bytes())
+  }
+
+  /// Record that the data block at `block` was written by the current batch.
+  pub fn insert (&mut self, block: u64) -> Result<(),Error> {
+    let offset = self.store.len()?.max(8);
+    let mut buf = Vec::with_capacity(24);
+    buf.extend_from_slice(&block.to_be_bytes());
+    write_meta(&mut buf, &self.current);
+    self.store.write(offset, &buf)?;
+    self.blocks.insert(block, self.current);
+    Ok(())
+  }
+
+  /// Append metadata for `n` rows that the current batch added to staging.
+  pub fn stage (&mut self, n: usize) -> Result<(),Error> {
+    let metas = vec![self.current;n];
+    self.append_staging(&metas)
+  }
+
+  /// Replace the staging metadata after the staged rows were rewritten.
+  pub fn restage (&mut self, metas: Vec<RowMeta>) -> Result<(),Error> {
+    self.staging_store.truncate(0)?;
+    self.staging.clear();
+    self.append_staging(&metas)
+  }
+
+  /// Drop the metadata of deleted staging rows, mirroring
+  /// `staging.delete()`.
+  pub fn unstage (&mut self, deletes: &[Location]) {
+    let del_set: HashSet<u32> = deletes.iter()
+      .filter(|loc| loc.0 == 0)
+      .map(|loc| loc.1)
+      .collect();
+    let mut i = 0;
+    self.staging.retain(|_| {
+      let j = i;
+      i += 1;
+      !del_set.contains(&j)
+    });
+  }
+
+  fn append_staging (&mut self, metas: &[RowMeta]) -> Result<(),Error> {
+    let mut buf = Vec::with_capacity(metas.len()*16);
+    for meta in metas.iter() {
+      write_meta(&mut buf, meta);
+    }
+    let offset = self.staging_store.len()?;
+    self.staging_store.write(offset, &buf)?;
+    self.staging.extend_from_slice(metas);
+    Ok(())
+  }
+
+  /// Metadata for the batch that is currently being written.
+  pub fn current (&self) -> RowMeta {
+    self.current
+  }
+
+  /// Metadata for the staged row at `index`.
+  pub fn staging (&self, index: usize) -> Option<RowMeta> {
+    self.staging.get(index).copied()
+  }
+
+  /// Metadata for a record at `location`.
+  pub fn get (&self, location: &Location) -> Option<RowMeta> {
+    match location {
+      (0,i) => self.staging(*i as usize),
+      (block,_) => self.blocks.get(&(block-1)).copied()
+    }
+  }
+
+  pub fn commit (&mut self) -> Result<(),Error> {
+    self.store.sync_all()?;
+    self.staging_store.sync_all()
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query for records that intersect `bbox` like `db.query()`, with the
+  /// metadata of the batch that wrote each record. Requires
+  /// `Setup::row_meta(true)`.
+  ///
+  /// Metadata is kept per data block, so records in a tree report the batch
+  /// that wrote their block, which is the batch that inserted them or a later
+  /// batch that moved them out of staging or merged their tree. Records that
+  /// are still in staging report the batch that inserted them.
+  pub fn query_meta<'b> (&mut self, bbox: &'b P::Bounds)
+  -> Result<MetaIterator<'b,S,P,V>,Error> {
+    if self.data_store.try_borrow()?.batch_meta.is_none() {
+      bail!["query_meta() requires Setup::row_meta(true)"];
+    }
+    Ok(MetaIterator {
+      query: self.query(bbox)?,
+      data_store: Rc::clone(&self.data_store)
+    })
+  }
+}
+
+/// Iterator of `Result<MetaResult<P,V>>` data returned by `db.query_meta()`.
+pub struct MetaIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  query: QueryIterator<'b,S,P,V>,
+  data_store: Rc<RefCell<DataStore<S,P,V>>>
+}
+
+impl<'b,S,P,V> Iterator for MetaIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  type Item = Result<MetaResult<P,V>,Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    let (p,v,loc) = match self.query.next()? {
+      Ok(result) => result,
+      Err(e) => return Some(Err(e))
+    };
+    let dstore = iwrap![self.data_store.try_borrow()];
+    let meta = dstore.batch_meta.as_ref().and_then(|m| m.get(&loc));
+    Some(Ok((p,v,loc,meta)))
+  }
+}
+
+fn read_u64 (buf: &[u8], offset: usize) -> Result<u64,Error> {
+  if offset+8 > buf.len() {
+    bail!["unexpected end of batch metadata at {}", offset];
+  }
+  let mut bytes = [0u8;8];
+  bytes.copy_from_slice(&buf[offset..offset+8]);
+  Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_meta (buf: &[u8], offset: usize) -> Result<RowMeta,Error> {
+  let seq = read_u64(buf, offset)?;
+  let time = read_u64(buf, offset+8)?;
+  Ok(RowMeta { seq, time: if time == 0 { None } else { Some(time) } })
+}
+
+fn write_meta (buf: &mut Vec<u8>, meta: &RowMeta) {
+  buf.extend_from_slice(&meta.seq.to_be_bytes());
+  buf.extend_from_slice(&meta.time.unwrap_or(0).to_be_bytes());
+}
@@ -0,0 +1,35 @@
+use crate::{DB,Point,Value,Location};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query `bbox` for a uniform random sample of at most `n` of the matching
+  /// records, for previews and estimates over large regions.
+  ///
+  /// Every matching record is visited once and kept in a reservoir of `n`
+  /// records, so this costs as much io as `db.query()`. The choices come from
+  /// the generator seeded with `Setup::seed()`: databases with the same seed
+  /// and the same records return the same samples for the same sequence of
+  /// calls.
+  pub fn query_sample (&mut self, bbox: &P::Bounds, n: usize)
+  -> Result<Vec<(P,V,Location)>,Error> {
+    let mut rng = self.rng.clone();
+    let mut sample = Vec::with_capacity(n);
+    let mut seen = 0u64;
+    for result in self.query(bbox)? {
+      let row = result?;
+      seen += 1;
+      if sample.len() < n {
+        sample.push(row);
+      } else {
+        let i = rng.below(seen) as usize;
+        if i < n { sample[i] = row }
+      }
+    }
+    self.rng = rng;
+    Ok(sample)
+  }
+}
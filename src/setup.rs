@@ -1,14 +1,70 @@
-use crate::{DB,Point,Value};
+use crate::{DB,Point,Value,Log,Curve,Durability,Clock,MergePolicy,Schema,
+  Transform,InvertedIntervals,Levels,Quantize};
+use crate::partition::Partition;
 use failure::Error;
 use random_access_storage::RandomAccess;
+use std::rc::Rc;
+use std::any::Any;
 
 /// Struct for reading database properties.
+#[derive(Clone,Debug)]
 pub struct SetupFields {
   pub max_data_size: usize,
   pub base_size: usize,
   pub branch_factor: usize,
+  pub branch_factors: Vec<usize>,
+  pub dimension_cycle: Vec<usize>,
+  pub adaptive_split: bool,
   pub bbox_cache_size: usize,
-  pub data_list_cache_size: usize
+  pub data_list_cache_size: usize,
+  pub history: bool,
+  pub generations: bool,
+  pub curve: Curve,
+  pub bloom_bits: usize,
+  pub dedup: bool,
+  pub dedup_results: bool,
+  pub row_meta: bool,
+  pub timestamps: bool,
+  pub durability: Durability,
+  pub write_cache_size: usize,
+  pub staging_spill: usize,
+  pub staging_threshold_rows: usize,
+  pub staging_threshold_bytes: usize,
+  pub auto_tune: bool,
+  pub delete_bitmap: bool,
+  pub branch_bounds: bool,
+  pub branch_prefetch: bool,
+  pub pin_levels: usize,
+  pub pin_bytes: usize,
+  pub block_bounds: bool,
+  pub pack_coords: bool,
+  pub strict_open: bool,
+  pub varint_deletes: bool,
+  pub inline_threshold: Option<usize>,
+  pub finite_coords: bool,
+  pub inverted_intervals: InvertedIntervals,
+  pub seed: Option<u64>,
+  pub batch_ids: usize,
+  pub schema: Option<Schema>,
+  pub crs: Option<String>,
+  pub quantize: Option<Vec<Quantize>>
+}
+
+impl SetupFields {
+  /// Branch factors for each dimension, or the single `branch_factor` for
+  /// every dimension if none were set.
+  pub fn branch_factors (&self) -> Vec<usize> {
+    if self.branch_factors.is_empty() {
+      vec![self.branch_factor]
+    } else {
+      self.branch_factors.clone()
+    }
+  }
+  /// Branch factor and split dimension of each tree depth.
+  pub fn levels (&self) -> Levels {
+    Levels::new(self.branch_factors(), self.dimension_cycle.clone(),
+      self.adaptive_split)
+  }
 }
 
 /// Builder to configure and instantiate an eyros database.
@@ -43,7 +99,14 @@ pub struct Setup<S,U> where
 S: RandomAccess<Error=Error>,
 U: (Fn(&str) -> Result<S,Error>) {
   pub open_store: U,
-  pub fields: SetupFields
+  pub fields: SetupFields,
+  pub log: Option<Box<dyn Log>>,
+  pub clock: Option<Rc<dyn Clock>>,
+  pub merge_policy: Option<Rc<dyn MergePolicy>>,
+  pub transform: Option<Rc<dyn Transform>>,
+  // partition key extractor for the value type of the database, from
+  // Setup::partition()
+  pub partition: Option<Rc<dyn Any>>
 }
 
 impl<S,U> Setup<S,U> where
@@ -55,17 +118,94 @@ U: (Fn(&str) -> Result<S,Error>) {
       open_store,
       fields: SetupFields {
         branch_factor: 5,
+        branch_factors: vec![],
+        dimension_cycle: vec![],
+        adaptive_split: false,
         max_data_size: 3_000,
         base_size: 9_000,
         bbox_cache_size: 10_000,
-        data_list_cache_size: 16_000
-      }
+        data_list_cache_size: 16_000,
+        history: false,
+        generations: false,
+        curve: Curve::None,
+        bloom_bits: 0,
+        dedup: false,
+        dedup_results: false,
+        row_meta: false,
+        timestamps: false,
+        durability: Durability::Always,
+        write_cache_size: 0,
+        staging_spill: 0,
+        staging_threshold_rows: 0,
+        staging_threshold_bytes: 0,
+        auto_tune: false,
+        delete_bitmap: false,
+        branch_bounds: false,
+        branch_prefetch: false,
+        pin_levels: 0,
+        pin_bytes: 4*1024*1024,
+        block_bounds: false,
+        pack_coords: false,
+        strict_open: false,
+        varint_deletes: false,
+        inline_threshold: None,
+        finite_coords: true,
+        inverted_intervals: InvertedIntervals::Reject,
+        seed: None,
+        batch_ids: 0,
+        schema: None,
+        crs: None,
+        quantize: None
+      },
+      log: None,
+      clock: None,
+      merge_policy: None,
+      transform: None,
+      partition: None
     }
   }
   pub fn branch_factor (mut self, bf: usize) -> Self {
     self.fields.branch_factor = bf;
     self
   }
+  /// Use a separate branch factor for each dimension instead of the same
+  /// `branch_factor()` everywhere. Tree levels cycle through the dimensions,
+  /// so `bfs[i]` is used for every level that splits on dimension `i`.
+  ///
+  /// Each branch factor must be a power of 2 plus 1 and there must be exactly
+  /// one for each dimension of the point type.
+  pub fn branch_factors (mut self, bfs: &[usize]) -> Self {
+    self.fields.branch_factors = bfs.to_vec();
+    self
+  }
+  /// Split the tree levels on the dimensions of `dims` in turn instead of
+  /// cycling through every dimension, so that level `d` splits on dimension
+  /// `dims[d % dims.len()]`. For example, `&[0,1,0,1,2]` spends two levels on
+  /// each of x and y for every level on a low selectivity t dimension.
+  /// Dimensions left out of the cycle are never split on.
+  ///
+  /// The cycle is not stored with the database, so it must be the same every
+  /// time the database is opened. With `branch_factors()`, each level uses
+  /// the branch factor of the dimension it splits on.
+  pub fn dimension_cycle (mut self, dims: &[usize]) -> Self {
+    self.fields.dimension_cycle = dims.to_vec();
+    self
+  }
+  /// Split each branch on the dimension where its rows have the greatest
+  /// spread, like a kd-tree, instead of following the dimension cycle. This
+  /// prunes better on anisotropic data such as long, thin road networks.
+  /// Only the dimensions of `dimension_cycle()` are considered if it is set,
+  /// and the cycle is used for point types that do not implement
+  /// `PointOrd::spread_at()`.
+  ///
+  /// Each branch records its split dimension in a header, so trees built
+  /// with this option are tagged `eyros::TREE_ADAPTIVE` and can only be read
+  /// by versions of eyros that support it. Trees built before the option was
+  /// set are read as they are until a merge rebuilds them.
+  pub fn adaptive_split (mut self, adaptive: bool) -> Self {
+    self.fields.adaptive_split = adaptive;
+    self
+  }
   pub fn base_size (mut self, size: usize) -> Self {
     self.fields.base_size = size;
     self
@@ -82,6 +222,319 @@ U: (Fn(&str) -> Result<S,Error>) {
     self.fields.data_list_cache_size = size;
     self
   }
+  /// Sort the rows of each new tree along a space-filling `curve` before
+  /// they are written so that spatially adjacent records share data blocks.
+  pub fn curve (mut self, curve: Curve) -> Self {
+    self.fields.curve = curve;
+    self
+  }
+  /// Write a bloom filter with `bits_per_row` bits for each point into the
+  /// `bloom` store alongside every new data block, so that `db.contains()`
+  /// can skip blocks that do not hold a point. Use `0` (the default) to
+  /// disable filters. Around 10 bits per row gives a 1% false positive rate.
+  pub fn bloom_filter (mut self, bits_per_row: usize) -> Self {
+    self.fields.bloom_bits = bits_per_row;
+    self
+  }
+  /// Record deletes of tree records in roaring bitmaps of deleted slots for
+  /// each data block, kept in the `delete_bitmap` store. Queries skip the
+  /// deleted slots as soon as the batch is written, without rewriting data
+  /// blocks, and the bitmaps are written into the data blocks once they hold
+  /// `base_size()` deletes. Not supported with `keep_generations()`.
+  pub fn delete_bitmap (mut self, enabled: bool) -> Self {
+    self.fields.delete_bitmap = enabled;
+    self
+  }
+  /// Store the bounding box of every child in each branch block, so queries
+  /// skip subtrees and data blocks whose records miss the query bounds
+  /// instead of relying on the pivots alone. This prunes much more on skewed
+  /// data at the cost of larger branch blocks. Trees written without bounds
+  /// remain readable.
+  pub fn branch_bounds (mut self, enabled: bool) -> Self {
+    self.fields.branch_bounds = enabled;
+    self
+  }
+  /// Sort records into up to 64 categories, such as the layers of a map
+  /// (roads, buildings, pois), with `key` extracting the partition key of a
+  /// record from its value. Each branch block then stores a bitmask of the
+  /// partitions below every child along with its bounding box, as with
+  /// `branch_bounds()`, so queries limited with `QueryOptions::partitions()`
+  /// skip the subtrees and data blocks that only hold other partitions.
+  ///
+  /// Keys must be below `eyros::PARTITIONS`, or the batch is rejected. Open
+  /// the database with the same `key` every time: the masks written with one
+  /// key function do not describe the partitions of another. Trees written
+  /// without masks remain readable and are filtered record by record.
+  pub fn partition<V,F> (mut self, key: F) -> Self
+  where V: Value, F: Fn(&V) -> u8 + 'static {
+    let partition: Partition<V> = Box::new(key);
+    self.partition = Some(Rc::new(partition));
+    self
+  }
+  /// Read every child branch that a query descends into as soon as their
+  /// parent is read, before any of them is visited, instead of reading each
+  /// one when its turn comes. This hides the latency of each read on network
+  /// or spinning-disk storage for deep queries, at the cost of holding the
+  /// prefetched blocks in memory until they are visited.
+  pub fn branch_prefetch (mut self, enabled: bool) -> Self {
+    self.fields.branch_prefetch = enabled;
+    self
+  }
+  /// Keep the branch blocks of the top `levels` of each tree in memory once
+  /// they are first read, since every query starts from the root and visits
+  /// them. Pinned blocks are only dropped when their tree is rebuilt by a
+  /// merge. The default of `0` reads every branch from the tree stores. Use
+  /// `db.cache_stats()` to check the hit rate.
+  pub fn pin_levels (mut self, levels: usize) -> Self {
+    self.fields.pin_levels = levels;
+    self
+  }
+  /// Pin at most `bytes` of branch blocks across every tree with
+  /// `pin_levels()`. Blocks past the budget are read from the stores as
+  /// usual. Defaults to 4 MB.
+  pub fn pin_bytes (mut self, bytes: usize) -> Self {
+    self.fields.pin_bytes = bytes;
+    self
+  }
+  /// Write the bounding box of the rows into a small header of each data
+  /// block, so queries read the header of a block that is not cached and skip
+  /// the block without reading or decoding its rows when the bounds miss the
+  /// query. Blocks written without a header remain readable.
+  pub fn block_bounds (mut self, enabled: bool) -> Self {
+    self.fields.block_bounds = enabled;
+    self
+  }
+  /// Bit-pack the points in each data block as the bits that differ from the
+  /// first point of the block, which shrinks the data store and the bytes
+  /// read by queries for spatially clustered blocks. This only applies to new
+  /// databases, which are written with a format version in the `meta` store
+  /// that earlier versions of eyros refuse to open. Upgrade an existing
+  /// database with `db.migrate()` instead.
+  pub fn pack_coords (mut self, enabled: bool) -> Self {
+    self.fields.pack_coords = enabled;
+    self
+  }
+  /// Fail to open the database when a staging store ends in a partial record
+  /// instead of truncating the partial record. By default the tail bytes are
+  /// discarded and the repairs are listed in `db.recovery_report()`.
+  pub fn strict_open (mut self, enabled: bool) -> Self {
+    self.fields.strict_open = enabled;
+    self
+  }
+  /// Write staged deletes to the `staging_deletes` store as varints, which
+  /// takes 2 to 5 bytes for the locations of most databases instead of 12, so
+  /// massive delete batches stage far fewer bytes. This needs format version
+  /// 6, which new databases are written in and which earlier versions of
+  /// eyros refuse to open. Upgrade an existing database with `db.migrate()`.
+  pub fn varint_deletes (mut self, enabled: bool) -> Self {
+    self.fields.varint_deletes = enabled;
+    self
+  }
+  /// Keep values that serialize to at most `bytes` inline in the data blocks
+  /// and append larger values to the `blobs` store, leaving a 12-byte
+  /// reference in the block. Blocks of small values stay compact for queries
+  /// over mixed-size workloads, and large values are only read for matching
+  /// rows. Blobs are never reclaimed: deleted values keep their bytes and
+  /// merges write large values again.
+  ///
+  /// This needs format version 7, which new databases are written in and
+  /// which earlier versions of eyros refuse to open. Upgrade an existing
+  /// database with `db.migrate()`. Once any value was written as a blob, the
+  /// database must always be opened with an inline threshold, though the
+  /// threshold itself may change.
+  pub fn inline_threshold (mut self, bytes: usize) -> Self {
+    self.fields.inline_threshold = Some(bytes);
+    self
+  }
+  /// Reject batches that insert a point with a NaN or infinite coordinate,
+  /// with an error naming the index of the row, before any of the batch is
+  /// written. NaN coordinates never match a query and have no order to place
+  /// them in the trees by. Enabled by default.
+  pub fn finite_coords (mut self, enabled: bool) -> Self {
+    self.fields.finite_coords = enabled;
+    self
+  }
+  /// Choose whether `db.batch()` rejects, swaps or stores inserted points
+  /// with an interval minimum greater than its maximum. Batches with inverted
+  /// intervals are rejected by default. See `InvertedIntervals`.
+  pub fn inverted_intervals (mut self, policy: InvertedIntervals) -> Self {
+    self.fields.inverted_intervals = policy;
+    self
+  }
+  /// Drop inserted rows whose point and value exactly match a record that is
+  /// already in the database or earlier in the same batch. Each insert costs
+  /// an extra lookup of the point, so pair this with `bloom_filter()` when
+  /// most inserts are new.
+  pub fn dedup (mut self, enabled: bool) -> Self {
+    self.fields.dedup = enabled;
+    self
+  }
+  /// Skip query results whose point and value are identical to a result that
+  /// was already returned by the same `db.query()`, so that a record stored
+  /// in both staging and a tree is only reported once. Legitimately repeated
+  /// rows are collapsed too.
+  pub fn dedup_results (mut self, enabled: bool) -> Self {
+    self.fields.dedup_results = enabled;
+    self
+  }
+  /// Record the sequence number of the batch that wrote each data block and
+  /// staged row in the `batch_meta` and `staging_meta` stores, for
+  /// `db.query_meta()`.
+  pub fn row_meta (mut self, enabled: bool) -> Self {
+    self.fields.row_meta = enabled;
+    self
+  }
+  /// Also record the wall-clock time of each batch with the row metadata.
+  /// Implies `row_meta(true)`.
+  pub fn timestamps (mut self, enabled: bool) -> Self {
+    self.fields.timestamps = enabled;
+    self
+  }
+  /// Set how often the stores are synced to durable storage. The default,
+  /// `Durability::Always`, syncs during every `batch()`. Other policies trade
+  /// the durability of recent batches for throughput when writing many small
+  /// batches; call `db.sync()` to sync on demand.
+  pub fn durability (mut self, durability: Durability) -> Self {
+    self.fields.durability = durability;
+    self
+  }
+  /// Limit the bytes of staging writes held in memory by each write cache
+  /// before they are written to the staging stores. When a write goes over
+  /// the limit, the oldest writes are flushed until the cache is half full.
+  /// The default of `0` only flushes when the stores are synced.
+  pub fn write_cache_size (mut self, bytes: usize) -> Self {
+    self.fields.write_cache_size = bytes;
+    self
+  }
+  /// Spill staged inserts to sorted runs in the `staging_runs` store whenever
+  /// more than `bytes` of them are held in memory. The runs are merged when
+  /// the staged rows are built into a tree, so with a large `base_size()`,
+  /// staging can grow past the available memory during an import. Queries
+  /// read every run from disk, so they get slower as the runs grow.
+  ///
+  /// Not supported with `row_meta()`, `timestamps()` or
+  /// `keep_generations()`. The default of `0` never spills.
+  pub fn staging_spill (mut self, bytes: usize) -> Self {
+    self.fields.staging_spill = bytes;
+    self
+  }
+  /// Build staged inserts into the trees once more than `rows` of them are
+  /// staged. The default of `0` uses `base_size()`. A smaller threshold keeps
+  /// staging small so queries, which scan every staged row, stay fast, at the
+  /// cost of building and merging small trees more often. A larger threshold
+  /// lets bulk loads build several `base_size()` trees at once, with fewer
+  /// merges. See also `db.flush_staging()`.
+  pub fn staging_threshold_rows (mut self, rows: usize) -> Self {
+    self.fields.staging_threshold_rows = rows;
+    self
+  }
+  /// Also build staged inserts into the trees once the staging stores would
+  /// hold more than `bytes`, for values of varying size. The default of `0`
+  /// only goes by `staging_threshold_rows()`.
+  pub fn staging_threshold_bytes (mut self, bytes: usize) -> Self {
+    self.fields.staging_threshold_bytes = bytes;
+    self
+  }
+  /// Adjust the staging threshold after each flush of staging into the
+  /// trees to the one suggested by `db.tuning_report()`, between one and 16
+  /// times `base_size()`, starting from `staging_threshold_rows()`. The
+  /// threshold is not saved, so it is measured again each time the database
+  /// is opened. Only write costs are measured: larger thresholds also make
+  /// queries scan more staged rows.
+  pub fn auto_tune (mut self, enabled: bool) -> Self {
+    self.fields.auto_tune = enabled;
+    self
+  }
+  /// Record a summary of each batch in the `history` and `history_index`
+  /// stores so that consumers can follow changes with `db.changes_since()`.
+  pub fn history (mut self, enabled: bool) -> Self {
+    self.fields.history = enabled;
+    self
+  }
+  /// Keep superseded trees and enough of each batch to query earlier states
+  /// of the database with `db.query_at()`. Old generations are never removed,
+  /// so storage grows with every batch.
+  pub fn keep_generations (mut self, enabled: bool) -> Self {
+    self.fields.generations = enabled;
+    self
+  }
+  /// Append each batch to `log` after it is written, for replication with
+  /// `db.replicate_from()`.
+  pub fn log<L> (mut self, log: L) -> Self where L: Log+'static {
+    self.log = Some(Box::new(log));
+    self
+  }
+  /// Seed the random number generator behind randomized choices, such as the
+  /// records picked by `db.query_sample()`, so they can be replayed. Without a
+  /// seed, the generator is seeded differently for every database instance.
+  pub fn seed (mut self, seed: u64) -> Self {
+    self.fields.seed = Some(seed);
+    self
+  }
+  /// Read the current time from `clock` instead of the system time, for
+  /// reproducible timestamps in tests and simulations.
+  pub fn clock<C> (mut self, clock: C) -> Self where C: Clock+'static {
+    self.clock = Some(Rc::new(clock));
+    self
+  }
+  /// Decide which trees are merged when the staging area is written out with
+  /// `policy`, such as `eyros::Leveled` for fewer trees to query or
+  /// `eyros::Lazy` for less rewriting. The default is `eyros::SizeTiered`.
+  pub fn merge_policy<M> (mut self, policy: M) -> Self
+  where M: MergePolicy+'static {
+    self.merge_policy = Some(Rc::new(policy));
+    self
+  }
+  /// Remember the ids of the last `window` batches written with
+  /// `db.batch_with_id()` in the `batch_ids` store, so that a batch that is
+  /// delivered again within the window is skipped. The default of `0` keeps
+  /// no ids.
+  pub fn batch_ids (mut self, window: usize) -> Self {
+    self.fields.batch_ids = window;
+    self
+  }
+  /// Store `schema` in the `meta` store to describe the fields of `Record`
+  /// values, so they can be fetched by name with `db.query_select()`. New
+  /// databases are written in the newest format version. Opening a database
+  /// with a different schema than the stored one fails.
+  pub fn schema (mut self, schema: Schema) -> Self {
+    self.fields.schema = Some(schema);
+    self
+  }
+  /// Record the name of the coordinate reference system of the points, such
+  /// as `EPSG:3857`, in the `meta` store. New databases are written in the
+  /// newest format version. Opening a database with a different reference
+  /// system than the stored one fails.
+  pub fn crs (mut self, name: &str) -> Self {
+    self.fields.crs = Some(name.to_string());
+    self
+  }
+  /// Store each coordinate in data blocks as a 4-byte step of `quantize[i]`
+  /// for its dimension `i` instead of at full precision, such as
+  /// `Quantize::new(1e-7, 0.0)` for degrees of longitude and latitude. This
+  /// halves the size of `f64` points that only need a fixed precision.
+  ///
+  /// Inserted points are rounded to the nearest step, so queries return the
+  /// rounded coordinates. Batches with a coordinate more than `i32::MAX`
+  /// steps from the offset fail before any row is written. The quantization
+  /// is stored in the `meta` store of new databases, which are written in the
+  /// newest format version, and is used whenever the database is opened.
+  /// Setting it on an existing database, or a different one than the stored
+  /// one, fails. Quantized data blocks are not bit-packed.
+  pub fn quantize (mut self, quantize: Vec<Quantize>) -> Self {
+    self.fields.quantize = Some(quantize);
+    self
+  }
+  /// Convert points between the reference system of the application and
+  /// that of the database with `transform` in `db.batch_reprojected()` and
+  /// `db.query_reprojected()`, such as `eyros::WebMercator` to query a
+  /// database stored in `EPSG:3857` with `EPSG:4326` boxes. The target of the
+  /// transform must match `Setup::crs()` if the database has one.
+  pub fn transform<T> (mut self, transform: T) -> Self
+  where T: Transform+'static {
+    self.transform = Some(Rc::new(transform));
+    self
+  }
   pub fn build<P,V> (self) -> Result<DB<S,U,P,V>,Error>
   where P: Point, V: Value {
     DB::open_from_setup(self)
@@ -0,0 +1,208 @@
+use crate::{DB,Setup,Point,Value,checksum::crc32_update};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+use std::io::{Read,Write};
+
+const MAGIC: &[u8;8] = b"EYROSARC";
+const VERSION: u16 = 1;
+const CHUNK_SIZE: u64 = 1024*1024;
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Write the complete state of the database (meta, staging, data and every
+  /// tree) to `writer` as a single archive that can be loaded with
+  /// `DB::import()`.
+  ///
+  /// The archive is a sequence of length-prefixed stores, each followed by a
+  /// crc32 checksum of its contents:
+  ///
+  /// ```text
+  /// [magic: "EYROSARC"][version: u16][store count: u32]
+  /// [name length: u16][name][data length: u64][data][crc32: u32]
+  /// ...
+  /// ```
+  ///
+  /// Stores are streamed in chunks, so exporting does not require the whole
+  /// database to fit in memory.
+  pub fn export<W> (&mut self, writer: &mut W) -> Result<(),Error>
+  where W: Write {
+    self.staging.commit()?;
+    self.data_store.try_borrow_mut()?.commit()?;
+    let names = self.store_names();
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_be_bytes())?;
+    writer.write_all(&(names.len() as u32).to_be_bytes())?;
+    for name in names.iter() {
+      let len = self.store_len(name)?;
+      writer.write_all(&(name.len() as u16).to_be_bytes())?;
+      writer.write_all(name.as_bytes())?;
+      writer.write_all(&len.to_be_bytes())?;
+      let mut crc = 0;
+      let mut offset = 0;
+      while offset < len {
+        let size = CHUNK_SIZE.min(len-offset);
+        let buf = self.read_store(name, offset, size)?;
+        crc = crc32_update(crc, &buf);
+        writer.write_all(&buf)?;
+        offset += size;
+      }
+      writer.write_all(&crc.to_be_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+  }
+
+  /// Create a database from an archive written by `db.export()`, writing each
+  /// store in the archive with `setup.open_store` before opening the database
+  /// with the rest of `setup`.
+  ///
+  /// The destination must not hold a database yet. Each store is written to
+  /// a temporary `import_` store and checked first, so that a checksum
+  /// mismatch or a truncated archive leaves the destination untouched. Only
+  /// the store names that a database writes itself are accepted.
+  pub fn import<R> (setup: Setup<S,U>, reader: &mut R) -> Result<Self,Error>
+  where R: Read {
+    let mut magic = [0u8;8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+      bail!["not an eyros archive"];
+    }
+    let version = u16::from_be_bytes(read_array(reader)?);
+    if version != VERSION {
+      bail!["unsupported archive version {}", version];
+    }
+    for name in BASE_STORES.iter() {
+      if !(setup.open_store)(name)?.is_empty()? {
+        bail!["import destination already holds a database, store {} is \
+          not empty", name];
+      }
+    }
+    let mut names: Vec<String> = vec![];
+    if let Err(err) = read_stores(&setup, reader, &mut names) {
+      clear_temp_stores(&setup, &names)?;
+      return Err(err);
+    }
+    // every store was checked, so they replace the stores of the destination
+    for name in names.iter() {
+      let mut src = (setup.open_store)(&temp_name(name))?;
+      let mut dst = (setup.open_store)(name)?;
+      dst.truncate(0)?;
+      let len = src.len()?;
+      let mut offset = 0;
+      while offset < len {
+        let size = CHUNK_SIZE.min(len-offset);
+        dst.write(offset, &src.read(offset, size)?)?;
+        offset += size;
+      }
+      dst.sync_all()?;
+    }
+    clear_temp_stores(&setup, &names)?;
+    DB::open_from_setup(setup)
+  }
+}
+
+// stores that every database writes, which are empty at a new destination
+const BASE_STORES: &[&str] = &[
+  "meta", "staging_inserts", "staging_deletes", "data", "range"
+];
+
+// names of the stores that a database writes besides its trees. names from an
+// archive are checked against these so that they can not reach outside of the
+// storage of the destination, such as "../x" with disk_storage()
+const STORE_NAMES: &[&str] = &[
+  "meta", "staging_inserts", "staging_deletes", "staging_runs", "data",
+  "range", "bloom", "blobs", "delete_bitmap", "batch_meta", "staging_meta",
+  "history", "history_index", "batch_ids", "generations",
+  "generations_index", "generations_trees"
+];
+
+// accept the names from STORE_NAMES, `tree{i}` and preserved `tree{i}@{v}`
+fn check_store_name (name: &str) -> Result<(),Error> {
+  let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+  let valid = STORE_NAMES.contains(&name) || match name.strip_prefix("tree") {
+    Some(rest) => match rest.split_once('@') {
+      Some((i,v)) => digits(i) && digits(v),
+      None => digits(rest)
+    },
+    None => false
+  };
+  if !valid {
+    bail!["archive has an unexpected store name {:?}", name];
+  }
+  Ok(())
+}
+
+fn temp_name (name: &str) -> String {
+  format!["import_{}", name]
+}
+
+// write every store of the archive to its temporary store, adding the names
+// of the stores written to `names`
+fn read_stores<S,U,R> (setup: &Setup<S,U>, reader: &mut R,
+names: &mut Vec<String>) -> Result<(),Error>
+where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>),
+R: Read {
+  let count = u32::from_be_bytes(read_array(reader)?);
+  for _ in 0..count {
+    let name_len = u16::from_be_bytes(read_array(reader)?) as usize;
+    let mut name_buf = vec![0u8;name_len];
+    reader.read_exact(&mut name_buf)?;
+    let name = String::from_utf8(name_buf)?;
+    check_store_name(&name)?;
+    if names.contains(&name) {
+      bail!["archive has store {} more than once", name];
+    }
+    let len = u64::from_be_bytes(read_array(reader)?);
+    let mut store = (setup.open_store)(&temp_name(&name))?;
+    store.truncate(0)?;
+    names.push(name);
+    if let Err(err) = read_store(reader, &mut store, len) {
+      bail!["store {}: {}", names[names.len()-1], err];
+    }
+  }
+  Ok(())
+}
+
+// copy `len` bytes of a store from the archive into `store` and check them
+// against the crc32 that follows
+fn read_store<R,T> (reader: &mut R, store: &mut T, len: u64)
+-> Result<(),Error> where R: Read, T: RandomAccess<Error=Error> {
+  let mut crc = 0;
+  let mut offset = 0;
+  while offset < len {
+    let size = CHUNK_SIZE.min(len-offset);
+    let mut buf = vec![0u8;size as usize];
+    reader.read_exact(&mut buf)?;
+    crc = crc32_update(crc, &buf);
+    store.write(offset, &buf)?;
+    offset += size;
+  }
+  let expected = u32::from_be_bytes(read_array(reader)?);
+  if crc != expected {
+    return Err(format_err![
+      "checksum mismatch (expected {:08x}, computed {:08x})",
+      expected, crc
+    ]);
+  }
+  store.sync_all()
+}
+
+fn clear_temp_stores<S,U> (setup: &Setup<S,U>, names: &[String])
+-> Result<(),Error>
+where S: RandomAccess<Error=Error>, U: (Fn(&str) -> Result<S,Error>) {
+  for name in names.iter() {
+    let mut store = (setup.open_store)(&temp_name(name))?;
+    store.truncate(0)?;
+    store.sync_all()?;
+  }
+  Ok(())
+}
+
+fn read_array<R,T> (reader: &mut R) -> Result<T,Error>
+where R: Read, T: Default+AsMut<[u8]> {
+  let mut buf = T::default();
+  reader.read_exact(buf.as_mut())?;
+  Ok(buf)
+}
@@ -0,0 +1,135 @@
+use crate::{DB,Point,Value,Location,DataStore,DeleteSet};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+
+/// Direction of the results from `db.query_sorted()`.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum Sort {
+  Ascending,
+  Descending
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query the database for all records that intersect `bbox`, returning them
+  /// sorted along dimension `dim` in the direction given by `sort`. Interval
+  /// coordinates are sorted by their upper bound.
+  ///
+  /// Each matching data block (and the staging area) is sorted on its own as
+  /// a run and the runs are combined with a k-way merge as the iterator
+  /// advances, so taking the first N results (the most recent N observations
+  /// in a region, for example) never builds a single sorted list of every
+  /// match.
+  pub fn query_sorted (&mut self, bbox: &P::Bounds, dim: usize, sort: Sort)
+  -> Result<SortedIterator<P,V>,Error> {
+    let deletes = self.staging.delete_set.try_borrow()?.clone();
+    let mut runs = vec![];
+    let mut staging = vec![];
+    self.staging.for_each(|p,v,loc| {
+      if p.overlaps(bbox) {
+        staging.push((*p,v.clone(),loc));
+      }
+      Ok(())
+    })?;
+    runs.push(staging);
+    let mut blocks = vec![];
+    for tree in self.trees.iter() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      blocks.extend(tree.query_blocks(bbox)?);
+    }
+    read_runs(&self.data_store, &blocks, bbox, &deletes, &mut runs)?;
+    Ok(SortedIterator::new(runs, dim % P::dim(), sort))
+  }
+}
+
+fn read_runs<S,P,V> (data_store: &Rc<RefCell<DataStore<S,P,V>>>,
+blocks: &[u64], bbox: &P::Bounds, deletes: &DeleteSet,
+runs: &mut Vec<Vec<(P,V,Location)>>) -> Result<(),Error>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  let mut dstore = data_store.try_borrow_mut()?;
+  for offset in blocks {
+    let mut run = dstore.query(*offset, bbox)?;
+    run.retain(|(_,_,loc)| !deletes.contains(loc));
+    runs.push(run);
+  }
+  Ok(())
+}
+
+/// Iterator of `(Point,Value,Location)` results in sorted order, returned by
+/// `db.query_sorted()`.
+pub struct SortedIterator<P,V> where P: Point, V: Value {
+  runs: Vec<std::vec::IntoIter<(P,V,Location)>>,
+  heap: BinaryHeap<Head<P,V>>
+}
+
+impl<P,V> SortedIterator<P,V> where P: Point, V: Value {
+  pub(crate) fn new (runs: Vec<Vec<(P,V,Location)>>, dim: usize, sort: Sort)
+  -> Self {
+    let mut iters = Vec::with_capacity(runs.len());
+    let mut heap = BinaryHeap::with_capacity(runs.len());
+    for mut run in runs.into_iter() {
+      run.sort_by(|a,b| compare(&a.0, &b.0, dim, sort));
+      let mut iter = run.into_iter();
+      if let Some(row) = iter.next() {
+        heap.push(Head { row, run: iters.len(), dim, sort });
+      }
+      iters.push(iter);
+    }
+    Self { runs: iters, heap }
+  }
+}
+
+impl<P,V> Iterator for SortedIterator<P,V> where P: Point, V: Value {
+  type Item = (P,V,Location);
+  fn next (&mut self) -> Option<Self::Item> {
+    let head = self.heap.pop()?;
+    if let Some(row) = self.runs[head.run].next() {
+      let (run,dim,sort) = (head.run,head.dim,head.sort);
+      self.heap.push(Head { row, run, dim, sort });
+    }
+    Some(head.row)
+  }
+}
+
+// compare points along a dimension, ordering intervals by their upper bound
+fn compare<P> (a: &P, b: &P, dim: usize, sort: Sort) -> Ordering
+where P: Point {
+  let o = a.midpoint_upper(a).cmp_at(&b.midpoint_upper(b), dim);
+  match sort {
+    Sort::Ascending => o,
+    Sort::Descending => o.reverse()
+  }
+}
+
+struct Head<P,V> where P: Point, V: Value {
+  row: (P,V,Location),
+  run: usize,
+  dim: usize,
+  sort: Sort
+}
+
+// BinaryHeap is a max-heap, so the ordering is reversed to pop the first row
+impl<P,V> Ord for Head<P,V> where P: Point, V: Value {
+  fn cmp (&self, other: &Self) -> Ordering {
+    compare(&other.row.0, &self.row.0, self.dim, self.sort)
+      .then(other.run.cmp(&self.run))
+  }
+}
+impl<P,V> PartialOrd for Head<P,V> where P: Point, V: Value {
+  fn partial_cmp (&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl<P,V> PartialEq for Head<P,V> where P: Point, V: Value {
+  fn eq (&self, other: &Self) -> bool {
+    self.cmp(other) == Ordering::Equal
+  }
+}
+impl<P,V> Eq for Head<P,V> where P: Point, V: Value {}
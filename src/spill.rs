@@ -0,0 +1,159 @@
+use crate::{Point,Value,Location};
+use crate::sorted::{Sort,SortedIterator};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use desert::{FromBytes,ToBytes,CountBytes};
+use std::marker::PhantomData;
+
+/// Sorted runs of staged inserts spilled to the `staging_runs` store, enabled
+/// with `Setup::staging_spill()`.
+///
+/// Each run is appended as `[row count: u32][byte length: u64][rows]` with
+/// its rows sorted along the first dimension, so that the runs can be combined
+/// with a k-way merge when the staged rows are built into a tree. Only the
+/// index of run offsets is kept in memory.
+pub struct Runs<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  pub store: S,
+  // offset of the rows, row count and byte length of each run
+  index: Vec<(u64,u32,u64)>,
+  rows: usize,
+  pub max_bytes: usize,
+  _marker: PhantomData<(P,V)>
+}
+
+impl<S,P,V> Runs<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  pub fn open (mut store: S, max_bytes: usize) -> Result<Self,Error> {
+    let mut index = vec![];
+    let mut rows = 0;
+    let len = store.len()?;
+    let mut offset = 0;
+    while offset < len {
+      let buf = store.read(offset, 12)?;
+      let count = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+      let size = u64::from_be_bytes([
+        buf[4], buf[5], buf[6], buf[7], buf[8], buf[9], buf[10], buf[11]
+      ]);
+      if offset + 12 + size > len {
+        bail!["run at {} extends past the end of the store", offset];
+      }
+      index.push((offset+12,count,size));
+      rows += count as usize;
+      offset += 12 + size;
+    }
+    Ok(Self { store, index, rows, max_bytes, _marker: PhantomData })
+  }
+
+  /// Number of rows in every run.
+  pub fn len (&self) -> usize {
+    self.rows
+  }
+
+  pub fn is_empty (&self) -> bool {
+    self.rows == 0
+  }
+
+  /// Sort `rows` and append them as a new run.
+  pub fn write (&mut self, rows: &mut Vec<(P,V)>) -> Result<(),Error> {
+    if rows.is_empty() { return Ok(()) }
+    rows.sort_by(|a,b| compare(&a.0, &b.0));
+    let size: usize = rows.iter().map(|row| row.count_bytes()).sum();
+    let mut buf = vec![0u8;12+size];
+    buf[0..4].copy_from_slice(&(rows.len() as u32).to_be_bytes());
+    buf[4..12].copy_from_slice(&(size as u64).to_be_bytes());
+    let mut offset = 12;
+    for row in rows.iter() {
+      offset += row.write_bytes(&mut buf[offset..])?;
+    }
+    let store_offset = self.store.len()?;
+    self.store.write(store_offset, &buf)?;
+    self.index.push((store_offset+12,rows.len() as u32,size as u64));
+    self.rows += rows.len();
+    Ok(())
+  }
+
+  /// Read the rows of run `i`.
+  pub fn read (&mut self, i: usize) -> Result<Vec<(P,V)>,Error> {
+    let (offset,count,size) = self.index[i];
+    let buf = self.store.read(offset, size)?;
+    let mut rows = Vec::with_capacity(count as usize);
+    let mut offset = 0;
+    for _ in 0..count {
+      let (n,row) = <(P,V)>::from_bytes(&buf[offset..])?;
+      rows.push(row);
+      offset += n;
+    }
+    Ok(rows)
+  }
+
+  /// Call `f` with every row and its staging index, one run at a time.
+  pub fn scan<F> (&mut self, mut f: F) -> Result<(),Error>
+  where F: FnMut(u32,&P,&V) -> Result<(),Error> {
+    let mut i = 0;
+    for r in 0..self.index.len() {
+      for (p,v) in self.read(r)?.iter() {
+        f(i, p, v)?;
+        i += 1;
+      }
+    }
+    Ok(())
+  }
+
+  /// Read every run, skipping the staging indexes in `skip`, and combine them
+  /// into a single list in sorted order.
+  pub fn merge<F> (&mut self, skip: F) -> Result<Vec<(P,V)>,Error>
+  where F: Fn(u32) -> bool {
+    let mut runs = Vec::with_capacity(self.index.len());
+    let mut i = 0;
+    for r in 0..self.index.len() {
+      let mut run = vec![];
+      for (p,v) in self.read(r)?.into_iter() {
+        if !skip(i) { run.push((p,v,(0,i))) }
+        i += 1;
+      }
+      runs.push(run);
+    }
+    Ok(SortedIterator::new(runs, 0, Sort::Ascending).map(|(p,v,_)| (p,v))
+      .collect())
+  }
+
+  /// Read every run in staging index order.
+  pub fn concat (&mut self) -> Result<Vec<(P,V)>,Error> {
+    let mut rows = Vec::with_capacity(self.rows);
+    for r in 0..self.index.len() {
+      rows.extend(self.read(r)?);
+    }
+    Ok(rows)
+  }
+
+  /// Rows from every run that overlap `bbox`, skipping `deleted` locations.
+  pub fn query<F> (&mut self, bbox: &P::Bounds, deleted: F)
+  -> Result<Vec<(P,V,Location)>,Error> where F: Fn(&Location) -> bool {
+    let mut results = vec![];
+    self.scan(|i,p,v| {
+      if !deleted(&(0,i)) && p.overlaps(bbox) {
+        results.push((*p,v.clone(),(0,i)));
+      }
+      Ok(())
+    })?;
+    Ok(results)
+  }
+
+  pub fn clear (&mut self) -> Result<(),Error> {
+    if !self.index.is_empty() {
+      self.store.truncate(0)?;
+      self.index.clear();
+      self.rows = 0;
+    }
+    Ok(())
+  }
+
+  pub fn commit (&mut self) -> Result<(),Error> {
+    self.store.sync_all()
+  }
+}
+
+fn compare<P> (a: &P, b: &P) -> std::cmp::Ordering where P: Point {
+  a.midpoint_upper(a).cmp_at(&b.midpoint_upper(b), 0)
+}
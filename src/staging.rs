@@ -1,22 +1,40 @@
-use crate::{Point,Value,Location,write_cache::WriteCache};
-use failure::{Error};
+use crate::{Point,Value,Location,write_cache::WriteCache,instrument};
+use crate::spill::Runs;
+use crate::staging_index::StagingIndex;
+use crate::delete_set::DeleteSet;
+use crate::recovery::RecoveryReport;
+use crate::varint::VarLocation;
+use failure::{Error,bail};
 use random_access_storage::RandomAccess;
 use std::collections::HashSet;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell,RefCell};
 use desert::{FromBytes,ToBytes,CountBytes};
 
 pub struct StagingIterator<'b,P,V> where P: Point, V: Value {
   inserts: Rc<RefCell<Vec<(P,V)>>>,
-  deletes: Rc<RefCell<HashSet<Location>>>,
+  deletes: Rc<RefCell<DeleteSet>>,
   bbox: &'b P::Bounds,
-  index: u32
+  // in-memory rows that may intersect bbox
+  candidates: std::vec::IntoIter<u32>,
+  // matching spilled rows, returned first
+  spilled: std::vec::IntoIter<(P,V,Location)>,
+  // staging index of the first in-memory insert
+  offset: u32
 }
 
 impl<'b,P,V> StagingIterator<'b,P,V> where P: Point, V: Value {
   pub fn new (inserts: Rc<RefCell<Vec<(P,V)>>>,
-  deletes: Rc<RefCell<HashSet<Location>>>, bbox: &'b P::Bounds) -> Self {
-    Self { index: 0, bbox, inserts, deletes }
+  deletes: Rc<RefCell<DeleteSet>>, bbox: &'b P::Bounds,
+  candidates: Vec<u32>, spilled: Vec<(P,V,Location)>, offset: u32) -> Self {
+    Self {
+      candidates: candidates.into_iter(),
+      bbox,
+      inserts,
+      deletes,
+      spilled: spilled.into_iter(),
+      offset
+    }
   }
 }
 
@@ -24,16 +42,23 @@ impl<'b,P,V> Iterator for StagingIterator<'b,P,V>
 where P: Point, V: Value {
   type Item = Result<(P,V,Location),Error>;
   fn next (&mut self) -> Option<Self::Item> {
-    let len = iwrap![self.inserts.try_borrow()].len();
-    while (self.index as usize) < len {
-      let i = self.index;
-      self.index += 1;
-      if iwrap![self.deletes.try_borrow()].contains(&(0,i)) {
+    if let Some(row) = self.spilled.next() {
+      instrument::staging_hit();
+      return Some(Ok(row));
+    }
+    while let Some(i) = self.candidates.next() {
+      let loc = (0, self.offset + i);
+      if iwrap![self.deletes.try_borrow()].contains(&loc) {
         continue;
       }
-      let (point,value) = &iwrap![self.inserts.try_borrow()][i as usize];
+      let inserts = iwrap![self.inserts.try_borrow()];
+      let (point,value) = match inserts.get(i as usize) {
+        Some(row) => row,
+        None => continue
+      };
       if point.overlaps(self.bbox) {
-        return Some(Ok((*point,value.clone(),(0, i))));
+        instrument::staging_hit();
+        return Some(Ok((*point,value.clone(),loc)));
       }
     }
     None
@@ -42,50 +67,85 @@ where P: Point, V: Value {
 
 pub struct Staging<S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
-  insert_store: WriteCache<S>,
-  delete_store: WriteCache<S>,
+  pub insert_store: WriteCache<S>,
+  pub delete_store: WriteCache<S>,
   pub inserts: Rc<RefCell<Vec<(P,V)>>>,
   pub deletes: Rc<RefCell<Vec<Location>>>,
-  pub delete_set: Rc<RefCell<HashSet<Location>>>
+  pub delete_set: Rc<RefCell<DeleteSet>>,
+  // inserts spilled to disk, which come before the in-memory inserts in
+  // staging index order
+  pub runs: Option<Runs<S,P,V>>,
+  // spatial index over the in-memory inserts for queries
+  index: StagingIndex<P>,
+  // whether commit() flushes the write caches, per the durability policy
+  pub sync: Rc<Cell<bool>>,
+  // bytes of staged records written since the stores were opened
+  pub written: u64,
+  // serialization buffer reused between batches
+  scratch: Vec<u8>,
+  // repairs made by load()
+  pub recovery: RecoveryReport,
+  // whether deletes are written as varints, from FORMAT_VARINT_DELETES on
+  varint: bool
 }
 
 impl<S,P,V> Staging<S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
-  pub fn open (istore: S, dstore: S) -> Result<Self,Error> {
+  pub fn open (istore: S, dstore: S, varint: bool, strict: bool, load: bool)
+  -> Result<Self,Error> {
     let mut staging = Self {
       insert_store: WriteCache::open(istore)?,
       delete_store: WriteCache::open(dstore)?,
       inserts: Rc::new(RefCell::new(vec![])),
       deletes: Rc::new(RefCell::new(vec![])),
-      delete_set: Rc::new(RefCell::new(HashSet::new()))
+      delete_set: Rc::new(RefCell::new(DeleteSet::new())),
+      runs: None,
+      index: StagingIndex::new(),
+      sync: Rc::new(Cell::new(true)),
+      written: 0,
+      scratch: vec![],
+      recovery: RecoveryReport::default(),
+      varint
     };
-    staging.load()?;
+    if load {
+      staging.load(strict)?;
+    }
     Ok(staging)
   }
-  fn load (&mut self) -> Result<(),Error> {
+  // read the staging stores, truncating any partial record at the end of a
+  // store unless `strict` is set
+  fn load (&mut self, strict: bool) -> Result<(),Error> {
     if !self.insert_store.is_empty()? {
-      self.inserts.try_borrow_mut()?.clear();
       let len = self.insert_store.len()?;
       let buf = self.insert_store.read(0, len)?;
-      let mut offset = 0;
-      while offset < len as usize {
-        let (size,pv) = <(P,V)>::from_bytes(&buf[offset..])?;
-        self.inserts.try_borrow_mut()?.push(pv);
-        offset += size;
+      let (rows,end) = read_records::<(P,V)>(&buf, strict,
+        "staging_inserts")?;
+      if end < buf.len() {
+        self.insert_store.truncate(end as u64)?;
+        self.insert_store.sync_all()?;
+        self.recovery.insert_bytes_discarded = (buf.len()-end) as u64;
       }
+      self.recovery.inserts_recovered = rows.len() as u64;
+      *self.inserts.try_borrow_mut()? = rows;
     }
     if !self.delete_store.is_empty()? {
-      self.deletes.try_borrow_mut()?.clear();
-      self.delete_set.try_borrow_mut()?.clear();
       let len = self.delete_store.len()?;
       let buf = self.delete_store.read(0, len)?;
-      let mut offset = 0;
-      while offset < len as usize {
-        let (size,loc) = Location::from_bytes(&buf[offset..])?;
-        self.deletes.try_borrow_mut()?.push(loc);
-        self.delete_set.try_borrow_mut()?.insert(loc);
-        offset += size;
+      let (locs,end) = if self.varint {
+        let (locs,end) = read_records::<VarLocation>(&buf, strict,
+          "staging_deletes")?;
+        (locs.into_iter().map(|loc| loc.0).collect(), end)
+      } else {
+        read_records::<Location>(&buf, strict, "staging_deletes")?
+      };
+      if end < buf.len() {
+        self.delete_store.truncate(end as u64)?;
+        self.delete_store.sync_all()?;
+        self.recovery.delete_bytes_discarded = (buf.len()-end) as u64;
       }
+      self.recovery.deletes_recovered = locs.len() as u64;
+      *self.delete_set.try_borrow_mut()? = locs.iter().copied().collect();
+      *self.deletes.try_borrow_mut()? = locs;
     }
     Ok(())
   }
@@ -97,6 +157,10 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   pub fn clear_inserts (&mut self) -> Result<(),Error> {
     self.insert_store.truncate(0)?;
     self.inserts.try_borrow_mut()?.clear();
+    self.index.invalidate();
+    if let Some(runs) = &mut self.runs {
+      runs.clear()?;
+    }
     Ok(())
   }
   pub fn clear_deletes (&mut self) -> Result<(),Error> {
@@ -106,6 +170,9 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
     Ok(())
   }
   pub fn delete (&mut self, deletes: &Vec<Location>) -> Result<(),Error> {
+    if self.spilled() > 0 && deletes.iter().any(|loc| loc.0 == 0) {
+      self.unspill()?;
+    }
     let mut del_set: HashSet<u32> = HashSet::new();
     for delete in deletes {
       if delete.0 == 0 { del_set.insert(delete.1); }
@@ -116,45 +183,91 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       i += 1;
       !del_set.contains(&j)
     });
+    self.index.invalidate();
+    if self.inserts.try_borrow()?.len() < i as usize {
+      // rewrite the staged inserts so deleted rows stay gone after reopening
+      let inserts = std::mem::take(&mut *self.inserts.try_borrow_mut()?);
+      self.insert_store.truncate(0)?;
+      self.append(&inserts, &vec![])?;
+    }
     Ok(())
   }
   pub fn bytes (&mut self) -> Result<u64,Error> {
     Ok(self.insert_store.len()? + self.delete_store.len()?)
   }
   pub fn len (&mut self) -> Result<usize,Error> {
-    Ok(self.inserts.try_borrow()?.len() + self.deletes.try_borrow()?.len())
+    Ok(self.inserts_len()? + self.deletes.try_borrow()?.len())
+  }
+  /// Number of staged inserts, including spilled rows.
+  pub fn inserts_len (&self) -> Result<usize,Error> {
+    Ok(self.spilled() + self.inserts.try_borrow()?.len())
+  }
+  /// Number of staged inserts that were spilled to sorted runs.
+  pub fn spilled (&self) -> usize {
+    self.runs.as_ref().map(|runs| runs.len()).unwrap_or(0)
+  }
+  /// Call `f` with every staged insert that has not been deleted, including
+  /// spilled rows, along with its location.
+  pub fn for_each<F> (&mut self, mut f: F) -> Result<(),Error>
+  where F: FnMut(&P,&V,Location) -> Result<(),Error> {
+    let deletes = Rc::clone(&self.delete_set);
+    let offset = self.spilled() as u32;
+    if let Some(runs) = &mut self.runs {
+      let deletes = deletes.try_borrow()?;
+      runs.scan(|i,p,v| {
+        if deletes.contains(&(0,i)) { Ok(()) } else { f(p,v,(0,i)) }
+      })?;
+    }
+    let deletes = deletes.try_borrow()?;
+    for (i,(p,v)) in self.inserts.try_borrow()?.iter().enumerate() {
+      let loc = (0,offset+i as u32);
+      if !deletes.contains(&loc) {
+        f(p,v,loc)?;
+      }
+    }
+    Ok(())
   }
   pub fn batch (&mut self, inserts: &Vec<(P,V)>, deletes: &Vec<Location>)
   -> Result<(),Error> {
-    // todo: calculate the necessary size before allocating
+    self.append(inserts, deletes)?;
+    self.spill()
+  }
+  fn append (&mut self, inserts: &Vec<(P,V)>, deletes: &Vec<Location>)
+  -> Result<(),Error> {
     let mut i_size = 0;
     for insert in inserts.iter() {
       i_size += insert.count_bytes();
     }
-    let mut ibuf = vec![0u8;i_size];
+    self.scratch.clear();
+    self.scratch.resize(i_size, 0);
     {
       let mut i_offset = 0;
       for insert in inserts.iter() {
-        i_offset += insert.write_bytes(&mut ibuf[i_offset..])?;
+        i_offset += insert.write_bytes(&mut self.scratch[i_offset..])?;
       }
     }
+    let i_offset = self.insert_store.len()?;
+    self.insert_store.write(i_offset,&self.scratch)?;
+    self.written += self.scratch.len() as u64;
 
     let mut d_size = 0;
     for delete in deletes.iter() {
-      d_size += delete.count_bytes();
+      d_size += if self.varint { VarLocation(*delete).count_bytes() }
+        else { delete.count_bytes() };
     }
-    let mut dbuf = vec![0u8;d_size];
+    self.scratch.clear();
+    self.scratch.resize(d_size, 0);
     {
       let mut d_offset = 0;
       for delete in deletes.iter() {
-        d_offset += delete.write_bytes(&mut dbuf[d_offset..])?;
+        let buf = &mut self.scratch[d_offset..];
+        d_offset += if self.varint { VarLocation(*delete).write_bytes(buf)? }
+          else { delete.write_bytes(buf)? };
       }
     }
-
-    let i_offset = self.insert_store.len()?;
-    self.insert_store.write(i_offset,&ibuf)?;
     let d_offset = self.delete_store.len()?;
-    self.delete_store.write(d_offset,&dbuf)?;
+    self.delete_store.write(d_offset,&self.scratch)?;
+    self.written += self.scratch.len() as u64;
     self.inserts.try_borrow_mut()?.extend_from_slice(inserts);
     self.deletes.try_borrow_mut()?.extend_from_slice(deletes);
     for delete in deletes {
@@ -162,17 +275,162 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
     }
     Ok(())
   }
+  // move the in-memory inserts into a new sorted run once the staging store
+  // grows past the spill threshold, dropping staged rows that were deleted
+  fn spill (&mut self) -> Result<(),Error> {
+    match &self.runs {
+      Some(runs) if self.insert_store.len()? > runs.max_bytes as u64 => {},
+      _ => return Ok(())
+    }
+    let offset = self.spilled() as u32;
+    let mut rows = vec![];
+    {
+      let mut delete_set = self.delete_set.try_borrow_mut()?;
+      for (i,row) in self.inserts.try_borrow_mut()?.drain(..).enumerate() {
+        if !delete_set.remove(&(0,offset+i as u32)) {
+          rows.push(row);
+        }
+      }
+    }
+    self.index.invalidate();
+    if let Some(runs) = &mut self.runs {
+      runs.write(&mut rows)?;
+      runs.commit()?;
+    }
+    self.insert_store.truncate(0)?;
+    self.resolve_deletes(offset)
+  }
+  // drop staged deletes of in-memory rows at or after staging index `offset`
+  // once those rows have been removed
+  fn resolve_deletes (&mut self, offset: u32) -> Result<(),Error> {
+    let deletes: Vec<Location> = self.deletes.try_borrow()?.iter()
+      .filter(|loc| loc.0 != 0 || loc.1 < offset)
+      .copied()
+      .collect();
+    if deletes.len() < self.deletes.try_borrow()?.len() {
+      self.clear_deletes()?;
+      self.append(&vec![], &deletes)?;
+    }
+    Ok(())
+  }
+  /// Rewrite the staged deletes with varints, for `db.migrate()`.
+  pub fn migrate_deletes (&mut self) -> Result<(),Error> {
+    if self.varint { return Ok(()) }
+    self.varint = true;
+    let deletes = self.deletes.try_borrow()?.clone();
+    self.clear_deletes()?;
+    self.append(&vec![], &deletes)?;
+    self.delete_store.sync_all()
+  }
+  // move spilled rows back into memory ahead of the in-memory inserts
+  fn restore (&mut self, mut rows: Vec<(P,V)>) -> Result<(),Error> {
+    rows.extend(self.inserts.try_borrow_mut()?.drain(..));
+    self.index.invalidate();
+    self.insert_store.truncate(0)?;
+    self.append(&rows, &vec![])?;
+    if let Some(runs) = &mut self.runs {
+      runs.clear()?;
+    }
+    Ok(())
+  }
+  // restore spilled rows in staging index order, so locations stay valid
+  fn unspill (&mut self) -> Result<(),Error> {
+    let rows = match &mut self.runs {
+      Some(runs) => runs.concat()?,
+      None => return Ok(())
+    };
+    self.restore(rows)
+  }
+  /// Combine the spilled runs with a k-way merge and move them back into
+  /// memory, dropping staged rows that were deleted. Staging indexes change,
+  /// so this is only done right before the staged rows are built into a tree.
+  pub fn merge_runs (&mut self) -> Result<(),Error> {
+    if self.spilled() == 0 { return Ok(()) }
+    let rows = match &mut self.runs {
+      Some(runs) => {
+        let deletes = self.delete_set.try_borrow()?;
+        runs.merge(|i| deletes.contains(&(0,i)))?
+      },
+      None => return Ok(())
+    };
+    let offset = self.spilled() as u32;
+    {
+      let deletes = self.delete_set.try_borrow()?;
+      let mut i = 0;
+      self.inserts.try_borrow_mut()?.retain(|_row| {
+        let j = i;
+        i += 1;
+        !deletes.contains(&(0,offset+j))
+      });
+    }
+    self.delete_set.try_borrow_mut()?.retain(|loc| loc.0 != 0);
+    self.restore(rows)?;
+    self.resolve_deletes(0)
+  }
   pub fn commit (&mut self) -> Result<(),Error> {
+    if !self.sync.get() { return Ok(()) }
     self.insert_store.sync_all()?;
     self.delete_store.sync_all()?;
+    if let Some(runs) = &mut self.runs {
+      runs.commit()?;
+    }
     Ok(())
   }
+  /// Limit the bytes queued in each write cache, or `0` for no limit.
+  pub fn set_cache_limit (&mut self, max_bytes: usize) -> Result<(),Error> {
+    self.insert_store.set_max_bytes(max_bytes)?;
+    self.delete_store.set_max_bytes(max_bytes)?;
+    Ok(())
+  }
+  /// Bytes queued in the write caches that have not been written to the
+  /// staging stores.
+  pub fn cached_bytes (&self) -> usize {
+    self.insert_store.cached_bytes() + self.delete_store.cached_bytes()
+  }
   pub fn query<'b> (&mut self, bbox: &'b P::Bounds)
-  -> StagingIterator<'b,P,V> {
-    <StagingIterator<'b,P,V>>::new(
+  -> Result<StagingIterator<'b,P,V>,Error> {
+    let offset = self.spilled() as u32;
+    let spilled = match &mut self.runs {
+      Some(runs) => {
+        let deletes = self.delete_set.try_borrow()?;
+        runs.query(bbox, |loc| deletes.contains(loc))?
+      },
+      None => vec![]
+    };
+    let candidates = self.index.candidates(&self.inserts.try_borrow()?, bbox);
+    Ok(<StagingIterator<'b,P,V>>::new(
       Rc::clone(&self.inserts),
       Rc::clone(&self.delete_set),
-      bbox
-    )
+      bbox,
+      candidates,
+      spilled,
+      offset
+    ))
+  }
+}
+
+// Decode the records of a staging store, returning them along with the length
+// of the bytes that hold complete records. Decoding stops at the first record
+// that does not fit in `buf`, which is an error if `strict` is set.
+fn read_records<T> (buf: &[u8], strict: bool, name: &str)
+-> Result<(Vec<T>,usize),Error> where T: FromBytes {
+  let mut records = vec![];
+  let mut offset = 0;
+  while offset < buf.len() {
+    match T::from_bytes(&buf[offset..]) {
+      Ok((size,record)) if size > 0 && offset+size <= buf.len() => {
+        records.push(record);
+        offset += size;
+      },
+      result => {
+        if strict {
+          bail!["{} has {} unreadable bytes at offset {}{}", name,
+            buf.len()-offset, offset,
+            result.err().map(|e| format!(": {}", e)).unwrap_or_default()];
+        }
+        break;
+      }
+    }
   }
+  Ok((records,offset))
 }
@@ -0,0 +1,89 @@
+use crate::{Point,PointOrd,Value};
+use crate::curve::{self,Curve};
+
+// rows per chunk
+const CHUNK_SIZE: usize = 128;
+// tail rows that are always scanned linearly before the index is rebuilt
+const MIN_TAIL: usize = 1_024;
+
+/// Coarse spatial index over the in-memory staging inserts, so that a query
+/// only tests the rows in chunks whose bounds intersect the query instead of
+/// every staged row.
+///
+/// Rows are grouped along a Hilbert curve into chunks of up to `CHUNK_SIZE`
+/// rows, each with the bounding range of its rows. Rows staged after the last
+/// build form an unindexed tail that is scanned in full until it grows past
+/// an eighth of the staging area, when the index is rebuilt from scratch.
+/// This keeps the cost of rebuilds amortized over the rows they index.
+///
+/// Pruning trusts `Point::bounds()` to cover every row it was given. If any
+/// row does not overlap the bounds of its own chunk, the bounds are taken to
+/// disagree with `Point::overlaps()` and every chunk is scanned instead.
+pub struct StagingIndex<P> where P: Point {
+  chunks: Vec<(Option<P::Range>,Vec<u32>)>,
+  // rows 0..indexed are covered by the chunks
+  indexed: usize,
+  dirty: bool
+}
+
+impl<P> StagingIndex<P> where P: Point {
+  pub fn new () -> Self {
+    Self { chunks: vec![], indexed: 0, dirty: false }
+  }
+
+  /// Mark the index as out of date after rows were removed or reordered.
+  pub fn invalidate (&mut self) {
+    self.dirty = true;
+  }
+
+  // rebuild the index if rows were changed or the tail grew too large
+  fn update<V> (&mut self, rows: &[(P,V)]) where V: Value {
+    let tail = rows.len().saturating_sub(self.indexed);
+    if self.dirty || rows.len() < self.indexed
+    || tail > MIN_TAIL.max(rows.len()/8) {
+      self.rebuild(rows);
+    }
+  }
+
+  fn rebuild<V> (&mut self, rows: &[(P,V)]) where V: Value {
+    let mut order: Vec<(P,u32)> = rows.iter().enumerate()
+      .map(|(i,(p,_))| (*p,i as u32))
+      .collect();
+    curve::sort(Curve::Hilbert, &mut order);
+    self.chunks.clear();
+    let mut trusted = true;
+    for chunk in order.chunks(CHUNK_SIZE) {
+      let points: Vec<P> = chunk.iter().map(|(p,_)| *p).collect();
+      let bbox = P::bounds(&points);
+      if let Some(b) = &bbox {
+        trusted = trusted && points.iter().all(|p| p.overlaps(b));
+      }
+      let range = bbox.map(P::bounds_to_range);
+      self.chunks.push((range, chunk.iter().map(|(_,i)| *i).collect()));
+    }
+    if !trusted {
+      for chunk in self.chunks.iter_mut() {
+        chunk.0 = None;
+      }
+    }
+    self.indexed = rows.len();
+    self.dirty = false;
+  }
+
+  /// Indexes of the rows that may intersect `bbox`, in ascending order.
+  pub fn candidates<V> (&mut self, rows: &[(P,V)], bbox: &P::Bounds)
+  -> Vec<u32> where V: Value {
+    self.update(rows);
+    let qbox = <P::Range as PointOrd>::bounds(&vec![P::bounds_to_range(*bbox)]);
+    let mut candidates = vec![];
+    for (range,indexes) in self.chunks.iter() {
+      match (range,&qbox) {
+        (Some(r),Some(q)) if !r.overlaps(q) => {},
+        _ => candidates.extend_from_slice(indexes)
+      }
+    }
+    candidates.sort_unstable();
+    candidates.extend((self.indexed..rows.len()).map(|i| i as u32));
+    candidates
+  }
+}
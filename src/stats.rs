@@ -0,0 +1,111 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+/// Write and space amplification of a database, returned by `db.stats()`.
+///
+/// Logical bytes are the serialized size of `(point,value)` records. The
+/// write counters start at zero when the database is opened, so the write
+/// amplification covers the current session only.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct Stats {
+  /// Logical bytes of the records inserted since the database was opened.
+  pub logical_bytes: u64,
+  /// Bytes of data blocks, branch blocks and staged records written since
+  /// the database was opened.
+  pub written_bytes: u64,
+  /// Size of the `data` store, including blocks that are no longer
+  /// referenced by any tree.
+  pub data_bytes: u64,
+  /// Size of the staging stores.
+  pub staging_bytes: u64,
+  /// Logical bytes of the staged records that have not been deleted.
+  pub staging_live_bytes: u64,
+  /// One entry for each tree that holds records.
+  pub trees: Vec<TreeStats>
+}
+
+/// Sizes of one tree, as part of `Stats`.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct TreeStats {
+  /// Position of the tree, as in `db.trees`.
+  pub index: usize,
+  /// Records in the tree that have not been deleted.
+  pub records: u64,
+  /// Logical bytes of the records that have not been deleted.
+  pub live_bytes: u64,
+  /// Bytes of the data blocks that the tree references.
+  pub total_bytes: u64,
+  /// Size of the tree's store of branch blocks.
+  pub branch_bytes: u64
+}
+
+impl Stats {
+  /// Bytes written for each logical byte inserted, or 0 before any inserts.
+  pub fn write_amplification (&self) -> f64 {
+    if self.logical_bytes == 0 { return 0.0 }
+    (self.written_bytes as f64) / (self.logical_bytes as f64)
+  }
+  /// Logical bytes of every record that has not been deleted.
+  pub fn live_bytes (&self) -> u64 {
+    self.staging_live_bytes
+      + self.trees.iter().map(|t| t.live_bytes).sum::<u64>()
+  }
+  /// Bytes on disk for each live logical byte, or 0 for an empty database.
+  /// Deleted records and blocks left behind by merges raise this ratio.
+  pub fn space_amplification (&self) -> f64 {
+    let live = self.live_bytes();
+    if live == 0 { return 0.0 }
+    let total = self.data_bytes + self.staging_bytes
+      + self.trees.iter().map(|t| t.branch_bytes).sum::<u64>();
+    (total as f64) / (live as f64)
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Measure the write and space amplification of the database.
+  ///
+  /// This walks every tree and reads every data block, so it costs about as
+  /// much io as a query over the whole database.
+  pub fn stats (&mut self) -> Result<Stats,Error> {
+    let mut stats = Stats::default();
+    stats.logical_bytes = self.logical_bytes;
+    stats.written_bytes = self.staging.written;
+    let mut staging_live = 0;
+    self.staging.for_each(|p,v,_| {
+      staging_live += (p.count_bytes() + v.count_bytes()) as u64;
+      Ok(())
+    })?;
+    stats.staging_live_bytes = staging_live;
+    stats.staging_bytes = self.staging.bytes()?;
+    let deletes = self.staging.delete_set.try_borrow()?;
+    for (i,tree) in self.trees.iter().enumerate() {
+      let mut tree = tree.try_borrow_mut()?;
+      stats.written_bytes += tree.written;
+      if tree.is_empty()? { continue }
+      let mut tstats = TreeStats {
+        index: i,
+        branch_bytes: tree.bytes,
+        ..TreeStats::default()
+      };
+      let blocks = tree.blocks()?;
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      for offset in blocks {
+        tstats.total_bytes += (dstore.read(offset)?.len() + 4) as u64;
+        for (p,v,loc) in dstore.list(offset)? {
+          if deletes.contains(&loc) { continue }
+          tstats.records += 1;
+          tstats.live_bytes += (p.count_bytes() + v.count_bytes()) as u64;
+        }
+      }
+      stats.trees.push(tstats);
+    }
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    stats.written_bytes += dstore.written;
+    stats.data_bytes = dstore.bytes()?;
+    Ok(stats)
+  }
+}
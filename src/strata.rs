@@ -0,0 +1,167 @@
+use crate::{DB,Setup,Point,Value,Row,Location,QueryIterator};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::rc::Rc;
+
+/// Storage function used by each tier of a `Strata` database.
+pub type TierStore<S> = Box<dyn Fn(&str) -> Result<S,Error>>;
+
+/// Location of a record in a `Strata` database: the tier index and the
+/// location within that tier.
+pub type TierLocation = (usize,Location);
+
+/// Database that separates rows into tiers by the size of their intervals.
+///
+/// Features with very large intervals (continent-sized polygons) overlap
+/// nearly every query and crowd the upper levels of the trees. A `Strata`
+/// database keeps each tier in its own set of stores: tier `0` uses the
+/// ordinary store names and tier `k` prefixes every name with `tier{k}_`.
+///
+/// `classify` picks the tier for each inserted point, with larger tiers for
+/// larger features. `select` picks the highest tier to read for a query
+/// bounding box, so like a tile pyramid, a small query can skip the tiers of
+/// features that are too large to be relevant. Skipped tiers are not
+/// searched at all, so queries only return every intersecting record when
+/// `select` returns the last tier.
+pub struct Strata<S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  pub tiers: Vec<DB<S,TierStore<S>,P,V>>,
+  classify: Box<dyn Fn(&P) -> usize>,
+  select: Box<dyn Fn(&P::Bounds) -> usize>
+}
+
+impl<S,P,V> Strata<S,P,V> where
+S: RandomAccess<Error=Error>+'static, P: Point, V: Value {
+  /// Open `count` tiers with the configuration in `setup`.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{Setup,Strata};
+  /// # use failure::Error;
+  /// # use random_access_disk::RandomAccessDisk;
+  /// # use std::path::PathBuf;
+  /// # fn main () -> Result<(),Error> {
+  /// type P = ((f32,f32),(f32,f32));
+  /// type V = u32;
+  /// let mut db: Strata<_,P,V> = Strata::open(Setup::new(storage), 2,
+  ///   |p: &P| if ((p.0).1-(p.0).0) > 1.0 { 1 } else { 0 },
+  ///   |bbox: &((f32,f32),(f32,f32))| {
+  ///     if (bbox.1).0-(bbox.0).0 > 1.0 { 1 } else { 0 }
+  ///   }
+  /// )?;
+  /// # Ok(()) }
+  /// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+  /// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+  /// #   p.push(name);
+  /// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  /// # }
+  /// ```
+  pub fn open<U,C,T> (setup: Setup<S,U>, count: usize, classify: C, select: T)
+  -> Result<Self,Error> where
+  U: (Fn(&str) -> Result<S,Error>)+'static,
+  C: Fn(&P) -> usize+'static,
+  T: Fn(&P::Bounds) -> usize+'static {
+    if count == 0 {
+      bail!["a strata database needs at least one tier"];
+    }
+    if setup.log.is_some() {
+      bail!["Setup::log() is not supported by a strata database"];
+    }
+    let open_store = Rc::new(setup.open_store);
+    let mut tiers = Vec::with_capacity(count);
+    for k in 0..count {
+      let open = Rc::clone(&open_store);
+      let store: TierStore<S> = if k == 0 {
+        Box::new(move |name: &str| (*open)(name))
+      } else {
+        Box::new(move |name: &str| (*open)(&format!["tier{}_{}",k,name]))
+      };
+      tiers.push(DB::open_from_setup(Setup {
+        open_store: store,
+        fields: setup.fields.clone(),
+        log: None,
+        clock: setup.clock.clone(),
+        merge_policy: setup.merge_policy.clone(),
+        transform: setup.transform.clone(),
+        partition: setup.partition.clone()
+      })?);
+    }
+    Ok(Self {
+      tiers,
+      classify: Box::new(classify),
+      select: Box::new(select)
+    })
+  }
+
+  /// Insert rows, writing each point to the tier chosen by `classify`.
+  ///
+  /// Use `strata.delete()` to remove records: `Row::Delete` does not say which
+  /// tier a location belongs to and is rejected.
+  pub fn batch (&mut self, rows: &[Row<P,V>]) -> Result<(),Error> {
+    let mut batches: Vec<Vec<Row<P,V>>> = vec![vec![];self.tiers.len()];
+    for row in rows.iter() {
+      match row {
+        Row::Insert(p,_) => {
+          let k = (self.classify)(p).min(self.tiers.len()-1);
+          batches[k].push(row.clone());
+        },
+        Row::Delete(_) => bail!["use strata.delete() to delete records"]
+      }
+    }
+    for (k,batch) in batches.iter().enumerate() {
+      if batch.is_empty() { continue }
+      self.tiers[k].batch(batch)?;
+    }
+    Ok(())
+  }
+
+  /// Delete records by the locations returned from `strata.query()`.
+  pub fn delete (&mut self, locations: &[TierLocation]) -> Result<(),Error> {
+    let mut batches: Vec<Vec<Row<P,V>>> = vec![vec![];self.tiers.len()];
+    for (k,loc) in locations.iter() {
+      if *k >= self.tiers.len() {
+        bail!["tier {} out of bounds", k];
+      }
+      batches[*k].push(Row::Delete(*loc));
+    }
+    for (k,batch) in batches.iter().enumerate() {
+      if batch.is_empty() { continue }
+      self.tiers[k].batch(batch)?;
+    }
+    Ok(())
+  }
+
+  /// Query tiers `0` through `select(bbox)` for records that intersect
+  /// `bbox`.
+  pub fn query<'b> (&mut self, bbox: &'b P::Bounds)
+  -> Result<StrataIterator<'b,S,P,V>,Error> {
+    let max = (self.select)(bbox).min(self.tiers.len()-1);
+    let mut queries = Vec::with_capacity(max+1);
+    for (k,tier) in self.tiers.iter_mut().enumerate().take(max+1) {
+      queries.push((k,tier.query(bbox)?));
+    }
+    Ok(StrataIterator { queries })
+  }
+}
+
+/// Iterator of `Result<(Point,Value,TierLocation)>` data returned by
+/// `strata.query()`.
+pub struct StrataIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  queries: Vec<(usize,QueryIterator<'b,S,P,V>)>
+}
+
+impl<'b,S,P,V> Iterator for StrataIterator<'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  type Item = Result<(P,V,TierLocation),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    while !self.queries.is_empty() {
+      let (k,q) = &mut self.queries[0];
+      match q.next() {
+        Some(Ok((p,v,loc))) => return Some(Ok((p,v,(*k,loc)))),
+        Some(Err(e)) => return Some(Err(e)),
+        None => { self.queries.remove(0); }
+      }
+    }
+    None
+  }
+}
@@ -0,0 +1,63 @@
+use crate::{DB,Point,PointOrd,Value,Extent,QueryOptions};
+use failure::Error;
+use random_access_storage::RandomAccess;
+
+/// Bounds and record count of a region of the database, returned by
+/// `db.query_summary()` in place of the records themselves.
+#[derive(Clone,Debug)]
+pub struct Summary<P> where P: Point {
+  /// Index of the tree in the forest, or `None` for the staging area.
+  pub tree: Option<usize>,
+  /// Branch level of the summarized region, starting from `0` at the root.
+  pub depth: usize,
+  /// Bounding box of the summarized records.
+  pub bounds: Extent<P>,
+  /// Number of summarized records.
+  pub count: u64
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Query `bbox` for a coarse summary of the records instead of the records
+  /// themselves. Each tree is walked down to `opts.max_depth` branch levels
+  /// and returns one summary for each intersecting branch at that depth along
+  /// with one for each data block above it. Without `max_depth`, each
+  /// intersecting data block gets its own summary. Matching staged records
+  /// are summarized together.
+  ///
+  /// Summaries are built from the cached bounds and sizes of data blocks, so
+  /// counts include every live record in an intersecting data block, whether
+  /// or not it intersects `bbox`, and deletes that are still staged. Query
+  /// the bounds of a summary to load its records.
+  pub fn query_summary (&mut self, bbox: &P::Bounds, opts: &QueryOptions)
+  -> Result<Vec<Summary<P>>,Error> {
+    let max_depth = opts.max_depth.unwrap_or(usize::MAX);
+    let mut summaries = vec![];
+    let mut points = vec![];
+    self.staging.for_each(|p,_,_| {
+      if p.overlaps(bbox) { points.push(*p) }
+      Ok(())
+    })?;
+    if let Some(b) = P::bounds(&points) {
+      let range = P::bounds_to_range(b);
+      if let Some(bounds) = <P::Range as PointOrd>::bounds(&vec![range]) {
+        summaries.push(Summary {
+          tree: None,
+          depth: 0,
+          bounds,
+          count: points.len() as u64
+        });
+      }
+    }
+    for (i,tree) in self.trees.iter().enumerate() {
+      let mut tree = tree.try_borrow_mut()?;
+      if tree.is_empty()? { continue }
+      for (depth,bounds,count) in tree.summary(bbox, max_depth)? {
+        summaries.push(Summary { tree: Some(i), depth, bounds, count });
+      }
+    }
+    Ok(summaries)
+  }
+}
@@ -0,0 +1,170 @@
+use crate::{DB,Point,Value,Location};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::f64::consts::PI;
+use std::ops::RangeInclusive;
+
+/// Latitude limit of the web mercator projection.
+pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// XYZ web mercator tile, as used by slippy maps and tile servers. `y` counts
+/// from the top of the map. Use `Tile::from_tms()` for the flipped rows of
+/// MBTiles.
+#[derive(Clone,Copy,Debug,PartialEq,Eq,Hash)]
+pub struct Tile {
+  pub z: u32,
+  pub x: u32,
+  pub y: u32
+}
+
+impl Tile {
+  pub fn new (z: u32, x: u32, y: u32) -> Self {
+    Self { z, x, y }
+  }
+  /// Tile from the TMS row numbering of MBTiles, which counts from the
+  /// bottom of the map.
+  pub fn from_tms (z: u32, x: u32, y: u32) -> Self {
+    Self { z, x, y: (1<<z) - 1 - y }
+  }
+  /// Row of the tile in TMS numbering.
+  pub fn tms_y (&self) -> u32 {
+    (1<<self.z) - 1 - self.y
+  }
+  /// Tile at zoom `z` that contains `(lon,lat)`.
+  pub fn containing (lon: f64, lat: f64, z: u32) -> Self {
+    let n = (1u64<<z) as f64;
+    let lat = lat.max(-MAX_LATITUDE).min(MAX_LATITUDE).to_radians();
+    let x = ((lon + 180.0) / 360.0 * n).floor();
+    let y = ((1.0 - lat.tan().asinh() / PI) / 2.0 * n).floor();
+    let max = (n - 1.0).max(0.0);
+    Self { z, x: x.max(0.0).min(max) as u32, y: y.max(0.0).min(max) as u32 }
+  }
+  /// The four tiles at the next zoom level that cover this tile.
+  pub fn children (&self) -> [Self;4] {
+    let (z, x, y) = (self.z+1, self.x*2, self.y*2);
+    [Self::new(z,x,y), Self::new(z,x+1,y), Self::new(z,x,y+1),
+      Self::new(z,x+1,y+1)]
+  }
+  /// Tile at the previous zoom level that covers this tile, or `None` at zoom
+  /// 0.
+  pub fn parent (&self) -> Option<Self> {
+    if self.z == 0 { return None }
+    Some(Self::new(self.z-1, self.x/2, self.y/2))
+  }
+  /// Bounding box `((west,south),(east,north))` in degrees, grown on every
+  /// side by `buffer` times the size of the tile, such as `1.0/16.0` for a
+  /// 16 pixel margin around a 256 pixel tile. Results in the margin let
+  /// features that cross the tile edge render on both tiles.
+  pub fn bbox (&self, buffer: f64) -> ((f32,f32),(f32,f32)) {
+    let n = (1u64<<self.z) as f64;
+    let lon = |x: f64| x / n * 360.0 - 180.0;
+    let lat = |y: f64| (PI * (1.0 - 2.0 * y / n)).sinh().atan().to_degrees();
+    let (x, y) = (self.x as f64, self.y as f64);
+    let west = lon(x - buffer).max(-180.0);
+    let east = lon(x + 1.0 + buffer).min(180.0);
+    let south = if self.y+1 == n as u32 && buffer >= 0.0 { -90.0 }
+      else { lat(y + 1.0 + buffer).max(-90.0) };
+    let north = if self.y == 0 && buffer >= 0.0 { 90.0 }
+      else { lat(y - buffer).min(90.0) };
+    ((west as f32, south as f32), (east as f32, north as f32))
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point<Bounds=((f32,f32),(f32,f32))>, V: Value {
+  /// Query for the records of each tile in `tiles`, with bounding boxes from
+  /// `tile.bbox(buffer)`, returning the results grouped in the order of
+  /// `tiles`. Records that intersect several tiles are returned for each.
+  ///
+  /// Points are `(lon,lat)` in degrees, as scalars or intervals. The database
+  /// is queried once for the bounds of all the tiles, so pass tiles that are
+  /// near each other, like the tiles of a viewport.
+  pub fn query_tiles (&mut self, tiles: &[Tile], buffer: f64)
+  -> Result<Vec<(Tile,Vec<(P,V,Location)>)>,Error> {
+    if tiles.is_empty() { return Ok(vec![]) }
+    let boxes: Vec<P::Bounds> = tiles.iter().map(|t| t.bbox(buffer)).collect();
+    if boxes.iter().any(|b| (b.0).0 > (b.1).0 || (b.0).1 > (b.1).1) {
+      bail!["negative tile buffer is larger than the tiles"];
+    }
+    let mut bbox = boxes[0];
+    for b in boxes.iter().skip(1) {
+      bbox = (
+        ((bbox.0).0.min((b.0).0), (bbox.0).1.min((b.0).1)),
+        ((bbox.1).0.max((b.1).0), (bbox.1).1.max((b.1).1))
+      );
+    }
+    let mut groups: Vec<(Tile,Vec<(P,V,Location)>)> = tiles.iter()
+      .map(|t| (*t,vec![])).collect();
+    for result in self.query(&bbox)? {
+      let (p,v,loc) = result?;
+      for (i,b) in boxes.iter().enumerate() {
+        if p.overlaps(b) { groups[i].1.push((p,v.clone(),loc)) }
+      }
+    }
+    Ok(groups)
+  }
+
+  /// Call `f` with the records of every tile from zoom `zooms.start()` to
+  /// `zooms.end()` that intersects `bbox` and holds any records, with tile
+  /// bounds from `tile.bbox(buffer)`, to build a static tile set.
+  ///
+  /// The database is queried once for each tile at the lowest zoom. The
+  /// records of each tile are split among its children in memory, depth
+  /// first, so a tile and everything below it share one traversal and
+  /// memory holds one tile of records per zoom level. Pick a lowest zoom
+  /// whose tiles fit in memory. Serialize or write out each tile from `f`.
+  ///
+  /// Returns the number of tiles passed to `f`.
+  pub fn materialize_tiles<F> (&mut self, bbox: &P::Bounds,
+  zooms: RangeInclusive<u32>, buffer: f64, mut f: F) -> Result<usize,Error>
+  where F: FnMut(Tile,&[(P,V,Location)]) -> Result<(),Error> {
+    if buffer < 0.0 {
+      bail!["tile buffer must not be negative, found {}", buffer];
+    }
+    if zooms.is_empty() || *zooms.end() > 31 {
+      bail!["invalid zoom range {:?}", zooms];
+    }
+    let z = *zooms.start();
+    let ((west,south),(east,north)) = *bbox;
+    let nw = Tile::containing(west as f64, north as f64, z);
+    let se = Tile::containing(east as f64, south as f64, z);
+    let mut count = 0;
+    for x in nw.x..=se.x {
+      for y in nw.y..=se.y {
+        let tile = Tile::new(z,x,y);
+        let rows = self.query(&tile.bbox(buffer))?
+          .collect::<Result<Vec<_>,Error>>()?;
+        count += descend(tile, rows, bbox, *zooms.end(), buffer, &mut f)?;
+      }
+    }
+    Ok(count)
+  }
+}
+
+// pass the rows of `tile` to `f` and split them among its children down to
+// zoom `max_z`
+fn descend<P,V,F> (tile: Tile, rows: Vec<(P,V,Location)>,
+bbox: &((f32,f32),(f32,f32)), max_z: u32, buffer: f64, f: &mut F)
+-> Result<usize,Error> where
+P: Point<Bounds=((f32,f32),(f32,f32))>, V: Value,
+F: FnMut(Tile,&[(P,V,Location)]) -> Result<(),Error> {
+  let ((w,s),(e,n)) = tile.bbox(0.0);
+  if rows.is_empty() || w > (bbox.1).0 || e < (bbox.0).0
+  || s > (bbox.1).1 || n < (bbox.0).1 {
+    return Ok(0);
+  }
+  f(tile, &rows)?;
+  let mut count = 1;
+  if tile.z >= max_z { return Ok(count) }
+  for child in tile.children().iter() {
+    let b = child.bbox(buffer);
+    let child_rows = rows.iter()
+      .filter(|(p,_,_)| p.overlaps(&b))
+      .cloned()
+      .collect();
+    count += descend(*child, child_rows, bbox, max_z, buffer, f)?;
+  }
+  Ok(count)
+}
@@ -0,0 +1,202 @@
+use crate::{DB,Value,Row};
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+use std::collections::BTreeMap;
+
+/// Point of a trajectory segment: `((x0,x1),(y0,y1),(t0,t1))`, the spatial
+/// bounds of the segment and the time interval it covers.
+pub type SegmentPoint = ((f32,f32),(f32,f32),(f64,f64));
+
+/// Value of a trajectory segment: the id of the trajectory, the position of
+/// the segment along it and the endpoints of the segment, which the bounds
+/// in the point do not keep.
+#[derive(Clone,Debug,PartialEq)]
+pub struct Segment<V> {
+  pub id: u64,
+  pub seq: u32,
+  pub from: (f32,f32),
+  pub to: (f32,f32),
+  pub value: V
+}
+
+impl<V> CountBytes for Segment<V> where V: CountBytes {
+  fn count_bytes (&self) -> usize {
+    28 + self.value.count_bytes()
+  }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    if buf.len() < 28 { bail!["buffer too small for segment in count"] }
+    Ok(28 + V::count_from_bytes(&buf[28..])?)
+  }
+}
+
+impl<V> ToBytes for Segment<V> where V: ToBytes+CountBytes {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    let mut buf = vec![0u8;self.count_bytes()];
+    self.write_bytes(&mut buf)?;
+    Ok(buf)
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    if dst.len() < 28 { bail!["dst buffer too small for segment"] }
+    dst[0..8].copy_from_slice(&self.id.to_be_bytes());
+    dst[8..12].copy_from_slice(&self.seq.to_be_bytes());
+    dst[12..16].copy_from_slice(&self.from.0.to_be_bytes());
+    dst[16..20].copy_from_slice(&self.from.1.to_be_bytes());
+    dst[20..24].copy_from_slice(&self.to.0.to_be_bytes());
+    dst[24..28].copy_from_slice(&self.to.1.to_be_bytes());
+    Ok(28 + self.value.write_bytes(&mut dst[28..])?)
+  }
+}
+
+impl<V> FromBytes for Segment<V> where V: FromBytes {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    if src.len() < 28 { bail!["buffer too small while loading segment"] }
+    let f = |i: usize| f32::from_be_bytes([src[i],src[i+1],src[i+2],src[i+3]]);
+    let mut id = [0u8;8];
+    id.copy_from_slice(&src[0..8]);
+    let (size,value) = V::from_bytes(&src[28..])?;
+    Ok((28 + size, Self {
+      id: u64::from_be_bytes(id),
+      seq: u32::from_be_bytes([src[8],src[9],src[10],src[11]]),
+      from: (f(12),f(16)),
+      to: (f(20),f(24)),
+      value
+    }))
+  }
+}
+
+/// Track of a moving object: positions `(x,y,t)` in time order and a value
+/// for the whole track.
+///
+/// `trajectory.segments()` splits the track into a row per segment between
+/// consecutive positions, so a query for a region and a time range finds the
+/// segments that pass through it. `db.query_trajectories()` reassembles the
+/// segments in a query into tracks:
+///
+/// ```rust,no_run
+/// use eyros::{DB,SegmentPoint,Segment,Trajectory};
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// # fn main () -> Result<(),Error> {
+/// let mut db: DB<_,_,SegmentPoint,Segment<u32>> = DB::open(storage)?;
+/// let mut bus = Trajectory::new(17, 42);
+/// bus.push(-147.72, 64.84, 1000.0);
+/// bus.push(-147.70, 64.85, 1060.0);
+/// bus.push(-147.69, 64.85, 1120.0);
+/// db.batch(&bus.segments()?)?;
+/// let bbox = ((-147.71,64.80,1050.0),(-147.60,64.90,1100.0));
+/// for track in db.query_trajectories(&bbox)? {
+///   println!["track {} with value {}: {:?}", track.id, track.value,
+///     track.points];
+/// }
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+#[derive(Clone,Debug,PartialEq)]
+pub struct Trajectory<V> {
+  pub id: u64,
+  pub points: Vec<(f32,f32,f64)>,
+  pub value: V
+}
+
+impl<V> Trajectory<V> where V: Value {
+  pub fn new (id: u64, value: V) -> Self {
+    Self { id, points: vec![], value }
+  }
+  /// Add the position `(x,y)` at time `t` to the end of the track.
+  pub fn push (&mut self, x: f32, y: f32, t: f64) {
+    self.points.push((x,y,t));
+  }
+  /// Insert rows for the segments between consecutive positions. A track
+  /// with a single position is stored as a segment of zero length. Fails if
+  /// the times of the positions decrease.
+  pub fn segments (&self) -> Result<Vec<Row<SegmentPoint,Segment<V>>>,Error> {
+    if self.points.is_empty() { return Ok(vec![]) }
+    if self.points.len() > u32::MAX as usize {
+      bail!["too many positions in trajectory {}", self.id];
+    }
+    let single = [self.points[0],self.points[0]];
+    let points = if self.points.len() == 1 { &single[..] }
+      else { &self.points[..] };
+    let mut rows = Vec::with_capacity(points.len()-1);
+    for (i,w) in points.windows(2).enumerate() {
+      let ((x0,y0,t0),(x1,y1,t1)) = (w[0],w[1]);
+      if t1 < t0 {
+        bail!["time decreases at position {} of trajectory {}", i+1, self.id];
+      }
+      rows.push(Row::Insert(
+        ((x0.min(x1),x0.max(x1)),(y0.min(y1),y0.max(y1)),(t0,t1)),
+        Segment {
+          id: self.id,
+          seq: i as u32,
+          from: (x0,y0),
+          to: (x1,y1),
+          value: self.value.clone()
+        }
+      ));
+    }
+    Ok(rows)
+  }
+  /// Rebuild tracks from segments, such as the results of a query. Segments
+  /// of the same track that are not consecutive, because the segments in
+  /// between were not in the query, make separate tracks with the same id.
+  pub fn from_segments<I> (segments: I) -> Vec<Self>
+  where I: IntoIterator<Item=(SegmentPoint,Segment<V>)> {
+    let mut by_track: BTreeMap<u64,Vec<(SegmentPoint,Segment<V>)>> =
+      BTreeMap::new();
+    for (p,s) in segments {
+      by_track.entry(s.id).or_default().push((p,s));
+    }
+    let mut tracks = vec![];
+    for (id,mut segments) in by_track {
+      segments.sort_unstable_by_key(|(_,s)| s.seq);
+      segments.dedup_by_key(|(_,s)| s.seq);
+      let mut track: Option<Self> = None;
+      let mut next = 0;
+      for (p,s) in segments {
+        let (t0,t1) = p.2;
+        match &mut track {
+          Some(t) if s.seq == next => {
+            t.push(s.to.0, s.to.1, t1);
+          },
+          _ => {
+            tracks.extend(track.take());
+            let mut t = Self::new(id, s.value.clone());
+            t.push(s.from.0, s.from.1, t0);
+            if s.from != s.to || t0 != t1 {
+              t.push(s.to.0, s.to.1, t1);
+            }
+            track = Some(t);
+          }
+        }
+        next = s.seq + 1;
+      }
+      tracks.extend(track);
+    }
+    tracks
+  }
+}
+
+impl<S,U,V> DB<S,U,SegmentPoint,Segment<V>> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+V: Value {
+  /// Query for the segments that intersect `bbox`, given as
+  /// `((xmin,ymin,tmin),(xmax,ymax,tmax))`, and reassemble them into tracks
+  /// with `Trajectory::from_segments()`.
+  pub fn query_trajectories (&mut self, bbox: &((f32,f32,f64),(f32,f32,f64)))
+  -> Result<Vec<Trajectory<V>>,Error> {
+    let mut segments = vec![];
+    for result in self.query(bbox)? {
+      let (p,s,_) = result?;
+      segments.push((p,s));
+    }
+    Ok(Trajectory::from_segments(segments))
+  }
+}
@@ -1,22 +1,44 @@
 use random_access_storage::RandomAccess;
 use failure::{Error,format_err,bail};
-use std::cell::RefCell;
+use std::cell::{Cell,RefCell};
 use std::rc::Rc;
 use std::mem::size_of;
 
-use crate::{Point,Value,Location};
+use crate::{Point,PointOrd,Value,Location,DeleteSet,Extent};
 use crate::branch::{Branch,Node};
 use crate::data::{DataStore,DataMerge,DataBatch};
-use crate::read_block::read_block;
+use crate::read_block::{read_block,read_blocks};
+use crate::order::Levels;
+use crate::point::{branch_bounds,Cursor,Block};
+use crate::meta::{TREE_V1,TREE_ADAPTIVE,TREE_FORMAT};
+use crate::instrument;
+use crate::explain::{TreeTrace,LevelTrace};
+use crate::partition::{Partition,partition_bit};
+use crate::branch_cache::BranchCache;
+use std::collections::{BTreeMap,HashMap,VecDeque};
+use desert::{FromBytes,CountBytes};
 
 pub struct TreeIterator<'b,S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
   tree: Rc<RefCell<Tree<S,P,V>>>,
   bbox: &'b P::Bounds,
-  cursors: Vec<(u64,usize)>,
+  cursors: VecDeque<(u64,usize)>,
   blocks: Vec<u64>,
   queue: Vec<(P,V,Location)>,
-  tree_size: u64
+  tree_size: u64,
+  interleave: bool,
+  spread: bool,
+  // branch blocks read ahead of their turn, with the level they split on
+  prefetched: HashMap<u64,(Vec<u8>,usize)>,
+  prefetched_bytes: usize,
+  // bytes of queued work to stay under, from QueryOptions::memory_budget()
+  budget: Option<usize>,
+  // reading the data blocks gathered so far, to get back under the budget
+  draining: bool,
+  // partitions to visit as a bitmask, from QueryOptions::partitions()
+  partitions: Option<u64>,
+  // children to visit by their bounds, for query shapes such as polygons
+  bounds_filter: Option<Rc<dyn Fn(&P::Bounds) -> bool+'b>>
 }
 
 impl<'b,S,P,V> TreeIterator<'b,S,P,V>
@@ -28,11 +50,62 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       tree,
       tree_size,
       bbox,
-      cursors: vec![(0,0)],
+      cursors: VecDeque::from(vec![(0,0)]),
       blocks: vec![],
-      queue: vec![]
+      queue: vec![],
+      interleave: false,
+      spread: false,
+      prefetched: HashMap::new(),
+      prefetched_bytes: 0,
+      budget: None,
+      draining: false,
+      partitions: None,
+      bounds_filter: None
     })
   }
+  /// Read every matching branch breadth-first before any data block, then
+  /// read the data blocks in bit-reversed order so that each prefix of the
+  /// results is spread across the whole tree.
+  pub fn interleave (mut self) -> Self {
+    self.interleave = true;
+    self
+  }
+  /// Keep the queued branch cursors, data block offsets, prefetched branches
+  /// and pending results of the iterator under about `bytes`. Once over the
+  /// budget, interleaved queries read the data blocks gathered so far before
+  /// reading more branches and walk the remaining branches depth-first, and
+  /// branches are no longer prefetched. Each step may go over the budget by
+  /// the children of one branch or the rows of one data block.
+  pub fn memory_budget (mut self, bytes: usize) -> Self {
+    self.budget = Some(bytes);
+    self
+  }
+  /// Skip the children of branch blocks whose partition masks share no bits
+  /// with `mask`. Branches written without masks are visited as before, so
+  /// the results still need to be filtered by partition.
+  pub fn partitions (mut self, mask: u64) -> Self {
+    self.partitions = Some(mask);
+    self
+  }
+  /// Skip the children of branch blocks whose bounds fail `filter`, for
+  /// query shapes that fit more tightly than a bounding box. Branches written
+  /// without bounds (see `Setup::branch_bounds()`) are visited as before, so
+  /// the results still need to be filtered by the shape.
+  pub fn filter_bounds (mut self, filter: Rc<dyn Fn(&P::Bounds) -> bool+'b>)
+  -> Self {
+    self.bounds_filter = Some(filter);
+    self
+  }
+  /// Estimated bytes held in the work queues of the iterator.
+  pub fn queued_bytes (&self) -> usize {
+    self.cursors.len()*size_of::<Cursor>()
+      + self.blocks.len()*size_of::<Block>()
+      + self.queue.len()*size_of::<(P,V,Location)>()
+      + self.prefetched_bytes
+  }
+  fn over_budget (&self) -> bool {
+    self.budget.map(|b| self.queued_bytes() > b).unwrap_or(false)
+  }
 }
 
 #[doc(hidden)]
@@ -50,7 +123,10 @@ impl<'b,S,P,V> Iterator for TreeIterator<'b,S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
   type Item = Result<(P,V,Location),Error>;
   fn next (&mut self) -> Option<Self::Item> {
-    let bf = iwrap![self.tree.try_borrow()].branch_factor;
+    let (levels,prefetch) = {
+      let tree = iwrap![self.tree.try_borrow()];
+      (Rc::clone(&tree.levels),tree.branch_prefetch)
+    };
 
     // todo: used cached size or rolling max to implicitly read an appropriate
     // amount of data
@@ -59,24 +135,74 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       if !self.queue.is_empty() {
         return Some(Ok(self.queue.pop().unwrap()));
       }
-      if !self.blocks.is_empty() { // data block:
+      if self.interleave && !self.spread && self.cursors.is_empty() {
+        self.blocks = spread_order(std::mem::take(&mut self.blocks));
+        self.spread = true;
+      }
+      if self.interleave && !self.draining && !self.cursors.is_empty()
+      && !self.blocks.is_empty() && self.over_budget() {
+        self.blocks = spread_order(std::mem::take(&mut self.blocks));
+        self.draining = true;
+      }
+      if !self.blocks.is_empty()
+      && (!self.interleave || self.cursors.is_empty() || self.draining) {
+        // data block:
         let offset = self.blocks.pop().unwrap();
+        if self.blocks.is_empty() { self.draining = false }
         let tree = iwrap![self.tree.try_borrow()];
         let mut dstore = iwrap![tree.data_store.try_borrow_mut()];
         self.queue.extend(iwrap![dstore.query(offset, self.bbox)]);
+        event!(target: "eyros::query", offset, rows = self.queue.len(), "data");
         continue
       }
       // branch block:
-      let (cursor,depth) = self.cursors.pop().unwrap();
+      let (cursor,depth) = if self.interleave && !self.over_budget() {
+        self.cursors.pop_front().unwrap()
+      } else {
+        self.cursors.pop_back().unwrap()
+      };
       if cursor >= self.tree_size { continue }
 
-      let buf = {
-        let mut tree = iwrap![self.tree.try_borrow_mut()];
-        iwrap![read_block(&mut tree.store, cursor, self.tree_size, 1024)]
+      let (buf,split) = match self.prefetched.remove(&cursor) {
+        Some(block) => {
+          self.prefetched_bytes -= block.0.len();
+          block
+        },
+        None => {
+          let mut tree = iwrap![self.tree.try_borrow_mut()];
+          iwrap![tree.branch(cursor, depth, self.tree_size)]
+        }
       };
-      let (cursors,blocks) = iwrap![
-        P::query_branch(&buf, &self.bbox, bf, depth)
+      let (mut cursors,mut blocks) = iwrap![
+        query_branch_at::<P>(&buf, &self.bbox, &levels, split, depth)
       ];
+      if let Some(want) = self.partitions {
+        if let Some(masks) = iwrap![child_masks::<P>(&buf, &levels, split)] {
+          let visit = |is_data: bool, offset: u64| {
+            masks.get(&(is_data,offset)).map(|m| m & want != 0).unwrap_or(true)
+          };
+          cursors.retain(|(c,_)| visit(false, *c));
+          blocks.retain(|b| visit(true, *b));
+        }
+      }
+      if let Some(filter) = &self.bounds_filter {
+        if let Some(bounds) = iwrap![child_bounds::<P>(&buf, &levels, split)] {
+          let visit = |is_data: bool, offset: u64| {
+            bounds.get(&(is_data,offset)).map(|b| filter(b)).unwrap_or(true)
+          };
+          cursors.retain(|(c,_)| visit(false, *c));
+          blocks.retain(|b| visit(true, *b));
+        }
+      }
+      event!(target: "eyros::query", cursor, depth,
+        branches = cursors.len(), blocks = blocks.len(), "branch");
+      if prefetch && cursors.len() > 1 && !self.over_budget() {
+        let mut tree = iwrap![self.tree.try_borrow_mut()];
+        let branches = iwrap![tree.read_branches(&cursors, self.tree_size)];
+        self.prefetched_bytes +=
+          branches.iter().map(|(_,(buf,_))| buf.len()).sum::<usize>();
+        self.prefetched.extend(branches);
+      }
       self.blocks.extend(blocks);
       self.cursors.extend(cursors);
     }
@@ -84,13 +210,34 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   }
 }
 
+// reorder blocks so that popping from the end visits them in bit-reversed
+// index order, which spreads every prefix evenly over the original order
+fn spread_order (blocks: Vec<u64>) -> Vec<u64> {
+  let n = blocks.len();
+  if n < 3 { return blocks.into_iter().rev().collect() }
+  let bits = n.next_power_of_two().trailing_zeros();
+  let mut order: Vec<u64> = (0..1usize << bits)
+    .map(|i| i.reverse_bits() >> (usize::BITS - bits))
+    .filter(|i| *i < n)
+    .map(|i| blocks[i])
+    .collect();
+  order.reverse();
+  order
+}
+
 pub struct TreeOpts<S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
   pub store: S,
   pub data_store: Rc<RefCell<DataStore<S,P,V>>>,
-  pub branch_factor: usize,
+  pub levels: Levels,
   pub max_data_size: usize,
   pub index: usize,
+  pub sync: Rc<Cell<bool>>,
+  pub branch_bounds: bool,
+  pub branch_prefetch: bool,
+  pub partition: Option<Rc<Partition<V>>>,
+  pub(crate) branch_cache: Rc<BranchCache>,
+  pub format: u16,
 }
 
 pub struct Tree<S,P,V>
@@ -98,15 +245,37 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   pub store: S,
   data_store: Rc<RefCell<DataStore<S,P,V>>>,
   data_merge: Rc<RefCell<DataMerge<S,P,V>>>,
-  branch_factor: usize,
+  levels: Rc<Levels>,
   pub bytes: u64,
   pub index: usize,
   max_data_size: usize,
+  branch_bounds: bool,
+  // read the child branches of each branch together during queries
+  branch_prefetch: bool,
+  // write the partitions below each child into branch blocks
+  partition: Option<Rc<Partition<V>>>,
+  // pin budget shared with the other trees and the branch blocks pinned
+  // here, by offset with the level they split on, for a store of
+  // `pinned_size` bytes
+  branch_cache: Rc<BranchCache>,
+  pinned: HashMap<u64,(Vec<u8>,usize)>,
+  pinned_size: u64,
+  // format tag of the tree, set to TREE_FORMAT whenever the tree is built
+  pub format: u16,
+  // bytes of branch blocks written since the tree was opened
+  pub written: u64,
+  // sync the store after each build, per the durability policy
+  sync: Rc<Cell<bool>>,
 }
 
 impl<S,P,V> Tree<S,P,V>
 where S: RandomAccess<Error=Error>, P: Point, V: Value {
   pub fn open (opts: TreeOpts<S,P,V>) -> Result<Self,Error> {
+    if opts.format > TREE_FORMAT {
+      bail!["tree {} has format version {}, newer than the newest version \
+        supported by this version of eyros ({})",
+        opts.index, opts.format, TREE_FORMAT];
+    }
     let bytes = opts.store.len()? as u64;
     let data_merge = Rc::new(RefCell::new(
       DataMerge::new(Rc::clone(&opts.data_store))));
@@ -116,16 +285,28 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       data_merge,
       index: opts.index,
       bytes,
-      branch_factor: opts.branch_factor,
+      levels: Rc::new(opts.levels),
       max_data_size: opts.max_data_size,
+      branch_bounds: opts.branch_bounds,
+      branch_prefetch: opts.branch_prefetch,
+      partition: opts.partition,
+      branch_cache: opts.branch_cache,
+      pinned: HashMap::new(),
+      pinned_size: 0,
+      format: opts.format,
+      written: 0,
+      sync: opts.sync,
     })
   }
   pub fn clear (&mut self) -> Result<(),Error> {
+    self.unpin();
     if self.bytes > 0 {
       self.bytes = 0;
       self.store.truncate(0)?;
     }
-    self.store.sync_all()?;
+    if self.sync.get() {
+      self.store.sync_all()?;
+    }
     Ok(())
   }
   pub fn is_empty (&mut self) -> Result<bool,Error> {
@@ -134,9 +315,15 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   }
   pub fn build (&mut self, rows: &Vec<(P,V)>) -> Result<(),Error> {
     let dstore = Rc::clone(&self.data_store);
+    let masks = match &self.partition {
+      Some(partition) => Some(rows.iter()
+        .map(|(_,v)| partition_bit(partition, v))
+        .collect::<Result<Vec<u64>,Error>>()?),
+      None => None
+    };
     self.builder(
       Rc::new(rows.iter().map(|row| { (row.clone(),1u64) }).collect()),
-      dstore
+      dstore, masks
     )
   }
   pub fn build_from_blocks (&mut self, blocks: Vec<(P::Bounds,u64,u64)>)
@@ -147,21 +334,41 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
     let rows = blocks.iter().enumerate().map(|(i,(_,_,len))| {
       (inserts[i],*len)
     }).collect();
+    let masks = match &self.partition {
+      Some(partition) => {
+        let mut dstore = self.data_store.try_borrow_mut()?;
+        let mut masks = Vec::with_capacity(blocks.len());
+        for (_,offset,_) in blocks.iter() {
+          let mut mask = 0;
+          for (_,v,_) in dstore.list(*offset)?.iter() {
+            mask |= partition_bit(partition, v)?;
+          }
+          masks.push(mask);
+        }
+        Some(masks)
+      },
+      None => None
+    };
     let dmerge = Rc::clone(&self.data_merge);
-    self.builder(Rc::new(rows), dmerge)
+    self.builder(Rc::new(rows), dmerge, masks)
   }
+  /// Build the tree from `rows`, each with the number of records it stands
+  /// for. `masks` holds the partitions of each row as a bitmask when the
+  /// database is partitioned.
   pub fn builder<D,T,U> (&mut self, rows: Rc<Vec<((T,U),u64)>>,
-  data_store: Rc<RefCell<D>>) -> Result<(),Error>
+  data_store: Rc<RefCell<D>>, masks: Option<Vec<u64>>) -> Result<(),Error>
   where D: DataBatch<T,U>, T: Point, U: Value {
     self.clear()?;
+    self.format = self.levels.tree_format();
     let bucket = (0..rows.len()).collect();
     let b = Branch::<D,T,U>::new(
       0,
       self.index,
       self.max_data_size,
-      self.branch_factor,
+      self.branch_bounds || masks.is_some(),
+      Rc::clone(&self.levels),
       Rc::clone(&data_store),
-      bucket, rows
+      bucket, rows, masks.map(Rc::new)
     )?;
     let mut branches = vec![Node::Branch(b)];
     match branches[0] {
@@ -171,6 +378,7 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       },
       _ => panic!["unexpected initial node type"]
     };
+    let mut data = vec![];
     while !branches.is_empty() {
       let mut nbranches = vec![];
       for mut branch in branches {
@@ -178,11 +386,12 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
           Node::Empty => {},
           Node::Data(_) => {},
           Node::Branch(ref mut b) => {
-            let (data,nb) = {
+            let nb = {
               let alloc = &mut {|bytes| self.alloc(bytes) };
-              b.build(alloc)?
+              b.build(alloc, &mut data)?
             };
             self.store.write(b.offset, &data)?;
+            self.written += data.len() as u64;
             self.bytes = self.bytes.max(b.offset + (data.len() as u64));
             nbranches.extend(nb);
           }
@@ -190,13 +399,265 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       }
       branches = nbranches;
     }
-    self.store.sync_all()?;
+    if self.sync.get() {
+      self.store.sync_all()?;
+    }
     Ok(())
   }
   pub fn query<'a,'b> (tree: Rc<RefCell<Self>>, bbox: &'b P::Bounds)
   -> Result<TreeIterator<'b,S,P,V>,Error> {
     TreeIterator::new(tree, bbox)
   }
+  /// Walk the branches that intersect `bbox`, grouping the data blocks under
+  /// each branch at `max_depth` and returning the depth, bounds and row count
+  /// of every group. Data blocks found above `max_depth` are their own group.
+  /// Bounds and counts come from the data block index, so data blocks are
+  /// only read when their bounds are not cached.
+  pub fn summary (&mut self, bbox: &P::Bounds, max_depth: usize)
+  -> Result<Vec<(usize,Extent<P>,u64)>,Error> {
+    let levels = Rc::clone(&self.levels);
+    let tree_size = self.store.len()? as u64;
+    let mut groups: Vec<(usize,Vec<P::Range>,u64)> = vec![];
+    let mut cursors: Vec<(u64,usize,Option<usize>)> = vec![(0,0,None)];
+    if max_depth == 0 {
+      groups.push((0,vec![],0));
+      cursors[0].2 = Some(0);
+    }
+    let data_store = Rc::clone(&self.data_store);
+    let mut dstore = data_store.try_borrow_mut()?;
+    while let Some((cursor,depth,group)) = cursors.pop() {
+      if cursor >= tree_size { continue }
+      let (buf,split) = self.branch(cursor, depth, tree_size)?;
+      let (bcursors,blocks) =
+        query_branch_at::<P>(&buf, bbox, &levels, split, depth)?;
+      for offset in blocks {
+        if let Some((b,len)) = dstore.bbox(offset)? {
+          let g = match group {
+            Some(g) => g,
+            None => {
+              groups.push((depth,vec![],0));
+              groups.len()-1
+            }
+          };
+          groups[g].1.push(P::bounds_to_range(b));
+          groups[g].2 += len;
+        }
+      }
+      for (c,d) in bcursors {
+        let g = match group {
+          Some(g) => Some(g),
+          None if d >= max_depth => {
+            groups.push((d,vec![],0));
+            Some(groups.len()-1)
+          },
+          None => None
+        };
+        cursors.push((c,d,g));
+      }
+    }
+    Ok(groups.into_iter().filter_map(|(depth,ranges,count)| {
+      <P::Range as PointOrd>::bounds(&ranges).map(|b| (depth,b,count))
+    }).collect())
+  }
+  /// Walk the tree for `bbox` like `Tree::query()`, recording the branches
+  /// and data blocks that were visited or pruned at each level.
+  pub fn explain (&mut self, bbox: &P::Bounds, deletes: &DeleteSet)
+  -> Result<TreeTrace,Error> {
+    let levels = Rc::clone(&self.levels);
+    let tree_size = self.store.len()? as u64;
+    let mut trace = TreeTrace { index: self.index, ..TreeTrace::default() };
+    let mut cursors: Vec<(u64,usize)> = vec![(0,0)];
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    while !cursors.is_empty() {
+      let (cursor,depth) = cursors.pop().unwrap();
+      if cursor >= tree_size { continue }
+      let (buf,split) = read_branch(&mut self.store, self.format, &levels,
+        cursor, depth, tree_size)?;
+      let (all_cursors,all_blocks) =
+        children::<P>(&buf, &levels, split, depth)?;
+      let (bcursors,blocks) =
+        query_branch_at::<P>(&buf, bbox, &levels, split, depth)?;
+      if trace.levels.len() <= depth {
+        trace.levels.resize(depth+1, LevelTrace::default());
+      }
+      let level = &mut trace.levels[depth];
+      level.branches += 1;
+      level.branches_pruned +=
+        all_cursors.len().saturating_sub(bcursors.len());
+      level.blocks += blocks.len();
+      level.blocks_pruned += all_blocks.len().saturating_sub(blocks.len());
+      trace.bytes += (buf.len() as u64)+4;
+      for offset in blocks {
+        let block = dstore.read(offset)?;
+        trace.bytes += (block.len() as u64)+4;
+        trace.results += dstore.parse(&block)?.iter().filter(|(p,_,i)| {
+          p.overlaps(bbox) && !deletes.contains(&(offset+1,*i))
+            && !dstore.is_deleted(offset, *i)
+        }).count();
+      }
+      cursors.extend(bcursors);
+    }
+    Ok(trace)
+  }
+  /// Check whether a data block that intersects `bbox` holds a live record
+  /// whose serialized point equals `key` (and serialized value equals
+  /// `value`, if given), consulting bloom filters (if any) before reading
+  /// each block.
+  pub fn contains (&mut self, bbox: &P::Bounds, key: &[u8],
+  value: Option<&[u8]>, deletes: &DeleteSet) -> Result<bool,Error> {
+    let levels = Rc::clone(&self.levels);
+    let tree_size = self.store.len()? as u64;
+    let mut cursors: Vec<(u64,usize)> = vec![(0,0)];
+    let data_store = Rc::clone(&self.data_store);
+    let mut dstore = data_store.try_borrow_mut()?;
+    while let Some((cursor,depth)) = cursors.pop() {
+      if cursor >= tree_size { continue }
+      let (buf,split) = self.branch(cursor, depth, tree_size)?;
+      let (bcursors,blocks) =
+        query_branch_at::<P>(&buf, bbox, &levels, split, depth)?;
+      cursors.extend(bcursors);
+      for offset in blocks {
+        if let Some(bloom) = &mut dstore.bloom {
+          if bloom.may_contain(offset, key)? == Some(false) { continue }
+        }
+        for (p,v,loc) in dstore.list(offset)? {
+          if deletes.contains(&loc) || p.to_bytes()? != key { continue }
+          match value {
+            Some(vk) if v.to_bytes()? != vk => {},
+            _ => return Ok(true)
+          }
+        }
+      }
+    }
+    Ok(false)
+  }
+  /// Offsets of the data blocks that may hold records intersecting `bbox`,
+  /// found by walking the branches without reading any data blocks.
+  pub fn query_blocks (&mut self, bbox: &P::Bounds) -> Result<Vec<u64>,Error> {
+    let levels = Rc::clone(&self.levels);
+    let tree_size = self.store.len()? as u64;
+    let mut cursors: Vec<(u64,usize)> = vec![(0,0)];
+    let mut offsets = vec![];
+    while let Some((cursor,depth)) = cursors.pop() {
+      if cursor >= tree_size { continue }
+      let (buf,split) = self.branch(cursor, depth, tree_size)?;
+      let (bcursors,blocks) =
+        query_branch_at::<P>(&buf, bbox, &levels, split, depth)?;
+      cursors.extend(bcursors);
+      offsets.extend(blocks);
+    }
+    Ok(offsets)
+  }
+  /// Cursors of the sub-branches and offsets of the data blocks below the
+  /// branch at `cursor` and `depth` that may hold records intersecting
+  /// `bbox`.
+  pub fn branch_children (&mut self, cursor: u64, depth: usize,
+  bbox: &P::Bounds) -> Result<(Vec<Cursor>,Vec<Block>),Error> {
+    let tree_size = self.store.len()? as u64;
+    if cursor >= tree_size { return Ok((vec![],vec![])) }
+    let (buf,split) = self.branch(cursor, depth, tree_size)?;
+    query_branch_at::<P>(&buf, bbox, &self.levels, split, depth)
+  }
+  /// Read the branch block at `cursor` and `depth`, along with the level it
+  /// splits on, from memory if it is pinned. Blocks in the top levels set
+  /// with `Setup::pin_levels()` are pinned as they are read, while the pin
+  /// budget lasts.
+  pub(crate) fn branch (&mut self, cursor: u64, depth: usize, tree_size: u64)
+  -> Result<(Vec<u8>,usize),Error> {
+    if tree_size != self.pinned_size {
+      // the store changed since the blocks were pinned
+      self.unpin();
+      self.pinned_size = tree_size;
+    }
+    if let Some(block) = self.pinned.get(&cursor) {
+      self.branch_cache.hit();
+      return Ok(block.clone());
+    }
+    let block = read_branch(&mut self.store, self.format, &self.levels,
+      cursor, depth, tree_size)?;
+    instrument::branch_read(block.0.len()+4);
+    self.branch_cache.read(1);
+    self.pin(cursor, depth, &block);
+    Ok(block)
+  }
+  fn pin (&mut self, cursor: u64, depth: usize, block: &(Vec<u8>,usize)) {
+    if self.branch_cache.reserve(depth, block.0.len()) {
+      self.pinned.insert(cursor, block.clone());
+    }
+  }
+  fn unpin (&mut self) {
+    let bytes = self.pinned.values().map(|(buf,_)| buf.len()).sum();
+    self.branch_cache.release(self.pinned.len(), bytes);
+    self.pinned.clear();
+  }
+  /// Read the branch blocks at `cursors` ahead of visiting them, returning
+  /// each block by offset along with the level it splits on. Sibling branches
+  /// are written next to each other, so their reads are coalesced.
+  pub(crate) fn read_branches (&mut self, cursors: &[Cursor], tree_size: u64)
+  -> Result<Vec<(u64,(Vec<u8>,usize))>,Error> {
+    // pinned blocks are left for branch() to serve from memory
+    let cursors: Vec<Cursor> = cursors.iter()
+      .filter(|(c,_)| *c < tree_size)
+      .filter(|(c,_)| tree_size != self.pinned_size
+        || !self.pinned.contains_key(c))
+      .copied()
+      .collect();
+    let offsets: Vec<u64> = cursors.iter().map(|(c,_)| *c).collect();
+    let bufs = read_blocks(&mut self.store, &offsets, tree_size, 1024)?;
+    self.branch_cache.read(bufs.len());
+    let mut branches = Vec::with_capacity(cursors.len());
+    for ((cursor,depth),buf) in cursors.into_iter().zip(bufs) {
+      instrument::branch_read(buf.len()+4);
+      let block = split_header(buf, self.format, &self.levels, depth)?;
+      if tree_size == self.pinned_size { self.pin(cursor, depth, &block) }
+      branches.push((cursor,block));
+    }
+    Ok(branches)
+  }
+  pub(crate) fn data_store (&self) -> Rc<RefCell<DataStore<S,P,V>>> {
+    Rc::clone(&self.data_store)
+  }
+  /// Query the tree for every bounding box in `bboxes` in a single
+  /// traversal, reading each branch and data block at most once. Results are
+  /// tagged with the index of the matching bounding box.
+  pub fn query_multi (&mut self, bboxes: &[P::Bounds],
+  deletes: &DeleteSet) -> Result<Vec<(usize,P,V,Location)>,Error> {
+    let levels = Rc::clone(&self.levels);
+    let tree_size = self.store.len()? as u64;
+    let mut results = vec![];
+    let mut cursors: Vec<((u64,usize),Vec<usize>)> =
+      vec![((0,0),(0..bboxes.len()).collect())];
+    let data_store = Rc::clone(&self.data_store);
+    let mut dstore = data_store.try_borrow_mut()?;
+    while let Some(((cursor,depth),active)) = cursors.pop() {
+      if cursor >= tree_size { continue }
+      let (buf,split) = self.branch(cursor, depth, tree_size)?;
+      let mut next: BTreeMap<(u64,usize),Vec<usize>> = BTreeMap::new();
+      let mut blocks: BTreeMap<u64,Vec<usize>> = BTreeMap::new();
+      for i in active {
+        let (bcursors,bblocks) =
+          query_branch_at::<P>(&buf, &bboxes[i], &levels, split, depth)?;
+        for c in bcursors {
+          next.entry(c).or_default().push(i);
+        }
+        for b in bblocks {
+          blocks.entry(b).or_default().push(i);
+        }
+      }
+      for (offset,matches) in blocks {
+        for (p,v,loc) in dstore.list(offset)? {
+          if deletes.contains(&loc) { continue }
+          for i in matches.iter() {
+            if p.overlaps(&bboxes[*i]) {
+              results.push((*i,p,v.clone(),loc));
+            }
+          }
+        }
+      }
+      cursors.extend(next);
+    }
+    Ok(results)
+  }
   fn alloc (&mut self, bytes: usize) -> u64 {
     let addr = self.bytes;
     self.bytes += bytes as u64;
@@ -204,6 +665,9 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
   }
   pub fn merge (trees: &mut Vec<Rc<RefCell<Self>>>, dst: usize, src: Vec<usize>,
   rows: &Vec<(P,V)>) -> Result<(),Error> {
+    let _span = span!("merge",
+      tree = dst, trees = src.len(), rows = rows.len());
+    instrument::merge(src.len()+1);
     let mut blocks = vec![];
     for i in src.iter() {
       blocks.extend(trees[*i].try_borrow_mut()?.unbuild()?);
@@ -227,59 +691,78 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
       ensure_eq!(srow_len, rows.len(), "divided rows incorrectly");
     }
     trees[dst].try_borrow_mut()?.build_from_blocks(blocks)?;
-    for i in src.iter() {
+    for i in src.iter().filter(|i| **i != dst) {
       trees[*i].try_borrow_mut()?.clear()?
     }
     Ok(())
   }
-  fn unbuild (&mut self) -> Result<Vec<(P::Bounds,u64,u64)>,Error> {
-    let mut offsets: Vec<u64> = vec![];
+  /// Offsets of every data block referenced by the tree.
+  pub fn blocks (&mut self) -> Result<Vec<u64>,Error> {
+    Ok(self.block_depths()?.into_iter().map(|(offset,_)| offset).collect())
+  }
+  /// Offset of every data block referenced by the tree along with the depth
+  /// of the branch that references it, starting from 0 at the root.
+  pub fn block_depths (&mut self) -> Result<Vec<(u64,usize)>,Error> {
+    let mut offsets: Vec<(u64,usize)> = vec![];
     let mut cursors: Vec<(u64,usize)> = vec![(0,0)];
-    let bf = self.branch_factor;
-    let n = bf*2-3;
+    let levels = Rc::clone(&self.levels);
     let tree_size = self.store.len()? as u64;
     while !cursors.is_empty() {
       let (c,depth) = cursors.pop().unwrap();
-      let buf = read_block(&mut self.store, c, tree_size, 1024)?;
-      let mut offset = 0;
-      for _i in 0..n {
-        offset += P::count_bytes_at(&buf[offset..], depth)?;
-      }
-      let d_start = offset;
-      let i_start = d_start + (n+bf+7)/8;
-      let b_start = i_start + n*size_of::<u64>();
-      let b_end = b_start+bf*size_of::<u64>();
-      ensure_eq!(b_end, buf.len(), "unexpected block length");
-      for i in 0..n {
-        let offset = u64::from_be_bytes([
-          buf[i_start+i*8+0], buf[i_start+i*8+1],
-          buf[i_start+i*8+2], buf[i_start+i*8+3],
-          buf[i_start+i*8+4], buf[i_start+i*8+5],
-          buf[i_start+i*8+6], buf[i_start+i*8+7]
-        ]);
-        let is_data = ((buf[d_start+i/8]>>(i%8))&1) == 1;
-        if offset > 0 && is_data {
-          offsets.push(offset-1);
-        } else if offset > 0 {
-          cursors.push((offset-1,depth+1));
-        }
-      }
-      for i in 0..bf {
-        let offset = u64::from_be_bytes([
-          buf[b_start+i*8+0], buf[b_start+i*8+1],
-          buf[b_start+i*8+2], buf[b_start+i*8+3],
-          buf[b_start+i*8+4], buf[b_start+i*8+5],
-          buf[b_start+i*8+6], buf[b_start+i*8+7]
-        ]);
-        let j = i + n;
-        let is_data = ((buf[d_start+(j/8)]>>(j%8))&1) == 1;
-        if offset > 0 && is_data {
-          offsets.push(offset-1);
-        } else if offset > 0 {
-          cursors.push((offset-1,depth+1));
-        }
+      let (buf,split) = read_branch(&mut self.store, self.format, &levels,
+        c, depth, tree_size)?;
+      let (bcursors,blocks) = children::<P>(&buf, &levels, split, depth)?;
+      cursors.extend(bcursors);
+      offsets.extend(blocks.into_iter().map(|offset| (offset,depth)));
+    }
+    Ok(offsets)
+  }
+  /// Human-readable dump of every branch of the tree, indented by depth, with
+  /// the pivots of each branch and what its intersection and bucket slots
+  /// point at. Data blocks are listed with their number of records and their
+  /// bounds.
+  pub fn format (&mut self) -> Result<String,Error> {
+    if self.is_empty()? { return Ok("empty tree".to_string()) }
+    let levels = Rc::clone(&self.levels);
+    let tree_size = self.store.len()? as u64;
+    let mut lines = vec![];
+    let mut cursors: Vec<(u64,usize)> = vec![(0,0)];
+    while let Some((c,depth)) = cursors.pop() {
+      let (buf,split) = read_branch(&mut self.store, self.format, &levels,
+        c, depth, tree_size)?;
+      let (pivots,slots) = branch_slots::<P>(&buf, &levels, split)?;
+      let indent = "  ".repeat(depth);
+      lines.push(format!["{}branch {} (depth {})", indent, c, depth]);
+      lines.push(format!["{}  pivots: [{}]", indent, pivots.join(", ")]);
+      let mut dstore = self.data_store.try_borrow_mut()?;
+      let mut branches = vec![];
+      for (j,(is_data,offset)) in slots.into_iter().enumerate() {
+        let slot = if j < pivots.len() {
+          format!["intersecting[{}]", j]
+        } else {
+          format!["bucket[{}]", j-pivots.len()]
+        };
+        let target = if offset == 0 {
+          "NULL".to_string()
+        } else if is_data {
+          match dstore.bbox(offset-1)? {
+            None => format!["data {} (empty)", offset-1],
+            Some((bbox,len)) => format!["data {} ({} records in {:?})",
+              offset-1, len, bbox]
+          }
+        } else {
+          branches.push((offset-1,depth+1));
+          format!["branch {}", offset-1]
+        };
+        lines.push(format!["{}  {}: {}", indent, slot, target]);
       }
+      // visit child branches in slot order
+      cursors.extend(branches.into_iter().rev());
     }
+    Ok(lines.join("\n"))
+  }
+  fn unbuild (&mut self) -> Result<Vec<(P::Bounds,u64,u64)>,Error> {
+    let offsets = self.blocks()?;
     let mut blocks = Vec::with_capacity(offsets.len());
     let mut dstore = self.data_store.try_borrow_mut()?;
     for offset in offsets {
@@ -291,3 +774,199 @@ where S: RandomAccess<Error=Error>, P: Point, V: Value {
     Ok(blocks)
   }
 }
+
+// kept out of order.rs, which tests include without the rest of the crate
+impl Levels {
+  /// Format tag of the trees built with these levels.
+  pub(crate) fn tree_format (&self) -> u16 {
+    if self.adaptive() { TREE_ADAPTIVE } else { TREE_V1 }
+  }
+}
+
+/// Read the branch block at `cursor` from a tree with the format tag `format`
+/// in the layout that `BranchQuery::query_branch()` expects. Trees written in
+/// older formats are converted here as they are read, so a database can hold
+/// trees of different formats until merges rebuild them. Returns the block
+/// along with the level it splits on: from the header of `TREE_ADAPTIVE`
+/// blocks, or from `levels` for the branch `depth` otherwise.
+pub(crate) fn read_branch<S> (store: &mut S, format: u16, levels: &Levels,
+cursor: u64, depth: usize, tree_size: u64) -> Result<(Vec<u8>,usize),Error>
+where S: RandomAccess<Error=Error> {
+  if format != TREE_V1 && format != TREE_ADAPTIVE {
+    bail!["unsupported tree format version {}", format];
+  }
+  let buf = read_block(store, cursor, tree_size, 1024)?;
+  split_header(buf, format, levels, depth)
+}
+
+// strip the header of a branch block read from a tree with the format tag
+// `format`, returning the rest of the block and the level it splits on
+fn split_header (mut buf: Vec<u8>, format: u16, levels: &Levels, depth: usize)
+-> Result<(Vec<u8>,usize),Error> {
+  match format {
+    TREE_V1 => Ok((buf,levels.level(depth))),
+    TREE_ADAPTIVE => {
+      if buf.len() < 2 { bail!["branch block too short for its split level"] }
+      let split = u16::from_be_bytes([buf[0],buf[1]]) as usize;
+      buf.drain(0..2);
+      Ok((buf,split))
+    },
+    _ => bail!["unsupported tree format version {}", format]
+  }
+}
+
+/// Query the branch block at tree `depth`, which splits on the level `split`,
+/// with `BranchQuery::query_branch()`. The returned cursors are set to the
+/// depth below, since the level passed to the query is not necessarily the
+/// depth.
+pub(crate) fn query_branch_at<P> (buf: &[u8], bbox: &P::Bounds,
+levels: &Levels, split: usize, depth: usize)
+-> Result<(Vec<Cursor>,Vec<Block>),Error> where P: Point {
+  let bf = levels.branch_factor(split);
+  let (cursors,blocks) = P::query_branch(buf, bbox, bf, split)?;
+  Ok((cursors.into_iter().map(|(c,_)| (c,depth+1)).collect(), blocks))
+}
+
+// byte length of the `n` pivots at the start of a branch block
+fn pivots_len<P> (buf: &[u8], split: usize, n: usize) -> Result<usize,Error>
+where P: Point {
+  let mut offset = 0;
+  for _i in 0..n {
+    offset += P::count_bytes_at(pivot_slice(buf, offset, None)?, split)?;
+  }
+  Ok(offset)
+}
+
+// bytes of a branch block from the pivot at `offset`, `size` bytes long or to
+// the end of the block, or an error if the block ends first
+fn pivot_slice (buf: &[u8], offset: usize, size: Option<usize>)
+-> Result<&[u8],Error> {
+  let end = size.map(|size| offset+size).unwrap_or(buf.len());
+  match buf.get(offset..end) {
+    Some(slice) => Ok(slice),
+    None => bail!["branch block too short for its pivots"]
+  }
+}
+
+/// List the branch cursors and data block offsets referenced by a branch block.
+fn children<P> (buf: &[u8], levels: &Levels, split: usize, depth: usize)
+-> Result<(Vec<(u64,usize)>,Vec<u64>),Error> where P: Point {
+  let mut cursors = vec![];
+  let mut blocks = vec![];
+  let bf = levels.branch_factor(split);
+  let n = bf*2-3;
+  let d_start = pivots_len::<P>(buf, split, n)?;
+  let i_start = d_start + (n+bf+7)/8;
+  let b_start = i_start + n*size_of::<u64>();
+  let b_end = b_start+bf*size_of::<u64>();
+  branch_bounds(buf, b_end, n+bf)?;
+  for j in 0..n+bf {
+    let k = if j < n { i_start+j*8 } else { b_start+(j-n)*8 };
+    let offset = u64::from_be_bytes([
+      buf[k+0], buf[k+1], buf[k+2], buf[k+3],
+      buf[k+4], buf[k+5], buf[k+6], buf[k+7]
+    ]);
+    let is_data = ((buf[d_start+j/8]>>(j%8))&1) == 1;
+    if offset > 0 && is_data {
+      blocks.push(offset-1);
+    } else if offset > 0 {
+      cursors.push((offset-1,depth+1));
+    }
+  }
+  Ok((cursors,blocks))
+}
+
+/// Map the children of a branch block, keyed by `(is_data,offset)`, to the
+/// masks of the partitions below them. Returns `None` for blocks written
+/// without partition masks.
+fn child_masks<P> (buf: &[u8], levels: &Levels, split: usize)
+-> Result<Option<HashMap<(bool,u64),u64>>,Error> where P: Point {
+  let bf = levels.branch_factor(split);
+  let n = bf*2-3;
+  let d_start = pivots_len::<P>(buf, split, n)?;
+  let i_start = d_start + (n+bf+7)/8;
+  let b_start = i_start + n*size_of::<u64>();
+  let b_end = b_start+bf*size_of::<u64>();
+  let (start,size) = match branch_bounds(buf, b_end, n+bf)? {
+    Some(bounds) => bounds,
+    None => return Ok(None)
+  };
+  let bounds_size = <P::Bounds as CountBytes>::count_from_bytes(&buf[start..])?;
+  if size != bounds_size + size_of::<u64>() { return Ok(None) }
+  let mut masks = HashMap::new();
+  for j in 0..n+bf {
+    let k = if j < n { i_start+j*8 } else { b_start+(j-n)*8 };
+    let offset = u64::from_be_bytes([
+      buf[k+0], buf[k+1], buf[k+2], buf[k+3],
+      buf[k+4], buf[k+5], buf[k+6], buf[k+7]
+    ]);
+    if offset == 0 { continue }
+    let is_data = ((buf[d_start+j/8]>>(j%8))&1) == 1;
+    let m = start + j*size + bounds_size;
+    let mask = u64::from_be_bytes([
+      buf[m+0], buf[m+1], buf[m+2], buf[m+3],
+      buf[m+4], buf[m+5], buf[m+6], buf[m+7]
+    ]);
+    masks.insert((is_data,offset-1),mask);
+  }
+  Ok(Some(masks))
+}
+
+/// Map the children of a branch block, keyed by `(is_data,offset)`, to their
+/// bounds. Returns `None` for blocks written without bounds.
+fn child_bounds<P> (buf: &[u8], levels: &Levels, split: usize)
+-> Result<Option<HashMap<(bool,u64),P::Bounds>>,Error> where P: Point {
+  let bf = levels.branch_factor(split);
+  let n = bf*2-3;
+  let d_start = pivots_len::<P>(buf, split, n)?;
+  let i_start = d_start + (n+bf+7)/8;
+  let b_start = i_start + n*size_of::<u64>();
+  let b_end = b_start+bf*size_of::<u64>();
+  let (start,size) = match branch_bounds(buf, b_end, n+bf)? {
+    Some(bounds) => bounds,
+    None => return Ok(None)
+  };
+  let mut bounds = HashMap::new();
+  for j in 0..n+bf {
+    let k = if j < n { i_start+j*8 } else { b_start+(j-n)*8 };
+    let offset = u64::from_be_bytes([
+      buf[k+0], buf[k+1], buf[k+2], buf[k+3],
+      buf[k+4], buf[k+5], buf[k+6], buf[k+7]
+    ]);
+    if offset == 0 { continue }
+    let is_data = ((buf[d_start+j/8]>>(j%8))&1) == 1;
+    let (_,b) = <P::Bounds as FromBytes>::from_bytes(&buf[start+j*size..])?;
+    bounds.insert((is_data,offset-1),b);
+  }
+  Ok(Some(bounds))
+}
+
+/// Format the pivots of a branch block with `PointCodec::format_at()` and
+/// list its intersection and bucket slots as `(is_data,offset+1)`, with an
+/// offset of 0 for empty slots.
+fn branch_slots<P> (buf: &[u8], levels: &Levels, split: usize)
+-> Result<(Vec<String>,Vec<(bool,u64)>),Error> where P: Point {
+  let bf = levels.branch_factor(split);
+  let n = bf*2-3;
+  let mut pivots = Vec::with_capacity(n);
+  let mut offset = 0;
+  for _i in 0..n {
+    let size = P::count_bytes_at(pivot_slice(buf, offset, None)?, split)?;
+    pivots.push(P::format_at(pivot_slice(buf, offset, Some(size))?, split)?);
+    offset += size;
+  }
+  let d_start = offset;
+  let i_start = d_start + (n+bf+7)/8;
+  let b_start = i_start + n*size_of::<u64>();
+  let b_end = b_start+bf*size_of::<u64>();
+  branch_bounds(buf, b_end, n+bf)?;
+  let slots = (0..n+bf).map(|j| {
+    let k = if j < n { i_start+j*8 } else { b_start+(j-n)*8 };
+    let offset = u64::from_be_bytes([
+      buf[k+0], buf[k+1], buf[k+2], buf[k+3],
+      buf[k+4], buf[k+5], buf[k+6], buf[k+7]
+    ]);
+    (((buf[d_start+j/8]>>(j%8))&1) == 1, offset)
+  }).collect();
+  Ok((pivots,slots))
+}
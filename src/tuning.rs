@@ -0,0 +1,188 @@
+use crate::{DB,Point,Value};
+use failure::Error;
+use random_access_storage::RandomAccess;
+use std::time::Duration;
+
+// share of the time of each flush or batch allowed for its fixed overhead
+const MAX_OVERHEAD: f64 = 0.1;
+// largest staging threshold that auto-tuning picks, in units of base_size
+const MAX_STAGING_BASES: usize = 16;
+// weight kept by earlier samples as each new one is added, so that the
+// measurements follow changes in the workload
+const DECAY: f64 = 0.95;
+
+/// Measured write costs and suggested write settings, returned by
+/// `db.tuning_report()`.
+///
+/// Timings start when the database is opened. A flush builds the staged
+/// inserts into trees, merging trees along the way. Both flushes and batches
+/// that only write to staging have a fixed cost (syncing, writing the meta
+/// store, planning merges) on top of a cost for each row. The suggestions
+/// are the smallest sizes that keep the fixed cost to about a tenth of the
+/// total, from a linear fit of the measured times against the number of
+/// rows. They stay at the current settings until enough differently sized
+/// flushes or batches have been measured for a fit.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct TuningReport {
+  /// Batches written since the database was opened.
+  pub batches: u64,
+  /// Batches that only wrote to staging.
+  pub staged_batches: u64,
+  /// Inserts and deletes in the batches that only wrote to staging.
+  pub staged_rows: u64,
+  /// Time spent in the batches that only wrote to staging.
+  pub staging_time: Duration,
+  /// Flushes of staging into the trees, by batches or `db.flush_staging()`.
+  pub flushes: u64,
+  /// Rows built into trees by the flushes.
+  pub flushed_rows: u64,
+  /// Time spent building and merging trees.
+  pub merge_time: Duration,
+  /// Current staging threshold in rows, as with
+  /// `Setup::staging_threshold_rows()`.
+  pub staging_threshold_rows: usize,
+  /// Suggested staging threshold in rows.
+  pub suggested_staging_rows: usize,
+  /// Suggested number of rows to pass to each `db.batch()`.
+  pub suggested_batch_rows: usize,
+  /// Whether the staging threshold follows the suggestion, from
+  /// `Setup::auto_tune()`.
+  pub auto_tune: bool
+}
+
+// write costs measured by a database
+#[derive(Clone,Debug,Default)]
+pub(crate) struct Tuning {
+  pub batches: u64,
+  pub staged_batches: u64,
+  pub staged_rows: u64,
+  pub staging_time: Duration,
+  pub flushes: u64,
+  pub flushed_rows: u64,
+  pub merge_time: Duration,
+  staging_fit: Fit,
+  merge_fit: Fit
+}
+
+impl Tuning {
+  // record a batch, whose time only goes toward staging if it did not flush
+  pub fn batch (&mut self, rows: usize, time: Duration, flushed: bool) {
+    self.batches += 1;
+    if flushed { return }
+    self.staged_batches += 1;
+    self.staged_rows += rows as u64;
+    self.staging_time += time;
+    self.staging_fit.add(rows as f64, time.as_secs_f64());
+  }
+  pub fn flushed (&mut self, rows: usize, time: Duration) {
+    self.flushes += 1;
+    self.flushed_rows += rows as u64;
+    self.merge_time += time;
+    self.merge_fit.add(rows as f64, time.as_secs_f64());
+  }
+  // staging threshold with a fixed cost of at most MAX_OVERHEAD per flush,
+  // between one and MAX_STAGING_BASES multiples of `base`
+  pub fn staging_rows (&self, base: usize) -> Option<usize> {
+    let rows = self.merge_fit.rows_for_overhead()?;
+    let units = ((rows/(base as f64)).ceil() as usize)
+      .max(1).min(MAX_STAGING_BASES);
+    Some(units*base)
+  }
+  // rows per batch with a fixed cost of at most MAX_OVERHEAD per batch, up to
+  // the staging threshold
+  pub fn batch_rows (&self, staging_rows: usize) -> Option<usize> {
+    let rows = self.staging_fit.rows_for_overhead()?;
+    Some((rows.ceil() as usize).max(1).min(staging_rows))
+  }
+}
+
+// decayed sums for a least squares fit of time = a + b*rows
+#[derive(Clone,Debug,Default)]
+struct Fit {
+  n: f64,
+  x: f64,
+  y: f64,
+  xx: f64,
+  xy: f64
+}
+
+impl Fit {
+  fn add (&mut self, x: f64, y: f64) {
+    self.n = self.n*DECAY + 1.0;
+    self.x = self.x*DECAY + x;
+    self.y = self.y*DECAY + y;
+    self.xx = self.xx*DECAY + x*x;
+    self.xy = self.xy*DECAY + x*y;
+  }
+  // rows at which the intercept is MAX_OVERHEAD of the fitted time, or None
+  // without samples of different sizes or a cost that grows with the rows
+  fn rows_for_overhead (&self) -> Option<f64> {
+    let d = self.n*self.xx - self.x*self.x;
+    if d <= f64::EPSILON*self.xx.max(1.0) { return None }
+    let b = (self.n*self.xy - self.x*self.y) / d;
+    if b <= 0.0 { return None }
+    let a = ((self.y - b*self.x) / self.n).max(0.0);
+    Some(a*(1.0-MAX_OVERHEAD)/(MAX_OVERHEAD*b))
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Report the write costs measured since the database was opened along
+  /// with suggested values for `Setup::staging_threshold_rows()` and for the
+  /// number of rows to pass to each `db.batch()`, instead of finding them by
+  /// trial and error. See `TuningReport` for how they are picked.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{DB,Setup,Row,IoMode,disk_storage};
+  /// # use failure::Error;
+  /// # fn main () -> Result<(),Error> {
+  /// # let rows: Vec<Row<(f32,f32),u32>> = vec![];
+  /// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+  /// let mut db: DB<_,_,(f32,f32),u32> = Setup::new(storage).build()?;
+  /// for batch in rows.chunks(5_000) {
+  ///   db.batch(batch)?;
+  /// }
+  /// let report = db.tuning_report();
+  /// println!["staging_threshold_rows({}), batches of {} rows",
+  ///   report.suggested_staging_rows, report.suggested_batch_rows];
+  /// # Ok(()) }
+  /// ```
+  pub fn tuning_report (&self) -> TuningReport {
+    let t = &self.tuning;
+    let staging_rows = self.staging_rows() as usize;
+    let suggested_staging_rows = t.staging_rows(self.fields.base_size)
+      .unwrap_or(staging_rows);
+    TuningReport {
+      batches: t.batches,
+      staged_batches: t.staged_batches,
+      staged_rows: t.staged_rows,
+      staging_time: t.staging_time,
+      flushes: t.flushes,
+      flushed_rows: t.flushed_rows,
+      merge_time: t.merge_time,
+      staging_threshold_rows: staging_rows,
+      suggested_staging_rows,
+      suggested_batch_rows: t.batch_rows(suggested_staging_rows)
+        .unwrap_or(suggested_staging_rows),
+      auto_tune: self.fields.auto_tune
+    }
+  }
+  // follow the suggested staging threshold after a flush, with
+  // Setup::auto_tune(). without a fit yet, the threshold is doubled once to
+  // measure flushes of another size
+  pub(crate) fn retune (&mut self) {
+    if !self.fields.auto_tune { return }
+    let base = self.fields.base_size;
+    let current = self.staging_rows() as usize;
+    self.fields.staging_threshold_rows = match self.tuning.staging_rows(base) {
+      Some(rows) => rows,
+      None if self.tuning.flushes == 2 => {
+        (current*2).max(base).min(base*MAX_STAGING_BASES)
+      },
+      None => current
+    };
+  }
+}
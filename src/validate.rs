@@ -0,0 +1,86 @@
+use crate::{DB,Point,Value,Row};
+use crate::partition::PARTITIONS;
+use failure::{Error,bail};
+use random_access_storage::RandomAccess;
+
+/// What `db.batch()` does with inserted points whose interval minimum is
+/// greater than its maximum, configured with `Setup::inverted_intervals()`.
+///
+/// Inverted intervals never match a query, so by default the batch is
+/// rejected with an error naming the index of the first inverted row before
+/// any of the batch is written.
+#[derive(Clone,Copy,Debug,Default,PartialEq)]
+pub enum InvertedIntervals {
+  /// Fail the batch.
+  #[default]
+  Reject,
+  /// Swap the minimum and maximum of every inverted interval.
+  Swap,
+  /// Store the points as they are.
+  Allow
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  // check the points inserted by `rows` before any of the batch is written,
+  // returning replacement rows when inverted intervals were swapped or
+  // points were quantized
+  pub(crate) fn validate_rows (&self, rows: &[Row<P,V>])
+  -> Result<Option<Vec<Row<P,V>>>,Error> {
+    let mut swap = false;
+    for (i,row) in rows.iter().enumerate() {
+      if let Row::Insert(p,v) = row {
+        swap |= self.validate_point(i, p)?;
+        if let Some(partition) = &self.partition {
+          let key = partition(v);
+          if key >= PARTITIONS {
+            bail!["row {}: partition {} out of range, partition keys must \
+              be below {}", i, key, PARTITIONS];
+          }
+        }
+      }
+    }
+    if !swap && self.quantize.is_none() { return Ok(None) }
+    let rows = rows.iter().enumerate().map(|(i,row)| Ok(match row {
+      Row::Insert(p,v) => Row::Insert(self.replacement(i, p, swap)?, v.clone()),
+      row => row.clone()
+    })).collect::<Result<Vec<_>,Error>>()?;
+    Ok(Some(rows))
+  }
+
+  pub(crate) fn validate_points (&self, points: &[P])
+  -> Result<Option<Vec<P>>,Error> {
+    let mut swap = false;
+    for (i,p) in points.iter().enumerate() {
+      swap |= self.validate_point(i, p)?;
+    }
+    if !swap && self.quantize.is_none() { return Ok(None) }
+    let points = points.iter().enumerate()
+      .map(|(i,p)| self.replacement(i, p, swap))
+      .collect::<Result<Vec<_>,Error>>()?;
+    Ok(Some(points))
+  }
+
+  // point written in place of `p`: normalized if `swap` and quantized
+  fn replacement (&self, i: usize, p: &P, swap: bool) -> Result<P,Error> {
+    self.quantized(i, &if swap { p.normalized() } else { *p })
+  }
+
+  // whether the point has inverted intervals to swap
+  fn validate_point (&self, i: usize, p: &P) -> Result<bool,Error> {
+    if self.fields.finite_coords && !p.is_finite() {
+      bail!["row {}: coordinates must not be NaN or infinite: {:?}", i, p];
+    }
+    match self.fields.inverted_intervals {
+      InvertedIntervals::Allow => Ok(false),
+      _ if p.is_ordered() => Ok(false),
+      InvertedIntervals::Swap => Ok(true),
+      InvertedIntervals::Reject => {
+        bail!["row {}: interval minimum is greater than its maximum: {:?}",
+          i, p];
+      }
+    }
+  }
+}
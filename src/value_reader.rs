@@ -0,0 +1,150 @@
+use crate::{DB,Point,Value,Location,data::{DataStore,StoredValue}};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+use std::cell::RefCell;
+use std::io::{self,Read};
+use std::rc::Rc;
+
+/// Reader of the serialized bytes of one value, returned by
+/// `db.value_reader()`.
+///
+/// Values written to the blob store with `Setup::inline_threshold()` are
+/// read from the store in pieces as the reader is consumed, so a value of
+/// many megabytes never has to be in memory at once. Other values are small
+/// enough to keep inline in their data block and are copied when the reader
+/// is created.
+///
+/// The bytes are the value as serialized by `ToBytes`, so reading the whole
+/// stream and passing it to `V::from_bytes()` gives the value back.
+pub struct ValueReader<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  source: Source<S,P,V>,
+  len: u64,
+  position: u64
+}
+
+enum Source<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  Bytes(Vec<u8>),
+  // data store that holds the blob store and the offset of the value in it
+  Blob(Rc<RefCell<DataStore<S,P,V>>>,u64)
+}
+
+impl<S,P,V> ValueReader<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  fn bytes (bytes: Vec<u8>) -> Self {
+    Self { len: bytes.len() as u64, source: Source::Bytes(bytes), position: 0 }
+  }
+  /// Length of the serialized value in bytes.
+  pub fn len (&self) -> u64 {
+    self.len
+  }
+  /// Whether the serialized value is empty, which no `Value` type writes.
+  pub fn is_empty (&self) -> bool {
+    self.len == 0
+  }
+  /// Whether the value is read from the blob store as the reader is
+  /// consumed, rather than from a copy made when the reader was created.
+  pub fn is_streamed (&self) -> bool {
+    match self.source {
+      Source::Blob(..) => true,
+      Source::Bytes(_) => false
+    }
+  }
+  fn read_at (&mut self, buf: &mut [u8]) -> Result<usize,Error> {
+    let n = ((self.len - self.position) as usize).min(buf.len());
+    if n == 0 { return Ok(0) }
+    match &self.source {
+      Source::Bytes(bytes) => {
+        let start = self.position as usize;
+        buf[..n].copy_from_slice(&bytes[start..start+n]);
+      },
+      Source::Blob(data_store,offset) => {
+        let mut dstore = data_store.try_borrow_mut()?;
+        let blobs = match &mut dstore.blobs {
+          Some(blobs) => blobs,
+          None => bail!["the blob store is not open"]
+        };
+        let bytes = blobs.store.read(offset + self.position, n as u64)?;
+        buf[..n].copy_from_slice(&bytes);
+      }
+    }
+    self.position += n as u64;
+    Ok(n)
+  }
+}
+
+impl<S,P,V> Read for ValueReader<S,P,V>
+where S: RandomAccess<Error=Error>, P: Point, V: Value {
+  fn read (&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    self.read_at(buf).map_err(|err| {
+      io::Error::new(io::ErrorKind::Other, err.to_string())
+    })
+  }
+}
+
+impl<S,U,P,V> DB<S,U,P,V> where
+S: RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>),
+P: Point, V: Value {
+  /// Open a reader of the serialized value of the record at `location`, as
+  /// returned by a query, to stream large values to a file or a socket
+  /// instead of holding them in memory. Values stored as blobs (see
+  /// `Setup::inline_threshold()`) are read in pieces as the reader is
+  /// consumed.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{DB,Setup,Location,IoMode,disk_storage};
+  /// # use failure::Error;
+  /// # use std::io;
+  /// # fn main () -> Result<(),Error> {
+  /// # let location: Location = (1,0);
+  /// let storage = disk_storage("/tmp/eyros-db/", IoMode::Buffered);
+  /// let mut db: DB<_,_,(f32,f32),Vec<u8>> = Setup::new(storage)
+  ///   .inline_threshold(64*1024)
+  ///   .build()?;
+  /// let mut reader = db.value_reader(&location)?;
+  /// io::copy(&mut reader, &mut io::sink())?;
+  /// # Ok(()) }
+  /// ```
+  ///
+  /// As with deletes, locations are only valid until the next `.batch()`.
+  /// Fails if no record is stored at `location`.
+  pub fn value_reader (&mut self, location: &Location)
+  -> Result<ValueReader<S,P,V>,Error> {
+    let missing = || format_err!["no record at location {:?}", location];
+    if self.staging.delete_set.try_borrow()?.contains(location) {
+      return Err(missing());
+    }
+    let (block,index) = *location;
+    if block == 0 {
+      let mut bytes = None;
+      self.staging.for_each(|_,v,loc| {
+        if loc == *location { bytes = Some(v.to_bytes()?) }
+        Ok(())
+      })?;
+      return Ok(ValueReader::bytes(bytes.ok_or_else(missing)?));
+    }
+    let mut dstore = self.data_store.try_borrow_mut()?;
+    match dstore.stored_value(block-1, index)? {
+      None => Err(missing()),
+      Some(StoredValue::Inline(bytes)) => Ok(ValueReader::bytes(bytes)),
+      Some(StoredValue::Blob(offset,len)) => {
+        let end = match &mut dstore.blobs {
+          Some(blobs) => blobs.store.len()?,
+          None => bail!["data block refers to a value in the blob store, \
+            which is only opened with Setup::inline_threshold()"]
+        };
+        if offset + len as u64 > end {
+          bail!["blob of {} bytes at {} is past the end of the blob store",
+            len, offset];
+        }
+        Ok(ValueReader {
+          source: Source::Blob(Rc::clone(&self.data_store),offset),
+          len: len as u64,
+          position: 0
+        })
+      }
+    }
+  }
+}
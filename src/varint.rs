@@ -0,0 +1,73 @@
+use crate::Location;
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::{Error,bail};
+
+// LEB128 encoding of a location for the staging delete log, from
+// FORMAT_VARINT_DELETES on: the block offset then the index, 7 bits per byte
+// with the high bit set on every byte but the last. Locations of small
+// databases take 2 to 5 bytes instead of 12.
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct VarLocation(pub Location);
+
+fn len (mut x: u64) -> usize {
+  let mut n = 1;
+  while x >= 0x80 {
+    x >>= 7;
+    n += 1;
+  }
+  n
+}
+
+fn write (mut x: u64, dst: &mut [u8]) -> Result<usize,Error> {
+  let n = len(x);
+  if dst.len() < n { bail!["dst buffer too small for varint"] }
+  for i in 0..n {
+    dst[i] = (x & 0x7f) as u8 | if i+1 < n { 0x80 } else { 0 };
+    x >>= 7;
+  }
+  Ok(n)
+}
+
+fn read (src: &[u8], max_bits: u32) -> Result<(usize,u64),Error> {
+  let mut x = 0u64;
+  for (i,b) in src.iter().enumerate() {
+    let shift = 7*i as u32;
+    let bits = (b & 0x7f) as u64;
+    if shift >= max_bits
+    || (max_bits-shift < 7 && bits >> (max_bits-shift) > 0) {
+      bail!["varint overflows {} bits", max_bits];
+    }
+    x |= bits << shift;
+    if b & 0x80 == 0 { return Ok((i+1,x)) }
+  }
+  bail!["buffer too small while loading varint"]
+}
+
+impl CountBytes for VarLocation {
+  fn count_bytes (&self) -> usize {
+    len((self.0).0) + len((self.0).1 as u64)
+  }
+  fn count_from_bytes (buf: &[u8]) -> Result<usize,Error> {
+    Ok(Self::from_bytes(buf)?.0)
+  }
+}
+
+impl ToBytes for VarLocation {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    let mut buf = vec![0;self.count_bytes()];
+    self.write_bytes(&mut buf)?;
+    Ok(buf)
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    let n = write((self.0).0, dst)?;
+    Ok(n + write((self.0).1 as u64, &mut dst[n..])?)
+  }
+}
+
+impl FromBytes for VarLocation {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    let (n,offset) = read(src, 64)?;
+    let (m,index) = read(&src[n..], 32)?;
+    Ok((n+m, Self((offset,index as u32))))
+  }
+}
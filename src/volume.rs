@@ -0,0 +1,77 @@
+use crate::{Point,Value,Row};
+use std::ops::{Add,Range,Sub};
+
+/// Point of a point cloud: `(x,y,z)`.
+pub type Point3<T> = (T,T,T);
+/// Volume with an interval in each dimension: `((x0,x1),(y0,y1),(z0,z1))`.
+pub type Box3<T> = ((T,T),(T,T),(T,T));
+/// Query bounds for both `Point3` and `Box3` databases:
+/// `((xmin,ymin,zmin),(xmax,ymax,zmax))`.
+pub type Bounds3<T> = ((T,T,T),(T,T,T));
+
+/// Constructors for the rows of three-dimensional databases, such as point
+/// clouds or buildings with an elevation range.
+///
+/// Without these, every dimension of a volume is its own `(min,max)` tuple
+/// and bounding boxes list the minimums of all dimensions before the
+/// maximums, which is easy to get backwards:
+///
+/// ```rust,no_run
+/// use eyros::{DB,Row3,Box3,bbox3};
+/// # use failure::Error;
+/// # use random_access_disk::RandomAccessDisk;
+/// # use std::path::PathBuf;
+///
+/// # fn main () -> Result<(),Error> {
+/// let mut db: DB<_,_,Box3<f32>,u32> = DB::open(storage)?;
+/// db.batch(&[
+///   Row3::insert_box(0.0..10.0, 0.0..4.0, 120.0..135.5, 1),
+///   Row3::insert_box(3.0..5.0, 2.0..8.0, 90.0..92.0, 2)
+/// ])?;
+/// for result in db.query(&bbox3(4.0..6.0, 1.0..3.0, 100.0..200.0))? {
+///   println!["{:?}", result?];
+/// }
+/// # Ok(()) }
+/// # fn storage(name: &str) -> Result<RandomAccessDisk,Error> {
+/// #   let mut p = PathBuf::from("/tmp/eyros-db/");
+/// #   p.push(name);
+/// #   Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+/// # }
+/// ```
+///
+/// Ranges are inclusive at both ends, like every interval in eyros.
+pub struct Row3;
+
+impl Row3 {
+  /// Insert `value` for the volume spanning the `x`, `y` and `z` ranges.
+  /// Reversed ranges are flipped.
+  pub fn insert_box<T,V> (x: Range<T>, y: Range<T>, z: Range<T>, value: V)
+  -> Row<Box3<T>,V> where Box3<T>: Point, T: PartialOrd, V: Value {
+    Row::Insert((interval(x), interval(y), interval(z)), value)
+  }
+  /// Insert `value` at the point `(x,y,z)`.
+  pub fn insert_point<T,V> (x: T, y: T, z: T, value: V)
+  -> Row<Point3<T>,V> where Point3<T>: Point, V: Value {
+    Row::Insert((x,y,z), value)
+  }
+}
+
+/// Bounds to query the volume spanning the `x`, `y` and `z` ranges.
+/// Reversed ranges are flipped.
+pub fn bbox3<T> (x: Range<T>, y: Range<T>, z: Range<T>) -> Bounds3<T>
+where T: PartialOrd {
+  let (x,y,z) = (interval(x), interval(y), interval(z));
+  ((x.0,y.0,z.0),(x.1,y.1,z.1))
+}
+
+/// Bounds to query the cube of half-width `radius` around `center`.
+pub fn cube<T> (center: Point3<T>, radius: T) -> Bounds3<T>
+where T: Copy+Add<Output=T>+Sub<Output=T> {
+  let (x,y,z) = center;
+  ((x-radius,y-radius,z-radius),(x+radius,y+radius,z+radius))
+}
+
+fn interval<T> (range: Range<T>) -> (T,T) where T: PartialOrd {
+  if range.start <= range.end { (range.start,range.end) }
+  else { (range.end,range.start) }
+}
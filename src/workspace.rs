@@ -0,0 +1,163 @@
+use crate::{DB,Setup,Point,Value,Row,Location,QueryIterator};
+use failure::{Error,bail,format_err};
+use random_access_storage::RandomAccess;
+use std::rc::Rc;
+
+/// Storage function used by each layer of a `Workspace`.
+pub type LayerStore<S> = Box<dyn Fn(&str) -> Result<S,Error>>;
+
+/// Set of named databases, one per layer (roads, buildings, pois), kept
+/// under one root.
+///
+/// Each layer is a complete database whose store names are prefixed with the
+/// name of the layer and a slash, so with `disk_storage(root, mode)` the
+/// stores of the `roads` layer are in `root/roads/`. Every layer is opened
+/// with the configuration of the same `Setup`, and the memory budgets of the
+/// setup (`bbox_cache_size()`, `data_list_cache_size()`, `write_cache_size()`,
+/// `staging_spill()` and `pin_bytes()`) are for the whole workspace and are
+/// split evenly between the layers.
+pub struct Workspace<S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  layers: Vec<(String,DB<S,LayerStore<S>,P,V>)>
+}
+
+impl<S,P,V> Workspace<S,P,V> where
+S: RandomAccess<Error=Error>+'static, P: Point, V: Value {
+  /// Open a layer for each of `names` with the configuration in `setup`.
+  /// Layer names may only contain ascii letters, digits, `_` and `-`.
+  ///
+  /// ```rust,no_run
+  /// # use eyros::{Setup,Workspace,IoMode,disk_storage};
+  /// # use failure::Error;
+  /// # fn main () -> Result<(),Error> {
+  /// type P = ((f32,f32),(f32,f32));
+  /// type V = u32;
+  /// let storage = disk_storage("/tmp/eyros-workspace/", IoMode::Buffered);
+  /// let mut ws: Workspace<_,P,V> = Workspace::open(
+  ///   Setup::new(storage).data_list_cache_size(30_000),
+  ///   &["roads","buildings","pois"]
+  /// )?;
+  /// for result in ws.query(&((-0.5,-0.5),(0.5,0.5)))? {
+  ///   let (layer,point,value,_location) = result?;
+  ///   println!["{}: {:?} {}", layer, point, value];
+  /// }
+  /// # Ok(()) }
+  /// ```
+  pub fn open<U> (setup: Setup<S,U>, names: &[&str]) -> Result<Self,Error>
+  where U: (Fn(&str) -> Result<S,Error>)+'static {
+    if names.is_empty() {
+      bail!["a workspace needs at least one layer"];
+    }
+    if setup.log.is_some() {
+      bail!["Setup::log() is not supported by a workspace"];
+    }
+    for (i,name) in names.iter().enumerate() {
+      if name.is_empty() || !name.chars().all(|c| {
+        c.is_ascii_alphanumeric() || c == '_' || c == '-'
+      }) {
+        bail!["invalid layer name {:?}", name];
+      }
+      if names[..i].contains(name) {
+        bail!["layer {} is listed more than once", name];
+      }
+    }
+    let n = names.len();
+    let mut fields = setup.fields.clone();
+    fields.bbox_cache_size = (fields.bbox_cache_size/n).max(1);
+    fields.data_list_cache_size = (fields.data_list_cache_size/n).max(1);
+    fields.write_cache_size = share(fields.write_cache_size, n);
+    fields.staging_spill = share(fields.staging_spill, n);
+    fields.pin_bytes = share(fields.pin_bytes, n);
+    let open_store = Rc::new(setup.open_store);
+    let mut layers = Vec::with_capacity(n);
+    for name in names.iter() {
+      let open = Rc::clone(&open_store);
+      let prefix = name.to_string();
+      let store: LayerStore<S> = Box::new(move |store_name: &str| {
+        (*open)(&format!["{}/{}",prefix,store_name])
+      });
+      layers.push((name.to_string(), DB::open_from_setup(Setup {
+        open_store: store,
+        fields: fields.clone(),
+        log: None,
+        clock: setup.clock.clone(),
+        merge_policy: setup.merge_policy.clone(),
+        transform: setup.transform.clone(),
+        partition: setup.partition.clone()
+      })?));
+    }
+    Ok(Self { layers })
+  }
+
+  /// Names of the layers, in the order they were opened.
+  pub fn layer_names (&self) -> impl Iterator<Item=&str> {
+    self.layers.iter().map(|(name,_)| name.as_str())
+  }
+
+  /// Database of the layer called `name`, for the operations that the
+  /// workspace does not cover.
+  pub fn layer (&mut self, name: &str)
+  -> Result<&mut DB<S,LayerStore<S>,P,V>,Error> {
+    self.layers.iter_mut()
+      .find(|(n,_)| n == name)
+      .map(|(_,db)| db)
+      .ok_or_else(|| format_err!["no layer named {}", name])
+  }
+
+  /// Write `rows` to the layer called `name`, like `db.batch()`. Locations
+  /// of deletes must come from the same layer.
+  pub fn batch (&mut self, name: &str, rows: &[Row<P,V>])
+  -> Result<(),Error> {
+    self.layer(name)?.batch(rows)
+  }
+
+  /// Query every layer for the records that intersect `bbox`, tagging each
+  /// result with the name of its layer. The results of each layer are
+  /// returned before those of the next layer.
+  pub fn query<'w,'b> (&'w mut self, bbox: &'b P::Bounds)
+  -> Result<WorkspaceIterator<'w,'b,S,P,V>,Error> {
+    let mut queries = Vec::with_capacity(self.layers.len());
+    for (name,db) in self.layers.iter_mut() {
+      queries.push((name.as_str(),db.query(bbox)?));
+    }
+    queries.reverse();
+    Ok(WorkspaceIterator { queries })
+  }
+
+  /// Sync the stores of every layer, as with `db.sync()`.
+  pub fn sync (&mut self) -> Result<(),Error> {
+    for (_,db) in self.layers.iter_mut() {
+      db.sync()?;
+    }
+    Ok(())
+  }
+}
+
+// even share of a byte budget for each of `n` layers, where 0 stays unlimited
+// or disabled
+fn share (bytes: usize, n: usize) -> usize {
+  if bytes == 0 { 0 } else { (bytes/n).max(1) }
+}
+
+/// Iterator of `Result<(layer,Point,Value,Location)>` records returned by
+/// `workspace.query()`.
+pub struct WorkspaceIterator<'w,'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  // queries of the layers left to read, in reverse order
+  queries: Vec<(&'w str,QueryIterator<'b,S,P,V>)>
+}
+
+impl<'w,'b,S,P,V> Iterator for WorkspaceIterator<'w,'b,S,P,V> where
+S: RandomAccess<Error=Error>, P: Point, V: Value {
+  type Item = Result<(&'w str,P,V,Location),Error>;
+  fn next (&mut self) -> Option<Self::Item> {
+    while let Some((name,q)) = self.queries.last_mut() {
+      match q.next() {
+        Some(Ok((p,v,loc))) => return Some(Ok((*name,p,v,loc))),
+        Some(Err(e)) => return Some(Err(e)),
+        None => { self.queries.pop(); }
+      }
+    }
+    None
+  }
+}
@@ -1,12 +1,19 @@
 use random_access_storage::RandomAccess;
 use std::io::Write;
 
+/// Queue of pending writes in front of a store, flushed by `sync_all()`.
+///
+/// With a `max_bytes` limit, a write that grows the queue past the limit
+/// flushes the writes at the lowest offsets until the queue is at most half
+/// full, so memory use stays bounded between syncs.
 #[derive(Debug,Clone)]
 pub struct WriteCache<S> where S: RandomAccess {
   store: S,
   queue: Vec<(u64,Vec<u8>)>,
   length: u64,
-  enabled: bool
+  enabled: bool,
+  bytes: usize,
+  max_bytes: usize
 }
 
 impl<S> WriteCache<S> where S: RandomAccess {
@@ -16,9 +23,46 @@ impl<S> WriteCache<S> where S: RandomAccess {
       store,
       queue: vec![],
       length,
-      enabled: true
+      enabled: true,
+      bytes: 0,
+      max_bytes: 0
     })
   }
+  /// Number of bytes of queued writes.
+  pub fn cached_bytes (&self) -> usize {
+    self.bytes
+  }
+  /// Limit on queued bytes, or `0` for no limit.
+  pub fn max_bytes (&self) -> usize {
+    self.max_bytes
+  }
+  /// Set the limit on queued bytes (`0` for no limit), flushing immediately if
+  /// the queue is already over the new limit.
+  pub fn set_max_bytes (&mut self, max_bytes: usize) -> Result<(),S::Error> {
+    self.max_bytes = max_bytes;
+    self.check_limit()
+  }
+  /// Write queued data to the store until at most `target` bytes remain
+  /// queued, starting from the lowest offsets.
+  pub fn flush_to (&mut self, target: usize) -> Result<(),S::Error> {
+    let mut n = 0;
+    let mut bytes = self.bytes;
+    while n < self.queue.len() && bytes > target {
+      let q = &self.queue[n];
+      self.store.write(q.0, &q.1)?;
+      bytes -= q.1.len();
+      n += 1;
+    }
+    self.queue.drain(..n);
+    self.bytes = bytes;
+    Ok(())
+  }
+  fn check_limit (&mut self) -> Result<(),S::Error> {
+    if self.max_bytes > 0 && self.bytes > self.max_bytes {
+      self.flush_to(self.max_bytes/2)?;
+    }
+    Ok(())
+  }
 }
 
 impl<S> RandomAccess for WriteCache<S> where S: RandomAccess {
@@ -50,8 +94,9 @@ impl<S> RandomAccess for WriteCache<S> where S: RandomAccess {
     ].copy_from_slice(data);
  
     for (i,ov) in overlapping.iter().enumerate() {
-      self.queue.remove(ov-i);
+      self.bytes -= self.queue.remove(ov-i).1.len();
     }
+    self.bytes += merged.1.len();
     if overlapping.is_empty() {
       let mut j = 0;
       for i in 0..self.queue.len() {
@@ -64,7 +109,7 @@ impl<S> RandomAccess for WriteCache<S> where S: RandomAccess {
       self.queue.insert(overlapping[0], merged);
     }
     self.length = self.length.max(end);
-    Ok(())
+    self.check_limit()
   }
   fn read (&mut self, offset: u64, length: u64)
   -> Result<Vec<u8>,Self::Error> {
@@ -117,10 +162,11 @@ impl<S> RandomAccess for WriteCache<S> where S: RandomAccess {
     while i < self.queue.len() {
       let q0 = self.queue[i].0;
       let qlen = self.queue[i].1.len() as u64;
-      if q0 < length {
-        self.queue.remove(i);
-      } else if q0 + qlen < length {
-        self.queue[i].1.truncate((length - q0 as u64) as usize);
+      if q0 >= length {
+        self.bytes -= self.queue.remove(i).1.len();
+      } else if q0 + qlen > length {
+        self.queue[i].1.truncate((length - q0) as usize);
+        self.bytes -= (q0 + qlen - length) as usize;
         i += 1;
       } else {
         i += 1;
@@ -139,11 +185,7 @@ impl<S> RandomAccess for WriteCache<S> where S: RandomAccess {
     else { self.store.is_empty() }
   }
   fn sync_all (&mut self) -> Result<(),S::Error> {
-    for q in self.queue.iter() {
-      self.store.write(q.0, &q.1)?;
-    }
-    self.queue.clear();
-    Ok(())
+    self.flush_to(0)
   }
 }
 
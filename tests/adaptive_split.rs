@@ -0,0 +1,101 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,PointOrd,MemoryStore,memory_storage,TREE_ADAPTIVE};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),f32);
+type V = u32;
+
+#[test]
+fn adaptive_split() -> Result<(),Error> {
+  let inserts = roads();
+  let bbox = ((10.0,-1.0),(12.0,2.0));
+  let mut round_robin = open(&inserts, false)?;
+  let mut adaptive = open(&inserts, true)?;
+  assert![adaptive.tree_formats()?.contains(&TREE_ADAPTIVE),
+    "adaptive trees are tagged"];
+  assert![!round_robin.tree_formats()?.contains(&TREE_ADAPTIVE),
+    "other trees keep the older format"];
+
+  let mut expected: Vec<V> = inserts.iter()
+    .filter(|(p,_)| p.overlaps(&bbox))
+    .map(|(_,v)| *v)
+    .collect();
+  expected.sort_unstable();
+  for db in [&mut round_robin, &mut adaptive] {
+    let mut results = db.query(&bbox)?
+      .map(|r| r.map(|(_,v,_)| v))
+      .collect::<Result<Vec<V>,Error>>()?;
+    results.sort_unstable();
+    assert_eq![results, expected];
+  }
+  assert![blocks(&mut adaptive, &bbox)? <= blocks(&mut round_robin, &bbox)?,
+    "splitting on the wide dimension reads no more blocks"];
+  Ok(())
+}
+
+#[test]
+fn adaptive_split_cycle() -> Result<(),Error> {
+  let inserts = roads();
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .adaptive_split(true)
+    .dimension_cycle(&[1])
+    .base_size(400)
+    .max_data_size(50)
+    .build()?;
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  db.batch(&rows)?;
+  let bbox = ((-100.0,0.2),(100.0,0.3));
+  let mut expected: Vec<V> = inserts.iter()
+    .filter(|(p,_)| p.overlaps(&bbox))
+    .map(|(_,v)| *v)
+    .collect();
+  expected.sort_unstable();
+  let mut results = db.query(&bbox)?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  results.sort_unstable();
+  assert_eq![results, expected, "only the dimensions of the cycle are split"];
+  Ok(())
+}
+
+// segments along a long, thin strip
+fn roads () -> Vec<(P,V)> {
+  let mut r = rand().seed([13,12]);
+  (0..5_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>();
+    (((x,x+r.read::<f32>()),y), i)
+  }).collect()
+}
+
+fn open (inserts: &[(P,V)], adaptive: bool) -> Result<DB<MemoryStore,
+impl Fn(&str) -> Result<MemoryStore,Error>,P,V>,Error> {
+  let mut db = Setup::new(memory_storage())
+    .adaptive_split(adaptive)
+    .base_size(400)
+    .max_data_size(50)
+    .build()?;
+  for batch in inserts.chunks(1_000) {
+    let rows: Vec<Row<P,V>> = batch.iter()
+      .map(|(p,v)| Row::Insert(*p,*v))
+      .collect();
+    db.batch(&rows)?;
+  }
+  Ok(db)
+}
+
+fn blocks<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<usize,Error> where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut count = 0;
+  for tree in db.trees.iter() {
+    let mut tree = tree.try_borrow_mut()?;
+    if !tree.is_empty()? { count += tree.query_blocks(bbox)?.len() }
+  }
+  Ok(count)
+}
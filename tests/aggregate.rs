@@ -0,0 +1,58 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Agg};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::collections::HashMap;
+
+type P = ((f32,f32),(f32,f32));
+type V = (u8,u32);
+type F = fn(&V) -> f64;
+
+#[test]
+fn aggregate_by() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let kind = r.read::<u8>() % 4;
+    Row::Insert(((xmin,xmax),(ymin,ymax)), (kind, r.read::<u32>() % 1000))
+  }).collect();
+  db.batch(&inserts)?;
+
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let mut count: HashMap<u8,f64> = HashMap::new();
+  let mut sum: HashMap<u8,f64> = HashMap::new();
+  let mut min: HashMap<u8,f64> = HashMap::new();
+  let mut max: HashMap<u8,f64> = HashMap::new();
+  for result in db.query(&bbox)? {
+    let (_,(k,x),_) = result?;
+    let x = x as f64;
+    *count.entry(k).or_insert(0.0) += 1.0;
+    *sum.entry(k).or_insert(0.0) += x;
+    let m = min.entry(k).or_insert(x);
+    *m = m.min(x);
+    let m = max.entry(k).or_insert(x);
+    *m = m.max(x);
+  }
+  assert_eq![count.len(), 4, "every kind is present"];
+  let value: F = |v| v.1 as f64;
+  assert_eq![db.aggregate_by(&bbox, |v| v.0, Agg::<F>::Count)?, count];
+  assert_eq![db.aggregate_by(&bbox, |v| v.0, Agg::Sum(value))?, sum];
+  assert_eq![db.aggregate_by(&bbox, |v| v.0, Agg::Min(value))?, min];
+  assert_eq![db.aggregate_by(&bbox, |v| v.0, Agg::Max(value))?, max];
+  Ok(())
+}
@@ -0,0 +1,40 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn analyze() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(500).max_data_size(100).build()?;
+  let mut inserts: Vec<Row<P,V>> = (0..4_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x),(y,y)), i)
+  }).collect();
+  // a pile of identical points, small enough to fit in one data block since
+  // identical points can not be split across blocks
+  for i in 0..90 {
+    inserts.push(Row::Insert(((0.25,0.25),(0.5,0.5)), 10_000+i));
+  }
+  for batch in inserts.chunks(1_000) {
+    db.batch(batch)?;
+  }
+  let analysis = db.analyze(5)?;
+  assert_eq![analysis.records, 4_090, "every record counted"];
+  assert_eq![analysis.max_duplicates, 90, "largest pile of duplicates"];
+  assert_eq![analysis.duplicate_records(), 89, "duplicates of one point"];
+  assert![analysis.blocks >= 41, "blocks of at most 100 records"];
+  assert![analysis.max_depth as f64 >= analysis.mean_depth, "depths"];
+  assert_eq![analysis.hot_blocks.len(), 5, "top blocks only"];
+  assert![analysis.hot_blocks.windows(2)
+    .all(|w| w[0].overlaps >= w[1].overlaps), "most overlaps first"];
+  Ok(())
+}
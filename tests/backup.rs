@@ -0,0 +1,78 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn backup() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut db = open(memory_storage())?;
+  for batch in inserts[0..2_000].chunks(250) {
+    db.batch(batch)?;
+  }
+  let expected = query(&mut db, &bbox)?;
+
+  let storage = memory_storage();
+  let mut backup = db.backup_to(storage.clone())?;
+  assert![db.backup_to(memory_storage()).is_err(),
+    "only one backup at a time"];
+  let mut batches = inserts[2_000..].chunks(250);
+  while !backup.step(4_096)? {
+    let (copied,total) = backup.progress();
+    assert![copied < total, "progress below the total until complete"];
+    // delete from blocks that were already written, then insert more
+    let deletes: Vec<Row<P,V>> = query(&mut db, &bbox)?.iter().step_by(7)
+      .map(|(_,_,loc)| Row::Delete(*loc)).collect();
+    db.batch(&deletes)?;
+    if let Some(batch) = batches.next() {
+      db.batch(batch)?;
+    }
+  }
+  assert![query(&mut db, &bbox)? != expected, "database changed meanwhile"];
+
+  let mut copy = open(storage)?;
+  assert_eq![query(&mut copy, &bbox)?, expected,
+    "backup holds the database as it was when the backup started"];
+  db.backup_to(memory_storage())?.finish()?;
+  Ok(())
+}
+
+fn open<U> (storage: U) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage).base_size(100).build()
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
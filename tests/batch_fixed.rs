@@ -0,0 +1,63 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+use std::cmp::Ordering;
+
+type P = (f32,f32,f32);
+type V = u32;
+
+#[test]
+fn batch_fixed() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let points: Vec<P> = (0..25_000).map(|_| {
+    (r.read::<f32>()*2.0-1.0, r.read::<f32>()*2.0-1.0, r.read::<f32>()*100.0)
+  }).collect();
+  let values: Vec<V> = (0..25_000).map(|_| r.read::<u32>()).collect();
+  let bbox = ((-0.5,-0.8,10.0),(0.3,-0.1,60.0));
+
+  let mut fixed = open(memory_storage())?;
+  let mut rows = open(memory_storage())?;
+  // batches below, at and above the base size
+  for (i,j) in [(0,300),(300,1_300),(1_300,25_000)].iter() {
+    fixed.batch_fixed(&points[*i..*j], &values[*i..*j])?;
+    let batch: Vec<Row<P,V>> = points[*i..*j].iter().zip(&values[*i..*j])
+      .map(|(p,v)| Row::Insert(*p,*v))
+      .collect();
+    rows.batch(&batch)?;
+    assert_eq![query(&mut fixed, &bbox)?, query(&mut rows, &bbox)?,
+      "same results as db.batch()"];
+  }
+  assert![fixed.batch_fixed(&points[0..2], &values[0..1]).is_err(),
+    "a value for each point"];
+  Ok(())
+}
+
+fn open<U> (storage: U) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage).base_size(1_000).build()
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>,
+bbox: &((f32,f32,f32),(f32,f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,63 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,BatchId,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn batch_ids() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..1_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let ids: Vec<BatchId> = (0..10u8).map(|i| [i;16]).collect();
+
+  let storage = memory_storage();
+  let mut db = open(storage.clone(), 4)?;
+  for (id,batch) in ids.iter().zip(inserts.chunks(100)) {
+    assert![db.batch_with_id(id, batch)?, "new batch is written"];
+    assert![!db.batch_with_id(id, batch)?, "repeated batch is skipped"];
+  }
+  assert_eq![count(&mut db, &bbox)?, 1_000, "each batch written once"];
+  assert![db.has_batch_id(&ids[9]), "recent id is kept"];
+  assert![!db.has_batch_id(&ids[5]), "id outside the window is evicted"];
+
+  let mut db = open(storage, 4)?;
+  for id in ids[6..].iter() {
+    assert![db.has_batch_id(id), "ids are kept when reopened"];
+    assert![!db.batch_with_id(id, &inserts[0..100])?,
+      "batch applied before reopening is skipped"];
+  }
+  assert_eq![count(&mut db, &bbox)?, 1_000, "no batch written again"];
+
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage()).build()?;
+  assert![db.batch_with_id(&ids[0], &inserts[0..100]).is_err(),
+    "batch ids must be enabled in the setup"];
+  Ok(())
+}
+
+fn open<U> (storage: U, window: usize) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage).base_size(100).batch_ids(window).build()
+}
+
+fn count<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<usize,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut n = 0;
+  for result in db.query(bbox)? {
+    result?;
+    n += 1;
+  }
+  Ok(n)
+}
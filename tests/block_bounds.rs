@@ -0,0 +1,106 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn block_bounds() -> Result<(),Error> {
+  let plain_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let bounds_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut plain: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = plain_dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).max_data_size(300).build()?;
+  let mut bounded: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = bounds_dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).max_data_size(300).block_bounds(true).build()?;
+
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..30_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  for batch in inserts.chunks(6_000) {
+    plain.batch(batch)?;
+    bounded.batch(batch)?;
+  }
+  assert![
+    std::fs::metadata(bounds_dir.path().join("data"))?.len()
+      > std::fs::metadata(plain_dir.path().join("data"))?.len(),
+    "data blocks include bounds headers"
+  ];
+
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.5,-0.8),(0.3,-0.5)),
+    ((0.1,0.1),(0.12,0.15)),
+    ((0.9,-1.0),(1.0,-0.9))
+  ];
+  for bbox in bboxes.iter() {
+    let results: Vec<(P,V)> = query(&mut bounded, bbox)?.into_iter()
+      .map(|(p,v,_)| (p,v)).collect();
+    let expected: Vec<(P,V)> = query(&mut plain, bbox)?.into_iter()
+      .map(|(p,v,_)| (p,v)).collect();
+    assert_eq![results, expected, "same results for {:?}", bbox];
+  }
+
+  // enough deletes to be written into the bitfields that precede the bounds
+  // headers
+  let full = query(&mut bounded, &bboxes[0])?;
+  let deleted: HashSet<Location> = full.iter().step_by(3)
+    .map(|row| row.2).collect();
+  let deletes: Vec<Row<P,V>> = deleted.iter()
+    .map(|loc| Row::Delete(*loc)).collect();
+  bounded.batch(&deletes)?;
+  let results = query(&mut bounded, &bboxes[0])?;
+  assert_eq![results.len(), full.len()-deleted.len(),
+    "deleted records removed"];
+  // compacting staging hands deleted staged locations to surviving rows, so
+  // compare records rather than locations
+  let kept: Vec<(P,V)> = full.iter()
+    .filter(|row| !deleted.contains(&row.2))
+    .map(|(p,v,_)| (*p,*v)).collect();
+  let results: Vec<(P,V)> = results.into_iter()
+    .map(|(p,v,_)| (p,v)).collect();
+  assert_eq![results, kept, "no deleted records in the results"];
+  Ok(())
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error> where
+S: RandomAccess<Error=Error>,
+U: Fn(&str) -> Result<S,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
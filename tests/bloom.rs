@@ -0,0 +1,62 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),f32);
+type V = u32;
+
+#[test]
+fn contains_with_bloom() -> Result<(),Error> {
+  contains(10)
+}
+
+#[test]
+fn contains_without_bloom() -> Result<(),Error> {
+  contains(0)
+}
+
+fn contains (bits_per_row: usize) -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  })
+    .base_size(1_000)
+    .max_data_size(100)
+    .bloom_filter(bits_per_row)
+    .build()?;
+  let mut r = rand().seed([13,12]);
+  let points: Vec<P> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    ((xmin,xmax),time)
+  }).collect();
+  let inserts: Vec<Row<P,V>> = points.iter()
+    .map(|p| Row::Insert(*p, r.read::<u32>()))
+    .collect();
+  db.batch(&inserts)?;
+
+  for p in points.iter().step_by(7) {
+    assert![db.contains(p)?, "inserted point {:?} is found", p];
+  }
+  for _ in 0..200 {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let p = ((x,x+0.5),r.read::<f32>()*1000.0+2000.0);
+    assert![!db.contains(&p)?, "missing point {:?} is not found", p];
+  }
+
+  let bbox = ((-1.0,0.0),(1.0,1000.0));
+  let (point,_,location) = db.query(&bbox)?.next().unwrap()?;
+  db.batch(&vec![Row::Delete(location)])?;
+  assert![!db.contains(&point)?, "deleted point is not found"];
+  Ok(())
+}
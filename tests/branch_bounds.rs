@@ -0,0 +1,99 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,Explain};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn branch_bounds() -> Result<(),Error> {
+  let plain_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let bounds_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut plain: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = plain_dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).max_data_size(200).build()?;
+  let mut bounded: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = bounds_dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).max_data_size(200).branch_bounds(true).build()?;
+
+  // two dense clusters on either side of an empty band
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..20_000).map(|i| {
+    let side = if i % 2 == 0 { -1.0 } else { 0.9 };
+    let xmin: f32 = side + r.read::<f32>()*0.1;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*0.01;
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*0.01;
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  for batch in inserts.chunks(5_000) {
+    plain.batch(batch)?;
+    bounded.batch(batch)?;
+  }
+
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.95,-0.2),(-0.92,0.1)),
+    ((0.91,0.5),(0.99,0.52)),
+    ((-0.5,-1.0),(0.5,1.0)),
+    ((-0.89,-0.5),(0.89,0.5))
+  ];
+  for bbox in bboxes.iter() {
+    let mut expected = query(&mut plain, bbox)?;
+    let mut results = query(&mut bounded, bbox)?;
+    results.sort_unstable_by(cmp);
+    expected.sort_unstable_by(cmp);
+    assert_eq![results, expected, "same results for {:?}", bbox];
+    assert![blocks(&bounded.query_explain(bbox)?)
+      <= blocks(&plain.query_explain(bbox)?),
+      "no more data blocks read for {:?}", bbox];
+  }
+
+  // data blocks that hold rows of both clusters span the empty band, but
+  // subtrees on one side of it are skipped
+  let gap = ((-0.5,-1.0),(0.5,1.0));
+  assert_eq![query(&mut bounded, &gap)?.len(), 0, "no records in the band"];
+  assert![blocks(&bounded.query_explain(&gap)?)
+    < blocks(&plain.query_explain(&gap)?),
+    "fewer data blocks read in the empty band"];
+  Ok(())
+}
+
+fn blocks (explain: &Explain) -> usize {
+  explain.trees.iter()
+    .map(|t| t.levels.iter().map(|l| l.blocks).sum::<usize>())
+    .sum()
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error> where
+S: RandomAccess<Error=Error>,
+U: Fn(&str) -> Result<S,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  Ok(results)
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
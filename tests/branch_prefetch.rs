@@ -0,0 +1,55 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,PointOrd,QueryOptions,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn branch_prefetch() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .branch_prefetch(true)
+    .max_data_size(50)
+    .base_size(1_000)
+    .build()?;
+  let inserts: Vec<(P,V)> = (0..8_000).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    (((xmin,xmax),(ymin,ymax)), i)
+  }).collect();
+  for batch in inserts.chunks(2_000) {
+    let rows: Vec<Row<P,V>> = batch.iter()
+      .map(|(p,v)| Row::Insert(*p,*v))
+      .collect();
+    db.batch(&rows)?;
+  }
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.3,-0.2),(0.1,0.4)),
+    ((0.5,0.5),(0.52,0.9))
+  ];
+  for bbox in bboxes.iter() {
+    let mut expected: Vec<V> = inserts.iter()
+      .filter(|(p,_)| p.overlaps(bbox))
+      .map(|(_,v)| *v)
+      .collect();
+    expected.sort_unstable();
+    for interleave in [false,true] {
+      let opts = QueryOptions::new().interleave(interleave);
+      let mut results = db.query_with(bbox, &opts)?
+        .map(|r| r.map(|(_,v,_)| v))
+        .collect::<Result<Vec<V>,Error>>()?;
+      results.sort_unstable();
+      assert_eq![results, expected, "results for {:?} with interleave={}",
+        bbox, interleave];
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,108 @@
+extern crate eyros;
+extern crate failure;
+extern crate serde_json;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,IoMode,disk_storage};
+use failure::{Error,ensure,format_err};
+use serde_json::Value as Json;
+use tempfile::Builder as Tmpfile;
+use std::fs;
+use std::process::Command;
+
+type P = ((f32,f32),(f32,f32));
+type V = Vec<u8>;
+
+const FEATURES: &str = r#"{"type":"FeatureCollection","features":[
+  {"type":"Feature","properties":{"id":0},
+    "geometry":{"type":"Point","coordinates":[1,2]}},
+  {"type":"Feature","properties":{"id":1},
+    "geometry":{"type":"LineString","coordinates":[[-10,-5],[10,5]]}},
+  {"type":"Feature","properties":{"id":2},
+    "geometry":{"type":"Polygon",
+      "coordinates":[[[20,20],[30,20],[30,25],[20,20]]]}},
+  {"type":"Feature","properties":{"id":3},
+    "geometry":{"type":"MultiPolygon","coordinates":[
+      [[[40,40],[41,41],[40,41],[40,40]]],
+      [[[50,50],[51,50],[51,51],[50,50]]]
+    ]}},
+  {"type":"Feature","properties":{"id":4},
+    "geometry":{"type":"GeometryCollection","geometries":[
+      {"type":"Point","coordinates":[-30,-30]},
+      {"type":"LineString","coordinates":[[-31,-32],[-29,-28]]}
+    ]}},
+  {"type":"Feature","properties":{"id":5},"geometry":null}
+]}
+{"type":"Feature","properties":{"id":6},
+  "geometry":{"type":"Point","coordinates":[60,60,12]}}
+"#;
+
+#[test]
+fn cli() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let file = dir.path().join("features.geojson");
+  fs::write(&file, FEATURES)?;
+  let db_path = dir.path().join("db");
+  let db = db_path.to_str().unwrap();
+  run(&["import", db, "geojson", file.to_str().unwrap()])?;
+
+  assert_eq![ids(&run(&["export", db])?)?, vec![0,1,2,3,4,6],
+    "features without a geometry are skipped"];
+  let queries = vec![
+    ("0,1,2,3", vec![0,1]),
+    ("25,21,26,22", vec![2]),
+    ("45,45,46,46", vec![3]),
+    ("-29.5,-29.5,-29.4,-29.4", vec![4]),
+    ("59,59,61,61", vec![6]),
+    ("-100,60,-90,70", vec![])
+  ];
+  for (bbox,expected) in queries.iter() {
+    assert_eq![&ids(&run(&["query", db, bbox])?)?, expected,
+      "bounds of the features in {}", bbox];
+  }
+
+  {
+    let storage = disk_storage(&db_path, IoMode::Buffered);
+    let mut db: DB<_,_,P,V> = DB::open(storage)?;
+    db.migrate()?;
+    db.meta_put("attribution", b"test fixture")?;
+    let deletes: Vec<Row<P,V>> = db.query(&((0.0,1.0),(2.0,3.0)))?
+      .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+      .collect::<Result<_,Error>>()?;
+    db.batch(&deletes)?;
+  }
+  Setup::new(disk_storage(&db_path, IoMode::Buffered))
+    .crs("EPSG:4326")
+    .build::<P,V>()?;
+  run(&["compact", db])?;
+
+  assert_eq![ids(&run(&["export", db])?)?, vec![2,3,4,6],
+    "live records after compacting"];
+  let storage = disk_storage(&db_path, IoMode::Buffered);
+  let db: DB<_,_,P,V> = DB::open(storage)?;
+  assert_eq![db.crs(), Some("EPSG:4326"), "crs kept"];
+  assert_eq![db.meta_get("attribution"), Some(&b"test fixture"[..]),
+    "meta entries kept"];
+  Ok(())
+}
+
+fn run (args: &[&str]) -> Result<String,Error> {
+  let output = Command::new(env!("CARGO_BIN_EXE_eyros-cli"))
+    .args(args)
+    .output()?;
+  ensure![output.status.success(), "eyros-cli {:?} failed: {}",
+    args, String::from_utf8_lossy(&output.stderr)];
+  Ok(String::from_utf8(output.stdout)?)
+}
+
+// sorted ids of the features printed one per line
+fn ids (stdout: &str) -> Result<Vec<u64>,Error> {
+  let mut ids = vec![];
+  for line in stdout.lines() {
+    let json: Json = serde_json::from_str(line)?;
+    ids.push(json["properties"]["id"].as_u64()
+      .ok_or_else(|| format_err!["feature without an id: {}", line])?);
+  }
+  ids.sort_unstable();
+  Ok(ids)
+}
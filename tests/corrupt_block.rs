@@ -0,0 +1,43 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_storage::RandomAccess;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn corrupt_data_block() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let rows: Vec<Row<P,V>> = (0..1_200).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>()*200.0-100.0;
+    Row::Insert(((x,x+1.0),(y,y+1.0)), i)
+  }).collect();
+  db.batch(&rows)?;
+
+  let mut blocks = vec![];
+  for tree in db.trees.iter() {
+    let mut tree = tree.try_borrow_mut()?;
+    if !tree.is_empty()? { blocks.extend(tree.blocks()?) }
+  }
+  assert![!blocks.is_empty(), "batch wrote data blocks"];
+  {
+    // claim a bitfield far larger than each block
+    let mut dstore = db.data_store.try_borrow_mut()?;
+    for offset in blocks {
+      dstore.store.write(offset+4, &[0x3f,0xff])?;
+    }
+  }
+  let bbox = ((-100.0,-100.0),(100.0,100.0));
+  let results = db.query(&bbox)?.collect::<Vec<_>>();
+  assert![results.iter().any(|r| r.is_err()),
+    "corrupt blocks return an error instead of panicking"];
+  Ok(())
+}
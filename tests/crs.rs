@@ -0,0 +1,74 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,WebMercator,Transform,
+  memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = (f64,f64);
+type V = u32;
+
+#[test]
+fn crs() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_000).map(|i| {
+    let lon: f64 = r.read::<f64>()*360.0-180.0;
+    let lat: f64 = r.read::<f64>()*160.0-80.0;
+    Row::Insert((lon,lat), i)
+  }).collect();
+  let storage = memory_storage();
+  let mut db = open(storage.clone())?;
+  assert_eq![db.crs(), Some("EPSG:3857"), "crs recorded"];
+  for batch in inserts.chunks(500) {
+    db.batch_reprojected(batch)?;
+  }
+
+  let bbox = ((-20.0,30.0),(45.0,70.0));
+  let mut expected: Vec<(P,V)> = inserts.iter().filter_map(|row| match row {
+    Row::Insert(p,v) if (bbox.0).0 <= p.0 && p.0 <= (bbox.1).0
+      && (bbox.0).1 <= p.1 && p.1 <= (bbox.1).1 => Some((*p,*v)),
+    _ => None
+  }).collect();
+  expected.sort_unstable_by_key(|x| x.1);
+  let mut results: Vec<(P,V)> = db.query_reprojected(&bbox)?.into_iter()
+    .map(|(p,v,_): (P,V,Location)| (p,v)).collect();
+  results.sort_unstable_by_key(|x| x.1);
+  assert_eq![results.len(), expected.len(), "same records as a scan"];
+  for (a,b) in results.iter().zip(expected.iter()) {
+    assert_eq![a.1, b.1, "same value"];
+    assert![((a.0).0-(b.0).0).abs() < 1e-6 && ((a.0).1-(b.0).1).abs() < 1e-6,
+      "points converted back: {:?} {:?}", a.0, b.0];
+  }
+
+  // the database holds web mercator meters
+  let meters = WebMercator.forward(45.0, 70.0)?;
+  let mut plain: DB<_,_,P,V> = Setup::new(storage.clone()).build()?;
+  assert_eq![plain.crs(), Some("EPSG:3857"), "crs kept after reopening"];
+  let mut n = 0;
+  for result in plain.query(&((-20.1e6,-20.1e6),(20.1e6,20.1e6)))? {
+    let (p,_,_) = result?;
+    assert![p.1.abs() <= 20.1e6, "projected coordinate"];
+    n += 1;
+  }
+  assert_eq![n, inserts.len(), "every record stored"];
+  assert![meters.0 > 5e6 && meters.1 > 1e7, "coordinates in meters"];
+
+  let other: Result<DB<_,_,P,V>,Error> = Setup::new(storage)
+    .crs("EPSG:4326").build();
+  assert![other.is_err(), "crs must match"];
+  let mut untransformed: DB<_,_,P,V> = Setup::new(memory_storage()).build()?;
+  assert![untransformed.query_reprojected(&bbox).is_err(),
+    "query_reprojected requires a transform"];
+  Ok(())
+}
+
+fn open<U> (storage: U) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage)
+    .base_size(500)
+    .crs("EPSG:3857")
+    .transform(WebMercator)
+    .build()
+}
@@ -1,4 +1,5 @@
-use eyros::{DB,Row,Point,Cursor,Block,order,order_len};
+use eyros::{DB,Row,PointOrd,PointCodec,BranchQuery,Cursor,Block,order,
+  order_len};
 use random::{Source,default as rand};
 use failure::{Error,bail};
 use random_access_disk::RandomAccessDisk;
@@ -93,7 +94,7 @@ impl CountBytes for P {
   }
 }
 
-impl Point for P {
+impl PointOrd for P {
   type Bounds = ((f32,f32),(f32,f32));
   type Range = ((f32,f32),(f32,f32));
 
@@ -165,6 +166,43 @@ impl Point for P {
     }
   }
 
+  fn dim () -> usize { 2 }
+
+  fn overlaps (&self, bbox: &Self::Bounds) -> bool {
+    match self {
+      P::Point(x,y) =>
+        (bbox.0).0 <= *x && *x <= (bbox.1).0
+        && (bbox.0).1 <= *y && *y <= (bbox.1).1,
+      P::Interval((x0,x1),(y0,y1)) =>
+        (bbox.0).0 <= *x1 && *x0 <= (bbox.1).0
+        && (bbox.0).1 <= *y1 && *y0 <= (bbox.1).1,
+    }
+  }
+
+  fn bounds (points: &Vec<Self>) -> Option<Self::Bounds> {
+    if points.is_empty() { return None }
+    let inf = f32::INFINITY;
+    let ninf = f32::NEG_INFINITY;
+    Some(points.iter().fold(((inf,inf),(ninf,ninf)), |acc,p| {
+      match p {
+        P::Point(x,y) => (
+          (f32::min((acc.0).0,*x),f32::min((acc.0).1,*y)),
+          (f32::max((acc.1).0,*x),f32::max((acc.1).1,*y))
+        ),
+        P::Interval((x0,y0),(x1,y1)) => (
+          (f32::min((acc.0).0,*x0),f32::min((acc.0).1,*y0)),
+          (f32::max((acc.1).0,*x1),f32::max((acc.1).1,*y1))
+        ),
+      }
+    }))
+  }
+
+  fn bounds_to_range (bbox: Self::Bounds) -> Self::Range {
+    (((bbox.0).0,(bbox.1).0),((bbox.0).1,(bbox.1).1))
+  }
+}
+
+impl PointCodec for P {
   fn serialize_at (&self, level: usize, dst: &mut [u8])
   -> Result<usize,Error> {
     match (level % Self::dim(), self) {
@@ -176,19 +214,30 @@ impl Point for P {
     }
   }
 
-  fn dim () -> usize { 2 }
+  fn pivot_bytes_at (&self, _level: usize) -> usize { 4 }
 
-  fn overlaps (&self, bbox: &Self::Bounds) -> bool {
-    match self {
-      P::Point(x,y) =>
-        (bbox.0).0 <= *x && *x <= (bbox.1).0
-        && (bbox.0).1 <= *y && *y <= (bbox.1).1,
-      P::Interval((x0,x1),(y0,y1)) =>
-        (bbox.0).0 <= *x1 && *x0 <= (bbox.1).0
-        && (bbox.0).1 <= *y1 && *y0 <= (bbox.1).1,
-    }
+  fn count_bytes_at (_buf: &[u8], _level: usize) -> Result<usize,Error> {
+    Ok(4)
+  }
+
+  fn pivot_cmp_at (buf: &[u8], bbox: &Self::Bounds, level: usize)
+  -> Result<(bool,bool),Error> {
+    let (_,pivot) = f32::from_bytes(buf)?;
+    Ok(match level % Self::dim() {
+      0 => ((bbox.0).0 <= pivot, pivot <= (bbox.1).0),
+      1 => ((bbox.0).1 <= pivot, pivot <= (bbox.1).1),
+      _ => panic!["dimension not expected"]
+    })
+  }
+
+  fn format_at (buf: &[u8], _level: usize)
+  -> Result<String,Error> {
+    Ok(format!["{:?}", f32::from_bytes(buf)?.1])
   }
+}
 
+// override the default traversal with one that decodes every pivot up front
+impl BranchQuery for P {
   fn query_branch (buf: &[u8], bbox: &Self::Bounds, bf: usize, level: usize)
   -> Result<(Vec<Cursor>,Vec<Block>),Error> {
     let mut cursors = vec![];
@@ -262,39 +311,6 @@ impl Point for P {
     }
     Ok((cursors,blocks))
   }
-
-  fn pivot_bytes_at (&self, _level: usize) -> usize { 4 }
-
-  fn count_bytes_at (_buf: &[u8], _level: usize) -> Result<usize,Error> {
-    Ok(4)
-  }
-
-  fn bounds (points: &Vec<Self>) -> Option<Self::Bounds> {
-    if points.is_empty() { return None }
-    let inf = f32::INFINITY;
-    let ninf = f32::NEG_INFINITY;
-    Some(points.iter().fold(((inf,inf),(ninf,ninf)), |acc,p| {
-      match p {
-        P::Point(x,y) => (
-          (f32::min((acc.0).0,*x),f32::min((acc.0).1,*y)),
-          (f32::max((acc.1).0,*x),f32::max((acc.1).1,*y))
-        ),
-        P::Interval((x0,y0),(x1,y1)) => (
-          (f32::min((acc.0).0,*x0),f32::min((acc.0).1,*y0)),
-          (f32::max((acc.1).0,*x1),f32::max((acc.1).1,*y1))
-        ),
-      }
-    }))
-  }
-
-  fn bounds_to_range (bbox: Self::Bounds) -> Self::Range {
-    (((bbox.0).0,(bbox.1).0),((bbox.0).1,(bbox.1).1))
-  }
-
-  fn format_at (_buf: &[u8], _level: usize)
-  -> Result<String,Error> {
-    unimplemented![]
-  }
 }
 
 #[test]
@@ -347,7 +363,7 @@ fn mix() -> Result<(),Error> {
   Ok(())
 }
 
-fn contains (point: &P, bbox: &<P as Point>::Bounds) -> bool {
+fn contains (point: &P, bbox: &<P as PointOrd>::Bounds) -> bool {
   match point {
     P::Point(x,y) => {
       contains_pt((bbox.0).0, (bbox.1).0, *x)
@@ -0,0 +1,56 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,DataRange,PointOrd};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn data_range() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let open = |name: &str| -> Result<RandomAccessDisk,Error> {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  };
+  let mut db: DB<_,_,P,V> = Setup::new(open)
+    .base_size(1_000).max_data_size(100).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  db.batch(&inserts)?;
+
+  let mut ranges: DataRange<_,P> = DataRange::new(open("range")?, 0);
+  let mut data = open("data")?;
+  let entries = ranges.iter()?.collect::<Result<Vec<_>,Error>>()?;
+  assert![!entries.is_empty(), "range store has entries"];
+  for (offset,_,len) in entries.iter() {
+    let block = ranges.block(&mut data, *offset)?;
+    let bitfield_len = u16::from_be_bytes([block[0],block[1]]) as usize;
+    let live: u32 = block[2..2+bitfield_len].iter()
+      .map(|b| b.count_ones()).sum();
+    assert_eq![live as u64, *len, "row count for block at {}", offset];
+  }
+
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let expected: Vec<(u64,P,u64)> = entries.iter()
+    .filter(|(_,range,_)| range.overlaps(&bbox))
+    .cloned().collect();
+  let filtered = ranges.filter(&bbox)?.collect::<Result<Vec<_>,Error>>()?;
+  assert![!filtered.is_empty(), "filtered entries"];
+  assert![filtered.len() < entries.len(), "filter skips entries"];
+  assert_eq![filtered, expected];
+  Ok(())
+}
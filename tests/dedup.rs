@@ -0,0 +1,55 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn dedup() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  })
+    .base_size(500)
+    .bloom_filter(10)
+    .dedup(true)
+    .build()?;
+  let mut r = rand().seed([13,12]);
+  let rows: Vec<Row<P,V>> = (0..1_200).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+
+  db.batch(&rows[0..800])?;
+  assert_eq![db.query(&bbox)?.count(), 800, "first batch"];
+
+  // overlapping import: 400 rows already stored, 400 new rows, and one row
+  // repeated within the batch
+  let mut overlap = rows[400..1_200].to_vec();
+  overlap.push(rows[1_100].clone());
+  db.batch(&overlap)?;
+  assert_eq![db.query(&bbox)?.count(), 1_200, "duplicates are dropped"];
+
+  // the same point with a different value is kept
+  let same_point = match &rows[0] {
+    Row::Insert(p,v) => Row::Insert(*p, v.wrapping_add(1)),
+    _ => panic!["unexpected row type"]
+  };
+  db.batch(&vec![same_point, rows[0].clone()])?;
+  assert_eq![db.query(&bbox)?.count(), 1_201, "distinct values are kept"];
+  Ok(())
+}
@@ -0,0 +1,60 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,QueryOptions,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use std::collections::HashSet;
+
+type P = ((f32,f32),(f32,f32));
+type V = (u32,u8);
+
+#[test]
+fn dedup_by() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  // each feature is split into parts along a grid of 0.1 by 0.1 tiles
+  let mut rows: Vec<Row<P,V>> = vec![];
+  for id in 0..1_000 {
+    let x = r.read::<f32>()*2.0-1.0;
+    let y = r.read::<f32>()*2.0-1.0;
+    let parts = 1 + r.read::<u8>()%4;
+    for part in 0..parts {
+      let x0 = x + (part as f32)*0.1;
+      rows.push(Row::Insert(((x0,x0+0.1),(y,y+0.05)), (id,part)));
+    }
+  }
+  for batch in rows.chunks(900) {
+    db.batch(batch)?;
+  }
+
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let mut ids: Vec<u32> = vec![];
+  for result in db.query(&bbox)? {
+    ids.push((result?.1).0);
+  }
+  let expected: HashSet<u32> = ids.iter().copied().collect();
+  assert![expected.len() < ids.len(), "features returned more than once"];
+
+  let opts = QueryOptions::new().dedup_by(|v: &V| v.0);
+  let mut results: Vec<u32> = vec![];
+  for result in db.query_with(&bbox, &opts)? {
+    results.push((result?.1).0);
+  }
+  assert_eq![results.len(), expected.len(), "one result for each feature"];
+  assert_eq![results.iter().copied().collect::<HashSet<u32>>(), expected,
+    "every feature returned"];
+
+  let opts = opts.limit(50);
+  let page: HashSet<u32> = db.query_with(&bbox, &opts)?
+    .map(|result| result.map(|(_,v,_)| v.0))
+    .collect::<Result<HashSet<u32>,Error>>()?;
+  assert_eq![page.len(), 50, "limit counts distinct features"];
+
+  let opts = QueryOptions::new().dedup_by(|v: &u32| *v);
+  assert![db.query_with(&bbox, &opts).is_err(),
+    "key for another value type rejected"];
+  Ok(())
+}
@@ -0,0 +1,49 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn dedup_results() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let open = |name: &str| -> Result<RandomAccessDisk,Error> {
+    Ok(RandomAccessDisk::builder(dir.path().join(name)).build()?)
+  };
+  let mut db: DB<_,_,P,V> = Setup::new(&open)
+    .base_size(500)
+    .dedup_results(true)
+    .build()?;
+  let mut r = rand().seed([13,12]);
+  let rows: Vec<Row<P,V>> = (0..1_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  // the first 1000 rows are written to trees, the repeated 100 stay in staging
+  db.batch(&rows)?;
+  db.batch(&rows[0..100])?;
+
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut locations = vec![];
+  for result in db.query(&bbox)? {
+    locations.push(result?.2);
+  }
+  assert_eq![locations.len(), 1_000, "duplicate results are skipped"];
+  drop(db);
+
+  let mut db: DB<_,_,P,V> = Setup::new(&open).base_size(500).build()?;
+  assert_eq![db.query(&bbox)?.count(), 1_100, "duplicates without dedup"];
+  Ok(())
+}
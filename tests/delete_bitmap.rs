@@ -0,0 +1,99 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+
+use std::collections::HashSet;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn delete_bitmap() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..8_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut deleted: HashSet<Location> = HashSet::new();
+  {
+    let mut db = open(dir.path())?;
+    for batch in inserts.chunks(2_000) {
+      db.batch(batch)?;
+    }
+    let full = query(&mut db, &bbox)?;
+    assert_eq![full.len(), inserts.len(), "all records before deleting"];
+
+    // a few tree deletes stay in the bitmaps
+    let deletes: Vec<Row<P,V>> = full.iter().step_by(20).map(|row| {
+      deleted.insert(row.2);
+      Row::Delete(row.2)
+    }).collect();
+    db.batch(&deletes)?;
+    let bitmap_len = std::fs::metadata(dir.path().join("delete_bitmap"))?.len();
+    assert![bitmap_len > 0, "deletes written to the delete_bitmap store"];
+    let results = query(&mut db, &bbox)?;
+    assert_eq![results.len(), full.len()-deleted.len(),
+      "deleted records excluded immediately"];
+    assert![results.iter().all(|row| !deleted.contains(&row.2)),
+      "no deleted locations in results"];
+  }
+  {
+    let mut db = open(dir.path())?;
+    let full = query(&mut db, &bbox)?;
+    assert_eq![full.len(), inserts.len()-deleted.len(),
+      "deleted records excluded after reopening"];
+    assert![full.iter().all(|row| !deleted.contains(&row.2)),
+      "no deleted locations after reopening"];
+
+    // enough deletes to write the bitmaps into the data blocks
+    let deletes: Vec<Row<P,V>> = full.iter().step_by(3).map(|row| {
+      Row::Delete(row.2)
+    }).collect();
+    db.batch(&deletes)?;
+    let bitmap_len = std::fs::metadata(dir.path().join("delete_bitmap"))?.len();
+    assert_eq![bitmap_len, 0, "delete bitmaps cleared once applied"];
+    let results = query(&mut db, &bbox)?;
+    assert_eq![results.len(), full.len()-deletes.len(),
+      "deleted records excluded after applying the bitmaps"];
+    let removed: HashSet<Location> = full.iter().step_by(3)
+      .map(|row| row.2).collect();
+    assert![results.iter().all(|row| !removed.contains(&row.2)),
+      "no deleted locations after applying the bitmaps"];
+  }
+  Ok(())
+}
+
+fn open (dir: &Path) -> Result<DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let open_store: Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>
+    = Box::new(move |name: &str| {
+      let p = dir.join(name);
+      Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+    });
+  Setup::new(open_store).base_size(1_000).delete_bitmap(true).build()
+}
+
+fn query (db: &mut DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,
+bbox: &((f32,f32),(f32,f32))) -> Result<Vec<(P,V,Location)>,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  Ok(results)
+}
@@ -0,0 +1,41 @@
+extern crate eyros;
+
+use eyros::{DeleteSet,Location};
+
+#[test]
+fn delete_set() {
+  let mut set = DeleteSet::new();
+  assert![set.is_empty()];
+  assert![set.insert((0,3))];
+  assert![set.insert((0,70_000))];
+  assert![set.insert((4097,0))];
+  assert![!set.insert((0,3)), "duplicate insert"];
+  assert_eq![set.len(), 3];
+  assert![set.contains(&(0,3))];
+  assert![set.contains(&(4097,0))];
+  assert![!set.contains(&(4097,3))];
+  assert![!set.contains(&(1,0))];
+
+  let mut blocks: Vec<u64> = set.blocks().collect();
+  blocks.sort();
+  assert_eq![blocks, vec![0,4097]];
+  let mut locations: Vec<Location> = set.iter().collect();
+  locations.sort();
+  assert_eq![locations, vec![(0,3),(0,70_000),(4097,0)]];
+
+  assert![set.remove(&(4097,0))];
+  assert![!set.remove(&(4097,0)), "already removed"];
+  assert_eq![set.blocks().count(), 1];
+
+  set.extend((0..1000).map(|i| (9,i*2)));
+  assert_eq![set.len(), 1002];
+  set.retain(|loc| loc.0 != 0);
+  assert_eq![set.len(), 1000];
+  assert![!set.contains(&(0,3))];
+  assert![set.contains(&(9,1998))];
+
+  let other: DeleteSet = set.iter().filter(|loc| loc.1 < 10).collect();
+  assert_eq![other.len(), 5];
+  set.clear();
+  assert![set.is_empty()];
+}
@@ -0,0 +1,97 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Point,PointOrd,Mix};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+#[derive(Point,Clone,Copy,Debug,PartialEq)]
+struct Event { x: (f32,f32), y: (f32,f32), t: f64 }
+
+#[derive(Point,Clone,Copy,Debug,PartialEq)]
+struct Feature { x: Mix<f32>, y: Mix<f32>, t: Mix<f64> }
+
+type V = u32;
+
+#[test]
+fn derive_tuple_fields() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,Event,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<Event,V>> = (0..3_500).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let t: f64 = r.read::<f64>()*1000.0;
+    Row::Insert(Event { x: (xmin,xmax), y: (ymin,ymax), t }, i)
+  }).collect();
+  db.batch(&inserts)?;
+
+  let bbox = ((-0.5,-0.8,0.0),(0.3,-0.5,500.0));
+  let mut expected: Vec<V> = inserts.iter().filter_map(|row| match row {
+    Row::Insert(p,v) if p.overlaps(&bbox) => Some(*v),
+    _ => None
+  }).collect();
+  let mut results = vec![];
+  for result in db.query(&bbox)? {
+    let (p,v,_) = result?;
+    assert![p.overlaps(&bbox), "result overlaps bbox"];
+    results.push(v);
+  }
+  expected.sort_unstable();
+  results.sort_unstable();
+  assert![!expected.is_empty(), "expected results"];
+  assert_eq![results, expected];
+  Ok(())
+}
+
+#[test]
+fn derive_mix_fields() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,Feature,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<Feature,V>> = (0..3_500).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let t: f64 = r.read::<f64>()*1000.0;
+    let feature = if r.read::<f32>() > 0.5 {
+      let w: f32 = r.read::<f32>().powf(32.0);
+      Feature {
+        x: Mix::Interval(x,x+w),
+        y: Mix::Interval(y,y+w),
+        t: Mix::Scalar(t)
+      }
+    } else {
+      Feature { x: Mix::Scalar(x), y: Mix::Scalar(y), t: Mix::Scalar(t) }
+    };
+    Row::Insert(feature, i)
+  }).collect();
+  db.batch(&inserts)?;
+
+  let bbox = ((-0.5,-0.8,0.0),(0.3,-0.5,500.0));
+  let mut expected: Vec<V> = inserts.iter().filter_map(|row| match row {
+    Row::Insert(p,v) if p.overlaps(&bbox) => Some(*v),
+    _ => None
+  }).collect();
+  let mut results = vec![];
+  for result in db.query(&bbox)? {
+    results.push(result?.1);
+  }
+  expected.sort_unstable();
+  results.sort_unstable();
+  assert![!expected.is_empty(), "expected results"];
+  assert_eq![results, expected];
+  Ok(())
+}
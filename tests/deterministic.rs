@@ -0,0 +1,91 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MetaResult,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn seeded_samples() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), i)
+  }).collect();
+  let open = |seed: u64| -> Result<DB<MemoryStore,_,P,V>,Error> {
+    Setup::new(memory_storage()).base_size(1_000).seed(seed).build()
+  };
+  let mut a = open(7)?;
+  let mut b = open(7)?;
+  let mut c = open(8)?;
+  for batch in inserts.chunks(1_500) {
+    a.batch(batch)?;
+    b.batch(batch)?;
+    c.batch(batch)?;
+  }
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let matches = a.query(&bbox)?.count();
+  for _ in 0..3 {
+    let sample = values(a.query_sample(&bbox, 50)?);
+    assert_eq![sample.len(), 50, "sample of the requested size"];
+    assert_eq![sample.iter().collect::<HashSet<_>>().len(), 50,
+      "no record sampled twice"];
+    assert_eq![sample, values(b.query_sample(&bbox, 50)?),
+      "same seed, same sample"];
+    assert_ne![sample, values(c.query_sample(&bbox, 50)?),
+      "different seed, different sample"];
+  }
+  assert_eq![a.query_sample(&bbox, matches+10)?.len(), matches,
+    "every match returned when the sample is larger than the results"];
+  Ok(())
+}
+
+#[test]
+fn injected_clock() -> Result<(),Error> {
+  let start = 1_600_000_000_000u64;
+  let now = Rc::new(Cell::new(start));
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage())
+    .base_size(500)
+    .timestamps(true)
+    .clock({
+      let now = Rc::clone(&now);
+      move || {
+        let t = now.get();
+        now.set(t+1_000);
+        t
+      }
+    })
+    .build()?;
+  for i in 0..4 {
+    let batch: Vec<Row<P,V>> = (0..300).map(|j| {
+      let x = (j as f32)/300.0;
+      Row::Insert(((x,x+0.01),(x,x+0.01)), i)
+    }).collect();
+    db.batch(&batch)?;
+  }
+  assert_eq![now.get(), start+4_000, "clock read once per batch"];
+  let results = db.query_meta(&((0.0,0.0),(1.0,1.0)))?
+    .collect::<Result<Vec<MetaResult<P,V>>,Error>>()?;
+  assert_eq![results.len(), 1_200, "every record returned"];
+  for (_,_,_,meta) in results.iter() {
+    let meta = meta.expect("metadata for every record");
+    assert_eq![meta.time, Some(start+(meta.seq-1)*1_000),
+      "timestamp from the injected clock"];
+  }
+  Ok(())
+}
+
+fn values (rows: Vec<(P,V,Location)>) -> Vec<V> {
+  rows.into_iter().map(|(_,v,_)| v).collect()
+}
@@ -0,0 +1,76 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,PointOrd,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32),f32);
+type V = u32;
+
+#[test]
+fn dimension_cycle_xyxyt() -> Result<(),Error> {
+  from_params(&[0,1,0,1,2], &[])
+}
+
+#[test]
+fn dimension_cycle_skip_t() -> Result<(),Error> {
+  from_params(&[0,1], &[])
+}
+
+#[test]
+fn dimension_cycle_branch_factors() -> Result<(),Error> {
+  from_params(&[1,0,2,0], &[9,5,17])
+}
+
+#[test]
+fn dimension_cycle_invalid() -> Result<(),Error> {
+  let db: Result<DB<MemoryStore,_,P,V>,Error> = Setup::new(memory_storage())
+    .dimension_cycle(&[0,1,3])
+    .build();
+  assert![db.is_err(), "dimensions in the cycle must exist"];
+  Ok(())
+}
+
+fn from_params (dims: &[usize], bfs: &[usize]) -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .dimension_cycle(dims)
+    .branch_factors(bfs)
+    .max_data_size(50)
+    .base_size(1_000)
+    .build()?;
+  let inserts: Vec<(P,V)> = (0..6_000).map(|i| {
+    let xmin = r.read::<f32>()*200.0-100.0;
+    let xmax = xmin + r.read::<f32>().powf(16.0)*(100.0-xmin);
+    let ymin = r.read::<f32>()*200.0-100.0;
+    let ymax = ymin + r.read::<f32>().powf(16.0)*(100.0-ymin);
+    let t = (r.read::<f32>()*4.0).floor();
+    (((xmin,xmax),(ymin,ymax),t), i)
+  }).collect();
+  for batch in inserts.chunks(1_500) {
+    let rows: Vec<Row<P,V>> = batch.iter()
+      .map(|(p,v)| Row::Insert(*p,*v))
+      .collect();
+    db.batch(&rows)?;
+  }
+  let bboxes = vec![
+    ((-30.0,-50.0,0.0),(20.0,10.0,3.0)),
+    ((-100.0,-100.0,1.0),(100.0,100.0,1.0)),
+    ((40.0,-5.0,0.0),(45.0,5.0,2.0))
+  ];
+  for bbox in bboxes.iter() {
+    let mut expected: Vec<V> = inserts.iter()
+      .filter(|(p,_)| p.overlaps(bbox))
+      .map(|(_,v)| *v)
+      .collect();
+    expected.sort_unstable();
+    let mut results = db.query(bbox)?
+      .map(|r| r.map(|(_,v,_)| v))
+      .collect::<Result<Vec<V>,Error>>()?;
+    results.sort_unstable();
+    assert_eq![results, expected, "query results for {:?}", bbox];
+  }
+  Ok(())
+}
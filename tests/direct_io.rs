@@ -0,0 +1,67 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,PointOrd,DiskStore,IoMode,disk_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn direct_io_store() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut store = DiskStore::open(dir.path().join("store"), IoMode::Direct)?;
+  store.write(0, b"hello")?;
+  store.write(4093, &[7;10])?;
+  store.write(3, b"p!")?;
+  assert_eq![store.len()?, 4103];
+  assert_eq![store.read(0, 5)?, b"help!".to_vec()];
+  assert_eq![store.read(4090, 13)?, vec![0,0,0,7,7,7,7,7,7,7,7,7,7]];
+  assert_eq![store.read(4100, 3)?, vec![7,7,7]];
+  assert![store.read(4100, 4).is_err(), "read past the end"];
+  drop(store);
+  let mut store = DiskStore::open(dir.path().join("store"), IoMode::Buffered)?;
+  assert_eq![store.len()?, 4103];
+  assert_eq![store.read(0, 5)?, b"help!".to_vec()];
+  Ok(())
+}
+
+#[test]
+fn direct_io_bulk_load() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-0.5,-0.8),(0.3,-0.1));
+  {
+    let storage = disk_storage(dir.path(), IoMode::Direct);
+    let mut db: DB<_,_,P,V> = Setup::new(storage).base_size(1_000).build()?;
+    db.batch(&inserts[..3_000])?;
+    db.batch(&inserts[3_000..])?;
+  }
+  let mut expected: Vec<(P,V)> = inserts.iter().filter_map(|row| match row {
+    Row::Insert(p,v) if p.overlaps(&bbox) => Some((*p,*v)),
+    _ => None
+  }).collect();
+  let storage = disk_storage(dir.path(), IoMode::Buffered);
+  let mut db: DB<_,_,P,V> = Setup::new(storage).base_size(1_000).build()?;
+  let mut results = db.query(&bbox)?.map(|r| r.map(|(p,v,_)| (p,v)))
+    .collect::<Result<Vec<(P,V)>,Error>>()?;
+  let cmp = |a: &(P,V), b: &(P,V)| a.partial_cmp(b).unwrap();
+  expected.sort_by(cmp);
+  results.sort_by(cmp);
+  assert![!expected.is_empty(), "expected results"];
+  assert_eq![results, expected];
+  Ok(())
+}
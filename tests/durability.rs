@@ -0,0 +1,111 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Durability};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+use std::cell::Cell;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+// disk store that counts calls to sync_all()
+struct CountSync {
+  store: RandomAccessDisk,
+  syncs: Rc<Cell<usize>>
+}
+
+impl RandomAccess for CountSync {
+  type Error = Error;
+  fn write (&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    self.store.write(offset, data)
+  }
+  fn read (&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    self.store.read(offset, length)
+  }
+  fn read_to_writer (&mut self, offset: u64, length: u64,
+  buf: &mut impl Write) -> Result<(),Error> {
+    self.store.read_to_writer(offset, length, buf)
+  }
+  fn del (&mut self, offset: u64, length: u64) -> Result<(),Error> {
+    self.store.del(offset, length)
+  }
+  fn truncate (&mut self, length: u64) -> Result<(),Error> {
+    self.store.truncate(length)
+  }
+  fn len (&self) -> Result<u64,Error> {
+    self.store.len()
+  }
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    self.store.is_empty()
+  }
+  fn sync_all (&mut self) -> Result<(),Error> {
+    self.syncs.set(self.syncs.get()+1);
+    self.store.sync_all()
+  }
+}
+
+fn open (dir: &Path, durability: Durability, syncs: &Rc<Cell<usize>>)
+-> Result<DB<CountSync,impl Fn(&str) -> Result<CountSync,Error>,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let syncs = Rc::clone(syncs);
+  Setup::new(move |name: &str| {
+    let p = dir.join(name);
+    Ok(CountSync {
+      store: RandomAccessDisk::builder(p).auto_sync(false).build()?,
+      syncs: Rc::clone(&syncs)
+    })
+  }).base_size(100).durability(durability).build()
+}
+
+fn batches () -> Vec<Vec<Row<P,V>>> {
+  let mut r = rand().seed([13,12]);
+  (0..10).map(|_| {
+    (0..250).map(|_| {
+      let x: f32 = r.read::<f32>()*2.0-1.0;
+      let y: f32 = r.read::<f32>()*2.0-1.0;
+      Row::Insert(((x,x+0.01),(y,y+0.01)), r.read::<u32>())
+    }).collect()
+  }).collect()
+}
+
+#[test]
+fn durability() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let batches = batches();
+  let mut counts = vec![];
+  for (i,policy) in [
+    Durability::Always,
+    Durability::EveryNBatches(5),
+    Durability::Never
+  ].iter().enumerate() {
+    let syncs = Rc::new(Cell::new(0));
+    let mut db = open(&dir.path().join(i.to_string()), *policy, &syncs)?;
+    for batch in batches.iter() {
+      db.batch(batch)?;
+    }
+    counts.push(syncs.get());
+    let before = syncs.get();
+    db.sync()?;
+    assert![syncs.get() > before, "db.sync() syncs regardless of policy"];
+  }
+  assert_eq![counts[2], 0, "no syncs with Durability::Never"];
+  assert![counts[1] > 0, "syncs every 5 batches"];
+  assert![counts[1] < counts[0], "fewer syncs than Durability::Always"];
+
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let syncs = Rc::new(Cell::new(0));
+  let mut db = open(&dir.path().join("2"), Durability::Always, &syncs)?;
+  assert_eq![db.query(&bbox)?.count(), 2_500, "synced batches reopen"];
+  Ok(())
+}
@@ -0,0 +1,69 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,PointOrd,IoMode,disk_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn executor() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<(P,V)> = (0..6_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    (((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  let storage = disk_storage(dir.path().to_path_buf(), IoMode::Buffered);
+  let mut db: DB<_,_,P,V> = Setup::new(storage).base_size(1_000).build()?;
+  db.batch(&rows[..4_500])?;
+
+  let executor = db.executor(3)?;
+  assert_eq![executor.threads(), 3];
+  let bboxes: Vec<((f32,f32),(f32,f32))> = (0..10).map(|i| {
+    let x = (i as f32)*0.2-1.0;
+    ((x,-0.5),(x+0.3,0.5))
+  }).collect();
+  let receivers: Vec<_> = bboxes.iter()
+    .map(|bbox| executor.query(*bbox))
+    .collect();
+  for (bbox,receiver) in bboxes.iter().zip(receivers) {
+    assert_eq![values(receiver.iter())?, expected(&inserts[..4_500], bbox),
+      "results for {:?}", bbox];
+  }
+
+  // views only see later batches after a refresh
+  db.batch(&rows[4_500..])?;
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  executor.refresh();
+  assert_eq![values(executor.query(bbox).iter())?, expected(&inserts, &bbox),
+    "results after a refresh"];
+  Ok(())
+}
+
+fn expected (inserts: &[(P,V)], bbox: &((f32,f32),(f32,f32))) -> Vec<V> {
+  let mut values: Vec<V> = inserts.iter()
+    .filter(|(p,_)| p.overlaps(bbox))
+    .map(|(_,v)| *v)
+    .collect();
+  values.sort_unstable();
+  values
+}
+
+fn values<I> (results: I) -> Result<Vec<V>,Error>
+where I: Iterator<Item=Result<(P,V,Location),Error>> {
+  let mut values = results
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  values.sort_unstable();
+  Ok(values)
+}
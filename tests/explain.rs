@@ -0,0 +1,55 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn explain() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let mut p = dir.path().to_path_buf();
+    p.push(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  db.batch(&inserts)?;
+
+  let bbox = ((-0.5,-0.8),(0.3,-0.5));
+  let mut count = 0;
+  for result in db.query(&bbox)? {
+    result?;
+    count += 1;
+  }
+  let explain = db.query_explain(&bbox)?;
+  assert![count > 0, "query has results"];
+  assert_eq![explain.results(), count, "explain counts every result"];
+  assert_eq![explain.staging_rows, 500, "staging rows scanned"];
+  assert![explain.trees.len() > 0, "trees are traced"];
+  assert![explain.bytes() > 0, "bytes are counted"];
+  let pruned: usize = explain.trees.iter()
+    .flat_map(|t| t.levels.iter())
+    .map(|l| l.branches_pruned + l.blocks_pruned)
+    .sum();
+  assert![pruned > 0, "a small bbox prunes part of the tree"];
+  for tree in explain.trees.iter() {
+    assert_eq![tree.levels[0].branches, 1, "root branch is visited once"];
+  }
+  Ok(())
+}
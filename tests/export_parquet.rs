@@ -0,0 +1,69 @@
+extern crate arrow;
+extern crate eyros;
+extern crate failure;
+extern crate parquet;
+extern crate random;
+extern crate tempfile;
+
+use arrow::array::{Array,Float32Array,UInt32Array};
+use eyros::{DB,Setup,Row,memory_storage};
+use failure::Error;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+use std::fs::File;
+
+type P = (f32,f32);
+type V = u32;
+
+#[test]
+fn export_parquet() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..30_000).map(|_| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert((x,y), r.read::<u32>())
+  }).collect();
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage())
+    .base_size(1_000)
+    .build()?;
+  for batch in inserts.chunks(5_000) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-0.5,-0.8),(0.9,0.6));
+  let mut expected = vec![];
+  for result in db.query(&bbox)? {
+    let (p,v,_) = result?;
+    expected.push((p.0,p.1,v));
+  }
+
+  let path = dir.path().join("region.parquet");
+  let map = [("dim0","lon"),("dim1","lat")];
+  assert_eq![db.export_parquet(&bbox, &path, &map)?, expected.len() as u64,
+    "every result written"];
+  assert![db.export_parquet(&bbox, &path, &[("dim9","z")]).is_err(),
+    "unknown column in the schema map"];
+  db.export_parquet(&bbox, &path, &map)?;
+
+  let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path)?)?
+    .build()?;
+  let mut rows = vec![];
+  for batch in reader {
+    let batch = batch?;
+    let names: Vec<String> = batch.schema().fields().iter()
+      .map(|f| f.name().clone()).collect();
+    assert_eq![names, vec!["lon","lat","value"], "columns renamed"];
+    let f = |i: usize| batch.column(i).as_any()
+      .downcast_ref::<Float32Array>().unwrap().clone();
+    let value = batch.column(2).as_any()
+      .downcast_ref::<UInt32Array>().unwrap().clone();
+    let (x,y) = (f(0),f(1));
+    for i in 0..batch.num_rows() {
+      rows.push((x.value(i), y.value(i), value.value(i)));
+    }
+  }
+  assert_eq![rows, expected, "parquet file holds the query results"];
+  Ok(())
+}
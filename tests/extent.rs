@@ -0,0 +1,60 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32),f32);
+type V = u32;
+
+#[test]
+fn extent() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).max_data_size(100).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert(((xmin,xmax),(ymin,ymax),time), r.read::<u32>())
+  }).collect();
+  db.batch(&inserts)?;
+  let all = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  // populate the block bounds cache for the fully contained case
+  db.extent(&all)?;
+
+  for bbox in [all, ((-0.5,-0.8,0.0),(0.3,-0.5,500.0))].iter() {
+    let mut points = vec![];
+    for result in db.query(bbox)? {
+      points.push(result?.0);
+    }
+    let expected = (
+      (
+        points.iter().map(|p| (p.0).0).fold(f32::INFINITY, f32::min),
+        points.iter().map(|p| (p.1).0).fold(f32::INFINITY, f32::min),
+        points.iter().map(|p| p.2).fold(f32::INFINITY, f32::min)
+      ),
+      (
+        points.iter().map(|p| (p.0).1).fold(f32::NEG_INFINITY, f32::max),
+        points.iter().map(|p| (p.1).1).fold(f32::NEG_INFINITY, f32::max),
+        points.iter().map(|p| p.2).fold(f32::NEG_INFINITY, f32::max)
+      )
+    );
+    assert![points.len() > 0, "query has results"];
+    assert_eq![db.extent(bbox)?, Some(expected), "extent of {:?}", bbox];
+  }
+  let empty = ((5.0,5.0,0.0),(6.0,6.0,1.0));
+  assert_eq![db.extent(&empty)?, None, "empty region has no extent"];
+  Ok(())
+}
@@ -0,0 +1,61 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,PointOrd,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),f32);
+type V = u32;
+
+#[test]
+fn reject_non_finite() -> Result<(),Error> {
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let rows = vec![
+    Row::Insert(((0.0,1.0),2.0), 0),
+    Row::Insert(((0.0,f32::NAN),2.0), 1),
+    Row::Insert(((0.0,1.0),f32::INFINITY), 2)
+  ];
+  let err = db.batch(&rows).unwrap_err().to_string();
+  assert![err.starts_with("row 1:"), "error names the row: {}", err];
+  let bbox = ((-10.0,-10.0),(10.0,10.0));
+  assert_eq![db.query(&bbox)?.count(), 0, "no rows of the batch written"];
+  assert![db.batch(&rows[2..]).is_err(), "infinite coordinate"];
+  db.batch(&rows[0..1])?;
+  assert_eq![db.query(&bbox)?.count(), 1];
+  Ok(())
+}
+
+#[test]
+fn stored_nan() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).finite_coords(false).build()?;
+  let inserts: Vec<(P,V)> = (0..3_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = if i % 7 == 0 { f32::NAN } else { r.read::<f32>()*200.0-100.0 };
+    (((x,x+2.0),y), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  for batch in rows.chunks(1_000) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-50.0,-20.0),(30.0,60.0));
+  let mut expected: Vec<V> = inserts.iter()
+    .filter(|(p,_)| p.overlaps(&bbox))
+    .map(|(_,v)| *v)
+    .collect();
+  expected.sort_unstable();
+  for _ in 0..2 {
+    let mut results = db.query(&bbox)?
+      .map(|r| r.map(|(_,v,_)| v))
+      .collect::<Result<Vec<V>,Error>>()?;
+    results.sort_unstable();
+    assert_eq![results, expected, "rows without NaN are all found"];
+  }
+  Ok(())
+}
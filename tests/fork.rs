@@ -0,0 +1,118 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,ForkStore,MemoryStore,fork_storage};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn fork() -> Result<(),Error> {
+  let base_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let fork_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let bp = base_dir.path().to_path_buf();
+  let fp = fork_dir.path().to_path_buf();
+  let base_fn = move |name: &str| storage(bp.clone(), name);
+  let fork_fn = move |name: &str| storage(fp.clone(), name);
+  let mut base: DB<_,_,P,V> = Setup::new(base_fn.clone())
+    .base_size(500)
+    .build()?;
+  let mut r = rand().seed([13,12]);
+  let mut batches: Vec<Vec<Row<P,V>>> = (0..3).map(|_| {
+    (0..1_200).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect()
+  }).collect();
+  let extra = batches.pop().unwrap();
+  for batch in batches.iter() {
+    base.batch(batch)?;
+  }
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let before = query(&mut base, &bbox)?;
+  assert_eq![before.len(), 2_400];
+
+  {
+    let mut forked = base.fork(fork_fn.clone())?;
+    assert_eq![query(&mut forked, &bbox)?, before, "fork starts as a copy"];
+    forked.batch(&extra)?;
+    assert_eq![query(&mut forked, &bbox)?.len(), 3_600, "fork sees new rows"];
+  }
+  assert_eq![query(&mut base, &bbox)?, before, "base is unchanged"];
+
+  let mut reopened: DB<_,_,P,V> = Setup::new(fork_storage(base_fn, fork_fn))
+    .base_size(500)
+    .build()?;
+  assert_eq![query(&mut reopened, &bbox)?.len(), 3_600, "reopened fork"];
+  Ok(())
+}
+
+#[test]
+fn fork_extents() -> Result<(),Error> {
+  let (base,overlay,extents) =
+    (MemoryStore::new(),MemoryStore::new(),MemoryStore::new());
+  let mut b = base.clone();
+  b.write(0, &[1u8;1_000])?;
+  let mut fork = ForkStore::open(base.clone(),overlay.clone(),extents.clone())?;
+  for i in 0..500u64 {
+    fork.write(900+i*4, &[2u8;4])?;
+  }
+  let log_len = extents.len()?;
+  assert![log_len < 500*20, "one record appended per write: {}", log_len];
+  fork.truncate(1_500)?;
+  fork.write(1_600, &[3u8;10])?;
+
+  let mut reopened =
+    ForkStore::open(base.clone(),overlay.clone(),extents.clone())?;
+  assert_eq![reopened.len()?, 1_610, "length from the extents log"];
+  let bytes = reopened.read(0, 1_610)?;
+  assert_eq![&bytes[..900], &[1u8;900][..]];
+  assert_eq![&bytes[900..1_500], &[2u8;600][..]];
+  assert_eq![&bytes[1_500..1_600], &[0u8;100][..], "truncated range"];
+  assert_eq![&bytes[1_600..], &[3u8;10][..]];
+
+  fork.sync_all()?;
+  assert![extents.len()? < 100, "extents compacted on sync_all()"];
+  let mut compacted =
+    ForkStore::open(base.clone(),overlay.clone(),extents.clone())?;
+  assert_eq![compacted.len()?, 1_610];
+  assert_eq![compacted.read(0, 1_610)?, bytes, "same bytes after compacting"];
+  Ok(())
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where S: random_access_storage::RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>) {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
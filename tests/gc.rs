@@ -0,0 +1,104 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_storage;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_storage::RandomAccess;
+
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn gc() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..1_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let storage = memory_storage();
+  let (expected,garbage) = {
+    let mut db = open(storage.clone())?;
+    for batch in inserts.chunks(100) {
+      db.batch(batch)?;
+    }
+    let report = db.gc(true)?;
+    assert_eq![report.trees, vec![], "no orphaned trees"];
+    // merges combine small data blocks into new blocks
+    assert![report.data_blocks > 0, "blocks replaced by merges"];
+    (query(&mut db, &bbox)?, report.data_bytes)
+  };
+  assert_eq![expected.len(), 1_000, "every record inserted"];
+
+  let mut db = open(storage.clone())?;
+  let report = db.gc(false)?;
+  assert_eq![report.trees, vec![], "no orphaned trees after reopening"];
+  assert_eq![report.reclaimed_bytes, 0, "nothing past the last block"];
+  assert_eq![query(&mut db, &bbox)?, expected, "records kept after reopening"];
+  drop(db);
+
+  // 10 full chunks of 100 fill trees 1 and 3 of trees 0 to 3
+  let mut tree = storage("tree4")?;
+  tree.write(0, &[7;50])?;
+  let mut data = storage("data")?;
+  let data_len = data.len()?;
+  data.write(data_len, &[9;100])?;
+
+  let mut db = open(storage.clone())?;
+  let report = db.gc(true)?;
+  assert_eq![report.trees, vec![(4,50)], "orphaned tree found"];
+  assert_eq![report.data_bytes, garbage+100, "partial data block found"];
+  assert_eq![report.reclaimed_bytes, 0, "dry run reclaims nothing"];
+  assert_eq![storage("tree4")?.len()?, 50, "dry run keeps the tree"];
+  assert_eq![storage("data")?.len()?, data_len+100, "dry run keeps the data"];
+
+  let hash = db.root_hash()?;
+  let report = db.gc(false)?;
+  assert_eq![report.bytes(), garbage+150, "same report without a dry run"];
+  assert![db.root_hash()? != hash, "root hash of the truncated stores"];
+  assert![db.root_hash()? == open(storage.clone())?.root_hash()?,
+    "same root hash after reopening"];
+  assert_eq![report.reclaimed_bytes, 150, "orphaned bytes reclaimed"];
+  assert_eq![storage("tree4")?.len()?, 0, "orphaned tree truncated"];
+  assert_eq![storage("data")?.len()?, data_len, "data tail truncated"];
+  let report = db.gc(true)?;
+  assert_eq![report.bytes(), garbage, "only blocks before the tail left"];
+  assert_eq![query(&mut db, &bbox)?, expected, "records unchanged"];
+
+  db.batch(&inserts[0..250])?;
+  assert_eq![query(&mut db, &bbox)?.len(), 1_250,
+    "records written after a collection"];
+  Ok(())
+}
+
+fn open<U> (storage: U) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage).base_size(100).build()
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
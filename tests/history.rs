@@ -0,0 +1,82 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn history() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let p = dir.path().to_path_buf();
+  let setup = || Setup::new({
+    let p = p.clone();
+    move |name: &str| storage(p.clone(), name)
+  })
+    .base_size(500)
+    .history(true);
+  let mut db: DB<_,_,P,V> = setup().build()?;
+  assert_eq![db.history_seq()?, Some(0)];
+
+  let mut r = rand().seed([13,12]);
+  let mut bounds = vec![];
+  for i in 0..4 {
+    let batch: Vec<Row<P,V>> = (0..300*(i+1)).map(|_| {
+      let xmin: f32 = r.read::<f32>()*(i as f32)-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*0.1;
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*0.1;
+      Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect();
+    let mut bbox = ((f32::INFINITY,f32::INFINITY),(f32::NEG_INFINITY,f32::NEG_INFINITY));
+    for row in batch.iter() {
+      if let Row::Insert(((xmin,xmax),(ymin,ymax)),_) = row {
+        (bbox.0).0 = (bbox.0).0.min(*xmin);
+        (bbox.0).1 = (bbox.0).1.min(*ymin);
+        (bbox.1).0 = (bbox.1).0.max(*xmax);
+        (bbox.1).1 = (bbox.1).1.max(*ymax);
+      }
+    }
+    bounds.push(bbox);
+    db.batch(&batch)?;
+  }
+  let mut locations = vec![];
+  for result in db.query(&((-1.0,-1.0),(0.0,0.0)))? {
+    locations.push(Row::Delete(result?.2));
+    if locations.len() == 5 { break }
+  }
+  db.batch(&locations)?;
+  assert_eq![db.history_seq()?, Some(5)];
+
+  let changes = db.changes_since(0)?;
+  assert_eq![changes.len(), 5];
+  for (i,change) in changes.iter().take(4).enumerate() {
+    assert_eq![change.seq, i as u64];
+    assert_eq![change.inserts, 300*(i as u64+1)];
+    assert_eq![change.deletes, 0];
+    assert_eq![change.bbox, Some(bounds[i])];
+  }
+  assert_eq![changes[4].inserts, 0];
+  assert_eq![changes[4].deletes, 5];
+  assert_eq![changes[4].bbox, None];
+
+  drop(db);
+  let mut db: DB<_,_,P,V> = setup().build()?;
+  let changes = db.changes_since(3)?;
+  assert_eq![changes.iter().map(|c| c.seq).collect::<Vec<_>>(), vec![3,4]];
+  assert_eq![db.changes_since(5)?.len(), 0];
+  Ok(())
+}
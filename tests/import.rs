@@ -0,0 +1,85 @@
+extern crate eyros;
+extern crate failure;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage,import};
+use failure::{Error,format_err};
+
+use std::cmp::Ordering;
+
+type P = (f32,f32);
+type V = u32;
+
+#[test]
+fn import_csv() -> Result<(),Error> {
+  let mut csv = String::from("id,name,lon,lat\n");
+  let mut expected = vec![];
+  for i in 0..2_500u32 {
+    let lon = (i as f32)/2_500.0*2.0-1.0;
+    let lat = ((i*7919 % 2_500) as f32)/2_500.0*2.0-1.0;
+    let name = format!["\"st {}, \"\"{}\"\"\"", i, i];
+    csv.push_str(&format!["{},{},{},{}\n", i, name, lon, lat]);
+    if i % 10 != 0 { expected.push(((lon,lat),i)) }
+    if i % 500 == 0 { csv.push_str("\n") }
+  }
+  let mut db = open()?;
+  let mut batches = 0;
+  let rows = import::csv(csv.as_bytes(), map).batch_size(1_000);
+  for batch in rows {
+    let batch = batch?;
+    assert![batch.len() <= 1_000, "batches hold at most batch_size rows"];
+    db.batch(&batch)?;
+    batches += 1;
+  }
+  assert_eq![batches, 3, "rows grouped into batches"];
+  assert_eq![query(&mut db)?, sorted(expected.clone()),
+    "imported rows match the input"];
+
+  let mut db = open()?;
+  let count = import::csv(csv.as_bytes(), map).load(&mut db)?;
+  assert_eq![count, expected.len() as u64, "load counts the rows written"];
+  assert_eq![query(&mut db)?, sorted(expected), "same rows with load"];
+
+  let bad = "id,name,lon,lat\n\
+    1,\"st 1, \"\"1\"\"\",0.5,0.5\n\
+    2,\"st 2, \"\"2\"\"\",x,0.5\n";
+  match import::csv(bad.as_bytes(), map).load(&mut open()?) {
+    Ok(_) => panic!["imported an invalid line"],
+    Err(err) => assert![err.to_string().contains("line 3"),
+      "error names the line: {}", err]
+  }
+  Ok(())
+}
+
+fn map (record: &import::CsvRecord) -> Result<Option<Row<P,V>>,Error> {
+  let id: u32 = record.field(0).ok_or_else(|| format_err!["no id"])?.parse()?;
+  let name = record.get("name").ok_or_else(|| format_err!["no name"])?;
+  assert_eq![name, format!["st {}, \"{}\"", id, id], "quoted field"];
+  if id % 10 == 0 { return Ok(None) }
+  let lon = record.get("lon").ok_or_else(|| format_err!["no lon"])?;
+  let lat = record.get("lat").ok_or_else(|| format_err!["no lat"])?;
+  Ok(Some(Row::Insert((lon.parse()?,lat.parse()?), id)))
+}
+
+fn open () -> Result<DB<MemoryStore,
+impl Fn(&str) -> Result<MemoryStore,Error>,P,V>,Error> {
+  Setup::new(memory_storage()).base_size(500).build()
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>) -> Result<Vec<(P,V)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = vec![];
+  for result in db.query(&((-1.0,-1.0),(1.0,1.0)))? {
+    let (p,v,_): (P,V,Location) = result?;
+    results.push((p,v));
+  }
+  Ok(sorted(results))
+}
+
+fn sorted (mut rows: Vec<(P,V)>) -> Vec<(P,V)> {
+  rows.sort_unstable_by(|a,b| match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  });
+  rows
+}
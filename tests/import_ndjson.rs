@@ -0,0 +1,45 @@
+extern crate eyros;
+extern crate failure;
+extern crate serde_json;
+
+use eyros::{DB,Setup,Row,memory_storage,import};
+use failure::{Error,format_err};
+
+type P = ((f32,f32),f32);
+type V = u32;
+
+#[test]
+fn import_ndjson() -> Result<(),Error> {
+  let mut input = String::new();
+  for i in 0..1_200u32 {
+    let x = (i as f32)/1_200.0;
+    input.push_str(&format![
+      "{{\"id\":{},\"xmin\":{},\"xmax\":{},\"t\":{}}}\n", i, x-0.01, x, i
+    ]);
+  }
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage())
+    .base_size(200)
+    .build()?;
+  let count = import::ndjson(input.as_bytes(), |json| {
+    let f = |key: &str| json[key].as_f64()
+      .ok_or_else(|| format_err!["missing {}", key]);
+    let id = f("id")? as u32;
+    if id % 4 == 0 { return Ok(None) }
+    let point = ((f("xmin")? as f32, f("xmax")? as f32), f("t")? as f32);
+    Ok(Some(Row::Insert(point, id)))
+  }).batch_size(100).load(&mut db)?;
+  assert_eq![count, 900, "skipped rows are not written"];
+
+  let mut ids = vec![];
+  for result in db.query(&((0.0,0.0),(1.0,2_000.0)))? {
+    ids.push(result?.1);
+  }
+  ids.sort_unstable();
+  let expected: Vec<u32> = (0..1_200).filter(|i| i % 4 != 0).collect();
+  assert_eq![ids, expected, "every mapped row imported"];
+
+  let bad = "{\"id\":1}\n{\"id\":\n";
+  assert![import::ndjson(bad.as_bytes(), |_| Ok(None::<Row<P,V>>))
+    .load(&mut db).is_err(), "invalid json fails"];
+  Ok(())
+}
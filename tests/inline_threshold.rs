@@ -0,0 +1,82 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_storage;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use eyros::reader::Reader;
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_storage::RandomAccess;
+
+type P = ((f32,f32),(f32,f32));
+type V = Vec<u8>;
+
+#[test]
+fn inline_threshold() -> Result<(),Error> {
+  let inserts = rows();
+  let bbox = ((-0.5,-0.3),(0.1,0.4));
+  let mut sizes = vec![];
+  let mut results = vec![];
+  for threshold in [None,Some(64)] {
+    let storage = memory_storage();
+    let mut db = open(storage.clone(), threshold)?;
+    for batch in inserts.chunks(1_100) {
+      db.batch(batch)?;
+    }
+    let deletes: Vec<Row<P,V>> = db.query(&((-0.2,-0.2),(0.2,0.2)))?
+      .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+      .collect::<Result<Vec<_>,Error>>()?;
+    assert![!deletes.is_empty(), "expected deletes"];
+    db.batch(&deletes)?;
+    let expected = query(&mut db, &bbox)?;
+    sizes.push(storage("data")?.len()?);
+
+    let mut db = open(storage.clone(), threshold)?;
+    assert_eq![query(&mut db, &bbox)?, expected,
+      "same results after reopening with threshold={:?}", threshold];
+    if threshold.is_some() {
+      assert![storage("blobs")?.len()? > 0, "large values written as blobs"];
+      let mut reader: Reader<_,P,V> = Reader::open(storage.clone())?;
+      assert![reader.scan()?.any(|r| r.is_err()),
+        "blobs can not be read without the blob store"];
+      let mut reader: Reader<_,P,V> = Reader::open(storage.clone())?
+        .blobs(storage("blobs")?);
+      let rows = reader.scan()?.collect::<Result<Vec<_>,Error>>()?;
+      assert![rows.iter().any(|(_,v,_)| v.len() > 64), "blob values read"];
+    }
+    results.push(expected.into_iter().map(|(p,v,_)| (p,v)).collect::<Vec<_>>());
+  }
+  assert_eq![results[0], results[1], "same results with an inline threshold"];
+  assert![sizes[1]*2 < sizes[0],
+    "large values kept out of the data store: {:?}", sizes];
+  Ok(())
+}
+
+fn rows () -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..5_500u32).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let size = if i % 10 == 0 { 500 } else { 1 };
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i.to_be_bytes().repeat(size))
+  }).collect()
+}
+
+fn open<U> (storage: U, threshold: Option<usize>)
+-> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let setup = Setup::new(storage).base_size(1_000);
+  match threshold {
+    Some(bytes) => setup.inline_threshold(bytes).build(),
+    None => setup.build()
+  }
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = db.query(bbox)?.collect::<Result<Vec<_>,Error>>()?;
+  results.sort_unstable_by(|a,b| a.1.cmp(&b.1));
+  Ok(results)
+}
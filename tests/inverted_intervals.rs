@@ -0,0 +1,78 @@
+extern crate eyros;
+extern crate failure;
+
+use eyros::{DB,Setup,Row,Mix,Mix2,InvertedIntervals,MemoryStore,
+  memory_storage};
+use failure::Error;
+
+type P = ((f32,f32),f32);
+type V = u32;
+type M = Mix2<f32,f32>;
+
+fn rows () -> Vec<Row<P,V>> {
+  vec![
+    Row::Insert(((0.0,1.0),2.0), 0),
+    Row::Insert(((3.0,3.0),2.0), 1),
+    Row::Insert(((5.0,4.0),2.0), 2),
+    Row::Insert(((7.0,6.0),2.0), 3)
+  ]
+}
+
+fn values<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V)>,Error> where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = db.query(bbox)?
+    .map(|r| r.map(|(p,v,_)| (p,v)))
+    .collect::<Result<Vec<(P,V)>,Error>>()?;
+  results.sort_unstable_by_key(|(_,v)| *v);
+  Ok(results)
+}
+
+#[test]
+fn inverted_intervals() -> Result<(),Error> {
+  let bbox = ((4.5,0.0),(4.6,10.0));
+
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .build()?;
+  let err = db.batch(&rows()).unwrap_err().to_string();
+  assert![err.starts_with("row 2:"), "error names the row: {}", err];
+  assert_eq![values(&mut db, &((-10.0,-10.0),(10.0,10.0)))?, vec![],
+    "no rows of the batch written"];
+
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .inverted_intervals(InvertedIntervals::Swap)
+    .build()?;
+  db.batch(&rows())?;
+  assert_eq![values(&mut db, &bbox)?, vec![(((4.0,5.0),2.0),2)],
+    "inverted intervals are swapped"];
+
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .inverted_intervals(InvertedIntervals::Allow)
+    .build()?;
+  db.batch(&rows())?;
+  assert_eq![values(&mut db, &bbox)?, vec![],
+    "inverted intervals are stored as they are"];
+  Ok(())
+}
+
+#[test]
+fn inverted_mix_intervals() -> Result<(),Error> {
+  let mut db: DB<MemoryStore,_,M,V> = Setup::new(memory_storage())
+    .inverted_intervals(InvertedIntervals::Swap)
+    .build()?;
+  db.batch(&[
+    Row::Insert(Mix2::new(Mix::Scalar(1.0),Mix::Interval(3.0,-3.0)), 0)
+  ])?;
+  let results = db.query(&((0.0,0.0),(2.0,1.0)))?
+    .map(|r| r.map(|(p,_,_)| p))
+    .collect::<Result<Vec<_>,Error>>()?;
+  assert_eq![results, vec![
+    Mix2::new(Mix::Scalar(1.0),Mix::Interval(-3.0,3.0))
+  ]];
+
+  let mut db: DB<MemoryStore,_,M,V> = Setup::new(memory_storage())
+    .build()?;
+  assert![db.batch(&[
+    Row::Insert(Mix2::new(Mix::Interval(2.0,1.0),Mix::Scalar(0.0)), 0)
+  ]).is_err(), "inverted Mix interval"];
+  Ok(())
+}
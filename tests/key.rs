@@ -0,0 +1,61 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Key,PointOrd};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = (Key<4>,f32,(f32,f32));
+type V = u32;
+
+#[test]
+fn key_dimension() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).max_data_size(100).build()?;
+  let mut r = rand().seed([13,12]);
+  let categories: Vec<&[u8]> = vec![b"cafe", b"park", b"pub", b"\xff\xffzz"];
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|i| {
+    let mut name = categories[(r.read::<u32>() as usize) % 4].to_vec();
+    name.push(r.read::<u8>());
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert((Key::from_slice(&name),x,(ymin,ymax)), i)
+  }).collect();
+  db.batch(&inserts)?;
+
+  let (kmin,kmax) = Key::prefix_range(b"pu");
+  assert_eq![kmin, Key::new(*b"pu\0\0")];
+  assert_eq![kmax, Key::new(*b"pu\xff\xff")];
+  for (kmin,kmax) in [(kmin,kmax), Key::prefix_range(b"\xff")].iter() {
+    let bbox = ((*kmin,-0.5,-0.5),(*kmax,0.5,0.5));
+    let mut expected: Vec<V> = inserts.iter().filter_map(|row| match row {
+      Row::Insert(p,v) if p.overlaps(&bbox) => Some(*v),
+      _ => None
+    }).collect();
+    let mut results = vec![];
+    for result in db.query(&bbox)? {
+      let (p,v,_) = result?;
+      assert![p.0 >= *kmin && p.0 <= *kmax, "key in range"];
+      results.push(v);
+    }
+    expected.sort_unstable();
+    results.sort_unstable();
+    assert![!expected.is_empty(), "expected results"];
+    assert_eq![results, expected];
+  }
+
+  let a: P = (Key::new([0xff,0xff,0xff,0xfe]),1.0,(0.0,1.0));
+  let b: P = (Key::new([0xff,0xff,0xff,0xff]),1.0,(0.0,1.0));
+  assert_eq![a.midpoint_upper(&b).0, a.0, "midpoint does not overflow"];
+  assert_eq![b.midpoint_upper(&b).0, b.0];
+  Ok(())
+}
@@ -0,0 +1,63 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Tree,QueryOptions,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use std::rc::Rc;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn memory_budget() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .branch_prefetch(true)
+    .max_data_size(50)
+    .base_size(1_000)
+    .build()?;
+  let rows: Vec<Row<P,V>> = (0..20_000).map(|i| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), i)
+  }).collect();
+  db.batch(&rows)?;
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+
+  let mut expected = values(&mut db, &bbox, &QueryOptions::new())?;
+  expected.sort_unstable();
+  assert_eq![expected.len(), rows.len()];
+  for interleave in [false,true] {
+    let opts = QueryOptions::new().interleave(interleave).memory_budget(2_000);
+    let mut results = values(&mut db, &bbox, &opts)?;
+    results.sort_unstable();
+    assert_eq![results, expected, "same results within a budget"];
+  }
+
+  let tree = db.trees.iter().max_by_key(|t| t.borrow().bytes).unwrap();
+  let mut peaks = vec![];
+  for budget in [None,Some(2_000)] {
+    let mut iter = Tree::query(Rc::clone(tree), &bbox)?.interleave();
+    if let Some(bytes) = budget { iter = iter.memory_budget(bytes) }
+    let mut peak = 0;
+    while let Some(result) = iter.next() {
+      result?;
+      peak = peak.max(iter.queued_bytes());
+    }
+    peaks.push(peak);
+  }
+  assert![peaks[1] < peaks[0], "budget lowers the queued bytes: {:?}", peaks];
+  Ok(())
+}
+
+fn values<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)),
+opts: &QueryOptions) -> Result<Vec<V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  db.query_with(bbox, opts)?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect()
+}
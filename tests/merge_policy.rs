@@ -0,0 +1,103 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage,
+  MergePolicy,Merge,SizeTiered,Leveled,Lazy};
+use failure::Error;
+use random::{Source,default as rand};
+
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn merge_policy() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..12_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let mut tiered: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(500).merge_policy(SizeTiered).build()?;
+  let mut leveled: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(500).merge_policy(Leveled).build()?;
+  let mut lazy: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(500).merge_policy(Lazy { max_trees: 3 }).build()?;
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.5,-0.8),(0.3,-0.5)),
+    ((0.1,0.1),(0.12,0.15))
+  ];
+  for batch in inserts.chunks(700) {
+    tiered.batch(batch)?;
+    leveled.batch(batch)?;
+    lazy.batch(batch)?;
+    assert![trees(&leveled)? <= 1, "leveled keeps a single tree"];
+    assert![trees(&lazy)? <= 3, "lazy keeps at most max_trees trees"];
+  }
+  assert![trees(&tiered)? > 1, "size-tiered keeps several trees"];
+  for bbox in bboxes.iter() {
+    let expected = query(&mut tiered, bbox)?;
+    assert_eq![query(&mut leveled, bbox)?, expected,
+      "leveled results for {:?}", bbox];
+    assert_eq![query(&mut lazy, bbox)?, expected,
+      "lazy results for {:?}", bbox];
+  }
+  Ok(())
+}
+
+// always writes into tree 0, without merging what is already there
+struct Overwrite;
+
+impl MergePolicy for Overwrite {
+  fn plan (&self, staging: &[bool], _trees: &[bool]) -> Vec<Merge> {
+    let staging = (0..staging.len()).filter(|j| staging[*j]).collect();
+    vec![Merge { tree: 0, staging, trees: vec![] }]
+  }
+}
+
+#[test]
+fn invalid_merge_policy() -> Result<(),Error> {
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(10).merge_policy(Overwrite).build()?;
+  let batch: Vec<Row<P,V>> = (0..15).map(|i| {
+    let x = (i as f32)/15.0;
+    Row::Insert(((x,x),(x,x)), i)
+  }).collect();
+  db.batch(&batch)?;
+  assert![db.batch(&batch).is_err(), "plan that overwrites a tree rejected"];
+  Ok(())
+}
+
+fn trees<U> (db: &DB<MemoryStore,U,P,V>) -> Result<usize,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut count = 0;
+  for tree in db.trees.iter() {
+    if !tree.try_borrow_mut()?.is_empty()? { count += 1 }
+  }
+  Ok(count)
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results: Vec<(P,V,Location)> = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results.into_iter().map(|(p,v,_)| (p,v)).collect())
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
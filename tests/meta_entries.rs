@@ -0,0 +1,75 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage,FORMAT_VERSION};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn meta_entries() -> Result<(),Error> {
+  let storage = memory_storage();
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone()).build()?;
+  assert_eq![db.meta_get("attribution"), None, "no entries yet"];
+  db.meta_put("attribution", b"(c) OpenStreetMap contributors")?;
+  db.meta_put("import", b"planet-230101.osm.pbf")?;
+  db.batch(&rows())?;
+  let hash = db.root_hash()?;
+  db.meta_put("import", b"extract.pbf")?;
+  assert![db.root_hash()? != hash, "root hash of the rewritten meta store"];
+  assert_eq![db.meta_get("import"), Some(&b"extract.pbf"[..]),
+    "entry replaced"];
+  assert_eq![db.meta_delete("missing")?, None, "nothing to delete"];
+  db.meta_put("schema_version", &3u32.to_be_bytes())?;
+  assert_eq![db.meta_delete("schema_version")?, Some(vec![0,0,0,3]),
+    "entry deleted"];
+  let hash = db.root_hash()?;
+  drop(db);
+
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone()).build()?;
+  assert_eq![db.format(), FORMAT_VERSION];
+  assert![db.root_hash()? == hash, "same root hash after reopening"];
+  assert_eq![db.meta_keys().collect::<Vec<_>>(), vec!["attribution","import"],
+    "keys after reopening"];
+  assert_eq![db.meta_get("attribution"),
+    Some(&b"(c) OpenStreetMap contributors"[..]), "entry after reopening"];
+  assert_eq![db.meta_get("import"), Some(&b"extract.pbf"[..]),
+    "replaced entry after reopening"];
+  Ok(())
+}
+
+#[test]
+fn meta_entries_migrate() -> Result<(),Error> {
+  let storage = memory_storage();
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone()).build()?;
+  // build the rows into trees so the meta store is written in format 1
+  db.batch(&rows())?;
+  db.flush_staging()?;
+  assert_eq![db.format(), 1, "database in an older format"];
+  assert![db.meta_put("crs", b"EPSG:4326").is_err(),
+    "existing databases must be migrated first"];
+  let hash = db.root_hash()?;
+  db.migrate()?;
+  assert![db.root_hash()? != hash, "root hash of the migrated meta store"];
+  db.meta_put("crs", b"EPSG:4326")?;
+  drop(db);
+
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone()).build()?;
+  assert_eq![db.meta_get("crs"), Some(&b"EPSG:4326"[..]),
+    "entry after migrating"];
+  let n = db.query(&((-1.0,-1.0),(1.0,1.0)))?.count();
+  assert_eq![n, rows().len(), "records kept after migrating"];
+  Ok(())
+}
+
+fn rows () -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..2_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i)
+  }).collect()
+}
@@ -0,0 +1,121 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,FORMAT_VERSION};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn migrate() -> Result<(),Error> {
+  let plain_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let migrated_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..20_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>()%1000)
+  }).collect();
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.5,-0.8),(0.3,-0.5)),
+    ((0.1,0.1),(0.12,0.15))
+  ];
+  {
+    let mut plain = open(plain_dir.path())?;
+    let mut db = open(migrated_dir.path())?;
+    assert_eq![db.format(), 1, "new databases start at format version 1"];
+    for batch in inserts[0..10_000].chunks(5_000) {
+      plain.batch(batch)?;
+      db.batch(batch)?;
+    }
+    assert_eq![db.migrate()?, 1, "migrated from format version 1"];
+    assert_eq![db.format(), FORMAT_VERSION, "migrated to the newest format"];
+    assert_eq![db.migrate()?, FORMAT_VERSION, "nothing left to migrate"];
+    for bbox in bboxes.iter() {
+      assert_eq![query(&mut db, bbox)?, query(&mut plain, bbox)?,
+        "same results after migrating for {:?}", bbox];
+    }
+  }
+  {
+    // the migrated format is kept after reopening and new blocks are packed
+    let mut plain = open(plain_dir.path())?;
+    let mut db = open(migrated_dir.path())?;
+    assert_eq![db.format(), FORMAT_VERSION, "format kept after reopening"];
+    let plain_before = std::fs::metadata(plain_dir.path().join("data"))?.len();
+    let before = std::fs::metadata(migrated_dir.path().join("data"))?.len();
+    for batch in inserts[10_000..].chunks(5_000) {
+      plain.batch(batch)?;
+      db.batch(batch)?;
+    }
+    let plain_len = std::fs::metadata(plain_dir.path().join("data"))?.len();
+    let len = std::fs::metadata(migrated_dir.path().join("data"))?.len();
+    assert![len - before < plain_len - plain_before,
+      "new data blocks written in the migrated format are smaller"];
+    for bbox in bboxes.iter() {
+      assert_eq![query(&mut db, bbox)?, query(&mut plain, bbox)?,
+        "same results after reopening for {:?}", bbox];
+    }
+  }
+  {
+    // pretend a newer version of eyros wrote the meta store
+    let meta_file = migrated_dir.path().join("meta");
+    let mut meta = std::fs::read(&meta_file)?;
+    // the format version follows the branch factor and the tree mask
+    let mask_len = u32::from_be_bytes([meta[2],meta[3],meta[4],meta[5]]);
+    let k = 6 + ((mask_len as usize)+7)/8;
+    meta[k..k+2].copy_from_slice(&(FORMAT_VERSION+1).to_be_bytes());
+    std::fs::write(&meta_file, &meta)?;
+    match open(migrated_dir.path()) {
+      Ok(_) => panic!["opened a database with a newer format version"],
+      Err(err) => assert![err.to_string().contains("newer"),
+        "clear error for a newer format version: {}", err]
+    }
+  }
+  Ok(())
+}
+
+fn open (dir: &Path) -> Result<DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let open_store: Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>
+    = Box::new(move |name: &str| {
+      let p = dir.join(name);
+      Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+    });
+  Setup::new(open_store).max_data_size(500).build()
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V)>,Error> where
+S: RandomAccess<Error=Error>,
+U: Fn(&str) -> Result<S,Error> {
+  let mut results: Vec<(P,V,Location)> = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results.into_iter().map(|(p,v,_)| (p,v)).collect())
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
@@ -1,4 +1,4 @@
-use eyros::{DB,Row,Point,Mix,Mix3};
+use eyros::{DB,Row,PointOrd,Mix,Mix3};
 use random::{Source,default as rand};
 use failure::Error;
 use random_access_disk::RandomAccessDisk;
@@ -78,7 +78,7 @@ fn mix3() -> Result<(),Error> {
   Ok(())
 }
 
-fn contains (point: &P, bbox: &<P as Point>::Bounds) -> bool {
+fn contains (point: &P, bbox: &<P as PointOrd>::Bounds) -> bool {
   (match point.v0 {
     Mix::Scalar(x) => contains_pt((bbox.0).0, (bbox.1).0, x),
     Mix::Interval(x0,x1) => contains_iv((bbox.0).0, (bbox.1).0, x0, x1),
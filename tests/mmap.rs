@@ -0,0 +1,59 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,mmap_storage};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn mmap() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-0.5,-0.8),(0.3,-0.1));
+
+  let mut expected = {
+    let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+      let p = dir.path().join("disk").join(name);
+      Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+    }).base_size(1_000).build()?;
+    db.batch(&inserts[..2_000])?;
+    db.batch(&inserts[2_000..])?;
+    db.query(&bbox)?.map(|r| r.map(|(p,v,_)| (p,v)))
+      .collect::<Result<Vec<(P,V)>,Error>>()?
+  };
+
+  let mmap_dir = dir.path().join("mmap");
+  {
+    let mut db: DB<_,_,P,V> = Setup::new(mmap_storage(&mmap_dir))
+      .base_size(1_000).build()?;
+    db.batch(&inserts[..2_000])?;
+    // reads between writes remap the files
+    assert![db.query(&bbox)?.count() > 0];
+    db.batch(&inserts[2_000..])?;
+  }
+  let mut db: DB<_,_,P,V> = Setup::new(mmap_storage(&mmap_dir))
+    .base_size(1_000).build()?;
+  let mut results = db.query(&bbox)?.map(|r| r.map(|(p,v,_)| (p,v)))
+    .collect::<Result<Vec<(P,V)>,Error>>()?;
+  let cmp = |a: &(P,V), b: &(P,V)| a.partial_cmp(b).unwrap();
+  expected.sort_by(cmp);
+  results.sort_by(cmp);
+  assert![!expected.is_empty(), "expected results"];
+  assert_eq![results, expected];
+  Ok(())
+}
@@ -0,0 +1,148 @@
+extern crate eyros;
+extern crate failure;
+extern crate proptest;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use proptest::prelude::*;
+use proptest::collection::vec;
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+type Bounds = ((f32,f32),(f32,f32));
+
+const ALL: Bounds = ((-100.0,-100.0),(100.0,100.0));
+
+// Random interleavings of batches, deletes, queries and restarts are applied
+// to eyros and to a Vec of live records. Every query must return exactly the
+// records of the Vec that a brute-force overlap test selects.
+#[derive(Clone,Debug)]
+enum Op {
+  Insert(Vec<P>),
+  // positions in the model of the records to delete
+  Delete(Vec<usize>),
+  Query(Bounds),
+  Reopen
+}
+
+fn interval () -> impl Strategy<Value=(f32,f32)> {
+  (-10i32..10, 0i32..4).prop_map(|(x,w)| (x as f32, (x+w) as f32))
+}
+
+fn point () -> impl Strategy<Value=P> {
+  (interval(), interval())
+}
+
+fn bounds () -> impl Strategy<Value=Bounds> {
+  (interval(), interval()).prop_map(|((x0,x1),(y0,y1))| ((x0,y0),(x1,y1)))
+}
+
+fn op () -> impl Strategy<Value=Op> {
+  prop_oneof![
+    4 => vec(point(), 1..30).prop_map(Op::Insert),
+    2 => vec(any::<usize>(), 1..10).prop_map(Op::Delete),
+    3 => bounds().prop_map(Op::Query),
+    1 => Just(Op::Reopen)
+  ]
+}
+
+proptest! {
+  #![proptest_config(ProptestConfig::with_cases(64))]
+  #[test]
+  fn model (ops in vec(op(), 1..40)) {
+    run(&ops).unwrap();
+  }
+}
+
+fn run (ops: &[Op]) -> Result<(),Error> {
+  let storage = memory_storage();
+  let mut db = open(storage.clone())?;
+  let mut model: Vec<(P,V)> = vec![];
+  let mut next_id = 0;
+  for op in ops {
+    match op {
+      Op::Insert(points) => {
+        let rows: Vec<Row<P,V>> = points.iter().map(|p| {
+          next_id += 1;
+          let p = spread(p, next_id);
+          model.push((p,next_id));
+          Row::Insert(p,next_id)
+        }).collect();
+        db.batch(&rows)?;
+      },
+      Op::Delete(picks) => {
+        if model.is_empty() { continue }
+        let ids: HashSet<V> = picks.iter()
+          .map(|i| model[i % model.len()].1)
+          .collect();
+        let deletes: Vec<Row<P,V>> = query(&mut db, &ALL)?.into_iter()
+          .filter(|(_,v,_)| ids.contains(v))
+          .map(|(_,_,loc)| Row::Delete(loc))
+          .collect();
+        assert_eq![deletes.len(), ids.len(), "every deleted record found"];
+        db.batch(&deletes)?;
+        model.retain(|(_,v)| !ids.contains(v));
+      },
+      Op::Query(bbox) => {
+        let results: Vec<(P,V)> = query(&mut db, bbox)?.into_iter()
+          .map(|(p,v,_)| (p,v)).collect();
+        let mut expected: Vec<(P,V)> = model.iter()
+          .filter(|(p,_)| overlaps(p, bbox)).cloned().collect();
+        expected.sort_unstable_by(cmp);
+        assert_eq![results, expected, "query results for {:?}", bbox];
+      },
+      Op::Reopen => {
+        drop(db);
+        db = open(storage.clone())?;
+      }
+    }
+  }
+  let count = query(&mut db, &ALL)?.len();
+  assert_eq![count, model.len(), "every live record stored"];
+  Ok(())
+}
+
+// points on the integer grid repeat, more so as failures are shrunk, and a pile
+// of identical points larger than max_data_size can not be split into blocks,
+// so each point is moved apart by its id
+fn spread (p: &P, id: V) -> P {
+  let d = id as f32 / 1024.0;
+  (((p.0).0+d,(p.0).1+d),((p.1).0+d,(p.1).1+d))
+}
+
+fn overlaps (p: &P, bbox: &Bounds) -> bool {
+  let (((x0,x1),(y0,y1)),((bx0,by0),(bx1,by1))) = (p,bbox);
+  x0 <= bx1 && bx0 <= x1 && y0 <= by1 && by0 <= y1
+}
+
+fn open<U> (storage: U) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  // small blocks and a small staging area to exercise merges
+  Setup::new(storage)
+    .branch_factor(5)
+    .max_data_size(3)
+    .base_size(4)
+    .build()
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &Bounds)
+-> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(|a,b| cmp(&(a.0,a.1),&(b.0,b.1)));
+  Ok(results)
+}
+
+fn cmp (a: &(P,V), b: &(P,V)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
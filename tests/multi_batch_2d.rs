@@ -4,7 +4,7 @@ extern crate random;
 extern crate random_access_disk;
 extern crate tempfile;
 
-use eyros::{DB,Row};
+use eyros::{DB,Setup,Row,InvertedIntervals};
 use failure::Error;
 use random_access_disk::RandomAccessDisk;
 use random::{Source,default as rand};
@@ -94,14 +94,15 @@ fn multi_batch_f64iv_f32_u16() -> Result<(),Error> {
   type P = ((f64,f64),f32);
   type V = u16;
   let dir = Tmpfile::new().prefix("eyros").tempdir()?;
-  let mut db: DB<_,_,P,V> = DB::open(
+  // some of the intervals are inverted
+  let mut db: DB<_,_,P,V> = Setup::new(
     |name: &str| -> Result<RandomAccessDisk,Error> {
       let p = dir.path().join(name);
       Ok(RandomAccessDisk::builder(p)
         .auto_sync(false)
         .build()?)
     }
-  )?;
+  ).inverted_intervals(InvertedIntervals::Allow).build()?;
   let mut r = rand().seed([13,12]);
   let size = 10_000;
   let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
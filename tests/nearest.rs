@@ -0,0 +1,63 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Nearest,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn nearest_within() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let inserts: Vec<(P,V)> = (0..5_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>()*200.0-100.0;
+    let (w,h) = (r.read::<f32>()*2.0, r.read::<f32>()*0.2);
+    (((x,x+w),(y,y+h)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+
+  for _ in 0..100 {
+    let position = (r.read::<f32>()*220.0-110.0, r.read::<f32>()*220.0-110.0);
+    let max_dist = (r.read::<f32>()*10.0) as f64;
+    let mut expected: Option<f64> = None;
+    for (p,_) in inserts.iter() {
+      let d = p.distance(&position);
+      expected = match expected {
+        Some(e) if e <= d => Some(e),
+        _ if d <= max_dist => Some(d),
+        e => e
+      };
+    }
+    let result = db.nearest_within(&position, max_dist)?;
+    match (expected, result) {
+      (None, None) => {},
+      (Some(d), Some((p,v,_))) => {
+        assert_eq![p.distance(&position), d, "closest distance"];
+        assert![inserts.contains(&(p,v)), "result was inserted"];
+      },
+      (expected, result) => {
+        panic!["expected distance {:?}, got {:?}", expected, result];
+      }
+    }
+  }
+
+  let (p,_) = inserts[17];
+  let inside = ((p.0).0, (p.1).0);
+  let (q,_,_) = db.nearest_within(&inside, 0.0)?.expect("record at position");
+  assert_eq![q.distance(&inside), 0.0, "record containing the position"];
+  assert![db.nearest_within(&(500.0,500.0), 50.0)?.is_none(),
+    "no records within the radius"];
+  assert![db.nearest_within(&(0.0,0.0), -1.0).is_err(), "negative radius"];
+  Ok(())
+}
@@ -0,0 +1,44 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Row,memory_storage};
+use eyros::osm::{OsmPoint,OsmFeature,OsmKind};
+use failure::Error;
+use random::{Source,default as rand};
+
+#[test]
+fn osm_feature() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<OsmPoint,OsmFeature>> = (0..2_000).map(|i| {
+    let lon: f32 = r.read::<f32>()*360.0-180.0;
+    let lat: f32 = r.read::<f32>()*180.0-90.0;
+    let way = i % 3 == 0;
+    let size = if way { r.read::<f32>()*0.1 } else { 0.0 };
+    let feature = OsmFeature {
+      kind: if way { OsmKind::Way } else { OsmKind::Node },
+      id: (i as i64)*1_000_003,
+      tags: (0..(i%4)).map(|j| {
+        (format!["key{}",j], "välue".repeat(j+1))
+      }).collect(),
+      refs: if way { (0..(i%9) as i64).map(|j| j*i as i64).collect() }
+        else { vec![] }
+    };
+    Row::Insert(((lon,lon+size),(lat,lat+size)), feature)
+  }).collect();
+  let mut db: DB<_,_,OsmPoint,OsmFeature> = DB::open(memory_storage())?;
+  for batch in inserts.chunks(500) {
+    db.batch(batch)?;
+  }
+  let mut results = vec![];
+  for result in db.query(&((-180.0,-90.0),(180.0,90.0)))? {
+    results.push(result?.1);
+  }
+  results.sort_unstable_by_key(|f| f.id);
+  let expected: Vec<OsmFeature> = inserts.iter().map(|row| match row {
+    Row::Insert(_,f) => f.clone(),
+    _ => panic!["unexpected row"]
+  }).collect();
+  assert_eq![results, expected, "features round-trip through the database"];
+  Ok(())
+}
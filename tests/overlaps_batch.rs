@@ -0,0 +1,43 @@
+extern crate eyros;
+extern crate random;
+
+use eyros::{Point,Mix,Mix2};
+use random::{Source,default as rand};
+
+fn check<P> (points: &[P], bbox: &P::Bounds) where P: Point {
+  let mut mask = vec![false;3]; // replaced by overlaps_batch
+  P::overlaps_batch(points, bbox, &mut mask);
+  let expected: Vec<bool> = points.iter().map(|p| p.overlaps(bbox)).collect();
+  assert_eq![mask, expected];
+  assert![mask.iter().any(|m| *m), "some points overlap"];
+  assert![mask.iter().any(|m| !*m), "some points do not overlap"];
+}
+
+#[test]
+fn overlaps_batch() {
+  let mut r = rand().seed([13,12]);
+  let intervals: Vec<((f32,f32),(f32,f32),f64)> = (0..2_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(16.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(16.0)*(1.0-ymin);
+    ((xmin,xmax),(ymin,ymax),r.read::<f64>()*1000.0)
+  }).collect();
+  check(&intervals, &((-0.5,-0.8,0.0),(0.3,-0.1,500.0)));
+
+  let scalars: Vec<(f64,f64)> = (0..2_000).map(|_| {
+    (r.read::<f64>()*2.0-1.0, r.read::<f64>()*2.0-1.0)
+  }).collect();
+  check(&scalars, &((-0.5,-0.5),(0.5,0.5)));
+
+  let mixed: Vec<Mix2<f32,f32>> = (0..2_000).map(|_| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    if r.read::<f32>() > 0.5 {
+      Mix2::new(Mix::Interval(x,x+0.1), Mix::Scalar(y))
+    } else {
+      Mix2::new(Mix::Scalar(x), Mix::Interval(y,y+0.1))
+    }
+  }).collect();
+  check(&mixed, &((-0.5,-0.5),(0.5,0.5)));
+}
@@ -0,0 +1,109 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32),f32);
+type V = u32;
+
+#[test]
+fn pack_coords() -> Result<(),Error> {
+  let plain_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let packed_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..30_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert(((xmin,xmax),(ymin,ymax),time), r.read::<u32>()%1000)
+  }).collect();
+  let bboxes = vec![
+    ((-1.0,-1.0,0.0),(1.0,1.0,1000.0)),
+    ((-0.5,-0.8,100.0),(0.3,-0.5,900.0)),
+    ((0.1,0.1,0.0),(0.12,0.15,500.0))
+  ];
+  {
+    let mut plain = open(plain_dir.path(), false)?;
+    let mut packed = open(packed_dir.path(), true)?;
+    for batch in inserts[0..20_000].chunks(5_000) {
+      plain.batch(batch)?;
+      packed.batch(batch)?;
+    }
+    let plain_len = std::fs::metadata(plain_dir.path().join("data"))?.len();
+    let packed_len = std::fs::metadata(packed_dir.path().join("data"))?.len();
+    // the low mantissa bytes of random coordinates share no bits, so only
+    // the sign, exponent and high mantissa bits of each block pack
+    assert![packed_len*20 < plain_len*19,
+      "packed data store ({} bytes) is smaller than plain ({} bytes)",
+      packed_len, plain_len];
+    for bbox in bboxes.iter() {
+      assert_eq![query(&mut packed, bbox)?, query(&mut plain, bbox)?,
+        "same results for {:?}", bbox];
+    }
+  }
+  {
+    // the format version in meta keeps packing enabled after reopening
+    let mut plain = open(plain_dir.path(), false)?;
+    let mut packed = open(packed_dir.path(), false)?;
+    let before = std::fs::metadata(packed_dir.path().join("data"))?.len();
+    for batch in inserts[20_000..].chunks(5_000) {
+      plain.batch(batch)?;
+      packed.batch(batch)?;
+    }
+    let plain_len = std::fs::metadata(plain_dir.path().join("data"))?.len();
+    let packed_len = std::fs::metadata(packed_dir.path().join("data"))?.len();
+    assert![packed_len > before, "more data written"];
+    assert![packed_len*20 < plain_len*19,
+      "data still packed after reopening"];
+    for bbox in bboxes.iter() {
+      assert_eq![query(&mut packed, bbox)?, query(&mut plain, bbox)?,
+        "same results after reopening for {:?}", bbox];
+    }
+  }
+  Ok(())
+}
+
+fn open (dir: &Path, pack: bool) -> Result<DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let open_store: Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>
+    = Box::new(move |name: &str| {
+      let p = dir.join(name);
+      Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+    });
+  Setup::new(open_store).max_data_size(500).pack_coords(pack).build()
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32,f32),(f32,f32,f32)))
+-> Result<Vec<(P,V)>,Error> where
+S: RandomAccess<Error=Error>,
+U: Fn(&str) -> Result<S,Error> {
+  let mut results: Vec<(P,V,Location)> = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results.into_iter().map(|(p,v,_)| (p,v)).collect())
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
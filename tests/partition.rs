@@ -0,0 +1,67 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,QueryOptions,PointOrd,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn partition() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400)
+    .max_data_size(100)
+    .partition(|v: &V| (v % 3) as u8)
+    .build()?;
+  let inserts: Vec<(P,V)> = (0..5_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    (((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  // the last batch stays in the staging area
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.5,-0.2),(0.3,0.1)),
+    ((0.9,0.9),(0.95,0.99))
+  ];
+  for bbox in bboxes.iter() {
+    for mask in [0b001u64, 0b010, 0b101, 0b111, 0b1000].iter() {
+      let opts = QueryOptions::new().partitions(*mask);
+      let mut results = db.query_with(bbox, &opts)?
+        .map(|r| r.map(|(_,v,_)| v))
+        .collect::<Result<Vec<V>,Error>>()?;
+      results.sort_unstable();
+      let mut expected: Vec<V> = inserts.iter()
+        .filter(|(p,v)| p.overlaps(bbox) && (1 << (v % 3)) & mask != 0)
+        .map(|(_,v)| *v)
+        .collect();
+      expected.sort_unstable();
+      assert_eq![results, expected,
+        "results for {:?} in partitions {:#b}", bbox, mask];
+    }
+  }
+
+  let bad = vec![Row::Insert(((0.0,0.1),(0.0,0.1)), 64)];
+  let mut wide: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .partition(|v: &V| *v as u8)
+    .build()?;
+  assert![wide.batch(&bad).is_err(), "partition keys of 64 and up rejected"];
+
+  let mut plain: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .build()?;
+  let opts = QueryOptions::new().partitions(1);
+  assert![plain.query_with(&((-1.0,-1.0),(1.0,1.0)), &opts).is_err(),
+    "partitions need a partitioned database"];
+  Ok(())
+}
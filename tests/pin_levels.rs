@@ -0,0 +1,96 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,PointOrd,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn pin_levels() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<(P,V)> = (0..12_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    (((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  let storage = memory_storage();
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone())
+    .base_size(1_000)
+    .max_data_size(50)
+    .pin_levels(2)
+    .build()?;
+  for batch in rows[..10_000].chunks(2_500) {
+    db.batch(batch)?;
+  }
+
+  let bboxes = vec![
+    ((-0.5,-0.4),(0.2,0.1)),
+    ((-0.1,0.3),(0.4,0.35)),
+    ((0.7,-0.9),(0.9,-0.6))
+  ];
+  let stats = db.cache_stats()?;
+  for bbox in bboxes.iter() {
+    assert_eq![values(&mut db, bbox)?, expected(&inserts[..10_000], bbox)];
+  }
+  let first = db.cache_stats()?;
+  assert![first.branch_reads > stats.branch_reads, "branches read"];
+  assert![first.pinned_branches > 0, "top branches pinned"];
+  assert![first.pinned_bytes > 0 && first.pinned_bytes <= 4*1024*1024];
+  for bbox in bboxes.iter() {
+    assert_eq![values(&mut db, bbox)?, expected(&inserts[..10_000], bbox)];
+  }
+  let second = db.cache_stats()?;
+  assert![second.branch_hits > first.branch_hits, "pinned branches hit"];
+  assert_eq![second.pinned_branches, first.pinned_branches,
+    "nothing more to pin"];
+  assert![second.branch_hit_rate() > 0.0];
+  assert![second.data_hits > first.data_hits, "cached data blocks hit"];
+
+  // merges rebuild the trees and drop their pins
+  db.batch(&rows[10_000..])?;
+  for bbox in bboxes.iter() {
+    assert_eq![values(&mut db, bbox)?, expected(&inserts, bbox),
+      "results after a merge for {:?}", bbox];
+  }
+
+  // nothing is pinned without a budget
+  let mut unpinned: DB<MemoryStore,_,P,V> = Setup::new(storage.clone())
+    .base_size(1_000)
+    .max_data_size(50)
+    .pin_levels(2)
+    .pin_bytes(0)
+    .build()?;
+  for bbox in bboxes.iter() {
+    values(&mut unpinned, bbox)?;
+    values(&mut unpinned, bbox)?;
+  }
+  let stats = unpinned.cache_stats()?;
+  assert_eq![(stats.pinned_branches,stats.branch_hits), (0,0)];
+  assert![stats.branch_reads > 0];
+  Ok(())
+}
+
+fn values<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<V>,Error> where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut values = db.query(bbox)?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  values.sort_unstable();
+  Ok(values)
+}
+
+fn expected (inserts: &[(P,V)], bbox: &((f32,f32),(f32,f32))) -> Vec<V> {
+  let mut values: Vec<V> = inserts.iter()
+    .filter(|(p,_)| p.overlaps(bbox))
+    .map(|(_,v)| *v)
+    .collect();
+  values.sort_unstable();
+  values
+}
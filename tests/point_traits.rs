@@ -0,0 +1,134 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage,
+  PointOrd,PointCodec,BranchQuery};
+use desert::{ToBytes,FromBytes,CountBytes};
+use failure::Error;
+use random::{Source,default as rand};
+use std::cmp::Ordering;
+
+// grid cell with integer coordinates, stored as two big-endian u16s
+#[derive(Clone,Copy,Debug,PartialEq)]
+struct Cell { x: u16, y: u16 }
+
+type V = u32;
+
+impl ToBytes for Cell {
+  fn to_bytes (&self) -> Result<Vec<u8>,Error> {
+    (self.x,self.y).to_bytes()
+  }
+  fn write_bytes (&self, dst: &mut [u8]) -> Result<usize,Error> {
+    (self.x,self.y).write_bytes(dst)
+  }
+}
+
+impl FromBytes for Cell {
+  fn from_bytes (src: &[u8]) -> Result<(usize,Self),Error> {
+    let (size,(x,y)) = <(u16,u16)>::from_bytes(src)?;
+    Ok((size, Cell { x, y }))
+  }
+}
+
+impl CountBytes for Cell {
+  fn count_bytes (&self) -> usize { 4 }
+  fn count_from_bytes (_buf: &[u8]) -> Result<usize,Error> { Ok(4) }
+}
+
+impl PointOrd for Cell {
+  type Bounds = ((u16,u16),(u16,u16));
+  type Range = ((u16,u16),(u16,u16));
+  fn cmp_at (&self, other: &Self, level: usize) -> Ordering {
+    match level % 2 {
+      0 => self.x.cmp(&other.x),
+      _ => self.y.cmp(&other.y)
+    }
+  }
+  fn midpoint_upper (&self, other: &Self) -> Self {
+    Cell {
+      x: ((self.x as u32 + other.x as u32)/2) as u16,
+      y: ((self.y as u32 + other.y as u32)/2) as u16
+    }
+  }
+  fn dim () -> usize { 2 }
+  fn overlaps (&self, bbox: &Self::Bounds) -> bool {
+    (bbox.0).0 <= self.x && self.x <= (bbox.1).0
+    && (bbox.0).1 <= self.y && self.y <= (bbox.1).1
+  }
+  fn bounds (cells: &Vec<Self>) -> Option<Self::Bounds> {
+    let first = cells.first()?;
+    Some(cells.iter().fold(((first.x,first.y),(first.x,first.y)), |b,c| {
+      ((b.0.0.min(c.x),b.0.1.min(c.y)),(b.1.0.max(c.x),b.1.1.max(c.y)))
+    }))
+  }
+  fn bounds_to_range (bbox: Self::Bounds) -> Self::Range {
+    (((bbox.0).0,(bbox.1).0),((bbox.0).1,(bbox.1).1))
+  }
+}
+
+impl PointCodec for Cell {
+  fn serialize_at (&self, level: usize, dst: &mut [u8])
+  -> Result<usize,Error> {
+    match level % 2 {
+      0 => self.x.write_bytes(dst),
+      _ => self.y.write_bytes(dst)
+    }
+  }
+  fn pivot_bytes_at (&self, _level: usize) -> usize { 2 }
+  fn count_bytes_at (_buf: &[u8], _level: usize) -> Result<usize,Error> {
+    Ok(2)
+  }
+  fn pivot_cmp_at (buf: &[u8], bbox: &Self::Bounds, level: usize)
+  -> Result<(bool,bool),Error> {
+    let (_,pivot) = u16::from_bytes(buf)?;
+    Ok(match level % 2 {
+      0 => ((bbox.0).0 <= pivot, pivot <= (bbox.1).0),
+      _ => ((bbox.0).1 <= pivot, pivot <= (bbox.1).1)
+    })
+  }
+  fn format_at (buf: &[u8], _level: usize) -> Result<String,Error> {
+    Ok(format!["{}", u16::from_bytes(buf)?.1])
+  }
+}
+
+// branch blocks are read with the default traversal
+impl BranchQuery for Cell {}
+
+#[test]
+fn default_branch_query() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,Cell,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let inserts: Vec<(Cell,V)> = (0..5_000).map(|i| {
+    (Cell { x: r.read::<u16>() % 1000, y: r.read::<u16>() % 1000 }, i)
+  }).collect();
+  let rows: Vec<Row<Cell,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+  let bboxes = vec![
+    ((0,0),(999,999)),
+    ((100,200),(300,250)),
+    ((500,500),(500,500)),
+    ((990,0),(999,40))
+  ];
+  for bbox in bboxes.iter() {
+    let mut expected: Vec<V> = inserts.iter()
+      .filter(|(p,_)| p.overlaps(bbox))
+      .map(|(_,v)| *v)
+      .collect();
+    expected.sort_unstable();
+    let mut results = vec![];
+    for result in db.query(bbox)? {
+      let (p,v,_) = result?;
+      assert![p.overlaps(bbox), "result overlaps bbox"];
+      results.push(v);
+    }
+    results.sort_unstable();
+    assert_eq![results, expected, "query for {:?}", bbox];
+  }
+  Ok(())
+}
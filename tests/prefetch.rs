@@ -0,0 +1,65 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,PointOrd,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn prefetch() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<(P,V)> = (0..8_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    (((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  let storage = memory_storage();
+  {
+    let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone())
+      .base_size(1_000)
+      .build()?;
+    for batch in rows.chunks(2_000) {
+      db.batch(batch)?;
+    }
+  }
+
+  let bbox = ((-0.4,-0.3),(0.2,0.5));
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone())
+    .base_size(1_000)
+    .build()?;
+  let report = db.prefetch(&bbox)?;
+  assert![report.blocks > 0, "blocks read into the cache"];
+  assert_eq![report.cached, 0];
+  assert_eq![report.over_capacity, 0];
+  let again = db.prefetch(&bbox)?;
+  assert_eq![again.blocks, 0, "nothing left to read"];
+  assert_eq![again.cached, report.blocks, "every block cached"];
+
+  let mut values = db.query(&bbox)?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  values.sort_unstable();
+  let mut expected: Vec<V> = inserts.iter()
+    .filter(|(p,_)| p.overlaps(&bbox))
+    .map(|(_,v)| *v)
+    .collect();
+  expected.sort_unstable();
+  assert_eq![values, expected, "same results after a prefetch"];
+
+  // a cache too small for the region
+  let mut small: DB<MemoryStore,_,P,V> = Setup::new(storage.clone())
+    .base_size(1_000)
+    .data_list_cache_size(3)
+    .build()?;
+  let report = small.prefetch(&bbox)?;
+  assert_eq![report.blocks, 3, "cache filled"];
+  assert![report.over_capacity > 0, "blocks past the cache capacity"];
+  Ok(())
+}
@@ -0,0 +1,53 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage,Sourced};
+use failure::Error;
+use random::{Source,default as rand};
+
+use std::collections::BTreeMap;
+
+type P = ((f32,f32),(f32,f32));
+type V = Sourced<u32>;
+
+#[test]
+fn provenance() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(200).build()?;
+  for source in 0..3 {
+    let inserts: Vec<Row<P,V>> = (0..1_000).map(|i| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      let value = Sourced::new(source, i as u64, r.read::<u32>());
+      Row::Insert(((xmin,xmax),(ymin,ymax)), value)
+    }).collect();
+    for batch in inserts.chunks(300) {
+      db.batch(batch)?;
+    }
+  }
+  let expected: BTreeMap<u32,u64> = (0..3).map(|s| (s,1_000)).collect();
+  assert_eq![db.sources()?, expected, "records counted by source"];
+
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let all = db.query(&bbox)?.collect::<Result<Vec<_>,Error>>()?;
+  let from_1 = db.query_source(&bbox, 1)?;
+  assert_eq![from_1.len(), all.iter().filter(|(_,v,_)| v.source == 1).count(),
+    "query by source"];
+  assert![from_1.iter().all(|(_,v,_)| v.source == 1 && v.offset < 1_000),
+    "source and offset kept with each record"];
+
+  assert_eq![db.purge_source(1)?, 1_000, "every record from the source"];
+  assert_eq![db.query_source(&bbox, 1)?.len(), 0, "source purged"];
+  assert_eq![db.query_source(&bbox, 2)?.len(),
+    all.iter().filter(|(_,v,_)| v.source == 2).count(),
+    "other sources kept"];
+  let mut expected = expected;
+  expected.remove(&1);
+  assert_eq![db.sources()?, expected, "remaining sources"];
+  assert_eq![db.purge_source(1)?, 0, "nothing left to purge"];
+  Ok(())
+}
@@ -0,0 +1,103 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Quantize,PointOrd,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = (f64,f64);
+type V = u32;
+
+#[test]
+fn quantize() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|i| {
+    let lon: f64 = r.read::<f64>()*360.0-180.0;
+    let lat: f64 = r.read::<f64>()*180.0-90.0;
+    Row::Insert((lon,lat), i)
+  }).collect();
+  let quantize = vec![Quantize::new(1e-7, 0.0); 2];
+  let storage = memory_storage();
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(storage.clone())
+    .base_size(500)
+    .quantize(quantize.clone())
+    .build()?;
+  let full_storage = memory_storage();
+  let mut full: DB<MemoryStore,_,P,V> = Setup::new(full_storage.clone())
+    .base_size(500)
+    .build()?;
+  for batch in inserts.chunks(1_000) {
+    db.batch(batch)?;
+    full.batch(batch)?;
+  }
+  let quantized_bytes = db.data_store.try_borrow_mut()?.bytes()?;
+  let full_bytes = full.data_store.try_borrow_mut()?.bytes()?;
+  assert![quantized_bytes*4 < full_bytes*3,
+    "quantized data blocks of {} bytes, {} bytes at full precision",
+    quantized_bytes, full_bytes];
+
+  let bbox = ((-20.0,-10.0),(40.0,30.0));
+  let expected: Vec<(P,V)> = inserts.iter().filter_map(|row| match row {
+    Row::Insert(p,v) => Some((snap(p),*v)),
+    _ => None
+  }).filter(|(p,_)| p.overlaps(&bbox)).collect();
+  assert![expected.len() > 100];
+  for reopen in [false,true].iter() {
+    if *reopen {
+      // the quantization is read back from the meta store
+      db = Setup::new(storage.clone()).base_size(500).build()?;
+    }
+    assert_eq![db.quantization(), Some(&quantize[..])];
+    let mut results = db.query(&bbox)?
+      .map(|r| r.map(|(p,v,_)| (p,v)))
+      .collect::<Result<Vec<(P,V)>,Error>>()?;
+    results.sort_unstable_by_key(|(_,v)| *v);
+    assert_eq![results.len(), expected.len()];
+    for ((p,v),(q,w)) in results.iter().zip(expected.iter()) {
+      assert_eq![v, w];
+      assert![(p.0-q.0).abs() < 1e-9 && (p.1-q.1).abs() < 1e-9,
+        "{:?} read back as {:?}", q, p];
+    }
+  }
+
+  assert![
+    Setup::new(storage.clone())
+      .quantize(vec![Quantize::new(1e-6, 0.0); 2])
+      .build::<P,V>().is_err(),
+    "opening with a different quantization fails"
+  ];
+  assert![
+    Setup::new(full_storage.clone())
+      .quantize(quantize.clone())
+      .build::<P,V>().is_err(),
+    "quantizing an existing database fails"
+  ];
+  Ok(())
+}
+
+#[test]
+fn quantize_range() -> Result<(),Error> {
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .quantize(vec![Quantize::new(1e-7, 0.0); 2])
+    .build()?;
+  let rows = vec![
+    Row::Insert((1.0,2.0), 0),
+    Row::Insert((300.0,2.0), 1)
+  ];
+  assert![db.batch(&rows).is_err(), "coordinate out of range for an i32 step"];
+  let bbox = ((-400.0,-400.0),(400.0,400.0));
+  assert_eq![db.query(&bbox)?.count(), 0, "no row of the batch written"];
+  assert![
+    Setup::new(memory_storage())
+      .quantize(vec![Quantize::new(1e-7, 0.0)])
+      .build::<P,V>().is_err(),
+    "one quantization for each dimension"
+  ];
+  Ok(())
+}
+
+fn snap (p: &P) -> P {
+  let q = Quantize::new(1e-7, 0.0);
+  (q.value(q.step(p.0).unwrap()), q.value(q.step(p.1).unwrap()))
+}
@@ -0,0 +1,58 @@
+extern crate arrow;
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use arrow::array::{Array,Float32Array,UInt32Array};
+use eyros::{DB,Setup,Row,ARROW_BATCH_ROWS,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),f32);
+type V = u32;
+
+#[test]
+fn query_arrow() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..20_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((xmin,xmax),y), r.read::<u32>())
+  }).collect();
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage())
+    .base_size(1_000)
+    .build()?;
+  for batch in inserts.chunks(5_000) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-0.5,-0.8),(0.9,0.6));
+  let mut expected = vec![];
+  for result in db.query(&bbox)? {
+    let (p,v,_) = result?;
+    expected.push(((p.0).0, (p.0).1, p.1, v));
+  }
+  assert![expected.len() > ARROW_BATCH_ROWS, "results span several batches"];
+
+  let batches = db.query_arrow(&bbox)?;
+  let names: Vec<String> = batches.schema().fields().iter()
+    .map(|f| f.name().clone()).collect();
+  assert_eq![names, vec!["dim0_min","dim0_max","dim1","value"],
+    "a column per interval bound, scalar and value"];
+  let mut rows = vec![];
+  for batch in batches {
+    let batch = batch?;
+    assert![batch.num_rows() <= ARROW_BATCH_ROWS, "batch size is bounded"];
+    let f = |i: usize| batch.column(i).as_any()
+      .downcast_ref::<Float32Array>().unwrap().clone();
+    let (xmin,xmax,y) = (f(0),f(1),f(2));
+    let value = batch.column(3).as_any()
+      .downcast_ref::<UInt32Array>().unwrap().clone();
+    assert_eq![value.null_count(), 0, "no nulls"];
+    for i in 0..batch.num_rows() {
+      rows.push((xmin.value(i), xmax.value(i), y.value(i), value.value(i)));
+    }
+  }
+  assert_eq![rows, expected, "same rows as the query in the same order"];
+  Ok(())
+}
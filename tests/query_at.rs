@@ -0,0 +1,94 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn query_at() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let p = dir.path().to_path_buf();
+  let setup = || Setup::new({
+    let p = p.clone();
+    move |name: &str| storage(p.clone(), name)
+  }).base_size(100).keep_generations(true);
+  let mut db: DB<_,_,P,V> = setup().build()?;
+  assert_eq![db.generation()?, Some(0)];
+
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let mut expected: Vec<Vec<(P,V,Location)>> = vec![vec![]];
+  let mut r = rand().seed([13,12]);
+  for i in 0..12 {
+    let mut batch: Vec<Row<P,V>> = (0..r.read::<u32>()%250).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect();
+    if i % 3 == 2 {
+      for (_,_,loc) in expected.last().unwrap().iter().take(40) {
+        batch.push(Row::Delete(*loc));
+      }
+    }
+    db.batch(&batch)?;
+    expected.push(query(&mut db, &bbox)?);
+  }
+  assert_eq![db.generation()?, Some(12)];
+  for (g,results) in expected.iter().enumerate() {
+    let mut actual = db.query_at(g as u64, &bbox)?;
+    actual.sort_unstable_by(cmp);
+    assert_eq![&actual, results, "generation {}", g];
+  }
+  assert![db.query_at(13, &bbox).is_err()];
+  let preserved = std::fs::read_dir(&p)?
+    .filter(|e| e.as_ref().map(|e| {
+      e.file_name().to_string_lossy().contains('@')
+    }).unwrap_or(false))
+    .count();
+  assert![preserved > 0, "superseded trees are preserved"];
+
+  drop(db);
+  let mut db: DB<_,_,P,V> = setup().build()?;
+  assert_eq![db.generation()?, Some(12)];
+  for (g,results) in expected.iter().enumerate() {
+    let mut actual = db.query_at(g as u64, &bbox)?;
+    actual.sort_unstable_by(cmp);
+    assert_eq![&actual, results, "generation {} after reopen", g];
+  }
+  Ok(())
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where S: random_access_storage::RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>) {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,51 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_deletes() -> Result<(),Error> {
+  for bitmap in [false,true].iter() {
+    let mut r = rand().seed([13,12]);
+    let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+      .base_size(1_000).delete_bitmap(*bitmap).build()?;
+    let inserts: Vec<Row<P,V>> = (0..5_000).map(|i| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), i)
+    }).collect();
+    db.batch(&inserts)?;
+    let bbox = ((-1.0,-1.0),(1.0,1.0));
+    let all = db.query(&bbox)?
+      .collect::<Result<Vec<(P,V,Location)>,Error>>()?;
+    assert_eq![all.len(), 5_000, "every record inserted"];
+
+    // delete records that the iterator already read from their data block
+    let mut iter = db.query(&bbox)?;
+    let mut results = vec![];
+    for _ in 0..10 {
+      results.push(iter.next().unwrap()?);
+    }
+    let deletes: Vec<Row<P,V>> = all[10..60].iter()
+      .map(|(_,_,loc)| Row::Delete(*loc)).collect();
+    db.batch(&deletes)?;
+    for result in iter {
+      results.push(result?);
+    }
+    let mut expected = all[0..10].to_vec();
+    expected.extend_from_slice(&all[60..]);
+    assert_eq![results, expected,
+      "deleted tree records skipped (bitmap: {})", bitmap];
+    assert_eq![db.query(&bbox)?.count(), 4_950,
+      "deletes visible to new queries (bitmap: {})", bitmap];
+  }
+  Ok(())
+}
@@ -0,0 +1,65 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_difference() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let inserts: Vec<(P,V)> = (0..5_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>()*200.0-100.0;
+    let (w,h) = (r.read::<f32>()*3.0, r.read::<f32>()*3.0);
+    (((x,x+w),(y,y+h)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+  // delete a few records from a tree and leave the deletes staged
+  let bbox = ((-10.0,-10.0),(10.0,10.0));
+  let mut deleted = vec![];
+  let mut deletes = vec![];
+  for result in db.query(&bbox)?.take(20) {
+    let (_,v,loc) = result?;
+    deleted.push(v);
+    deletes.push(Row::Delete(loc));
+  }
+  db.batch(&deletes)?;
+
+  let viewports: Vec<(P,P)> = vec![
+    (((-20.0,-20.0),(30.0,30.0)), ((-30.0,-25.0),(20.0,25.0))),
+    (((0.0,0.0),(50.0,50.0)), ((60.0,60.0),(90.0,90.0))),
+    (((-50.0,-50.0),(50.0,50.0)), ((-60.0,-60.0),(60.0,60.0)))
+  ];
+  for (include,exclude) in viewports.iter() {
+    let mut expected: Vec<V> = inserts.iter()
+      .filter(|(p,v)| {
+        overlaps(p, include) && !overlaps(p, exclude) && !deleted.contains(v)
+      })
+      .map(|(_,v)| *v)
+      .collect();
+    expected.sort_unstable();
+    let mut results: Vec<V> = db.query_difference(include, exclude)?
+      .into_iter().map(|(_,v,_)| v).collect();
+    results.sort_unstable();
+    assert_eq![results, expected, "records in {:?} but not in {:?}",
+      include, exclude];
+  }
+  Ok(())
+}
+
+fn overlaps (p: &P, bbox: &((f32,f32),(f32,f32))) -> bool {
+  let (((x0,x1),(y0,y1)),((bx0,by0),(bx1,by1))) = (p,bbox);
+  x0 <= bx1 && bx0 <= x1 && y0 <= by1 && by0 <= y1
+}
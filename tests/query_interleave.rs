@@ -0,0 +1,70 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,QueryOptions,Location};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_interleave() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).max_data_size(200).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let size = 40_000;
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  for batch in inserts.chunks(10_000) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut expected = vec![];
+  for result in db.query(&bbox)? {
+    expected.push(result?);
+  }
+  let opts = QueryOptions::new().interleave(true);
+  let mut results = vec![];
+  for result in db.query_with(&bbox, &opts)? {
+    results.push(result?);
+  }
+  assert_eq![results.len(), size, "every record with interleave"];
+
+  // the first 10% of the results cover every quadrant
+  let mut quadrants = [0usize;4];
+  for ((x,y),_,_) in results.iter().take(size/10) {
+    let q = (if x.0 < 0.0 { 0 } else { 1 }) + (if y.0 < 0.0 { 0 } else { 2 });
+    quadrants[q] += 1;
+  }
+  assert![quadrants.iter().all(|n| *n > 0),
+    "early results in every quadrant: {:?}", quadrants];
+
+  results.sort_unstable_by(cmp);
+  expected.sort_unstable_by(cmp);
+  assert_eq![results, expected, "same records as the default order"];
+  Ok(())
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,54 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,IoMode,disk_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::sync::mpsc::sync_channel;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_into() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_500).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let storage = disk_storage(dir.path().to_path_buf(), IoMode::Buffered);
+  let mut db: DB<_,_,P,V> = Setup::new(storage).base_size(1_000).build()?;
+  for batch in inserts.chunks(1_100) {
+    db.batch(batch)?;
+  }
+  let deletes: Vec<Row<P,V>> = db.query(&((-0.2,-0.2),(0.2,0.2)))?
+    .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+    .collect::<Result<Vec<_>,Error>>()?;
+  db.batch(&deletes)?;
+
+  let bbox = ((-0.5,-0.8),(0.3,0.1));
+  let mut expected = db.query(&bbox)?
+    .collect::<Result<Vec<(P,V,Location)>,Error>>()?;
+  expected.sort_unstable_by_key(|row| row.1);
+  assert![!expected.is_empty(), "expected results"];
+
+  let (sender,receiver) = sync_channel(16);
+  let handle = db.query_into(&bbox, sender)?;
+  let mut results = receiver.iter().collect::<Result<Vec<_>,Error>>()?;
+  handle.join().unwrap();
+  results.sort_unstable_by_key(|row| row.1);
+  assert_eq![results, expected, "same results as db.query()"];
+
+  // the query stops once the receiver is dropped
+  let (sender,receiver) = sync_channel(1);
+  let handle = db.query_into(&bbox, sender)?;
+  assert![receiver.recv()?.is_ok(), "first result"];
+  drop(receiver);
+  handle.join().unwrap();
+  Ok(())
+}
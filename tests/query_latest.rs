@@ -0,0 +1,59 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,QueryOptions,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use std::collections::HashMap;
+
+type P = (f32,f32);
+type V = (u32,u64);
+
+#[test]
+fn query_latest() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let mut inserts: Vec<(P,V)> = vec![];
+  for time in 0..10 {
+    let batch: Vec<Row<P,V>> = (0..500).map(|_| {
+      let p = (r.read::<f32>()*2.0-1.0, r.read::<f32>()*2.0-1.0);
+      let v = (r.read::<u32>()%300, time*100 + r.read::<u64>()%50);
+      inserts.push((p,v));
+      Row::Insert(p,v)
+    }).collect();
+    db.batch(&batch)?;
+  }
+
+  for bbox in [((-1.0,-1.0),(1.0,1.0)),((-0.5,-0.2),(0.3,0.6))].iter() {
+    let mut expected: HashMap<u32,(P,V)> = HashMap::new();
+    for (p,v) in inserts.iter() {
+      if p.0 < (bbox.0).0 || p.0 > (bbox.1).0 || p.1 < (bbox.0).1
+      || p.1 > (bbox.1).1 { continue }
+      let newer = match expected.get(&v.0) {
+        Some((_,w)) => v.1 > w.1,
+        None => true
+      };
+      if newer { expected.insert(v.0, (*p,*v)); }
+    }
+    let opts = QueryOptions::new().latest_by(|v: &V| v.0, |v: &V| v.1);
+    let mut results: HashMap<u32,(P,V)> = HashMap::new();
+    for result in db.query_with(bbox, &opts)? {
+      let (p,v,_) = result?;
+      assert![results.insert(v.0, (p,v)).is_none(), "one result per id"];
+    }
+    assert_eq![results.len(), expected.len(), "result for every id"];
+    for (id,(_,v)) in results.iter() {
+      assert_eq![v.1, expected[id].1.1, "latest time for id {}", id];
+    }
+    let opts = opts.limit(10);
+    assert_eq![db.query_with(bbox, &opts)?.count(), 10.min(expected.len()),
+      "limit applies to latest records"];
+  }
+
+  let opts = QueryOptions::new().latest_by(|v: &u32| *v, |v: &u32| *v);
+  assert![db.query_with(&((-1.0,-1.0),(1.0,1.0)), &opts).is_err(),
+    "extractors for another value type rejected"];
+  Ok(())
+}
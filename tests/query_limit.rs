@@ -0,0 +1,56 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,QueryOptions,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_limit() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  for batch in inserts.chunks(700) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let all = db.query(&bbox)?.collect::<Result<Vec<(P,V,Location)>,Error>>()?;
+  assert![all.len() > 1_000, "enough results to page through"];
+
+  let first = query(&mut db, &bbox, &QueryOptions::new().limit(100))?;
+  assert_eq![first, all[0..100].to_vec(), "limit returns the first results"];
+  let mut pages = vec![];
+  let mut offset = 0;
+  loop {
+    let opts = QueryOptions::new().offset(offset).limit(300);
+    let page = query(&mut db, &bbox, &opts)?;
+    assert![page.len() <= 300, "page no larger than the limit"];
+    if page.is_empty() { break }
+    offset += page.len();
+    pages.extend(page);
+  }
+  assert_eq![pages, all, "pages cover every result once"];
+  let opts = QueryOptions::new().offset(all.len()-10);
+  assert_eq![query(&mut db, &bbox, &opts)?, all[all.len()-10..].to_vec(),
+    "offset without a limit"];
+  assert_eq![query(&mut db, &bbox, &QueryOptions::new().limit(0))?.len(), 0,
+    "limit of 0"];
+  Ok(())
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)),
+opts: &QueryOptions) -> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  db.query_with(bbox, opts)?.collect()
+}
@@ -0,0 +1,66 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32),f32);
+type V = u32;
+
+#[test]
+fn query_multi() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert(((xmin,xmax),(ymin,ymax),time), r.read::<u32>())
+  }).collect();
+  db.batch(&inserts)?;
+  let bbox = ((-1.0,-1.0,0.0),(1.0,1.0,1000.0));
+  let deletes: Vec<Row<P,V>> = db.query(&bbox)?
+    .step_by(5)
+    .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+    .collect::<Result<_,Error>>()?;
+  db.batch(&deletes)?;
+
+  let bboxes: Vec<((f32,f32,f32),(f32,f32,f32))> = (0..16).map(|i| {
+    let x = (i % 4) as f32 * 0.5 - 1.0;
+    let y = (i / 4) as f32 * 0.5 - 1.0;
+    ((x,y,0.0),(x+0.5,y+0.5,500.0))
+  }).collect();
+  let mut results = db.query_multi(&bboxes)?;
+  results.sort_unstable_by(cmp);
+  let mut expected: Vec<(usize,P,V,Location)> = vec![];
+  for (i,bbox) in bboxes.iter().enumerate() {
+    for result in db.query(bbox)? {
+      let (p,v,loc) = result?;
+      expected.push((i,p,v,loc));
+    }
+  }
+  expected.sort_unstable_by(cmp);
+  assert![expected.len() > 0, "queries have results"];
+  assert_eq![results, expected, "query_multi matches separate queries"];
+  Ok(())
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,77 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,IoMode,disk_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_parallel() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let rows: Vec<Row<P,V>> = (0..7_300).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let storage = disk_storage(dir.path().to_path_buf(), IoMode::Buffered);
+  let mut db: DB<_,_,P,V> = Setup::new(storage).base_size(500).build()?;
+  for batch in rows.chunks(700) {
+    db.batch(batch)?;
+  }
+  let mut trees = 0;
+  for tree in db.trees.iter() {
+    if !tree.try_borrow_mut()?.is_empty()? { trees += 1 }
+  }
+  assert![trees > 2, "several trees to divide between the workers"];
+
+  // deletes from trees and from staging
+  let deletes: Vec<Row<P,V>> = db.query(&((-0.3,-0.3),(0.0,0.0)))?
+    .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+    .collect::<Result<Vec<_>,Error>>()?;
+  assert![!deletes.is_empty(), "expected deletes"];
+  db.batch(&deletes)?;
+
+  let bboxes = vec![
+    ((-1.0,-1.0),(1.0,1.0)),
+    ((-0.5,-0.4),(0.2,0.1)),
+    ((0.9,0.9),(0.95,0.99))
+  ];
+  for bbox in bboxes.iter() {
+    let expected = values(db.query(bbox)?)?;
+    for threads in [1,2,16].iter() {
+      assert_eq![values(db.query_parallel(bbox, *threads)?)?, expected,
+        "results for {:?} with {} threads", bbox, threads];
+    }
+  }
+
+  // dropping a partly read query stops the workers
+  let mut results = db.query_parallel(&bboxes[0], 4)?;
+  assert![results.next().is_some()];
+  drop(results);
+
+  // the workers reopen their views of the trees after the database changes
+  db.batch(&rows[..10])?;
+  db.flush_staging()?;
+  for bbox in bboxes.iter() {
+    let expected = values(db.query(bbox)?)?;
+    assert_eq![values(db.query_parallel(bbox, 4)?)?, expected,
+      "results for {:?} after a batch", bbox];
+  }
+  Ok(())
+}
+
+fn values<I> (results: I) -> Result<Vec<V>,Error>
+where I: Iterator<Item=Result<(P,V,Location),Error>> {
+  let mut values = results
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  values.sort_unstable();
+  Ok(values)
+}
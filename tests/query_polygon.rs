@@ -0,0 +1,101 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,QueryPolygon,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_polygon() -> Result<(),Error> {
+  check(false)
+}
+
+#[test]
+fn query_polygon_branch_bounds() -> Result<(),Error> {
+  check(true)
+}
+
+fn check (branch_bounds: bool) -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400)
+    .branch_bounds(branch_bounds)
+    .build()?;
+  let inserts: Vec<(P,V)> = (0..5_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>()*200.0-100.0;
+    let (w,h) = (r.read::<f32>()*3.0, r.read::<f32>()*3.0);
+    (((x,x+w),(y,y+h)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+
+  let polygons: Vec<Vec<(f64,f64)>> = vec![
+    // thin diagonal band
+    vec![(-90.0,-80.0),(80.0,90.0),(75.0,95.0),(-95.0,-75.0)],
+    vec![(0.0,0.0),(50.0,10.0),(20.0,60.0)],
+    vec![(-40.0,30.0)]
+  ];
+  for polygon in polygons.iter() {
+    let mut expected: Vec<V> = inserts.iter()
+      .filter(|(p,_)| overlaps(polygon, p))
+      .map(|(_,v)| *v)
+      .collect();
+    expected.sort_unstable();
+    let query: QueryPolygon<P> = QueryPolygon::new(polygon)?;
+    let mut results: Vec<V> = db.query_polygon(&query)?
+      .map(|r| r.map(|(_,v,_)| v))
+      .collect::<Result<Vec<V>,Error>>()?;
+    results.sort_unstable();
+    assert_eq![results, expected, "records in polygon {:?}", polygon];
+  }
+
+  // interior points do not change the hull
+  let mut square = vec![(-20.0,-20.0),(20.0,-20.0),(20.0,20.0),(-20.0,20.0)];
+  let mut expected = polygon_query(&mut db, &square)?;
+  square.extend(vec![(0.0,0.0),(5.0,-3.0)]);
+  let mut results = polygon_query(&mut db, &square)?;
+  expected.sort_unstable_by_key(|(_,v,_)| *v);
+  results.sort_unstable_by_key(|(_,v,_)| *v);
+  assert_eq![results, expected, "query of the convex hull"];
+  assert![QueryPolygon::<P>::new(&[]).is_err(), "empty polygon"];
+  Ok(())
+}
+
+fn polygon_query<U> (db: &mut DB<MemoryStore,U,P,V>, points: &[(f64,f64)])
+-> Result<Vec<(P,V,Location)>,Error>
+where U: (Fn(&str) -> Result<MemoryStore,Error>) {
+  let polygon: QueryPolygon<P> = QueryPolygon::new(points)?;
+  let results = db.query_polygon(&polygon)?
+    .collect::<Result<Vec<_>,Error>>()?;
+  Ok(results)
+}
+
+// separating axis test of the bounds of `p` against the convex `polygon`,
+// given in order
+fn overlaps (polygon: &[(f64,f64)], p: &P) -> bool {
+  let ((x0,x1),(y0,y1)) = *p;
+  let (x0,x1,y0,y1) = (x0 as f64, x1 as f64, y0 as f64, y1 as f64);
+  let corners = [(x0,y0),(x0,y1),(x1,y0),(x1,y1)];
+  let mut axes = vec![(1.0,0.0),(0.0,1.0)];
+  for (i,a) in polygon.iter().enumerate() {
+    let b = polygon[(i+1)%polygon.len()];
+    axes.push((a.1-b.1, b.0-a.0));
+  }
+  axes.iter().all(|axis| {
+    let dot = |q: &(f64,f64)| q.0*axis.0 + q.1*axis.1;
+    let plo = polygon.iter().map(dot).fold(f64::INFINITY, f64::min);
+    let phi = polygon.iter().map(dot).fold(f64::NEG_INFINITY, f64::max);
+    let clo = corners.iter().map(dot).fold(f64::INFINITY, f64::min);
+    let chi = corners.iter().map(dot).fold(f64::NEG_INFINITY, f64::max);
+    chi >= plo && clo <= phi
+  })
+}
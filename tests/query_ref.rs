@@ -0,0 +1,54 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = Vec<u8>;
+
+#[test]
+fn query_ref() -> Result<(),Error> {
+  for delete_bitmap in [false,true] {
+    let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+      .delete_bitmap(delete_bitmap)
+      .max_data_size(50)
+      .base_size(1_000)
+      .build()?;
+    let mut r = rand().seed([13,12]);
+    let rows: Vec<Row<P,V>> = (0..5_500u32).map(|i| {
+      let x: f32 = r.read::<f32>()*2.0-1.0;
+      let y: f32 = r.read::<f32>()*2.0-1.0;
+      let value = i.to_be_bytes().repeat(1 + (i%50) as usize);
+      Row::Insert(((x,x+0.01),(y,y+0.01)), value)
+    }).collect();
+    for batch in rows.chunks(1_100) {
+      db.batch(batch)?;
+    }
+    let deletes: Vec<Row<P,V>> = db.query(&((-0.2,-0.2),(0.2,0.2)))?
+      .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+      .collect::<Result<Vec<_>,Error>>()?;
+    db.batch(&deletes)?;
+
+    for bbox in [((-1.0,-1.0),(1.0,1.0)),((-0.5,-0.3),(0.1,0.4))].iter() {
+      let mut expected = db.query(bbox)?
+        .collect::<Result<Vec<(P,V,Location)>,Error>>()?;
+      expected.sort_unstable_by(|a,b| a.1.cmp(&b.1));
+      let mut results = vec![];
+      let mut iter = db.query_ref(bbox)?;
+      while let Some(result) = iter.next_ref() {
+        let (point,value,location) = result?;
+        results.push((point,value.clone(),location));
+      }
+      results.sort_unstable_by(|a,b| a.1.cmp(&b.1));
+      assert_eq![results.len(), expected.len(),
+        "result count for {:?} with delete_bitmap={}", bbox, delete_bitmap];
+      assert_eq![results, expected,
+        "same results as db.query() for {:?} with delete_bitmap={}",
+        bbox, delete_bitmap];
+    }
+  }
+  Ok(())
+}
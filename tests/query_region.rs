@@ -0,0 +1,55 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,QueryRegion,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32),u64);
+type V = u32;
+
+#[test]
+fn query_region() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(200)
+    .build()?;
+  let inserts: Vec<Row<P,V>> = (0..2_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2e6-1e6;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let t = r.read::<u64>() % 1_000;
+    let t = if i % 100 == 0 { (1<<60)+t } else { t };
+    Row::Insert(((x,x+0.5),(y,y+0.01),t), i)
+  }).collect();
+  for batch in inserts.chunks(300) {
+    db.batch(batch)?;
+  }
+
+  let (a,b,t0) = (-0.25,0.5,600);
+  let region: QueryRegion<P> = QueryRegion::new(
+    &((None,Some(a),Some(t0)),(None,Some(b),None))
+  )?;
+  let mut expected: Vec<V> = inserts.iter().filter_map(|row| match row {
+    Row::Insert(((_,_),(y0,y1),t),v) if *y1 >= a && *y0 <= b && *t >= t0 => {
+      Some(*v)
+    },
+    _ => None
+  }).collect();
+  expected.sort_unstable();
+  assert![expected.iter().any(|v| v % 100 == 0), "largest times included"];
+  let mut values = db.query_region(&region)?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  values.sort_unstable();
+  assert_eq![values, expected];
+
+  let all: QueryRegion<P> = QueryRegion::new(
+    &((None,None,None),(None,None,None))
+  )?;
+  assert_eq![db.query_region(&all)?.count(), inserts.len()];
+  let extent = db.extent(all.bounds())?.unwrap();
+  assert![(extent.0).2 < 1_000 && (extent.1).2 >= 1<<60,
+    "extent over every time: {:?}", extent];
+  Ok(())
+}
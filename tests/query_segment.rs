@@ -0,0 +1,74 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,SegmentQuery,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_segment() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let inserts: Vec<(P,V)> = (0..5_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>()*200.0-100.0;
+    let (w,h) = (r.read::<f32>()*3.0, r.read::<f32>()*3.0);
+    (((x,x+w),(y,y+h)), i)
+  }).collect();
+  let rows: Vec<Row<P,V>> = inserts.iter()
+    .map(|(p,v)| Row::Insert(*p,*v))
+    .collect();
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+
+  for _ in 0..50 {
+    let from = [r.read::<f64>()*200.0-100.0, r.read::<f64>()*200.0-100.0];
+    let to = [r.read::<f64>()*200.0-100.0, r.read::<f64>()*200.0-100.0];
+    let mut expected: Vec<(f64,V)> = inserts.iter()
+      .filter_map(|(p,v)| enter(p, &from, &to).map(|t| (t,*v)))
+      .collect();
+    expected.sort_by(|a,b| a.0.total_cmp(&b.0));
+    let results = db.query_segment(&from, &to)?;
+    assert_eq![results.len(), expected.len(), "every record on the segment"];
+    let mut prev = 0.0;
+    for (p,v,_) in results.iter() {
+      let t = enter(p, &from, &to).expect("result intersects segment");
+      assert![t >= prev, "results sorted along the segment"];
+      assert![expected.iter().any(|(_,w)| w == v), "result expected"];
+      prev = t;
+    }
+  }
+
+  let (p,v) = inserts[42];
+  let origin = [(p.0).0 as f64 - 10.0, (p.1).0 as f64];
+  let hits = db.query_ray(&origin, &[1.0,0.0], 10.0)?;
+  assert![hits.iter().any(|(_,w,_)| *w == v), "ray reaches the record"];
+  let misses = db.query_ray(&origin, &[-1.0,0.0], 5.0)?;
+  assert![misses.iter().all(|(_,w,_)| *w != v), "ray points away"];
+  assert![db.query_segment(&[0.0], &[1.0]).is_err(), "wrong dimension"];
+  assert![db.query_ray(&origin, &[0.0,0.0], 1.0).is_err(), "zero direction"];
+  Ok(())
+}
+
+fn enter (p: &P, from: &[f64;2], to: &[f64;2]) -> Option<f64> {
+  let (mut tmin, mut tmax) = (0.0f64, 1.0f64);
+  for (i,(a,b)) in from.iter().zip(to.iter()).enumerate() {
+    let (lo,hi) = p.span_at(i);
+    let d = b - a;
+    if d == 0.0 {
+      if *a < lo || *a > hi { return None }
+      continue;
+    }
+    let (t0,t1) = ((lo-a)/d, (hi-a)/d);
+    tmin = tmin.max(t0.min(t1));
+    tmax = tmax.min(t0.max(t1));
+    if tmin > tmax { return None }
+  }
+  Some(tmin)
+}
@@ -0,0 +1,56 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Sort};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32),f32);
+type V = u32;
+
+#[test]
+fn query_sorted() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert(((xmin,xmax),(ymin,ymax),time), r.read::<u32>())
+  }).collect();
+  db.batch(&inserts)?;
+
+  let bbox = ((-0.5,-0.5,0.0),(0.5,0.5,1000.0));
+  let count = db.query(&bbox)?.count();
+  let times: Vec<f32> = db.query_sorted(&bbox, 2, Sort::Ascending)?
+    .map(|(p,_,_)| p.2)
+    .collect();
+  assert![count > 0, "query has results"];
+  assert_eq![times.len(), count, "every result is returned"];
+  assert![times.windows(2).all(|w| w[0] <= w[1]), "ascending by time"];
+
+  let mut expected = times.clone();
+  expected.reverse();
+  let recent: Vec<f32> = db.query_sorted(&bbox, 2, Sort::Descending)?
+    .take(10)
+    .map(|(p,_,_)| p.2)
+    .collect();
+  assert_eq![recent, expected[0..10].to_vec(), "most recent 10"];
+
+  let xmax: Vec<f32> = db.query_sorted(&bbox, 0, Sort::Ascending)?
+    .map(|(p,_,_)| (p.0).1)
+    .collect();
+  assert![xmax.windows(2).all(|w| w[0] <= w[1]), "intervals by upper bound"];
+  Ok(())
+}
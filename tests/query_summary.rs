@@ -0,0 +1,76 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,QueryOptions};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn query_summary() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).max_data_size(200).base_size(1_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let size = 20_500;
+  let inserts: Vec<Row<P,V>> = (0..size).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  for batch in inserts.chunks(5_000) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut prev = 0;
+  for max_depth in [0,1,2].iter() {
+    let opts = QueryOptions::new().max_depth(*max_depth);
+    let summaries = db.query_summary(&bbox, &opts)?;
+    let count: u64 = summaries.iter().map(|s| s.count).sum();
+    assert_eq![count, size as u64, "every record summarized at depth {}",
+      max_depth];
+    assert![summaries.iter().all(|s| s.depth <= *max_depth),
+      "summaries no deeper than {}", max_depth];
+    assert![summaries.iter().all(|s| {
+      let ((xmin,ymin),(xmax,ymax)) = s.bounds;
+      -1.0 <= xmin && xmin <= xmax && xmax <= 1.0
+        && -1.0 <= ymin && ymin <= ymax && ymax <= 1.0
+    }), "summary bounds within the data"];
+    assert![summaries.len() >= prev, "finer summaries at greater depth"];
+    prev = summaries.len();
+  }
+  {
+    let leaves = db.query_summary(&bbox, &QueryOptions::new())?;
+    assert![leaves.len() >= prev, "data block summaries are the finest"];
+    let count: u64 = leaves.iter().map(|s| s.count).sum();
+    assert_eq![count, size as u64, "every record in data block summaries"];
+  }
+  {
+    let small = ((-0.2,0.1),(0.05,0.3));
+    let mut results = 0;
+    for result in db.query(&small)? {
+      result?;
+      results += 1;
+    }
+    let opts = QueryOptions::new().max_depth(1);
+    let count: u64 = db.query_summary(&small, &opts)?.iter()
+      .map(|s| s.count).sum();
+    assert![count >= results, "summaries cover every result"];
+    assert![count < size as u64, "summaries skip disjoint branches"];
+  }
+  let opts = QueryOptions::new().max_depth(1);
+  assert![db.query_with(&bbox, &opts).is_err(),
+    "query_with() rejects max_depth"];
+  Ok(())
+}
@@ -0,0 +1,84 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_storage;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_storage::RandomAccess;
+use std::cell::Cell;
+use std::io::Write;
+use std::rc::Rc;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+// memory store that counts calls to read()
+struct CountReads {
+  store: MemoryStore,
+  reads: Rc<Cell<usize>>
+}
+
+impl RandomAccess for CountReads {
+  type Error = Error;
+  fn write (&mut self, offset: u64, data: &[u8]) -> Result<(),Error> {
+    self.store.write(offset, data)
+  }
+  fn read (&mut self, offset: u64, length: u64) -> Result<Vec<u8>,Error> {
+    self.reads.set(self.reads.get()+1);
+    self.store.read(offset, length)
+  }
+  fn read_to_writer (&mut self, offset: u64, length: u64,
+  buf: &mut impl Write) -> Result<(),Error> {
+    self.store.read_to_writer(offset, length, buf)
+  }
+  fn del (&mut self, offset: u64, length: u64) -> Result<(),Error> {
+    self.store.del(offset, length)
+  }
+  fn truncate (&mut self, length: u64) -> Result<(),Error> {
+    self.store.truncate(length)
+  }
+  fn len (&self) -> Result<u64,Error> {
+    self.store.len()
+  }
+  fn is_empty (&mut self) -> Result<bool,Error> {
+    self.store.is_empty()
+  }
+  fn sync_all (&mut self) -> Result<(),Error> {
+    self.store.sync_all()
+  }
+}
+
+#[test]
+fn coalesced_branch_reads() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let rows: Vec<Row<P,V>> = (0..8_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut counts = vec![];
+  for prefetch in [false,true] {
+    let reads = Rc::new(Cell::new(0));
+    let mut db = open(prefetch, &reads)?;
+    db.batch(&rows)?;
+    reads.set(0);
+    let n = db.query(&bbox)?.collect::<Result<Vec<_>,Error>>()?.len();
+    assert_eq![n, rows.len(), "every record with prefetch={}", prefetch];
+    counts.push(reads.get());
+  }
+  assert![counts[1] < counts[0],
+    "prefetched sibling branches share reads: {:?}", counts];
+  Ok(())
+}
+
+fn open (prefetch: bool, reads: &Rc<Cell<usize>>) -> Result<DB<CountReads,
+impl Fn(&str) -> Result<CountReads,Error>,P,V>,Error> {
+  let storage = memory_storage();
+  let reads = Rc::clone(reads);
+  Setup::new(move |name: &str| {
+    Ok(CountReads { store: storage(name)?, reads: Rc::clone(&reads) })
+  }).branch_prefetch(prefetch).max_data_size(50).base_size(1_000).build()
+}
@@ -0,0 +1,64 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,PointOrd,reader::Reader};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::collections::HashSet;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn reader() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let open = |name: &str| -> Result<RandomAccessDisk,Error> {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  };
+  let mut db: DB<_,_,P,V> = Setup::new(open)
+    .base_size(1_000).max_data_size(100).build()?;
+  let mut r = rand().seed([13,12]);
+  for _ in 0..3 {
+    let inserts: Vec<Row<P,V>> = (0..2_500).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect();
+    db.batch(&inserts)?;
+  }
+
+  let mut reader: Reader<_,P,V> = Reader::open(open)?;
+  let mut scanned = 0;
+  for result in reader.scan()? {
+    result?;
+    scanned += 1;
+  }
+  assert![scanned > 0, "scan returns records"];
+
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let mut found = HashSet::new();
+  for result in reader.query(&bbox)? {
+    let (p,v,loc) = result?;
+    assert![p.overlaps(&bbox), "reader result overlaps bbox"];
+    assert![loc.0 > 0, "reader result is in a data block"];
+    found.insert(format!["{:?}",(p,v)]);
+  }
+  let mut expected = 0;
+  for result in db.query(&bbox)? {
+    let (p,v,loc) = result?;
+    if loc.0 == 0 { continue } // staging
+    expected += 1;
+    assert![found.contains(&format!["{:?}",(p,v)]),
+      "db result {:?} found by reader", (p,v)];
+  }
+  assert![expected > 0, "db results outside of staging"];
+  Ok(())
+}
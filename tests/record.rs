@@ -0,0 +1,90 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Record,Schema,Field,FieldType,
+  MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = (f32,f32);
+
+#[test]
+fn record() -> Result<(),Error> {
+  let schema = Schema::new()
+    .field("name", FieldType::String)
+    .field("height", FieldType::F32)
+    .field("count", FieldType::U64)
+    .field("photo", FieldType::Bytes);
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,Record>> = (0..2_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let photo = if i % 3 == 0 { Field::Null }
+      else { Field::Bytes(vec![(i % 256) as u8;i % 40]) };
+    Ok(Row::Insert((x,y), Record::new(&schema, &[
+      Field::String(format!["tree{}",i]),
+      Field::F32(r.read::<f32>()*30.0),
+      Field::U64(i as u64),
+      photo
+    ])?))
+  }).collect::<Result<Vec<_>,Error>>()?;
+  assert![Record::new(&schema, &[Field::U32(1)]).is_err(),
+    "wrong number of fields"];
+  assert![Record::new(&schema, &[Field::U32(1), Field::F32(1.0),
+    Field::U64(1), Field::Null]).is_err(), "wrong field type"];
+
+  let storage = memory_storage();
+  let mut db = open(storage.clone(), schema.clone())?;
+  for batch in inserts.chunks(250) {
+    db.batch(batch)?;
+  }
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let mut expected: Vec<(u64,String,Field)> = inserts.iter().map(|row| {
+    match row {
+      Row::Insert(_,v) => Ok(match (v.get(2)?,v.get(0)?) {
+        (Field::U64(n),Field::String(s)) => (n,s,v.get(3)?),
+        _ => panic!["unexpected fields"]
+      }),
+      _ => panic!["unexpected row"]
+    }
+  }).collect::<Result<Vec<_>,Error>>()?;
+  expected.sort_unstable_by_key(|x| x.0);
+  assert_eq![select(&mut db, &bbox)?, expected,
+    "selected fields match the inserted records"];
+
+  let mut db = open(storage.clone(), schema.clone())?;
+  assert_eq![db.schema(), Some(&schema), "schema kept after reopening"];
+  assert_eq![select(&mut db, &bbox)?, expected, "same fields after reopening"];
+  assert![db.query_select(&bbox, &["missing"]).is_err(), "unknown field"];
+  let other = schema.clone().field("extra", FieldType::Bool);
+  assert![open(storage, other).is_err(), "schema must match"];
+
+  let mut plain: DB<_,_,P,Record> = Setup::new(memory_storage()).build()?;
+  plain.batch(&inserts[0..10])?;
+  assert![plain.query_select(&bbox, &["name"]).is_err(),
+    "query_select requires a schema"];
+  Ok(())
+}
+
+fn open<U> (storage: U, schema: Schema)
+-> Result<DB<MemoryStore,U,P,Record>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage).base_size(100).schema(schema).build()
+}
+
+fn select<U> (db: &mut DB<MemoryStore,U,P,Record>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(u64,String,Field)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut results = vec![];
+  for result in db.query_select(bbox, &["count","name","photo"])? {
+    let (_,record,_) = result?;
+    assert_eq![record.len(), 3, "only the selected fields"];
+    match (record.get(0)?,record.get(1)?) {
+      (Field::U64(n),Field::String(s)) => results.push((n,s,record.get(2)?)),
+      x => panic!["unexpected fields {:?}", x]
+    }
+  }
+  results.sort_unstable_by_key(|x| x.0);
+  Ok(results)
+}
@@ -0,0 +1,105 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,RecoveryReport};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn recovery() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let expected = {
+    let mut db = open(dir.path(), false)?;
+    assert![db.recovery_report().is_clean(), "new database is clean"];
+    db.batch(&inserts)?;
+    let deletes: Vec<Row<P,V>> = query(&mut db, &bbox)?.iter().step_by(5)
+      .map(|(_,_,loc)| Row::Delete(*loc)).collect();
+    db.batch(&deletes)?;
+    query(&mut db, &bbox)?
+  };
+  assert_eq![expected.len(), 400, "staged deletes applied"];
+
+  // a crash in the middle of a batch leaves partial records behind
+  append(&dir.path().join("staging_inserts"), &[1,2,3,4,5])?;
+  append(&dir.path().join("staging_deletes"), &[0,0,0])?;
+  match open(dir.path(), true) {
+    Ok(_) => panic!["strict open accepted partial records"],
+    Err(err) => assert![err.to_string().contains("staging_inserts"),
+      "error names the damaged store: {}", err]
+  }
+  {
+    let mut db = open(dir.path(), false)?;
+    assert_eq![db.recovery_report(), &RecoveryReport {
+      insert_bytes_discarded: 5,
+      delete_bytes_discarded: 3,
+      inserts_recovered: 500,
+      deletes_recovered: 100
+    }, "partial records discarded"];
+    assert_eq![db.recovery_report().bytes_discarded(), 8];
+    assert_eq![query(&mut db, &bbox)?, expected, "complete records kept"];
+  }
+  {
+    let mut db = open(dir.path(), true)?;
+    assert![db.recovery_report().is_clean(), "repairs were written"];
+    assert_eq![db.recovery_report().inserts_recovered, 500];
+    assert_eq![query(&mut db, &bbox)?, expected, "same records after repair"];
+  }
+  Ok(())
+}
+
+fn append (file: &Path, bytes: &[u8]) -> Result<(),Error> {
+  OpenOptions::new().append(true).open(file)?.write_all(bytes)?;
+  Ok(())
+}
+
+fn open (dir: &Path, strict: bool) -> Result<DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let open_store: Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>
+    = Box::new(move |name: &str| {
+      let p = dir.join(name);
+      Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+    });
+  Setup::new(open_store).base_size(10_000).strict_open(strict).build()
+}
+
+fn query (db: &mut DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,
+bbox: &((f32,f32),(f32,f32))) -> Result<Vec<(P,V,Location)>,Error> {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,78 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn replace_region() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(300).build()?;
+  let inserts: Vec<Row<P,V>> = (0..3_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), 0)
+  }).collect();
+  for batch in inserts.chunks(400) {
+    db.batch(batch)?;
+  }
+  let all = ((-1.0,-1.0),(1.0,1.0));
+  let region = ((-0.2,-0.3),(0.4,0.1));
+  let inside = query(&mut db, &region)?.len();
+  let outside = query(&mut db, &all)?.len() - inside;
+  let replacements: Vec<Row<P,V>> = (0..100).map(|i| {
+    let x = -0.2 + (i as f32)*0.006;
+    let y = -0.3 + (i as f32)*0.004;
+    Row::Insert(((x,x),(y,y)), 1)
+  }).collect();
+  assert_eq![db.replace_region(&region, &replacements)?, inside,
+    "every record in the region deleted"];
+  let results = query(&mut db, &region)?;
+  assert_eq![results.len(), 100, "only replacements in the region"];
+  assert![results.iter().all(|(_,v,_)| *v == 1), "replacement values"];
+  assert_eq![query(&mut db, &all)?.len(), outside + 100,
+    "records outside the region kept"];
+  Ok(())
+}
+
+#[test]
+fn replace_staged_region() -> Result<(),Error> {
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(300).build()?;
+  // 250 staged rows, 50 of them in the region
+  let staged: Vec<Row<P,V>> = (0..250).map(|i| {
+    let x = if i % 5 == 0 { 0.1 } else { -0.8 };
+    let y = (i as f32)*0.001 - 0.2;
+    Row::Insert(((x,x),(y,y)), 0)
+  }).collect();
+  db.batch(&staged)?;
+  let region = ((0.0,-0.3),(0.2,0.1));
+  assert_eq![query(&mut db, &region)?.len(), 50];
+  // the replacements push staging past its threshold, which builds a tree
+  let replacements: Vec<Row<P,V>> = (0..100).map(|i| {
+    let x = 0.05 + (i as f32)*0.001;
+    Row::Insert(((x,x),(0.0,0.0)), 1)
+  }).collect();
+  assert_eq![db.replace_region(&region, &replacements)?, 50];
+  let results = query(&mut db, &region)?;
+  assert_eq![results.len(), 100, "only replacements in the region"];
+  assert![results.iter().all(|(_,v,_)| *v == 1), "replacement values"];
+  let all = ((-1.0,-1.0),(1.0,1.0));
+  assert_eq![query(&mut db, &all)?.len(), 300,
+    "200 staged records outside the region and 100 replacements"];
+  Ok(())
+}
+
+fn query<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  db.query(bbox)?.collect()
+}
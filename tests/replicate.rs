@@ -0,0 +1,93 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,StorageLog};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn replicate() -> Result<(),Error> {
+  let src_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let dst_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let sp = src_dir.path().to_path_buf();
+  let dp = dst_dir.path().to_path_buf();
+  let mut src: DB<_,_,P,V> = Setup::new(|name: &str| storage(sp.clone(), name))
+    .base_size(500)
+    .log(StorageLog::open(
+      storage(sp.clone(), "log_data")?,
+      storage(sp.clone(), "log_index")?
+    )?)
+    .build()?;
+  let mut dst: DB<_,_,P,V> = Setup::new(|name: &str| storage(dp.clone(), name))
+    .base_size(500)
+    .log(StorageLog::open(
+      storage(dp.clone(), "log_data")?,
+      storage(dp.clone(), "log_index")?
+    )?)
+    .build()?;
+
+  let mut r = rand().seed([13,12]);
+  for _ in 0..4 {
+    let batch: Vec<Row<P,V>> = (0..700).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect();
+    src.batch(&batch)?;
+  }
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let deletes: Vec<Row<P,V>> = query(&mut src, &bbox)?.iter().enumerate()
+    .filter(|(i,_)| i % 3 == 0)
+    .map(|(_,r)| Row::Delete(r.2))
+    .collect();
+  src.batch(&deletes)?;
+
+  let mut log = StorageLog::open(
+    storage(sp.clone(), "log_data")?,
+    storage(sp.clone(), "log_index")?
+  )?;
+  assert_eq![dst.replicate_from(&mut log)?, 5, "applied every batch"];
+  assert_eq![dst.replicate_from(&mut log)?, 0, "second replication is a no-op"];
+
+  let bbox = ((-0.5,-0.8),(0.3,-0.5));
+  let expected = query(&mut src, &bbox)?;
+  let results = query(&mut dst, &bbox)?;
+  assert_eq![results.len(), expected.len(), "same number of results"];
+  assert_eq![results, expected, "same results on the replica"];
+  Ok(())
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where S: random_access_storage::RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>) {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,60 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn root_hash() -> Result<(),Error> {
+  let a_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let b_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let ap = a_dir.path().to_path_buf();
+  let bp = b_dir.path().to_path_buf();
+  let mut a: DB<_,_,P,V> = Setup::new(|name: &str| storage(ap.clone(), name))
+    .base_size(500)
+    .build()?;
+  let mut b: DB<_,_,P,V> = Setup::new(|name: &str| storage(bp.clone(), name))
+    .base_size(500)
+    .history(true)
+    .build()?;
+  assert_eq![a.root_hash()?, b.root_hash()?, "empty databases match"];
+
+  let mut r = rand().seed([13,12]);
+  let mut hashes = vec![a.root_hash()?];
+  for _ in 0..3 {
+    let batch: Vec<Row<P,V>> = (0..700).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect();
+    a.batch(&batch)?;
+    b.batch(&batch)?;
+    let hash = a.root_hash()?;
+    assert_eq![hash, b.root_hash()?, "hashes match after identical batches"];
+    assert![!hashes.contains(&hash), "hash changes after each batch"];
+    hashes.push(hash);
+  }
+
+  let batch = vec![Row::Insert(((0.0,0.1),(0.0,0.1)), 5)];
+  a.batch(&batch)?;
+  assert_ne![a.root_hash()?, b.root_hash()?, "hashes differ after divergence"];
+  b.batch(&batch)?;
+  assert_eq![a.root_hash()?, b.root_hash()?, "hashes match after catching up"];
+  Ok(())
+}
@@ -0,0 +1,82 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,MetaResult};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn row_meta() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let p = dir.path().to_path_buf();
+  let setup = || Setup::new({
+    let p = p.clone();
+    move |name: &str| storage(p.clone(), name)
+  })
+    .base_size(500)
+    .timestamps(true);
+  let mut db: DB<_,_,P,V> = setup().build()?;
+
+  let mut r = rand().seed([13,12]);
+  for i in 0..5 {
+    let batch: Vec<Row<P,V>> = (0..300).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      Row::Insert(((xmin,xmax),(ymin,ymax)), i)
+    }).collect();
+    db.batch(&batch)?;
+  }
+
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let results = db.query_meta(&bbox)?
+    .collect::<Result<Vec<MetaResult<P,V>>,Error>>()?;
+  assert_eq![results.len(), 1500, "every record returned"];
+  let (mut staged, mut stored) = (0, 0);
+  for (_,v,loc,meta) in results.iter() {
+    let meta = meta.expect("metadata for every record");
+    assert![meta.time.is_some(), "timestamp recorded"];
+    if loc.0 == 0 {
+      assert_eq![meta.seq, (*v as u64)+1, "staged row from its own batch"];
+      staged += 1;
+    } else {
+      assert![meta.seq >= (*v as u64)+1, "block written at or after insert"];
+      assert![meta.seq <= 5];
+      stored += 1;
+    }
+  }
+  assert![staged > 0 && stored > 0, "records in staging and in trees"];
+
+  let mut reopened: DB<_,_,P,V> = setup().build()?;
+  let mut reloaded = reopened.query_meta(&bbox)?
+    .collect::<Result<Vec<MetaResult<P,V>>,Error>>()?;
+  let mut expected = results.clone();
+  reloaded.sort_by_key(|r| r.2);
+  expected.sort_by_key(|r| r.2);
+  assert_eq![
+    reloaded.iter().map(|r| r.3).collect::<Vec<_>>(),
+    expected.iter().map(|r| r.3).collect::<Vec<_>>(),
+    "metadata persists"
+  ];
+
+  let mut plain: DB<_,_,P,V> = Setup::new({
+    let p = dir.path().join("plain");
+    move |name: &str| storage(p.clone(), name)
+  }).build()?;
+  assert![plain.query_meta(&bbox).is_err(), "row_meta not enabled"];
+  Ok(())
+}
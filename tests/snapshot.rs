@@ -0,0 +1,111 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+use std::path::PathBuf;
+use std::cmp::Ordering;
+
+type P = ((f32,f32),(f32,f32),f32);
+type V = u32;
+
+fn storage (dir: PathBuf, name: &str) -> Result<RandomAccessDisk,Error> {
+  Ok(RandomAccessDisk::builder(dir.join(name)).auto_sync(false).build()?)
+}
+
+#[test]
+fn export_import() -> Result<(),Error> {
+  let src_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let dst_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let bad_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let sp = src_dir.path().to_path_buf();
+  let dp = dst_dir.path().to_path_buf();
+  let bp = bad_dir.path().to_path_buf();
+  let mut src: DB<_,_,P,V> = Setup::new(|name: &str| storage(sp.clone(), name))
+    .base_size(1_000)
+    .build()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..3_500).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    let time: f32 = r.read::<f32>()*1000.0;
+    Row::Insert(((xmin,xmax),(ymin,ymax),time), r.read::<u32>())
+  }).collect();
+  src.batch(&inserts)?;
+
+  let mut archive: Vec<u8> = vec![];
+  src.export(&mut archive)?;
+
+  let mut dst: DB<_,_,P,V> = DB::import(
+    Setup::new(|name: &str| storage(dp.clone(), name)).base_size(1_000),
+    &mut archive.as_slice()
+  )?;
+  let bbox = ((-0.5,-0.8,0.0),(0.3,-0.5,500.0));
+  let expected = query(&mut src, &bbox)?;
+  assert![expected.len() > 0, "query has results"];
+  assert_eq![query(&mut dst, &bbox)?, expected, "imported results match"];
+
+  let i = archive.len()-100;
+  archive[i] = archive[i].wrapping_add(1);
+  let result: Result<DB<_,_,P,V>,Error> = DB::import(
+    Setup::new(|name: &str| storage(bp.clone(), name)),
+    &mut archive.as_slice()
+  );
+  assert![result.is_err(), "corrupt archive is rejected"];
+  for name in ["meta","data","tree0"].iter() {
+    assert_eq![std::fs::metadata(bp.join(name)).map(|m| m.len()).unwrap_or(0),
+      0, "store {} left untouched by the corrupt archive", name];
+  }
+
+  let result: Result<DB<_,_,P,V>,Error> = DB::import(
+    Setup::new(|name: &str| storage(dp.clone(), name)),
+    &mut archive.as_slice()
+  );
+  assert![result.is_err(), "destination that holds a database is rejected"];
+
+  // an archive with a store named "../escape"
+  let mut evil: Vec<u8> = b"EYROSARC".to_vec();
+  evil.extend(&1u16.to_be_bytes());
+  evil.extend(&1u32.to_be_bytes());
+  evil.extend(&9u16.to_be_bytes());
+  evil.extend(b"../escape");
+  evil.extend(&0u64.to_be_bytes());
+  evil.extend(&0u32.to_be_bytes());
+  let nested = bp.join("nested");
+  std::fs::create_dir(&nested)?;
+  let result: Result<DB<_,_,P,V>,Error> = DB::import(
+    Setup::new(|name: &str| storage(nested.clone(), name)),
+    &mut evil.as_slice()
+  );
+  assert![result.is_err(), "store names outside the database are rejected"];
+  assert![!bp.join("escape").exists() && !bp.join("import_../escape").exists(),
+    "nothing written outside the destination"];
+  Ok(())
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32,f32),(f32,f32,f32)))
+-> Result<Vec<(P,V,Location)>,Error>
+where S: random_access_storage::RandomAccess<Error=Error>,
+U: (Fn(&str) -> Result<S,Error>) {
+  let mut results = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results)
+}
+
+fn cmp<T> (a: &T, b: &T) -> Ordering where T: PartialOrd {
+  match a.partial_cmp(b) {
+    Some(o) => o,
+    None => panic!["comparison failed"]
+  }
+}
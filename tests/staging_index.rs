@@ -0,0 +1,73 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,PointOrd,Location};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn staging_index() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut db: DB<_,_,P,V> = Setup::new(|name: &str| {
+    let p = dir.path().join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  }).base_size(100_000).build()?;
+  let mut r = rand().seed([13,12]);
+  let mut expected: Vec<(P,V)> = vec![];
+  let bboxes = vec![
+    ((-0.5,-0.8),(0.3,-0.1)),
+    ((0.1,0.1),(0.15,0.12)),
+    ((-1.0,-1.0),(1.0,1.0))
+  ];
+  for size in [10,5_000,3,1_200,8_000].iter() {
+    let rows: Vec<(P,V)> = (0..*size).map(|_| {
+      let xmin: f32 = r.read::<f32>()*2.0-1.0;
+      let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+      let ymin: f32 = r.read::<f32>()*2.0-1.0;
+      let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+      (((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+    }).collect();
+    let batch: Vec<Row<P,V>> = rows.iter()
+      .map(|(p,v)| Row::Insert(*p,*v)).collect();
+    db.batch(&batch)?;
+    expected.extend(rows);
+    for bbox in bboxes.iter() {
+      check(&mut db, &expected, bbox)?;
+    }
+  }
+  assert_eq![db.trees.len(), 0, "every row is staged"];
+
+  let deletes: Vec<(P,V,Location)> = db.query(&bboxes[0])?
+    .collect::<Result<Vec<_>,Error>>()?.into_iter().step_by(2).collect();
+  let batch: Vec<Row<P,V>> = deletes.iter()
+    .map(|(_,_,loc)| Row::Delete(*loc)).collect();
+  db.batch(&batch)?;
+  expected.retain(|(p,v)| !deletes.iter().any(|(dp,dv,_)| dp == p && dv == v));
+  for bbox in bboxes.iter() {
+    check(&mut db, &expected, bbox)?;
+  }
+  Ok(())
+}
+
+fn check<S,U> (db: &mut DB<S,U,P,V>, rows: &[(P,V)],
+bbox: &<P as PointOrd>::Bounds) -> Result<(),Error> where
+S: random_access_storage::RandomAccess<Error=Error>,
+U: Fn(&str) -> Result<S,Error> {
+  let cmp = |a: &(P,V), b: &(P,V)| a.partial_cmp(b).unwrap();
+  let mut results = db.query(bbox)?.map(|r| r.map(|(p,v,_)| (p,v)))
+    .collect::<Result<Vec<(P,V)>,Error>>()?;
+  let mut expected: Vec<(P,V)> = rows.iter()
+    .filter(|(p,_)| p.overlaps(bbox)).cloned().collect();
+  results.sort_by(cmp);
+  expected.sort_by(cmp);
+  assert_eq![results, expected];
+  Ok(())
+}
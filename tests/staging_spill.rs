@@ -0,0 +1,93 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,PointOrd};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+type Storage = Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>;
+
+fn open (dir: &Path) -> Result<DB<RandomAccessDisk,Storage,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let storage: Storage = Box::new(move |name: &str| {
+    let p = dir.join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  });
+  Setup::new(storage)
+    .base_size(5_000)
+    .staging_spill(2_000)
+    .build()
+}
+
+fn check (db: &mut DB<RandomAccessDisk,Storage,P,V>, expected: &[(P,V)])
+-> Result<(),Error> {
+  let bbox = ((-0.5,-0.8),(0.3,-0.1));
+  let cmp = |a: &(P,V), b: &(P,V)| a.partial_cmp(b).unwrap();
+  let mut results = db.query(&bbox)?.map(|r| r.map(|(p,v,_)| (p,v)))
+    .collect::<Result<Vec<(P,V)>,Error>>()?;
+  let mut expected: Vec<(P,V)> = expected.iter()
+    .filter(|(p,_)| p.overlaps(&bbox)).cloned().collect();
+  results.sort_by(cmp);
+  expected.sort_by(cmp);
+  assert![!expected.is_empty(), "expected results"];
+  assert_eq![results, expected];
+  Ok(())
+}
+
+#[test]
+fn staging_spill() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let batches: Vec<Vec<(P,V)>> = (0..13).map(|i| {
+    (0..if i < 12 { 400 } else { 2_000 }).map(|_| {
+      let x: f32 = r.read::<f32>()*2.0-1.0;
+      let y: f32 = r.read::<f32>()*2.0-1.0;
+      (((x,x+0.01),(y,y+0.01)), r.read::<u32>())
+    }).collect()
+  }).collect();
+  let mut expected: Vec<(P,V)> = vec![];
+  let mut db = open(dir.path())?;
+  for batch in batches[..12].iter() {
+    let rows: Vec<Row<P,V>> = batch.iter()
+      .map(|(p,v)| Row::Insert(*p,*v)).collect();
+    db.batch(&rows)?;
+    expected.extend_from_slice(batch);
+  }
+  assert![db.staging.spilled() > 0, "inserts were spilled"];
+  assert_eq![db.staging.inserts_len()?, 4_800];
+  check(&mut db, &expected)?;
+
+  // delete some spilled rows by location
+  let bbox = ((-1.0,-1.0),(0.0,0.0));
+  let deletes: Vec<(P,V,eyros::Location)> = db.query(&bbox)?
+    .collect::<Result<Vec<_>,Error>>()?.into_iter().step_by(3).collect();
+  assert![!deletes.is_empty(), "rows to delete"];
+  let rows: Vec<Row<P,V>> = deletes.iter()
+    .map(|(_,_,loc)| Row::Delete(*loc)).collect();
+  db.batch(&rows)?;
+  expected.retain(|(p,v)| !deletes.iter().any(|(dp,dv,_)| dp == p && dv == v));
+  check(&mut db, &expected)?;
+
+  // reopen with the runs on disk
+  drop(db);
+  let mut db = open(dir.path())?;
+  check(&mut db, &expected)?;
+
+  // going over the base size merges the runs into a tree
+  let rows: Vec<Row<P,V>> = batches[12].iter()
+    .map(|(p,v)| Row::Insert(*p,*v)).collect();
+  db.batch(&rows)?;
+  expected.extend_from_slice(&batches[12]);
+  assert![db.staging.inserts_len()? < 5_000, "runs were merged"];
+  assert![db.trees.len() > 0, "tree was built"];
+  check(&mut db, &expected)?;
+  Ok(())
+}
@@ -0,0 +1,151 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn staging_threshold_rows_small() -> Result<(),Error> {
+  let rows = rows(1_000);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .staging_threshold_rows(100)
+    .base_size(1_000)
+    .build()?;
+  db.batch(&rows[0..80])?;
+  assert_eq![db.staging.inserts_len()?, 80, "under the threshold"];
+  db.batch(&rows[80..150])?;
+  assert_eq![db.staging.inserts_len()?, 0, "flushed past the threshold"];
+  for batch in rows[150..].chunks(70) {
+    db.batch(batch)?;
+    assert![db.staging.inserts_len()? <= 100, "staging kept small"];
+  }
+  assert_eq![values(&mut db)?, (0..1_000).collect::<Vec<V>>()];
+  Ok(())
+}
+
+#[test]
+fn staging_threshold_rows_large() -> Result<(),Error> {
+  let rows = rows(4_500);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .staging_threshold_rows(4_000)
+    .base_size(1_000)
+    .build()?;
+  for batch in rows[0..3_000].chunks(1_000) {
+    db.batch(batch)?;
+  }
+  assert_eq![db.staging.inserts_len()?, 3_000, "staged past base_size"];
+  assert_eq![trees(&mut db)?, 0, "no trees built yet"];
+  db.batch(&rows[3_000..])?;
+  assert_eq![db.staging.inserts_len()?, 500, "remainder of whole trees"];
+  assert_eq![values(&mut db)?, (0..4_500).collect::<Vec<V>>()];
+  Ok(())
+}
+
+#[test]
+fn staging_threshold_deletes() -> Result<(),Error> {
+  let rows = rows(200);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .staging_threshold_rows(100)
+    .base_size(1_000)
+    .build()?;
+  db.batch(&rows[0..80])?;
+  // staged deletes before the batch that flushes and in the same batch
+  let mut deletes = staged_deletes(&mut db, &((-1.0,-1.0),(0.0,1.0)))?;
+  db.batch(&deletes)?;
+  let later = staged_deletes(&mut db, &((0.0,-1.0),(1.0,0.0)))?;
+  let n = deletes.len() + later.len();
+  deletes = later;
+  deletes.extend_from_slice(&rows[80..150]);
+  db.batch(&deletes)?;
+  assert_eq![db.staging.len()?, 0, "flushed past the threshold"];
+  assert_eq![trees(&mut db)?, 1];
+  let values = values(&mut db)?;
+  assert_eq![values.len(), 150-n, "deleted staged rows not built"];
+  Ok(())
+}
+
+#[test]
+fn staging_threshold_bytes() -> Result<(),Error> {
+  let rows = rows(500);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .staging_threshold_bytes(1_000)
+    .base_size(1_000)
+    .build()?;
+  for batch in rows.chunks(10) {
+    db.batch(batch)?;
+    assert![db.staging.bytes()? <= 1_000, "staging bytes kept under the limit"];
+  }
+  assert![trees(&mut db)? > 0, "flushed before base_size rows"];
+  assert_eq![values(&mut db)?, (0..500).collect::<Vec<V>>()];
+  Ok(())
+}
+
+#[test]
+fn flush_staging() -> Result<(),Error> {
+  let rows = rows(300);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(1_000)
+    .build()?;
+  db.flush_staging()?;
+  db.batch(&rows)?;
+  let deletes: Vec<Row<P,V>> = db.query(&((-1.0,-1.0),(0.0,0.0)))?
+    .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+    .collect::<Result<Vec<_>,Error>>()?;
+  db.batch(&deletes)?;
+  let expected = values(&mut db)?;
+  assert_eq![expected.len(), rows.len()-deletes.len()];
+  assert_eq![trees(&mut db)?, 0];
+  db.flush_staging()?;
+  assert_eq![db.staging.len()?, 0, "nothing left in staging"];
+  assert_eq![trees(&mut db)?, 1, "staged rows built into a tree"];
+  assert_eq![values(&mut db)?, expected, "same results after flushing"];
+
+  // locations from the tree delete records after the flush
+  let locs: Vec<Location> = db.query(&((-1.0,-1.0),(1.0,1.0)))?
+    .map(|r| r.map(|(_,_,loc)| loc))
+    .collect::<Result<Vec<_>,Error>>()?;
+  assert![locs.iter().all(|loc| loc.0 > 0), "records moved out of staging"];
+  db.batch(&[Row::Delete(locs[0])])?;
+  assert_eq![values(&mut db)?.len(), expected.len()-1];
+  Ok(())
+}
+
+fn rows (n: usize) -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..n).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i as V)
+  }).collect()
+}
+
+fn staged_deletes<U> (db: &mut DB<MemoryStore,U,P,V>,
+bbox: &((f32,f32),(f32,f32))) -> Result<Vec<Row<P,V>>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  db.query(bbox)?
+    .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+    .collect()
+}
+
+fn trees<U> (db: &mut DB<MemoryStore,U,P,V>) -> Result<usize,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut count = 0;
+  for tree in db.trees.iter() {
+    if !tree.try_borrow_mut()?.is_empty()? { count += 1 }
+  }
+  Ok(count)
+}
+
+fn values<U> (db: &mut DB<MemoryStore,U,P,V>) -> Result<Vec<V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let mut values = db.query(&((-1.0,-1.0),(1.0,1.0)))?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect::<Result<Vec<V>,Error>>()?;
+  values.sort_unstable();
+  Ok(values)
+}
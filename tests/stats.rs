@@ -0,0 +1,57 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage,Leveled};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn stats() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..8_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let mut tiered: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(500).build()?;
+  let mut leveled: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(500).merge_policy(Leveled).build()?;
+  for batch in inserts.chunks(600) {
+    tiered.batch(batch)?;
+    leveled.batch(batch)?;
+  }
+  let t = tiered.stats()?;
+  let l = leveled.stats()?;
+  let logical = (8_000*(16+4)) as u64;
+  assert_eq![t.logical_bytes, logical, "logical bytes of the inserts"];
+  assert_eq![t.live_bytes(), logical, "every inserted byte is live"];
+  assert_eq![l.live_bytes(), logical, "every inserted byte is live"];
+  let records: u64 = t.trees.iter().map(|t| t.records).sum();
+  assert_eq![records + (t.staging_live_bytes/20), 8_000,
+    "every record counted once"];
+  assert![t.write_amplification() > 1.0, "records are rewritten by merges"];
+  assert![l.write_amplification() > t.write_amplification(),
+    "leveled rewrites more than size-tiered"];
+  assert![t.space_amplification() >= 1.0, "storage holds every live byte"];
+
+  let mut deletes: Vec<Row<P,V>> = vec![];
+  for result in tiered.query(&((-1.0,-1.0),(0.0,1.0)))? {
+    let (_,_,loc): (P,V,Location) = result?;
+    deletes.push(Row::Delete(loc));
+  }
+  let before = tiered.stats()?;
+  tiered.batch(&deletes)?;
+  let after = tiered.stats()?;
+  assert_eq![after.live_bytes(), before.live_bytes() - 20*deletes.len() as u64,
+    "deleted records are no longer live"];
+  assert![after.space_amplification() > before.space_amplification(),
+    "deletes raise space amplification"];
+  Ok(())
+}
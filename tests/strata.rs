@@ -0,0 +1,62 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{Setup,Row,Strata};
+use failure::Error;
+use random_access_disk::RandomAccessDisk;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+type B = ((f32,f32),(f32,f32));
+
+#[test]
+fn strata() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let dp = dir.path().to_path_buf();
+  let setup = Setup::new(move |name: &str| {
+    Ok(RandomAccessDisk::builder(dp.join(name)).auto_sync(false).build()?)
+  }).base_size(500);
+  let mut db: Strata<_,P,V> = Strata::open(setup, 2,
+    |p: &P| if (p.0).1-(p.0).0 > 0.5 { 1 } else { 0 },
+    |bbox: &B| if (bbox.1).0-(bbox.0).0 > 0.5 { 1 } else { 0 }
+  )?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..2_000).map(|i| {
+    let size = if i % 10 == 0 { 1.0 } else { 0.01 };
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((xmin,xmin+size),(ymin,ymin+size)), i)
+  }).collect();
+  db.batch(&inserts)?;
+  assert![dir.path().join("tier1_staging_inserts").exists(), "tier 1 stores"];
+
+  let large = ((-1.0,-1.0),(1.0,1.0));
+  let mut results = vec![];
+  for result in db.query(&large)? {
+    results.push(result?);
+  }
+  assert_eq![results.len(), 2_000, "large query reads every tier"];
+  for (p,v,(k,_)) in results.iter() {
+    assert_eq![*k, if v % 10 == 0 { 1 } else { 0 }, "tier for {:?}", p];
+  }
+
+  let small = ((-0.1,-0.1),(0.1,0.1));
+  let mut results = vec![];
+  for result in db.query(&small)? {
+    results.push(result?);
+  }
+  assert![results.len() > 0, "small query has results"];
+  assert![results.iter().all(|(_,_,(k,_))| *k == 0), "small query skips tier 1"];
+
+  let deletes: Vec<_> = db.query(&large)?
+    .map(|r| r.map(|(_,_,loc)| loc))
+    .collect::<Result<_,Error>>()?;
+  db.delete(&deletes)?;
+  assert_eq![db.query(&large)?.count(), 0, "every record deleted"];
+  Ok(())
+}
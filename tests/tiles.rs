@@ -0,0 +1,98 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Tile,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = (f32,f32);
+type V = u32;
+
+#[test]
+fn tiles() -> Result<(),Error> {
+  let world = Tile::new(0,0,0).bbox(0.0);
+  assert_eq![world, ((-180.0,-90.0),(180.0,90.0)), "one tile at zoom 0"];
+  let t = Tile::new(3,5,2);
+  assert_eq![Tile::from_tms(3,5,t.tms_y()), t, "tms rows round-trip"];
+  let ((w,s),(e,n)) = t.bbox(0.0);
+  assert_eq![Tile::containing(((w+e)/2.0) as f64, ((s+n)/2.0) as f64, 3), t,
+    "center of a tile is in the tile"];
+
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|i| {
+    let lon: f32 = r.read::<f32>()*360.0-180.0;
+    let lat: f32 = r.read::<f32>()*160.0-80.0;
+    Row::Insert((lon,lat), i)
+  }).collect();
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage())
+    .base_size(500)
+    .build()?;
+  for batch in inserts.chunks(1_000) {
+    db.batch(batch)?;
+  }
+
+  let z = 2;
+  let tiles: Vec<Tile> = (0..4).flat_map(|x| {
+    (0..4).map(move |y| Tile::new(z,x,y))
+  }).collect();
+  let groups = db.query_tiles(&tiles, 0.0)?;
+  assert_eq![groups.len(), tiles.len(), "a group per tile"];
+  let mut total = 0;
+  for (tile,results) in groups.iter() {
+    for (p,_,_) in results.iter() {
+      let ((w,s),(e,n)) = tile.bbox(0.0);
+      assert![w <= p.0 && p.0 <= e && s <= p.1 && p.1 <= n,
+        "{:?} in {:?}", p, tile];
+    }
+    total += results.len();
+  }
+  assert![total >= inserts.len(), "every point is in a tile"];
+
+  let buffered = db.query_tiles(&tiles[0..1], 1.0/16.0)?;
+  assert![buffered[0].1.len() > groups[0].1.len(),
+    "buffer adds records around the tile"];
+  assert![db.query_tiles(&tiles[0..1], -0.6).is_err(), "tile buffer too small"];
+  Ok(())
+}
+
+#[test]
+fn materialize_tiles() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|i| {
+    let lon: f32 = r.read::<f32>()*360.0-180.0;
+    let lat: f32 = r.read::<f32>()*160.0-80.0;
+    Row::Insert((lon,lat), i)
+  }).collect();
+  let mut db: DB<_,_,P,V> = Setup::new(memory_storage())
+    .base_size(500)
+    .build()?;
+  for batch in inserts.chunks(1_000) {
+    db.batch(batch)?;
+  }
+
+  let bbox = ((-40.0,-30.0),(60.0,50.0));
+  let buffer = 1.0/16.0;
+  let mut tiles = vec![];
+  let count = db.materialize_tiles(&bbox, 1..=4, buffer, |tile,rows| {
+    let mut values: Vec<V> = rows.iter().map(|(_,v,_)| *v).collect();
+    values.sort_unstable();
+    tiles.push((tile,values));
+    Ok(())
+  })?;
+  assert_eq![count, tiles.len(), "count of emitted tiles"];
+  assert![tiles.iter().any(|(t,_)| t.z == 1), "lowest zoom emitted"];
+  assert![tiles.iter().any(|(t,_)| t.z == 4), "highest zoom emitted"];
+  for (tile,values) in tiles.iter() {
+    assert![tile.z >= 1 && tile.z <= 4, "zoom in range"];
+    assert![!values.is_empty(), "empty tiles are skipped"];
+    let mut expected: Vec<V> = db.query_tiles(&[*tile], buffer)?[0].1.iter()
+      .map(|(_,v,_)| *v)
+      .collect();
+    expected.sort_unstable();
+    assert_eq![values, &expected, "same records as query_tiles for {:?}", tile];
+  }
+  assert![db.materialize_tiles(&bbox, 1..=2, -0.1, |_,_| Ok(())).is_err(),
+    "negative buffer"];
+  Ok(())
+}
@@ -0,0 +1,69 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Trajectory,SegmentPoint,Segment,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+#[test]
+fn trajectory() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let tracks: Vec<Trajectory<u32>> = (0..50).map(|i| {
+    let mut track = Trajectory::new(i as u64, i*10);
+    let (mut x, mut y) = (r.read::<f32>()*100.0, r.read::<f32>()*100.0);
+    for j in 0..40 {
+      track.push(x, y, (j*60) as f64);
+      x += r.read::<f32>()*2.0-1.0;
+      y += r.read::<f32>()*2.0-1.0;
+    }
+    track
+  }).collect();
+  let mut db: DB<_,_,SegmentPoint,Segment<u32>> =
+    DB::open(memory_storage())?;
+  for track in tracks.iter() {
+    let rows = track.segments()?;
+    assert_eq![rows.len(), 39, "a segment between each pair of positions"];
+    db.batch(&rows)?;
+  }
+
+  let everything = ((-1e3,-1e3,0.0),(1e3,1e3,1e6));
+  let mut results = db.query_trajectories(&everything)?;
+  results.sort_unstable_by_key(|t| t.id);
+  assert_eq![results, tracks, "whole tracks reassembled"];
+
+  let bbox = ((20.0,20.0,300.0),(60.0,60.0,900.0));
+  let results = db.query_trajectories(&bbox)?;
+  assert![!results.is_empty(), "query has results"];
+  for piece in results.iter() {
+    let track = &tracks[piece.id as usize];
+    assert_eq![piece.value, track.value, "value of track"];
+    let start = track.points.iter().position(|p| *p == piece.points[0])
+      .expect("piece starts at a position of the track");
+    assert_eq![&track.points[start..start+piece.points.len()],
+      &piece.points[..], "piece is a contiguous part of the track"];
+    for w in piece.points.windows(2) {
+      let ((x0,y0,t0),(x1,y1,t1)) = (w[0],w[1]);
+      assert![x0.min(x1) <= 60.0 && 20.0 <= x0.max(x1)
+        && y0.min(y1) <= 60.0 && 20.0 <= y0.max(y1)
+        && t0 <= 900.0 && 300.0 <= t1, "segment intersects bbox"];
+    }
+  }
+
+  let mut parked = Trajectory::new(100, 7);
+  parked.push(5.0, 5.0, 10.0);
+  let rows = parked.segments()?;
+  assert_eq![rows.len(), 1, "single position stored as a segment"];
+  db.batch(&rows)?;
+  let results = db.query_trajectories(&((4.0,4.0,0.0),(6.0,6.0,20.0)))?;
+  assert_eq![results.into_iter().filter(|t| t.id == 100).collect::<Vec<_>>(),
+    vec![parked], "single position reassembled"];
+
+  let mut backwards = Trajectory::new(101, 0);
+  backwards.push(0.0, 0.0, 10.0);
+  backwards.push(1.0, 1.0, 5.0);
+  assert![backwards.segments().is_err(), "decreasing times rejected"];
+  assert![Trajectory::new(102, 0u32).segments()?.is_empty(),
+    "empty track has no segments"];
+  Ok(())
+}
@@ -0,0 +1,74 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn tree_cursor() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let inserts: Vec<Row<P,V>> = (0..5_000).map(|i| {
+    let x = r.read::<f32>()*200.0-100.0;
+    let y = r.read::<f32>()*200.0-100.0;
+    let (w,h) = (r.read::<f32>()*3.0, r.read::<f32>()*3.0);
+    Row::Insert(((x,x+w),(y,y+h)), i)
+  }).collect();
+  for batch in inserts.chunks(1_200) {
+    db.batch(batch)?;
+  }
+  let mut deletes = vec![];
+  for result in db.query(&((-5.0,-5.0),(5.0,5.0)))? {
+    let (_,_,loc) = result?;
+    if loc.0 > 0 { deletes.push(Row::Delete(loc)) }
+  }
+  assert![!deletes.is_empty(), "records to delete from the trees"];
+  db.batch(&deletes)?;
+
+  let bbox = ((-40.0,-30.0),(20.0,35.0));
+  let mut expected: Vec<(V,Location)> = vec![];
+  for result in db.query(&bbox)? {
+    let (_,v,loc) = result?;
+    if loc.0 > 0 { expected.push((v,loc)) }
+  }
+  expected.sort_unstable();
+
+  // depth-first traversal with the same pruning as db.query()
+  let mut results = vec![];
+  let mut stack = db.root_cursors()?;
+  assert![!stack.is_empty(), "trees to traverse"];
+  while let Some(cursor) = stack.pop() {
+    match cursor.block()? {
+      Some(records) => {
+        assert![cursor.is_block(), "records from a data block"];
+        let bounds = cursor.bounds()?;
+        for (p,v,loc) in records {
+          let ((x0,x1),(y0,y1)) = p;
+          if let Some(((bx0,by0),(bx1,by1))) = bounds {
+            assert![bx0 <= x0 && x1 <= bx1 && by0 <= y0 && y1 <= by1,
+              "record within block bounds"];
+          }
+          let ((qx0,qy0),(qx1,qy1)) = bbox;
+          if x0 <= qx1 && qx0 <= x1 && y0 <= qy1 && qy0 <= y1 {
+            results.push((v,loc));
+          }
+        }
+      },
+      None => {
+        for child in cursor.children(&bbox)? {
+          assert![child.depth() > cursor.depth(), "children are deeper"];
+          stack.push(child);
+        }
+      }
+    }
+  }
+  results.sort_unstable();
+  assert_eq![results, expected, "custom traversal matches query"];
+  Ok(())
+}
@@ -0,0 +1,52 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,Mix,Mix2,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = Mix2<f32,f32>;
+type V = u32;
+
+#[test]
+fn tree_format() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(400).build()?;
+  let rows: Vec<Row<P,V>> = (0..5_000).map(|i| {
+    let x = r.read::<f32>()*2.0-1.0;
+    let y = r.read::<f32>()*2.0-1.0;
+    let p = if i % 2 == 0 {
+      P::new(Mix::Scalar(x),Mix::Scalar(y))
+    } else {
+      P::new(Mix::Interval(x,x+0.01),Mix::Scalar(y))
+    };
+    Row::Insert(p,i)
+  }).collect();
+  for batch in rows.chunks(1_200) {
+    db.batch(batch)?;
+  }
+
+  let mut records = db.staging.len()? as u64;
+  let mut dumped = 0;
+  for tree in db.trees.iter() {
+    let mut tree = tree.try_borrow_mut()?;
+    let dump = tree.format()?;
+    if tree.is_empty()? {
+      assert_eq![dump, "empty tree"];
+      continue
+    }
+    dumped += 1;
+    assert![dump.starts_with("branch 0 (depth 0)\n  pivots: ["),
+      "dump starts at the root: {}", dump];
+    for line in dump.lines().filter(|l| l.contains(" records in ")) {
+      let count = line.split(" (").nth(1).unwrap()
+        .split(' ').next().unwrap();
+      records += count.parse::<u64>()?;
+    }
+  }
+  assert![dumped > 0, "at least one tree was dumped"];
+  assert_eq![records, rows.len() as u64, "every record is in a data block"];
+  Ok(())
+}
@@ -0,0 +1,109 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate random_access_storage;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Location,TREE_FORMAT};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use random_access_storage::RandomAccess;
+use tempfile::Builder as Tmpfile;
+
+use std::cmp::Ordering;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn tree_formats() -> Result<(),Error> {
+  let plain_dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..12_000).map(|_| {
+    let xmin: f32 = r.read::<f32>()*2.0-1.0;
+    let xmax: f32 = xmin + r.read::<f32>().powf(64.0)*(1.0-xmin);
+    let ymin: f32 = r.read::<f32>()*2.0-1.0;
+    let ymax: f32 = ymin + r.read::<f32>().powf(64.0)*(1.0-ymin);
+    Row::Insert(((xmin,xmax),(ymin,ymax)), r.read::<u32>())
+  }).collect();
+  let bbox = ((-0.5,-0.8),(0.3,-0.5));
+  let trees = {
+    let mut plain = open(plain_dir.path())?;
+    let mut db = open(dir.path())?;
+    for batch in inserts[0..9_000].chunks(3_000) {
+      plain.batch(batch)?;
+      db.batch(batch)?;
+    }
+    db.migrate()?;
+    let formats = db.tree_formats()?;
+    assert![formats.len() > 1, "several trees"];
+    assert![formats.iter().all(|f| *f == 1),
+      "trees written before migrating are tagged with version 1"];
+    for batch in inserts[9_000..].chunks(3_000) {
+      plain.batch(batch)?;
+      db.batch(batch)?;
+    }
+    assert![db.tree_formats()?.iter().all(|f| *f <= TREE_FORMAT),
+      "trees tagged with a supported format"];
+    assert_eq![query(&mut db, &bbox)?, query(&mut plain, &bbox)?,
+      "same results"];
+    db.tree_formats()?
+  };
+  {
+    let mut plain = open(plain_dir.path())?;
+    let mut db = open(dir.path())?;
+    assert_eq![db.tree_formats()?, trees, "tree formats kept after reopening"];
+    assert_eq![query(&mut db, &bbox)?, query(&mut plain, &bbox)?,
+      "same results after reopening"];
+  }
+  {
+    // pretend a newer version of eyros rebuilt the first tree
+    let meta_file = dir.path().join("meta");
+    let mut meta = std::fs::read(&meta_file)?;
+    let mask_len = u32::from_be_bytes([meta[2],meta[3],meta[4],meta[5]]);
+    let k = 6 + ((mask_len as usize)+7)/8 + 2;
+    meta[k..k+2].copy_from_slice(&(TREE_FORMAT+1).to_be_bytes());
+    std::fs::write(&meta_file, &meta)?;
+    match open(dir.path()) {
+      Ok(_) => panic!["opened a tree with a newer format version"],
+      Err(err) => assert![err.to_string().contains("tree 0"),
+        "clear error for a newer tree format: {}", err]
+    }
+  }
+  Ok(())
+}
+
+fn open (dir: &Path) -> Result<DB<RandomAccessDisk,
+Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let open_store: Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>
+    = Box::new(move |name: &str| {
+      let p = dir.join(name);
+      Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+    });
+  Setup::new(open_store).max_data_size(300).base_size(1_000).build()
+}
+
+fn query<S,U> (db: &mut DB<S,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<(P,V)>,Error> where
+S: RandomAccess<Error=Error>,
+U: Fn(&str) -> Result<S,Error> {
+  let mut results: Vec<(P,V,Location)> = vec![];
+  for result in db.query(bbox)? {
+    results.push(result?);
+  }
+  results.sort_unstable_by(cmp);
+  Ok(results.into_iter().map(|(p,v,_)| (p,v)).collect())
+}
+
+fn cmp (a: &(P,V,Location), b: &(P,V,Location)) -> Ordering {
+  match a.0.partial_cmp(&b.0) {
+    Some(Ordering::Equal) => a.1.cmp(&b.1),
+    Some(x) => x,
+    None => panic!["comparison failed"]
+  }
+}
@@ -0,0 +1,67 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn tuning() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<Row<P,V>> = (0..20_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  // batches of varying sizes
+  let mut batches = vec![];
+  let mut offset = 0;
+  while offset < inserts.len() {
+    let size = 10 + (r.read::<usize>() % 500);
+    let end = (offset+size).min(inserts.len());
+    batches.push(&inserts[offset..end]);
+    offset = end;
+  }
+
+  for auto in [false,true].iter() {
+    let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+      .base_size(200)
+      .auto_tune(*auto)
+      .build()?;
+    assert_eq![db.tuning_report().batches, 0];
+    for batch in batches.iter() {
+      db.batch(batch)?;
+    }
+    let report = db.tuning_report();
+    assert_eq![report.auto_tune, *auto];
+    assert_eq![report.batches, batches.len() as u64];
+    assert![report.staged_batches < report.batches, "some batches flushed"];
+    assert![report.flushes > 0 && report.flushed_rows > 0, "flushes measured"];
+    assert![report.flushed_rows <= inserts.len() as u64];
+    let s = report.suggested_staging_rows;
+    assert![s >= 200 && s <= 200*16 && s % 200 == 0,
+      "suggested staging threshold of {} rows", s];
+    let b = report.suggested_batch_rows;
+    assert![b >= 1 && b <= s, "suggested batches of {} rows", b];
+    if !*auto {
+      assert_eq![report.staging_threshold_rows, 200,
+        "threshold unchanged without auto-tuning"];
+    } else {
+      let t = report.staging_threshold_rows;
+      assert![t >= 200 && t <= 200*16, "auto-tuned threshold of {} rows", t];
+    }
+
+    let bbox = ((-1.0,-1.0),(1.0,1.0));
+    let mut values = db.query(&bbox)?
+      .map(|r| r.map(|(_,v,_)| v))
+      .collect::<Result<Vec<V>,Error>>()?;
+    values.sort_unstable();
+    assert_eq![values, (0..inserts.len() as u32).collect::<Vec<V>>(),
+      "every record returned with auto_tune({})", auto];
+  }
+  Ok(())
+}
@@ -0,0 +1,63 @@
+extern crate desert;
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use desert::{ToBytes,CountBytes};
+use eyros::{DB,Setup,Row,MemoryStore,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use std::io::Read;
+
+type P = ((f32,f32),(f32,f32));
+type V = Vec<u8>;
+
+#[test]
+fn value_reader() -> Result<(),Error> {
+  let mut r = rand().seed([13,12]);
+  let mut db: DB<MemoryStore,_,P,V> = Setup::new(memory_storage())
+    .base_size(200)
+    .inline_threshold(1_000)
+    .build()?;
+  let inserts: Vec<Row<P,V>> = (0..500).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    let len = if i % 50 == 0 { 200_000 + i } else { 1 + i % 40 };
+    let value = (0..len).map(|j| (i*7+j) as u8).collect();
+    Row::Insert(((x,x+0.01),(y,y+0.01)), value)
+  }).collect();
+  // the last batch stays in the staging area
+  for batch in inserts.chunks(220) {
+    db.batch(batch)?;
+  }
+
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+  let results = db.query(&bbox)?.collect::<Result<Vec<_>,Error>>()?;
+  assert_eq![results.len(), inserts.len()];
+  let mut streamed = 0;
+  for (_,v,loc) in results.iter() {
+    let mut reader = db.value_reader(loc)?;
+    assert_eq![reader.len(), v.count_bytes() as u64];
+    if reader.is_streamed() { streamed += 1 }
+    // read in small pieces to cross the chunk boundaries
+    let mut bytes = vec![];
+    let mut buf = [0u8;4093];
+    loop {
+      let n = reader.read(&mut buf)?;
+      if n == 0 { break }
+      bytes.extend_from_slice(&buf[..n]);
+    }
+    assert_eq![bytes, v.to_bytes()?, "bytes of the value at {:?}", loc];
+  }
+  let blobs = results.iter()
+    .filter(|(_,v,loc)| loc.0 > 0 && v.count_bytes() > 1_000)
+    .count();
+  assert![blobs > 0, "large values merged into trees"];
+  assert_eq![streamed, blobs, "large tree values streamed from the blob store"];
+
+  let (_,_,loc) = results.iter().find(|(_,v,_)| v.len() > 1_000).unwrap();
+  db.batch(&[Row::Delete(*loc)])?;
+  assert![db.value_reader(loc).is_err(), "no reader for a deleted record"];
+  assert![db.value_reader(&(1,10_000)).is_err(), "no reader past a block"];
+  Ok(())
+}
@@ -0,0 +1,95 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_storage;
+
+use eyros::{DB,Setup,Row,Location,MemoryStore,memory_storage,FORMAT_VERSION};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_storage::RandomAccess;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+#[test]
+fn varint_deletes() -> Result<(),Error> {
+  let inserts = rows();
+  let bbox = ((-0.5,-0.5),(0.5,0.5));
+  let mut sizes = vec![];
+  for varint in [false,true] {
+    let storage = memory_storage();
+    let mut db = open(storage.clone(), varint)?;
+    db.batch(&inserts)?;
+    let deletes = deletes(&mut db, &bbox)?;
+    db.batch(&deletes)?;
+    sizes.push(storage("staging_deletes")?.len()?);
+    assert_eq![values(&mut db)?.len(), inserts.len()-deletes.len(),
+      "deletes applied with varint={}", varint];
+
+    let mut db = open(storage.clone(), false)?;
+    assert_eq![db.staging.deletes.try_borrow()?.len(), deletes.len(),
+      "staged deletes read back with varint={}", varint];
+    assert_eq![values(&mut db)?.len(), inserts.len()-deletes.len(),
+      "deletes kept after reopening with varint={}", varint];
+  }
+  assert![sizes[1]*2 < sizes[0], "varints take fewer bytes: {:?}", sizes];
+  Ok(())
+}
+
+#[test]
+fn varint_deletes_migrate() -> Result<(),Error> {
+  let inserts = rows();
+  let storage = memory_storage();
+  let mut db = open(storage.clone(), false)?;
+  db.batch(&inserts)?;
+  let deletes = deletes(&mut db, &((-0.5,-0.5),(0.5,0.5)))?;
+  db.batch(&deletes)?;
+  let mut expected = values(&mut db)?;
+  expected.sort_unstable();
+  let before = storage("staging_deletes")?.len()?;
+
+  let existing: Result<DB<_,_,P,V>,Error> = open(storage.clone(), true);
+  assert![existing.is_err(), "existing databases must be migrated first"];
+  db.migrate()?;
+  assert_eq![db.format(), FORMAT_VERSION];
+  let after = storage("staging_deletes")?.len()?;
+  assert![after*2 < before, "staged deletes rewritten: {} {}", before, after];
+
+  let mut db = open(storage.clone(), true)?;
+  let mut results = values(&mut db)?;
+  results.sort_unstable();
+  assert_eq![results, expected, "same results after migrating"];
+  Ok(())
+}
+
+fn rows () -> Vec<Row<P,V>> {
+  let mut r = rand().seed([13,12]);
+  (0..5_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    Row::Insert(((x,x+0.01),(y,y+0.01)), i)
+  }).collect()
+}
+
+fn open<U> (storage: U, varint: bool) -> Result<DB<MemoryStore,U,P,V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  Setup::new(storage)
+    .varint_deletes(varint)
+    .base_size(4_000)
+    .build()
+}
+
+fn deletes<U> (db: &mut DB<MemoryStore,U,P,V>, bbox: &((f32,f32),(f32,f32)))
+-> Result<Vec<Row<P,V>>,Error> where U: Fn(&str) -> Result<MemoryStore,Error> {
+  let locs = db.query(bbox)?
+    .map(|r| r.map(|(_,_,loc)| loc))
+    .collect::<Result<Vec<Location>,Error>>()?;
+  Ok(locs.into_iter().map(Row::Delete).collect())
+}
+
+fn values<U> (db: &mut DB<MemoryStore,U,P,V>) -> Result<Vec<V>,Error>
+where U: Fn(&str) -> Result<MemoryStore,Error> {
+  db.query(&((-1.0,-1.0),(1.0,1.0)))?
+    .map(|r| r.map(|(_,v,_)| v))
+    .collect()
+}
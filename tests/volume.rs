@@ -0,0 +1,59 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+
+use eyros::{DB,Row,Row3,Point3,Box3,bbox3,cube,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+
+#[test]
+fn volume() -> Result<(),Error> {
+  let (lo,hi) = (3.0,5.0);
+  assert_eq![bbox3(1.0..2.0, hi..lo, -1.0..1.0),
+    ((1.0,3.0,-1.0),(2.0,5.0,1.0)), "bounds from ranges"];
+  assert_eq![cube((1.0,2.0,3.0), 0.5), ((0.5,1.5,2.5),(1.5,2.5,3.5)),
+    "bounds around a point"];
+
+  let mut r = rand().seed([13,12]);
+  let boxes: Vec<Row<Box3<f32>,u32>> = (0..2_000).map(|i| {
+    let (x,y,z) = (r.read::<f32>()*100.0, r.read::<f32>()*100.0,
+      r.read::<f32>()*50.0);
+    let size = r.read::<f32>()*5.0;
+    Row3::insert_box(x..x+size, y+size..y, z..z+size/2.0, i)
+  }).collect();
+  let mut db: DB<_,_,Box3<f32>,u32> = DB::open(memory_storage())?;
+  db.batch(&boxes)?;
+  let bbox = bbox3(20.0..60.0, 10.0..30.0, 5.0..25.0);
+  let mut expected: Vec<u32> = boxes.iter().filter_map(|row| match row {
+    Row::Insert(((x0,x1),(y0,y1),(z0,z1)),v) => {
+      assert![y0 <= y1, "reversed ranges flipped"];
+      let hit = *x0 <= 60.0 && 20.0 <= *x1 && *y0 <= 30.0 && 10.0 <= *y1
+        && *z0 <= 25.0 && 5.0 <= *z1;
+      if hit { Some(*v) } else { None }
+    },
+    _ => None
+  }).collect();
+  expected.sort_unstable();
+  let mut results = vec![];
+  for result in db.query(&bbox)? {
+    results.push(result?.1);
+  }
+  results.sort_unstable();
+  assert_eq![results, expected, "volumes that intersect the bounds"];
+
+  let points: Vec<Row<Point3<f32>,u32>> = (0..2_000).map(|i| {
+    Row3::insert_point(r.read::<f32>()*10.0, r.read::<f32>()*10.0,
+      r.read::<f32>()*10.0, i)
+  }).collect();
+  let mut cloud: DB<_,_,Point3<f32>,u32> = DB::open(memory_storage())?;
+  cloud.batch(&points)?;
+  let mut n = 0;
+  for result in cloud.query(&cube((5.0,5.0,5.0), 2.0))? {
+    let (p,_,_) = result?;
+    assert![(p.0-5.0).abs() <= 2.0 && (p.1-5.0).abs() <= 2.0
+      && (p.2-5.0).abs() <= 2.0, "point in the cube"];
+    n += 1;
+  }
+  assert![n > 0, "points in the cube"];
+  Ok(())
+}
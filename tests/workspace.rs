@@ -0,0 +1,98 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate tempfile;
+
+use eyros::{Setup,Row,PointOrd,Workspace,IoMode,disk_storage,memory_storage};
+use failure::Error;
+use random::{Source,default as rand};
+use tempfile::Builder as Tmpfile;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+
+const LAYERS: [&str;3] = ["roads","buildings","pois"];
+
+#[test]
+fn workspace() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let storage = disk_storage(dir.path().to_path_buf(), IoMode::Buffered);
+  let setup = Setup::new(storage.clone()).base_size(500);
+  let mut ws: Workspace<_,P,V> = Workspace::open(setup, &LAYERS)?;
+  assert_eq![ws.layer_names().collect::<Vec<_>>(), LAYERS.to_vec()];
+  let mut r = rand().seed([13,12]);
+  let inserts: Vec<(usize,P,V)> = (0..3_000).map(|i| {
+    let x: f32 = r.read::<f32>()*2.0-1.0;
+    let y: f32 = r.read::<f32>()*2.0-1.0;
+    ((i%3) as usize, ((x,x+0.01),(y,y+0.01)), i)
+  }).collect();
+  for (k,name) in LAYERS.iter().enumerate() {
+    let rows: Vec<Row<P,V>> = inserts.iter()
+      .filter(|(layer,_,_)| *layer == k)
+      .map(|(_,p,v)| Row::Insert(*p,*v))
+      .collect();
+    ws.batch(name, &rows)?;
+  }
+  assert![dir.path().join("pois").join("staging_inserts").exists(),
+    "stores of each layer in its own directory"];
+  assert![ws.batch("rivers", &[]).is_err(), "unknown layer"];
+
+  let bbox = ((-0.5,-0.3),(0.1,0.4));
+  let mut expected: Vec<(String,V)> = inserts.iter()
+    .filter(|(_,p,_)| p.overlaps(&bbox))
+    .map(|(k,_,v)| (LAYERS[*k].to_string(),*v))
+    .collect();
+  expected.sort_unstable_by_key(|(_,v)| *v);
+  assert![!expected.is_empty(), "expected results"];
+  let mut results = vec![];
+  for result in ws.query(&bbox)? {
+    let (layer,_,v,_) = result?;
+    results.push((layer.to_string(),v));
+  }
+  results.sort_unstable_by_key(|(_,v)| *v);
+  assert_eq![results, expected, "results tagged with their layer"];
+
+  // delete from one layer through its database
+  let deletes: Vec<Row<P,V>> = ws.layer("roads")?.query(&bbox)?
+    .map(|r| r.map(|(_,_,loc)| Row::Delete(loc)))
+    .collect::<Result<_,Error>>()?;
+  ws.batch("roads", &deletes)?;
+  ws.sync()?;
+  drop(ws);
+
+  let setup = Setup::new(storage.clone()).base_size(500);
+  let mut ws: Workspace<_,P,V> = Workspace::open(setup, &LAYERS)?;
+  let mut results = vec![];
+  for result in ws.query(&bbox)? {
+    let (layer,_,v,_) = result?;
+    results.push((layer.to_string(),v));
+  }
+  results.sort_unstable_by_key(|(_,v)| *v);
+  expected.retain(|(layer,_)| layer != "roads");
+  assert_eq![results, expected, "results after reopening"];
+
+  let setup = Setup::new(storage.clone());
+  let invalid: Result<Workspace<_,P,V>,Error> =
+    Workspace::open(setup, &["roads","../roads"]);
+  assert![invalid.is_err(), "invalid layer name"];
+  Ok(())
+}
+
+#[test]
+fn workspace_budget() -> Result<(),Error> {
+  let setup = Setup::new(memory_storage())
+    .bbox_cache_size(3_000)
+    .data_list_cache_size(3_000)
+    .write_cache_size(300_000)
+    .pin_bytes(6_000_000);
+  let mut ws: Workspace<_,P,V> = Workspace::open(setup, &LAYERS)?;
+  for name in LAYERS.iter() {
+    let fields = &ws.layer(name)?.fields;
+    assert_eq![fields.bbox_cache_size, 1_000];
+    assert_eq![fields.data_list_cache_size, 1_000];
+    assert_eq![fields.write_cache_size, 100_000];
+    assert_eq![fields.pin_bytes, 2_000_000];
+    assert_eq![fields.staging_spill, 0, "spilling stays disabled"];
+  }
+  Ok(())
+}
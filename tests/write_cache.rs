@@ -0,0 +1,68 @@
+extern crate eyros;
+extern crate failure;
+extern crate random;
+extern crate random_access_disk;
+extern crate tempfile;
+
+use eyros::{DB,Setup,Row,Durability};
+use failure::Error;
+use random::{Source,default as rand};
+use random_access_disk::RandomAccessDisk;
+use tempfile::Builder as Tmpfile;
+use std::path::Path;
+
+type P = ((f32,f32),(f32,f32));
+type V = u32;
+type Storage = Box<dyn Fn(&str) -> Result<RandomAccessDisk,Error>>;
+
+fn open (dir: &Path, max_bytes: usize)
+-> Result<DB<RandomAccessDisk,Storage,P,V>,Error> {
+  let dir = dir.to_path_buf();
+  let storage: Storage = Box::new(move |name: &str| {
+    let p = dir.join(name);
+    Ok(RandomAccessDisk::builder(p).auto_sync(false).build()?)
+  });
+  Setup::new(storage)
+    .base_size(100_000)
+    .durability(Durability::Never)
+    .write_cache_size(max_bytes)
+    .build()
+}
+
+#[test]
+fn write_cache_limit() -> Result<(),Error> {
+  let dir = Tmpfile::new().prefix("eyros").tempdir()?;
+  let mut r = rand().seed([13,12]);
+  let batches: Vec<Vec<Row<P,V>>> = (0..20).map(|_| {
+    (0..200).map(|_| {
+      let x: f32 = r.read::<f32>()*2.0-1.0;
+      let y: f32 = r.read::<f32>()*2.0-1.0;
+      Row::Insert(((x,x+0.01),(y,y+0.01)), r.read::<u32>())
+    }).collect()
+  }).collect();
+  let bbox = ((-1.0,-1.0),(1.0,1.0));
+
+  let max_bytes = 20_000;
+  {
+    let mut db = open(dir.path(), max_bytes)?;
+    let mut peak = 0;
+    for batch in batches.iter() {
+      db.batch(batch)?;
+      peak = peak.max(db.write_cache_bytes());
+      assert![db.write_cache_bytes() <= max_bytes, "cache over the limit"];
+    }
+    assert![peak > 0, "writes were cached"];
+    assert_eq![db.query(&bbox)?.count(), 4_000];
+    db.sync()?;
+    assert_eq![db.write_cache_bytes(), 0, "sync flushes the cache"];
+  }
+  let mut db = open(dir.path(), max_bytes)?;
+  assert_eq![db.query(&bbox)?.count(), 4_000, "flushed rows reopen"];
+
+  let mut db = open(&dir.path().join("unbounded"), 0)?;
+  for batch in batches.iter() {
+    db.batch(batch)?;
+  }
+  assert![db.write_cache_bytes() > max_bytes, "no limit"];
+  Ok(())
+}